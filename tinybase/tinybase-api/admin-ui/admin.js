@@ -0,0 +1,372 @@
+// The admin dashboard: a page talking to the REST API with an admin API key.
+// Everything is built with DOM calls, never innerHTML, so record data can't
+// inject markup.
+'use strict';
+
+const PER_PAGE = 25;
+const KEY = 'tinybase-admin-key';
+
+const ui = {
+  fieldTypes: [],
+  collections: [],
+  current: null,
+  page: 1,
+};
+
+function el(tag, attrs = {}, ...children) {
+  const node = document.createElement(tag);
+  for (const [name, value] of Object.entries(attrs)) {
+    if (name.startsWith('on')) {
+      node.addEventListener(name.slice(2), value);
+    } else if (value === true) {
+      node.setAttribute(name, '');
+    } else if (value !== false && value != null) {
+      node.setAttribute(name, value);
+    }
+  }
+  for (const child of children.flat()) {
+    node.append(child instanceof Node ? child : document.createTextNode(String(child)));
+  }
+  return node;
+}
+
+function showError(message) {
+  const box = document.getElementById('error');
+  box.textContent = message;
+  box.hidden = false;
+  clearTimeout(showError.timer);
+  showError.timer = setTimeout(() => { box.hidden = true; }, 6000);
+}
+
+async function api(method, path, body) {
+  const headers = {};
+  const key = sessionStorage.getItem(KEY);
+  if (key) headers.Authorization = `Bearer ${key}`;
+  if (body !== undefined) headers['Content-Type'] = 'application/json';
+  const response = await fetch(`/api/v1${path}`, {
+    method,
+    headers,
+    body: body === undefined ? undefined : JSON.stringify(body),
+  });
+  if (response.status === 401) {
+    signOut();
+    throw new Error('Please sign in');
+  }
+  const text = await response.text();
+  const json = text ? JSON.parse(text) : null;
+  if (!response.ok) {
+    const details = json && json.details ? `\n${JSON.stringify(json.details, null, 2)}` : '';
+    throw new Error(`${(json && json.message) || response.statusText}${details}`);
+  }
+  return json;
+}
+
+// Runs an action, reporting its failure instead of throwing.
+function attempt(action) {
+  return (...args) => Promise.resolve(action(...args)).catch((e) => showError(e.message));
+}
+
+function signOut() {
+  sessionStorage.removeItem(KEY);
+  document.getElementById('app').hidden = true;
+  document.getElementById('logout').hidden = true;
+  document.getElementById('login').hidden = false;
+}
+
+async function signIn(event) {
+  event.preventDefault();
+  const form = event.target;
+  const auth = await api('POST', '/admin/auth', {
+    email: form.email.value,
+    password: form.password.value,
+  });
+  sessionStorage.setItem(KEY, auth.key);
+  form.reset();
+  await start();
+}
+
+async function start() {
+  ui.fieldTypes = await api('GET', '/admin/field-types');
+  await loadCollections();
+  document.getElementById('login').hidden = true;
+  document.getElementById('app').hidden = false;
+  document.getElementById('logout').hidden = !sessionStorage.getItem(KEY);
+}
+
+async function loadCollections() {
+  ui.collections = await api('GET', '/admin/collections');
+  const list = document.getElementById('collections');
+  list.replaceChildren(...ui.collections.map((c) => el('li', {
+    class: ui.current && ui.current.id === c.id ? 'active' : null,
+    onclick: attempt(() => openCollection(c.id)),
+  },
+  el('span', {}, c.name, c.group ? el('span', { class: 'group' }, ` ${c.group}`) : ''),
+  el('span', { class: 'count' }, c.records))));
+}
+
+function recordCount() {
+  const summary = ui.collections.find((c) => c.id === ui.current.id);
+  return summary ? summary.records : 0;
+}
+
+async function openCollection(id, tab = 'records') {
+  ui.current = await api('GET', `/collections/${id}`);
+  ui.page = 1;
+  await loadCollections();
+  if (tab === 'schema') {
+    showSchema();
+  } else {
+    await showRecords();
+  }
+}
+
+function tabs(active) {
+  const tab = (name, label, action) => el('button', {
+    class: active === name ? 'active' : null,
+    onclick: attempt(action),
+  }, label);
+  return el('div', { class: 'tabs' },
+    el('h2', {}, ui.current.name),
+    tab('records', 'Records', showRecords),
+    tab('schema', 'Schema', showSchema));
+}
+
+function fields() {
+  return ui.current.schema ? Object.entries(ui.current.schema.fields) : [];
+}
+
+function typeName(type) {
+  return typeof type === 'string' ? type : Object.keys(type)[0];
+}
+
+function typeInfo(type) {
+  return ui.fieldTypes.find((t) => t.name === typeName(type)) || { input: 'json', constraints: [] };
+}
+
+function cell(value) {
+  const text = value === undefined ? '' : typeof value === 'string' ? value : JSON.stringify(value);
+  return el('td', { class: 'value', title: text }, text);
+}
+
+// Records
+
+async function showRecords() {
+  const main = document.getElementById('main');
+  const body = await api('GET', `/collections/${ui.current.id}/records?page=${ui.page}&per_page=${PER_PAGE}`);
+  const records = Array.isArray(body) ? body : body.items;
+  const columns = fields().map(([name]) => name);
+  const pages = Math.max(1, Math.ceil(recordCount() / PER_PAGE));
+  const turn = (page) => attempt(() => { ui.page = page; return showRecords(); });
+  main.replaceChildren(
+    tabs('records'),
+    el('div', { class: 'toolbar' },
+      el('button', { class: 'primary', onclick: () => showRecordForm(null) }, 'New record'),
+      el('button', { disabled: ui.page <= 1, onclick: turn(ui.page - 1) }, 'Previous'),
+      el('span', {}, `Page ${ui.page} of ${pages}`),
+      el('button', { disabled: ui.page >= pages, onclick: turn(ui.page + 1) }, 'Next')),
+    el('table', {},
+      el('thead', {}, el('tr', {},
+        el('th', {}, 'id'),
+        columns.length ? columns.map((name) => el('th', {}, name)) : el('th', {}, 'data'),
+        el('th', {}, 'updated'),
+        el('th', {}))),
+      el('tbody', {}, records.map((record) => el('tr', {},
+        el('td', {}, record.id),
+        columns.length ? columns.map((name) => cell(record.data[name])) : cell(record.data),
+        el('td', {}, record.updated),
+        el('td', {},
+          el('button', { onclick: () => showRecordForm(record) }, 'Edit'),
+          ' ',
+          el('button', { class: 'danger', onclick: attempt(() => deleteRecord(record)) }, 'Delete')))))));
+}
+
+async function deleteRecord(record) {
+  if (!confirm(`Delete record ${record.id}?`)) return;
+  await api('DELETE', `/collections/${ui.current.id}/records/${record.id}`);
+  await loadCollections();
+  await showRecords();
+}
+
+// An input for a value of a field; `read` gets the value back from it.
+function valueInput(name, definition, value) {
+  const input = typeInfo(definition.type).input;
+  let node;
+  let read;
+  switch (input) {
+    case 'checkbox':
+      node = el('input', { type: 'checkbox', name, checked: value === true });
+      read = () => node.checked;
+      break;
+    case 'number':
+    case 'relation':
+      node = el('input', { type: 'number', name, step: 'any' });
+      node.value = value == null ? '' : value;
+      read = () => (node.value === '' ? undefined : Number(node.value));
+      break;
+    case 'json':
+      node = el('textarea', { name });
+      node.value = value === undefined ? '' : JSON.stringify(value, null, 2);
+      read = () => (node.value.trim() === '' ? undefined : JSON.parse(node.value));
+      break;
+    case 'textarea':
+      node = el('textarea', { name });
+      node.value = value == null ? '' : value;
+      read = () => (node.value === '' ? undefined : node.value);
+      break;
+    case 'file':
+      node = el('input', { name, disabled: true, placeholder: 'Upload files through the API' });
+      node.value = value ? value.filename : '';
+      read = () => value;
+      break;
+    default:
+      node = el('input', { type: input, name });
+      node.value = value == null ? '' : value;
+      read = () => (node.value === '' ? undefined : node.value);
+  }
+  return { node, read };
+}
+
+function showRecordForm(record) {
+  const main = document.getElementById('main');
+  const data = record ? record.data : {};
+  let inputs;
+  if (fields().length) {
+    inputs = fields()
+      .filter(([, definition]) => !definition.compute)
+      .map(([name, definition]) => ({ name, ...valueInput(name, definition, data[name]) }));
+  } else {
+    const whole = valueInput('data', { type: 'json' }, data);
+    inputs = [{ name: null, ...whole }];
+  }
+  const save = attempt(async (event) => {
+    event.preventDefault();
+    let values = {};
+    for (const input of inputs) {
+      const value = input.read();
+      if (input.name === null) {
+        values = value || {};
+      } else if (value !== undefined) {
+        values[input.name] = value;
+      }
+    }
+    if (record) {
+      await api('PUT', `/collections/${ui.current.id}/records/${record.id}`, { data: values });
+    } else {
+      await api('POST', `/collections/${ui.current.id}/records`, { data: values });
+    }
+    await loadCollections();
+    await showRecords();
+  });
+  main.replaceChildren(
+    tabs('records'),
+    el('h3', {}, record ? `Record ${record.id}` : 'New record'),
+    el('form', { onsubmit: save },
+      inputs.map((input) => el('label', {}, input.name || 'data', input.node)),
+      el('div', { class: 'toolbar' },
+        el('button', { type: 'submit', class: 'primary' }, 'Save'),
+        el('button', { type: 'button', onclick: attempt(showRecords) }, 'Cancel'))));
+}
+
+// Schema
+
+function fieldRow(name, definition) {
+  const info = () => ui.fieldTypes.find((t) => t.name === type.value);
+  const nameInput = el('input', { placeholder: 'name', required: true });
+  nameInput.value = name;
+  const type = el('select', {}, ui.fieldTypes.map((t) => el('option', { value: t.name, title: t.description }, t.name)));
+  type.value = typeName(definition.type);
+  const target = el('select', {}, ui.collections.map((c) => el('option', { value: c.id }, c.name)));
+  if (definition.type.relation) target.value = definition.type.relation.collection_id;
+  const targetLabel = el('label', {}, 'collection', target);
+  const required = el('input', { type: 'checkbox', checked: definition.required === true });
+  const constraints = el('span', { class: 'field-row' });
+  const bounds = {};
+  const renderConstraints = () => {
+    targetLabel.hidden = type.value !== 'relation';
+    constraints.replaceChildren(...(info() ? info().constraints : []).map((constraint) => {
+      const input = el('input', { type: constraint === 'pattern' ? 'text' : 'number', step: 'any' });
+      input.value = definition[constraint] == null ? '' : definition[constraint];
+      bounds[constraint] = input;
+      return el('label', {}, constraint, input);
+    }));
+  };
+  type.addEventListener('change', () => {
+    for (const key of Object.keys(bounds)) delete bounds[key];
+    renderConstraints();
+  });
+  renderConstraints();
+  const row = el('div', { class: 'field-row' },
+    el('label', {}, 'name', nameInput),
+    el('label', {}, 'type', type),
+    targetLabel,
+    el('label', {}, 'required', required),
+    constraints,
+    el('button', { type: 'button', class: 'danger', onclick: () => row.remove() }, 'Remove'));
+  // Builds the definition back, keeping what the form doesn't edit, such as
+  // defaults and descriptions.
+  row.read = () => {
+    const result = { ...definition, required: required.checked };
+    for (const constraint of ['min', 'max', 'min_length', 'max_length', 'pattern', 'min_items', 'max_items']) {
+      delete result[constraint];
+    }
+    for (const [constraint, input] of Object.entries(bounds)) {
+      if (input.value !== '') {
+        result[constraint] = constraint === 'pattern' ? input.value : Number(input.value);
+      }
+    }
+    if (type.value === 'relation') {
+      const cascade = definition.type.relation ? definition.type.relation.cascade_delete : false;
+      result.type = { relation: { collection_id: Number(target.value), cascade_delete: cascade } };
+    } else {
+      result.type = type.value;
+    }
+    return [nameInput.value, result];
+  };
+  return row;
+}
+
+function showSchema() {
+  const main = document.getElementById('main');
+  const rows = el('div', {}, fields().map(([name, definition]) => fieldRow(name, definition)));
+  const save = attempt(async (event) => {
+    event.preventDefault();
+    const schema = { ...(ui.current.schema || {}), fields: {} };
+    for (const row of rows.children) {
+      const [name, definition] = row.read();
+      schema.fields[name] = definition;
+    }
+    ui.current = await api('PATCH', `/collections/${ui.current.id}`, { schema });
+    showSchema();
+  });
+  main.replaceChildren(
+    tabs('schema'),
+    el('form', { onsubmit: save },
+      rows,
+      el('div', { class: 'toolbar' },
+        el('button', {
+          type: 'button',
+          onclick: () => rows.append(fieldRow('', { type: 'string', required: false })),
+        }, 'Add field'),
+        el('button', { type: 'submit', class: 'primary' }, 'Save schema'),
+        el('button', { type: 'button', class: 'danger', onclick: attempt(deleteCollection) }, 'Delete collection'))));
+}
+
+async function deleteCollection() {
+  if (!confirm(`Delete ${ui.current.name} and all of its records?`)) return;
+  await api('DELETE', `/collections/${ui.current.id}`);
+  ui.current = null;
+  await loadCollections();
+  document.getElementById('main').replaceChildren(el('p', { class: 'hint' }, 'Collection deleted.'));
+}
+
+async function createCollection() {
+  const name = prompt('Name of the new collection');
+  if (!name) return;
+  const collection = await api('POST', '/collections', { name, schema: { fields: {} } });
+  await openCollection(collection.id, 'schema');
+}
+
+document.getElementById('login').addEventListener('submit', attempt(signIn));
+document.getElementById('logout').addEventListener('click', signOut);
+document.getElementById('new-collection').addEventListener('click', attempt(createCollection));
+attempt(start)();
@@ -0,0 +1,306 @@
+//! Holding record reads and writes to the rules of their collection.
+//!
+//! Each operation on records has a rule in the collection schema, see
+//! [`CollectionRules`]. An empty rule lets everyone through, no rule lets
+//! admins only. Rules see the request as `@request.auth`, its body as
+//! `@request.data` and the record at hand by its field names, `id`,
+//! `created` and `updated` included. A list rule doesn't refuse a list, it
+//! narrows it to the records the rule holds for. The collections a rule
+//! looks up with `@collection.<name>` are loaded with it, up to
+//! [`LOOKUP_LIMIT`] records each; a rule looking up a collection with more
+//! denies, rather than deciding on part of it.
+//!
+//! Admins, API tokens, which their scopes govern instead, setup mode and
+//! the writes the server makes on its own are not held to rules.
+
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use tinybase_core::{
+    rules::{evaluate, referenced_collections, RuleContext},
+    schema::CollectionRules,
+    Collection, ListOptions, Record,
+};
+
+use crate::{auth, db_error, AppError, AppState};
+
+/// Most records loaded of each collection a rule looks up. Rules looking
+/// up a collection holding more deny every request.
+pub(crate) const LOOKUP_LIMIT: u64 = 1000;
+
+/// A record operation a collection rule governs.
+#[derive(Clone, Copy)]
+pub(crate) enum Operation {
+    List,
+    View,
+    Create,
+    Update,
+    Delete,
+}
+
+impl Operation {
+    fn name(self) -> &'static str {
+        match self {
+            Operation::List => "list",
+            Operation::View => "view",
+            Operation::Create => "create",
+            Operation::Update => "update",
+            Operation::Delete => "delete",
+        }
+    }
+
+    fn rule(self, rules: &CollectionRules) -> Option<&str> {
+        match self {
+            Operation::List => rules.list.as_deref(),
+            Operation::View => rules.view.as_deref(),
+            Operation::Create => rules.create.as_deref(),
+            Operation::Update => rules.update.as_deref(),
+            Operation::Delete => rules.delete.as_deref(),
+        }
+    }
+}
+
+/// What the request being handled may do with the records of a collection.
+pub(crate) struct Access {
+    /// Whether the request is held to rules.
+    ruled: bool,
+    collection: String,
+    rules: CollectionRules,
+    /// The looked-up collections holding more than [`LOOKUP_LIMIT`] records.
+    truncated: HashSet<String>,
+    /// The ids of the looked-up collections, `None` if one doesn't exist.
+    looked_up: Option<Vec<i64>>,
+    /// `@request.auth` and the records of the collections the rules look
+    /// up, reused for every record checked.
+    context: Mutex<RuleContext>,
+}
+
+impl Access {
+    pub(crate) async fn to(state: &AppState, collection: &Collection) -> Result<Self, AppError> {
+        let auth = auth::rule_auth(state).await?;
+        let rules = collection
+            .schema
+            .as_ref()
+            .map(|s| s.rules.clone())
+            .unwrap_or_default();
+        Access::new(state, auth, collection.name.clone(), rules).await
+    }
+
+    /// Like [`to`](Self::to), loading the collection only if the request is
+    /// held to rules.
+    pub(crate) async fn to_id(state: &AppState, collection_id: i64) -> Result<Self, AppError> {
+        let auth = auth::rule_auth(state).await?;
+        let collection = match auth {
+            Some(_) => state
+                .db
+                .get_collection(collection_id)
+                .await
+                .map_err(db_error)?,
+            None => None,
+        };
+        let name = collection
+            .as_ref()
+            .map(|c| c.name.clone())
+            .unwrap_or_default();
+        let rules = collection
+            .and_then(|c| c.schema)
+            .map(|s| s.rules)
+            .unwrap_or_default();
+        Access::new(state, auth, name, rules).await
+    }
+
+    async fn new(
+        state: &AppState,
+        auth: Option<Value>,
+        collection: String,
+        rules: CollectionRules,
+    ) -> Result<Self, AppError> {
+        let mut lookups = HashMap::new();
+        let mut truncated = HashSet::new();
+        let mut looked_up = Some(Vec::new());
+        let ruled = auth.is_some();
+        if ruled {
+            let mut names: Vec<String> = rules
+                .iter()
+                .flat_map(|(_, rule)| referenced_collections(rule))
+                .collect();
+            names.sort();
+            names.dedup();
+            for name in names {
+                let collection = state
+                    .db
+                    .get_collection_by_name(&name)
+                    .await
+                    .map_err(db_error)?;
+                let Some(collection) = collection else {
+                    looked_up = None;
+                    lookups.insert(name, Vec::new());
+                    continue;
+                };
+                if let Some(ids) = &mut looked_up {
+                    ids.push(collection.id);
+                }
+                match lookup_records(state, collection.id).await? {
+                    Some(records) => {
+                        lookups.insert(name, records);
+                    }
+                    None => {
+                        truncated.insert(name);
+                    }
+                }
+            }
+        }
+        Ok(Access {
+            ruled,
+            collection,
+            rules,
+            truncated,
+            looked_up,
+            context: Mutex::new(RuleContext {
+                auth: auth.unwrap_or_default(),
+                collections: lookups,
+                ..Default::default()
+            }),
+        })
+    }
+
+    /// The ids of the collections the rules look up, which a cached answer
+    /// must be dropped with; `None` if one of them doesn't exist yet, so its
+    /// creation couldn't be noticed.
+    pub(crate) fn looked_up(&self) -> Option<&[i64]> {
+        self.looked_up.as_deref()
+    }
+
+    /// Whether the request is held to the rules at all.
+    pub(crate) fn is_ruled(&self) -> bool {
+        self.ruled
+    }
+
+    /// Whether the rule of `op` lets the request through for `record`, a
+    /// [`record_value`] or `{}` before the record exists, with `data` the
+    /// request body.
+    pub(crate) fn allows(&self, op: Operation, record: &Value, data: &Value) -> bool {
+        if !self.ruled {
+            return true;
+        }
+        let Some(rule) = op.rule(&self.rules) else {
+            return false;
+        };
+        if let Some(lookup) = referenced_collections(rule)
+            .into_iter()
+            .find(|name| self.truncated.contains(name))
+        {
+            tracing::warn!(
+                collection = %self.collection,
+                operation = op.name(),
+                lookup = %lookup,
+                limit = LOOKUP_LIMIT,
+                "collection rule looks up too many records; denying"
+            );
+            return false;
+        }
+        let mut ctx = self.context.lock().unwrap();
+        ctx.data = data.clone();
+        ctx.record = record.clone();
+        match evaluate(rule, &ctx) {
+            Ok(evaluation) => evaluation.allowed,
+            Err(e) => {
+                tracing::warn!(
+                    collection = %self.collection,
+                    operation = op.name(),
+                    error = %e,
+                    "collection rule failed to evaluate"
+                );
+                false
+            }
+        }
+    }
+
+    /// Refuses the request unless the rule of `op` lets it through.
+    pub(crate) fn check(
+        &self,
+        op: Operation,
+        record: &Value,
+        data: &Value,
+    ) -> Result<(), AppError> {
+        if self.allows(op, record, data) {
+            return Ok(());
+        }
+        Err(AppError::Forbidden(match op.rule(&self.rules) {
+            Some(_) => format!(
+                "The {} rule of collection '{}' denies this request",
+                op.name(),
+                self.collection
+            ),
+            None => format!(
+                "Only admins may {} records of collection '{}'",
+                op.name(),
+                self.collection
+            ),
+        }))
+    }
+
+    /// Whether the list rule lets the request see `record`.
+    pub(crate) fn sees(&self, record: &Record) -> bool {
+        !self.is_ruled() || self.allows(Operation::List, &record_value(record), &Value::Null)
+    }
+}
+
+/// Refuses the request unless the rule of `op` lets it through for the
+/// record `record_id`, loaded only if the request is held to rules. A
+/// missing record passes, for the handler to report.
+pub(crate) async fn check_record(
+    state: &AppState,
+    collection_id: i64,
+    record_id: i64,
+    op: Operation,
+    data: &Value,
+) -> Result<(), AppError> {
+    let access = Access::to_id(state, collection_id).await?;
+    if !access.is_ruled() {
+        return Ok(());
+    }
+    let record = state
+        .db
+        .get_record(collection_id, record_id)
+        .await
+        .map_err(db_error)?;
+    match record {
+        Some(record) => access.check(op, &record_value(&record), data),
+        None => Ok(()),
+    }
+}
+
+/// The records of the collection `collection_id` as rules look them up,
+/// `None` if there are more than [`LOOKUP_LIMIT`].
+async fn lookup_records(
+    state: &AppState,
+    collection_id: i64,
+) -> Result<Option<Vec<Value>>, AppError> {
+    let options = ListOptions {
+        limit: Some(LOOKUP_LIMIT + 1),
+        ..Default::default()
+    };
+    let records = state
+        .db
+        .list_records(collection_id, &options)
+        .await
+        .map_err(db_error)?;
+    if records.len() as u64 > LOOKUP_LIMIT {
+        return Ok(None);
+    }
+    Ok(Some(records.iter().map(record_value).collect()))
+}
+
+/// The record as rules see it: its data with its `id`, `created` and
+/// `updated`.
+pub(crate) fn record_value(record: &Record) -> Value {
+    let mut value = match &record.data {
+        Value::Object(data) => data.clone(),
+        _ => Default::default(),
+    };
+    value.insert("id".to_string(), json!(record.id));
+    value.insert("created".to_string(), json!(record.created));
+    value.insert("updated".to_string(), json!(record.updated));
+    Value::Object(value)
+}
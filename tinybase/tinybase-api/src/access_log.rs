@@ -0,0 +1,231 @@
+//! Access logs in standard formats, for log shippers that ingest them.
+//!
+//! Separate from the tracing logs: one line per request, in the Apache
+//! combined format or as JSON Lines, appended to a file. When the file would
+//! grow past its size limit it is rotated to `<path>.1`, the previous
+//! `<path>.1` to `<path>.2` and so on, dropping the oldest beyond the number
+//! of files kept.
+
+use axum::{
+    body::HttpBody as _,
+    extract::{ConnectInfo, Request, State},
+    http::{
+        header::{REFERER, USER_AGENT},
+        HeaderMap,
+    },
+    middleware::{self, Next},
+    response::Response,
+    Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+use tinybase_core::clock;
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+/// Size an access log file grows to before it is rotated, unless configured
+/// otherwise.
+pub const DEFAULT_ACCESS_LOG_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Rotated access log files kept unless configured otherwise.
+pub const DEFAULT_ACCESS_LOG_FILES: usize = 5;
+
+/// How access log lines are written.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    /// The Apache/NCSA combined log format.
+    #[default]
+    Combined,
+    /// One JSON object per request.
+    Json,
+}
+
+/// One request, as logged.
+#[derive(Serialize, Debug)]
+struct Entry {
+    time: String,
+    /// The client address, `-` when unknown.
+    remote_addr: String,
+    method: String,
+    /// The path and query.
+    uri: String,
+    protocol: String,
+    status: u16,
+    /// The size of the response body, when known up front.
+    bytes: Option<u64>,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    duration_ms: u128,
+}
+
+impl Entry {
+    fn line(&self, format: AccessLogFormat, time: DateTime<Utc>) -> String {
+        match format {
+            AccessLogFormat::Combined => format!(
+                "{} - - [{}] \"{} {} {}\" {} {} \"{}\" \"{}\"\n",
+                self.remote_addr,
+                time.format("%d/%b/%Y:%H:%M:%S %z"),
+                self.method,
+                self.uri,
+                self.protocol,
+                self.status,
+                self.bytes.map_or("-".to_string(), |b| b.to_string()),
+                quoted(self.referer.as_deref()),
+                quoted(self.user_agent.as_deref()),
+            ),
+            AccessLogFormat::Json => {
+                let mut line = serde_json::to_string(self).unwrap_or_default();
+                line.push('\n');
+                line
+            }
+        }
+    }
+}
+
+/// A header value for a quoted combined-log field: `-` when absent, with
+/// quotes and backslashes escaped.
+fn quoted(value: Option<&str>) -> String {
+    value.map_or("-".to_string(), |v| {
+        v.replace('\\', "\\\\").replace('"', "\\\"")
+    })
+}
+
+fn header(headers: &HeaderMap, name: impl axum::http::header::AsHeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+struct LogFile {
+    file: File,
+    size: u64,
+}
+
+/// An access log file, rotated by size.
+#[derive(Clone)]
+pub struct AccessLog {
+    path: Arc<PathBuf>,
+    format: AccessLogFormat,
+    max_bytes: u64,
+    /// Rotated files kept besides the current one.
+    keep: usize,
+    file: Arc<Mutex<LogFile>>,
+}
+
+impl AccessLog {
+    /// Appends to `path`, rotating it once it would exceed `max_bytes` and
+    /// keeping `keep` rotated files.
+    pub async fn open(
+        path: impl Into<PathBuf>,
+        format: AccessLogFormat,
+        max_bytes: u64,
+        keep: usize,
+    ) -> std::io::Result<Self> {
+        let path = path.into();
+        let file = append_to(&path).await?;
+        let size = file.metadata().await?.len();
+        Ok(Self {
+            path: Arc::new(path),
+            format,
+            max_bytes,
+            keep,
+            file: Arc::new(Mutex::new(LogFile { file, size })),
+        })
+    }
+
+    async fn write(&self, line: &str) -> std::io::Result<()> {
+        let mut log = self.file.lock().await;
+        if log.size > 0 && log.size + line.len() as u64 > self.max_bytes {
+            log.file.flush().await?;
+            self.rotate().await?;
+            *log = LogFile {
+                file: append_to(&self.path).await?,
+                size: 0,
+            };
+        }
+        log.file.write_all(line.as_bytes()).await?;
+        // Tokio finishes file writes in the background; flushing makes the
+        // line visible to readers of the log once the request is answered.
+        log.file.flush().await?;
+        log.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Shifts `<path>.N` to `<path>.N+1`, oldest first, and the current file
+    /// to `<path>.1`.
+    async fn rotate(&self) -> std::io::Result<()> {
+        if self.keep == 0 {
+            return fs::remove_file(self.path.as_ref()).await;
+        }
+        let _ = fs::remove_file(rotated(&self.path, self.keep)).await;
+        for n in (1..self.keep).rev() {
+            let from = rotated(&self.path, n);
+            if fs::try_exists(&from).await? {
+                fs::rename(&from, rotated(&self.path, n + 1)).await?;
+            }
+        }
+        fs::rename(self.path.as_ref(), rotated(&self.path, 1)).await
+    }
+}
+
+async fn append_to(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+}
+
+/// The `n`th rotated file of `path`.
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// Wraps `router` so every request it serves is written to `log`.
+pub fn log(router: Router, log: AccessLog) -> Router {
+    router.layer(middleware::from_fn_with_state(log, log_access))
+}
+
+async fn log_access(State(log): State<AccessLog>, request: Request, next: Next) -> Response {
+    let time = clock::now();
+    let started = Instant::now();
+    let remote_addr = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map_or("-".to_string(), |info| info.0.ip().to_string());
+    let method = request.method().to_string();
+    let uri = request
+        .uri()
+        .path_and_query()
+        .map_or("/".to_string(), ToString::to_string);
+    let protocol = format!("{:?}", request.version());
+    let referer = header(request.headers(), REFERER);
+    let user_agent = header(request.headers(), USER_AGENT);
+
+    let response = next.run(request).await;
+    let entry = Entry {
+        time: tinybase_core::format_timestamp(time),
+        remote_addr,
+        method,
+        uri,
+        protocol,
+        status: response.status().as_u16(),
+        bytes: response.body().size_hint().exact(),
+        referer,
+        user_agent,
+        duration_ms: started.elapsed().as_millis(),
+    };
+    if let Err(e) = log.write(&entry.line(log.format, time.into())).await {
+        tracing::warn!(error = %e, path = %log.path.display(), "failed to write access log");
+    }
+    response
+}
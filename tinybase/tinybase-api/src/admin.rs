@@ -0,0 +1,791 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{
+        sse::{Event as SseEvent, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Json,
+};
+use futures_util::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use tinybase_core::{
+    events::{Event, EventAction, EventBus},
+    read_replicas::{ReplicaStats, RoutingStatus},
+    rules::{evaluate, RuleContext},
+    schema::CollectionRules,
+    snapshot::{self, SnapshotInfo, VerificationReport},
+    template::PayloadTemplate,
+};
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    auth::RequireAdmin,
+    check_schema_rules,
+    coalesce::CoalescingStats,
+    db_error,
+    jobs::{accepted, spawn_job},
+    query_cache::QueryCacheStats,
+    AppError, AppState, DbState,
+};
+
+/// Version of the policy document format produced by `export_policy`.
+const POLICY_VERSION: u32 = 1;
+
+#[derive(Deserialize, ToSchema)]
+pub struct RuleTestRequest {
+    rule: String,
+    #[serde(default)]
+    auth: serde_json::Value,
+    #[serde(default)]
+    record: serde_json::Value,
+    #[serde(default)]
+    data: serde_json::Value,
+    /// Sample records of the collections the rule looks up with
+    /// `@collection.<name>`, by collection name.
+    #[serde(default)]
+    collections: HashMap<String, Vec<serde_json::Value>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RuleTestResponse {
+    allowed: bool,
+    trace: Vec<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/rules/test",
+    request_body = RuleTestRequest,
+    responses(
+        (status = 200, description = "Evaluate a rule expression", body = RuleTestResponse),
+        (status = 400, description = "Invalid rule expression", body = ProblemDetail),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn test_rule(
+    _: RequireAdmin,
+    Json(payload): Json<RuleTestRequest>,
+) -> Result<Json<RuleTestResponse>, AppError> {
+    let ctx = RuleContext {
+        auth: payload.auth,
+        data: payload.data,
+        record: payload.record,
+        collections: payload.collections,
+    };
+    let evaluation =
+        evaluate(&payload.rule, &ctx).map_err(|e| AppError::BadRequest(e.to_string()))?;
+    Ok(Json(RuleTestResponse {
+        allowed: evaluation.allowed,
+        trace: evaluation.trace,
+    }))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct TemplateRenderRequest {
+    /// JSON document with `{{ path }}` placeholders.
+    template: serde_json::Value,
+    /// Values the placeholders are looked up in, e.g. `{ "event": ..., "record": ... }`.
+    #[serde(default)]
+    context: serde_json::Value,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TemplateRenderResponse {
+    payload: serde_json::Value,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/templates/render",
+    request_body = TemplateRenderRequest,
+    responses(
+        (status = 200, description = "Render a webhook payload template against a sample context", body = TemplateRenderResponse),
+        (status = 400, description = "Invalid template", body = ProblemDetail),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn render_template(
+    _: RequireAdmin,
+    Json(payload): Json<TemplateRenderRequest>,
+) -> Result<Json<TemplateRenderResponse>, AppError> {
+    let template =
+        PayloadTemplate::new(payload.template).map_err(|e| AppError::BadRequest(e.to_string()))?;
+    Ok(Json(TemplateRenderResponse {
+        payload: template.render(&payload.context),
+    }))
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CollectionPolicy {
+    name: String,
+    rules: CollectionRules,
+}
+
+/// Every access rule of the instance in one document, so policies can be
+/// diffed between environments and applied back with `import_policy`.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PolicyDocument {
+    version: u32,
+    collections: Vec<CollectionPolicy>,
+}
+
+async fn current_policy(db: &DbState) -> Result<PolicyDocument, AppError> {
+    let mut collections: Vec<CollectionPolicy> = db
+        .list_collections()
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|c| CollectionPolicy {
+            name: c.name,
+            rules: c.schema.map(|s| s.rules).unwrap_or_default(),
+        })
+        .collect();
+    collections.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(PolicyDocument {
+        version: POLICY_VERSION,
+        collections,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/policy",
+    responses(
+        (status = 200, description = "Export the access policy of all collections", body = PolicyDocument),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn export_policy(
+    _: RequireAdmin,
+    State(db): State<DbState>,
+) -> Result<Json<PolicyDocument>, AppError> {
+    Ok(Json(current_policy(&db).await?))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/policy",
+    request_body = PolicyDocument,
+    responses(
+        (status = 200, description = "Apply a policy document and return the resulting policy", body = PolicyDocument),
+        (status = 400, description = "Invalid policy document", body = ProblemDetail),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Collection not found", body = ProblemDetail),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn import_policy(
+    _: RequireAdmin,
+    State(db): State<DbState>,
+    State(events): State<EventBus>,
+    Json(payload): Json<PolicyDocument>,
+) -> Result<Json<PolicyDocument>, AppError> {
+    if payload.version != POLICY_VERSION {
+        return Err(AppError::BadRequest(format!(
+            "Unsupported policy version {}",
+            payload.version
+        )));
+    }
+    let collections = db.list_collections().await.map_err(db_error)?;
+    let mut updates = Vec::new();
+    for policy in payload.collections {
+        let collection = collections
+            .iter()
+            .find(|c| c.name == policy.name)
+            .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", policy.name)))?;
+        let mut schema = collection.schema.clone().unwrap_or_default();
+        schema.rules = policy.rules;
+        check_schema_rules(Some(&schema))?;
+        updates.push((collection.id, schema));
+    }
+    for (id, schema) in updates {
+        db.update_collection(id, None, Some(schema))
+            .await
+            .map_err(db_error)?;
+        events.publish(Event::new(EventAction::CollectionUpdated, id, None));
+    }
+    Ok(Json(current_policy(&db).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/activity",
+    responses(
+        (status = 200, description = "Server-sent stream of activity entries as they happen: collection and record changes, and admin sign-ins", content_type = "text/event-stream"),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn activity_feed(
+    _: RequireAdmin,
+    State(events): State<EventBus>,
+) -> Sse<impl Stream<Item = Result<SseEvent, Infallible>>> {
+    let receiver = events.subscribe();
+    let stream = stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    let sse = SseEvent::default()
+                        .event("activity")
+                        .json_data(&event)
+                        .unwrap_or_default();
+                    return Some((Ok(sse), receiver));
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SnapshotVerificationResponse {
+    /// The snapshot file that was verified, if one was found.
+    snapshot: Option<String>,
+    ok: bool,
+    /// Result of `PRAGMA integrity_check`.
+    integrity: Option<String>,
+    collections: usize,
+    records: i64,
+    errors: Vec<String>,
+    /// Milliseconds since the Unix epoch.
+    checked_at: u64,
+    duration_ms: u64,
+}
+
+impl From<VerificationReport> for SnapshotVerificationResponse {
+    fn from(r: VerificationReport) -> Self {
+        SnapshotVerificationResponse {
+            snapshot: r.snapshot,
+            ok: r.ok,
+            integrity: r.integrity,
+            collections: r.collections,
+            records: r.records,
+            errors: r.errors,
+            checked_at: r.checked_at,
+            duration_ms: r.duration_ms,
+        }
+    }
+}
+
+/// Runtime counters of the instance.
+#[derive(Serialize, ToSchema)]
+pub struct Metrics {
+    coalescing: CoalescingStats,
+    query_cache: QueryCacheStats,
+    /// Present when reads are spread over read replicas.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    read_routing: Option<ReadRoutingResponse>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/metrics",
+    responses(
+        (status = 200, description = "Runtime counters, such as how many reads were coalesced", body = Metrics),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn metrics(_: RequireAdmin, State(state): State<AppState>) -> Json<Metrics> {
+    Json(Metrics {
+        coalescing: state.coalescer.stats(),
+        query_cache: state.query_cache.stats(),
+        read_routing: state
+            .read_replicas
+            .as_ref()
+            .map(|replicas| replicas.status().into()),
+    })
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReadReplicaResponse {
+    name: String,
+    /// Whether the replica answered its last probe in time.
+    healthy: bool,
+    /// Smoothed probe round trip.
+    latency_ms: Option<f64>,
+    /// Reads routed to the replica since startup.
+    reads: u64,
+    last_probe: Option<String>,
+    /// Why the last probe failed.
+    error: Option<String>,
+}
+
+impl From<ReplicaStats> for ReadReplicaResponse {
+    fn from(s: ReplicaStats) -> Self {
+        ReadReplicaResponse {
+            name: s.name,
+            healthy: s.healthy,
+            latency_ms: s.latency_ms,
+            reads: s.reads,
+            last_probe: s.last_probe,
+            error: s.error,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReadRoutingResponse {
+    /// The replica, or `primary`, reads are pinned to.
+    pinned: Option<String>,
+    /// Where reads go now: a replica name or `primary`.
+    selected: String,
+    /// Reads served by the primary since startup.
+    primary_reads: u64,
+    replicas: Vec<ReadReplicaResponse>,
+}
+
+impl From<RoutingStatus> for ReadRoutingResponse {
+    fn from(s: RoutingStatus) -> Self {
+        ReadRoutingResponse {
+            pinned: s.pinned,
+            selected: s.selected,
+            primary_reads: s.primary_reads,
+            replicas: s.replicas.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PinReplicaRequest {
+    /// A replica name, `primary`, or `null` to go back to the fastest
+    /// healthy replica.
+    replica: Option<String>,
+}
+
+fn read_replicas_disabled() -> AppError {
+    AppError::NotFound("No read replicas are configured".to_string())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/replicas",
+    responses(
+        (status = 200, description = "Latency and health of the read replicas, and where reads go", body = ReadRoutingResponse),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "No read replicas configured", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn read_routing(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+) -> Result<Json<ReadRoutingResponse>, AppError> {
+    let replicas = state
+        .read_replicas
+        .as_ref()
+        .ok_or_else(read_replicas_disabled)?;
+    Ok(Json(replicas.status().into()))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/replicas/pin",
+    request_body = PinReplicaRequest,
+    responses(
+        (status = 200, description = "Reads go to the given replica until unpinned", body = ReadRoutingResponse),
+        (status = 400, description = "Unknown replica", body = ProblemDetail),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "No read replicas configured", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn pin_read_replica(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Json(payload): Json<PinReplicaRequest>,
+) -> Result<Json<ReadRoutingResponse>, AppError> {
+    let replicas = state
+        .read_replicas
+        .as_ref()
+        .ok_or_else(read_replicas_disabled)?;
+    replicas
+        .pin(payload.replica.as_deref())
+        .map_err(AppError::BadRequest)?;
+    Ok(Json(replicas.status().into()))
+}
+
+fn verification_disabled() -> AppError {
+    AppError::NotFound("Snapshot verification is not configured".to_string())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/backups/verification",
+    responses(
+        (status = 200, description = "Report of the last snapshot verification run", body = SnapshotVerificationResponse),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Verification not configured or not run yet", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn last_snapshot_verification(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+) -> Result<Json<SnapshotVerificationResponse>, AppError> {
+    let verifier = state.verifier.as_ref().ok_or_else(verification_disabled)?;
+    let report = verifier
+        .last_report()
+        .ok_or_else(|| AppError::NotFound("No snapshot verification has run yet".to_string()))?;
+    Ok(Json(report.into()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/backups/verification",
+    responses(
+        (status = 200, description = "Verify the latest snapshot now", body = SnapshotVerificationResponse),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Verification not configured", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn run_snapshot_verification(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+) -> Result<Json<SnapshotVerificationResponse>, AppError> {
+    let verifier = state.verifier.as_ref().ok_or_else(verification_disabled)?;
+    Ok(Json(verifier.run_once().await.into()))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BackupResponse {
+    /// File name of the snapshot, used to restore it.
+    name: String,
+    /// Size in bytes.
+    size: u64,
+    created: String,
+}
+
+impl From<SnapshotInfo> for BackupResponse {
+    fn from(s: SnapshotInfo) -> Self {
+        BackupResponse {
+            name: s.name,
+            size: s.size,
+            created: s.created,
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct BackupQuery {
+    /// Answer `202 Accepted` with a job to follow instead of waiting for the
+    /// snapshot.
+    #[serde(default)]
+    background: bool,
+}
+
+fn backup_dir(state: &AppState) -> Result<&std::path::Path, AppError> {
+    state
+        .backup_dir
+        .as_deref()
+        .ok_or_else(|| AppError::NotFound("Backups are not configured".to_string()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/backup",
+    params(BackupQuery),
+    responses(
+        (status = 201, description = "Snapshot the database into a new, timestamped file in the backup directory", body = BackupResponse),
+        (status = 202, description = "The snapshot is taken in the background as a job, found at the `Location`", body = JobResponse),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Backups not configured", body = ProblemDetail),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn create_backup(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Query(query): Query<BackupQuery>,
+) -> Result<Response, AppError> {
+    let dir = backup_dir(&state)?.to_path_buf();
+    if query.background {
+        let db = state.db.clone();
+        let job = spawn_job(
+            &state,
+            "backup",
+            serde_json::json!({}),
+            move |_| async move {
+                let snapshot = snapshot::take_snapshot(db.as_ref(), &dir)
+                    .await
+                    .map_err(db_error)?;
+                serde_json::to_value(BackupResponse::from(snapshot))
+                    .map_err(|e| AppError::JsonError(e.to_string()))
+            },
+        )
+        .await?;
+        return Ok(accepted(job).into_response());
+    }
+    let snapshot = snapshot::take_snapshot(state.db.as_ref(), &dir)
+        .await
+        .map_err(db_error)?;
+    Ok((StatusCode::CREATED, Json(BackupResponse::from(snapshot))).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/backups",
+    responses(
+        (status = 200, description = "List the snapshots in the backup directory, newest first", body = Vec<BackupResponse>),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Backups not configured", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn list_backups(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<BackupResponse>>, AppError> {
+    let dir = backup_dir(&state)?;
+    let snapshots = match snapshot::list_snapshots(dir) {
+        Ok(snapshots) => snapshots,
+        // No backup has been taken yet.
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(AppError::UnknownError(e.to_string())),
+    };
+    Ok(Json(snapshots.into_iter().map(Into::into).collect()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/backups/{name}/restore",
+    params(
+        ("name" = String, Path, description = "File name of the snapshot")
+    ),
+    responses(
+        (status = 200, description = "Replace the contents of the database with those of a snapshot", body = BackupResponse),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Backups not configured or snapshot not found", body = ProblemDetail),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn restore_backup(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<BackupResponse>, AppError> {
+    let dir = backup_dir(&state)?;
+    let not_found = || AppError::NotFound(format!("Backup {} not found", name));
+    // Only plain file names, so no file outside the backup directory is read.
+    if name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(not_found());
+    }
+    let snapshot = snapshot::list_snapshots(dir)
+        .unwrap_or_default()
+        .into_iter()
+        .find(|s| s.name == name)
+        .ok_or_else(not_found)?;
+    snapshot::restore_snapshot(state.db.as_ref(), &dir.join(&name))
+        .await
+        .map_err(db_error)?;
+    Ok(Json(snapshot.into()))
+}
+
+/// Records rewritten per batch of a storage upgrade.
+const UPGRADE_BATCH: usize = 500;
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/storage/upgrade",
+    responses(
+        (status = 202, description = "Rewrite every record stored in an older format in the current one, in the background, as a job found at the `Location`", body = JobResponse),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn upgrade_storage(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+) -> Result<Response, AppError> {
+    let db = state.db.clone();
+    let job = spawn_job(
+        &state,
+        "upgrade",
+        serde_json::json!({ "records": 0 }),
+        move |job| async move {
+            let mut records = 0;
+            loop {
+                let upgraded = db
+                    .upgrade_record_formats(UPGRADE_BATCH)
+                    .await
+                    .map_err(db_error)?;
+                if upgraded == 0 {
+                    break;
+                }
+                records += upgraded;
+                job.progress(serde_json::json!({ "records": records }))
+                    .await?;
+            }
+            Ok(serde_json::json!({ "records": records }))
+        },
+    )
+    .await?;
+    Ok(accepted(job).into_response())
+}
+
+/// How much room the records of a collection take.
+#[derive(Serialize, ToSchema)]
+pub struct CollectionStorageResponse {
+    collection: String,
+    records: u64,
+    /// Records stored with their large members compressed.
+    compressed: u64,
+    /// The size of the stored record data.
+    data_bytes: u64,
+    /// How many bytes compression saves on the collection's records.
+    saved_bytes: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct StorageResponse {
+    data_bytes: u64,
+    saved_bytes: u64,
+    collections: Vec<CollectionStorageResponse>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/storage",
+    responses(
+        (status = 200, description = "The room record data takes per collection, and how much compression saves", body = StorageResponse),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn storage(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+) -> Result<Json<StorageResponse>, AppError> {
+    let names: HashMap<i64, String> = state
+        .db
+        .list_collections()
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|c| (c.id, c.name))
+        .collect();
+    let collections: Vec<_> = state
+        .db
+        .storage_stats()
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .filter_map(|s| {
+            Some(CollectionStorageResponse {
+                collection: names.get(&s.collection_id)?.clone(),
+                records: s.records,
+                compressed: s.packed,
+                data_bytes: s.data_bytes,
+                saved_bytes: s.saved_bytes,
+            })
+        })
+        .collect();
+    Ok(Json(StorageResponse {
+        data_bytes: collections.iter().map(|c| c.data_bytes).sum(),
+        saved_bytes: collections.iter().map(|c| c.saved_bytes).sum(),
+        collections,
+    }))
+}
+
+/// Records checked per batch of a scrub.
+const SCRUB_BATCH: usize = 500;
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/checksums/scrub",
+    responses(
+        (status = 202, description = "Check the checksum of every record in the background, as a job found at the `Location`, and list the mismatches at `/admin/checksums`", body = JobResponse),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn scrub_checksums(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+) -> Result<Response, AppError> {
+    let db = state.db.clone();
+    let job = spawn_job(
+        &state,
+        "scrub",
+        serde_json::json!({ "checked": 0, "mismatches": 0 }),
+        move |job| async move {
+            let (mut after, mut checked, mut sealed, mut mismatches) = (0, 0, 0, 0);
+            loop {
+                let batch = db
+                    .scrub_records(after, SCRUB_BATCH)
+                    .await
+                    .map_err(db_error)?;
+                let Some(last) = batch.last_id else {
+                    break;
+                };
+                after = last;
+                checked += batch.checked;
+                sealed += batch.sealed;
+                mismatches += batch.mismatches;
+                job.progress(serde_json::json!({ "checked": checked, "mismatches": mismatches }))
+                    .await?;
+            }
+            Ok(serde_json::json!({
+                "checked": checked,
+                "sealed": sealed,
+                "mismatches": mismatches
+            }))
+        },
+    )
+    .await?;
+    Ok(accepted(job).into_response())
+}
+
+/// A record whose data doesn't match its checksum.
+#[derive(Serialize, ToSchema)]
+pub struct ChecksumMismatchResponse {
+    /// The collection's name, or its id if it is gone.
+    collection: String,
+    record_id: i64,
+    /// The checksum stored when the record was written, in hex.
+    stored: String,
+    /// The checksum of the data as it is now, in hex.
+    computed: String,
+    /// When the scrub that found it ran.
+    detected: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/checksums",
+    responses(
+        (status = 200, description = "The records the last scrub found not matching their checksums", body = Vec<ChecksumMismatchResponse>),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn checksum_mismatches(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ChecksumMismatchResponse>>, AppError> {
+    let names: HashMap<i64, String> = state
+        .db
+        .list_collections()
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|c| (c.id, c.name))
+        .collect();
+    let reports = state
+        .db
+        .list_checksum_mismatches()
+        .await
+        .map_err(db_error)?;
+    Ok(Json(
+        reports
+            .into_iter()
+            .map(|r| ChecksumMismatchResponse {
+                collection: names
+                    .get(&r.collection_id)
+                    .cloned()
+                    .unwrap_or_else(|| r.collection_id.to_string()),
+                record_id: r.mismatch.record_id,
+                stored: format!("{:08x}", r.mismatch.stored),
+                computed: format!("{:08x}", r.mismatch.computed),
+                detected: r.detected,
+            })
+            .collect(),
+    ))
+}
@@ -0,0 +1,196 @@
+//! The admin dashboard served at `/admin`: a static page for browsing
+//! collections, editing their schemas and managing records, for admins who
+//! would rather not use the API directly.
+//!
+//! The page talks to the REST API with the admin API key it signs in for;
+//! besides the static files, this module adds the JSON endpoints it needs
+//! that the rest of the API doesn't offer.
+
+use axum::{
+    extract::State,
+    http::header,
+    response::{IntoResponse, Redirect},
+    routing::get,
+    Json, Router,
+};
+use serde::Serialize;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::{auth::RequireAdmin, db_error, AppError, AppState, ProblemDetail};
+
+const INDEX: &str = include_str!("../admin-ui/index.html");
+const SCRIPT: &str = include_str!("../admin-ui/admin.js");
+const STYLE: &str = include_str!("../admin-ui/admin.css");
+
+/// The routes of the page itself, outside `/api/v1`.
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/admin", get(|| async { Redirect::permanent("/admin/") }))
+        .route(
+            "/admin/",
+            get(|| async { asset("text/html; charset=utf-8", INDEX) }),
+        )
+        .route(
+            "/admin/admin.js",
+            get(|| async { asset("text/javascript; charset=utf-8", SCRIPT) }),
+        )
+        .route(
+            "/admin/admin.css",
+            get(|| async { asset("text/css; charset=utf-8", STYLE) }),
+        )
+}
+
+fn asset(content_type: &'static str, body: &'static str) -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        body,
+    )
+}
+
+/// A field type of collection schemas, as the schema editor offers it.
+#[derive(Serialize, ToSchema)]
+pub struct FieldTypeInfo {
+    /// The name of the type in schemas; relations are declared as
+    /// `{"relation": {"collection_id": ...}}`.
+    name: &'static str,
+    description: &'static str,
+    /// How values are entered: an HTML input type, or `textarea`, `json`,
+    /// `file` or `relation`.
+    input: &'static str,
+    /// The constraints that apply to values of the type.
+    constraints: &'static [&'static str],
+}
+
+const STRING_CONSTRAINTS: &[&str] = &["min_length", "max_length", "pattern"];
+
+const FIELD_TYPES: &[FieldTypeInfo] = &[
+    FieldTypeInfo {
+        name: "string",
+        description: "A line of text",
+        input: "text",
+        constraints: STRING_CONSTRAINTS,
+    },
+    FieldTypeInfo {
+        name: "text",
+        description: "Longer text",
+        input: "textarea",
+        constraints: STRING_CONSTRAINTS,
+    },
+    FieldTypeInfo {
+        name: "number",
+        description: "An integer or decimal number",
+        input: "number",
+        constraints: &["min", "max"],
+    },
+    FieldTypeInfo {
+        name: "boolean",
+        description: "True or false",
+        input: "checkbox",
+        constraints: &[],
+    },
+    FieldTypeInfo {
+        name: "json",
+        description: "Any JSON object or array",
+        input: "json",
+        constraints: &["min_items", "max_items"],
+    },
+    FieldTypeInfo {
+        name: "file",
+        description: "An uploaded file",
+        input: "file",
+        constraints: &[],
+    },
+    FieldTypeInfo {
+        name: "email",
+        description: "An email address",
+        input: "email",
+        constraints: STRING_CONSTRAINTS,
+    },
+    FieldTypeInfo {
+        name: "url",
+        description: "An absolute http(s) URL",
+        input: "url",
+        constraints: STRING_CONSTRAINTS,
+    },
+    FieldTypeInfo {
+        name: "date",
+        description: "An RFC 3339 date, e.g. 2024-05-01",
+        input: "date",
+        constraints: &[],
+    },
+    FieldTypeInfo {
+        name: "datetime",
+        description: "An RFC 3339 date-time, e.g. 2024-05-01T12:00:00Z",
+        input: "text",
+        constraints: &[],
+    },
+    FieldTypeInfo {
+        name: "relation",
+        description: "The id of a record of another collection",
+        input: "relation",
+        constraints: &[],
+    },
+];
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/field-types",
+    responses(
+        (status = 200, description = "The field types of collection schemas, with how to enter their values and the constraints that apply to them", body = Vec<FieldTypeInfo>),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn field_types(_: RequireAdmin) -> Json<&'static [FieldTypeInfo]> {
+    Json(FIELD_TYPES)
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CollectionSummary {
+    id: i64,
+    name: String,
+    group: Option<String>,
+    /// How many records the collection holds.
+    records: i64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/collections",
+    responses(
+        (status = 200, description = "Every collection with its record count, by name", body = Vec<CollectionSummary>),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn list_collections(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<CollectionSummary>>, AppError> {
+    let mut collections = state.db.list_collections().await.map_err(db_error)?;
+    collections.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut summaries = Vec::with_capacity(collections.len());
+    for collection in collections {
+        let records = state
+            .db
+            .count_records(collection.id)
+            .await
+            .map_err(db_error)?;
+        summaries.push(CollectionSummary {
+            id: collection.id,
+            name: collection.name,
+            group: collection.group,
+            records,
+        });
+    }
+    Ok(Json(summaries))
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(field_types, list_collections),
+    components(schemas(FieldTypeInfo, CollectionSummary, ProblemDetail))
+)]
+pub(crate) struct AdminUiApiDoc;
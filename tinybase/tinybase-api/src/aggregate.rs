@@ -0,0 +1,185 @@
+//! Aggregates over the records of a collection: counts, sums, averages and
+//! extremes of fields, optionally per group of records sharing the values of
+//! other fields.
+
+use axum::{
+    extract::{OriginalUri, Path, Query, State},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use tinybase_core::{
+    aggregate::{AggregateFunction, AggregateGroup, AggregateQuery, Aggregation},
+    schema::{CollectionSchema, FieldType},
+};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    access::{Access, Operation},
+    db_error,
+    query_cache::Lookup,
+    resolve_collection, AppError, AppState, ListQuery,
+};
+
+#[derive(Deserialize, IntoParams)]
+pub struct AggregateParams {
+    /// Comma-separated number fields to total, e.g. `price,qty`.
+    sum: Option<String>,
+    /// Comma-separated number fields to average.
+    avg: Option<String>,
+    /// Comma-separated fields to find the smallest value of.
+    min: Option<String>,
+    /// Comma-separated fields to find the largest value of.
+    max: Option<String>,
+    /// Comma-separated fields to group the records by, e.g. `status`.
+    group_by: Option<String>,
+}
+
+fn split(fields: Option<&str>) -> Vec<String> {
+    fields
+        .map(|f| {
+            f.split(',')
+                .map(str::trim)
+                .filter(|f| !f.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl AggregateParams {
+    /// The query the parameters ask for, checked against the fields of
+    /// `schema` when the collection has one.
+    fn query(&self, schema: Option<&CollectionSchema>) -> Result<AggregateQuery, AppError> {
+        let mut query = AggregateQuery {
+            aggregations: Vec::new(),
+            group_by: split(self.group_by.as_deref()),
+        };
+        for function in AggregateFunction::ALL {
+            let fields = match function {
+                AggregateFunction::Sum => &self.sum,
+                AggregateFunction::Avg => &self.avg,
+                AggregateFunction::Min => &self.min,
+                AggregateFunction::Max => &self.max,
+            };
+            for field in split(fields.as_deref()) {
+                query.aggregations.push(Aggregation { function, field });
+            }
+        }
+        let Some(schema) = schema else {
+            return Ok(query);
+        };
+        let fields = query.group_by.iter().map(|field| (field, None)).chain(
+            query
+                .aggregations
+                .iter()
+                .map(|a| (&a.field, Some(a.function))),
+        );
+        for (field, function) in fields {
+            let Some(definition) = schema.fields.get(field) else {
+                return Err(AppError::BadRequest(format!(
+                    "Cannot aggregate unknown field '{}'",
+                    field
+                )));
+            };
+            if let Some(function) = function.filter(|f| f.numeric()) {
+                if definition.r#type != FieldType::Number {
+                    return Err(AppError::BadRequest(format!(
+                        "Cannot {} field '{}'; it is not a number",
+                        function.name(),
+                        field
+                    )));
+                }
+            }
+        }
+        Ok(query)
+    }
+}
+
+/// The aggregates over one group of records.
+#[derive(Serialize, ToSchema)]
+pub struct AggregateGroupResponse {
+    /// The values of the `group_by` fields shared by the records of the
+    /// group; empty without `group_by`.
+    #[schema(value_type = Object)]
+    group: serde_json::Map<String, serde_json::Value>,
+    /// Number of records in the group.
+    count: i64,
+    /// Results by function and field, e.g. `"sum": {"price": 12.5}`; `null`
+    /// when no record of the group has the field.
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    values: serde_json::Map<String, serde_json::Value>,
+}
+
+impl From<AggregateGroup> for AggregateGroupResponse {
+    fn from(g: AggregateGroup) -> Self {
+        AggregateGroupResponse {
+            group: g.group,
+            count: g.count,
+            values: g.values,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/collections/{id}/records/aggregate",
+    params(
+        ("id" = String, Path, description = "Collection id or name"),
+        AggregateParams,
+        ListQuery
+    ),
+    responses(
+        (status = 200, description = "Aggregates over the matching records, one entry per group", body = Vec<AggregateGroupResponse>),
+        (status = 400, description = "Unknown field, or a sum or average over a field that is not a number", body = ProblemDetail),
+        (status = 403, description = "The list rule of the collection depends on the records", body = ProblemDetail),
+        (status = 404, description = "Collection not found", body = ProblemDetail),
+        (status = 504, description = "The query did not finish within the query time limit", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn aggregate_records(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Query(params): Query<AggregateParams>,
+    Query(list): Query<ListQuery>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let db = &state.db;
+    let id = resolve_collection(db.as_ref(), &key).await?;
+    let collection = db
+        .get_collection(id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", key)))?;
+    // Aggregates can't leave out the records a list rule hides, so the rule
+    // must hold without looking at any. It is checked before the cache is,
+    // so cached answers don't depend on it.
+    Access::to(&state, &collection).await?.check(
+        Operation::List,
+        &serde_json::json!({}),
+        &serde_json::Value::Null,
+    )?;
+    let miss =
+        match state
+            .query_cache
+            .lookup(id, collection.schema.as_ref(), Some(&[]), &uri, &headers)
+        {
+            Lookup::Hit(response) => return Ok(response),
+            Lookup::Miss(miss) => Some(miss),
+            Lookup::Uncached => None,
+        };
+    let query = params.query(collection.schema.as_ref())?;
+    let groups = db
+        .aggregate_records(id, &query, &list.options()?)
+        .await
+        .map_err(db_error)?;
+    let groups: Vec<AggregateGroupResponse> = groups.into_iter().map(Into::into).collect();
+    let response = Json(groups).into_response();
+    Ok(match miss {
+        Some(miss) => state.query_cache.store(miss, response).await,
+        None => response,
+    })
+}
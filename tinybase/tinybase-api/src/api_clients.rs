@@ -0,0 +1,471 @@
+//! Postman and Insomnia collections converted from the live OpenAPI
+//! document, so testers can start calling an instance without writing
+//! requests by hand.
+//!
+//! The document is the one served at `/api-docs/openapi.json` plus the typed
+//! record endpoints of every collection, as served at
+//! `/api-docs/collections/{id}/openapi.json`. Each operation becomes a
+//! request, in a folder per area of the API or per collection, with its
+//! path parameters, its query parameters disabled, and a JSON body sketched
+//! from the request schema: examples and defaults where the schema has them,
+//! empty values of the right type elsewhere. Requests use two variables,
+//! `baseUrl` and `token` in Postman, `base_url` and `token` in Insomnia's
+//! base environment, and send the token as `Authorization: Bearer`.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{
+        header::{CONTENT_DISPOSITION, HOST},
+        HeaderMap,
+    },
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use tinybase_core::{clock, format_timestamp, Db};
+use utoipa::IntoParams;
+
+use crate::{api_doc, db_error, docs, AppError, DbState};
+
+/// Deepest nesting of schemas sketched into example bodies.
+const MAX_EXAMPLE_DEPTH: usize = 8;
+
+const METHODS: [&str; 5] = ["get", "post", "put", "patch", "delete"];
+
+/// The API client to export for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClientFormat {
+    /// A Postman collection, format 2.1.
+    Postman,
+    /// An Insomnia export, format 4.
+    Insomnia,
+}
+
+impl FromStr for ClientFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "postman" => Ok(ClientFormat::Postman),
+            "insomnia" => Ok(ClientFormat::Insomnia),
+            _ => Err(format!("'{}' is not postman or insomnia", s)),
+        }
+    }
+}
+
+impl ClientFormat {
+    /// The name clients save the export as.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            ClientFormat::Postman => "tinybase.postman_collection.json",
+            ClientFormat::Insomnia => "tinybase.insomnia.json",
+        }
+    }
+}
+
+/// The OpenAPI document of the API with the typed record endpoints of every
+/// collection merged in, under a tag named after the collection.
+pub async fn live_document(db: &dyn Db) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+    let mut doc = serde_json::to_value(api_doc())?;
+    for collection in db.list_collections().await? {
+        let typed = docs::collection_document(&collection);
+        let tag = json!({
+            "name": collection.name,
+            "description": format!("Records of the {} collection", collection.name),
+        });
+        match doc["tags"].as_array_mut() {
+            Some(tags) => tags.push(tag),
+            None => doc["tags"] = json!([tag]),
+        }
+        if let Some(paths) = typed["paths"].as_object() {
+            for (path, item) in paths {
+                let mut item = item.clone();
+                for method in METHODS {
+                    if let Some(operation) = item.get_mut(method) {
+                        operation["tags"] = json!([collection.name]);
+                    }
+                }
+                doc["paths"][path] = item;
+            }
+        }
+        if let (Some(schemas), Some(typed)) = (
+            doc["components"]["schemas"].as_object_mut(),
+            typed["components"]["schemas"].as_object(),
+        ) {
+            for (name, schema) in typed {
+                schemas
+                    .entry(name.clone())
+                    .or_insert_with(|| schema.clone());
+            }
+        }
+    }
+    Ok(doc)
+}
+
+/// One operation of the document, as the clients need it.
+struct Operation<'a> {
+    folder: String,
+    name: String,
+    description: Option<&'a str>,
+    method: String,
+    path: &'a str,
+    path_params: Vec<(&'a str, Option<&'a str>)>,
+    query_params: Vec<(&'a str, Option<&'a str>)>,
+    body: Option<Value>,
+}
+
+/// The folder of an operation without a declared tag: the area of the API
+/// its path is in, e.g. `collections` or `admin/webhooks`.
+fn folder(path: &str) -> String {
+    let mut segments = path
+        .trim_start_matches("/api/v1")
+        .split('/')
+        .filter(|s| !s.is_empty() && !s.starts_with('{'));
+    match segments.next() {
+        Some("admin") => match segments.next() {
+            Some(area) => format!("admin/{}", area),
+            None => "admin".to_string(),
+        },
+        Some(area) => area.to_string(),
+        None => "api".to_string(),
+    }
+}
+
+/// The schema `schema` refers to, if it is a reference into `doc`.
+fn resolve<'a>(doc: &'a Value, schema: &'a Value) -> &'a Value {
+    match schema["$ref"]
+        .as_str()
+        .and_then(|r| r.strip_prefix("#/components/schemas/"))
+    {
+        Some(name) => &doc["components"]["schemas"][name],
+        None => schema,
+    }
+}
+
+/// A value fitting `schema`, for a request body to start from.
+fn example(doc: &Value, schema: &Value, depth: usize) -> Value {
+    let schema = resolve(doc, schema);
+    if let Some(example) = schema["examples"].get(0).or(schema.get("example")) {
+        return example.clone();
+    }
+    if let Some(default) = schema.get("default") {
+        return default.clone();
+    }
+    if depth >= MAX_EXAMPLE_DEPTH {
+        return Value::Null;
+    }
+    for combined in ["allOf", "oneOf", "anyOf"] {
+        if let Some(first) = schema[combined].get(0) {
+            return example(doc, first, depth + 1);
+        }
+    }
+    let kind = match &schema["type"] {
+        Value::String(kind) => kind.as_str(),
+        Value::Array(kinds) => kinds
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|k| *k != "null")
+            .unwrap_or("null"),
+        _ if schema.get("properties").is_some() => "object",
+        _ => "null",
+    };
+    match kind {
+        "object" => {
+            let properties = schema["properties"].as_object();
+            let body: Map<String, Value> = properties
+                .into_iter()
+                .flatten()
+                .filter(|(_, p)| p["readOnly"] != true)
+                .map(|(name, p)| (name.clone(), example(doc, p, depth + 1)))
+                .collect();
+            Value::Object(body)
+        }
+        "array" => json!([]),
+        "string" => json!(""),
+        "integer" | "number" => json!(0),
+        "boolean" => json!(false),
+        _ => Value::Null,
+    }
+}
+
+/// The operations of `doc`, by path and method.
+fn operations(doc: &Value) -> Vec<Operation<'_>> {
+    let mut operations = Vec::new();
+    let Some(paths) = doc["paths"].as_object() else {
+        return operations;
+    };
+    // Operations may carry tags the document doesn't declare, such as the
+    // Rust modules utoipa tags them with; only declared ones are folders.
+    let declared: Vec<&str> = doc["tags"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|tag| tag["name"].as_str())
+        .collect();
+    for (path, item) in paths {
+        for method in METHODS {
+            let Some(operation) = item.get(method) else {
+                continue;
+            };
+            let parameters = item["parameters"]
+                .as_array()
+                .into_iter()
+                .chain(operation["parameters"].as_array())
+                .flatten();
+            let (mut path_params, mut query_params) = (Vec::new(), Vec::new());
+            for parameter in parameters {
+                let Some(name) = parameter["name"].as_str() else {
+                    continue;
+                };
+                let description = parameter["description"].as_str();
+                match parameter["in"].as_str() {
+                    Some("path") => path_params.push((name, description)),
+                    Some("query") => query_params.push((name, description)),
+                    _ => {}
+                }
+            }
+            let body = operation["requestBody"]["content"]["application/json"]
+                .get("schema")
+                .map(|schema| example(doc, schema, 0));
+            let folder = match operation["tags"][0].as_str() {
+                Some(tag) if declared.contains(&tag) => tag.to_string(),
+                _ => folder(path),
+            };
+            let name = operation["summary"]
+                .as_str()
+                .or(operation["operationId"].as_str())
+                .map(String::from)
+                .unwrap_or_else(|| format!("{} {}", method.to_uppercase(), path));
+            operations.push(Operation {
+                folder,
+                name,
+                description: operation["description"].as_str(),
+                method: method.to_uppercase(),
+                path,
+                path_params,
+                query_params,
+                body,
+            });
+        }
+    }
+    operations
+}
+
+/// `path` with its `{param}`s written as `:param`, as both clients take path
+/// parameters.
+fn colon_params(path: &str) -> String {
+    path.split('/')
+        .map(
+            |segment| match segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                Some(name) => format!(":{}", name),
+                None => segment.to_string(),
+            },
+        )
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn pretty(body: &Value) -> String {
+    serde_json::to_string_pretty(body).unwrap_or_default()
+}
+
+/// A Postman 2.1 collection of `doc`'s operations, with `baseUrl` and
+/// `token` collection variables.
+fn postman(doc: &Value, base_url: &str) -> Value {
+    let mut folders: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+    for operation in operations(doc) {
+        let path = colon_params(operation.path);
+        let mut request = json!({
+            "method": operation.method,
+            "header": [],
+            "url": {
+                "raw": format!("{{{{baseUrl}}}}{}", path),
+                "host": ["{{baseUrl}}"],
+                "path": path.trim_start_matches('/').split('/').collect::<Vec<_>>(),
+                "variable": operation.path_params.iter().map(|(name, description)| json!({
+                    "key": name,
+                    "value": "",
+                    "description": description.unwrap_or_default(),
+                })).collect::<Vec<_>>(),
+                "query": operation.query_params.iter().map(|(name, description)| json!({
+                    "key": name,
+                    "value": "",
+                    "description": description.unwrap_or_default(),
+                    "disabled": true,
+                })).collect::<Vec<_>>(),
+            },
+        });
+        if let Some(description) = operation.description {
+            request["description"] = json!(description);
+        }
+        if let Some(body) = &operation.body {
+            request["header"] = json!([{ "key": "Content-Type", "value": "application/json" }]);
+            request["body"] = json!({
+                "mode": "raw",
+                "raw": pretty(body),
+                "options": { "raw": { "language": "json" } },
+            });
+        }
+        folders
+            .entry(operation.folder)
+            .or_default()
+            .push(json!({ "name": operation.name, "request": request }));
+    }
+    json!({
+        "info": {
+            "name": doc["info"]["title"].as_str().unwrap_or("Tinybase"),
+            "description": "Exported from the OpenAPI document of a Tinybase instance.",
+            "schema": "https://schema.getpostman.com/json/collection/v2.1.0/collection.json",
+        },
+        "auth": {
+            "type": "bearer",
+            "bearer": [{ "key": "token", "value": "{{token}}", "type": "string" }],
+        },
+        "variable": [
+            { "key": "baseUrl", "value": base_url },
+            { "key": "token", "value": "" },
+        ],
+        "item": folders
+            .into_iter()
+            .map(|(name, item)| json!({ "name": name, "item": item }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// An Insomnia export of `doc`'s operations in a workspace of their own,
+/// whose base environment holds `base_url` and `token`.
+fn insomnia(doc: &Value, base_url: &str) -> Value {
+    const WORKSPACE: &str = "wrk_tinybase";
+    let mut resources = vec![
+        json!({
+            "_id": WORKSPACE,
+            "_type": "workspace",
+            "parentId": null,
+            "name": doc["info"]["title"].as_str().unwrap_or("Tinybase"),
+            "description": "Exported from the OpenAPI document of a Tinybase instance.",
+            "scope": "collection",
+        }),
+        json!({
+            "_id": "env_tinybase",
+            "_type": "environment",
+            "parentId": WORKSPACE,
+            "name": "Base Environment",
+            "data": { "base_url": base_url, "token": "" },
+        }),
+    ];
+    let mut folders: BTreeMap<String, String> = BTreeMap::new();
+    for (n, operation) in operations(doc).into_iter().enumerate() {
+        let next = folders.len();
+        let folder = folders.entry(operation.folder.clone()).or_insert_with(|| {
+            let id = format!("fld_{}", next);
+            resources.push(json!({
+                "_id": id,
+                "_type": "request_group",
+                "parentId": WORKSPACE,
+                "name": operation.folder,
+            }));
+            id
+        });
+        let mut request = json!({
+            "_id": format!("req_{}", n),
+            "_type": "request",
+            "parentId": folder,
+            "name": operation.name,
+            "description": operation.description.unwrap_or_default(),
+            "method": operation.method,
+            "url": format!("{{{{ _.base_url }}}}{}", colon_params(operation.path)),
+            "pathParameters": operation.path_params.iter().map(|(name, _)| json!({
+                "name": name,
+                "value": "",
+            })).collect::<Vec<_>>(),
+            "parameters": operation.query_params.iter().map(|(name, description)| json!({
+                "name": name,
+                "value": "",
+                "description": description.unwrap_or_default(),
+                "disabled": true,
+            })).collect::<Vec<_>>(),
+            "headers": [],
+            "body": {},
+            "authentication": { "type": "bearer", "token": "{{ _.token }}" },
+        });
+        if let Some(body) = &operation.body {
+            request["headers"] = json!([{ "name": "Content-Type", "value": "application/json" }]);
+            request["body"] = json!({ "mimeType": "application/json", "text": pretty(body) });
+        }
+        resources.push(request);
+    }
+    json!({
+        "_type": "export",
+        "__export_format": 4,
+        "__export_date": format_timestamp(clock::now()),
+        "__export_source": "tinybase",
+        "resources": resources,
+    })
+}
+
+/// `doc` as a collection for `format`, calling the instance at `base_url`.
+pub fn convert(doc: &Value, format: ClientFormat, base_url: &str) -> Value {
+    let base_url = base_url.trim_end_matches('/');
+    match format {
+        ClientFormat::Postman => postman(doc, base_url),
+        ClientFormat::Insomnia => insomnia(doc, base_url),
+    }
+}
+
+/// The live document of the instance on `db` as a collection for `format`.
+pub async fn export(
+    db: &dyn Db,
+    format: ClientFormat,
+    base_url: &str,
+) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(convert(&live_document(db).await?, format, base_url))
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ExportQuery {
+    /// The URL the requests go to; the host the export was asked from,
+    /// over HTTP, by default.
+    base_url: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api-docs/clients/{format}",
+    params(
+        ("format" = String, Path, description = "`postman` or `insomnia`"),
+        ExportQuery
+    ),
+    responses(
+        (status = 200, description = "The API, the record endpoints of every collection included, as a Postman collection or Insomnia export with `baseUrl`/`base_url` and `token` variables", body = Object),
+        (status = 400, description = "Unknown format", body = ProblemDetail),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn export_client(
+    State(db): State<DbState>,
+    Path(format): Path<String>,
+    Query(query): Query<ExportQuery>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    let format = ClientFormat::from_str(&format).map_err(AppError::BadRequest)?;
+    let base_url = query.base_url.unwrap_or_else(|| {
+        let host = headers
+            .get(HOST)
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or("localhost:3000");
+        format!("http://{}", host)
+    });
+    let export = export(db.as_ref(), format, &base_url)
+        .await
+        .map_err(db_error)?;
+    Ok((
+        [(
+            CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", format.file_name()),
+        )],
+        Json(export),
+    ))
+}
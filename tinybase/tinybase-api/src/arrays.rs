@@ -0,0 +1,115 @@
+//! Array operations: pushing onto, removing from or adding to a set-like
+//! array field of a record in one SQL update, so tag lists don't need a
+//! read-modify-write of the whole record that concurrent writers would race.
+//! Script hooks don't run on them.
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use tinybase_core::{
+    arrays::ArrayOp,
+    events::{Event, EventAction},
+    schema::FieldType,
+    validation::{pointer, validate_value},
+};
+use utoipa::ToSchema;
+
+use crate::{
+    access::{check_record, Operation},
+    counters::is_derived,
+    db_error, resolve_collection,
+    shape::shape_records,
+    AppError, AppState, RecordResponse,
+};
+
+#[derive(Deserialize, ToSchema)]
+pub struct ArrayUpdateRequest {
+    /// The array field to change; a missing field counts as empty.
+    field: String,
+    /// `push`, `remove` (every equal element) or `add_unique`.
+    #[schema(value_type = String, example = "add_unique")]
+    op: ArrayOp,
+    /// The element to push, remove or add.
+    #[schema(value_type = Object)]
+    value: Value,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/collections/{id}/records/{record_id}/array",
+    params(
+        ("id" = String, Path, description = "Collection id or name"),
+        ("record_id" = i64, Path, description = "Record id")
+    ),
+    request_body = ArrayUpdateRequest,
+    responses(
+        (status = 200, description = "Change an array field of a record atomically, returning the updated record", body = RecordResponse),
+        (status = 400, description = "The field is not a JSON or array field, or is ordered, a tree parent or read by a computed field", body = ProblemDetail),
+        (status = 403, description = "A collection rule denies the request", body = ProblemDetail),
+        (status = 404, description = "Collection or record not found", body = ProblemDetail),
+        (status = 422, description = "The field holds something other than an array, or the value is not an item of its type", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn update_array(
+    State(state): State<AppState>,
+    Path((key, record_id)): Path<(String, i64)>,
+    Json(request): Json<ArrayUpdateRequest>,
+) -> Result<Json<RecordResponse>, AppError> {
+    let field = request.field.trim();
+    if field.is_empty() {
+        return Err(AppError::BadRequest("Name the array field".to_string()));
+    }
+    let db = &state.db;
+    let collection_id = resolve_collection(db.as_ref(), &key).await?;
+    let collection = db
+        .get_collection(collection_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", collection_id)))?;
+    if let Some(schema) = &collection.schema {
+        if let Some(definition) = schema.fields.get(field) {
+            match &definition.r#type {
+                FieldType::Json => {}
+                // Elements added must be items of the array's type.
+                FieldType::Array { items } => {
+                    if request.op != ArrayOp::Remove {
+                        // `-` points past the end of an array.
+                        let path = format!("{}/-", pointer("", field));
+                        validate_value(&path, items, &request.value)
+                            .map_err(AppError::Validation)?;
+                    }
+                }
+                _ => {
+                    return Err(AppError::BadRequest(format!(
+                        "Field '{}' is not a JSON or array field",
+                        field
+                    )))
+                }
+            }
+        }
+        if is_derived(schema, field) {
+            return Err(AppError::BadRequest(format!(
+                "Field '{}' is computed, ordered or a tree parent and can't be changed in place",
+                field
+            )));
+        }
+    }
+    let data = serde_json::json!({ field: request.value.clone() });
+    check_record(&state, collection_id, record_id, Operation::Update, &data).await?;
+    let record = db
+        .update_array(collection_id, record_id, field, request.op, &request.value)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| AppError::NotFound(format!("Record {} not found", record_id)))?;
+    state.events.publish(Event::new(
+        EventAction::RecordUpdated,
+        collection_id,
+        Some(record_id),
+    ));
+    let mut response = RecordResponse::from(record);
+    shape_records(&state, collection_id, [&mut response]).await?;
+    Ok(Json(response))
+}
@@ -0,0 +1,650 @@
+//! Admin accounts and the authorization of collection management.
+//!
+//! Admins authenticate at `/api/v1/admin/auth` and get an API key, sent back
+//! as `Authorization: Bearer <key>`. Creating, updating and deleting
+//! collections requires one; record CRUD stays governed by collection rules.
+//! Until the first admin is created the instance is in setup mode and every
+//! endpoint is open, so a fresh instance can be bootstrapped.
+//!
+//! Services in front of or beside Tinybase, e.g. a gateway, check the keys
+//! presented to them at `/api/v1/auth/introspect` rather than keeping their
+//! own copy of the sessions.
+//!
+//! With the `jwt` feature, tokens of an external identity provider are
+//! trusted as well, see [`jwt`](crate::jwt). Setup mode then never applies:
+//! admins may come from the provider alone.
+//!
+//! Admin accounts move between instances with their password hashes, so
+//! nobody has to reset a password, as a [`UsersDocument`]:
+//!
+//! ```json
+//! {
+//!   "format": "tinybase.users",
+//!   "version": 1,
+//!   "users": [{ "email": "ada@example.com", "password_hash": "$argon2id$v=19$..." }]
+//! }
+//! ```
+//!
+//! Hashes are PHC strings of Argon2 (`$argon2id$`, `$argon2i$`, `$argon2d$`),
+//! which Tinybase writes, or bcrypt (`$2a$`, `$2b$`, `$2y$`), which many
+//! other systems do; both are verified at sign-in. Accounts of the identity
+//! provider aren't stored, so there are no provider links to carry over.
+
+use argon2::{
+    password_hash::{rand_core::OsRng, rand_core::RngCore, SaltString},
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+};
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Request, State},
+    http::{header::AUTHORIZATION, request::Parts, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::sync::OnceLock;
+use std::time::{Duration, UNIX_EPOCH};
+use tinybase_core::{
+    clock,
+    events::{Event, EventAction},
+    format_timestamp,
+    lineage::{with_lineage, Lineage, LineageSource},
+    tokens::{ApiToken, TokenScope},
+    Admin,
+};
+use utoipa::ToSchema;
+
+#[cfg(feature = "jwt")]
+use crate::jwt::Identity;
+use crate::{
+    db_error,
+    tokens::{self, TOKEN_PREFIX},
+    AppError, AppState,
+};
+
+/// How long an API key issued by `/admin/auth` stays valid.
+const KEY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// The `format` of a [`UsersDocument`].
+const USERS_FORMAT: &str = "tinybase.users";
+
+/// The `version` of [`USERS_FORMAT`] written and read.
+const USERS_FORMAT_VERSION: u32 = 1;
+
+/// The store entry of API key `key`, named by its hash.
+fn key_entry(key: &str) -> String {
+    format!("admin_key:{}", tokens::hash(key))
+}
+
+/// What an API key grants, as kept in the shared store.
+#[derive(Serialize, Deserialize)]
+struct KeyGrant {
+    admin_id: i64,
+    /// Seconds since the Unix epoch.
+    expires: u64,
+}
+
+/// The bearer token of a request, if any.
+pub(crate) fn bearer(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+/// The admin `key` was issued to and when it expires, unless the key is
+/// unknown or expired. Keys of deleted admins are no longer honoured.
+async fn resolve_key(state: &AppState, key: &str) -> Result<Option<(Admin, u64)>, AppError> {
+    let Some(entry) = state.store.get(&key_entry(key)).await? else {
+        return Ok(None);
+    };
+    let Ok(grant) = serde_json::from_slice::<KeyGrant>(&entry) else {
+        return Ok(None);
+    };
+    let admin = state.db.get_admin(grant.admin_id).await.map_err(db_error)?;
+    Ok(admin.map(|admin| (admin, grant.expires)))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct AdminCredentials {
+    email: String,
+    password: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AdminResponse {
+    id: i64,
+    email: String,
+    created: String,
+    updated: String,
+}
+
+impl From<Admin> for AdminResponse {
+    fn from(a: Admin) -> Self {
+        AdminResponse {
+            id: a.id,
+            email: a.email,
+            created: a.created,
+            updated: a.updated,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AdminAuthResponse {
+    /// API key to send as `Authorization: Bearer <key>`.
+    key: String,
+    /// Seconds until the key expires.
+    expires_in: u64,
+    admin: AdminResponse,
+}
+
+/// Who a bearer token identifies.
+enum Bearer {
+    /// An API key issued by `/admin/auth`, with its expiry.
+    Admin(Admin, u64),
+    /// An API token minted by an admin; see [`tokens`](crate::tokens).
+    Token(ApiToken),
+    /// A token of the trusted identity provider.
+    #[cfg(feature = "jwt")]
+    External(Identity),
+}
+
+impl Bearer {
+    /// Whom the token was issued to, e.g. `admin:1`.
+    fn subject(&self) -> String {
+        match self {
+            Bearer::Admin(admin, _) => format!("admin:{}", admin.id),
+            Bearer::Token(token) => format!("token:{}", token.id),
+            #[cfg(feature = "jwt")]
+            Bearer::External(identity) => identity.subject.clone(),
+        }
+    }
+}
+
+/// Resolves `token` as an API key, then as a token of the identity provider.
+async fn resolve_bearer(state: &AppState, token: &str) -> Result<Option<Bearer>, AppError> {
+    if token.starts_with(TOKEN_PREFIX) {
+        return Ok(tokens::resolve(state, token).await?.map(Bearer::Token));
+    }
+    if let Some((admin, expires)) = resolve_key(state, token).await? {
+        return Ok(Some(Bearer::Admin(admin, expires)));
+    }
+    #[cfg(feature = "jwt")]
+    if let Some(verifier) = &state.jwt {
+        return match verifier.verify(token).await {
+            Ok(identity) => Ok(Some(Bearer::External(identity))),
+            Err(error) => {
+                tracing::debug!(%error, "bearer token rejected");
+                Ok(None)
+            }
+        };
+    }
+    Ok(None)
+}
+
+/// Rejects the request unless it carries a valid admin API key or admin
+/// token of the identity provider, or no admin exists yet.
+pub(crate) struct RequireAdmin;
+
+#[async_trait]
+impl FromRequestParts<AppState> for RequireAdmin {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, AppError> {
+        if in_setup_mode(state).await? {
+            return Ok(RequireAdmin);
+        }
+        let key = bearer(&parts.headers)
+            .ok_or_else(|| AppError::Unauthorized("An admin API key is required".to_string()))?;
+        match resolve_bearer(state, key).await? {
+            Some(Bearer::Admin(..)) => Ok(RequireAdmin),
+            Some(Bearer::Token(_)) => Err(AppError::Forbidden(
+                "API tokens only reach the records of their collections".to_string(),
+            )),
+            #[cfg(feature = "jwt")]
+            Some(Bearer::External(identity)) if identity.admin => Ok(RequireAdmin),
+            #[cfg(feature = "jwt")]
+            Some(Bearer::External(_)) => Err(AppError::Unauthorized(
+                "The token does not carry the admin role".to_string(),
+            )),
+            None => Err(AppError::Unauthorized(
+                "Invalid or expired admin API key".to_string(),
+            )),
+        }
+    }
+}
+
+/// Whether the instance has no admin yet and no identity provider, leaving
+/// every endpoint open.
+async fn in_setup_mode(state: &AppState) -> Result<bool, AppError> {
+    #[cfg(feature = "jwt")]
+    let setup = state.jwt.is_none();
+    #[cfg(not(feature = "jwt"))]
+    let setup = true;
+    Ok(setup && state.db.count_admins().await.map_err(db_error)? == 0)
+}
+
+tokio::task_local! {
+    /// The bearer token of the request being handled, which decides what it
+    /// sees of masked fields.
+    static VIEWER_TOKEN: Option<String>;
+}
+
+/// Remembers the bearer token of the request for [`viewer_roles`].
+pub(crate) async fn remember_viewer(request: Request, next: Next) -> Response {
+    let token = bearer(request.headers()).map(str::to_string);
+    VIEWER_TOKEN.scope(token, next.run(request)).await
+}
+
+/// Attributes the records a write request creates to whoever sent it, with
+/// [`Lineage`] of source `api`; importers and hooks narrow it further.
+pub(crate) async fn track_lineage(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    ) {
+        return next.run(request).await;
+    }
+    let actor = match bearer(request.headers()) {
+        Some(token) => resolve_bearer(&state, token)
+            .await
+            .ok()
+            .flatten()
+            .map(|bearer| bearer.subject()),
+        None => None,
+    };
+    let lineage = Lineage::new(LineageSource::Api).with_actor(actor);
+    with_lineage(lineage, next.run(request)).await
+}
+
+/// The roles of whoever the request being handled is for, or `None` for
+/// admins, who see every field unmasked. Requests without a valid token have
+/// no roles.
+pub(crate) async fn viewer_roles(state: &AppState) -> Result<Option<Vec<String>>, AppError> {
+    if in_setup_mode(state).await? {
+        return Ok(None);
+    }
+    let Some(token) = VIEWER_TOKEN.try_with(Clone::clone).ok().flatten() else {
+        return Ok(Some(Vec::new()));
+    };
+    Ok(match resolve_bearer(state, &token).await? {
+        Some(Bearer::Admin(..)) => None,
+        Some(Bearer::Token(_)) => Some(Vec::new()),
+        #[cfg(feature = "jwt")]
+        Some(Bearer::External(identity)) if identity.admin => None,
+        #[cfg(feature = "jwt")]
+        Some(Bearer::External(identity)) => Some(identity.roles),
+        None => Some(Vec::new()),
+    })
+}
+
+/// Whom collection rules see the request being handled as, `@request.auth`:
+/// an empty `id` when it carries no valid token, the subject, roles and
+/// claims of a token of the identity provider. `None` when rules don't hold
+/// it: for admins, for API tokens, which [`tokens`] holds to their scopes
+/// instead, in setup mode and outside of requests.
+pub(crate) async fn rule_auth(state: &AppState) -> Result<Option<Value>, AppError> {
+    let Ok(token) = VIEWER_TOKEN.try_with(Clone::clone) else {
+        return Ok(None);
+    };
+    if in_setup_mode(state).await? {
+        return Ok(None);
+    }
+    let anonymous = json!({ "id": "", "roles": [] });
+    let Some(token) = token else {
+        return Ok(Some(anonymous));
+    };
+    Ok(match resolve_bearer(state, &token).await? {
+        Some(Bearer::Admin(..) | Bearer::Token(_)) => None,
+        #[cfg(feature = "jwt")]
+        Some(Bearer::External(identity)) if identity.admin => None,
+        #[cfg(feature = "jwt")]
+        Some(Bearer::External(identity)) => Some(json!({
+            "id": identity.subject,
+            "roles": identity.roles,
+            "claims": identity.claims,
+        })),
+        None => Some(anonymous),
+    })
+}
+
+fn hash_password(password: &str) -> Result<String, AppError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| AppError::UnknownError(e.to_string()))
+}
+
+/// An Argon2 hash of a random password, which no password matches.
+fn dummy_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| hash_password(&generate_key()).unwrap_or_default())
+}
+
+/// Whether `hash` is a bcrypt hash, as imported from other systems.
+fn is_bcrypt(hash: &str) -> bool {
+    ["$2a$", "$2b$", "$2y$"].iter().any(|p| hash.starts_with(p))
+}
+
+/// Whether admins with password hash `hash` can sign in.
+fn is_supported_hash(hash: &str) -> bool {
+    if is_bcrypt(hash) {
+        return hash.parse::<bcrypt::HashParts>().is_ok();
+    }
+    PasswordHash::new(hash).is_ok_and(|hash| hash.algorithm.as_str().starts_with("argon2"))
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    if is_bcrypt(hash) {
+        return bcrypt::verify(password, hash).unwrap_or(false);
+    }
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+pub(crate) fn generate_key() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/auth",
+    request_body = AdminCredentials,
+    responses(
+        (status = 200, description = "Issue an admin API key", body = AdminAuthResponse),
+        (status = 401, description = "Invalid email or password", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn authenticate(
+    State(state): State<AppState>,
+    Json(credentials): Json<AdminCredentials>,
+) -> Result<Json<AdminAuthResponse>, AppError> {
+    let admin = state
+        .db
+        .get_admin_by_email(&credentials.email)
+        .await
+        .map_err(db_error)?;
+    // Unknown emails are checked against a hash too, so they take as long.
+    let verified = match &admin {
+        Some(admin) => verify_password(&credentials.password, &admin.password_hash),
+        None => verify_password(&credentials.password, dummy_hash()),
+    };
+    let admin = admin
+        .filter(|_| verified)
+        .ok_or_else(|| AppError::Unauthorized("Invalid email or password".to_string()))?;
+    let key = generate_key();
+    let expires = clock::now() + KEY_TTL;
+    let grant = KeyGrant {
+        admin_id: admin.id,
+        expires: expires
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+    };
+    let grant = serde_json::to_vec(&grant).map_err(|e| AppError::JsonError(e.to_string()))?;
+    state
+        .store
+        .set(&key_entry(&key), &grant, Some(KEY_TTL))
+        .await?;
+    state
+        .events
+        .publish(Event::admin(EventAction::AdminAuthenticated, admin.id));
+    Ok(Json(AdminAuthResponse {
+        key,
+        expires_in: KEY_TTL.as_secs(),
+        admin: admin.into(),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/admins",
+    request_body = AdminCredentials,
+    responses(
+        (status = 201, description = "Create an admin; open only while no admin exists", body = AdminResponse),
+        (status = 400, description = "Invalid email or password too short", body = ProblemDetail),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 409, description = "Email already taken", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn create_admin(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Json(credentials): Json<AdminCredentials>,
+) -> Result<(StatusCode, Json<AdminResponse>), AppError> {
+    let email = credentials.email.trim();
+    if !email.contains('@') {
+        return Err(AppError::BadRequest(format!(
+            "'{}' is not an email address",
+            email
+        )));
+    }
+    if credentials.password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(AppError::BadRequest(format!(
+            "Passwords must be at least {} characters long",
+            MIN_PASSWORD_LENGTH
+        )));
+    }
+    let db = &state.db;
+    if db
+        .get_admin_by_email(email)
+        .await
+        .map_err(db_error)?
+        .is_some()
+    {
+        return Err(AppError::Conflict(format!(
+            "An admin with email '{}' already exists",
+            email
+        )));
+    }
+    let hash = hash_password(&credentials.password)?;
+    let admin = db.create_admin(email, &hash).await.map_err(db_error)?;
+    Ok((StatusCode::CREATED, Json(admin.into())))
+}
+
+/// Admin accounts with their password hashes, as exported and imported.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct UsersDocument {
+    /// Always `tinybase.users`.
+    format: String,
+    /// Always 1.
+    version: u32,
+    users: Vec<ExportedUser>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ExportedUser {
+    email: String,
+    /// PHC string of an Argon2 or bcrypt hash.
+    password_hash: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UsersImportResponse {
+    /// Emails of the admins created.
+    imported: Vec<String>,
+    /// Emails already taken here, whose accounts were left as they are.
+    skipped: Vec<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/admins/export",
+    responses(
+        (status = 200, description = "Export every admin account with its password hash", body = UsersDocument),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn export_admins(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+) -> Result<Json<UsersDocument>, AppError> {
+    let admins = state.db.list_admins().await.map_err(db_error)?;
+    Ok(Json(UsersDocument {
+        format: USERS_FORMAT.to_string(),
+        version: USERS_FORMAT_VERSION,
+        users: admins
+            .into_iter()
+            .map(|a| ExportedUser {
+                email: a.email,
+                password_hash: a.password_hash,
+            })
+            .collect(),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/admins/import",
+    request_body = UsersDocument,
+    responses(
+        (status = 200, description = "Create the admins of an export, keeping their password hashes; emails already taken are skipped. Nothing is imported unless every account is valid", body = UsersImportResponse),
+        (status = 400, description = "Unknown format or version, invalid email, duplicate email or unsupported password hash", body = ProblemDetail),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn import_admins(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Json(document): Json<UsersDocument>,
+) -> Result<Json<UsersImportResponse>, AppError> {
+    if document.format != USERS_FORMAT || document.version != USERS_FORMAT_VERSION {
+        return Err(AppError::BadRequest(format!(
+            "Expected format '{}' version {}, got '{}' version {}",
+            USERS_FORMAT, USERS_FORMAT_VERSION, document.format, document.version
+        )));
+    }
+    let mut emails = HashSet::new();
+    for user in &document.users {
+        let email = user.email.trim();
+        if !email.contains('@') {
+            return Err(AppError::BadRequest(format!(
+                "'{}' is not an email address",
+                email
+            )));
+        }
+        if !emails.insert(email) {
+            return Err(AppError::BadRequest(format!(
+                "'{}' appears more than once",
+                email
+            )));
+        }
+        if !is_supported_hash(&user.password_hash) {
+            return Err(AppError::BadRequest(format!(
+                "The password hash of '{}' is not an Argon2 or bcrypt PHC string",
+                email
+            )));
+        }
+    }
+    let db = &state.db;
+    let (mut imported, mut skipped) = (Vec::new(), Vec::new());
+    for user in &document.users {
+        let email = user.email.trim();
+        if db
+            .get_admin_by_email(email)
+            .await
+            .map_err(db_error)?
+            .is_some()
+        {
+            skipped.push(email.to_string());
+            continue;
+        }
+        db.create_admin(email, &user.password_hash)
+            .await
+            .map_err(db_error)?;
+        imported.push(email.to_string());
+    }
+    Ok(Json(UsersImportResponse { imported, skipped }))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TokenIntrospection {
+    /// Whom the token was issued to: e.g. `admin:1` for an admin API key,
+    /// `token:1` for an API token, the `sub` claim for a token of the
+    /// identity provider.
+    subject: String,
+    /// What the token allows: `admin` for admin API keys, e.g. `posts:read`
+    /// for API tokens, the roles claimed by a token of the identity provider.
+    scopes: Vec<String>,
+    /// When the token expires, unless it was issued without a recorded expiry.
+    expires: Option<String>,
+    /// Seconds until then.
+    expires_in: Option<u64>,
+    /// The admin account an API key belongs to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    admin: Option<AdminResponse>,
+    /// The claims of a token of the identity provider.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    claims: Option<serde_json::Value>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/auth/introspect",
+    responses(
+        (status = 200, description = "The token sent as `Authorization: Bearer <token>` is valid", body = TokenIntrospection),
+        (status = 401, description = "No token, or an invalid or expired one", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn introspect(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<TokenIntrospection>, AppError> {
+    let key = bearer(&headers)
+        .ok_or_else(|| AppError::Unauthorized("A bearer token is required".to_string()))?;
+    let bearer = resolve_bearer(&state, key)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("Invalid or expired token".to_string()))?;
+    let subject = bearer.subject();
+    let (scopes, expires, admin, claims) = match bearer {
+        Bearer::Admin(admin, expires) => (
+            vec!["admin".to_string()],
+            Some(expires),
+            Some(admin.into()),
+            None,
+        ),
+        Bearer::Token(token) => (
+            token.scopes.iter().map(TokenScope::name).collect(),
+            None,
+            None,
+            None,
+        ),
+        #[cfg(feature = "jwt")]
+        Bearer::External(identity) => (
+            identity.roles,
+            identity.expires,
+            None,
+            Some(identity.claims),
+        ),
+    };
+    let now = clock::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    Ok(Json(TokenIntrospection {
+        subject,
+        scopes,
+        expires: expires.map(|secs| format_timestamp(UNIX_EPOCH + Duration::from_secs(secs))),
+        expires_in: expires.map(|secs| secs.saturating_sub(now)),
+        admin,
+        claims,
+    }))
+}
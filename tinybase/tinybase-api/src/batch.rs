@@ -0,0 +1,248 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tinybase_core::{
+    batch::{BatchError, BatchOperation, BatchResult},
+    embedded::{prepare_record, Write},
+    events::{Event, EventAction},
+    patch::merge_patch,
+    relations::delete_dependents,
+    Record,
+};
+use utoipa::ToSchema;
+
+use crate::{
+    access::{record_value, Access, Operation},
+    after_delete, db_error, resolve_collection,
+    shape::shape_records,
+    AppError, AppState, RecordResponse,
+};
+
+const MAX_OPERATIONS: usize = 1000;
+
+#[derive(Deserialize, ToSchema)]
+pub struct BatchRequest {
+    operations: Vec<BatchOperationRequest>,
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum BatchOperationRequest {
+    Create {
+        data: serde_json::Value,
+    },
+    /// Applies `data` to record `id` as a JSON Merge Patch, like `PATCH`.
+    Update {
+        id: i64,
+        data: serde_json::Value,
+    },
+    Delete {
+        id: i64,
+    },
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BatchItemResponse {
+    /// `create`, `update` or `delete`.
+    op: String,
+    /// The status the equivalent single-record request would have returned.
+    status: u16,
+    /// The created or updated record; absent for deletes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    record: Option<RecordResponse>,
+}
+
+impl BatchItemResponse {
+    fn new(op: &str, status: StatusCode, record: Option<Record>) -> Self {
+        BatchItemResponse {
+            op: op.to_string(),
+            status: status.as_u16(),
+            record: record.map(Into::into),
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/collections/{id}/records/batch",
+    params(
+        ("id" = String, Path, description = "Collection id or name")
+    ),
+    request_body = BatchRequest,
+    responses(
+        (status = 200, description = "All operations applied; one result per operation, in order", body = Vec<BatchItemResponse>),
+        (status = 400, description = "Too many operations", body = ProblemDetail),
+        (status = 403, description = "A collection rule denies the request", body = ProblemDetail),
+        (status = 404, description = "Collection or record not found; nothing was applied", body = ProblemDetail),
+        (status = 422, description = "Validation error; nothing was applied", body = ProblemDetail),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn batch_records(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Json(payload): Json<BatchRequest>,
+) -> Result<Json<Vec<BatchItemResponse>>, AppError> {
+    if payload.operations.len() > MAX_OPERATIONS {
+        return Err(AppError::BadRequest(format!(
+            "A batch may contain at most {} operations",
+            MAX_OPERATIONS
+        )));
+    }
+    let db = &state.db;
+    let id = resolve_collection(db.as_ref(), &key).await?;
+    let collection = db
+        .get_collection(id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", id)))?;
+
+    let access = Access::to(&state, &collection).await?;
+    let mut operations = Vec::with_capacity(payload.operations.len());
+    // The data of the records updated so far, for later updates to patch.
+    let mut updated = HashMap::new();
+    for (index, operation) in payload.operations.into_iter().enumerate() {
+        if access.is_ruled() {
+            check_rule(&state, &access, id, &operation)
+                .await
+                .map_err(|e| AppError::BatchItem(index, Box::new(e)))?;
+        }
+        let mut operation = match operation {
+            BatchOperationRequest::Create { data } => BatchOperation::Create { data },
+            BatchOperationRequest::Update {
+                id: record_id,
+                data: patch,
+            } => {
+                let current = match updated.remove(&record_id) {
+                    Some(data) => Some(data),
+                    None => db
+                        .get_record(id, record_id)
+                        .await
+                        .map_err(db_error)?
+                        .map(|record| record.data),
+                };
+                let data = match current {
+                    Some(mut data) => {
+                        merge_patch(&mut data, &patch);
+                        data
+                    }
+                    None => patch,
+                };
+                BatchOperation::Update {
+                    id: record_id,
+                    data,
+                }
+            }
+            BatchOperationRequest::Delete { id } => BatchOperation::Delete { id },
+        };
+        let write = match &mut operation {
+            BatchOperation::Create { data } => Some((data, Write::Create)),
+            BatchOperation::Update { data, .. } => Some((data, Write::Update)),
+            BatchOperation::Delete { .. } => None,
+        };
+        if let (Some(schema), Some((data, write))) = (&collection.schema, write) {
+            prepare_record(db.as_ref(), schema, data, write)
+                .await
+                .map_err(|e| AppError::BatchItem(index, Box::new(e.into())))?;
+        }
+        if let BatchOperation::Update { id, data } = &operation {
+            updated.insert(*id, data.clone());
+        }
+        operations.push(operation);
+    }
+
+    let results = db
+        .batch(id, &operations)
+        .await
+        .map_err(|e| batch_error(id, e))?;
+
+    let mut responses = Vec::with_capacity(results.len());
+    let mut removed = Vec::new();
+    for result in results {
+        let response = match result {
+            BatchResult::Created(record) => {
+                state
+                    .events
+                    .publish(Event::new(EventAction::RecordCreated, id, Some(record.id)));
+                BatchItemResponse::new("create", StatusCode::CREATED, Some(record))
+            }
+            BatchResult::Updated(record) => {
+                state
+                    .events
+                    .publish(Event::new(EventAction::RecordUpdated, id, Some(record.id)));
+                BatchItemResponse::new("update", StatusCode::OK, Some(record))
+            }
+            BatchResult::Deleted(record) => {
+                removed.push((id, record));
+                BatchItemResponse::new("delete", StatusCode::NO_CONTENT, None)
+            }
+        };
+        responses.push(response);
+    }
+
+    // Cascades run after the batch has committed, outside its transaction.
+    let mut cascaded = Vec::new();
+    for (cid, record) in &removed {
+        cascaded.extend(
+            delete_dependents(db.as_ref(), *cid, record.id)
+                .await
+                .map_err(db_error)?,
+        );
+    }
+    removed.extend(cascaded);
+    after_delete(&state, removed).await?;
+    shape_records(
+        &state,
+        id,
+        responses.iter_mut().filter_map(|r| r.record.as_mut()),
+    )
+    .await?;
+    Ok(Json(responses))
+}
+
+/// Refuses an operation its collection rule denies. Updates and deletes of
+/// missing records pass, for the batch to report.
+async fn check_rule(
+    state: &AppState,
+    access: &Access,
+    collection_id: i64,
+    operation: &BatchOperationRequest,
+) -> Result<(), AppError> {
+    let (op, record_id, data) = match operation {
+        BatchOperationRequest::Create { data } => {
+            return access.check(Operation::Create, &serde_json::json!({}), data);
+        }
+        BatchOperationRequest::Update { id, data } => (Operation::Update, *id, data),
+        BatchOperationRequest::Delete { id } => (Operation::Delete, *id, &serde_json::Value::Null),
+    };
+    match state
+        .db
+        .get_record(collection_id, record_id)
+        .await
+        .map_err(db_error)?
+    {
+        Some(record) => access.check(op, &record_value(&record), data),
+        None => Ok(()),
+    }
+}
+
+/// Attributes a failed batch to the operation that caused the rollback.
+fn batch_error(collection_id: i64, e: Box<dyn std::error::Error + Send + Sync>) -> AppError {
+    match e.downcast::<BatchError>() {
+        Ok(e) => match *e {
+            BatchError::RecordNotFound(index, record_id) => AppError::BatchItem(
+                index,
+                Box::new(AppError::NotFound(format!(
+                    "Record {} not found in collection {}",
+                    record_id, collection_id
+                ))),
+            ),
+            BatchError::Failed(index, e) => AppError::BatchItem(index, Box::new(db_error(e))),
+        },
+        Err(e) => db_error(e),
+    }
+}
@@ -0,0 +1,191 @@
+//! Single-flight de-duplication of identical concurrent reads.
+//!
+//! When many clients ask for the same collection, record or filtered list at
+//! once, e.g. after a cache purge, only the first request runs; the others
+//! wait for it and get a copy of its response. Requests are identical when
+//! their URI, including the query string, and the [headers](KEYED_HEADERS)
+//! their response depends on match, so a CSV list is never handed to a
+//! client asking for JSON. A read started before a write completed is never
+//! shared with requests arriving after it, so clients still read their own
+//! writes. Responses are buffered to be shared, so streamed downloads such
+//! as collection exports are left out.
+
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::{Request, State},
+    http::{
+        header::{ACCEPT, AUTHORIZATION},
+        HeaderMap, HeaderName, Method, StatusCode,
+    },
+    middleware::{self, Next},
+    response::Response,
+    Router,
+};
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use utoipa::ToSchema;
+
+/// The request headers a response can vary with: who asks, in which
+/// format, and with which `Prefer`ences.
+const KEYED_HEADERS: [HeaderName; 3] = [AUTHORIZATION, ACCEPT, HeaderName::from_static("prefer")];
+
+/// A response buffered so it can be handed to every waiting request.
+struct Snapshot {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Option<Bytes>,
+}
+
+type InFlight = Shared<BoxFuture<'static, Arc<Snapshot>>>;
+
+/// Tracks the reads in flight and how many requests were served by them.
+pub struct Coalescer {
+    /// Only `GET`s below this path are coalesced; streams must not be.
+    prefix: &'static str,
+    /// Ends of the paths below `prefix` whose responses are streamed.
+    streamed: Vec<&'static str>,
+    in_flight: Mutex<HashMap<String, InFlight>>,
+    /// Bumped after every write, so reads started earlier are not joined.
+    generation: AtomicU64,
+    executed: AtomicU64,
+    coalesced: AtomicU64,
+}
+
+/// Counters of the reads subject to coalescing.
+#[derive(Serialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CoalescingStats {
+    /// Reads that ran their handler.
+    pub executed: u64,
+    /// Reads answered with the response of an identical read in flight.
+    pub coalesced: u64,
+}
+
+impl Coalescer {
+    /// Coalesces the `GET`s whose path starts with `prefix`.
+    pub fn new(prefix: &'static str) -> Self {
+        Self {
+            prefix,
+            streamed: Vec::new(),
+            in_flight: Mutex::new(HashMap::new()),
+            generation: AtomicU64::new(0),
+            executed: AtomicU64::new(0),
+            coalesced: AtomicU64::new(0),
+        }
+    }
+
+    /// Leaves out the paths ending with `suffix`, e.g. `/export`, whose
+    /// responses are streamed and would have to be buffered to be shared.
+    pub fn except(mut self, suffix: &'static str) -> Self {
+        self.streamed.push(suffix);
+        self
+    }
+
+    /// Whether `GET`s to `path` are coalesced.
+    fn covers(&self, path: &str) -> bool {
+        path.starts_with(self.prefix) && !self.streamed.iter().any(|s| path.ends_with(s))
+    }
+
+    pub fn stats(&self) -> CoalescingStats {
+        CoalescingStats {
+            executed: self.executed.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+        }
+    }
+
+    fn key(&self, request: &Request) -> String {
+        let mut key = format!(
+            "{} {}",
+            self.generation.load(Ordering::Acquire),
+            request.uri()
+        );
+        for name in &KEYED_HEADERS {
+            for value in request.headers().get_all(name) {
+                key.push_str(&format!(
+                    "\n{}: {}",
+                    name,
+                    String::from_utf8_lossy(value.as_bytes())
+                ));
+            }
+        }
+        key
+    }
+}
+
+/// Wraps `router` so identical concurrent `GET`s to the coalesced paths run
+/// once. Writes through `router` end the sharing of reads started before
+/// them.
+pub fn layer<S>(router: Router<S>, coalescer: Arc<Coalescer>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router.layer(middleware::from_fn_with_state(coalescer, coalesce))
+}
+
+async fn coalesce(
+    State(coalescer): State<Arc<Coalescer>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if request.method() != Method::GET {
+        let response = next.run(request).await;
+        coalescer.generation.fetch_add(1, Ordering::AcqRel);
+        return response;
+    }
+    if !coalescer.covers(request.uri().path()) {
+        return next.run(request).await;
+    }
+    let key = coalescer.key(&request);
+    let flight = {
+        let mut in_flight = coalescer.in_flight.lock().unwrap();
+        match in_flight.get(&key) {
+            Some(flight) => {
+                coalescer.coalesced.fetch_add(1, Ordering::Relaxed);
+                flight.clone()
+            }
+            None => {
+                coalescer.executed.fetch_add(1, Ordering::Relaxed);
+                let flight = run(coalescer.clone(), key.clone(), request, next)
+                    .boxed()
+                    .shared();
+                in_flight.insert(key, flight.clone());
+                flight
+            }
+        }
+    };
+    let snapshot = flight.await;
+    let mut response = Response::new(match &snapshot.body {
+        Some(body) => Body::from(body.clone()),
+        None => Body::empty(),
+    });
+    *response.status_mut() = snapshot.status;
+    *response.headers_mut() = snapshot.headers.clone();
+    response
+}
+
+/// Runs the first of a set of identical reads. It is driven by whichever
+/// waiting request polls it, so it completes even if its own client left.
+async fn run(
+    coalescer: Arc<Coalescer>,
+    key: String,
+    request: Request,
+    next: Next,
+) -> Arc<Snapshot> {
+    let (parts, body) = next.run(request).await.into_parts();
+    let snapshot = match to_bytes(body, usize::MAX).await {
+        Ok(body) => Snapshot {
+            status: parts.status,
+            headers: parts.headers,
+            body: Some(body),
+        },
+        Err(_) => Snapshot {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            headers: HeaderMap::new(),
+            body: None,
+        },
+    };
+    coalescer.in_flight.lock().unwrap().remove(&key);
+    Arc::new(snapshot)
+}
@@ -0,0 +1,689 @@
+//! Server configuration: a TOML file plus environment overrides.
+//!
+//! Settings are read from the file named by `TINYBASE_CONFIG`, or from
+//! `tinybase.toml` in the working directory if it exists, then overridden by
+//! `TINYBASE_*` environment variables. Anything left unset keeps its default:
+//!
+//! ```toml
+//! db_path = "local.db"
+//! db_pool_size = 8
+//! uploads_dir = "uploads"
+//! addr = "0.0.0.0:3000"
+//! admin_addr = "127.0.0.1:3001"
+//! log_level = "info"
+//! log_format = "text"
+//! max_body_bytes = 2097152
+//! max_json_bytes = 1048576
+//! max_json_depth = 32
+//! cors_origins = ["https://app.example.com"]
+//! job_workers = 2
+//! query_timeout_ms = 10000
+//! access_log = "access.log"
+//! access_log_format = "combined"
+//! access_log_max_bytes = 10485760
+//! access_log_files = 5
+//! manifest = "collections.toml"
+//! developer_mode = false
+//! rate_limit_per_minute = 0
+//! warm_queries = ["/api/v1/collections/posts/records?sort=-created"]
+//! checksums = "log"
+//! drain_timeout_ms = 30000
+//! attach = { postcodes = "data/postcodes.db" }
+//! list_format = "envelope"
+//! redis_url = "redis://127.0.0.1:6379"
+//! backup_dir = "backups"
+//! backup_interval_hours = 24
+//! backup_keep = 7
+//! verify_interval_hours = 24
+//! record_fixtures = "fixture.jsonl"
+//! search = "tantivy"
+//! search_dir = "search"
+//! meilisearch_url = "http://127.0.0.1:7700"
+//! meilisearch_key = "masterKey"
+//! namespaces = { shop = "data/shop.db", blog = "data/blog.db" }
+//! ```
+//!
+//! The file may hold named profiles, one per environment, picked with
+//! `TINYBASE_ENV`. A profile overrides the settings at the top of the file,
+//! or those of the profile it `inherits`, so one file and one build run
+//! everywhere:
+//!
+//! ```toml
+//! cors_origins = ["https://app.example.com"]
+//! rate_limit_per_minute = 600
+//!
+//! [profiles.development]
+//! cors_origins = ["*"]
+//! rate_limit_per_minute = 0
+//! log_level = "debug"
+//!
+//! [profiles.staging]
+//! inherits = "production"
+//! cors_origins = ["https://staging.example.com"]
+//!
+//! [profiles.production]
+//! log_format = "json"
+//! ```
+//!
+//! Without `TINYBASE_ENV` the top of the file applies. Every profile is
+//! checked whichever is picked, so a typo in `production` fails in
+//! development too. The `development` environment reports error internals
+//! to clients unless it sets `developer_mode = false`.
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tinybase_core::{checksums::ChecksumMode, pool::DEFAULT_POOL_SIZE};
+
+use crate::access_log::{AccessLogFormat, DEFAULT_ACCESS_LOG_FILES, DEFAULT_ACCESS_LOG_MAX_BYTES};
+use crate::envelope::ListFormat;
+use crate::limits::{DEFAULT_MAX_JSON_BYTES, DEFAULT_MAX_JSON_DEPTH};
+use crate::search::SearchBackend;
+use crate::shutdown::DEFAULT_DRAIN_TIMEOUT;
+use crate::{jobs::DEFAULT_JOB_WORKERS, DEFAULT_MAX_BODY_BYTES, DEFAULT_QUERY_TIMEOUT};
+
+const DEFAULT_CONFIG_FILE: &str = "tinybase.toml";
+
+/// The environment that defaults to developer mode.
+const DEVELOPMENT: &str = "development";
+const LOG_LEVELS: [&str; 5] = ["error", "warn", "info", "debug", "trace"];
+const DEFAULT_VERIFY_INTERVAL_HOURS: u64 = 24;
+
+/// How log lines are written.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// One human-readable line per event.
+    #[default]
+    Text,
+    /// One JSON object per event, for log collectors.
+    Json,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The database file. `TINYBASE_DB_PATH`.
+    pub db_path: PathBuf,
+    /// Connections to the database kept for concurrent requests.
+    /// `TINYBASE_DB_POOL_SIZE`.
+    pub db_pool_size: usize,
+    /// Directory uploaded files are stored in. `TINYBASE_UPLOADS_DIR`.
+    pub uploads_dir: PathBuf,
+    /// Address to listen on. `TINYBASE_ADDR`.
+    pub addr: String,
+    /// Address to serve the admin API, dashboard and metrics on instead of
+    /// `addr`; none by default. `TINYBASE_ADMIN_ADDR`.
+    pub admin_addr: Option<String>,
+    /// `error`, `warn`, `info`, `debug` or `trace`. `TINYBASE_LOG_LEVEL`.
+    pub log_level: String,
+    /// `text` or `json`. `TINYBASE_LOG_FORMAT`.
+    pub log_format: LogFormat,
+    /// Largest request body accepted, in bytes. `TINYBASE_MAX_BODY_BYTES`.
+    pub max_body_bytes: usize,
+    /// Largest JSON request body accepted, in bytes, if under
+    /// `max_body_bytes`.
+    /// `TINYBASE_MAX_JSON_BYTES`.
+    pub max_json_bytes: usize,
+    /// Deepest nesting of arrays and objects accepted in a JSON request
+    /// body. `TINYBASE_MAX_JSON_DEPTH`.
+    pub max_json_depth: usize,
+    /// Origins browsers may call the API from; `*` allows any. None by
+    /// default. `TINYBASE_CORS_ORIGINS`, comma separated.
+    pub cors_origins: Vec<String>,
+    /// Background jobs, such as imports, run at once. `TINYBASE_JOB_WORKERS`.
+    pub job_workers: usize,
+    /// Time a record list, count or lookup may run before it is interrupted,
+    /// in milliseconds; 0 for no limit. `TINYBASE_QUERY_TIMEOUT_MS`.
+    pub query_timeout_ms: u64,
+    /// File to write an access log line per request to; none by default.
+    /// `TINYBASE_ACCESS_LOG`.
+    pub access_log: Option<PathBuf>,
+    /// `combined` or `json`. `TINYBASE_ACCESS_LOG_FORMAT`.
+    pub access_log_format: AccessLogFormat,
+    /// Size the access log is rotated at, in bytes.
+    /// `TINYBASE_ACCESS_LOG_MAX_BYTES`.
+    pub access_log_max_bytes: u64,
+    /// Rotated access logs kept. `TINYBASE_ACCESS_LOG_FILES`.
+    pub access_log_files: usize,
+    /// File declaring collections, indexes and webhooks, reconciled at
+    /// startup; none by default. `TINYBASE_MANIFEST`.
+    pub manifest: Option<PathBuf>,
+    /// Reports error internals, e.g. failed SQL, to clients. Off by default,
+    /// on in the `development` environment. `TINYBASE_DEVELOPER_MODE`.
+    pub developer_mode: bool,
+    /// Requests each client IP may make per minute; 0 for no limit.
+    /// `TINYBASE_RATE_LIMIT_PER_MINUTE`.
+    pub rate_limit_per_minute: u32,
+    /// Anonymous GET requests, by path and query, run at startup to fill
+    /// the caches before the server listens; none by default.
+    /// `TINYBASE_WARM_QUERIES`, whitespace separated.
+    pub warm_queries: Vec<String>,
+    /// What reading a record that doesn't match its checksum does: `off`,
+    /// `log` or `enforce`. `TINYBASE_CHECKSUMS`.
+    pub checksums: ChecksumMode,
+    /// Time a stopping server gives the requests in flight and the queued
+    /// webhook, notification and search deliveries to finish, in
+    /// milliseconds. `TINYBASE_DRAIN_TIMEOUT_MS`.
+    pub drain_timeout_ms: u64,
+    /// Read-only reference databases to attach, by alias; none by default.
+    /// `TINYBASE_ATTACH`, comma separated `alias=path` pairs.
+    pub attach: BTreeMap<String, PathBuf>,
+    /// `bare` lists, or `envelope` ones wrapped as `{ items, page, per_page,
+    /// total, links }`. `TINYBASE_LIST_FORMAT`.
+    pub list_format: ListFormat,
+    /// Redis server sharing rate limits, sessions and idempotency keys
+    /// between instances, with the `redis` feature; none by default.
+    /// `TINYBASE_REDIS_URL`.
+    pub redis_url: Option<String>,
+    /// Directory admins take and restore backups in; none by default.
+    /// `TINYBASE_BACKUP_DIR`.
+    pub backup_dir: Option<PathBuf>,
+    /// Hours between the backups taken into `backup_dir`; none by default.
+    /// `TINYBASE_BACKUP_INTERVAL_HOURS`.
+    pub backup_interval_hours: Option<u64>,
+    /// Newest backups kept when one is taken; all by default.
+    /// `TINYBASE_BACKUP_KEEP`.
+    pub backup_keep: Option<usize>,
+    /// Hours between checks that the latest backup restores cleanly.
+    /// `TINYBASE_VERIFY_INTERVAL_HOURS`.
+    pub verify_interval_hours: u64,
+    /// File to append every JSON request and response to, for regression
+    /// tests; none by default. `TINYBASE_RECORD_FIXTURES`.
+    pub record_fixtures: Option<PathBuf>,
+    /// `fts5`, `tantivy` or `meilisearch`. `TINYBASE_SEARCH`.
+    pub search: SearchBackend,
+    /// Directory the `tantivy` backend indexes into. `TINYBASE_SEARCH_DIR`.
+    pub search_dir: Option<PathBuf>,
+    /// Server the `meilisearch` backend indexes on.
+    /// `TINYBASE_MEILISEARCH_URL`.
+    pub meilisearch_url: Option<String>,
+    /// API key of the Meilisearch server; none by default.
+    /// `TINYBASE_MEILISEARCH_KEY`.
+    pub meilisearch_key: Option<String>,
+    /// Isolated apps served next to the default one, each on its own
+    /// database file, by name; none by default. `TINYBASE_NAMESPACES`,
+    /// comma separated `name=path` pairs.
+    pub namespaces: BTreeMap<String, PathBuf>,
+    /// The profile the settings were taken from, picked by `TINYBASE_ENV`.
+    #[serde(skip)]
+    pub profile: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            db_path: PathBuf::from("local.db"),
+            db_pool_size: DEFAULT_POOL_SIZE,
+            uploads_dir: PathBuf::from("uploads"),
+            addr: "0.0.0.0:3000".to_string(),
+            admin_addr: None,
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            max_json_bytes: DEFAULT_MAX_JSON_BYTES,
+            max_json_depth: DEFAULT_MAX_JSON_DEPTH,
+            cors_origins: Vec::new(),
+            job_workers: DEFAULT_JOB_WORKERS,
+            query_timeout_ms: DEFAULT_QUERY_TIMEOUT.as_millis() as u64,
+            access_log: None,
+            access_log_format: AccessLogFormat::Combined,
+            access_log_max_bytes: DEFAULT_ACCESS_LOG_MAX_BYTES,
+            access_log_files: DEFAULT_ACCESS_LOG_FILES,
+            manifest: None,
+            developer_mode: false,
+            rate_limit_per_minute: 0,
+            warm_queries: Vec::new(),
+            checksums: ChecksumMode::Log,
+            drain_timeout_ms: DEFAULT_DRAIN_TIMEOUT.as_millis() as u64,
+            attach: BTreeMap::new(),
+            list_format: ListFormat::Bare,
+            redis_url: None,
+            backup_dir: None,
+            backup_interval_hours: None,
+            backup_keep: None,
+            verify_interval_hours: DEFAULT_VERIFY_INTERVAL_HOURS,
+            record_fixtures: None,
+            search: SearchBackend::Fts5,
+            search_dir: None,
+            meilisearch_url: None,
+            meilisearch_key: None,
+            namespaces: BTreeMap::new(),
+            profile: None,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration file, if any, in the profile `TINYBASE_ENV`
+    /// names, and applies the environment.
+    pub fn load() -> Result<Self, String> {
+        Self::load_from(|name| std::env::var(name).ok())
+    }
+
+    /// [`Config::load`] with the `TINYBASE_*` variables `var` returns,
+    /// `TINYBASE_CONFIG` and `TINYBASE_ENV` included.
+    pub fn load_from(var: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let path = var("TINYBASE_CONFIG").map(PathBuf::from);
+        let env = var("TINYBASE_ENV");
+        let profile = env.as_deref().filter(|env| !env.is_empty());
+        let mut config = match &path {
+            Some(path) => Self::from_file_profile(path, profile)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::from_file_profile(Path::new(DEFAULT_CONFIG_FILE), profile)?
+            }
+            None => Self::from_toml_profile("", profile)?,
+        };
+        config.apply_env(var)?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        Self::from_file_profile(path, None)
+    }
+
+    /// Reads the file in `profile`; see [`Config::from_toml_profile`].
+    pub fn from_file_profile(path: &Path, profile: Option<&str>) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        Self::from_toml_profile(&text, profile)
+            .map_err(|e| format!("Invalid {}: {}", path.display(), e))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        Self::from_toml_profile(text, None)
+    }
+
+    /// Parses `text` with the settings of `profile`, if given, over those at
+    /// the top. A file without profiles runs in any environment; one with
+    /// profiles must have the one asked for.
+    pub fn from_toml_profile(text: &str, profile: Option<&str>) -> Result<Self, String> {
+        let mut base: toml::Table = text
+            .parse()
+            .map_err(|e: toml::de::Error| e.message().to_string())?;
+        let profiles = match base.remove("profiles") {
+            Some(toml::Value::Table(profiles)) => profiles,
+            Some(_) => return Err("profiles must be a table of profiles".to_string()),
+            None => toml::Table::new(),
+        };
+        for name in profiles.keys() {
+            Self::resolve(&base, &profiles, name)
+                .map_err(|e| format!("profile '{}': {}", name, e))?;
+        }
+        let mut config = match profile {
+            Some(name) if profiles.contains_key(name) => Self::resolve(&base, &profiles, name)
+                .map_err(|e| format!("profile '{}': {}", name, e))?,
+            Some(name) if !profiles.is_empty() => {
+                return Err(format!(
+                    "TINYBASE_ENV '{}' is not one of the profiles {}",
+                    name,
+                    profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+                ))
+            }
+            _ => Self::parse(base.clone())?,
+        };
+        if profile == Some(DEVELOPMENT) {
+            let set = Self::chain(&profiles, DEVELOPMENT)?
+                .iter()
+                .filter_map(|name| profiles[name.as_str()].as_table())
+                .chain([&base])
+                .any(|table| table.contains_key("developer_mode"));
+            config.developer_mode = config.developer_mode || !set;
+        }
+        config.profile = profile.map(str::to_string);
+        Ok(config)
+    }
+
+    /// `name` and the profiles it inherits from, nearest first.
+    fn chain(profiles: &toml::Table, name: &str) -> Result<Vec<String>, String> {
+        let mut chain = Vec::new();
+        let mut next = profiles.contains_key(name).then(|| name.to_string());
+        while let Some(name) = next {
+            if chain.contains(&name) {
+                return Err(format!("profile '{}' inherits from itself", name));
+            }
+            let profile = profiles[name.as_str()]
+                .as_table()
+                .ok_or_else(|| format!("profile '{}' must be a table", name))?;
+            next = match profile.get("inherits") {
+                Some(toml::Value::String(parent)) if profiles.contains_key(parent) => {
+                    Some(parent.clone())
+                }
+                Some(parent) => return Err(format!("inherits {}, which is not a profile", parent)),
+                None => None,
+            };
+            chain.push(name);
+        }
+        Ok(chain)
+    }
+
+    /// The settings of profile `name`, over those it inherits, over `base`.
+    fn resolve(base: &toml::Table, profiles: &toml::Table, name: &str) -> Result<Self, String> {
+        let mut settings = base.clone();
+        for name in Self::chain(profiles, name)?.iter().rev() {
+            for (key, value) in profiles[name.as_str()].as_table().into_iter().flatten() {
+                if key != "inherits" {
+                    settings.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        Self::parse(settings)
+    }
+
+    fn parse(settings: toml::Table) -> Result<Self, String> {
+        let config: Self = toml::Value::Table(settings)
+            .try_into()
+            .map_err(|e: toml::de::Error| e.message().to_string())?;
+        config.check()?;
+        Ok(config)
+    }
+
+    /// Overrides settings with the `TINYBASE_*` variables `var` returns.
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), String> {
+        if let Some(path) = var("TINYBASE_DB_PATH") {
+            self.db_path = PathBuf::from(path);
+        }
+        if let Some(size) = var("TINYBASE_DB_POOL_SIZE") {
+            self.db_pool_size = size
+                .parse()
+                .map_err(|_| format!("TINYBASE_DB_POOL_SIZE '{}' is not a number", size))?;
+        }
+        if let Some(dir) = var("TINYBASE_UPLOADS_DIR") {
+            self.uploads_dir = PathBuf::from(dir);
+        }
+        if let Some(addr) = var("TINYBASE_ADDR") {
+            self.addr = addr;
+        }
+        if let Some(addr) = var("TINYBASE_ADMIN_ADDR") {
+            self.admin_addr = Some(addr);
+        }
+        if let Some(level) = var("TINYBASE_LOG_LEVEL") {
+            self.log_level = level;
+        }
+        if let Some(format) = var("TINYBASE_LOG_FORMAT") {
+            self.log_format = match format.as_str() {
+                "text" => LogFormat::Text,
+                "json" => LogFormat::Json,
+                _ => {
+                    return Err(format!(
+                        "TINYBASE_LOG_FORMAT '{}' is not text or json",
+                        format
+                    ))
+                }
+            };
+        }
+        if let Some(bytes) = var("TINYBASE_MAX_BODY_BYTES") {
+            self.max_body_bytes = bytes
+                .parse()
+                .map_err(|_| format!("TINYBASE_MAX_BODY_BYTES '{}' is not a number", bytes))?;
+        }
+        if let Some(bytes) = var("TINYBASE_MAX_JSON_BYTES") {
+            self.max_json_bytes = bytes
+                .parse()
+                .map_err(|_| format!("TINYBASE_MAX_JSON_BYTES '{}' is not a number", bytes))?;
+        }
+        if let Some(depth) = var("TINYBASE_MAX_JSON_DEPTH") {
+            self.max_json_depth = depth
+                .parse()
+                .map_err(|_| format!("TINYBASE_MAX_JSON_DEPTH '{}' is not a number", depth))?;
+        }
+        if let Some(origins) = var("TINYBASE_CORS_ORIGINS") {
+            self.cors_origins = origins
+                .split(',')
+                .map(str::trim)
+                .filter(|o| !o.is_empty())
+                .map(String::from)
+                .collect();
+        }
+        if let Some(workers) = var("TINYBASE_JOB_WORKERS") {
+            self.job_workers = workers
+                .parse()
+                .map_err(|_| format!("TINYBASE_JOB_WORKERS '{}' is not a number", workers))?;
+        }
+        if let Some(ms) = var("TINYBASE_QUERY_TIMEOUT_MS") {
+            self.query_timeout_ms = ms
+                .parse()
+                .map_err(|_| format!("TINYBASE_QUERY_TIMEOUT_MS '{}' is not a number", ms))?;
+        }
+        if let Some(path) = var("TINYBASE_ACCESS_LOG") {
+            self.access_log = Some(PathBuf::from(path));
+        }
+        if let Some(format) = var("TINYBASE_ACCESS_LOG_FORMAT") {
+            self.access_log_format = match format.as_str() {
+                "combined" => AccessLogFormat::Combined,
+                "json" => AccessLogFormat::Json,
+                _ => {
+                    return Err(format!(
+                        "TINYBASE_ACCESS_LOG_FORMAT '{}' is not combined or json",
+                        format
+                    ))
+                }
+            };
+        }
+        if let Some(bytes) = var("TINYBASE_ACCESS_LOG_MAX_BYTES") {
+            self.access_log_max_bytes = bytes.parse().map_err(|_| {
+                format!("TINYBASE_ACCESS_LOG_MAX_BYTES '{}' is not a number", bytes)
+            })?;
+        }
+        if let Some(files) = var("TINYBASE_ACCESS_LOG_FILES") {
+            self.access_log_files = files
+                .parse()
+                .map_err(|_| format!("TINYBASE_ACCESS_LOG_FILES '{}' is not a number", files))?;
+        }
+        if let Some(path) = var("TINYBASE_MANIFEST") {
+            self.manifest = Some(PathBuf::from(path));
+        }
+        if let Some(enabled) = var("TINYBASE_DEVELOPER_MODE") {
+            self.developer_mode = enabled.parse().map_err(|_| {
+                format!("TINYBASE_DEVELOPER_MODE '{}' is not true or false", enabled)
+            })?;
+        }
+        if let Some(limit) = var("TINYBASE_RATE_LIMIT_PER_MINUTE") {
+            self.rate_limit_per_minute = limit.parse().map_err(|_| {
+                format!("TINYBASE_RATE_LIMIT_PER_MINUTE '{}' is not a number", limit)
+            })?;
+        }
+        if let Some(queries) = var("TINYBASE_WARM_QUERIES") {
+            self.warm_queries = queries.split_whitespace().map(String::from).collect();
+        }
+        if let Some(mode) = var("TINYBASE_CHECKSUMS") {
+            self.checksums = match mode.as_str() {
+                "off" => ChecksumMode::Off,
+                "log" => ChecksumMode::Log,
+                "enforce" => ChecksumMode::Enforce,
+                _ => {
+                    return Err(format!(
+                        "TINYBASE_CHECKSUMS '{}' is not off, log or enforce",
+                        mode
+                    ))
+                }
+            };
+        }
+        if let Some(ms) = var("TINYBASE_DRAIN_TIMEOUT_MS") {
+            self.drain_timeout_ms = ms
+                .parse()
+                .map_err(|_| format!("TINYBASE_DRAIN_TIMEOUT_MS '{}' is not a number", ms))?;
+        }
+        if let Some(entries) = var("TINYBASE_ATTACH") {
+            self.attach = pairs("TINYBASE_ATTACH", &entries, "alias=path")?;
+        }
+        if let Some(format) = var("TINYBASE_LIST_FORMAT") {
+            self.list_format = match format.as_str() {
+                "bare" => ListFormat::Bare,
+                "envelope" => ListFormat::Envelope,
+                _ => {
+                    return Err(format!(
+                        "TINYBASE_LIST_FORMAT '{}' is not bare or envelope",
+                        format
+                    ))
+                }
+            };
+        }
+        if let Some(url) = var("TINYBASE_REDIS_URL") {
+            self.redis_url = Some(url);
+        }
+        if let Some(dir) = var("TINYBASE_BACKUP_DIR") {
+            self.backup_dir = Some(PathBuf::from(dir));
+        }
+        if let Some(hours) = var("TINYBASE_BACKUP_INTERVAL_HOURS") {
+            self.backup_interval_hours = Some(hours.parse().map_err(|_| {
+                format!("TINYBASE_BACKUP_INTERVAL_HOURS '{}' is not a number", hours)
+            })?);
+        }
+        if let Some(keep) = var("TINYBASE_BACKUP_KEEP") {
+            self.backup_keep = Some(
+                keep.parse()
+                    .map_err(|_| format!("TINYBASE_BACKUP_KEEP '{}' is not a number", keep))?,
+            );
+        }
+        if let Some(hours) = var("TINYBASE_VERIFY_INTERVAL_HOURS") {
+            self.verify_interval_hours = hours.parse().map_err(|_| {
+                format!("TINYBASE_VERIFY_INTERVAL_HOURS '{}' is not a number", hours)
+            })?;
+        }
+        if let Some(path) = var("TINYBASE_RECORD_FIXTURES") {
+            self.record_fixtures = Some(PathBuf::from(path));
+        }
+        if let Some(backend) = var("TINYBASE_SEARCH") {
+            self.search = match backend.as_str() {
+                "fts5" => SearchBackend::Fts5,
+                "tantivy" => SearchBackend::Tantivy,
+                "meilisearch" => SearchBackend::Meilisearch,
+                _ => {
+                    return Err(format!(
+                        "TINYBASE_SEARCH '{}' is not fts5, tantivy or meilisearch",
+                        backend
+                    ))
+                }
+            };
+        }
+        if let Some(dir) = var("TINYBASE_SEARCH_DIR") {
+            self.search_dir = Some(PathBuf::from(dir));
+        }
+        if let Some(url) = var("TINYBASE_MEILISEARCH_URL") {
+            self.meilisearch_url = Some(url);
+        }
+        if let Some(key) = var("TINYBASE_MEILISEARCH_KEY") {
+            self.meilisearch_key = Some(key);
+        }
+        if let Some(entries) = var("TINYBASE_NAMESPACES") {
+            self.namespaces = pairs("TINYBASE_NAMESPACES", &entries, "name=path")?;
+        }
+        self.check()
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if !LOG_LEVELS.contains(&self.log_level.as_str()) {
+            return Err(format!(
+                "log_level '{}' is not one of {}",
+                self.log_level,
+                LOG_LEVELS.join(", ")
+            ));
+        }
+        if self.admin_addr.as_deref() == Some(self.addr.as_str()) {
+            return Err(format!(
+                "admin_addr must differ from addr, both are {}",
+                self.addr
+            ));
+        }
+        if self.max_body_bytes == 0 {
+            return Err("max_body_bytes must be positive".to_string());
+        }
+        if self.max_json_bytes == 0 {
+            return Err("max_json_bytes must be positive".to_string());
+        }
+        if self.max_json_depth == 0 {
+            return Err("max_json_depth must be positive".to_string());
+        }
+        if self.access_log_max_bytes == 0 {
+            return Err("access_log_max_bytes must be positive".to_string());
+        }
+        if self.job_workers == 0 {
+            return Err("job_workers must be positive".to_string());
+        }
+        if self.db_pool_size == 0 {
+            return Err("db_pool_size must be positive".to_string());
+        }
+        if self.uploads_dir.as_os_str().is_empty() {
+            return Err("uploads_dir must not be empty".to_string());
+        }
+        for origin in &self.cors_origins {
+            let valid = origin == "*"
+                || ((origin.starts_with("http://") || origin.starts_with("https://"))
+                    && !origin.ends_with('/')
+                    && origin.is_ascii());
+            if !valid {
+                return Err(format!(
+                    "CORS origin '{}' must be '*' or a scheme and host, e.g. https://app.example.com",
+                    origin
+                ));
+            }
+        }
+        for query in &self.warm_queries {
+            if !query.starts_with('/') {
+                return Err(format!(
+                    "Warm query '{}' must be a path, e.g. /api/v1/collections/posts/records",
+                    query
+                ));
+            }
+        }
+        if self.attach.keys().any(|alias| alias.is_empty()) {
+            return Err("attach aliases must not be empty".to_string());
+        }
+        if self.backup_interval_hours == Some(0) {
+            return Err("backup_interval_hours must be positive".to_string());
+        }
+        if self.backup_keep == Some(0) {
+            return Err("backup_keep must be positive".to_string());
+        }
+        if self.verify_interval_hours == 0 {
+            return Err("verify_interval_hours must be positive".to_string());
+        }
+        if self.backup_dir.is_none()
+            && (self.backup_interval_hours.is_some() || self.backup_keep.is_some())
+        {
+            return Err("backup_interval_hours and backup_keep need a backup_dir".to_string());
+        }
+        if self.search == SearchBackend::Tantivy && self.search_dir.is_none() {
+            return Err("search = \"tantivy\" needs a search_dir".to_string());
+        }
+        if self.search == SearchBackend::Meilisearch && self.meilisearch_url.is_none() {
+            return Err("search = \"meilisearch\" needs a meilisearch_url".to_string());
+        }
+        for name in self.namespaces.keys() {
+            let valid = name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if name.is_empty() || !valid {
+                return Err(format!(
+                    "Namespace name '{}' must be letters, digits, '-' and '_'",
+                    name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether messages at `level` are shown at the configured log level.
+    pub fn logs(&self, level: &str) -> bool {
+        let rank = |l: &str| LOG_LEVELS.iter().position(|x| *x == l);
+        rank(level) <= rank(&self.log_level)
+    }
+}
+
+/// Parses `entries`, the comma separated `name=path` pairs of variable `var`.
+fn pairs(var: &str, entries: &str, expected: &str) -> Result<BTreeMap<String, PathBuf>, String> {
+    let mut pairs = BTreeMap::new();
+    for entry in entries.split(',').filter(|e| !e.trim().is_empty()) {
+        let Some((name, path)) = entry.split_once('=') else {
+            return Err(format!(
+                "{} entry '{}' is not {}",
+                var,
+                entry.trim(),
+                expected
+            ));
+        };
+        pairs.insert(name.trim().to_string(), PathBuf::from(path.trim()));
+    }
+    Ok(pairs)
+}
@@ -0,0 +1,113 @@
+//! Atomic counters: adding to a number field of a record in one SQL update,
+//! for like counts and stock levels that concurrent read-modify-write cycles
+//! would get wrong. The field's `min` and `max` are checked in the same
+//! update, so a decrement can't take stock below zero. Script hooks don't
+//! run on increments.
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::Number;
+use tinybase_core::{
+    compute::affects_computed,
+    events::{Event, EventAction},
+    schema::{CollectionSchema, FieldType},
+};
+use utoipa::ToSchema;
+
+use crate::{
+    access::{check_record, Operation},
+    db_error, resolve_collection,
+    shape::shape_records,
+    AppError, AppState, RecordResponse,
+};
+
+/// Whether the server keeps `field` in line with other data: an order or tree
+/// parent field, or one a computed field reads. Changing those in place would
+/// leave the rest stale.
+pub(crate) fn is_derived(schema: &CollectionSchema, field: &str) -> bool {
+    schema.order.as_ref().map(|o| o.field.as_str()) == Some(field)
+        || schema.tree.as_ref().map(|t| t.parent.as_str()) == Some(field)
+        || affects_computed(schema, field)
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct IncrementRequest {
+    /// The number field to add to; a missing field counts as 0.
+    field: String,
+    /// What to add, negative to subtract (default 1).
+    #[schema(value_type = f64)]
+    delta: Option<Number>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/collections/{id}/records/{record_id}/increment",
+    params(
+        ("id" = String, Path, description = "Collection id or name"),
+        ("record_id" = i64, Path, description = "Record id")
+    ),
+    request_body = IncrementRequest,
+    responses(
+        (status = 200, description = "Add to a number field of a record atomically, returning the updated record", body = RecordResponse),
+        (status = 400, description = "The field is not a number field, or is computed, ordered or a tree parent", body = ProblemDetail),
+        (status = 403, description = "A collection rule denies the request", body = ProblemDetail),
+        (status = 404, description = "Collection or record not found", body = ProblemDetail),
+        (status = 422, description = "The field holds something other than a number, or the result is out of bounds", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn increment_field(
+    State(state): State<AppState>,
+    Path((key, record_id)): Path<(String, i64)>,
+    Json(request): Json<IncrementRequest>,
+) -> Result<Json<RecordResponse>, AppError> {
+    let field = request.field.trim();
+    if field.is_empty() {
+        return Err(AppError::BadRequest(
+            "Name the field to increment".to_string(),
+        ));
+    }
+    let delta = request.delta.unwrap_or_else(|| Number::from(1));
+    let db = &state.db;
+    let collection_id = resolve_collection(db.as_ref(), &key).await?;
+    let collection = db
+        .get_collection(collection_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", collection_id)))?;
+    let mut bounds = (None, None);
+    if let Some(schema) = &collection.schema {
+        if let Some(definition) = schema.fields.get(field) {
+            if definition.r#type != FieldType::Number {
+                return Err(AppError::BadRequest(format!(
+                    "Field '{}' is not a number field",
+                    field
+                )));
+            }
+            bounds = (definition.min, definition.max);
+        }
+        if is_derived(schema, field) {
+            return Err(AppError::BadRequest(format!(
+                "Field '{}' is computed, ordered or a tree parent and can't be incremented",
+                field
+            )));
+        }
+    }
+    let data = serde_json::json!({ field: serde_json::Value::Number(delta.clone()) });
+    check_record(&state, collection_id, record_id, Operation::Update, &data).await?;
+    let record = db
+        .increment_field(collection_id, record_id, field, &delta, bounds)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| AppError::NotFound(format!("Record {} not found", record_id)))?;
+    state.events.publish(Event::new(
+        EventAction::RecordUpdated,
+        collection_id,
+        Some(record_id),
+    ));
+    let mut response = RecordResponse::from(record);
+    shape_records(&state, collection_id, [&mut response]).await?;
+    Ok(Json(response))
+}
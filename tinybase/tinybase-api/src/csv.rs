@@ -0,0 +1,304 @@
+//! Records as CSV, for people who live in spreadsheets.
+//!
+//! Lists are returned as CSV when asked for with `Accept: text/csv`: a header
+//! row of `id`, `created`, `updated` and the data fields, then one row per
+//! record. Strings are written as they are, other values as JSON. Imports
+//! take the same shape; cells are coerced to the types of the schema fields
+//! named in the header, and `id`, `created` and `updated` are ignored.
+
+use axum::{
+    extract::{Path, State},
+    http::{
+        header::{ACCEPT, CONTENT_TYPE},
+        HeaderMap,
+    },
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use tinybase_core::{
+    embedded::{self, prepare_record, Write},
+    events::{Event, EventAction},
+    lineage::{with_lineage, Lineage, LineageSource},
+    schema::{CollectionSchema, FieldType},
+};
+use utoipa::ToSchema;
+
+use crate::{
+    access::{Access, Operation},
+    db_error, resolve_collection, AppError, AppState, RecordResponse,
+};
+
+const TEXT_CSV: &str = "text/csv";
+
+/// Columns every row starts with; ignored on import.
+const SYSTEM_COLUMNS: [&str; 3] = ["id", "created", "updated"];
+
+/// Whether the request asks for CSV.
+pub(crate) fn wants_csv(headers: &HeaderMap) -> bool {
+    headers
+        .get(ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|t| t.trim().starts_with(TEXT_CSV)))
+}
+
+fn push_cell(row: &mut String, cell: &str) {
+    if cell.contains([',', '"', '\n', '\r']) {
+        row.push('"');
+        row.push_str(&cell.replace('"', "\"\""));
+        row.push('"');
+    } else {
+        row.push_str(cell);
+    }
+}
+
+fn push_row<'a>(csv: &mut String, cells: impl IntoIterator<Item = &'a str>) {
+    for (i, cell) in cells.into_iter().enumerate() {
+        if i > 0 {
+            csv.push(',');
+        }
+        push_cell(csv, cell);
+    }
+    csv.push_str("\r\n");
+}
+
+fn cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+/// Responds with `records` as CSV. The data columns are the schema's fields,
+/// or every field found in the records when there is no schema.
+pub(crate) fn records_response(
+    schema: Option<&CollectionSchema>,
+    records: &[RecordResponse],
+) -> Response {
+    let fields: BTreeSet<&str> = match schema {
+        Some(schema) => schema.fields.keys().map(String::as_str).collect(),
+        None => records
+            .iter()
+            .filter_map(|r| r.data.as_object())
+            .flat_map(|data| data.keys().map(String::as_str))
+            .collect(),
+    };
+    let mut csv = String::new();
+    push_row(
+        &mut csv,
+        SYSTEM_COLUMNS.into_iter().chain(fields.iter().copied()),
+    );
+    for record in records {
+        let mut cells = vec![
+            record.id.to_string(),
+            record.created.clone(),
+            record.updated.clone(),
+        ];
+        cells.extend(fields.iter().map(|f| cell(record.data.get(*f))));
+        push_row(&mut csv, cells.iter().map(String::as_str));
+    }
+    ([(CONTENT_TYPE, "text/csv; charset=utf-8")], csv).into_response()
+}
+
+/// Splits RFC 4180 CSV into rows of cells, paired with their line numbers.
+fn parse(text: &str) -> Result<Vec<(usize, Vec<String>)>, String> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut row_line = 1;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                cell.push('"');
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if cell.is_empty() => quoted = true,
+            (',', false) => row.push(std::mem::take(&mut cell)),
+            ('\r', false) if chars.peek() == Some(&'\n') => {}
+            ('\n', false) => {
+                row.push(std::mem::take(&mut cell));
+                rows.push((row_line, std::mem::take(&mut row)));
+                line += 1;
+                row_line = line;
+            }
+            (c, _) => {
+                if c == '\n' {
+                    line += 1;
+                }
+                cell.push(c);
+            }
+        }
+    }
+    if quoted {
+        return Err(format!("unclosed quote in the row on line {}", row_line));
+    }
+    if !cell.is_empty() || !row.is_empty() {
+        row.push(cell);
+        rows.push((row_line, row));
+    }
+    // Blank lines, such as a trailing one, carry no row.
+    rows.retain(|(_, cells)| !(cells.len() == 1 && cells[0].is_empty()));
+    Ok(rows)
+}
+
+/// The value of a CSV cell for a field of type `field_type`; `None` for a
+/// field without a schema, which is kept as text.
+fn coerce(text: &str, field_type: Option<&FieldType>) -> Result<Value, String> {
+    match field_type {
+        Some(FieldType::Number) => text
+            .parse::<i64>()
+            .map(Value::from)
+            .or_else(|_| text.parse::<f64>().map(Value::from))
+            .map_err(|_| format!("'{}' is not a number", text)),
+        Some(FieldType::Boolean) => match text.to_ascii_lowercase().as_str() {
+            "true" | "yes" | "1" => Ok(Value::Bool(true)),
+            "false" | "no" | "0" => Ok(Value::Bool(false)),
+            _ => Err(format!("'{}' is not a boolean", text)),
+        },
+        Some(FieldType::Json | FieldType::Object { .. } | FieldType::Array { .. }) => {
+            serde_json::from_str(text).map_err(|_| format!("'{}' is not valid JSON", text))
+        }
+        Some(FieldType::Relation { .. }) => text
+            .parse::<i64>()
+            .map(Value::from)
+            .map_err(|_| format!("'{}' is not a record id", text)),
+        _ => Ok(Value::String(text.to_string())),
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CsvRowError {
+    /// Line of the CSV the row starts on; the header is line 1.
+    line: usize,
+    errors: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CsvImportReport {
+    /// Ids of the created records, in row order.
+    created: Vec<i64>,
+    /// Rows that were not imported, with why.
+    failed: Vec<CsvRowError>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/collections/{id}/records/import",
+    params(
+        ("id" = String, Path, description = "Collection id or name")
+    ),
+    request_body(content = String, content_type = "text/csv", description = "A header row naming the fields, then one record per row; `id`, `created` and `updated` columns are ignored"),
+    responses(
+        (status = 200, description = "Create a record per valid row and report the rows that failed", body = CsvImportReport),
+        (status = 400, description = "Malformed CSV or unknown columns", body = ProblemDetail),
+        (status = 404, description = "Collection not found", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn import_records(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    body: String,
+) -> Result<Json<CsvImportReport>, AppError> {
+    let db = &state.db;
+    let id = resolve_collection(db.as_ref(), &key).await?;
+    let collection = db
+        .get_collection(id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", id)))?;
+    let schema = collection.schema.as_ref();
+    let access = Access::to(&state, &collection).await?;
+
+    let mut rows = parse(&body)
+        .map_err(|e| AppError::BadRequest(format!("Invalid CSV: {}", e)))?
+        .into_iter();
+    let (_, header) = rows
+        .next()
+        .ok_or_else(|| AppError::BadRequest("The CSV has no header row".to_string()))?;
+    let mut columns = Vec::with_capacity(header.len());
+    for name in &header {
+        let name = name.trim();
+        let field_type = match schema {
+            _ if SYSTEM_COLUMNS.contains(&name) => None,
+            Some(schema) => match schema.fields.get(name) {
+                Some(field) => Some(&field.r#type),
+                None => {
+                    return Err(AppError::BadRequest(format!(
+                        "Column '{}' is not a field of collection {}",
+                        name, collection.name
+                    )))
+                }
+            },
+            None => None,
+        };
+        columns.push((name, field_type));
+    }
+
+    let mut report = CsvImportReport {
+        created: Vec::new(),
+        failed: Vec::new(),
+    };
+    for (line, cells) in rows {
+        let mut errors = Vec::new();
+        if cells.len() != columns.len() {
+            errors.push(format!(
+                "Expected {} cells, found {}",
+                columns.len(),
+                cells.len()
+            ));
+        }
+        let mut data = serde_json::Map::new();
+        for ((name, field_type), text) in columns.iter().zip(&cells) {
+            // Empty cells leave the field out, so defaults apply.
+            if SYSTEM_COLUMNS.contains(name) || text.is_empty() {
+                continue;
+            }
+            match coerce(text, *field_type) {
+                Ok(value) => {
+                    data.insert(name.to_string(), value);
+                }
+                Err(e) => errors.push(format!("Field '{}': {}", name, e)),
+            }
+        }
+        let mut data = Value::Object(data);
+        if errors.is_empty() {
+            if let Some(schema) = schema {
+                match prepare_record(db.as_ref(), schema, &mut data, Write::Create).await {
+                    Ok(()) => {}
+                    Err(embedded::Error::Validation(invalid)) => {
+                        errors.extend(invalid.iter().map(ToString::to_string));
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        if errors.is_empty()
+            && !access.allows(Operation::Create, &Value::Object(Default::default()), &data)
+        {
+            errors.push(format!(
+                "The create rule of collection '{}' denies this row",
+                collection.name
+            ));
+        }
+        if !errors.is_empty() {
+            report.failed.push(CsvRowError { line, errors });
+            continue;
+        }
+        let lineage = Lineage::inherit(LineageSource::Import);
+        let record = with_lineage(lineage, db.create_record(id, &data))
+            .await
+            .map_err(db_error)?;
+        state
+            .events
+            .publish(Event::new(EventAction::RecordCreated, id, Some(record.id)));
+        report.created.push(record.id);
+    }
+    Ok(Json(report))
+}
@@ -0,0 +1,70 @@
+//! Opaque cursors for keyset paging of record lists. A cursor names the sort
+//! order it was made for and the sort key of the record a page ended with,
+//! hex-encoded so clients pass it back without reading into it.
+
+use tinybase_core::{Cursor, Record, SortField};
+
+use crate::AppError;
+
+fn direction(descending: bool) -> &'static str {
+    if descending {
+        "desc"
+    } else {
+        "asc"
+    }
+}
+
+/// Whether lists sorted by `sort` can be paged with cursors.
+pub(crate) fn supports(sort: SortField) -> bool {
+    matches!(sort, SortField::Id | SortField::Created)
+}
+
+/// The cursor for the page after `last`, in a list sorted by `sort`.
+pub(crate) fn encode(sort: SortField, descending: bool, last: &Record) -> String {
+    let plain = match sort {
+        SortField::Created => format!(
+            "created:{}:{}:{}",
+            direction(descending),
+            last.id,
+            last.created
+        ),
+        _ => format!("id:{}:{}", direction(descending), last.id),
+    };
+    plain.bytes().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Reads a cursor back, checking that it was made for the same sort order.
+pub(crate) fn decode(token: &str, sort: SortField, descending: bool) -> Result<Cursor, AppError> {
+    let invalid = || AppError::BadRequest("Invalid cursor".to_string());
+    if !supports(sort) {
+        return Err(AppError::BadRequest(
+            "Cursors page lists sorted by id or created only".to_string(),
+        ));
+    }
+    let bytes = (0..token.len())
+        .step_by(2)
+        .map(|i| {
+            token
+                .get(i..i + 2)
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(invalid)?;
+    let plain = String::from_utf8(bytes).map_err(|_| invalid())?;
+    let mut parts = plain.splitn(4, ':');
+    let (kind, dir, id) = (parts.next(), parts.next(), parts.next());
+    let id = id.and_then(|id| id.parse().ok()).ok_or_else(invalid)?;
+    let expected = match sort {
+        SortField::Created => "created",
+        _ => "id",
+    };
+    if kind != Some(expected) || dir != Some(direction(descending)) {
+        return Err(AppError::BadRequest(
+            "The cursor belongs to a list in another order".to_string(),
+        ));
+    }
+    Ok(match sort {
+        SortField::Created => Cursor::Created(parts.next().ok_or_else(invalid)?.to_string(), id),
+        _ => Cursor::Id(id),
+    })
+}
@@ -0,0 +1,160 @@
+//! OpenAPI documents generated from a collection's schema, so clients can be
+//! generated with typed records instead of the generic `data` object.
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use serde_json::{json, Value};
+use tinybase_core::{
+    json_schema::{data_schema, record_schema},
+    Collection,
+};
+
+use crate::{db_error, resolve_collection, AppError, DbState};
+
+/// `blog_posts` -> `BlogPosts`, for component names.
+fn type_name(collection: &str) -> String {
+    let name: String = collection
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        name
+    } else {
+        format!("Collection{}", name)
+    }
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn json_content(schema: Value) -> Value {
+    json!({ "application/json": { "schema": schema } })
+}
+
+pub(crate) fn collection_document(collection: &Collection) -> Value {
+    let name = type_name(&collection.name);
+    let data = format!("{}Data", name);
+    let record = format!("{}Record", name);
+    let write = format!("{}Write", name);
+    let (data_ref, record_ref, write_ref) =
+        (schema_ref(&data), schema_ref(&record), schema_ref(&write));
+    let records_path = format!("/api/v1/collections/{}/records", collection.id);
+    let record_path = format!("{}/{{record_id}}", records_path);
+
+    let problem = |description: &str| json!({ "description": description, "content": json_content(schema_ref("ProblemDetail")) });
+    let record_id = json!([{
+        "name": "record_id",
+        "in": "path",
+        "required": true,
+        "schema": { "type": "integer" }
+    }]);
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": format!("Tinybase collection {}", collection.name),
+            "version": env!("CARGO_PKG_VERSION")
+        },
+        "paths": {
+            records_path: {
+                "get": {
+                    "operationId": format!("list{}", name),
+                    "responses": {
+                        "200": {
+                            "description": "Records of the collection",
+                            "content": json_content(json!({ "type": "array", "items": record_ref.clone() }))
+                        },
+                        "400": problem("Invalid sort order or filter")
+                    }
+                },
+                "post": {
+                    "operationId": format!("create{}", name),
+                    "requestBody": { "required": true, "content": json_content(write_ref.clone()) },
+                    "responses": {
+                        "201": { "description": "The created record", "content": json_content(record_ref.clone()) },
+                        "422": problem("Validation error")
+                    }
+                }
+            },
+            record_path: {
+                "parameters": record_id,
+                "get": {
+                    "operationId": format!("get{}", name),
+                    "responses": {
+                        "200": { "description": "The record", "content": json_content(record_ref.clone()) },
+                        "404": problem("Record not found")
+                    }
+                },
+                "patch": {
+                    "operationId": format!("update{}", name),
+                    "requestBody": { "required": true, "content": json_content(write_ref.clone()) },
+                    "responses": {
+                        "200": { "description": "The updated record", "content": json_content(record_ref.clone()) },
+                        "404": problem("Record not found"),
+                        "422": problem("Validation error")
+                    }
+                },
+                "delete": {
+                    "operationId": format!("delete{}", name),
+                    "responses": { "204": { "description": "Record deleted" } }
+                }
+            }
+        },
+        "components": {
+            "schemas": {
+                data: data_schema(collection.schema.as_ref()),
+                record: record_schema(data_ref.clone()),
+                write: {
+                    "type": "object",
+                    "properties": { "data": data_ref.clone() },
+                    "required": ["data"]
+                },
+                "ProblemDetail": {
+                    "type": "object",
+                    "properties": {
+                        "error": { "type": "string" },
+                        "message": { "type": "string" },
+                        "details": {},
+                        "status": { "type": "integer" }
+                    },
+                    "required": ["error", "message", "status"]
+                }
+            }
+        }
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api-docs/collections/{id}/openapi.json",
+    params(
+        ("id" = String, Path, description = "Collection id or name")
+    ),
+    responses(
+        (status = 200, description = "OpenAPI 3.1 document for the records of one collection, typed by its schema", body = Object),
+        (status = 404, description = "Collection not found", body = ProblemDetail),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn collection_openapi(
+    State(db): State<DbState>,
+    Path(key): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let id = resolve_collection(db.as_ref(), &key).await?;
+    let collection = db
+        .get_collection(id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", id)))?;
+    Ok(Json(collection_document(&collection)))
+}
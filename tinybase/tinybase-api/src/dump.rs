@@ -0,0 +1,583 @@
+//! Collection dumps: a collection's schema and records as one JSON document
+//! or as NDJSON, exported and imported by admins to move collections between
+//! instances.
+//!
+//! The NDJSON form has the collection on its first line and one record per
+//! following line. Exports stream the records out of the database a page at
+//! a time; NDJSON imports are read line by line. Imports write their records
+//! in batches, a few at a time, and can run in the background as a job.
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+        HeaderMap,
+    },
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures_util::{stream, Stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tinybase_core::{
+    clock,
+    events::{Event, EventAction},
+    lineage::{self, with_lineage, Lineage, LineageSource},
+    schema::CollectionSchema,
+    stream_records,
+    validation::{validate_record, ValidationError},
+    Collection, Record,
+};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    auth::RequireAdmin,
+    check_collection_name, check_group_name, check_schema_rules, db_error,
+    jobs::{accepted, spawn_job, JobContext},
+    limits, resolve_collection, AppError, AppState, CollectionResponse,
+};
+
+const NDJSON: &str = "application/x-ndjson";
+
+/// Records an import writes one after the other.
+const BATCH_SIZE: usize = 100;
+
+/// Batches an import writes at the same time.
+const CONCURRENT_BATCHES: usize = 4;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CollectionDump {
+    name: String,
+    schema: Option<CollectionSchema>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+    /// Absent from the first line of an NDJSON dump.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    records: Vec<RecordDump>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RecordDump {
+    id: i64,
+    data: serde_json::Value,
+    created: String,
+    updated: String,
+}
+
+impl From<Record> for RecordDump {
+    fn from(r: Record) -> Self {
+        RecordDump {
+            id: r.id,
+            data: r.data,
+            created: r.created,
+            updated: r.updated,
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DumpFormat {
+    #[default]
+    Json,
+    Ndjson,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ExportQuery {
+    /// `json` (default) or `ndjson`.
+    #[serde(default)]
+    format: DumpFormat,
+}
+
+/// What an import does when the collection already exists.
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictStrategy {
+    /// Reject the import.
+    #[default]
+    Fail,
+    /// Keep the existing schema and records, adding only new records.
+    Skip,
+    /// Replace the schema and the records with the same id.
+    Overwrite,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ImportQuery {
+    /// `fail` (default), `skip` or `overwrite`.
+    #[serde(default)]
+    on_conflict: ConflictStrategy,
+    /// Answer `202 Accepted` with a job to follow instead of waiting for the
+    /// import to finish.
+    #[serde(default)]
+    background: bool,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct InvalidRecord {
+    /// The id of the record in the dump.
+    id: i64,
+    #[schema(value_type = Vec<Object>)]
+    errors: Vec<ValidationError>,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct ImportReport {
+    collection: CollectionResponse,
+    created: usize,
+    overwritten: usize,
+    skipped: usize,
+    /// Records left out because they fail the collection's validation.
+    invalid: Vec<InvalidRecord>,
+}
+
+fn json_line<T: Serialize>(value: &T) -> Result<Bytes, AppError> {
+    serde_json::to_vec(value)
+        .map(Bytes::from)
+        .map_err(|e| AppError::JsonError(e.to_string()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/collections/{id}/export",
+    params(
+        ("id" = String, Path, description = "Collection id or name"),
+        ExportQuery
+    ),
+    responses(
+        (status = 200, description = "Download the collection's schema and records as a `CollectionDump`, or as NDJSON", body = CollectionDump),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Collection not found", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn export_collection(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, AppError> {
+    let id = resolve_collection(state.db.as_ref(), &key).await?;
+    let collection = state
+        .db
+        .get_collection(id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", id)))?;
+    let header = json_line(&CollectionDump {
+        name: collection.name.clone(),
+        schema: collection.schema,
+        group: collection.group,
+        records: Vec::new(),
+    })?;
+    let format = query.format;
+    let records = stream_records(state.db.clone(), id)
+        .enumerate()
+        .map(move |(index, record)| {
+            let mut line = serde_json::to_vec(&RecordDump::from(record?))?;
+            match format {
+                DumpFormat::Json if index > 0 => line.insert(0, b','),
+                DumpFormat::Json => {}
+                DumpFormat::Ndjson => line.push(b'\n'),
+            }
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(Bytes::from(line))
+        });
+    // The JSON form splices the records into the collection object.
+    let (open, close, content_type, extension) = match format {
+        DumpFormat::Json => {
+            let mut open = header.to_vec();
+            open.pop();
+            open.extend_from_slice(b",\"records\":[");
+            (
+                Bytes::from(open),
+                Bytes::from_static(b"]}"),
+                "application/json",
+                "json",
+            )
+        }
+        DumpFormat::Ndjson => {
+            let mut open = header.to_vec();
+            open.push(b'\n');
+            (Bytes::from(open), Bytes::new(), NDJSON, "ndjson")
+        }
+    };
+    let body = stream::once(async { Ok(open) })
+        .chain(records)
+        .chain(stream::once(async { Ok(close) }));
+    Ok((
+        [
+            (CONTENT_TYPE, content_type.to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.{}\"", collection.name, extension),
+            ),
+        ],
+        Body::from_stream(body),
+    )
+        .into_response())
+}
+
+/// Counts of the records an import has processed so far, shared by the
+/// batches importing concurrently.
+#[derive(Default)]
+struct ImportProgress {
+    created: AtomicUsize,
+    overwritten: AtomicUsize,
+    skipped: AtomicUsize,
+    invalid: Mutex<Vec<InvalidRecord>>,
+}
+
+impl ImportProgress {
+    /// The progress of an import of `total` records that started at
+    /// `started`, as reported by its job.
+    fn report(&self, total: usize, started: SystemTime) -> Value {
+        let created = self.created.load(Ordering::Relaxed);
+        let overwritten = self.overwritten.load(Ordering::Relaxed);
+        let skipped = self.skipped.load(Ordering::Relaxed);
+        let invalid = self.invalid.lock().unwrap().len();
+        let processed = created + overwritten + skipped + invalid;
+        // Assumes the rest of the dump goes as fast as what is done so far.
+        let elapsed = clock::now().duration_since(started).unwrap_or_default();
+        let left = total.saturating_sub(processed) as f64;
+        let eta_seconds = (processed > 0)
+            .then(|| (elapsed.as_secs_f64() * left / processed as f64).ceil() as u64);
+        json!({
+            "total": total,
+            "processed": processed,
+            "created": created,
+            "overwritten": overwritten,
+            "skipped": skipped,
+            "invalid": invalid,
+            "eta_seconds": eta_seconds,
+        })
+    }
+}
+
+/// An import in progress into one collection.
+struct Import {
+    state: AppState,
+    strategy: ConflictStrategy,
+    collection: Collection,
+    progress: Arc<ImportProgress>,
+}
+
+impl Import {
+    /// Creates the collection of `dump`, or settles the conflict with the
+    /// existing one of the same name.
+    async fn open(
+        state: &AppState,
+        dump: &CollectionDump,
+        strategy: ConflictStrategy,
+    ) -> Result<Import, AppError> {
+        let db = &state.db;
+        let existing = db
+            .get_collection_by_name(&dump.name)
+            .await
+            .map_err(db_error)?;
+        let collection = match (existing, strategy) {
+            (None, _) => {
+                check_schema_rules(dump.schema.as_ref())?;
+                check_collection_name(db.as_ref(), &dump.name, None).await?;
+                let group = dump.group.as_deref().map(check_group_name).transpose()?;
+                let mut collection = db
+                    .create_collection(&dump.name, &dump.schema)
+                    .await
+                    .map_err(db_error)?;
+                if let Some(group) = group {
+                    collection = db
+                        .set_collection_group(collection.id, Some(group))
+                        .await
+                        .map_err(db_error)?;
+                }
+                state.events.publish(Event::new(
+                    EventAction::CollectionCreated,
+                    collection.id,
+                    None,
+                ));
+                collection
+            }
+            (Some(_), ConflictStrategy::Fail) => {
+                return Err(AppError::Conflict(format!(
+                    "A collection named '{}' already exists",
+                    dump.name
+                )))
+            }
+            (Some(collection), ConflictStrategy::Skip) => collection,
+            (Some(collection), ConflictStrategy::Overwrite) => {
+                check_schema_rules(dump.schema.as_ref())?;
+                let group = dump.group.as_deref().map(check_group_name).transpose()?;
+                let id = collection.id;
+                let mut collection = db
+                    .update_collection(id, None, dump.schema.clone())
+                    .await
+                    .map_err(db_error)?
+                    .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", id)))?;
+                if let Some(group) = group {
+                    collection = db
+                        .set_collection_group(collection.id, Some(group))
+                        .await
+                        .map_err(db_error)?;
+                }
+                state.events.publish(Event::new(
+                    EventAction::CollectionUpdated,
+                    collection.id,
+                    None,
+                ));
+                collection
+            }
+        };
+        Ok(Import {
+            state: state.clone(),
+            strategy,
+            collection,
+            progress: Arc::default(),
+        })
+    }
+
+    /// Imports `records` in batches, [`CONCURRENT_BATCHES`] at a time,
+    /// reporting the progress to `job` after each batch if the import runs
+    /// as one, of `total` records. The first error stops the import.
+    async fn records(
+        &self,
+        records: RecordStream,
+        job: Option<(&JobContext, usize)>,
+    ) -> Result<(), AppError> {
+        let mut batches = records
+            .chunks(BATCH_SIZE)
+            .map(|batch| async move {
+                for record in batch {
+                    self.record(record?).await?;
+                }
+                Ok::<_, AppError>(())
+            })
+            .buffer_unordered(CONCURRENT_BATCHES);
+        while let Some(batch) = batches.next().await {
+            batch?;
+            if let Some((job, total)) = job {
+                job.progress(self.progress.report(total, job.started))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Imports one record, keeping its id unless another collection uses it.
+    async fn record(&self, record: RecordDump) -> Result<(), AppError> {
+        let db = &self.state.db;
+        let collection_id = self.collection.id;
+        let progress = &self.progress;
+        if let Some(schema) = &self.collection.schema {
+            if let Err(errors) = validate_record(schema, &record.data) {
+                progress.invalid.lock().unwrap().push(InvalidRecord {
+                    id: record.id,
+                    errors,
+                });
+                return Ok(());
+            }
+        }
+        let existing = db
+            .get_record(collection_id, record.id)
+            .await
+            .map_err(db_error)?;
+        let (action, id) = if existing.is_some() {
+            if self.strategy != ConflictStrategy::Overwrite {
+                progress.skipped.fetch_add(1, Ordering::Relaxed);
+                return Ok(());
+            }
+            db.update_record(collection_id, record.id, &record.data)
+                .await
+                .map_err(db_error)?;
+            progress.overwritten.fetch_add(1, Ordering::Relaxed);
+            (EventAction::RecordUpdated, record.id)
+        } else {
+            let record = Record {
+                id: record.id,
+                data: record.data,
+                created: record.created,
+                updated: record.updated,
+                version: 1,
+                lineage: lineage::current(),
+            };
+            let id = if db
+                .insert_record(collection_id, &record)
+                .await
+                .map_err(db_error)?
+            {
+                record.id
+            } else {
+                db.create_record(collection_id, &record.data)
+                    .await
+                    .map_err(db_error)?
+                    .id
+            };
+            progress.created.fetch_add(1, Ordering::Relaxed);
+            (EventAction::RecordCreated, id)
+        };
+        self.state
+            .events
+            .publish(Event::new(action, collection_id, Some(id)));
+        Ok(())
+    }
+
+    fn finish(self) -> ImportReport {
+        let progress = &self.progress;
+        let mut invalid = std::mem::take(&mut *progress.invalid.lock().unwrap());
+        // Batches finish out of order.
+        invalid.sort_by_key(|record| record.id);
+        ImportReport {
+            collection: self.collection.into(),
+            created: progress.created.load(Ordering::Relaxed),
+            overwritten: progress.overwritten.load(Ordering::Relaxed),
+            skipped: progress.skipped.load(Ordering::Relaxed),
+            invalid,
+        }
+    }
+}
+
+fn invalid_dump(e: impl std::fmt::Display) -> AppError {
+    AppError::BadRequest(format!("Invalid dump: {}", e))
+}
+
+/// Takes the next complete line out of `buffer`, or what is left of it at
+/// the end of the body.
+fn take_line(buffer: &mut Vec<u8>, end: bool) -> Option<Vec<u8>> {
+    match buffer.iter().position(|b| *b == b'\n') {
+        Some(i) => Some(buffer.drain(..=i).collect()),
+        None if end && !buffer.is_empty() => Some(std::mem::take(buffer)),
+        None => None,
+    }
+}
+
+fn is_blank(line: &[u8]) -> bool {
+    line.iter().all(u8::is_ascii_whitespace)
+}
+
+/// The records of a dump, as they are read.
+type RecordStream = Pin<Box<dyn Stream<Item = Result<RecordDump, AppError>> + Send>>;
+
+/// The non-blank lines of an NDJSON body with their line numbers, read as
+/// the body arrives.
+fn ndjson_lines(body: Body) -> impl Stream<Item = Result<(usize, Vec<u8>), AppError>> {
+    let chunks = body.into_data_stream();
+    let lines = stream::unfold(
+        (chunks, Vec::new(), 0, false),
+        |(mut chunks, mut buffer, mut line_number, mut end)| async move {
+            loop {
+                if let Some(line) = take_line(&mut buffer, end) {
+                    line_number += 1;
+                    return Some((Ok((line_number, line)), (chunks, buffer, line_number, end)));
+                }
+                if end {
+                    return None;
+                }
+                match chunks.next().await.transpose() {
+                    Ok(chunk) => {
+                        end = chunk.is_none();
+                        buffer.extend_from_slice(&chunk.unwrap_or_default());
+                    }
+                    Err(e) => {
+                        return Some((
+                            Err(limits::read_error(e)),
+                            (chunks, Vec::new(), line_number, true),
+                        ))
+                    }
+                }
+            }
+        },
+    );
+    lines.try_filter(|(_, line)| futures_util::future::ready(!is_blank(line)))
+}
+
+/// Reads the collection of a dump, leaving its records to be read as they
+/// are imported. Also returns how many records came with the collection.
+async fn read_dump(
+    body: Body,
+    ndjson: bool,
+) -> Result<(CollectionDump, usize, RecordStream), AppError> {
+    if !ndjson {
+        let body = axum::body::to_bytes(body, usize::MAX)
+            .await
+            .map_err(limits::read_error)?;
+        let mut dump: CollectionDump = serde_json::from_slice(&body).map_err(invalid_dump)?;
+        let records = std::mem::take(&mut dump.records);
+        return Ok((dump, records.len(), Box::pin(stream::iter(records).map(Ok))));
+    }
+    let line_error = |line_number, e| invalid_dump(format!("line {}: {}", line_number, e));
+    let mut lines = Box::pin(ndjson_lines(body));
+    let (line_number, header) = lines
+        .next()
+        .await
+        .ok_or_else(|| invalid_dump("the dump is empty"))??;
+    let mut dump: CollectionDump =
+        serde_json::from_slice(&header).map_err(|e| line_error(line_number, e))?;
+    let records = lines.and_then(move |(line_number, line)| async move {
+        serde_json::from_slice(&line).map_err(|e| line_error(line_number, e))
+    });
+    let header_records = std::mem::take(&mut dump.records);
+    let count = header_records.len();
+    let records = stream::iter(header_records).map(Ok).chain(records);
+    Ok((dump, count, Box::pin(records)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/collections/import",
+    params(ImportQuery),
+    request_body(content = CollectionDump, description = "A dump as produced by the export endpoint; send NDJSON as `application/x-ndjson`"),
+    responses(
+        (status = 200, description = "Recreate a collection from a dump", body = ImportReport),
+        (status = 202, description = "The import goes on in the background as a job, found at the `Location`", body = JobResponse),
+        (status = 400, description = "Malformed dump or invalid schema", body = ProblemDetail),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 409, description = "The collection exists and `on_conflict` is `fail`", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn import_collection(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Query(query): Query<ImportQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, AppError> {
+    let ndjson = headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with(NDJSON));
+    if !query.background {
+        let (dump, _, records) = read_dump(body, ndjson).await?;
+        let import = Import::open(&state, &dump, query.on_conflict).await?;
+        let lineage = Lineage::inherit(LineageSource::Import);
+        with_lineage(lineage, import.records(records, None)).await?;
+        return Ok(Json(import.finish()).into_response());
+    }
+
+    // The whole dump is read first, to count its records.
+    let body = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .map_err(limits::read_error)?;
+    let record_lines = match ndjson {
+        true => body
+            .split(|b| *b == b'\n')
+            .filter(|l| !is_blank(l))
+            .count()
+            .saturating_sub(1),
+        false => 0,
+    };
+    let (dump, with_collection, records) = read_dump(Body::from(body), ndjson).await?;
+    let total = with_collection + record_lines;
+    let import = Import::open(&state, &dump, query.on_conflict).await?;
+    let progress = import.progress.report(total, clock::now());
+    let lineage = Lineage::inherit(LineageSource::Import);
+    let job = spawn_job(&state, "import", progress, move |job| async move {
+        let lineage = lineage.with_job(job.id);
+        with_lineage(lineage, import.records(records, Some((&job, total)))).await?;
+        serde_json::to_value(import.finish()).map_err(|e| AppError::JsonError(e.to_string()))
+    })
+    .await?;
+    Ok(accepted(job).into_response())
+}
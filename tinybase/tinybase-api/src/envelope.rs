@@ -0,0 +1,190 @@
+//! The shape of list responses.
+//!
+//! Lists are returned as a bare JSON array, the original format, unless the
+//! instance is configured with [`ListFormat::Envelope`]. The envelope wraps
+//! the items with their pagination metadata:
+//! `{ items, page, per_page, total, links }`, and `next_cursor` for lists
+//! that can be paged with cursors.
+
+use axum::{
+    http::Uri,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{AppError, CollectionResponse, RecordResponse};
+
+pub(crate) const DEFAULT_PER_PAGE: u64 = 30;
+pub(crate) const MAX_PER_PAGE: u64 = 500;
+
+/// How list endpoints shape their responses.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ListFormat {
+    /// A plain array. Pages only when `page` or `per_page` is given.
+    #[default]
+    Bare,
+    /// A [`ListEnvelope`], always paged.
+    Envelope,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct Pagination {
+    /// 1-based page number.
+    page: Option<u64>,
+    /// Items per page (default 30, at most 500).
+    per_page: Option<u64>,
+}
+
+/// The page a list request asked for.
+pub(crate) struct Page {
+    number: u64,
+    /// `None` returns every item.
+    size: Option<u64>,
+    /// Whether the page was asked for with a cursor, so links to the next
+    /// page use one too.
+    by_cursor: bool,
+    /// Where the next page starts, `None` on the last one.
+    next_cursor: Option<String>,
+}
+
+impl Pagination {
+    pub(crate) fn page(&self, format: ListFormat) -> Result<Page, AppError> {
+        let number = self.page.unwrap_or(1);
+        if number == 0 {
+            return Err(AppError::BadRequest("page starts at 1".to_string()));
+        }
+        let paged = format == ListFormat::Envelope || self.page.is_some();
+        let size = match self.per_page {
+            Some(size) if size == 0 || size > MAX_PER_PAGE => {
+                return Err(AppError::BadRequest(format!(
+                    "per_page must be between 1 and {}",
+                    MAX_PER_PAGE
+                )))
+            }
+            Some(size) => Some(size),
+            None if paged => Some(DEFAULT_PER_PAGE),
+            None => None,
+        };
+        Ok(Page {
+            number,
+            size,
+            by_cursor: false,
+            next_cursor: None,
+        })
+    }
+}
+
+impl Page {
+    pub(crate) fn limit(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// This page with the cursor of the page after it, `None` on the last
+    /// or when the list can't be paged with cursors. `by_cursor` tells
+    /// whether the page itself was asked for with a cursor.
+    pub(crate) fn with_cursors(self, by_cursor: bool, next_cursor: Option<String>) -> Page {
+        Page {
+            by_cursor,
+            next_cursor,
+            ..self
+        }
+    }
+
+    pub(crate) fn offset(&self) -> u64 {
+        self.size.map_or(0, |size| (self.number - 1) * size)
+    }
+
+    /// The items of this page out of all of them, for lists built in memory.
+    pub(crate) fn slice<T>(&self, items: Vec<T>) -> Vec<T> {
+        let offset = self.offset() as usize;
+        let limit = self.size.map_or(usize::MAX, |size| size as usize);
+        items.into_iter().skip(offset).take(limit).collect()
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PageLinks {
+    #[serde(rename = "self")]
+    current: String,
+    first: String,
+    prev: Option<String>,
+    next: Option<String>,
+    last: String,
+}
+
+#[derive(Serialize, ToSchema)]
+#[aliases(RecordPage = ListEnvelope<RecordResponse>, CollectionPage = ListEnvelope<CollectionResponse>)]
+pub struct ListEnvelope<T> {
+    items: Vec<T>,
+    page: u64,
+    per_page: u64,
+    /// Number of items across all pages.
+    total: u64,
+    links: PageLinks,
+    /// Pass as `?cursor=` for the next page; absent on the last page and for
+    /// lists that can't be paged with cursors.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
+}
+
+/// `uri` with `paging` in place of its paging parameters, keeping the others.
+fn paged_link(uri: &Uri, paging: &str) -> String {
+    let mut query: Vec<&str> = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|p| {
+            !p.is_empty()
+                && !p.starts_with("page=")
+                && !p.starts_with("per_page=")
+                && !p.starts_with("cursor=")
+        })
+        .collect();
+    query.push(paging);
+    format!("{}?{}", uri.path(), query.join("&"))
+}
+
+/// `uri` asking for page `number` of `size` items.
+fn page_link(uri: &Uri, number: u64, size: u64) -> String {
+    paged_link(uri, &format!("page={}&per_page={}", number, size))
+}
+
+/// Responds with one page of `total` items, in the configured format. `uri`
+/// is the request's original URI, used for the envelope's links.
+pub(crate) fn list_response<T: Serialize>(
+    format: ListFormat,
+    items: Vec<T>,
+    page: Page,
+    total: u64,
+    uri: &Uri,
+) -> Response {
+    let size = match (format, page.size) {
+        (ListFormat::Envelope, Some(size)) => size,
+        _ => return Json(items).into_response(),
+    };
+    let last = total.div_ceil(size).max(1);
+    let links = PageLinks {
+        current: page_link(uri, page.number, size),
+        first: page_link(uri, 1, size),
+        prev: (page.number > 1).then(|| page_link(uri, (page.number - 1).min(last), size)),
+        next: match (page.by_cursor, &page.next_cursor) {
+            (true, cursor) => cursor
+                .as_ref()
+                .map(|c| paged_link(uri, &format!("cursor={}&per_page={}", c, size))),
+            (false, _) => (page.number < last).then(|| page_link(uri, page.number + 1, size)),
+        },
+        last: page_link(uri, last, size),
+    };
+    Json(ListEnvelope {
+        items,
+        page: page.number,
+        per_page: size,
+        total,
+        links,
+        next_cursor: page.next_cursor,
+    })
+    .into_response()
+}
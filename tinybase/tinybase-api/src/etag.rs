@@ -0,0 +1,105 @@
+//! Optimistic concurrency through `ETag` and `If-Match` (RFC 9110).
+//!
+//! Record reads and writes answer with the record's version as its `ETag`. A
+//! `PUT`, `PATCH` or `DELETE` carrying `If-Match` only goes ahead while the
+//! record is still at one of the versions given, answering `412 Precondition
+//! Failed` otherwise; the check and the write run in one transaction, so two
+//! clients editing the same record can't both win. Collections with
+//! `"optimistic_locking": true` in their schema refuse those writes without
+//! `If-Match` (`428 Precondition Required`), so no client overwrites changes
+//! it hasn't seen.
+
+use axum::http::{
+    header::{ETAG, IF_MATCH},
+    HeaderMap, HeaderName,
+};
+use tinybase_core::{schema::CollectionSchema, Record};
+
+use crate::{db_error, AppError, AppState};
+
+/// The `ETag` header of a response carrying `record`.
+pub(crate) fn header(record: &Record) -> [(HeaderName, String); 1] {
+    [(ETAG, format!("\"{}\"", record.version))]
+}
+
+/// The answer to a write expecting another version of record `record_id`
+/// than the current `version`.
+pub(crate) fn changed(record_id: i64, version: i64) -> AppError {
+    AppError::PreconditionFailed(format!(
+        "Record {} changed since it was read; its ETag is now \"{}\"",
+        record_id, version
+    ))
+}
+
+/// The version a write to `current` must still find, from the `If-Match`
+/// header of the request: `None` when the write needn't check, because the
+/// request has no `If-Match` or sent `*`.
+pub(crate) fn expected_version(
+    headers: &HeaderMap,
+    schema: Option<&CollectionSchema>,
+    current: &Record,
+) -> Result<Option<i64>, AppError> {
+    let mut tags = headers
+        .get_all(IF_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .peekable();
+    if tags.peek().is_none() {
+        if schema.is_some_and(|s| s.optimistic_locking) {
+            return Err(AppError::PreconditionRequired(format!(
+                "Send the ETag of record {} as If-Match to change it",
+                current.id
+            )));
+        }
+        return Ok(None);
+    }
+    let mut matched = false;
+    for tag in tags {
+        if tag == "*" {
+            return Ok(None);
+        }
+        // Weak tags never match here; If-Match compares strongly.
+        let version = tag
+            .strip_prefix('"')
+            .and_then(|t| t.strip_suffix('"'))
+            .and_then(|t| t.parse::<i64>().ok());
+        matched |= version == Some(current.version);
+    }
+    if matched {
+        Ok(Some(current.version))
+    } else {
+        Err(changed(current.id, current.version))
+    }
+}
+
+/// The record a `DELETE` must find unchanged, when the request sent
+/// `If-Match` or the collection requires it. `None` for deletes that needn't
+/// check, and when there's no record to check.
+pub(crate) async fn checked_record(
+    state: &AppState,
+    collection_id: i64,
+    record_id: i64,
+    headers: &HeaderMap,
+) -> Result<Option<Record>, AppError> {
+    let db = &state.db;
+    let schema = db
+        .get_collection(collection_id)
+        .await
+        .map_err(db_error)?
+        .and_then(|c| c.schema);
+    let required = schema.as_ref().is_some_and(|s| s.optimistic_locking);
+    if !required && !headers.contains_key(IF_MATCH) {
+        return Ok(None);
+    }
+    let Some(current) = db
+        .get_record(collection_id, record_id)
+        .await
+        .map_err(db_error)?
+    else {
+        return Ok(None);
+    };
+    Ok(expected_version(headers, schema.as_ref(), &current)?.map(|_| current))
+}
@@ -0,0 +1,328 @@
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{FromRequest, Multipart, Path, Request, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::{Map, Value};
+use tinybase_core::{
+    models::Record,
+    schema::{CollectionSchema, FieldType},
+};
+use tinybase_storage::Storage;
+
+use crate::{
+    access::{record_value, Access, Operation},
+    db_error, resolve_collection, AppError, AppState,
+};
+
+pub struct UploadedFile {
+    field: String,
+    filename: String,
+    bytes: Bytes,
+}
+
+/// A record write body, accepted either as JSON (`{ "data": { ... } }`) or as
+/// `multipart/form-data`.
+///
+/// In a multipart body, a part named `data` holds the JSON record data, other
+/// text parts set individual fields, and file parts are stored as blobs with
+/// their `filename`, `size` and `mime` kept in the record under the part name.
+pub struct RecordPayload {
+    pub data: Value,
+    pub files: Vec<UploadedFile>,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequest<S> for RecordPayload {
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let is_multipart = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|ct| ct.starts_with("multipart/form-data"));
+        if !is_multipart {
+            let Json(record) = Json::<Record>::from_request(req, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            return Ok(RecordPayload {
+                data: record.data,
+                files: Vec::new(),
+            });
+        }
+
+        let mut multipart = Multipart::from_request(req, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let mut data = Map::new();
+        let mut files = Vec::new();
+        while let Some(field) = multipart
+            .next_field()
+            .await
+            .map_err(IntoResponse::into_response)?
+        {
+            let name = field.name().unwrap_or_default().to_string();
+            match field.file_name().map(sanitize_filename) {
+                Some(filename) => {
+                    let filename = filename.map_err(IntoResponse::into_response)?;
+                    let mime = field
+                        .content_type()
+                        .unwrap_or("application/octet-stream")
+                        .to_string();
+                    let bytes = field.bytes().await.map_err(IntoResponse::into_response)?;
+                    data.insert(
+                        name.clone(),
+                        serde_json::json!({
+                            "filename": filename,
+                            "size": bytes.len(),
+                            "mime": mime,
+                        }),
+                    );
+                    files.push(UploadedFile {
+                        field: name,
+                        filename,
+                        bytes,
+                    });
+                }
+                None => {
+                    let text = field.text().await.map_err(IntoResponse::into_response)?;
+                    if name == "data" {
+                        match serde_json::from_str(&text) {
+                            Ok(Value::Object(map)) => {
+                                for (k, v) in map {
+                                    data.entry(k).or_insert(v);
+                                }
+                            }
+                            _ => {
+                                return Err(AppError::BadRequest(
+                                    "The 'data' part must be a JSON object".to_string(),
+                                )
+                                .into_response())
+                            }
+                        }
+                    } else {
+                        let value = serde_json::from_str(&text).unwrap_or(Value::String(text));
+                        data.insert(name, value);
+                    }
+                }
+            }
+        }
+        Ok(RecordPayload {
+            data: Value::Object(data),
+            files,
+        })
+    }
+}
+
+fn sanitize_filename(filename: &str) -> Result<String, AppError> {
+    let name = filename
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .trim();
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(AppError::BadRequest(format!(
+            "Invalid file name '{}'",
+            filename
+        )));
+    }
+    Ok(name.to_string())
+}
+
+fn file_fields(schema: Option<&CollectionSchema>) -> impl Iterator<Item = &String> {
+    schema
+        .into_iter()
+        .flat_map(|s| s.fields.iter())
+        .filter(|(_, def)| def.r#type == FieldType::File)
+        .map(|(name, _)| name)
+}
+
+/// Rejects uploads sent for fields that are not declared as `file` fields.
+pub fn check_file_fields(
+    schema: Option<&CollectionSchema>,
+    files: &[UploadedFile],
+) -> Result<(), AppError> {
+    for file in files {
+        if !file_fields(schema).any(|name| *name == file.field) {
+            return Err(AppError::BadRequest(format!(
+                "Field '{}' is not a file field",
+                file.field
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Types browsers show without running anything in them, so files of these
+/// types are shown in place. Anything else, e.g. HTML or SVG uploads, is
+/// downloaded, so it can't run script on the API's origin.
+const INLINE_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/avif",
+    "image/bmp",
+    "application/pdf",
+];
+
+/// Where the file `filename` of a record's `field` is stored, so two fields
+/// may hold files of the same name.
+fn file_key(collection_id: i64, record_id: i64, field: &str, filename: &str) -> String {
+    format!("{}/{}/{}/{}", collection_id, record_id, field, filename)
+}
+
+/// The file fields of a record that hold a file, with the file's name.
+fn filenames<'a>(
+    schema: Option<&'a CollectionSchema>,
+    data: &'a Value,
+) -> impl Iterator<Item = (&'a str, &'a str)> {
+    file_fields(schema).filter_map(|name| {
+        let filename = data
+            .get(name)
+            .and_then(|f| f.get("filename"))
+            .and_then(Value::as_str)?;
+        Some((name.as_str(), filename))
+    })
+}
+
+/// Stores the uploaded blobs of a record. If one fails, those stored before
+/// it are removed again.
+pub async fn store_files(
+    storage: &dyn Storage,
+    collection_id: i64,
+    record_id: i64,
+    files: &[UploadedFile],
+) -> Result<(), AppError> {
+    for (stored, file) in files.iter().enumerate() {
+        let key = file_key(collection_id, record_id, &file.field, &file.filename);
+        if let Err(e) = storage.put(&key, &file.bytes).await {
+            discard_files(storage, collection_id, record_id, &files[..stored], None).await;
+            return Err(e.into());
+        }
+    }
+    Ok(())
+}
+
+/// Removes the blobs of uploads whose write to the database failed, except
+/// those the stored record `kept` still names. Failures are logged only, as
+/// the write's own error is what the client is told.
+pub async fn discard_files(
+    storage: &dyn Storage,
+    collection_id: i64,
+    record_id: i64,
+    files: &[UploadedFile],
+    kept: Option<(Option<&CollectionSchema>, &Value)>,
+) {
+    for file in files {
+        if let Some((schema, data)) = kept {
+            if filenames(schema, data).any(|kept| kept == (&file.field, &file.filename)) {
+                continue;
+            }
+        }
+        let key = file_key(collection_id, record_id, &file.field, &file.filename);
+        if let Err(e) = storage.delete(&key).await {
+            tracing::warn!(key, error = %e, "failed to remove an orphaned upload");
+        }
+    }
+}
+
+/// Removes the blobs referenced by a record's file fields.
+pub async fn delete_files(
+    storage: &dyn Storage,
+    schema: Option<&CollectionSchema>,
+    collection_id: i64,
+    record_id: i64,
+    data: &Value,
+) -> Result<(), AppError> {
+    for (field, filename) in filenames(schema, data) {
+        storage
+            .delete(&file_key(collection_id, record_id, field, filename))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Removes the blobs an update replaced or dropped: the files of `old` that
+/// `new` no longer names.
+pub async fn delete_replaced_files(
+    storage: &dyn Storage,
+    schema: Option<&CollectionSchema>,
+    collection_id: i64,
+    record_id: i64,
+    old: &Value,
+    new: &Value,
+) -> Result<(), AppError> {
+    for file in filenames(schema, old) {
+        if !filenames(schema, new).any(|kept| kept == file) {
+            let (field, filename) = file;
+            storage
+                .delete(&file_key(collection_id, record_id, field, filename))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/files/{collection}/{record}/{field}/{filename}",
+    params(
+        ("collection" = String, Path, description = "Collection id or name"),
+        ("record" = i64, Path, description = "Record id"),
+        ("field" = String, Path, description = "File field holding the file"),
+        ("filename" = String, Path, description = "File name")
+    ),
+    responses(
+        (status = 200, description = "Download a file attached to a record; only images and PDFs are served inline"),
+        (status = 403, description = "A collection rule denies the request", body = ProblemDetail),
+        (status = 404, description = "File not found", body = ProblemDetail),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn serve_file(
+    State(state): State<AppState>,
+    Path((collection, record_id, field, filename)): Path<(String, i64, String, String)>,
+) -> Result<Response, AppError> {
+    let not_found = || AppError::NotFound(format!("File {} not found", filename));
+    let collection_id = resolve_collection(state.db.as_ref(), &collection).await?;
+    let record = state
+        .db
+        .get_record(collection_id, record_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(not_found)?;
+    Access::to_id(&state, collection_id).await?.check(
+        Operation::View,
+        &record_value(&record),
+        &Value::Null,
+    )?;
+    let mime = record
+        .data
+        .get(&field)
+        .filter(|file| file.get("filename").and_then(Value::as_str) == Some(filename.as_str()))
+        .map(|file| {
+            file.get("mime")
+                .and_then(Value::as_str)
+                .unwrap_or("application/octet-stream")
+                .to_string()
+        })
+        .ok_or_else(not_found)?;
+    let key = file_key(collection_id, record_id, &field, &filename);
+    let bytes = state.storage.get(&key).await?.ok_or_else(not_found)?;
+    let disposition = if INLINE_TYPES.contains(&mime.as_str()) {
+        "inline"
+    } else {
+        "attachment"
+    };
+    let headers = [
+        (header::CONTENT_TYPE, mime),
+        (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        (header::CONTENT_DISPOSITION, disposition.to_string()),
+    ];
+    Ok((StatusCode::OK, headers, bytes).into_response())
+}
@@ -0,0 +1,285 @@
+//! Recording of API traffic into replayable fixture files, to turn a bug
+//! report into a regression test.
+//!
+//! With `TINYBASE_RECORD_FIXTURES=<path>` the server appends every exchange
+//! to `<path>` as one JSON object per line. A test then [`load`]s the file
+//! and [`replay`]s it against a fresh [`in_memory_state`] instance. Only the
+//! request method, URI, `content-type` and body are kept, so credentials in
+//! other headers are never written out. Exchanges with a non-UTF-8 or
+//! non-JSON body, such as file uploads and downloads, are not recorded.
+
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::{Request, State},
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE},
+        HeaderValue, StatusCode,
+    },
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tinybase_core::{setup_database, Db};
+use tinybase_storage::LocalStorage;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tower::ServiceExt;
+
+use crate::AppState;
+
+/// Requests with a larger body are passed through unrecorded.
+const MAX_RECORDED_BODY: usize = 1024 * 1024;
+
+/// Response fields that legitimately differ between runs, at any depth.
+const VOLATILE_FIELDS: &[&str] = &["created", "updated"];
+
+/// One recorded request and the response it got.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Exchange {
+    pub method: String,
+    pub uri: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// The body as JSON when it parses, otherwise as a string.
+    #[serde(default)]
+    pub request: Value,
+    pub status: u16,
+    #[serde(default)]
+    pub response: Value,
+}
+
+#[derive(Clone)]
+struct Recorder {
+    path: Arc<PathBuf>,
+    lock: Arc<Mutex<()>>,
+}
+
+/// Wraps `router` so every exchange it serves is appended to `path`.
+pub fn record(router: Router, path: impl Into<PathBuf>) -> Router {
+    let recorder = Recorder {
+        path: Arc::new(path.into()),
+        lock: Arc::new(Mutex::new(())),
+    };
+    router.layer(middleware::from_fn_with_state(recorder, record_exchange))
+}
+
+async fn record_exchange(
+    State(recorder): State<Recorder>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (parts, body) = request.into_parts();
+    let too_large = parts
+        .headers
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok()?.parse::<usize>().ok())
+        .is_some_and(|length| length > MAX_RECORDED_BODY);
+    if too_large {
+        return next.run(Request::from_parts(parts, body)).await;
+    }
+    let request_body = match buffer(body).await {
+        Buffered::Complete(bytes) => bytes,
+        Buffered::TooLarge(body) => return next.run(Request::from_parts(parts, body)).await,
+        Buffered::Failed(e) => {
+            tracing::warn!(error = %e, "failed to read request body");
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+    };
+    let mut exchange = Exchange {
+        method: parts.method.to_string(),
+        uri: parts.uri.to_string(),
+        content_type: parts
+            .headers
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string),
+        request: Value::Null,
+        status: 0,
+        response: Value::Null,
+    };
+    let request_text = std::str::from_utf8(&request_body).ok().map(body_value);
+
+    let response = next
+        .run(Request::from_parts(parts, Body::from(request_body)))
+        .await;
+    // Streams such as the activity feed never end, so only JSON and empty
+    // responses are buffered.
+    let recordable = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .is_none_or(|v| v.as_bytes().starts_with(b"application/json"));
+    if !recordable {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let Ok(response_body) = to_bytes(body, usize::MAX).await else {
+        return Response::from_parts(parts, Body::empty());
+    };
+    if let (Some(request), Ok(response)) = (request_text, std::str::from_utf8(&response_body)) {
+        exchange.request = request;
+        exchange.status = parts.status.as_u16();
+        exchange.response = body_value(response);
+        if let Err(e) = append(&recorder, &exchange).await {
+            tracing::warn!(error = %e, "failed to record fixture");
+        }
+    }
+    Response::from_parts(parts, Body::from(response_body))
+}
+
+enum Buffered {
+    Complete(Bytes),
+    /// A body longer than [`MAX_RECORDED_BODY`], whole again.
+    TooLarge(Body),
+    Failed(axum::Error),
+}
+
+/// Reads a body without a declared length, such as a chunked one, up to
+/// [`MAX_RECORDED_BODY`]; a longer one is handed back with the chunks read
+/// so far put in front of the rest.
+async fn buffer(body: Body) -> Buffered {
+    let mut stream = body.into_data_stream();
+    let mut chunks = Vec::new();
+    let mut length = 0;
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => return Buffered::Failed(e),
+        };
+        length += chunk.len();
+        chunks.push(Ok(chunk));
+        if length > MAX_RECORDED_BODY {
+            let read = futures_util::stream::iter(chunks);
+            return Buffered::TooLarge(Body::from_stream(read.chain(stream)));
+        }
+    }
+    let mut bytes = Vec::with_capacity(length);
+    for chunk in chunks.into_iter().flatten() {
+        bytes.extend_from_slice(&chunk);
+    }
+    Buffered::Complete(bytes.into())
+}
+
+fn body_value(text: &str) -> Value {
+    if text.is_empty() {
+        return Value::Null;
+    }
+    serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string()))
+}
+
+async fn append(recorder: &Recorder, exchange: &Exchange) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(exchange)?;
+    line.push(b'\n');
+    let _guard = recorder.lock.lock().await;
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(recorder.path.as_ref())
+        .await?;
+    file.write_all(&line).await
+}
+
+/// Reads the exchanges recorded in `path`.
+pub async fn load(path: impl AsRef<Path>) -> std::io::Result<Vec<Exchange>> {
+    let text = tokio::fs::read_to_string(path).await?;
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(std::io::Error::from))
+        .collect()
+}
+
+/// A replayed exchange whose response differs from the recorded one.
+#[derive(Debug)]
+pub struct Mismatch {
+    /// Position of the exchange in the fixture.
+    pub index: usize,
+    pub exchange: Exchange,
+    pub status: u16,
+    pub response: Value,
+}
+
+/// Sends the recorded requests to `app`, in order, and returns the exchanges
+/// whose status or body differ. `created` and `updated` fields are ignored.
+/// A request that can't be built, e.g. for an invalid method or URI, is a
+/// mismatch with status `0` and the error as its response.
+pub async fn replay(app: &Router, exchanges: &[Exchange]) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+    for (index, exchange) in exchanges.iter().enumerate() {
+        let body = match &exchange.request {
+            Value::Null => Body::empty(),
+            Value::String(text) => Body::from(text.clone()),
+            value => Body::from(value.to_string()),
+        };
+        let request = Request::builder()
+            .method(exchange.method.as_str())
+            .uri(exchange.uri.as_str())
+            .body(body);
+        let mut request = match request {
+            Ok(request) => request,
+            Err(e) => {
+                mismatches.push(Mismatch {
+                    index,
+                    exchange: exchange.clone(),
+                    status: 0,
+                    response: Value::String(e.to_string()),
+                });
+                continue;
+            }
+        };
+        if let Some(content_type) = exchange
+            .content_type
+            .as_deref()
+            .and_then(|c| HeaderValue::from_str(c).ok())
+        {
+            request.headers_mut().insert(CONTENT_TYPE, content_type);
+        }
+        let response = match app.clone().oneshot(request).await {
+            Ok(response) => response,
+            Err(never) => match never {},
+        };
+        let status = response.status().as_u16();
+        let body = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap_or_default();
+        let response = body_value(&String::from_utf8_lossy(&body));
+        if status != exchange.status || stable(&response) != stable(&exchange.response) {
+            mismatches.push(Mismatch {
+                index,
+                exchange: exchange.clone(),
+                status,
+                response,
+            });
+        }
+    }
+    mismatches
+}
+
+/// `value` without its volatile fields.
+fn stable(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(key, _)| !VOLATILE_FIELDS.contains(&key.as_str()))
+                .map(|(key, value)| (key.clone(), stable(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(stable).collect()),
+        value => value.clone(),
+    }
+}
+
+/// A fresh instance backed by an in-memory database, to replay fixtures on.
+pub async fn in_memory_state(uploads: impl Into<PathBuf>) -> libsql::Result<AppState> {
+    let db = libsql::Builder::new_local(":memory:").build().await?;
+    let conn = db.connect()?;
+    setup_database(&conn).await?;
+    let db: Arc<dyn Db> = Arc::new(Mutex::new(conn));
+    Ok(AppState::new(
+        db,
+        Arc::new(LocalStorage::new(uploads.into())),
+    ))
+}
@@ -0,0 +1,159 @@
+//! Create-if-absent: `PUT /collections/{id}/records/by/{field}/{value}`
+//! returns the record whose field holds the value, creating it from the body
+//! when there is none. The lookup and the insert share a write lock, so two
+//! clients racing to create the same settings record end up with one.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde_json::{Number, Value};
+#[cfg(feature = "scripting")]
+use tinybase_core::scripts::HookEvent;
+use tinybase_core::{
+    embedded::{prepare_record, Write},
+    events::{Event, EventAction},
+    schema::{CollectionSchema, FieldType},
+};
+
+#[cfg(feature = "scripting")]
+use crate::scripting;
+use crate::{
+    access::{record_value, Access, Operation},
+    db_error,
+    files::{check_file_fields, store_files, RecordPayload},
+    resolve_collection,
+    shape::shape_records,
+    AppError, AppState, RecordResponse,
+};
+
+/// The value `raw` stands for in `field`: a number or boolean for fields
+/// declared as such, a record id for relations, a string otherwise.
+fn field_value(
+    schema: Option<&CollectionSchema>,
+    field: &str,
+    raw: &str,
+) -> Result<Value, AppError> {
+    let field_type = schema.and_then(|s| s.fields.get(field)).map(|f| &f.r#type);
+    let invalid = |kind: &str| {
+        AppError::BadRequest(format!("'{}' is not {} for field '{}'", raw, kind, field))
+    };
+    Ok(match field_type {
+        Some(FieldType::Number) => raw
+            .parse::<i64>()
+            .map(Number::from)
+            .ok()
+            .or_else(|| raw.parse::<f64>().ok().and_then(Number::from_f64))
+            .map(Value::Number)
+            .ok_or_else(|| invalid("a number"))?,
+        Some(FieldType::Boolean) => Value::Bool(raw.parse().map_err(|_| invalid("a boolean"))?),
+        Some(FieldType::Relation { .. }) => {
+            Value::from(raw.parse::<i64>().map_err(|_| invalid("a record id"))?)
+        }
+        _ => Value::String(raw.to_string()),
+    })
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/collections/{id}/records/by/{field}/{value}",
+    params(
+        ("id" = String, Path, description = "Collection id or name"),
+        ("field" = String, Path, description = "Top-level field to look the record up by"),
+        ("value" = String, Path, description = "Value of the field; parsed as a number or boolean for fields of those types")
+    ),
+    request_body(content = Record, description = "Data of the record to create if there is none; the field is set to the value"),
+    responses(
+        (status = 200, description = "The record already existed", body = RecordResponse),
+        (status = 201, description = "The record was created", body = RecordResponse),
+        (status = 400, description = "The value doesn't fit the field's type, or the data is not an object", body = ProblemDetail),
+        (status = 403, description = "A collection rule denies the request", body = ProblemDetail),
+        (status = 404, description = "Collection not found", body = ProblemDetail),
+        (status = 422, description = "Validation error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn get_or_create_record(
+    State(state): State<AppState>,
+    Path((key, field, raw)): Path<(String, String, String)>,
+    mut payload: RecordPayload,
+) -> Result<(StatusCode, Json<RecordResponse>), AppError> {
+    let db = &state.db;
+    let id = resolve_collection(db.as_ref(), &key).await?;
+    let collection = db
+        .get_collection(id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", id)))?;
+    let value = field_value(collection.schema.as_ref(), &field, &raw)?;
+    let access = Access::to(&state, &collection).await?;
+
+    // Most calls find the record, so look before running hooks and
+    // validation for a record that may not be written.
+    let existing = db
+        .find_records_by_field(id, &field, &value)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .min_by_key(|r| r.id);
+    if let Some(record) = existing {
+        access.check(Operation::View, &record_value(&record), &Value::Null)?;
+        let mut response = RecordResponse::from(record);
+        shape_records(&state, id, [&mut response]).await?;
+        return Ok((StatusCode::OK, Json(response)));
+    }
+
+    let Value::Object(data) = &mut payload.data else {
+        return Err(AppError::BadRequest(
+            "Record data must be an object".to_string(),
+        ));
+    };
+    data.insert(field.clone(), value);
+    access.check(
+        Operation::Create,
+        &Value::Object(Default::default()),
+        &payload.data,
+    )?;
+    check_file_fields(collection.schema.as_ref(), &payload.files)?;
+    #[cfg(feature = "scripting")]
+    scripting::run_hooks(
+        &state,
+        &collection,
+        HookEvent::BeforeCreate,
+        None,
+        &mut payload.data,
+        None,
+    )
+    .await?;
+    if let Some(schema) = &collection.schema {
+        prepare_record(db.as_ref(), schema, &mut payload.data, Write::Create).await?;
+    }
+    // Look up what is stored, after transforms, not what was asked for.
+    let value = payload.data.get(&field).cloned().unwrap_or(Value::Null);
+    let (record, created) = db
+        .get_or_create_record(id, &field, &value, &payload.data)
+        .await
+        .map_err(db_error)?;
+    if !created {
+        access.check(Operation::View, &record_value(&record), &Value::Null)?;
+    }
+    if created {
+        if let Err(e) = store_files(state.storage.as_ref(), id, record.id, &payload.files).await {
+            db.delete_record(id, record.id).await?;
+            return Err(e);
+        }
+        state
+            .events
+            .publish(Event::new(EventAction::RecordCreated, id, Some(record.id)));
+        #[cfg(feature = "scripting")]
+        scripting::after_create(&state, &collection, &record).await;
+    }
+    let mut response = RecordResponse::from(record);
+    shape_records(&state, id, [&mut response]).await?;
+    let status = if created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    Ok((status, Json(response)))
+}
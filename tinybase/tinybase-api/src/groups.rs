@@ -0,0 +1,162 @@
+//! Collection groups, so instances with many collections stay navigable.
+//!
+//! A group is just a name collections are filed under: it exists while at
+//! least one collection is in it. Collections join and leave groups through
+//! their `group` field; these endpoints list the groups and rename or
+//! dissolve a group as a whole.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tinybase_core::{
+    events::{Event, EventAction},
+    Collection,
+};
+use utoipa::ToSchema;
+
+use crate::{auth::RequireAdmin, db_error, AppError, AppState};
+
+const MAX_GROUP_NAME_LEN: usize = 64;
+
+/// Checks a group name, returning it without surrounding whitespace.
+pub(crate) fn check_group_name(name: &str) -> Result<&str, AppError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_GROUP_NAME_LEN {
+        return Err(AppError::BadRequest(format!(
+            "Group names must be between 1 and {} characters",
+            MAX_GROUP_NAME_LEN
+        )));
+    }
+    Ok(name)
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CollectionGroup {
+    name: String,
+    /// Names of the collections in the group, sorted.
+    collections: Vec<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RenameGroup {
+    name: String,
+}
+
+async fn group_members(state: &AppState, group: &str) -> Result<Vec<Collection>, AppError> {
+    let mut collections = state.db.list_collections().await.map_err(db_error)?;
+    collections.retain(|c| c.group.as_deref() == Some(group));
+    if collections.is_empty() {
+        return Err(AppError::NotFound(format!("Group {} not found", group)));
+    }
+    Ok(collections)
+}
+
+/// Files every collection of `members` under `group`.
+async fn regroup(
+    state: &AppState,
+    members: Vec<Collection>,
+    group: Option<&str>,
+) -> Result<(), AppError> {
+    for collection in members {
+        state
+            .db
+            .set_collection_group(collection.id, group)
+            .await
+            .map_err(db_error)?;
+        state.events.publish(Event::new(
+            EventAction::CollectionUpdated,
+            collection.id,
+            None,
+        ));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/collection-groups",
+    responses(
+        (status = 200, description = "List the collection groups with their collections, sorted by name", body = Vec<CollectionGroup>),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn list_groups(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<CollectionGroup>>, AppError> {
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for collection in state.db.list_collections().await.map_err(db_error)? {
+        if let Some(group) = collection.group {
+            groups.entry(group).or_default().push(collection.name);
+        }
+    }
+    Ok(Json(
+        groups
+            .into_iter()
+            .map(|(name, mut collections)| {
+                collections.sort();
+                CollectionGroup { name, collections }
+            })
+            .collect(),
+    ))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/collection-groups/{name}",
+    params(
+        ("name" = String, Path, description = "Group name")
+    ),
+    request_body = RenameGroup,
+    responses(
+        (status = 200, description = "Rename a group, merging it into the group of the new name if there is one", body = CollectionGroup),
+        (status = 400, description = "Invalid group name", body = ProblemDetail),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Group not found", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn rename_group(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(payload): Json<RenameGroup>,
+) -> Result<Json<CollectionGroup>, AppError> {
+    let new_name = check_group_name(&payload.name)?;
+    let members = group_members(&state, &name).await?;
+    regroup(&state, members, Some(new_name)).await?;
+    let mut collections: Vec<String> = group_members(&state, new_name)
+        .await?
+        .into_iter()
+        .map(|c| c.name)
+        .collect();
+    collections.sort();
+    Ok(Json(CollectionGroup {
+        name: new_name.to_string(),
+        collections,
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/collection-groups/{name}",
+    params(
+        ("name" = String, Path, description = "Group name")
+    ),
+    responses(
+        (status = 204, description = "Dissolve a group; its collections are kept, ungrouped"),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Group not found", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn delete_group(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let members = group_members(&state, &name).await?;
+    regroup(&state, members, None).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
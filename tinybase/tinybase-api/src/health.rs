@@ -0,0 +1,203 @@
+//! Liveness and replication health, for load balancers and monitoring.
+//!
+//! `GET /api/v1/health?verbose=true` also checks the services the instance
+//! depends on, concurrently: the database, upload storage, the shared state
+//! store (e.g. Redis), the search backend (e.g. Meilisearch), the identity
+//! provider's key set and the primary of an embedded replica. Any failing
+//! check degrades the instance, so deploy pipelines polling it catch
+//! misconfiguration before the first request that needs the service does.
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use futures_util::future::{join_all, BoxFuture, FutureExt};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tinybase_core::replica::ReplicationStatus;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::AppState;
+
+/// How long a dependency may take to answer its check.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Key probed in upload storage and the state store; it needn't exist.
+const PROBE_KEY: &str = "tinybase-health-probe";
+
+#[derive(Serialize, ToSchema)]
+pub struct ReplicationHealth {
+    /// URL of the primary.
+    url: String,
+    /// Whether the last sync succeeded.
+    ok: bool,
+    /// When the replica last synced successfully.
+    last_sync: Option<String>,
+    /// When a sync was last attempted.
+    last_attempt: Option<String>,
+    /// Replication frame the replica is at.
+    frame_no: Option<u64>,
+    /// Write-ahead log frames pulled since startup.
+    frames_synced: u64,
+    /// Why the last sync failed.
+    error: Option<String>,
+    sync_interval_secs: u64,
+}
+
+impl From<ReplicationStatus> for ReplicationHealth {
+    fn from(s: ReplicationStatus) -> Self {
+        ReplicationHealth {
+            url: s.url,
+            ok: s.ok,
+            last_sync: s.last_sync,
+            last_attempt: s.last_attempt,
+            frame_no: s.frame_no,
+            frames_synced: s.frames_synced,
+            error: s.error,
+            sync_interval_secs: s.sync_interval_secs,
+        }
+    }
+}
+
+/// The outcome of checking one dependency.
+#[derive(Serialize, ToSchema)]
+pub struct DependencyHealth {
+    /// `database`, `storage`, `store`, `search`, `identity_provider` or
+    /// `replica`.
+    name: &'static str,
+    /// `ok` or `failed`.
+    status: &'static str,
+    /// How long the check took; absent for the replica, whose last sync is
+    /// reported instead.
+    latency_ms: Option<f64>,
+    /// Why the check failed.
+    error: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Health {
+    /// `ok`, or `degraded` when the replica failed to sync or, verbosely, a
+    /// dependency failed its check.
+    status: &'static str,
+    /// Present when running on an embedded replica.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    replication: Option<ReplicationHealth>,
+    /// The dependency checks, when asked for with `verbose`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dependencies: Option<Vec<DependencyHealth>>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct HealthQuery {
+    /// Also check the services the instance depends on.
+    #[serde(default)]
+    verbose: bool,
+}
+
+/// Runs `check`, timing it and giving up after [`CHECK_TIMEOUT`].
+fn timed<'a>(
+    name: &'static str,
+    check: impl Future<Output = Result<(), String>> + Send + 'a,
+) -> BoxFuture<'a, DependencyHealth> {
+    async move {
+        let started = Instant::now();
+        let result = tokio::time::timeout(CHECK_TIMEOUT, check)
+            .await
+            .unwrap_or_else(|_| Err(format!("No answer within {:?}", CHECK_TIMEOUT)));
+        DependencyHealth {
+            name,
+            status: if result.is_ok() { "ok" } else { "failed" },
+            latency_ms: Some(started.elapsed().as_secs_f64() * 1000.0),
+            error: result.err(),
+        }
+    }
+    .boxed()
+}
+
+/// Checks every configured dependency, concurrently.
+async fn check_dependencies(state: &AppState) -> Vec<DependencyHealth> {
+    let checks = vec![
+        timed("database", async {
+            state.db.ping().await.map_err(|e| e.to_string())
+        }),
+        timed("storage", async {
+            state
+                .storage
+                .get(PROBE_KEY)
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }),
+        timed("store", async {
+            state
+                .store
+                .get(PROBE_KEY)
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }),
+        timed("search", async {
+            state.search.check().await.map_err(|e| e.to_string())
+        }),
+    ];
+    #[cfg(feature = "jwt")]
+    let checks: Vec<_> = checks
+        .into_iter()
+        .chain(
+            state
+                .jwt
+                .as_ref()
+                .map(|jwt| timed("identity_provider", jwt.check())),
+        )
+        .collect();
+    let mut dependencies = join_all(checks).await;
+    if let Some(replica) = &state.replica {
+        let status = replica.status();
+        dependencies.push(DependencyHealth {
+            name: "replica",
+            status: if status.ok { "ok" } else { "failed" },
+            latency_ms: None,
+            error: status.error,
+        });
+    }
+    dependencies
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/health",
+    params(HealthQuery),
+    responses(
+        (status = 200, description = "The instance is healthy", body = Health),
+        (status = 503, description = "The instance is degraded, e.g. its replica failed to sync", body = Health)
+    )
+)]
+pub(crate) async fn health(
+    State(state): State<AppState>,
+    Query(query): Query<HealthQuery>,
+) -> (StatusCode, Json<Health>) {
+    let replication = state.replica.as_ref().map(|replica| replica.status());
+    let dependencies = if query.verbose {
+        Some(check_dependencies(&state).await)
+    } else {
+        None
+    };
+    let ok = replication.as_ref().is_none_or(|status| status.ok)
+        && dependencies
+            .iter()
+            .flatten()
+            .all(|dependency| dependency.status == "ok");
+    let health = Health {
+        status: if ok { "ok" } else { "degraded" },
+        replication: replication.map(Into::into),
+        dependencies,
+    };
+    let code = if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (code, Json(health))
+}
@@ -0,0 +1,97 @@
+//! `Idempotency-Key` on record creation.
+//!
+//! A `POST /api/v1/collections/{id}/records` carrying `Idempotency-Key` is
+//! remembered for a day: repeating it with the same key answers `201
+//! Created` with the record the first request created, as it is now, and
+//! `Idempotent-Replayed: true`, instead of creating another. A repeat
+//! arriving while the first request is still running gets `409 Conflict`,
+//! and a key first used on another collection `400 Bad Request`. A request
+//! that fails creates nothing and frees its key, so it can be retried.
+
+use axum::http::{HeaderMap, HeaderName};
+use std::time::Duration;
+use tinybase_core::{idempotency::IdempotencyClaim, Record};
+
+use crate::{db_error, AppError, AppState};
+
+const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+pub(crate) const IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+/// How long a key is remembered.
+const KEY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Longest key accepted.
+const MAX_KEY_LENGTH: usize = 255;
+
+/// The `Idempotency-Key` of a request, if it sent one.
+pub(crate) fn key(headers: &HeaderMap) -> Result<Option<String>, AppError> {
+    let Some(value) = headers.get(IDEMPOTENCY_KEY) else {
+        return Ok(None);
+    };
+    let key = value.to_str().map(str::trim).unwrap_or_default();
+    if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+        return Err(AppError::BadRequest(format!(
+            "Idempotency-Key must be between 1 and {} visible ASCII characters",
+            MAX_KEY_LENGTH
+        )));
+    }
+    Ok(Some(key.to_string()))
+}
+
+/// Claims `key` for creating a record in collection `collection_id`,
+/// returning the record created under it before, if any, for replaying.
+pub(crate) async fn claim(
+    state: &AppState,
+    key: &str,
+    collection_id: i64,
+) -> Result<Option<Record>, AppError> {
+    let db = &state.db;
+    let claim = db
+        .claim_idempotency_key(key, collection_id, KEY_TTL)
+        .await
+        .map_err(db_error)?;
+    let (claimed_for, record_id) = match claim {
+        IdempotencyClaim::Claimed => return Ok(None),
+        IdempotencyClaim::InProgress { collection_id } => (collection_id, None),
+        IdempotencyClaim::Completed {
+            collection_id,
+            record_id,
+        } => (collection_id, Some(record_id)),
+    };
+    if claimed_for != collection_id {
+        return Err(AppError::BadRequest(format!(
+            "Idempotency-Key '{}' was used to create a record in collection {}",
+            key, claimed_for
+        )));
+    }
+    let Some(record_id) = record_id else {
+        return Err(AppError::Conflict(format!(
+            "A request with Idempotency-Key '{}' is still being processed",
+            key
+        )));
+    };
+    db.get_record(collection_id, record_id)
+        .await
+        .map_err(db_error)?
+        .map(Some)
+        .ok_or_else(|| {
+            AppError::NotFound(format!(
+                "Record {} created with Idempotency-Key '{}' has been deleted",
+                record_id, key
+            ))
+        })
+}
+
+/// Completes a claimed `key` with the record created under it, or frees it
+/// when none was.
+pub(crate) async fn finish(
+    state: &AppState,
+    key: &str,
+    created: Option<&Record>,
+) -> Result<(), AppError> {
+    let db = &state.db;
+    match created {
+        Some(record) => db.complete_idempotency_key(key, record.id).await?,
+        None => db.release_idempotency_key(key).await?,
+    }
+    Ok(())
+}
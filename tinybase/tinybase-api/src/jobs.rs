@@ -0,0 +1,262 @@
+//! Background jobs, for operations too long to wait on in one request:
+//! background imports and backups.
+//!
+//! Starting one answers `202 Accepted` with the job, whose state, progress
+//! and outcome are kept in the `jobs` table. A fixed number of workers run
+//! jobs at once; the rest wait, queued. Admins poll a job, follow it as a
+//! stream of server-sent events, or cancel it. Cancelling stops the job at
+//! its next step, keeping what it has done so far.
+
+use axum::{
+    extract::{Path, State},
+    http::{header::LOCATION, HeaderName, StatusCode},
+    response::sse::{Event as SseEvent, KeepAlive, Sse},
+    Json,
+};
+use futures_util::{stream, Future, Stream};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tinybase_core::{
+    checksums, clock,
+    jobs::{Job, JobState},
+};
+use tokio::{sync::Semaphore, task::AbortHandle};
+use utoipa::ToSchema;
+
+use crate::{auth::RequireAdmin, db_error, AppError, AppState, DbState};
+
+/// Jobs run at once unless configured otherwise.
+pub const DEFAULT_JOB_WORKERS: usize = 2;
+
+/// How often the event stream reports progress.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The workers running jobs, and the jobs they run.
+pub struct Jobs {
+    workers: Arc<Semaphore>,
+    running: Mutex<HashMap<i64, AbortHandle>>,
+}
+
+impl Jobs {
+    /// Runs at most `workers` jobs at once.
+    pub fn new(workers: usize) -> Self {
+        Self {
+            workers: Arc::new(Semaphore::new(workers)),
+            running: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// What a running job gets to report its progress with.
+pub(crate) struct JobContext {
+    pub(crate) id: i64,
+    db: DbState,
+    /// When the job left the queue.
+    pub(crate) started: SystemTime,
+}
+
+impl JobContext {
+    /// Replaces the progress of the job.
+    pub(crate) async fn progress(&self, progress: Value) -> Result<(), AppError> {
+        self.db
+            .update_job(self.id, JobState::Running, Some(&progress), None)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Queues `work` as a job of `kind`, starting with `progress`, and runs it
+/// once a worker is free. The job completes with the value `work` returns,
+/// or fails with its error.
+pub(crate) async fn spawn_job<F, Fut>(
+    state: &AppState,
+    kind: &str,
+    progress: Value,
+    work: F,
+) -> Result<Job, AppError>
+where
+    F: FnOnce(JobContext) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<Value, AppError>> + Send,
+{
+    let db = state.db.clone();
+    let job = db.create_job(kind, &progress).await.map_err(db_error)?;
+    let jobs = state.jobs.clone();
+    let id = job.id;
+    let checksum_mode = state.checksum_mode;
+    // Held until the job is registered, so it cannot unregister first.
+    let mut running = jobs.running.lock().unwrap();
+    let task = tokio::spawn(clock::with_clock(state.clock.clone(), {
+        let jobs = jobs.clone();
+        async move {
+            let _worker = jobs.workers.clone().acquire_owned().await;
+            // Cancelled while queued.
+            if let Ok(true) = db.update_job(id, JobState::Running, None, None).await {
+                let context = JobContext {
+                    id,
+                    db: db.clone(),
+                    started: clock::now(),
+                };
+                let (state, outcome) =
+                    match checksums::with_checksum_mode(checksum_mode, work(context)).await {
+                        Ok(result) => (JobState::Completed, result),
+                        Err(e) => (JobState::Failed, json!(e.into_problem().1)),
+                    };
+                if let Err(e) = db.update_job(id, state, None, Some(&outcome)).await {
+                    tracing::error!(job = id, error = %e, "failed to record the outcome of a job");
+                }
+            }
+            jobs.running.lock().unwrap().remove(&id);
+        }
+    }));
+    running.insert(id, task.abort_handle());
+    Ok(job)
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct JobResponse {
+    id: i64,
+    /// What the job does: `import`, `backup`, `reindex`, `upgrade` or `scrub`.
+    kind: String,
+    #[schema(value_type = String, example = "running")]
+    state: JobState,
+    /// How far the job got; imports report `total`, `processed` and
+    /// `eta_seconds` among others.
+    progress: Value,
+    /// What a completed job produced, e.g. the import report.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    /// Why a job failed, as a problem detail.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<Value>,
+    created: String,
+    updated: String,
+}
+
+impl From<Job> for JobResponse {
+    fn from(job: Job) -> Self {
+        let (result, error) = match job.state {
+            JobState::Completed => (job.outcome, None),
+            JobState::Failed => (None, job.outcome),
+            _ => (None, None),
+        };
+        JobResponse {
+            id: job.id,
+            kind: job.kind,
+            state: job.state,
+            progress: job.progress,
+            result,
+            error,
+            created: job.created,
+            updated: job.updated,
+        }
+    }
+}
+
+/// The `202 Accepted` answer to a request that started `job`.
+pub(crate) fn accepted(job: Job) -> (StatusCode, [(HeaderName, String); 1], Json<JobResponse>) {
+    (
+        StatusCode::ACCEPTED,
+        [(LOCATION, format!("/api/v1/jobs/{}", job.id))],
+        Json(job.into()),
+    )
+}
+
+async fn find_job(db: &DbState, id: i64) -> Result<Job, AppError> {
+    db.get_job(id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| AppError::NotFound(format!("Job {} not found", id)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/jobs/{id}",
+    params(
+        ("id" = i64, Path, description = "Job id")
+    ),
+    responses(
+        (status = 200, description = "Get the state and progress of a job", body = JobResponse),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Job not found", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn get_job(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<JobResponse>, AppError> {
+    Ok(Json(find_job(&state.db, id).await?.into()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/jobs/{id}/cancel",
+    params(
+        ("id" = i64, Path, description = "Job id")
+    ),
+    responses(
+        (status = 200, description = "Cancel a queued or running job", body = JobResponse),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Job not found", body = ProblemDetail),
+        (status = 409, description = "The job has already finished", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn cancel_job(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<JobResponse>, AppError> {
+    find_job(&state.db, id).await?;
+    let cancelled = state
+        .db
+        .update_job(id, JobState::Cancelled, None, None)
+        .await?;
+    if !cancelled {
+        return Err(AppError::Conflict(format!(
+            "Job {} has already finished",
+            id
+        )));
+    }
+    if let Some(task) = state.jobs.running.lock().unwrap().remove(&id) {
+        task.abort();
+    }
+    Ok(Json(find_job(&state.db, id).await?.into()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/jobs/{id}/events",
+    params(
+        ("id" = i64, Path, description = "Job id")
+    ),
+    responses(
+        (status = 200, description = "Server-sent stream of `progress` events with the job, every second until it finishes", content_type = "text/event-stream"),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Job not found", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn job_events(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Sse<impl Stream<Item = Result<SseEvent, Infallible>>>, AppError> {
+    find_job(&state.db, id).await?;
+    let ticker = tokio::time::interval(PROGRESS_INTERVAL);
+    let stream = stream::unfold(Some((state.db, ticker)), move |next| async move {
+        let (db, mut ticker) = next?;
+        ticker.tick().await;
+        let job = find_job(&db, id).await.ok()?;
+        // The last event is the finished job.
+        let finished = job.state.is_finished();
+        let sse = SseEvent::default()
+            .event("progress")
+            .json_data(JobResponse::from(job))
+            .unwrap_or_default();
+        Some((Ok(sse), (!finished).then_some((db, ticker))))
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
@@ -0,0 +1,221 @@
+//! Trusting the tokens of an external identity provider, e.g. Auth0 or
+//! Keycloak, instead of only the API keys Tinybase issues itself.
+//!
+//! With an issuer and its JWKS URL configured, a bearer token that is a JWT
+//! signed by one of the provider's keys, issued by that issuer (and for the
+//! configured audience, if any) and not expired identifies its `sub`. Its
+//! roles are read from a claim, `roles` unless set otherwise; a token holding
+//! the admin role may manage collections like a Tinybase admin. The key set
+//! is fetched lazily, cached, and fetched again when a token names a key it
+//! doesn't hold, so the provider can rotate keys.
+
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{Jwk, JwkSet},
+    DecodingKey, Validation,
+};
+use serde_json::Value;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tinybase_core::clock;
+use tokio::sync::Mutex;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a fetched key set is used before it is fetched again.
+const KEYS_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Least time between two fetches, so tokens naming unknown keys can't make
+/// every request hit the provider.
+const MIN_REFRESH: Duration = Duration::from_secs(30);
+
+/// Seconds tokens are still accepted past their `exp`, for clock skew.
+const LEEWAY_SECS: u64 = 60;
+
+/// Where tokens come from and how their claims map to Tinybase roles.
+#[derive(Clone, Debug)]
+pub struct JwtSettings {
+    /// The `iss` tokens must carry.
+    pub issuer: String,
+    pub jwks_url: String,
+    /// The `aud` tokens must carry, if checked.
+    pub audience: Option<String>,
+    /// Dot-separated path of the claim holding the roles, e.g.
+    /// `realm_access.roles` for Keycloak. An array of strings, or a string
+    /// of space-separated roles such as `scope`.
+    pub roles_claim: String,
+    /// The role that makes a token an admin one.
+    pub admin_role: String,
+}
+
+impl JwtSettings {
+    /// Reads `TINYBASE_JWT_ISSUER` and `TINYBASE_JWKS_URL`, plus the optional
+    /// `TINYBASE_JWT_AUDIENCE`, `TINYBASE_JWT_ROLES_CLAIM` and
+    /// `TINYBASE_JWT_ADMIN_ROLE`. `Ok(None)` unless an issuer is set.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let Ok(issuer) = std::env::var("TINYBASE_JWT_ISSUER") else {
+            return Ok(None);
+        };
+        let jwks_url = std::env::var("TINYBASE_JWKS_URL")
+            .map_err(|_| "TINYBASE_JWT_ISSUER is set but TINYBASE_JWKS_URL is not".to_string())?;
+        Ok(Some(Self {
+            issuer,
+            jwks_url,
+            audience: std::env::var("TINYBASE_JWT_AUDIENCE").ok(),
+            roles_claim: std::env::var("TINYBASE_JWT_ROLES_CLAIM")
+                .unwrap_or_else(|_| "roles".to_string()),
+            admin_role: std::env::var("TINYBASE_JWT_ADMIN_ROLE")
+                .unwrap_or_else(|_| "admin".to_string()),
+        }))
+    }
+}
+
+/// Who a verified token identifies.
+#[derive(Clone, Debug)]
+pub struct Identity {
+    /// The `sub` claim.
+    pub subject: String,
+    pub roles: Vec<String>,
+    /// Seconds since the Unix epoch.
+    pub expires: Option<u64>,
+    /// Whether `roles` holds the admin role.
+    pub admin: bool,
+    pub claims: Value,
+}
+
+/// Verifies tokens against the provider's key set, see the
+/// [module docs](self).
+pub struct JwtVerifier {
+    settings: JwtSettings,
+    client: reqwest::Client,
+    keys: Mutex<Option<(JwkSet, Instant)>>,
+}
+
+fn unix_now() -> u64 {
+    clock::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn lookup<'a>(claims: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(claims, |value, key| value.get(key))
+}
+
+impl JwtVerifier {
+    pub fn new(settings: JwtSettings) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(FETCH_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self {
+            settings,
+            client,
+            keys: Mutex::new(None),
+        }
+    }
+
+    pub fn settings(&self) -> &JwtSettings {
+        &self.settings
+    }
+
+    /// Fetches the key set, bypassing the cache, to check the provider
+    /// answers.
+    pub async fn check(&self) -> Result<(), String> {
+        self.fetch_keys().await.map(|_| ())
+    }
+
+    async fn fetch_keys(&self) -> Result<JwkSet, String> {
+        let response = self
+            .client
+            .get(&self.settings.jwks_url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Fetching the JWKS failed: {}", e))?;
+        response
+            .json()
+            .await
+            .map_err(|e| format!("Invalid JWKS: {}", e))
+    }
+
+    /// The key `kid` names, fetching the key set if it is not cached, stale,
+    /// or lacks the key.
+    async fn key(&self, kid: Option<&str>) -> Result<Jwk, String> {
+        let mut cached = self.keys.lock().await;
+        let find = |keys: &JwkSet| match kid {
+            Some(kid) => keys.find(kid).cloned(),
+            None => keys.keys.first().cloned(),
+        };
+        if let Some((keys, fetched)) = cached.as_ref() {
+            let fresh = fetched.elapsed() < KEYS_TTL;
+            match find(keys) {
+                Some(key) if fresh => return Ok(key),
+                None if fetched.elapsed() < MIN_REFRESH => {
+                    return Err("Token signed by an unknown key".to_string())
+                }
+                _ => {}
+            }
+        }
+        let keys = self.fetch_keys().await?;
+        let key = find(&keys);
+        *cached = Some((keys, Instant::now()));
+        key.ok_or_else(|| "Token signed by an unknown key".to_string())
+    }
+
+    /// Checks the signature, issuer, audience and expiry of `token`.
+    pub async fn verify(&self, token: &str) -> Result<Identity, String> {
+        let header = decode_header(token).map_err(|e| format!("Invalid token: {}", e))?;
+        let jwk = self.key(header.kid.as_deref()).await?;
+        if jwk
+            .common
+            .key_algorithm
+            .is_some_and(|alg| format!("{:?}", alg) != format!("{:?}", header.alg))
+        {
+            return Err("Token algorithm does not match its key".to_string());
+        }
+        let key = DecodingKey::from_jwk(&jwk).map_err(|e| format!("Invalid JWK: {}", e))?;
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.settings.issuer]);
+        match &self.settings.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        // Expiry is checked below, on the server's clock.
+        validation.validate_exp = false;
+        let claims = decode::<Value>(token, &key, &validation)
+            .map_err(|e| format!("Invalid token: {}", e))?
+            .claims;
+        let expires = claims.get("exp").and_then(Value::as_u64);
+        let now = unix_now();
+        if expires.is_some_and(|exp| exp + LEEWAY_SECS <= now) {
+            return Err("Token expired".to_string());
+        }
+        if claims
+            .get("nbf")
+            .and_then(Value::as_u64)
+            .is_some_and(|nbf| nbf > now + LEEWAY_SECS)
+        {
+            return Err("Token not valid yet".to_string());
+        }
+        let roles: Vec<String> = match lookup(&claims, &self.settings.roles_claim) {
+            Some(Value::Array(roles)) => roles
+                .iter()
+                .filter_map(|role| role.as_str().map(str::to_string))
+                .collect(),
+            Some(Value::String(roles)) => roles.split_whitespace().map(str::to_string).collect(),
+            _ => Vec::new(),
+        };
+        Ok(Identity {
+            subject: claims
+                .get("sub")
+                .and_then(Value::as_str)
+                .ok_or("Token has no subject")?
+                .to_string(),
+            admin: roles.contains(&self.settings.admin_role),
+            roles,
+            expires,
+            claims,
+        })
+    }
+}
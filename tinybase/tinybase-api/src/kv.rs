@@ -0,0 +1,149 @@
+//! The key-value store, for app settings, feature flags and counters that
+//! don't deserve a collection: JSON values under `/kv/{namespace}/{key}`,
+//! optionally expiring after a TTL. Script hooks reach the same entries
+//! through `tinybase.kv`.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+use tinybase_core::kv::KvEntry;
+use utoipa::ToSchema;
+
+use crate::{auth::RequireAdmin, db_error, AppError, AppState};
+
+#[derive(Serialize, ToSchema)]
+pub struct KvEntryResponse {
+    namespace: String,
+    key: String,
+    #[schema(value_type = Object)]
+    value: Value,
+    /// When the entry expires; never when null.
+    expires: Option<String>,
+    created: String,
+    updated: String,
+}
+
+impl From<KvEntry> for KvEntryResponse {
+    fn from(e: KvEntry) -> Self {
+        KvEntryResponse {
+            namespace: e.namespace,
+            key: e.key,
+            value: e.value,
+            expires: e.expires,
+            created: e.created,
+            updated: e.updated,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct KvSetRequest {
+    #[schema(value_type = Object)]
+    value: Value,
+    /// Seconds until the entry expires; it never does when left out.
+    ttl: Option<u64>,
+}
+
+/// Sets an entry, for the API and for scripts.
+pub(crate) async fn set_entry(
+    state: &AppState,
+    namespace: &str,
+    key: &str,
+    value: &Value,
+    ttl: Option<u64>,
+) -> Result<KvEntry, AppError> {
+    if ttl == Some(0) {
+        return Err(AppError::BadRequest(
+            "ttl must be at least 1 second".to_string(),
+        ));
+    }
+    state
+        .db
+        .set_kv(namespace, key, value, ttl.map(Duration::from_secs))
+        .await
+        .map_err(db_error)
+}
+
+fn not_found(namespace: &str, key: &str) -> AppError {
+    AppError::NotFound(format!("Key '{}' not found in '{}'", key, namespace))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/kv/{namespace}/{key}",
+    params(
+        ("namespace" = String, Path, description = "Namespace of the entry"),
+        ("key" = String, Path, description = "Key of the entry")
+    ),
+    responses(
+        (status = 200, description = "Get an entry", body = KvEntryResponse),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "No entry, or it expired", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn get_entry(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Path((namespace, key)): Path<(String, String)>,
+) -> Result<Json<KvEntryResponse>, AppError> {
+    let entry = state
+        .db
+        .get_kv(&namespace, &key)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| not_found(&namespace, &key))?;
+    Ok(Json(entry.into()))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/kv/{namespace}/{key}",
+    params(
+        ("namespace" = String, Path, description = "Namespace of the entry"),
+        ("key" = String, Path, description = "Key of the entry")
+    ),
+    request_body = KvSetRequest,
+    responses(
+        (status = 200, description = "Set an entry, replacing its value and TTL", body = KvEntryResponse),
+        (status = 400, description = "Invalid TTL", body = ProblemDetail),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn put_entry(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Path((namespace, key)): Path<(String, String)>,
+    Json(request): Json<KvSetRequest>,
+) -> Result<Json<KvEntryResponse>, AppError> {
+    let entry = set_entry(&state, &namespace, &key, &request.value, request.ttl).await?;
+    Ok(Json(entry.into()))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/kv/{namespace}/{key}",
+    params(
+        ("namespace" = String, Path, description = "Namespace of the entry"),
+        ("key" = String, Path, description = "Key of the entry")
+    ),
+    responses(
+        (status = 204, description = "Delete an entry"),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "No entry, or it expired", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn delete_entry(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Path((namespace, key)): Path<(String, String)>,
+) -> Result<StatusCode, AppError> {
+    if !state.db.delete_kv(&namespace, &key).await? {
+        return Err(not_found(&namespace, &key));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,44 +1,605 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{DefaultBodyLimit, FromRef, OriginalUri, Path, Query, Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware::{self, Next},
     response::{IntoResponse, Response},
-    routing::{get, post},
+    routing::{get, patch, post, put},
     Json, Router,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::{hash_map::Entry, HashMap};
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "scripting")]
+use tinybase_core::scripts::HookEvent;
 use tinybase_core::{
-    models::{Collection as CollectionModel, Record},
+    checksums::{self, ChecksumMode},
+    clock::{self, Clock, SystemClock},
+    embedded::{self, prepare_record, Write},
+    events::{Event, EventAction, EventBus},
+    lineage::{Lineage, LineageSource},
+    models::Collection as CollectionModel,
+    patch::merge_patch,
+    read_replicas::ReadReplicas,
+    relations::{
+        delete_collection_dependents, delete_dependents, delete_record_cascade, relation_fields,
+    },
+    replica::ReplicaSync,
+    rules::check_rule,
     schema::CollectionSchema,
-    validation::{validate_record, ValidationError},
-    Db,
+    search::{Fts5Index, SearchIndex},
+    snapshot::SnapshotVerifier,
+    timeouts::{self, QueryTimeout},
+    validation::{self, check_schema, ValidationError},
+    views::AttachedDatabases,
+    Collection, CollectionDeletion, Db, ListOptions, QueryError, Record, SortField,
+    VersionMismatch,
 };
-use utoipa::{OpenApi, ToSchema};
+use tinybase_storage::{
+    store::{DistributedStore, MemoryStore, PrefixedStore},
+    Storage, StorageError,
+};
+use tower_http::{
+    cors::{AllowOrigin, Any, CorsLayer},
+    trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer},
+};
+use tracing::Level;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+#[cfg(feature = "swagger-ui")]
 use utoipa_swagger_ui::SwaggerUi;
 
-pub type AppState = Arc<dyn Db>;
+mod access;
+pub mod access_log;
+mod admin;
+#[cfg(feature = "admin-ui")]
+mod admin_ui;
+mod aggregate;
+pub mod api_clients;
+mod arrays;
+mod auth;
+mod batch;
+pub mod coalesce;
+pub mod config;
+mod counters;
+mod csv;
+mod cursor;
+mod docs;
+mod dump;
+pub mod envelope;
+mod etag;
+mod files;
+pub mod fixtures;
+mod get_or_create;
+mod groups;
+mod health;
+mod idempotency;
+pub mod jobs;
+#[cfg(feature = "jwt")]
+pub mod jwt;
+mod kv;
+mod limits;
+mod locks;
+pub mod logging;
+#[cfg(feature = "manifest")]
+pub mod manifest;
+#[cfg(feature = "meilisearch")]
+pub mod meilisearch;
+pub mod meta;
+pub mod namespaces;
+#[cfg(feature = "notifications")]
+pub mod notify;
+mod ordering;
+pub mod plugin;
+mod prefer;
+mod projection;
+pub mod query_cache;
+mod rate_limit;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod search;
+pub mod server;
+pub mod shape;
+pub mod shutdown;
+#[cfg(feature = "schema-sync")]
+pub mod sync;
+#[cfg(feature = "tantivy")]
+pub mod tantivy_search;
+mod tokens;
+mod tree;
+pub mod version;
+mod versions;
+mod views;
+pub mod warmup;
+#[cfg(feature = "webhooks")]
+pub mod webhooks;
 
-#[derive(Serialize, ToSchema)]
+use access::{check_record, record_value, Access, Operation};
+use auth::RequireAdmin;
+use coalesce::Coalescer;
+use config::Config;
+use envelope::{list_response, ListFormat, Pagination};
+use files::{
+    check_file_fields, delete_files, delete_replaced_files, discard_files, store_files,
+    RecordPayload,
+};
+use groups::check_group_name;
+use jobs::{Jobs, DEFAULT_JOB_WORKERS};
+use limits::{DEFAULT_MAX_JSON_BYTES, DEFAULT_MAX_JSON_DEPTH};
+use projection::Projection;
+use query_cache::{Lookup, QueryCache};
+use shape::{shape_records, ResponseHooks};
+use shutdown::Shutdown;
+
+pub type DbState = Arc<dyn Db>;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub db: DbState,
+    pub storage: Arc<dyn Storage>,
+    pub events: EventBus,
+    pub views: Arc<AttachedDatabases>,
+    /// State shared between instances: rate limits, sessions, idempotency keys.
+    pub store: Arc<dyn DistributedStore>,
+    pub verifier: Option<SnapshotVerifier>,
+    /// Syncs the database when it is an embedded replica of a remote one.
+    pub replica: Option<ReplicaSync>,
+    /// Spreads reads over read replicas, if any are configured.
+    pub read_replicas: Option<ReadReplicas>,
+    /// Verifies the tokens of a trusted identity provider, if one is
+    /// configured.
+    #[cfg(feature = "jwt")]
+    pub jwt: Option<Arc<jwt::JwtVerifier>>,
+    /// Where admins take and restore snapshots from, if anywhere.
+    pub backup_dir: Option<PathBuf>,
+    /// Runs long operations in the background.
+    pub jobs: Arc<Jobs>,
+    pub response_hooks: Arc<ResponseHooks>,
+    pub coalescer: Arc<Coalescer>,
+    /// Cached list and aggregate responses of the collections enabling it.
+    pub query_cache: Arc<QueryCache>,
+    /// Where records of searchable collections are indexed for full-text
+    /// search; FTS5 in the database unless replaced.
+    pub search: Arc<dyn SearchIndex>,
+    pub list_format: ListFormat,
+    /// Largest request body accepted, uploads included; see [`limits`].
+    pub max_body_bytes: usize,
+    /// Largest JSON request body accepted, if under `max_body_bytes`.
+    pub max_json_bytes: usize,
+    /// Deepest nesting of arrays and objects accepted in a JSON body.
+    pub max_json_depth: usize,
+    /// Origins browsers may call the API from; `*` allows any.
+    pub cors_origins: Arc<Vec<String>>,
+    /// Names of the plugins registered on the [`Tinybase`](plugin::Tinybase)
+    /// builder.
+    pub plugins: Arc<Vec<&'static str>>,
+    /// Reports error internals to clients; see [`AppState::with_developer_mode`].
+    pub developer_mode: bool,
+    /// The time requests and background tasks run on.
+    pub clock: Arc<dyn Clock>,
+    /// How long the queries of a request may run, if limited; see
+    /// [`timeouts`](tinybase_core::timeouts).
+    pub query_timeout: Option<Duration>,
+    /// Requests each client may make per minute, if limited; see
+    /// [`AppState::with_rate_limit`].
+    pub rate_limit: Option<u32>,
+    /// What reading a record that doesn't match its checksum does; see
+    /// [`checksums`](tinybase_core::checksums).
+    pub checksum_mode: ChecksumMode,
+    /// Stops the background dispatchers when the server shuts down and
+    /// tracks the deliveries they run; see [`shutdown`].
+    pub shutdown: Shutdown,
+}
+
+impl AppState {
+    pub fn new(db: DbState, storage: Arc<dyn Storage>) -> Self {
+        let events = EventBus::new();
+        Self {
+            search: Arc::new(Fts5Index::new(db.clone())),
+            db,
+            storage,
+            query_cache: Arc::new(QueryCache::new(&events)),
+            events,
+            views: Arc::new(AttachedDatabases::new()),
+            store: Arc::new(MemoryStore::new()),
+            verifier: None,
+            replica: None,
+            read_replicas: None,
+            #[cfg(feature = "jwt")]
+            jwt: None,
+            backup_dir: None,
+            jobs: Arc::new(Jobs::new(DEFAULT_JOB_WORKERS)),
+            response_hooks: Arc::new(ResponseHooks::new()),
+            coalescer: Arc::new(Coalescer::new("/collections").except("/export")),
+            list_format: ListFormat::Bare,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            max_json_bytes: DEFAULT_MAX_JSON_BYTES,
+            max_json_depth: DEFAULT_MAX_JSON_DEPTH,
+            cors_origins: Arc::new(Vec::new()),
+            plugins: Arc::new(Vec::new()),
+            developer_mode: false,
+            clock: Arc::new(SystemClock),
+            query_timeout: Some(DEFAULT_QUERY_TIMEOUT),
+            rate_limit: None,
+            checksum_mode: ChecksumMode::default(),
+            shutdown: Shutdown::new(),
+        }
+    }
+
+    /// The state of namespace `name`, an isolated app on its own database
+    /// and storage, with its own events, caches and jobs; see
+    /// [`namespaces`]. It shares the settings of this state, its clock,
+    /// response hooks and trusted identity provider, and keeps its API keys
+    /// and other shared state under its own prefix of this state's store,
+    /// and stops with it.
+    pub fn for_namespace(&self, name: &str, db: DbState, storage: Arc<dyn Storage>) -> Self {
+        let mut state = AppState::new(db, storage)
+            .with_store(Arc::new(PrefixedStore::new(
+                self.store.clone(),
+                format!("ns:{}:", name),
+            )))
+            .with_clock(self.clock.clone())
+            .with_developer_mode(self.developer_mode)
+            .with_query_timeout(self.query_timeout)
+            .with_list_format(self.list_format)
+            .with_rate_limit(self.rate_limit)
+            .with_checksum_mode(self.checksum_mode)
+            .with_shutdown(self.shutdown.clone());
+        state.response_hooks = self.response_hooks.clone();
+        state.max_body_bytes = self.max_body_bytes;
+        state.max_json_bytes = self.max_json_bytes;
+        state.max_json_depth = self.max_json_depth;
+        state.cors_origins = self.cors_origins.clone();
+        #[cfg(feature = "jwt")]
+        {
+            state.jwt = self.jwt.clone();
+        }
+        state
+    }
+
+    /// Lets admins snapshot the database into `dir` and restore it from the
+    /// snapshots there.
+    pub fn with_backups(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.backup_dir = Some(dir.into());
+        self
+    }
+
+    /// Applies the settings of `config` that shape the API: the body size
+    /// limits, the CORS origins, the number of job workers, the query time
+    /// limit and the checksum mode.
+    pub fn with_config(mut self, config: &Config) -> Self {
+        self.max_body_bytes = config.max_body_bytes;
+        self.max_json_bytes = config.max_json_bytes;
+        self.max_json_depth = config.max_json_depth;
+        self.cors_origins = Arc::new(config.cors_origins.clone());
+        self.jobs = Arc::new(Jobs::new(config.job_workers));
+        self.with_developer_mode(config.developer_mode)
+            .with_rate_limit(
+                (config.rate_limit_per_minute > 0).then_some(config.rate_limit_per_minute),
+            )
+            .with_query_timeout(
+                (config.query_timeout_ms > 0)
+                    .then(|| Duration::from_millis(config.query_timeout_ms)),
+            )
+            .with_checksum_mode(config.checksums)
+            .with_list_format(config.list_format)
+    }
+
+    /// Runs requests, plugin hooks and scheduled tasks on `clock`: record
+    /// timestamps, event times and the expiry of stored entries such as API
+    /// keys follow it. Tests use a [`ManualClock`](clock::ManualClock).
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Includes error chains, failed SQL statements and other internals in the
+    /// `details` of error responses. Off in production, where server errors
+    /// carry no details.
+    pub fn with_developer_mode(mut self, enabled: bool) -> Self {
+        self.developer_mode = enabled;
+        self
+    }
+
+    /// Answers `429 Too Many Requests` to clients making more than
+    /// `per_minute` requests a minute, counted in the shared store; `None`
+    /// lets them all through. See [`rate_limit`].
+    pub fn with_rate_limit(mut self, per_minute: Option<u32>) -> Self {
+        self.rate_limit = per_minute;
+        self
+    }
+
+    /// Interrupts record lists, counts and lookups running longer than
+    /// `limit`, answering `504 Gateway Timeout`; `None` lets them run.
+    pub fn with_query_timeout(mut self, limit: Option<Duration>) -> Self {
+        self.query_timeout = limit;
+        self
+    }
+
+    /// Sets what reading a record whose data doesn't match its checksum
+    /// does, in requests and jobs: nothing, log it, or fail.
+    pub fn with_checksum_mode(mut self, mode: ChecksumMode) -> Self {
+        self.checksum_mode = mode;
+        self
+    }
+
+    /// Stops the background dispatchers of this state with `shutdown`,
+    /// letting them finish their queued events first.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Chooses between bare arrays and paged envelopes for list responses.
+    pub fn with_list_format(mut self, format: ListFormat) -> Self {
+        self.list_format = format;
+        self
+    }
+
+    /// Shapes the records returned by the API, see [`ResponseHooks`].
+    pub fn with_response_hooks(mut self, hooks: ResponseHooks) -> Self {
+        self.response_hooks = Arc::new(hooks);
+        self
+    }
+
+    /// Exposes the reports of a backup snapshot verifier to admins.
+    pub fn with_snapshot_verifier(mut self, verifier: SnapshotVerifier) -> Self {
+        self.verifier = Some(verifier);
+        self
+    }
+
+    /// Reports the sync status of an embedded replica in the health check.
+    pub fn with_replica(mut self, replica: ReplicaSync) -> Self {
+        self.replica = Some(replica);
+        self
+    }
+
+    /// Serves collection and record reads from the fastest healthy of
+    /// `replicas`, and exposes their latency and the routing override to
+    /// admins.
+    pub fn with_read_replicas(mut self, replicas: ReadReplicas) -> Self {
+        self.db = Arc::new(replicas.clone());
+        self.read_replicas = Some(replicas);
+        self
+    }
+
+    /// Accepts the tokens `verifier` trusts, besides the admin API keys
+    /// Tinybase issues.
+    #[cfg(feature = "jwt")]
+    pub fn with_jwt(mut self, verifier: jwt::JwtVerifier) -> Self {
+        self.jwt = Some(Arc::new(verifier));
+        self
+    }
+
+    /// Replaces the in-memory shared-state store, e.g. with a Redis one.
+    pub fn with_store(mut self, store: Arc<dyn DistributedStore>) -> Self {
+        self.store = store;
+        self
+    }
+
+    /// Indexes searchable collections in `index` instead of the built-in FTS5
+    /// table; see [`search`].
+    pub fn with_search(mut self, index: Arc<dyn SearchIndex>) -> Self {
+        self.search = index;
+        self
+    }
+
+    /// Serves the tables of `views` as read-only view collections.
+    pub fn with_views(mut self, views: AttachedDatabases) -> Self {
+        self.views = Arc::new(views);
+        self
+    }
+}
+
+impl FromRef<AppState> for DbState {
+    fn from_ref(state: &AppState) -> Self {
+        state.db.clone()
+    }
+}
+
+impl FromRef<AppState> for EventBus {
+    fn from_ref(state: &AppState) -> Self {
+        state.events.clone()
+    }
+}
+
+#[derive(Serialize, Clone, ToSchema)]
 pub struct CollectionResponse {
     id: i64,
     name: String,
     schema: Option<CollectionSchema>,
+    created: String,
+    updated: String,
+    /// The group the collection is filed under.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+}
+
+impl From<Collection> for CollectionResponse {
+    fn from(c: Collection) -> Self {
+        CollectionResponse {
+            id: c.id,
+            name: c.name,
+            schema: c.schema,
+            created: c.created,
+            updated: c.updated,
+            group: c.group,
+        }
+    }
 }
 
 #[derive(Deserialize, ToSchema)]
 pub struct UpdateCollection {
     name: Option<String>,
     schema: Option<CollectionSchema>,
+    /// Moves the collection to this group; an empty string ungroups it.
+    group: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct CollectionFilter {
+    /// Only collections in this group; empty for ungrouped collections.
+    group: Option<String>,
 }
 
 #[derive(Serialize, ToSchema)]
 pub struct RecordResponse {
     id: i64,
     data: serde_json::Value,
+    created: String,
+    updated: String,
+    /// How and by whom the record was created: its `source` (`api`,
+    /// `import` or `hook`), the `job` that created it and the `actor`;
+    /// absent for records from before lineage was kept.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    lineage: Option<Lineage>,
+    /// Related records inlined for the relation fields named in `?expand=`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expand: Option<serde_json::Map<String, serde_json::Value>>,
+    /// The advisory lock held on the record, in reads.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lock: Option<locks::RecordLock>,
 }
 
-#[derive(Serialize, ToSchema)]
-struct ProblemDetail {
+impl From<Record> for RecordResponse {
+    fn from(r: Record) -> Self {
+        RecordResponse {
+            id: r.id,
+            data: r.data,
+            created: r.created,
+            updated: r.updated,
+            lineage: r.lineage,
+            expand: None,
+            lock: None,
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ExpandQuery {
+    /// Comma-separated relation fields to inline, e.g. `author,category`.
+    expand: Option<String>,
+    /// Comma-separated fields of the data to return, e.g. `title,author.name`;
+    /// all of them when left out. `relation.field` picks a field of the
+    /// related record, which is expanded.
+    fields: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ListQuery {
+    /// `id`, `created`, `updated` or `position`, the manual order of ordered
+    /// collections; prefix with `-` for descending order.
+    sort: Option<String>,
+    /// Only records created after this RFC 3339 timestamp or date.
+    created_after: Option<String>,
+    /// Only records created before this RFC 3339 timestamp or date.
+    created_before: Option<String>,
+    /// Only records updated after this RFC 3339 timestamp or date.
+    updated_after: Option<String>,
+    /// Only records updated before this RFC 3339 timestamp or date.
+    updated_before: Option<String>,
+    /// Resume after the page a `next_cursor` was returned with; only for
+    /// lists sorted by id or created, and not together with `page`.
+    cursor: Option<String>,
+    /// Only records created this way: `api`, `import` or `hook`.
+    source: Option<String>,
+    /// Only records created by this job, e.g. an import.
+    source_job: Option<i64>,
+    /// Only records created by this actor, e.g. `admin:1` or `token:2`.
+    source_actor: Option<String>,
+}
+
+impl ListQuery {
+    fn options(self) -> Result<ListOptions, AppError> {
+        let (descending, field) = match self.sort.as_deref() {
+            Some(sort) => match sort.strip_prefix('-') {
+                Some(field) => (true, field),
+                None => (false, sort),
+            },
+            None => (false, "id"),
+        };
+        let sort = match field {
+            "id" => SortField::Id,
+            "created" => SortField::Created,
+            "updated" => SortField::Updated,
+            "position" => SortField::Position,
+            other => {
+                return Err(AppError::BadRequest(format!(
+                    "Cannot sort by '{}'; expected id, created, updated or position",
+                    other
+                )))
+            }
+        };
+        let cursor = self
+            .cursor
+            .as_deref()
+            .map(|token| cursor::decode(token, sort, descending))
+            .transpose()?;
+        let source = self
+            .source
+            .as_deref()
+            .map(|name| {
+                LineageSource::parse(name).ok_or_else(|| {
+                    AppError::BadRequest(format!(
+                        "Unknown source '{}'; expected api, import or hook",
+                        name
+                    ))
+                })
+            })
+            .transpose()?;
+        Ok(ListOptions {
+            sort,
+            descending,
+            created_after: self.created_after,
+            created_before: self.created_before,
+            updated_after: self.updated_after,
+            updated_before: self.updated_before,
+            limit: None,
+            offset: 0,
+            after_id: None,
+            cursor,
+            fields: None,
+            source,
+            source_job: self.source_job,
+            source_actor: self.source_actor,
+        })
+    }
+}
+
+impl ExpandQuery {
+    fn fields(&self) -> Vec<&str> {
+        self.expand
+            .as_deref()
+            .map(|e| {
+                e.split(',')
+                    .map(str::trim)
+                    .filter(|f| !f.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The `?fields=` selection, `None` when every field is wanted.
+    fn projection(&self) -> Option<Projection> {
+        self.fields.as_deref().map(Projection::parse)
+    }
+}
+
+/// The relation fields to expand: those of `?expand=` and those dotted
+/// fields of `?fields=` pick from.
+fn expanded_fields<'a>(query: &'a ExpandQuery, projection: Option<&'a Projection>) -> Vec<&'a str> {
+    let mut fields = query.fields();
+    for relation in projection.into_iter().flat_map(Projection::relations) {
+        if !fields.contains(&relation) {
+            fields.push(relation);
+        }
+    }
+    fields
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub(crate) struct ProblemDetail {
     error: String,
     message: String,
     details: Option<serde_json::Value>,
@@ -47,21 +608,104 @@ struct ProblemDetail {
 
 pub enum AppError {
     LibsqlError(libsql::Error),
+    /// A failed statement whose SQL is known.
+    Query(QueryError),
     JsonError(String),
     UnknownError(String),
+    StorageError(StorageError),
     NotFound(String),
+    BadRequest(String),
+    Conflict(String),
+    Unauthorized(String),
     Validation(Vec<ValidationError>),
+    /// An error raised by one operation of a batch, by position.
+    BatchItem(usize, Box<AppError>),
+    /// A query interrupted for running out of time.
+    Timeout(String),
+    /// A write refused by a script hook.
+    Rejected(String),
+    /// A conditional write whose `If-Match` no longer matches.
+    PreconditionFailed(String),
+    /// A write lacking the `If-Match` its collection requires.
+    PreconditionRequired(String),
+    /// A request over the rate limit of its client.
+    RateLimited(String),
+    /// A request its credentials don't allow.
+    Forbidden(String),
+    /// A request body over its size limit.
+    PayloadTooLarge(String),
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let (status, problem) = match self {
+tokio::task_local! {
+    /// Whether the request being handled may see error internals.
+    static DEVELOPER_MODE: bool;
+}
+
+/// Runs the rest of the stack in developer mode, see
+/// [`AppState::with_developer_mode`].
+async fn developer_mode(request: Request, next: Next) -> Response {
+    DEVELOPER_MODE.scope(true, next.run(request)).await
+}
+
+/// Runs the rest of the stack on the clock of the state.
+async fn on_clock(State(clock): State<Arc<dyn Clock>>, request: Request, next: Next) -> Response {
+    clock::with_clock(clock, next.run(request)).await
+}
+
+/// Limits the queries of the rest of the stack to `limit`.
+async fn limit_queries(State(limit): State<Duration>, request: Request, next: Next) -> Response {
+    timeouts::with_query_timeout(limit, next.run(request)).await
+}
+
+/// Checks the checksums of the records the rest of the stack reads in
+/// `mode`.
+async fn check_checksums(
+    State(mode): State<ChecksumMode>,
+    request: Request,
+    next: Next,
+) -> Response {
+    checksums::with_checksum_mode(mode, next.run(request)).await
+}
+
+/// The messages of `e` and of the errors that caused it, outermost first.
+fn error_chain(e: &(dyn std::error::Error + 'static)) -> Vec<String> {
+    std::iter::successors(Some(e), |e| e.source())
+        .map(ToString::to_string)
+        .collect()
+}
+
+impl AppError {
+    pub(crate) fn into_problem(self) -> (StatusCode, ProblemDetail) {
+        // Server errors only describe their cause to clients in developer
+        // mode, but always in the logs.
+        let internals = DEVELOPER_MODE.try_with(|enabled| *enabled).unwrap_or(false);
+        let internal = |details: serde_json::Value| {
+            tracing::error!(%details, "internal error");
+            internals.then_some(details)
+        };
+        match self {
             AppError::LibsqlError(e) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 ProblemDetail {
                     error: "database_error".to_string(),
                     message: "A database error occurred.".to_string(),
-                    details: Some(serde_json::json!({ "db_error": e.to_string() })),
+                    details: internal(serde_json::json!({
+                        "db_error": e.to_string(),
+                        "chain": error_chain(&e),
+                    })),
+                    status: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                },
+            ),
+            AppError::Query(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ProblemDetail {
+                    error: "database_error".to_string(),
+                    message: "A database error occurred.".to_string(),
+                    details: internal(serde_json::json!({
+                        "db_error": e.error.to_string(),
+                        "sql": e.sql,
+                        "chain": error_chain(&e.error),
+                    })),
                     status: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
                 },
             ),
@@ -70,7 +714,7 @@ impl IntoResponse for AppError {
                 ProblemDetail {
                     error: "serialization_error".to_string(),
                     message: "Failed to serialize data.".to_string(),
-                    details: Some(serde_json::json!({ "json_error": e })),
+                    details: internal(serde_json::json!({ "json_error": e })),
                     status: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
                 },
             ),
@@ -79,7 +723,19 @@ impl IntoResponse for AppError {
                 ProblemDetail {
                     error: "unknown_error".to_string(),
                     message: "An unknown error occurred.".to_string(),
-                    details: Some(serde_json::json!({ "error": e })),
+                    details: internal(serde_json::json!({ "error": e })),
+                    status: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                },
+            ),
+            AppError::StorageError(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ProblemDetail {
+                    error: "storage_error".to_string(),
+                    message: "A file storage error occurred.".to_string(),
+                    details: internal(serde_json::json!({
+                        "storage_error": e.to_string(),
+                        "chain": error_chain(&e),
+                    })),
                     status: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
                 },
             ),
@@ -92,6 +748,33 @@ impl IntoResponse for AppError {
                     status: StatusCode::NOT_FOUND.as_u16(),
                 },
             ),
+            AppError::BadRequest(e) => (
+                StatusCode::BAD_REQUEST,
+                ProblemDetail {
+                    error: "bad_request".to_string(),
+                    message: e,
+                    details: None,
+                    status: StatusCode::BAD_REQUEST.as_u16(),
+                },
+            ),
+            AppError::Conflict(e) => (
+                StatusCode::CONFLICT,
+                ProblemDetail {
+                    error: "conflict".to_string(),
+                    message: e,
+                    details: None,
+                    status: StatusCode::CONFLICT.as_u16(),
+                },
+            ),
+            AppError::Unauthorized(e) => (
+                StatusCode::UNAUTHORIZED,
+                ProblemDetail {
+                    error: "unauthorized".to_string(),
+                    message: e,
+                    details: None,
+                    status: StatusCode::UNAUTHORIZED.as_u16(),
+                },
+            ),
             AppError::Validation(e) => (
                 StatusCode::UNPROCESSABLE_ENTITY,
                 ProblemDetail {
@@ -101,8 +784,90 @@ impl IntoResponse for AppError {
                     status: StatusCode::UNPROCESSABLE_ENTITY.as_u16(),
                 },
             ),
-        };
+            AppError::Timeout(e) => (
+                StatusCode::GATEWAY_TIMEOUT,
+                ProblemDetail {
+                    error: "query_timeout".to_string(),
+                    message: e,
+                    details: None,
+                    status: StatusCode::GATEWAY_TIMEOUT.as_u16(),
+                },
+            ),
+            AppError::Rejected(e) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ProblemDetail {
+                    error: "rejected".to_string(),
+                    message: e,
+                    details: None,
+                    status: StatusCode::UNPROCESSABLE_ENTITY.as_u16(),
+                },
+            ),
+            AppError::PreconditionFailed(e) => (
+                StatusCode::PRECONDITION_FAILED,
+                ProblemDetail {
+                    error: "precondition_failed".to_string(),
+                    message: e,
+                    details: None,
+                    status: StatusCode::PRECONDITION_FAILED.as_u16(),
+                },
+            ),
+            AppError::PreconditionRequired(e) => (
+                StatusCode::PRECONDITION_REQUIRED,
+                ProblemDetail {
+                    error: "precondition_required".to_string(),
+                    message: e,
+                    details: None,
+                    status: StatusCode::PRECONDITION_REQUIRED.as_u16(),
+                },
+            ),
+            AppError::Forbidden(e) => (
+                StatusCode::FORBIDDEN,
+                ProblemDetail {
+                    error: "forbidden".to_string(),
+                    message: e,
+                    details: None,
+                    status: StatusCode::FORBIDDEN.as_u16(),
+                },
+            ),
+            AppError::RateLimited(e) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                ProblemDetail {
+                    error: "rate_limited".to_string(),
+                    message: e,
+                    details: None,
+                    status: StatusCode::TOO_MANY_REQUESTS.as_u16(),
+                },
+            ),
+            AppError::PayloadTooLarge(e) => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                ProblemDetail {
+                    error: "payload_too_large".to_string(),
+                    message: e,
+                    details: None,
+                    status: StatusCode::PAYLOAD_TOO_LARGE.as_u16(),
+                },
+            ),
+            AppError::BatchItem(index, e) => {
+                let (status, problem) = e.into_problem();
+                (
+                    status,
+                    ProblemDetail {
+                        message: format!("Batch operation {} failed: {}", index, problem.message),
+                        details: Some(serde_json::json!({
+                            "index": index,
+                            "details": problem.details,
+                        })),
+                        ..problem
+                    },
+                )
+            }
+        }
+    }
+}
 
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let (status, problem) = self.into_problem();
         (status, Json(problem)).into_response()
     }
 }
@@ -113,9 +878,151 @@ impl From<libsql::Error> for AppError {
     }
 }
 
+impl From<StorageError> for AppError {
+    fn from(e: StorageError) -> Self {
+        AppError::StorageError(e)
+    }
+}
+
+impl From<embedded::Error> for AppError {
+    fn from(e: embedded::Error) -> Self {
+        match e {
+            embedded::Error::NotFound(what) => AppError::NotFound(format!("{} not found", what)),
+            embedded::Error::InvalidSchema(e) => AppError::BadRequest(e),
+            embedded::Error::Validation(errors) => AppError::Validation(errors),
+            embedded::Error::Database(e) => db_error(e),
+        }
+    }
+}
+
+/// Rejects schemas carrying rule expressions that do not parse.
+pub(crate) fn check_schema_rules(schema: Option<&CollectionSchema>) -> Result<(), AppError> {
+    if let Some(schema) = schema {
+        check_schema(schema).map_err(AppError::BadRequest)?;
+    }
+    for (operation, rule) in schema.iter().flat_map(|s| s.rules.iter()) {
+        check_rule(rule)
+            .map_err(|e| AppError::BadRequest(format!("Invalid {} rule: {}", operation, e)))?;
+    }
+    Ok(())
+}
+
+/// Resolves the `{id}` path segment, a collection id or name, to an id.
+///
+/// Numeric segments are taken as ids without a lookup, leaving each endpoint
+/// to report a missing collection its own way.
+pub(crate) async fn resolve_collection(db: &dyn Db, key: &str) -> Result<i64, AppError> {
+    if let Ok(id) = key.parse::<i64>() {
+        return Ok(id);
+    }
+    db.get_collection_by_name(key)
+        .await
+        .map_err(db_error)?
+        .map(|c| c.id)
+        .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", key)))
+}
+
+/// Checks that `name` can address a collection: it must follow the naming
+/// rules, see [`validation::check_collection_name`], and must not be taken
+/// by a collection other than `current`.
+pub(crate) async fn check_collection_name(
+    db: &dyn Db,
+    name: &str,
+    current: Option<i64>,
+) -> Result<(), AppError> {
+    validation::check_collection_name(name).map_err(|e| AppError::Validation(vec![e]))?;
+    let existing = db.get_collection_by_name(name).await.map_err(db_error)?;
+    if existing.is_some_and(|c| Some(c.id) != current) {
+        return Err(AppError::Conflict(format!(
+            "A collection named '{}' already exists",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Loads the related records for the requested relation fields of `data`.
+/// A related record the view rule of its collection hides is left as its
+/// bare id. `accesses` keeps the [`Access`] to each related collection
+/// across the records of a list.
+async fn expand_relations(
+    state: &AppState,
+    schema: Option<&CollectionSchema>,
+    data: &serde_json::Value,
+    fields: &[&str],
+    accesses: &mut HashMap<i64, Access>,
+) -> Result<Option<serde_json::Map<String, serde_json::Value>>, AppError> {
+    if fields.is_empty() {
+        return Ok(None);
+    }
+    let mut expanded = serde_json::Map::new();
+    for field in fields {
+        let target = schema
+            .and_then(|s| relation_fields(s).find(|(name, _, _)| name == field))
+            .map(|(_, target, _)| target)
+            .ok_or_else(|| {
+                AppError::BadRequest(format!("Field '{}' is not a relation field", field))
+            })?;
+        let related = match data.get(*field).and_then(serde_json::Value::as_i64) {
+            Some(id) => state.db.get_record(target, id).await.map_err(db_error)?,
+            None => None,
+        };
+        if let Some(r) = &related {
+            let access = match accesses.entry(target) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(Access::to_id(state, target).await?),
+            };
+            let hidden =
+                !access.allows(Operation::View, &record_value(r), &serde_json::Value::Null);
+            if hidden {
+                expanded.insert(field.to_string(), r.id.into());
+                continue;
+            }
+        }
+        let value = match related {
+            Some(r) => {
+                let mut response = RecordResponse::from(r);
+                shape_records(state, target, [&mut response]).await?;
+                serde_json::to_value(response).map_err(|e| AppError::JsonError(e.to_string()))?
+            }
+            None => serde_json::Value::Null,
+        };
+        expanded.insert(field.to_string(), value);
+    }
+    Ok(Some(expanded))
+}
+
+/// Maps the boxed errors returned by the `Db` trait onto `AppError`.
+pub(crate) fn db_error(e: Box<dyn std::error::Error + Send + Sync>) -> AppError {
+    if let Some(e) = e.downcast_ref::<serde_json::Error>() {
+        return AppError::JsonError(e.to_string());
+    }
+    if let Some(e) = e.downcast_ref::<QueryTimeout>() {
+        return AppError::Timeout(e.to_string());
+    }
+    let e = match e.downcast::<QueryError>() {
+        Ok(e) => return AppError::Query(*e),
+        Err(e) => e,
+    };
+    if let Some(e) = e.downcast_ref::<VersionMismatch>() {
+        return etag::changed(e.record_id, e.current);
+    }
+    let e = match e.downcast::<ValidationError>() {
+        Ok(e) => return AppError::Validation(vec![*e]),
+        Err(e) => e,
+    };
+    match e.downcast::<libsql::Error>() {
+        Ok(e) => AppError::LibsqlError(*e),
+        Err(e) => AppError::UnknownError(error_chain(e.as_ref()).join(": ")),
+    }
+}
+
 #[derive(OpenApi)]
 #[openapi(
     paths(
+        health::health,
+        meta::get_meta,
+        version::get_version,
         create_collection,
         list_collections,
         get_collection,
@@ -123,12 +1030,139 @@ impl From<libsql::Error> for AppError {
         delete_collection,
         create_record,
         list_records,
+        aggregate::aggregate_records,
         get_record,
         update_record,
+        replace_record,
         delete_record,
+        batch::batch_records,
+        csv::import_records,
+        search::search_records,
+        search::reindex_search,
+        dump::export_collection,
+        dump::import_collection,
+        jobs::get_job,
+        jobs::cancel_job,
+        jobs::job_events,
+        kv::get_entry,
+        kv::put_entry,
+        kv::delete_entry,
+        versions::list_versions,
+        versions::restore_version,
+        ordering::move_record,
+        counters::increment_field,
+        arrays::update_array,
+        get_or_create::get_or_create_record,
+        tree::list_roots,
+        tree::get_subtree,
+        tree::list_children,
+        tree::move_node,
+        locks::lock_record,
+        groups::list_groups,
+        groups::rename_group,
+        groups::delete_group,
+        locks::unlock_record,
+        docs::collection_openapi,
+        api_clients::export_client,
+        files::serve_file,
+        auth::authenticate,
+        auth::create_admin,
+        auth::export_admins,
+        auth::import_admins,
+        tokens::list_tokens,
+        tokens::create_token,
+        tokens::delete_token,
+        auth::introspect,
+        admin::test_rule,
+        admin::render_template,
+        admin::export_policy,
+        admin::import_policy,
+        admin::activity_feed,
+        admin::metrics,
+        admin::read_routing,
+        admin::pin_read_replica,
+        admin::last_snapshot_verification,
+        admin::run_snapshot_verification,
+        admin::create_backup,
+        admin::list_backups,
+        admin::restore_backup,
+        admin::upgrade_storage,
+        admin::storage,
+        admin::scrub_checksums,
+        admin::checksum_mismatches,
+        views::list_views,
+        views::list_view_records,
+        views::get_view_record,
     ),
     components(
-        schemas(CollectionResponse, UpdateCollection, RecordResponse, ProblemDetail)
+        schemas(
+            health::Health,
+            health::ReplicationHealth,
+            health::DependencyHealth,
+            meta::Meta,
+            meta::Limits,
+            meta::ContentTypes,
+            version::VersionInfo,
+            CollectionResponse,
+            UpdateCollection,
+            RecordResponse,
+            aggregate::AggregateGroupResponse,
+            versions::RecordVersionResponse,
+            ordering::MoveRecordRequest,
+            counters::IncrementRequest,
+            arrays::ArrayUpdateRequest,
+            tree::TreeNodeResponse,
+            tree::MoveRequest,
+            locks::RecordLock,
+            kv::KvEntryResponse,
+            kv::KvSetRequest,
+            groups::CollectionGroup,
+            groups::RenameGroup,
+            locks::LockRequest,
+            csv::CsvImportReport,
+            csv::CsvRowError,
+            search::SearchResult,
+            dump::CollectionDump,
+            dump::RecordDump,
+            dump::ImportReport,
+            dump::InvalidRecord,
+            jobs::JobResponse,
+            ProblemDetail,
+            envelope::RecordPage,
+            envelope::CollectionPage,
+            envelope::PageLinks,
+            auth::AdminCredentials,
+            auth::AdminResponse,
+            auth::UsersDocument,
+            auth::ExportedUser,
+            auth::UsersImportResponse,
+            tokens::TokenRequest,
+            tokens::TokenResponse,
+            auth::AdminAuthResponse,
+            auth::TokenIntrospection,
+            admin::RuleTestRequest,
+            admin::RuleTestResponse,
+            admin::TemplateRenderRequest,
+            admin::TemplateRenderResponse,
+            admin::CollectionPolicy,
+            admin::PolicyDocument,
+            admin::SnapshotVerificationResponse,
+            admin::BackupResponse,
+            admin::Metrics,
+            admin::ReadReplicaResponse,
+            admin::ReadRoutingResponse,
+            admin::PinReplicaRequest,
+            admin::CollectionStorageResponse,
+            admin::StorageResponse,
+            admin::ChecksumMismatchResponse,
+            coalesce::CoalescingStats,
+            query_cache::QueryCacheStats,
+            batch::BatchRequest,
+            batch::BatchOperationRequest,
+            batch::BatchItemResponse,
+            views::ViewCollectionResponse,
+            views::ViewRecordResponse
+        )
     ),
     tags(
         (name = "Tinybase", description = "Tinybase API")
@@ -136,31 +1170,334 @@ impl From<libsql::Error> for AppError {
 )]
 struct ApiDoc;
 
-pub fn app_router(db: AppState) -> Router {
-    Router::new()
-        .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()))
-        .nest(
-            "/api/v1",
-            Router::new()
-                .route("/collections", post(create_collection).get(list_collections))
-                .route(
-                    "/collections/:id",
-                    get(get_collection)
-                        .patch(update_collection)
-                        .delete(delete_collection),
-                )
-                .route(
-                    "/collections/:id/records",
-                    post(create_record).get(list_records),
-                )
-                .route(
-                    "/collections/:id/records/:record_id",
-                    get(get_record)
-                        .patch(update_record)
-                        .delete(delete_record),
-                ),
+/// The OpenAPI document, including the endpoints of enabled features.
+pub(crate) fn api_doc() -> utoipa::openapi::OpenApi {
+    let doc = ApiDoc::openapi();
+    #[cfg(feature = "schema-sync")]
+    let doc = {
+        let mut doc = doc;
+        doc.merge(sync::SyncApiDoc::openapi());
+        doc
+    };
+    #[cfg(feature = "notifications")]
+    let doc = {
+        let mut doc = doc;
+        doc.merge(notify::NotifyApiDoc::openapi());
+        doc
+    };
+    #[cfg(feature = "webhooks")]
+    let doc = {
+        let mut doc = doc;
+        doc.merge(webhooks::WebhooksApiDoc::openapi());
+        doc
+    };
+    #[cfg(feature = "scripting")]
+    let doc = {
+        let mut doc = doc;
+        doc.merge(scripting::ScriptingApiDoc::openapi());
+        doc
+    };
+    #[cfg(feature = "admin-ui")]
+    let doc = {
+        let mut doc = doc;
+        doc.merge(admin_ui::AdminUiApiDoc::openapi());
+        doc
+    };
+    doc
+}
+
+/// Largest request body accepted by default, uploads included.
+pub(crate) const DEFAULT_MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// How long the queries of a request may run unless configured otherwise.
+pub(crate) const DEFAULT_QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn app_router(state: AppState) -> Router {
+    router_with(state, Router::new())
+}
+
+/// Builds the application router, with `extra` routes (e.g. from plugins)
+/// served under `/api/v1` alongside the built-in ones.
+pub(crate) fn router_with(state: AppState, extra: Router<AppState>) -> Router {
+    #[cfg(feature = "swagger-ui")]
+    let docs =
+        Router::new().merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", api_doc()));
+    #[cfg(not(feature = "swagger-ui"))]
+    let docs = {
+        let doc = api_doc();
+        Router::new().route(
+            "/api-docs/openapi.json",
+            get(move || async move { Json(doc) }),
+        )
+    };
+    let api = Router::new()
+        .route("/health", get(health::health))
+        .route("/meta", get(meta::get_meta))
+        .route("/version", get(version::get_version))
+        .route(
+            "/collections",
+            post(create_collection).get(list_collections),
+        )
+        .route("/collections/import", post(dump::import_collection))
+        .route("/jobs/:id", get(jobs::get_job))
+        .route("/jobs/:id/cancel", post(jobs::cancel_job))
+        .route("/jobs/:id/events", get(jobs::job_events))
+        .route(
+            "/kv/:namespace/:key",
+            get(kv::get_entry)
+                .put(kv::put_entry)
+                .delete(kv::delete_entry),
+        )
+        .route("/collection-groups", get(groups::list_groups))
+        .route(
+            "/collection-groups/:name",
+            patch(groups::rename_group).delete(groups::delete_group),
+        )
+        .route(
+            "/collections/:id",
+            get(get_collection)
+                .patch(update_collection)
+                .delete(delete_collection),
+        )
+        .route("/collections/:id/export", get(dump::export_collection))
+        .route(
+            "/collections/:id/records",
+            post(create_record).get(list_records),
+        )
+        .route(
+            "/collections/:id/records/aggregate",
+            get(aggregate::aggregate_records),
+        )
+        .route("/collections/:id/records/batch", post(batch::batch_records))
+        .route(
+            "/collections/:id/records/by/:field/:value",
+            put(get_or_create::get_or_create_record),
+        )
+        .route("/collections/:id/records/import", post(csv::import_records))
+        .route(
+            "/collections/:id/records/search",
+            get(search::search_records),
         )
-        .with_state(db)
+        .route(
+            "/collections/:id/records/:record_id",
+            get(get_record)
+                .patch(update_record)
+                .put(replace_record)
+                .delete(delete_record),
+        )
+        .route(
+            "/collections/:id/records/:record_id/move",
+            post(ordering::move_record),
+        )
+        .route(
+            "/collections/:id/records/:record_id/increment",
+            post(counters::increment_field),
+        )
+        .route(
+            "/collections/:id/records/:record_id/array",
+            post(arrays::update_array),
+        )
+        .route(
+            "/collections/:id/records/:record_id/lock",
+            post(locks::lock_record).delete(locks::unlock_record),
+        )
+        .route(
+            "/collections/:id/records/:record_id/versions",
+            get(versions::list_versions),
+        )
+        .route(
+            "/collections/:id/records/:record_id/versions/:version/restore",
+            post(versions::restore_version),
+        )
+        .route("/collections/:id/tree", get(tree::list_roots))
+        .route("/collections/:id/tree/:record_id", get(tree::get_subtree))
+        .route(
+            "/collections/:id/tree/:record_id/children",
+            get(tree::list_children),
+        )
+        .route(
+            "/collections/:id/tree/:record_id/move",
+            post(tree::move_node),
+        )
+        .route(
+            "/files/:collection/:record/:field/:filename",
+            get(files::serve_file),
+        )
+        .route("/admin/auth", post(auth::authenticate))
+        .route("/admin/admins", post(auth::create_admin))
+        .route("/admin/admins/export", get(auth::export_admins))
+        .route("/admin/admins/import", post(auth::import_admins))
+        .route(
+            "/admin/tokens",
+            get(tokens::list_tokens).post(tokens::create_token),
+        )
+        .route(
+            "/admin/tokens/:id",
+            axum::routing::delete(tokens::delete_token),
+        )
+        .route("/auth/introspect", get(auth::introspect))
+        .route("/admin/rules/test", post(admin::test_rule))
+        .route("/admin/templates/render", post(admin::render_template))
+        .route(
+            "/admin/policy",
+            get(admin::export_policy).put(admin::import_policy),
+        )
+        .route("/admin/activity", get(admin::activity_feed))
+        .route("/admin/metrics", get(admin::metrics))
+        .route("/admin/replicas", get(admin::read_routing))
+        .route("/admin/replicas/pin", put(admin::pin_read_replica))
+        .route(
+            "/admin/backups/verification",
+            get(admin::last_snapshot_verification).post(admin::run_snapshot_verification),
+        )
+        .route("/admin/backup", post(admin::create_backup))
+        .route("/admin/backups", get(admin::list_backups))
+        .route("/admin/backups/:name/restore", post(admin::restore_backup))
+        .route("/admin/search/reindex", post(search::reindex_search))
+        .route("/admin/storage", get(admin::storage))
+        .route("/admin/storage/upgrade", post(admin::upgrade_storage))
+        .route("/admin/checksums", get(admin::checksum_mismatches))
+        .route("/admin/checksums/scrub", post(admin::scrub_checksums))
+        .route("/views", get(views::list_views))
+        .route("/views/:name/records", get(views::list_view_records))
+        .route(
+            "/views/:name/records/:record_id",
+            get(views::get_view_record),
+        );
+    #[cfg(feature = "schema-sync")]
+    let api = api.route("/admin/schema/sync", post(sync::sync_schema));
+    #[cfg(feature = "notifications")]
+    let api = api
+        .route(
+            "/admin/notifications",
+            get(notify::list_channels).post(notify::create_channel),
+        )
+        .route(
+            "/admin/notifications/:id",
+            axum::routing::delete(notify::delete_channel),
+        )
+        .route("/admin/notifications/:id/test", post(notify::test_channel));
+    #[cfg(feature = "webhooks")]
+    let api = api
+        .route(
+            "/admin/webhooks",
+            get(webhooks::list_webhooks).post(webhooks::create_webhook),
+        )
+        .route(
+            "/admin/webhooks/:id",
+            axum::routing::delete(webhooks::delete_webhook),
+        )
+        .route(
+            "/admin/webhooks/:id/deliveries",
+            get(webhooks::list_deliveries),
+        )
+        .route(
+            "/admin/webhooks/:id/deliveries/:delivery_id",
+            get(webhooks::get_delivery),
+        )
+        .route(
+            "/admin/webhooks/:id/deliveries/:delivery_id/replay",
+            post(webhooks::replay_delivery),
+        );
+    #[cfg(feature = "scripting")]
+    let api = api
+        .route(
+            "/admin/hooks",
+            get(scripting::list_hooks).post(scripting::create_hook),
+        )
+        .route(
+            "/admin/hooks/:id",
+            axum::routing::delete(scripting::delete_hook),
+        );
+    #[cfg(feature = "admin-ui")]
+    let api = api
+        .route("/admin/field-types", get(admin_ui::field_types))
+        .route("/admin/collections", get(admin_ui::list_collections));
+    let api = coalesce::layer(api.merge(extra), state.coalescer.clone());
+    let api = prefer::layer(api)
+        .layer(middleware::from_fn(auth::remember_viewer))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::track_lineage,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            tokens::enforce,
+        ));
+    let developer = state.developer_mode;
+    let clock = state.clock.clone();
+    let query_timeout = state.query_timeout;
+    let rate_limit = state.rate_limit.map(|limit| (state.store.clone(), limit));
+    let checksum_mode = state.checksum_mode;
+    let body_limits = limits::BodyLimits {
+        body_bytes: state.max_body_bytes,
+        json_bytes: state.max_json_bytes.min(state.max_body_bytes),
+        json_depth: state.max_json_depth,
+    };
+    let cors = cors_layer(&state.cors_origins);
+    let app = Router::new()
+        .merge(docs)
+        .route(
+            "/api-docs/collections/:id/openapi.json",
+            get(docs::collection_openapi),
+        )
+        .route("/api-docs/clients/:format", get(api_clients::export_client));
+    #[cfg(feature = "admin-ui")]
+    let app = app.merge(admin_ui::routes());
+    let app = app
+        .nest("/api/v1", api)
+        .with_state(state)
+        .layer(DefaultBodyLimit::disable())
+        .layer(middleware::from_fn_with_state(body_limits, limits::limit));
+    let app = match query_timeout {
+        Some(limit) => app.layer(middleware::from_fn_with_state(limit, limit_queries)),
+        None => app,
+    };
+    let app = match rate_limit {
+        Some(limit) => app.layer(middleware::from_fn_with_state(limit, rate_limit::limit)),
+        None => app,
+    };
+    let app = app.layer(middleware::from_fn_with_state(
+        checksum_mode,
+        check_checksums,
+    ));
+    let app = app
+        .layer(middleware::from_fn_with_state(clock, on_clock))
+        // A span per request, which the query spans nest in, and a log line
+        // per response; 5xx responses are logged as errors.
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(DefaultMakeSpan::new().level(Level::INFO))
+                .on_response(DefaultOnResponse::new().level(Level::INFO)),
+        );
+    let app = match cors {
+        Some(cors) => app.layer(cors),
+        None => app,
+    };
+    if developer {
+        app.layer(middleware::from_fn(developer_mode))
+    } else {
+        app
+    }
+}
+
+/// Lets browsers on `origins` call the API; `None` when there are none.
+fn cors_layer(origins: &[String]) -> Option<CorsLayer> {
+    if origins.is_empty() {
+        return None;
+    }
+    let layer = CorsLayer::new()
+        .allow_methods(Any)
+        .allow_headers(Any)
+        .expose_headers(Any);
+    if origins.iter().any(|o| o == "*") {
+        return Some(layer.allow_origin(Any));
+    }
+    let origins: Vec<HeaderValue> = origins
+        .iter()
+        .filter_map(|o| HeaderValue::from_str(o).ok())
+        .collect();
+    Some(layer.allow_origin(AllowOrigin::list(origins)))
 }
 
 #[utoipa::path(
@@ -169,69 +1506,95 @@ pub fn app_router(db: AppState) -> Router {
     request_body = CollectionModel,
     responses(
         (status = 201, description = "Create a new collection", body = CollectionResponse),
+        (status = 400, description = "Invalid collection rules, or a JSON Schema the schema can't be taken from", body = ProblemDetail),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
         (status = 500, description = "Internal server error", body = ProblemDetail)
     )
 )]
 async fn create_collection(
-    State(db): State<AppState>,
+    _: RequireAdmin,
+    State(db): State<DbState>,
+    State(events): State<EventBus>,
     Json(payload): Json<CollectionModel>,
 ) -> Result<(StatusCode, Json<CollectionResponse>), AppError> {
-    let id = db
-        .create_collection(&payload.name, &payload.schema)
+    let schema = match (payload.schema, payload.json_schema) {
+        (Some(_), Some(_)) => {
+            return Err(AppError::BadRequest(
+                "Send either schema or json_schema, not both".to_string(),
+            ))
+        }
+        (None, Some(document)) => Some(
+            CollectionSchema::from_json_schema(&document)
+                .map_err(|e| AppError::BadRequest(format!("Invalid JSON Schema: {}", e)))?,
+        ),
+        (schema, None) => schema,
+    };
+    check_schema_rules(schema.as_ref())?;
+    check_collection_name(db.as_ref(), &payload.name, None).await?;
+    let group = payload.group.as_deref().map(check_group_name).transpose()?;
+    let mut collection = db
+        .create_collection(&payload.name, &schema)
         .await
-        .map_err(|e| {
-            if let Some(e) = e.downcast_ref::<serde_json::Error>() {
-                AppError::JsonError(e.to_string())
-            } else if let Ok(e) = e.downcast::<libsql::Error>() {
-                AppError::LibsqlError(*e)
-            } else {
-                AppError::UnknownError("An unknown error occurred".to_string())
-            }
-        })?;
-    Ok((
-        StatusCode::CREATED,
-        Json(CollectionResponse {
-            id,
-            name: payload.name,
-            schema: payload.schema,
-        }),
-    ))
+        .map_err(db_error)?;
+    if let Some(group) = group {
+        collection = db
+            .set_collection_group(collection.id, Some(group))
+            .await
+            .map_err(db_error)?;
+    }
+    events.publish(Event::new(
+        EventAction::CollectionCreated,
+        collection.id,
+        None,
+    ));
+    Ok((StatusCode::CREATED, Json(collection.into())))
 }
 
 #[utoipa::path(
     get,
     path = "/api/v1/collections",
+    params(
+        Pagination,
+        CollectionFilter
+    ),
     responses(
-        (status = 200, description = "List all collections", body = Vec<CollectionResponse>),
+        (status = 200, description = "List all collections; a `CollectionPage` when the envelope format is enabled", body = Vec<CollectionResponse>),
+        (status = 400, description = "Invalid page", body = ProblemDetail),
         (status = 500, description = "Internal server error", body = ProblemDetail)
     )
 )]
 async fn list_collections(
-    State(db): State<AppState>,
-) -> Result<Json<Vec<CollectionResponse>>, AppError> {
-    let collections = db.list_collections().await.map_err(|e| {
-        if let Ok(e) = e.downcast::<libsql::Error>() {
-            AppError::LibsqlError(*e)
-        } else {
-            AppError::UnknownError("An unknown error occurred".to_string())
-        }
-    })?;
-    let collections = collections
+    State(state): State<AppState>,
+    OriginalUri(uri): OriginalUri,
+    Query(pagination): Query<Pagination>,
+    Query(filter): Query<CollectionFilter>,
+) -> Result<Response, AppError> {
+    let page = pagination.page(state.list_format)?;
+    let mut collections = state.db.list_collections().await.map_err(db_error)?;
+    if let Some(group) = &filter.group {
+        let group = Some(group.trim()).filter(|g| !g.is_empty());
+        collections.retain(|c| c.group.as_deref() == group);
+    }
+    let total = collections.len() as u64;
+    let collections: Vec<CollectionResponse> = page
+        .slice(collections)
         .into_iter()
-        .map(|c| CollectionResponse {
-            id: c.id,
-            name: c.name,
-            schema: c.schema,
-        })
+        .map(Into::into)
         .collect();
-    Ok(Json(collections))
+    Ok(list_response(
+        state.list_format,
+        collections,
+        page,
+        total,
+        &uri,
+    ))
 }
 
 #[utoipa::path(
     get,
     path = "/api/v1/collections/{id}",
     params(
-        ("id" = i64, Path, description = "Collection id")
+        ("id" = String, Path, description = "Collection id or name")
     ),
     responses(
         (status = 200, description = "Get a single collection", body = CollectionResponse),
@@ -240,22 +1603,13 @@ async fn list_collections(
     )
 )]
 async fn get_collection(
-    State(db): State<AppState>,
-    Path(id): Path<i64>,
+    State(db): State<DbState>,
+    Path(key): Path<String>,
 ) -> Result<Json<CollectionResponse>, AppError> {
-    let collection = db.get_collection(id).await.map_err(|e| {
-        if let Ok(e) = e.downcast::<libsql::Error>() {
-            AppError::LibsqlError(*e)
-        } else {
-            AppError::UnknownError("An unknown error occurred".to_string())
-        }
-    })?;
+    let id = resolve_collection(db.as_ref(), &key).await?;
+    let collection = db.get_collection(id).await.map_err(db_error)?;
     match collection {
-        Some(c) => Ok(Json(CollectionResponse {
-            id: c.id,
-            name: c.name,
-            schema: c.schema,
-        })),
+        Some(c) => Ok(Json(c.into())),
         None => Err(AppError::NotFound(format!("Collection {} not found", id))),
     }
 }
@@ -264,50 +1618,101 @@ async fn get_collection(
     patch,
     path = "/api/v1/collections/{id}",
     params(
-        ("id" = i64, Path, description = "Collection id")
+        ("id" = String, Path, description = "Collection id or name")
     ),
     request_body = UpdateCollection,
     responses(
         (status = 200, description = "Update a collection", body = CollectionResponse),
+        (status = 400, description = "Invalid collection rules", body = ProblemDetail),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
         (status = 404, description = "Collection not found", body = ProblemDetail),
         (status = 500, description = "Internal server error", body = ProblemDetail)
     )
 )]
 async fn update_collection(
-    State(db): State<AppState>,
-    Path(id): Path<i64>,
+    _: RequireAdmin,
+    State(db): State<DbState>,
+    State(events): State<EventBus>,
+    Path(key): Path<String>,
     Json(payload): Json<UpdateCollection>,
 ) -> Result<Json<CollectionResponse>, AppError> {
-    let collection = db
+    let id = resolve_collection(db.as_ref(), &key).await?;
+    check_schema_rules(payload.schema.as_ref())?;
+    if let Some(name) = &payload.name {
+        check_collection_name(db.as_ref(), name, Some(id)).await?;
+    }
+    let group = match payload.group.as_deref().map(str::trim) {
+        Some("") => Some(None),
+        Some(group) => Some(Some(check_group_name(group)?)),
+        None => None,
+    };
+    let mut collection = db
         .update_collection(id, payload.name, payload.schema)
         .await
-        .map_err(|e| {
-            if let Ok(e) = e.downcast::<libsql::Error>() {
-                AppError::LibsqlError(*e)
-            } else {
-                AppError::UnknownError("An unknown error occurred".to_string())
-            }
-        })?;
-    Ok(Json(CollectionResponse {
-        id: collection.id,
-        name: collection.name,
-        schema: collection.schema,
-    }))
+        .map_err(db_error)?
+        .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", id)))?;
+    if let Some(group) = group {
+        collection = db.set_collection_group(id, group).await.map_err(db_error)?;
+    }
+    events.publish(Event::new(EventAction::CollectionUpdated, id, None));
+    Ok(Json(collection.into()))
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct DeleteCollectionQuery {
+    /// Also delete the collection's records. Required when it has any.
+    #[serde(default)]
+    force: bool,
 }
 
 #[utoipa::path(
     delete,
     path = "/api/v1/collections/{id}",
     params(
-        ("id" = i64, Path, description = "Collection id")
+        ("id" = String, Path, description = "Collection id or name"),
+        DeleteCollectionQuery
     ),
     responses(
-        (status = 204, description = "Delete a collection"),
+        (status = 204, description = "Delete a collection and its records"),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Collection not found", body = ProblemDetail),
+        (status = 409, description = "Collection still has records and `force` was not set", body = ProblemDetail),
         (status = 500, description = "Internal server error", body = ProblemDetail)
     )
 )]
-async fn delete_collection(State(db): State<AppState>, Path(id): Path<i64>) -> Result<StatusCode, AppError> {
-    db.delete_collection(id).await?;
+async fn delete_collection(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Query(query): Query<DeleteCollectionQuery>,
+) -> Result<StatusCode, AppError> {
+    let db = &state.db;
+    let id = resolve_collection(db.as_ref(), &key).await?;
+    match db.delete_collection(id, query.force).await? {
+        CollectionDeletion::Missing => {
+            return Err(AppError::NotFound(format!("Collection {} not found", id)));
+        }
+        CollectionDeletion::NotEmpty(count) => {
+            return Err(AppError::Conflict(format!(
+                "Collection {} still has {} records; pass ?force=true to delete them as well",
+                id, count
+            )));
+        }
+        CollectionDeletion::Deleted(_) => {}
+    }
+
+    // The records go as one, announced by the collection's deletion; those
+    // of other collections cascading from them go one by one.
+    if let Err(e) = state.storage.delete_prefix(&id.to_string()).await {
+        tracing::warn!(collection_id = id, error = %e, "failed to delete collection files");
+    }
+    let dependents = delete_collection_dependents(db.clone(), id)
+        .await
+        .map_err(db_error)?;
+    after_delete(&state, dependents).await?;
+    state
+        .events
+        .publish(Event::new(EventAction::CollectionDeleted, id, None));
     Ok(StatusCode::NO_CONTENT)
 }
 
@@ -315,118 +1720,262 @@ async fn delete_collection(State(db): State<AppState>, Path(id): Path<i64>) -> R
     post,
     path = "/api/v1/collections/{id}/records",
     params(
-        ("id" = i64, Path, description = "Collection id")
+        ("id" = String, Path, description = "Collection id or name"),
+        ("Idempotency-Key" = Option<String>, Header, description = "Makes retries of this request return the record it created instead of creating another")
     ),
-    request_body = Record,
+    request_body(content = Record, description = "Record data as JSON, or multipart/form-data with a `data` part and file parts"),
     responses(
-        (status = 201, description = "Create a new record", body = RecordResponse),
+        (status = 201, description = "Create a new record, or return the record created earlier with the same `Idempotency-Key`", body = RecordResponse),
+        (status = 400, description = "Invalid Idempotency-Key, or one used on another collection", body = ProblemDetail),
+        (status = 403, description = "A collection rule denies the request", body = ProblemDetail),
         (status = 404, description = "Collection not found", body = ProblemDetail),
+        (status = 409, description = "A request with the same Idempotency-Key is still running", body = ProblemDetail),
         (status = 422, description = "Validation error", body = ProblemDetail),
         (status = 500, description = "Internal server error", body = ProblemDetail)
     )
 )]
 async fn create_record(
-    State(db): State<AppState>,
-    Path(id): Path<i64>,
-    Json(payload): Json<Record>,
-) -> Result<(StatusCode, Json<RecordResponse>), AppError> {
-    let collection = db.get_collection(id).await.map_err(|e| {
-        if let Ok(e) = e.downcast::<libsql::Error>() {
-            AppError::LibsqlError(*e)
-        } else {
-            AppError::UnknownError("An unknown error occurred".to_string())
-        }
-    })?;
-    if let Some(c) = collection {
-        if let Some(schema) = &c.schema {
-            validate_record(schema, &payload.data).map_err(AppError::Validation)?;
-        }
-    } else {
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+    mut payload: RecordPayload,
+) -> Result<Response, AppError> {
+    let db = &state.db;
+    let id = resolve_collection(db.as_ref(), &key).await?;
+    let collection = db.get_collection(id).await.map_err(db_error)?;
+    let Some(c) = collection else {
         return Err(AppError::NotFound(format!("Collection {} not found", id)));
+    };
+    Access::to(&state, &c).await?.check(
+        Operation::Create,
+        &serde_json::json!({}),
+        &payload.data,
+    )?;
+    let idempotency_key = idempotency::key(&headers)?;
+    if let Some(key) = &idempotency_key {
+        if let Some(record) = idempotency::claim(&state, key, id).await? {
+            let mut response = RecordResponse::from(record);
+            shape_records(&state, id, [&mut response]).await?;
+            let replayed = [(idempotency::IDEMPOTENT_REPLAYED, "true")];
+            return Ok((StatusCode::CREATED, replayed, Json(response)).into_response());
+        }
     }
 
-    let record_id = db.create_record(id, &payload.data).await.map_err(|e| {
-        if let Some(e) = e.downcast_ref::<serde_json::Error>() {
-            AppError::JsonError(e.to_string())
-        } else if let Ok(e) = e.downcast::<libsql::Error>() {
-            AppError::LibsqlError(*e)
-        } else {
-            AppError::UnknownError("An unknown error occurred".to_string())
+    let created = async {
+        check_file_fields(c.schema.as_ref(), &payload.files)?;
+        #[cfg(feature = "scripting")]
+        scripting::run_hooks(
+            &state,
+            &c,
+            HookEvent::BeforeCreate,
+            None,
+            &mut payload.data,
+            None,
+        )
+        .await?;
+        if let Some(schema) = &c.schema {
+            prepare_record(db.as_ref(), schema, &mut payload.data, Write::Create).await?;
         }
-    })?;
-    Ok((
-        StatusCode::CREATED,
-        Json(RecordResponse {
-            id: record_id,
-            data: payload.data,
-        }),
-    ))
+        let record = db
+            .create_record(id, &payload.data)
+            .await
+            .map_err(db_error)?;
+        // The record's id names its blobs, so they can only be stored after
+        // it; a record whose files failed to store is removed again.
+        if let Err(e) = store_files(state.storage.as_ref(), id, record.id, &payload.files).await {
+            db.delete_record(id, record.id).await?;
+            return Err(e);
+        }
+        Ok(record)
+    }
+    .await;
+    if let Some(key) = &idempotency_key {
+        idempotency::finish(&state, key, created.as_ref().ok()).await?;
+    }
+    let record = created?;
+    state
+        .events
+        .publish(Event::new(EventAction::RecordCreated, id, Some(record.id)));
+    #[cfg(feature = "scripting")]
+    scripting::after_create(&state, &c, &record).await;
+    let mut response = RecordResponse::from(record);
+    shape_records(&state, id, [&mut response]).await?;
+    Ok((StatusCode::CREATED, Json(response)).into_response())
 }
 
 #[utoipa::path(
     get,
     path = "/api/v1/collections/{id}/records",
     params(
-        ("id" = i64, Path, description = "Collection id")
+        ("id" = String, Path, description = "Collection id or name"),
+        ExpandQuery,
+        ListQuery,
+        Pagination
     ),
     responses(
-        (status = 200, description = "List all records in a collection", body = Vec<RecordResponse>),
-        (status = 500, description = "Internal server error", body = ProblemDetail)
+        (status = 200, description = "List the records of a collection; a `RecordPage` when the envelope format is enabled", body = Vec<RecordResponse>,
+            content_type = ["application/json", "text/csv"]),
+        (status = 400, description = "Invalid expand field, sort order or page", body = ProblemDetail),
+        (status = 500, description = "Internal server error", body = ProblemDetail),
+        (status = 504, description = "The query did not finish within the query time limit", body = ProblemDetail)
     )
 )]
 async fn list_records(
-    State(db): State<AppState>,
-    Path(id): Path<i64>,
-) -> Result<Json<Vec<RecordResponse>>, AppError> {
-    let records = db.list_records(id).await.map_err(|e| {
-        if let Ok(e) = e.downcast::<libsql::Error>() {
-            AppError::LibsqlError(*e)
-        } else {
-            AppError::UnknownError("An unknown error occurred".to_string())
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Query(query): Query<ExpandQuery>,
+    Query(list): Query<ListQuery>,
+    Query(pagination): Query<Pagination>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let page = pagination.page(state.list_format)?;
+    let projection = query.projection();
+    let fields = expanded_fields(&query, projection.as_ref());
+    let mut options = ListOptions {
+        limit: page.limit(),
+        offset: page.offset(),
+        fields: projection.as_ref().map(|p| p.stored_fields(&fields)),
+        ..list.options()?
+    };
+    if options.cursor.is_some() && options.offset > 0 {
+        return Err(AppError::BadRequest(
+            "cursor can't be combined with page".to_string(),
+        ));
+    }
+    // Loading one record more than the page tells whether another follows.
+    let cursor_limit = options.limit.filter(|_| cursor::supports(options.sort));
+    options.limit = cursor_limit.map(|limit| limit + 1).or(options.limit);
+    let db = &state.db;
+    let id = resolve_collection(db.as_ref(), &key).await?;
+    let collection = db.get_collection(id).await.map_err(db_error)?;
+    let access = match &collection {
+        Some(c) => Some(Access::to(&state, c).await?),
+        None => None,
+    };
+    let schema = collection.and_then(|c| c.schema);
+    let looked_up = access.as_ref().map_or(Some(&[][..]), Access::looked_up);
+    let miss = match state
+        .query_cache
+        .lookup(id, schema.as_ref(), looked_up, &uri, &headers)
+    {
+        Lookup::Hit(response) => return Ok(response),
+        Lookup::Miss(miss) => Some(miss),
+        Lookup::Uncached => None,
+    };
+    let total = match state.list_format {
+        ListFormat::Envelope => {
+            let all = ListOptions {
+                cursor: None,
+                ..options.clone()
+            };
+            db.count_matching_records(id, &all)
+                .await
+                .map_err(db_error)? as u64
         }
-    })?;
-    let records = records
-        .into_iter()
-        .map(|r| RecordResponse {
-            id: r.id,
-            data: r.data,
-        })
-        .collect();
-    Ok(Json(records))
+        ListFormat::Bare => 0,
+    };
+    let mut records = db.list_records(id, &options).await.map_err(db_error)?;
+    let mut next_cursor = None;
+    if let Some(limit) = cursor_limit {
+        if records.len() as u64 > limit {
+            records.truncate(limit as usize);
+            next_cursor = records
+                .last()
+                .map(|r| cursor::encode(options.sort, options.descending, r));
+        }
+    }
+    if let Some(access) = &access {
+        records.retain(|r| access.sees(r));
+    }
+    let mut responses = Vec::with_capacity(records.len());
+    let mut accesses = HashMap::new();
+    for r in records {
+        let expand =
+            expand_relations(&state, schema.as_ref(), &r.data, &fields, &mut accesses).await?;
+        responses.push(RecordResponse { expand, ..r.into() });
+    }
+    shape_records(&state, id, &mut responses).await?;
+    if let Some(projection) = &projection {
+        responses.iter_mut().for_each(|r| projection.apply(r));
+    }
+    locks::attach_locks(&state, id, &mut responses).await?;
+    let response = if csv::wants_csv(&headers) {
+        csv::records_response(schema.as_ref(), &responses)
+    } else {
+        let page = page.with_cursors(options.cursor.is_some(), next_cursor);
+        list_response(state.list_format, responses, page, total, &uri)
+    };
+    Ok(match miss {
+        Some(miss) => state.query_cache.store(miss, response).await,
+        None => response,
+    })
 }
 
 #[utoipa::path(
     get,
     path = "/api/v1/collections/{id}/records/{record_id}",
     params(
-        ("id" = i64, Path, description = "Collection id"),
-        ("record_id" = i64, Path, description = "Record id")
+        ("id" = String, Path, description = "Collection id or name"),
+        ("record_id" = i64, Path, description = "Record id"),
+        ExpandQuery
     ),
     responses(
-        (status = 200, description = "Get a single record", body = RecordResponse),
+        (status = 200, description = "Get a single record, with its version as ETag", body = RecordResponse),
+        (status = 400, description = "Invalid expand field", body = ProblemDetail),
+        (status = 403, description = "A collection rule denies the request", body = ProblemDetail),
         (status = 404, description = "Record not found", body = ProblemDetail),
         (status = 500, description = "Internal server error", body = ProblemDetail)
     )
 )]
 async fn get_record(
-    State(db): State<AppState>,
-    Path((collection_id, record_id)): Path<(i64, i64)>,
-) -> Result<Json<RecordResponse>, AppError> {
+    State(state): State<AppState>,
+    Path((key, record_id)): Path<(String, i64)>,
+    Query(query): Query<ExpandQuery>,
+) -> Result<([(HeaderName, String); 1], Json<RecordResponse>), AppError> {
+    let db = &state.db;
+    let collection_id = resolve_collection(db.as_ref(), &key).await?;
     let record = db
         .get_record(collection_id, record_id)
         .await
-        .map_err(|e| {
-            if let Ok(e) = e.downcast::<libsql::Error>() {
-                AppError::LibsqlError(*e)
+        .map_err(db_error)?;
+    match record {
+        Some(r) => {
+            Access::to_id(&state, collection_id).await?.check(
+                Operation::View,
+                &record_value(&r),
+                &serde_json::Value::Null,
+            )?;
+            let projection = query.projection();
+            let fields = expanded_fields(&query, projection.as_ref());
+            let schema = if fields.is_empty() {
+                None
             } else {
-                AppError::UnknownError("An unknown error occurred".to_string())
+                db.get_collection(collection_id)
+                    .await
+                    .map_err(db_error)?
+                    .and_then(|c| c.schema)
+            };
+            let expand = expand_relations(
+                &state,
+                schema.as_ref(),
+                &r.data,
+                &fields,
+                &mut HashMap::new(),
+            )
+            .await?;
+            let etag = etag::header(&r);
+            let mut response = RecordResponse {
+                expand,
+                lock: locks::current_lock(&state, collection_id, record_id).await?,
+                ..r.into()
+            };
+            shape_records(&state, collection_id, [&mut response]).await?;
+            if let Some(projection) = &projection {
+                projection.apply(&mut response);
             }
-        })?;
-    match record {
-        Some(r) => Ok(Json(RecordResponse {
-            id: r.id,
-            data: r.data,
-        })),
+            Ok((etag, Json(response)))
+        }
         None => Err(AppError::NotFound(format!(
             "Record {} not found in collection {}",
             record_id, collection_id
@@ -438,72 +1987,274 @@ async fn get_record(
     patch,
     path = "/api/v1/collections/{id}/records/{record_id}",
     params(
-        ("id" = i64, Path, description = "Collection id"),
+        ("id" = String, Path, description = "Collection id or name"),
         ("record_id" = i64, Path, description = "Record id")
     ),
-    request_body = Record,
+    request_body(content = Record, description = "JSON Merge Patch (RFC 7396) of the record data: given fields are set, `null` removes a field. Also accepted as multipart/form-data with a `data` part and file parts"),
     responses(
-        (status = 200, description = "Update a record", body = RecordResponse),
+        (status = 200, description = "Update some fields of a record", body = RecordResponse),
+        (status = 403, description = "A collection rule denies the request", body = ProblemDetail),
         (status = 404, description = "Record not found", body = ProblemDetail),
+        (status = 412, description = "The record changed since the ETag sent as If-Match", body = ProblemDetail),
         (status = 422, description = "Validation error", body = ProblemDetail),
+        (status = 428, description = "The collection requires If-Match", body = ProblemDetail),
         (status = 500, description = "Internal server error", body = ProblemDetail)
     )
 )]
 async fn update_record(
-    State(db): State<AppState>,
-    Path((collection_id, record_id)): Path<(i64, i64)>,
-    Json(payload): Json<Record>,
-) -> Result<Json<RecordResponse>, AppError> {
-    let collection = db.get_collection(collection_id).await.map_err(|e| {
-        if let Ok(e) = e.downcast::<libsql::Error>() {
-            AppError::LibsqlError(*e)
-        } else {
-            AppError::UnknownError("An unknown error occurred".to_string())
+    State(state): State<AppState>,
+    Path((key, record_id)): Path<(String, i64)>,
+    headers: HeaderMap,
+    payload: RecordPayload,
+) -> Result<([(HeaderName, String); 1], Json<RecordResponse>), AppError> {
+    write_record(
+        state,
+        &key,
+        record_id,
+        payload,
+        WriteMode::Merge,
+        Some(&headers),
+    )
+    .await
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/collections/{id}/records/{record_id}",
+    params(
+        ("id" = String, Path, description = "Collection id or name"),
+        ("record_id" = i64, Path, description = "Record id")
+    ),
+    request_body(content = Record, description = "The complete record data as JSON, or multipart/form-data with a `data` part and file parts"),
+    responses(
+        (status = 200, description = "Replace the data of a record", body = RecordResponse),
+        (status = 403, description = "A collection rule denies the request", body = ProblemDetail),
+        (status = 404, description = "Record not found", body = ProblemDetail),
+        (status = 412, description = "The record changed since the ETag sent as If-Match", body = ProblemDetail),
+        (status = 422, description = "Validation error", body = ProblemDetail),
+        (status = 428, description = "The collection requires If-Match", body = ProblemDetail),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+async fn replace_record(
+    State(state): State<AppState>,
+    Path((key, record_id)): Path<(String, i64)>,
+    headers: HeaderMap,
+    payload: RecordPayload,
+) -> Result<([(HeaderName, String); 1], Json<RecordResponse>), AppError> {
+    write_record(
+        state,
+        &key,
+        record_id,
+        payload,
+        WriteMode::Replace,
+        Some(&headers),
+    )
+    .await
+}
+
+/// How a write body combines with the stored record data.
+#[derive(Clone, Copy)]
+enum WriteMode {
+    /// Merge-patch the stored data (`PATCH`).
+    Merge,
+    /// Replace it as a whole (`PUT`).
+    Replace,
+}
+
+/// Writes a record. `headers` are those of the client request, whose
+/// `If-Match` the write must meet; `None` for writes the server makes on its
+/// own.
+async fn write_record(
+    state: AppState,
+    key: &str,
+    record_id: i64,
+    mut payload: RecordPayload,
+    mode: WriteMode,
+    headers: Option<&HeaderMap>,
+) -> Result<([(HeaderName, String); 1], Json<RecordResponse>), AppError> {
+    let db = &state.db;
+    let collection_id = resolve_collection(db.as_ref(), key).await?;
+    let collection = db.get_collection(collection_id).await.map_err(db_error)?;
+    let (expected, schema, current) = if let Some(c) = collection {
+        check_file_fields(c.schema.as_ref(), &payload.files)?;
+        let current = db
+            .get_record(collection_id, record_id)
+            .await
+            .map_err(db_error)?
+            .ok_or_else(|| AppError::NotFound(format!("Record {} not found", record_id)))?;
+        Access::to(&state, &c).await?.check(
+            Operation::Update,
+            &record_value(&current),
+            &payload.data,
+        )?;
+        let expected = match headers {
+            Some(headers) => etag::expected_version(headers, c.schema.as_ref(), &current)?,
+            None => None,
+        };
+        if let WriteMode::Merge = mode {
+            let mut data = current.data.clone();
+            merge_patch(&mut data, &payload.data);
+            payload.data = data;
         }
-    })?;
-    if let Some(c) = collection {
+        #[cfg(feature = "scripting")]
+        scripting::run_hooks(
+            &state,
+            &c,
+            HookEvent::BeforeUpdate,
+            Some(record_id),
+            &mut payload.data,
+            Some(&current.data),
+        )
+        .await?;
         if let Some(schema) = &c.schema {
-            validate_record(schema, &payload.data).map_err(AppError::Validation)?;
+            prepare_record(db.as_ref(), schema, &mut payload.data, Write::Update).await?;
         }
+        (expected, c.schema, current)
     } else {
         return Err(AppError::NotFound(format!(
             "Collection {} not found",
             collection_id
         )));
-    }
+    };
 
-    let record = db
-        .update_record(collection_id, record_id, &payload.data)
-        .await
-        .map_err(|e| {
-            if let Ok(e) = e.downcast::<libsql::Error>() {
-                AppError::LibsqlError(*e)
-            } else {
-                AppError::UnknownError("An unknown error occurred".to_string())
-            }
-        })?;
-    Ok(Json(RecordResponse {
-        id: record.id,
-        data: record.data,
-    }))
+    // The blobs are stored first, so the record never names a file that
+    // isn't there; if the write fails, the new ones are removed again.
+    let storage = state.storage.as_ref();
+    store_files(storage, collection_id, record_id, &payload.files).await?;
+    let written = match expected {
+        Some(version) => {
+            db.update_record_at(collection_id, record_id, version, &payload.data)
+                .await
+        }
+        None => {
+            db.update_record(collection_id, record_id, &payload.data)
+                .await
+        }
+    }
+    .map_err(db_error)
+    .and_then(|r| r.ok_or_else(|| AppError::NotFound(format!("Record {} not found", record_id))));
+    let record = match written {
+        Ok(record) => record,
+        Err(e) => {
+            let kept = Some((schema.as_ref(), &current.data));
+            discard_files(storage, collection_id, record_id, &payload.files, kept).await;
+            return Err(e);
+        }
+    };
+    delete_replaced_files(
+        storage,
+        schema.as_ref(),
+        collection_id,
+        record_id,
+        &current.data,
+        &record.data,
+    )
+    .await?;
+    state.events.publish(Event::new(
+        EventAction::RecordUpdated,
+        collection_id,
+        Some(record_id),
+    ));
+    let etag = etag::header(&record);
+    let mut response = RecordResponse::from(record);
+    shape_records(&state, collection_id, [&mut response]).await?;
+    Ok((etag, Json(response)))
 }
 
 #[utoipa::path(
     delete,
     path = "/api/v1/collections/{id}/records/{record_id}",
     params(
-        ("id" = i64, Path, description = "Collection id"),
+        ("id" = String, Path, description = "Collection id or name"),
         ("record_id" = i64, Path, description = "Record id")
     ),
     responses(
-        (status = 204, description = "Delete a record"),
+        (status = 204, description = "Delete a record and any records cascading from it"),
+        (status = 403, description = "A collection rule denies the request", body = ProblemDetail),
+        (status = 404, description = "Record not found", body = ProblemDetail),
+        (status = 412, description = "The record changed since the ETag sent as If-Match", body = ProblemDetail),
+        (status = 428, description = "The collection requires If-Match", body = ProblemDetail),
         (status = 500, description = "Internal server error", body = ProblemDetail)
     )
 )]
 async fn delete_record(
-    State(db): State<AppState>,
-    Path((collection_id, record_id)): Path<(i64, i64)>,
+    State(state): State<AppState>,
+    Path((key, record_id)): Path<(String, i64)>,
+    headers: HeaderMap,
 ) -> Result<StatusCode, AppError> {
-    db.delete_record(collection_id, record_id).await?;
+    let collection_id = resolve_collection(state.db.as_ref(), &key).await?;
+    check_record(
+        &state,
+        collection_id,
+        record_id,
+        Operation::Delete,
+        &serde_json::Value::Null,
+    )
+    .await?;
+    let removed = match etag::checked_record(&state, collection_id, record_id, &headers).await? {
+        // Deletes the record at the version checked only, then what
+        // cascades from it.
+        Some(current) => {
+            let db = state.db.as_ref();
+            let deleted = db
+                .delete_record_at(collection_id, record_id, current.version)
+                .await
+                .map_err(db_error)?;
+            if deleted == 0 {
+                Vec::new()
+            } else {
+                let mut removed = delete_dependents(db, collection_id, record_id)
+                    .await
+                    .map_err(db_error)?;
+                removed.insert(0, (collection_id, current));
+                removed
+            }
+        }
+        None => delete_record_cascade(state.db.as_ref(), collection_id, record_id)
+            .await
+            .map_err(db_error)?,
+    };
+    if removed.is_empty() {
+        let db = &state.db;
+        if db
+            .get_collection(collection_id)
+            .await
+            .map_err(db_error)?
+            .is_none()
+        {
+            return Err(AppError::NotFound(format!(
+                "Collection {} not found",
+                collection_id
+            )));
+        }
+        return Err(AppError::NotFound(format!(
+            "Record {} not found in collection {}",
+            record_id, collection_id
+        )));
+    }
+    after_delete(&state, removed).await?;
     Ok(StatusCode::NO_CONTENT)
 }
+
+/// Removes the files of deleted records and announces their deletion.
+pub(crate) async fn after_delete(
+    state: &AppState,
+    removed: Vec<(i64, Record)>,
+) -> Result<(), AppError> {
+    if removed.is_empty() {
+        return Ok(());
+    }
+    let collections = state.db.list_collections().await.map_err(db_error)?;
+    for (cid, record) in removed {
+        let schema = collections
+            .iter()
+            .find(|c| c.id == cid)
+            .and_then(|c| c.schema.as_ref());
+        delete_files(state.storage.as_ref(), schema, cid, record.id, &record.data).await?;
+        state
+            .events
+            .publish(Event::new(EventAction::RecordDeleted, cid, Some(record.id)));
+    }
+    Ok(())
+}
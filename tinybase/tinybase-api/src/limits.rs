@@ -0,0 +1,153 @@
+//! Request body limits.
+//!
+//! No body may be over `max_body_bytes`. JSON bodies are smaller still:
+//! they are read whole, up to `max_json_bytes`, and refused if nested deeper
+//! than `max_json_depth` before any handler parses them. Other bodies,
+//! uploads and NDJSON or CSV imports, are counted as they stream in rather
+//! than buffered. A body announcing more than its limit in `Content-Length`
+//! is refused before a byte is read. Bodies over their limit get `413
+//! Payload Too Large` and bodies too deep `400 Bad Request`, as problem
+//! details.
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE},
+        HeaderMap, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http_body_util::{LengthLimitError, Limited};
+use std::error::Error;
+
+use crate::AppError;
+
+/// Largest JSON request body accepted by default.
+pub(crate) const DEFAULT_MAX_JSON_BYTES: usize = 1024 * 1024;
+
+/// Deepest nesting of arrays and objects accepted in a JSON body by
+/// default, the outermost counting one.
+pub(crate) const DEFAULT_MAX_JSON_DEPTH: usize = 32;
+
+/// The limits [`limit`] applies.
+#[derive(Clone, Copy)]
+pub(crate) struct BodyLimits {
+    pub body_bytes: usize,
+    pub json_bytes: usize,
+    pub json_depth: usize,
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|v| {
+            let v = v.trim();
+            v.eq_ignore_ascii_case("application/json") || v.ends_with("+json")
+        })
+}
+
+fn too_large(limit: usize) -> AppError {
+    AppError::PayloadTooLarge(format!(
+        "The request body is larger than the limit of {} bytes",
+        limit
+    ))
+}
+
+/// The error reading a body failed with: [`AppError::PayloadTooLarge`]
+/// once the body went over its limit, a bad request otherwise.
+pub(crate) fn read_error(e: axum::Error) -> AppError {
+    let mut source: Option<&(dyn Error + 'static)> = Some(&e);
+    while let Some(error) = source {
+        if error.is::<LengthLimitError>() {
+            return AppError::PayloadTooLarge(
+                "The request body is larger than the limit".to_string(),
+            );
+        }
+        source = error.source();
+    }
+    AppError::BadRequest(format!("Failed to read the request body: {}", e))
+}
+
+/// How deeply the arrays and objects of a JSON document nest, strings
+/// ignored. Malformed documents are left for the handler to refuse.
+fn depth(json: &[u8]) -> usize {
+    let (mut depth, mut deepest) = (0usize, 0);
+    let (mut in_string, mut escaped) = (false, false);
+    for b in json {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_string = true,
+            b'[' | b'{' => {
+                depth += 1;
+                deepest = deepest.max(depth);
+            }
+            b']' | b'}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    deepest
+}
+
+/// Applies `limits` to the body of the request.
+pub(crate) async fn limit(
+    State(limits): State<BodyLimits>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let json = is_json(request.headers());
+    let max = match json {
+        true => limits.json_bytes,
+        false => limits.body_bytes,
+    };
+    let declared = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if declared.is_some_and(|length| length > max as u64) {
+        return too_large(max).into_response();
+    }
+
+    let (parts, body) = request.into_parts();
+    let body = match json {
+        true => {
+            let bytes = match to_bytes(body, max).await {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    return match read_error(e) {
+                        AppError::PayloadTooLarge(_) => too_large(max),
+                        e => e,
+                    }
+                    .into_response()
+                }
+            };
+            if depth(&bytes) > limits.json_depth {
+                return AppError::BadRequest(format!(
+                    "The JSON body nests deeper than {} levels",
+                    limits.json_depth
+                ))
+                .into_response();
+            }
+            Body::from(bytes)
+        }
+        false => Body::new(Limited::new(body, max)),
+    };
+    let response = next.run(Request::from_parts(parts, body)).await;
+    // Extractors refusing a body over the limit answer in plain text.
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE && !is_json(response.headers()) {
+        return too_large(max).into_response();
+    }
+    response
+}
@@ -0,0 +1,197 @@
+//! Advisory record locks, for editors that should not overwrite each other.
+//!
+//! A lock names its owner, e.g. the editing user, and expires after a TTL
+//! unless the owner renews it by locking again. Locks are advisory: writes
+//! are not refused, but reads show the lock and taking or releasing one
+//! publishes `record.locked` and `record.unlocked` events, so clients can
+//! keep out of each other's way. Locks live in the shared-state store.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tinybase_core::{
+    clock,
+    events::{Event, EventAction},
+    format_timestamp,
+};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    access::{check_record, record_value, Access, Operation},
+    db_error, resolve_collection, AppError, AppState, RecordResponse,
+};
+
+const DEFAULT_TTL: u64 = 300;
+const MAX_TTL: u64 = 3600;
+
+fn lock_key(collection_id: i64, record_id: i64) -> String {
+    format!("record_lock:{}:{}", collection_id, record_id)
+}
+
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub struct RecordLock {
+    owner: String,
+    /// When the lock lapses unless renewed.
+    expires: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct LockRequest {
+    /// Who holds the lock, e.g. a user name or editor session id.
+    owner: String,
+    /// Seconds until the lock expires (default 300, at most 3600).
+    ttl: Option<u64>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct UnlockQuery {
+    /// The owner the lock was taken for.
+    owner: String,
+}
+
+/// The lock held on a record, if any.
+pub(crate) async fn current_lock(
+    state: &AppState,
+    collection_id: i64,
+    record_id: i64,
+) -> Result<Option<RecordLock>, AppError> {
+    let lock = state.store.get(&lock_key(collection_id, record_id)).await?;
+    Ok(lock.and_then(|lock| serde_json::from_slice(&lock).ok()))
+}
+
+/// Fills in the `lock` of each of `records`.
+pub(crate) async fn attach_locks(
+    state: &AppState,
+    collection_id: i64,
+    records: &mut [RecordResponse],
+) -> Result<(), AppError> {
+    for record in records {
+        record.lock = current_lock(state, collection_id, record.id).await?;
+    }
+    Ok(())
+}
+
+fn locked_by(lock: &RecordLock) -> AppError {
+    AppError::Conflict(format!(
+        "The record is locked by '{}' until {}",
+        lock.owner, lock.expires
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/collections/{id}/records/{record_id}/lock",
+    params(
+        ("id" = String, Path, description = "Collection id or name"),
+        ("record_id" = i64, Path, description = "Record id")
+    ),
+    request_body = LockRequest,
+    responses(
+        (status = 200, description = "Lock a record, or renew the owner's lock", body = RecordLock),
+        (status = 400, description = "Invalid owner or TTL", body = ProblemDetail),
+        (status = 403, description = "A collection rule denies the request", body = ProblemDetail),
+        (status = 404, description = "Record not found", body = ProblemDetail),
+        (status = 409, description = "Locked by someone else", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn lock_record(
+    State(state): State<AppState>,
+    Path((key, record_id)): Path<(String, i64)>,
+    Json(request): Json<LockRequest>,
+) -> Result<Json<RecordLock>, AppError> {
+    let owner = request.owner.trim();
+    if owner.is_empty() {
+        return Err(AppError::BadRequest("A lock needs an owner".to_string()));
+    }
+    let ttl = request.ttl.unwrap_or(DEFAULT_TTL);
+    if ttl == 0 || ttl > MAX_TTL {
+        return Err(AppError::BadRequest(format!(
+            "ttl must be between 1 and {} seconds",
+            MAX_TTL
+        )));
+    }
+    let collection_id = resolve_collection(state.db.as_ref(), &key).await?;
+    let record = state
+        .db
+        .get_record(collection_id, record_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| AppError::NotFound(format!("Record {} not found", record_id)))?;
+    // Locking is for editors, so it takes the right to update.
+    Access::to_id(&state, collection_id).await?.check(
+        Operation::Update,
+        &record_value(&record),
+        &serde_json::Value::Null,
+    )?;
+
+    let ttl = Duration::from_secs(ttl);
+    let lock = RecordLock {
+        owner: owner.to_string(),
+        expires: format_timestamp(clock::now() + ttl),
+    };
+    let entry = serde_json::to_vec(&lock).map_err(|e| AppError::JsonError(e.to_string()))?;
+    let key = lock_key(collection_id, record_id);
+    if !state.store.set_if_absent(&key, &entry, Some(ttl)).await? {
+        match current_lock(&state, collection_id, record_id).await? {
+            Some(held) if held.owner != lock.owner => return Err(locked_by(&held)),
+            _ => state.store.set(&key, &entry, Some(ttl)).await?,
+        }
+    }
+    state.events.publish(Event::new(
+        EventAction::RecordLocked,
+        collection_id,
+        Some(record_id),
+    ));
+    Ok(Json(lock))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/collections/{id}/records/{record_id}/lock",
+    params(
+        ("id" = String, Path, description = "Collection id or name"),
+        ("record_id" = i64, Path, description = "Record id"),
+        UnlockQuery
+    ),
+    responses(
+        (status = 204, description = "Release a lock"),
+        (status = 403, description = "A collection rule denies the request", body = ProblemDetail),
+        (status = 404, description = "The record is not locked", body = ProblemDetail),
+        (status = 409, description = "Locked by someone else", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn unlock_record(
+    State(state): State<AppState>,
+    Path((key, record_id)): Path<(String, i64)>,
+    Query(query): Query<UnlockQuery>,
+) -> Result<StatusCode, AppError> {
+    let collection_id = resolve_collection(state.db.as_ref(), &key).await?;
+    let lock = current_lock(&state, collection_id, record_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Record {} is not locked", record_id)))?;
+    check_record(
+        &state,
+        collection_id,
+        record_id,
+        Operation::Update,
+        &serde_json::Value::Null,
+    )
+    .await?;
+    if lock.owner != query.owner.trim() {
+        return Err(locked_by(&lock));
+    }
+    state
+        .store
+        .delete(&lock_key(collection_id, record_id))
+        .await?;
+    state.events.publish(Event::new(
+        EventAction::RecordUnlocked,
+        collection_id,
+        Some(record_id),
+    ));
+    Ok(StatusCode::NO_CONTENT)
+}
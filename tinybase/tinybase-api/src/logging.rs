@@ -0,0 +1,31 @@
+//! Logs and traces.
+//!
+//! Every request runs in a span, and every database query in a `debug` span
+//! nested in it carrying its collection and record ids. At `debug` and
+//! `trace` the log shows each query as its span closes, with how long it
+//! took and the error it failed with, if any. `RUST_LOG` takes precedence
+//! over the configured level, e.g. `RUST_LOG=tinybase_core=debug`.
+
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+
+use crate::config::{Config, LogFormat};
+
+/// Logs the events at the configured level and above, in the configured
+/// format, to standard output. Dependencies only log warnings and errors.
+pub fn init(config: &Config) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        EnvFilter::new(format!(
+            "warn,tinybase_api={0},tinybase_core={0},tinybase_storage={0},tower_http={0}",
+            config.log_level
+        ))
+    });
+    let logs = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE);
+    // Fails only when logging is already set up, e.g. by an embedding
+    // application, which is then left as it is.
+    let _ = match config.log_format {
+        LogFormat::Text => logs.try_init(),
+        LogFormat::Json => logs.json().with_current_span(true).try_init(),
+    };
+}
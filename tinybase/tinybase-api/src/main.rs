@@ -1,29 +1,28 @@
-use axum::serve;
-use std::sync::Arc;
-use tinybase_api::app_router;
-use tinybase_core::a_new_database_connection;
-use tokio::net::TcpListener;
+use tinybase_api::{config::Config, logging, server::Server};
 
 #[tokio::main]
 async fn main() {
-    let db = match a_new_database_connection().await {
-        Ok(db) => db,
+    // tinybase.toml, or the file named by TINYBASE_CONFIG, overridden by
+    // TINYBASE_* variables.
+    let config = match Config::load() {
+        Ok(config) => config,
         Err(e) => {
-            eprintln!("Failed to connect to database: {}", e);
+            eprintln!("Invalid configuration: {}", e);
             return;
         }
     };
-    let app = app_router(Arc::new(db));
+    logging::init(&config);
 
-    let listener = match TcpListener::bind("0.0.0.0:3000").await {
-        Ok(listener) => listener,
+    let server = match Server::open(config).await {
+        Ok(server) => server,
         Err(e) => {
-            eprintln!("Failed to bind to port 3000: {}", e);
+            eprintln!("{}", e);
             return;
         }
     };
-    println!("listening on {}", listener.local_addr().unwrap());
-    if let Err(e) = serve(listener, app).await {
-        eprintln!("Server error: {}", e);
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = server.run(&args).await {
+        eprintln!("{}", e);
     }
 }
@@ -0,0 +1,403 @@
+//! Collections as code: collections, their rules and indexes, and webhooks
+//! declared in a TOML or YAML file kept under version control, which the
+//! database is reconciled against.
+//!
+//! ```toml
+//! [[collections]]
+//! name = "posts"
+//! group = "blog"
+//! indexes = ["author"]
+//! schema.rules = { list = "true" }
+//! schema.fields.title = { type = "string", required = true }
+//! schema.fields.author = { type = { relation = { collection = "users" } }, required = true }
+//!
+//! [[webhooks]]
+//! collection = "posts"
+//! url = "https://hooks.example.com/posts"
+//! events = ["record.created"]
+//! ```
+//!
+//! Collections are matched by name, and relation fields name their target
+//! collection instead of its id, since ids differ between instances.
+//! Webhooks are matched by collection and URL. Missing collections and
+//! webhooks are created and differing ones updated; a declared collection
+//! ends up with exactly the declared indexes. The schema or group of a
+//! collection is left alone when not declared. Collections and webhooks the
+//! file doesn't declare are reported but never deleted, so a file can manage
+//! part of an instance.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use tinybase_core::{
+    rules::check_rule,
+    schema::CollectionSchema,
+    validation::{check_collection_name, check_schema},
+    webhooks::{WebhookSettings, WEBHOOK_EVENTS},
+    Db,
+};
+
+use crate::{auth::generate_key, check_group_name};
+
+type BoxResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Stands in for the id of a declared collection that doesn't exist yet
+/// when planning without applying; no collection has it.
+const UNCREATED: i64 = -1;
+
+/// The contents of a manifest file.
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(default)]
+    pub collections: Vec<CollectionSpec>,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookSpec>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CollectionSpec {
+    pub name: String,
+    pub group: Option<String>,
+    /// A schema as the collection endpoints take it, except that relations
+    /// name their target: `{ relation = { collection = "users" } }`.
+    pub schema: Option<Value>,
+    /// Top-level fields to index.
+    #[serde(default)]
+    pub indexes: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookSpec {
+    /// Name of the collection whose changes are delivered.
+    pub collection: String,
+    pub url: String,
+    /// `record.created`, `record.updated` and/or `record.deleted`.
+    pub events: Vec<String>,
+}
+
+impl Manifest {
+    /// Reads `path`, as YAML for `.yaml` and `.yml` files and as TOML
+    /// otherwise.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        let yaml = path
+            .extension()
+            .is_some_and(|ext| ext == "yaml" || ext == "yml");
+        let manifest = if yaml {
+            Self::from_yaml(&text)
+        } else {
+            Self::from_toml(&text)
+        };
+        manifest.map_err(|e| format!("Invalid {}: {}", path.display(), e))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| e.message().to_string())
+    }
+
+    pub fn from_yaml(text: &str) -> Result<Self, String> {
+        serde_yaml::from_str(text).map_err(|e| e.to_string())
+    }
+
+    fn declares(&self, collection: &str) -> bool {
+        self.collections.iter().any(|c| c.name == collection)
+    }
+
+    /// Checks names, schemas, rules, relation targets, indexes and webhooks
+    /// before anything is changed; `local` holds the ids of the existing
+    /// collections by name.
+    fn check(&self, local: &HashMap<String, i64>) -> Result<(), String> {
+        let mut ids = local.clone();
+        let mut declared = Vec::new();
+        for collection in &self.collections {
+            if declared.contains(&&collection.name) {
+                return Err(format!(
+                    "Collection '{}' is declared twice",
+                    collection.name
+                ));
+            }
+            declared.push(&collection.name);
+            ids.entry(collection.name.clone()).or_insert(UNCREATED);
+        }
+        for collection in &self.collections {
+            let name = &collection.name;
+            check_collection_name(name)
+                .map_err(|e| format!("Collection '{}' has an invalid name: {}", name, e))?;
+            if let Some(group) = &collection.group {
+                check_group_name(group).map_err(|_| {
+                    format!("Collection '{}' has an invalid group '{}'", name, group)
+                })?;
+            }
+            if let Some(schema) = &collection.schema {
+                let schema = resolve_relations(name, schema, &ids)?;
+                check_schema(&schema)
+                    .map_err(|e| format!("Collection '{}' has an invalid schema: {}", name, e))?;
+                for (operation, rule) in schema.rules.iter() {
+                    check_rule(rule).map_err(|e| {
+                        format!(
+                            "Collection '{}' has an invalid {} rule: {}",
+                            name, operation, e
+                        )
+                    })?;
+                }
+                if let Some(field) = collection
+                    .indexes
+                    .iter()
+                    .find(|f| !schema.fields.contains_key(*f))
+                {
+                    return Err(format!(
+                        "Collection '{}' indexes unknown field '{}'",
+                        name, field
+                    ));
+                }
+            }
+        }
+        for webhook in &self.webhooks {
+            if !ids.contains_key(&webhook.collection) {
+                return Err(format!(
+                    "Webhook '{}' watches unknown collection '{}'",
+                    webhook.url, webhook.collection
+                ));
+            }
+            if !webhook.url.starts_with("http://") && !webhook.url.starts_with("https://") {
+                return Err(format!("Webhook '{}' is not an http(s) URL", webhook.url));
+            }
+            if webhook.events.is_empty() {
+                return Err(format!(
+                    "Webhook '{}' must subscribe to at least one event",
+                    webhook.url
+                ));
+            }
+            if let Some(event) = webhook
+                .events
+                .iter()
+                .find(|e| !WEBHOOK_EVENTS.iter().any(|w| w.name() == e.as_str()))
+            {
+                return Err(format!(
+                    "Webhook '{}': '{}' is not one of record.created, record.updated or record.deleted",
+                    webhook.url, event
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The schema `declared` for `collection`, with relation targets named in
+/// it replaced by their ids.
+fn resolve_relations(
+    collection: &str,
+    declared: &Value,
+    ids: &HashMap<String, i64>,
+) -> Result<CollectionSchema, String> {
+    let mut schema = declared.clone();
+    if let Some(fields) = schema.get_mut("fields").and_then(Value::as_object_mut) {
+        for (name, field) in fields.iter_mut() {
+            let Some(relation) = field
+                .pointer_mut("/type/relation")
+                .and_then(Value::as_object_mut)
+            else {
+                continue;
+            };
+            let Some(target) = relation.remove("collection") else {
+                continue;
+            };
+            let target = target.as_str().unwrap_or_default();
+            let id = ids.get(target).ok_or_else(|| {
+                format!(
+                    "Field '{}' of collection '{}' relates to unknown collection '{}'",
+                    name, collection, target
+                )
+            })?;
+            relation.insert("collection_id".to_string(), json!(id));
+        }
+    }
+    serde_json::from_value(schema)
+        .map_err(|e| format!("Collection '{}' has an invalid schema: {}", collection, e))
+}
+
+/// What reconciling changes, or would change.
+#[derive(Serialize, Default, Debug)]
+pub struct ReconcilePlan {
+    /// Declared collections that don't exist.
+    pub create: Vec<String>,
+    /// Declared collections whose schema or group differ.
+    pub update: Vec<String>,
+    pub unchanged: Vec<String>,
+    /// Indexes to add, as `collection.field`.
+    pub create_indexes: Vec<String>,
+    /// Indexes of declared collections the file doesn't list.
+    pub drop_indexes: Vec<String>,
+    /// Webhooks to add, as `collection url`.
+    pub create_webhooks: Vec<String>,
+    /// Webhooks whose events differ.
+    pub update_webhooks: Vec<String>,
+    /// Collections and webhooks the file doesn't declare; they are left
+    /// untouched.
+    pub undeclared: Vec<String>,
+}
+
+impl ReconcilePlan {
+    /// Whether the database already matches the file.
+    pub fn is_empty(&self) -> bool {
+        self.create.is_empty()
+            && self.update.is_empty()
+            && self.create_indexes.is_empty()
+            && self.drop_indexes.is_empty()
+            && self.create_webhooks.is_empty()
+            && self.update_webhooks.is_empty()
+    }
+
+    /// One line per difference, e.g. `    create  posts`.
+    pub fn lines(&self) -> Vec<String> {
+        [
+            ("create", &self.create),
+            ("update", &self.update),
+            ("unchanged", &self.unchanged),
+            ("index", &self.create_indexes),
+            ("drop index", &self.drop_indexes),
+            ("webhook", &self.create_webhooks),
+            ("update hook", &self.update_webhooks),
+            ("undeclared", &self.undeclared),
+        ]
+        .into_iter()
+        .flat_map(|(label, names)| names.iter().map(move |n| format!("{:>11}  {}", label, n)))
+        .collect()
+    }
+}
+
+/// Compares the database with `manifest` and, with `apply`, makes the
+/// changes. The manifest is checked first, so an invalid one changes
+/// nothing.
+pub async fn reconcile(db: &dyn Db, manifest: &Manifest, apply: bool) -> BoxResult<ReconcilePlan> {
+    let local = db.list_collections().await?;
+    let mut ids: HashMap<String, i64> = local.iter().map(|c| (c.name.clone(), c.id)).collect();
+    manifest.check(&ids)?;
+    let mut plan = ReconcilePlan::default();
+
+    // Missing collections come first, so relations to them resolve.
+    for spec in &manifest.collections {
+        if ids.contains_key(&spec.name) {
+            continue;
+        }
+        plan.create.push(spec.name.clone());
+        let id = match apply {
+            true => db.create_collection(&spec.name, &None).await?.id,
+            false => UNCREATED,
+        };
+        ids.insert(spec.name.clone(), id);
+    }
+
+    for spec in &manifest.collections {
+        let id = ids[&spec.name];
+        let existing = local.iter().find(|c| c.id == id);
+        let schema = match &spec.schema {
+            Some(schema) => Some(resolve_relations(&spec.name, schema, &ids)?),
+            None => None,
+        };
+        let schema_differs = schema
+            .as_ref()
+            .is_some_and(|s| existing.and_then(|c| c.schema.as_ref()) != Some(s));
+        let group = spec.group.as_deref().map(str::trim);
+        let group_differs =
+            group.is_some_and(|g| existing.and_then(|c| c.group.as_deref()) != Some(g));
+        if existing.is_some() {
+            if schema_differs || group_differs {
+                plan.update.push(spec.name.clone());
+            } else {
+                plan.unchanged.push(spec.name.clone());
+            }
+        }
+        if apply && schema_differs {
+            db.update_collection(id, None, schema).await?;
+        }
+        if apply && group_differs {
+            db.set_collection_group(id, group).await?;
+        }
+
+        let indexed = match existing {
+            Some(_) => db.list_record_indexes(id).await?,
+            None => Vec::new(),
+        };
+        for field in spec.indexes.iter().filter(|f| !indexed.contains(f)) {
+            plan.create_indexes.push(format!("{}.{}", spec.name, field));
+            if apply {
+                db.create_record_index(id, field).await?;
+            }
+        }
+        for field in indexed.iter().filter(|f| !spec.indexes.contains(f)) {
+            plan.drop_indexes.push(format!("{}.{}", spec.name, field));
+            if apply {
+                db.drop_record_index(id, field).await?;
+            }
+        }
+    }
+    plan.undeclared.extend(
+        local
+            .iter()
+            .filter(|c| !manifest.declares(&c.name))
+            .map(|c| c.name.clone()),
+    );
+
+    let webhooks = db.list_webhooks().await?;
+    let name_of = |id: i64| {
+        ids.iter()
+            .find(|(_, &i)| i == id)
+            .map(|(name, _)| name.as_str())
+    };
+    for spec in &manifest.webhooks {
+        let collection_id = ids[&spec.collection];
+        let label = format!("{} {}", spec.collection, spec.url);
+        let settings = WebhookSettings {
+            url: spec.url.clone(),
+            collection_id,
+            events: spec.events.clone(),
+        };
+        let existing = webhooks
+            .iter()
+            .find(|w| w.settings.collection_id == collection_id && w.settings.url == spec.url);
+        match existing {
+            None => {
+                plan.create_webhooks.push(label);
+                if apply {
+                    db.create_webhook(&settings, &generate_key()).await?;
+                }
+            }
+            Some(webhook) => {
+                let mut events = webhook.settings.events.clone();
+                let mut wanted = spec.events.clone();
+                events.sort();
+                wanted.sort();
+                if events != wanted {
+                    plan.update_webhooks.push(label);
+                    if apply {
+                        db.update_webhook(webhook.id, &settings).await?;
+                    }
+                }
+            }
+        }
+    }
+    plan.undeclared.extend(
+        webhooks
+            .iter()
+            .filter(|w| {
+                !manifest.webhooks.iter().any(|spec| {
+                    spec.url == w.settings.url
+                        && name_of(w.settings.collection_id) == Some(spec.collection.as_str())
+                })
+            })
+            .map(|w| {
+                format!(
+                    "webhook {} {}",
+                    name_of(w.settings.collection_id).unwrap_or("?"),
+                    w.settings.url
+                )
+            }),
+    );
+    Ok(plan)
+}
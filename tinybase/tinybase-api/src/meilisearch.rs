@@ -0,0 +1,143 @@
+//! A [`SearchIndex`] on a Meilisearch server, for typo-tolerant search with
+//! ranking rules configured on the server. Each collection gets an index
+//! named `tinybase_<collection id>` holding the searchable fields of its
+//! records, keyed by record id.
+//!
+//! Meilisearch applies writes asynchronously, in order, so a record shows up
+//! in results shortly after it is indexed.
+
+use axum::async_trait;
+use reqwest::{RequestBuilder, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::time::Duration;
+use tinybase_core::search::{SearchHit, SearchIndex};
+
+type BoxResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// How long a request to the server may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct MeilisearchIndex {
+    url: String,
+    key: Option<String>,
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    hits: Vec<Hit>,
+}
+
+#[derive(Deserialize)]
+struct Hit {
+    id: i64,
+    #[serde(rename = "_rankingScore", default)]
+    ranking_score: f64,
+}
+
+impl MeilisearchIndex {
+    /// The server at `url`, authenticating with the API `key` if given.
+    pub fn new(url: impl Into<String>, key: Option<String>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self {
+            url: url.into().trim_end_matches('/').to_string(),
+            key,
+            client,
+        }
+    }
+
+    fn index_url(&self, collection_id: i64) -> String {
+        format!("{}/indexes/tinybase_{}", self.url, collection_id)
+    }
+
+    fn authorized(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+
+    /// Sends `request`, treating a missing index as success when
+    /// `missing_ok`, since there is nothing to remove or find in it.
+    async fn send(
+        &self,
+        request: RequestBuilder,
+        missing_ok: bool,
+    ) -> BoxResult<Option<reqwest::Response>> {
+        let response = self.authorized(request).send().await?;
+        if missing_ok && response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?))
+    }
+}
+
+#[async_trait]
+impl SearchIndex for MeilisearchIndex {
+    fn name(&self) -> &'static str {
+        "meilisearch"
+    }
+
+    async fn index(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        document: &Map<String, Value>,
+    ) -> BoxResult<()> {
+        let mut document = document.clone();
+        document.insert("id".to_string(), json!(record_id));
+        let url = format!("{}/documents?primaryKey=id", self.index_url(collection_id));
+        self.send(self.client.post(url).json(&[document]), false)
+            .await?;
+        Ok(())
+    }
+
+    async fn remove(&self, collection_id: i64, record_id: i64) -> BoxResult<()> {
+        let url = format!("{}/documents/{}", self.index_url(collection_id), record_id);
+        self.send(self.client.delete(url), true).await?;
+        Ok(())
+    }
+
+    async fn clear(&self, collection_id: i64) -> BoxResult<()> {
+        self.send(self.client.delete(self.index_url(collection_id)), true)
+            .await?;
+        Ok(())
+    }
+
+    async fn search(
+        &self,
+        collection_id: i64,
+        query: &str,
+        limit: usize,
+    ) -> BoxResult<Vec<SearchHit>> {
+        let url = format!("{}/search", self.index_url(collection_id));
+        let body = json!({
+            "q": query,
+            "limit": limit,
+            "attributesToRetrieve": ["id"],
+            "showRankingScore": true,
+        });
+        let Some(response) = self.send(self.client.post(url).json(&body), true).await? else {
+            return Ok(Vec::new());
+        };
+        let response: SearchResponse = response.json().await?;
+        Ok(response
+            .hits
+            .into_iter()
+            .map(|hit| SearchHit {
+                record_id: hit.id,
+                score: hit.ranking_score,
+            })
+            .collect())
+    }
+
+    async fn check(&self) -> BoxResult<()> {
+        let url = format!("{}/health", self.url);
+        self.send(self.client.get(url), false).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+//! What this instance is and can do, so SDKs can feature-detect instead of
+//! assuming, and so the startup banner can say the same.
+
+use axum::{extract::State, Json};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    envelope::{ListFormat, DEFAULT_PER_PAGE, MAX_PER_PAGE},
+    AppState,
+};
+
+#[derive(Serialize, ToSchema)]
+pub struct Limits {
+    /// Largest request body accepted, in bytes.
+    max_body_bytes: usize,
+    /// Largest JSON request body accepted, in bytes.
+    max_json_bytes: usize,
+    /// Deepest nesting of arrays and objects accepted in a JSON body.
+    max_json_depth: usize,
+    /// Page size of lists when none is asked for.
+    default_per_page: u64,
+    max_per_page: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ContentTypes {
+    /// Request body types accepted by at least one endpoint.
+    requests: Vec<&'static str>,
+    /// Response types that can be asked for or are produced.
+    responses: Vec<&'static str>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Meta {
+    name: &'static str,
+    version: &'static str,
+    /// Optional features enabled on this instance.
+    features: Vec<&'static str>,
+    /// Names of the registered plugins.
+    plugins: Vec<&'static str>,
+    /// `bare` or `envelope`, the shape of list responses.
+    list_format: &'static str,
+    /// The backend of full-text search: `fts5`, `tantivy` or `meilisearch`.
+    search: &'static str,
+    limits: Limits,
+    content_types: ContentTypes,
+}
+
+impl Meta {
+    pub fn new(state: &AppState) -> Self {
+        let mut features = Vec::new();
+        if cfg!(feature = "swagger-ui") {
+            features.push("swagger-ui");
+        }
+        if cfg!(feature = "schema-sync") {
+            features.push("schema-sync");
+        }
+        if cfg!(feature = "notifications") {
+            features.push("notifications");
+        }
+        if cfg!(feature = "webhooks") {
+            features.push("webhooks");
+        }
+        if cfg!(feature = "scripting") {
+            features.push("scripting");
+        }
+        if cfg!(feature = "jwt") {
+            features.push("jwt");
+        }
+        if cfg!(feature = "manifest") {
+            features.push("manifest");
+        }
+        if cfg!(feature = "admin-ui") {
+            features.push("admin-ui");
+        }
+        if cfg!(feature = "redis") {
+            features.push("redis");
+        }
+        if cfg!(feature = "tantivy") {
+            features.push("tantivy");
+        }
+        if cfg!(feature = "meilisearch") {
+            features.push("meilisearch");
+        }
+        if state.replica.is_some() {
+            features.push("replica");
+        }
+        if state.read_replicas.is_some() {
+            features.push("read-replicas");
+        }
+        if state.backup_dir.is_some() {
+            features.push("backups");
+        }
+        if state.verifier.is_some() {
+            features.push("snapshot-verification");
+        }
+        if state.developer_mode {
+            features.push("developer-mode");
+        }
+        Meta {
+            name: "tinybase",
+            version: env!("CARGO_PKG_VERSION"),
+            features,
+            plugins: state.plugins.to_vec(),
+            list_format: match state.list_format {
+                ListFormat::Bare => "bare",
+                ListFormat::Envelope => "envelope",
+            },
+            search: state.search.name(),
+            limits: Limits {
+                max_body_bytes: state.max_body_bytes,
+                max_json_bytes: state.max_json_bytes,
+                max_json_depth: state.max_json_depth,
+                default_per_page: DEFAULT_PER_PAGE,
+                max_per_page: MAX_PER_PAGE,
+            },
+            content_types: ContentTypes {
+                requests: vec![
+                    "application/json",
+                    "multipart/form-data",
+                    "application/x-ndjson",
+                    "text/csv",
+                ],
+                responses: vec![
+                    "application/json",
+                    "application/x-ndjson",
+                    "text/csv",
+                    "text/event-stream",
+                ],
+            },
+        }
+    }
+
+    /// A few lines describing the instance, printed at startup.
+    pub fn banner(&self) -> String {
+        let list = |items: &[&str]| {
+            if items.is_empty() {
+                "none".to_string()
+            } else {
+                items.join(", ")
+            }
+        };
+        format!(
+            "{} {}\n  features: {}\n  plugins:  {}",
+            self.name,
+            self.version,
+            list(&self.features),
+            list(&self.plugins)
+        )
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/meta",
+    responses(
+        (status = 200, description = "The server version, enabled features and plugins, limits and supported content types", body = Meta)
+    )
+)]
+pub(crate) async fn get_meta(State(state): State<AppState>) -> Json<Meta> {
+    Json(Meta::new(&state))
+}
@@ -0,0 +1,136 @@
+//! Namespaces: one instance serving several isolated apps.
+//!
+//! Each namespace is an app of its own, on its own database file: its
+//! collections, records, admins and API keys, uploads, events, caches and
+//! jobs are invisible to the others. A request is scoped to namespace
+//! `shop` by a path prefix, `/api/v1/ns/shop/collections/...`, or by the
+//! `X-Tinybase-Namespace: shop` header on the plain path; requests naming
+//! neither go to the default app. The namespaces are declared up front in
+//! the `namespaces` setting, e.g.
+//! `TINYBASE_NAMESPACES=shop=data/shop.db,blog=data/blog.db`, so requests
+//! can't create databases. Namespaces share the settings of the instance and
+//! its identity provider, search them with the built-in FTS5 index and don't
+//! serve plugin routes.
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, Uri},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tinybase_core::{a_new_database_connection, pool::ConnectionPool};
+use tinybase_storage::LocalStorage;
+use tower::ServiceExt;
+
+use crate::{config::Config, AppError, AppState};
+
+const NAMESPACE: HeaderName = HeaderName::from_static("x-tinybase-namespace");
+const PATH_PREFIX: &str = "/api/v1/ns/";
+
+/// The routers of the namespaces of an instance, by name.
+#[derive(Clone, Default)]
+pub struct Namespaces {
+    routers: HashMap<String, Router>,
+}
+
+impl Namespaces {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves `router` as namespace `name`, which must be letters, digits,
+    /// `-` and `_`.
+    pub fn add(&mut self, name: &str, router: Router) -> Result<(), String> {
+        let valid = name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if name.is_empty() || !valid {
+            return Err(format!(
+                "Invalid namespace name '{}', use letters, digits, '-' and '_'",
+                name
+            ));
+        }
+        if self.routers.insert(name.to_string(), router).is_some() {
+            return Err(format!("Namespace '{}' is declared twice", name));
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routers.is_empty()
+    }
+}
+
+/// Opens the databases of the configured `namespaces`, creating them if
+/// needed, and returns the state of each, derived from `base` with
+/// [`AppState::for_namespace`]. Uploads go to `ns/<name>` in the
+/// configured `uploads_dir`.
+pub async fn open(base: &AppState, config: &Config) -> Result<Vec<(String, AppState)>, String> {
+    let mut namespaces = Vec::new();
+    for (name, path) in &config.namespaces {
+        let db = a_new_database_connection(path)
+            .await
+            .map_err(|e| format!("Failed to open the database of namespace {}: {}", name, e))?;
+        let pool = ConnectionPool::new(Arc::new(db), config.db_pool_size)
+            .await
+            .map_err(|e| {
+                format!(
+                    "Failed to open the connection pool of namespace {}: {}",
+                    name, e
+                )
+            })?;
+        let storage = LocalStorage::new(config.uploads_dir.join("ns").join(name));
+        let state = base.for_namespace(name, Arc::new(pool), Arc::new(storage));
+        namespaces.push((name.clone(), state));
+    }
+    Ok(namespaces)
+}
+
+/// Wraps `router`, the default app, so requests scoped to one of
+/// `namespaces` are served by that namespace instead.
+pub fn route(router: Router, namespaces: Namespaces) -> Router {
+    router.layer(middleware::from_fn_with_state(
+        Arc::new(namespaces),
+        dispatch,
+    ))
+}
+
+async fn dispatch(
+    State(namespaces): State<Arc<Namespaces>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let name = match request.uri().path().strip_prefix(PATH_PREFIX) {
+        Some(rest) => {
+            let (name, rest) = rest.split_once('/').unwrap_or((rest, ""));
+            let path = match request.uri().query() {
+                Some(query) => format!("/api/v1/{}?{}", rest, query),
+                None => format!("/api/v1/{}", rest),
+            };
+            let name = name.to_string();
+            let mut parts = request.uri().clone().into_parts();
+            parts.path_and_query = path.parse().ok();
+            match Uri::from_parts(parts) {
+                Ok(uri) => *request.uri_mut() = uri,
+                Err(_) => {
+                    return AppError::BadRequest("Invalid request path".to_string()).into_response()
+                }
+            }
+            name
+        }
+        None => match request.headers().get(NAMESPACE) {
+            Some(name) => name.to_str().unwrap_or_default().to_string(),
+            None => return next.run(request).await,
+        },
+    };
+    let Some(router) = namespaces.routers.get(&name) else {
+        return AppError::NotFound(format!("Namespace {} not found", name)).into_response();
+    };
+    match router.clone().oneshot(request).await {
+        Ok(response) => response,
+        Err(never) => match never {},
+    }
+}
@@ -0,0 +1,284 @@
+//! Slack and Discord notification channels, managed by admins and told about
+//! collection events and admin alerts such as failed backup verification.
+//!
+//! [`spawn`] starts the dispatcher delivering to them. Deliveries are best
+//! effort: a failing webhook is reported on stderr and not retried.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tinybase_core::{
+    clock,
+    events::{Alert, Event},
+    notifications::{ChannelSettings, NotificationChannel},
+};
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::{auth::RequireAdmin, db_error, AppError, AppState, ProblemDetail};
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, ToSchema)]
+pub struct ChannelResponse {
+    id: i64,
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    settings: ChannelSettings,
+    created: String,
+    updated: String,
+}
+
+impl From<NotificationChannel> for ChannelResponse {
+    fn from(c: NotificationChannel) -> Self {
+        ChannelResponse {
+            id: c.id,
+            settings: c.settings,
+            created: c.created,
+            updated: c.updated,
+        }
+    }
+}
+
+fn channel_not_found(id: i64) -> AppError {
+    AppError::NotFound(format!("Notification channel {} not found", id))
+}
+
+/// The template context of an event.
+async fn event_context(state: &AppState, event: &Event) -> Value {
+    if let Some(admin_id) = event.admin_id {
+        return json!({
+            "event": event.action.name(),
+            "text": format!("{}: admin {}", event.action.name(), admin_id),
+            "admin_id": admin_id,
+            "timestamp": event.timestamp,
+        });
+    }
+    let name = match state.db.get_collection(event.collection_id).await {
+        Ok(Some(collection)) => collection.name,
+        _ => event.collection_id.to_string(),
+    };
+    let text = match event.record_id {
+        Some(record_id) => format!("{} in {}: record {}", event.action.name(), name, record_id),
+        None => format!("{}: {}", event.action.name(), name),
+    };
+    json!({
+        "event": event.action.name(),
+        "text": text,
+        "collection": { "id": event.collection_id, "name": name },
+        "record_id": event.record_id,
+        "timestamp": event.timestamp,
+    })
+}
+
+fn alert_context(alert: &Alert) -> Value {
+    json!({
+        "event": alert.name,
+        "text": alert.message,
+        "timestamp": alert.timestamp,
+    })
+}
+
+async fn deliver(client: &reqwest::Client, settings: &ChannelSettings, context: &Value) {
+    let result = client
+        .post(&settings.url)
+        .json(&settings.payload(context))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status());
+    if let Err(e) = result {
+        tracing::warn!(
+            channel = %settings.name,
+            event = %context["event"],
+            error = %e,
+            "failed to notify channel"
+        );
+    }
+}
+
+/// Delivers `context` to every channel subscribed to it.
+async fn notify(
+    state: &AppState,
+    client: &reqwest::Client,
+    context: Value,
+    collection: Option<i64>,
+) {
+    let channels = match state.db.list_notification_channels().await {
+        Ok(channels) => channels,
+        Err(e) => {
+            tracing::error!(error = %e, "failed to load notification channels");
+            return;
+        }
+    };
+    let event = context["event"].as_str().unwrap_or_default();
+    for channel in channels {
+        if channel.settings.wants(event, collection) {
+            deliver(client, &channel.settings, &context).await;
+        }
+    }
+}
+
+/// Starts delivering the instance's events and alerts to the notification
+/// channels, in the background. When the server shuts down the events and
+/// alerts already queued are still delivered.
+pub fn spawn(state: AppState) {
+    let client = reqwest::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    let mut events = state.events.subscribe();
+    let mut alerts = state.events.subscribe_alerts();
+    let shutdown = state.shutdown.clone();
+    shutdown.spawn(clock::with_clock(state.clock.clone(), async move {
+        loop {
+            tokio::select! {
+                biased;
+                event = events.recv() => match event {
+                    Ok(event) => {
+                        let context = event_context(&state, &event).await;
+                        notify(&state, &client, context, event.collection()).await;
+                    }
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+                alert = alerts.recv() => match alert {
+                    Ok(alert) => notify(&state, &client, alert_context(&alert), None).await,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+                _ = state.shutdown.flushing() => break,
+            }
+        }
+    }));
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/notifications",
+    responses(
+        (status = 200, description = "List the Slack and Discord notification channels", body = Vec<ChannelResponse>),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn list_channels(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ChannelResponse>>, AppError> {
+    let channels = state
+        .db
+        .list_notification_channels()
+        .await
+        .map_err(db_error)?;
+    Ok(Json(channels.into_iter().map(Into::into).collect()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/notifications",
+    request_body(content = Object, description = "`name`, `kind` (`slack` or `discord`), webhook `url`, `events` to deliver (`*` for all), optional `collection_id` and payload `template`"),
+    responses(
+        (status = 201, description = "Add a notification channel", body = ChannelResponse),
+        (status = 400, description = "Invalid webhook URL or no events", body = ProblemDetail),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn create_channel(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Json(settings): Json<ChannelSettings>,
+) -> Result<(StatusCode, Json<ChannelResponse>), AppError> {
+    if reqwest::Url::parse(&settings.url).is_err() {
+        return Err(AppError::BadRequest(format!(
+            "'{}' is not a webhook URL",
+            settings.url
+        )));
+    }
+    if settings.events.is_empty() {
+        return Err(AppError::BadRequest(
+            "A channel must subscribe to at least one event".to_string(),
+        ));
+    }
+    let channel = state
+        .db
+        .create_notification_channel(&settings)
+        .await
+        .map_err(db_error)?;
+    Ok((StatusCode::CREATED, Json(channel.into())))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/notifications/{id}",
+    params(
+        ("id" = i64, Path, description = "Channel id")
+    ),
+    responses(
+        (status = 204, description = "Remove a notification channel"),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Channel not found", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn delete_channel(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, AppError> {
+    let db = &state.db;
+    db.get_notification_channel(id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| channel_not_found(id))?;
+    db.delete_notification_channel(id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/notifications/{id}/test",
+    params(
+        ("id" = i64, Path, description = "Channel id")
+    ),
+    responses(
+        (status = 204, description = "Send a test message to a notification channel"),
+        (status = 400, description = "The webhook rejected the message", body = ProblemDetail),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Channel not found", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn test_channel(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, AppError> {
+    let channel = state
+        .db
+        .get_notification_channel(id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| channel_not_found(id))?;
+    let context = alert_context(&Alert::new(
+        "test",
+        format!("Test message for channel '{}'", channel.settings.name),
+    ));
+    reqwest::Client::new()
+        .post(&channel.settings.url)
+        .timeout(DELIVERY_TIMEOUT)
+        .json(&channel.settings.payload(&context))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| AppError::BadRequest(format!("Delivery failed: {}", e)))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(list_channels, create_channel, delete_channel, test_channel),
+    components(schemas(ChannelResponse, ProblemDetail))
+)]
+pub(crate) struct NotifyApiDoc;
@@ -0,0 +1,99 @@
+//! Manual ordering of records, for collections whose schema sets `order`:
+//! moving a record before or after another one, as drag and drop does, with
+//! the positions managed server-side.
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use serde::Deserialize;
+use tinybase_core::{
+    events::{Event, EventAction},
+    ordering::Placement,
+};
+use utoipa::ToSchema;
+
+use crate::{
+    access::{check_record, Operation},
+    db_error, resolve_collection,
+    shape::shape_records,
+    AppError, AppState, RecordResponse,
+};
+
+/// Names exactly one of `before` and `after`.
+#[derive(Deserialize, ToSchema)]
+pub struct MoveRecordRequest {
+    /// Move the record right before this one.
+    before: Option<i64>,
+    /// Move the record right after this one.
+    after: Option<i64>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/collections/{id}/records/{record_id}/move",
+    params(
+        ("id" = String, Path, description = "Collection id or name"),
+        ("record_id" = i64, Path, description = "Record id")
+    ),
+    request_body = MoveRecordRequest,
+    responses(
+        (status = 200, description = "Move a record before or after another one in the order of the collection; list with `?sort=position` to read the order", body = RecordResponse),
+        (status = 400, description = "The collection is not ordered, or the request names neither or both of `before` and `after`", body = ProblemDetail),
+        (status = 403, description = "A collection rule denies the request", body = ProblemDetail),
+        (status = 404, description = "Collection or record not found", body = ProblemDetail),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn move_record(
+    State(state): State<AppState>,
+    Path((key, record_id)): Path<(String, i64)>,
+    Json(request): Json<MoveRecordRequest>,
+) -> Result<Json<RecordResponse>, AppError> {
+    let placement = match (request.before, request.after) {
+        (Some(id), None) => Placement::Before(id),
+        (None, Some(id)) => Placement::After(id),
+        _ => {
+            return Err(AppError::BadRequest(
+                "Give exactly one of before and after".to_string(),
+            ))
+        }
+    };
+    let db = &state.db;
+    let collection_id = resolve_collection(db.as_ref(), &key).await?;
+    let collection = db
+        .get_collection(collection_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", collection_id)))?;
+    if collection.schema.and_then(|s| s.order).is_none() {
+        return Err(AppError::BadRequest(format!(
+            "Collection {} is not ordered; set order in its schema",
+            collection.name
+        )));
+    }
+    for id in [record_id, placement.anchor()] {
+        if db
+            .get_record(collection_id, id)
+            .await
+            .map_err(db_error)?
+            .is_none()
+        {
+            return Err(AppError::NotFound(format!("Record {} not found", id)));
+        }
+    }
+    let data = serde_json::json!({ "before": request.before, "after": request.after });
+    check_record(&state, collection_id, record_id, Operation::Update, &data).await?;
+    let record = db
+        .move_record(collection_id, record_id, placement)
+        .await
+        .map_err(db_error)?;
+    state.events.publish(Event::new(
+        EventAction::RecordUpdated,
+        collection_id,
+        Some(record_id),
+    ));
+    let mut response = RecordResponse::from(record);
+    shape_records(&state, collection_id, [&mut response]).await?;
+    Ok(Json(response))
+}
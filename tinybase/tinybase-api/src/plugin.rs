@@ -0,0 +1,196 @@
+//! Extension point for features shipped outside the core API.
+//!
+//! A plugin implements [`TinybasePlugin`] and is registered on the
+//! [`Tinybase`] builder, which wires its routes, migrations, event and
+//! response hooks, scheduled tasks and CLI subcommands into the server.
+
+use axum::{async_trait, Router};
+use futures_util::future::BoxFuture;
+use libsql::{params, Connection};
+use std::sync::Arc;
+use std::time::Duration;
+use tinybase_core::{clock, events::Event};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{router_with, shape::ResponseHooks, AppState};
+
+/// A task run every `every`, starting one interval after startup.
+pub struct Schedule {
+    pub name: &'static str,
+    pub every: Duration,
+    pub task: Arc<dyn Fn(AppState) -> BoxFuture<'static, ()> + Send + Sync>,
+}
+
+/// A command-line subcommand, e.g. `tinybase-api <name> [args...]`.
+pub struct Command {
+    pub name: &'static str,
+    pub about: &'static str,
+}
+
+#[async_trait]
+pub trait TinybasePlugin: Send + Sync + 'static {
+    /// Unique name, also used to track which migrations have run.
+    fn name(&self) -> &'static str;
+
+    /// Routes served under `/api/v1`.
+    fn routes(&self) -> Router<AppState> {
+        Router::new()
+    }
+
+    /// SQL statements applied once each, in order. Append new ones; never
+    /// edit or reorder statements that may already have run.
+    fn migrations(&self) -> Vec<&'static str> {
+        Vec::new()
+    }
+
+    /// Registers hooks shaping how records are returned by the API.
+    fn response_hooks(&self, _hooks: &mut ResponseHooks) {}
+
+    /// Called for every event published on the instance's event bus.
+    async fn on_event(&self, _state: &AppState, _event: &Event) {}
+
+    fn schedules(&self) -> Vec<Schedule> {
+        Vec::new()
+    }
+
+    fn commands(&self) -> Vec<Command> {
+        Vec::new()
+    }
+
+    /// Runs one of the subcommands declared by [`commands`](Self::commands).
+    async fn run_command(
+        &self,
+        _state: &AppState,
+        command: &str,
+        _args: &[String],
+    ) -> Result<(), String> {
+        Err(format!("Unknown command '{}'", command))
+    }
+}
+
+/// Assembles the server from its state and the registered plugins.
+pub struct Tinybase {
+    state: AppState,
+    plugins: Vec<Arc<dyn TinybasePlugin>>,
+}
+
+impl Tinybase {
+    pub fn new(state: AppState) -> Self {
+        Self {
+            state,
+            plugins: Vec::new(),
+        }
+    }
+
+    pub fn plugin(mut self, plugin: impl TinybasePlugin) -> Self {
+        let mut hooks = (*self.state.response_hooks).clone();
+        plugin.response_hooks(&mut hooks);
+        self.state = self.state.with_response_hooks(hooks);
+        Arc::make_mut(&mut self.state.plugins).push(plugin.name());
+        self.plugins.push(Arc::new(plugin));
+        self
+    }
+
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    /// Applies the plugins' pending migrations.
+    pub async fn migrate(&self, conn: &Connection) -> libsql::Result<()> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS plugin_migrations (plugin TEXT NOT NULL, version INTEGER NOT NULL, PRIMARY KEY (plugin, version))",
+            (),
+        )
+        .await?;
+        for plugin in &self.plugins {
+            let mut rows = conn
+                .query(
+                    "SELECT COALESCE(MAX(version), 0) FROM plugin_migrations WHERE plugin = ?1",
+                    params![plugin.name()],
+                )
+                .await?;
+            let applied: i64 = match rows.next().await? {
+                Some(row) => row.get(0)?,
+                None => 0,
+            };
+            for (index, sql) in plugin.migrations().into_iter().enumerate() {
+                let version = index as i64 + 1;
+                if version <= applied {
+                    continue;
+                }
+                conn.execute("BEGIN", ()).await?;
+                let result = async {
+                    conn.execute_batch(sql).await?;
+                    conn.execute(
+                        "INSERT INTO plugin_migrations (plugin, version) VALUES (?1, ?2)",
+                        params![plugin.name(), version],
+                    )
+                    .await
+                }
+                .await;
+                match result {
+                    Ok(_) => conn.execute("COMMIT", ()).await?,
+                    Err(e) => {
+                        conn.execute("ROLLBACK", ()).await?;
+                        return Err(e);
+                    }
+                };
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the application router, including the plugins' routes.
+    pub fn router(&self) -> Router {
+        let extra = self.plugins.iter().fold(Router::new(), |router, plugin| {
+            router.merge(plugin.routes())
+        });
+        router_with(self.state.clone(), extra)
+    }
+
+    /// Starts delivering events to the plugins' hooks and running their
+    /// scheduled tasks in the background.
+    pub fn start(&self) {
+        for plugin in &self.plugins {
+            let hooks = plugin.clone();
+            let state = self.state.clone();
+            let mut events = state.events.subscribe();
+            tokio::spawn(clock::with_clock(state.clock.clone(), async move {
+                loop {
+                    match events.recv().await {
+                        Ok(event) => hooks.on_event(&state, &event).await,
+                        Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => break,
+                    }
+                }
+            }));
+            for schedule in plugin.schedules() {
+                let state = self.state.clone();
+                tokio::spawn(clock::with_clock(state.clock.clone(), async move {
+                    let mut ticker = tokio::time::interval(schedule.every);
+                    ticker.tick().await;
+                    loop {
+                        ticker.tick().await;
+                        (schedule.task)(state.clone()).await;
+                    }
+                }));
+            }
+        }
+    }
+
+    /// Lists the subcommands contributed by plugins.
+    pub fn commands(&self) -> Vec<Command> {
+        self.plugins.iter().flat_map(|p| p.commands()).collect()
+    }
+
+    /// Runs the plugin subcommand named by `args[0]`, or returns `None` if no
+    /// plugin declares it.
+    pub async fn run_command(&self, args: &[String]) -> Option<Result<(), String>> {
+        let name = args.first()?;
+        let plugin = self
+            .plugins
+            .iter()
+            .find(|p| p.commands().iter().any(|c| c.name == name))?;
+        Some(plugin.run_command(&self.state, name, &args[1..]).await)
+    }
+}
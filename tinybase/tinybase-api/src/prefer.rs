@@ -0,0 +1,87 @@
+//! The `Prefer` header (RFC 7240) on writes.
+//!
+//! `Prefer: return=minimal` answers a `POST`, `PUT` or `PATCH` that would
+//! get `200 OK` or `201 Created` with `204 No Content` and no body, for bulk
+//! writers that don't read what they wrote; headers such as `Location` are
+//! kept. `Prefer: return=representation` asks for the written resource with
+//! its server-computed fields, which writes return by default. Either way
+//! the response says which was honored in `Preference-Applied`.
+
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE},
+        HeaderMap, HeaderName, HeaderValue, Method, StatusCode,
+    },
+    middleware::{self, Next},
+    response::Response,
+    Router,
+};
+
+const PREFER: HeaderName = HeaderName::from_static("prefer");
+const PREFERENCE_APPLIED: HeaderName = HeaderName::from_static("preference-applied");
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Return {
+    Minimal,
+    Representation,
+}
+
+/// The `return` preference of a request, if it states one it can have.
+fn return_preference(headers: &HeaderMap) -> Option<Return> {
+    headers
+        .get_all(PREFER)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|preference| {
+            // Parameters after `;` don't apply to `return`.
+            let preference = preference.split(';').next()?;
+            let (name, value) = preference.split_once('=')?;
+            if !name.trim().eq_ignore_ascii_case("return") {
+                return None;
+            }
+            match value.trim().trim_matches('"') {
+                v if v.eq_ignore_ascii_case("minimal") => Some(Return::Minimal),
+                v if v.eq_ignore_ascii_case("representation") => Some(Return::Representation),
+                _ => None,
+            }
+        })
+}
+
+/// Wraps `router` so writes honor `Prefer: return=...`.
+pub(crate) fn layer<S>(router: Router<S>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router.layer(middleware::from_fn(honor_prefer))
+}
+
+async fn honor_prefer(request: Request, next: Next) -> Response {
+    let write = matches!(
+        *request.method(),
+        Method::POST | Method::PUT | Method::PATCH
+    );
+    let preference = return_preference(request.headers()).filter(|_| write);
+    let response = next.run(request).await;
+    // Other answers, e.g. `202 Accepted` for background jobs, are kept.
+    let answered = matches!(response.status(), StatusCode::OK | StatusCode::CREATED);
+    let Some(preference) = preference.filter(|_| answered) else {
+        return response;
+    };
+    let (mut parts, body) = response.into_parts();
+    let (applied, body) = match preference {
+        Return::Minimal => {
+            parts.status = StatusCode::NO_CONTENT;
+            parts.headers.remove(CONTENT_TYPE);
+            parts.headers.remove(CONTENT_LENGTH);
+            ("return=minimal", Body::empty())
+        }
+        Return::Representation => ("return=representation", body),
+    };
+    parts
+        .headers
+        .insert(PREFERENCE_APPLIED, HeaderValue::from_static(applied));
+    Response::from_parts(parts, body)
+}
@@ -0,0 +1,83 @@
+//! Field selection on record reads: `?fields=title,author.name` returns only
+//! the named fields of the data, and of the related records expanded for a
+//! dotted field, to keep payloads small for mobile clients. Lists load only
+//! the selected fields from the database.
+
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::RecordResponse;
+
+/// The fields selected with `?fields=`.
+pub(crate) struct Projection {
+    /// Top-level fields of the data, including relations named in dotted
+    /// fields.
+    fields: Vec<String>,
+    /// The fields of expanded related records, by relation field.
+    related: BTreeMap<String, Vec<String>>,
+}
+
+impl Projection {
+    /// Parses a comma-separated `?fields=` list.
+    pub(crate) fn parse(list: &str) -> Projection {
+        let mut projection = Projection {
+            fields: Vec::new(),
+            related: BTreeMap::new(),
+        };
+        for field in list.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            let top = match field.split_once('.') {
+                Some((relation, inner)) => {
+                    projection
+                        .related
+                        .entry(relation.to_string())
+                        .or_default()
+                        .push(inner.to_string());
+                    relation
+                }
+                None => field,
+            };
+            if !projection.fields.iter().any(|f| f == top) {
+                projection.fields.push(top.to_string());
+            }
+        }
+        projection
+    }
+
+    /// The relations dotted fields pick from, which must be expanded.
+    pub(crate) fn relations(&self) -> impl Iterator<Item = &str> {
+        self.related.keys().map(String::as_str)
+    }
+
+    /// The top-level fields to load: the selected ones and those `expand`
+    /// needs to find the related records.
+    pub(crate) fn stored_fields(&self, expand: &[&str]) -> Vec<String> {
+        let mut fields = self.fields.clone();
+        for field in expand {
+            if !fields.iter().any(|f| f == field) {
+                fields.push(field.to_string());
+            }
+        }
+        fields
+    }
+
+    /// Drops the fields that weren't selected from a shaped response.
+    pub(crate) fn apply(&self, response: &mut RecordResponse) {
+        retain(&mut response.data, &self.fields);
+        let Some(expand) = &mut response.expand else {
+            return;
+        };
+        for (relation, fields) in &self.related {
+            if let Some(Value::Object(related)) = expand.get_mut(relation) {
+                if let Some(data) = related.get_mut("data") {
+                    retain(data, fields);
+                }
+            }
+        }
+    }
+}
+
+fn retain(data: &mut Value, fields: &[String]) {
+    if let Value::Object(map) = data {
+        map.retain(|key, _| fields.iter().any(|f| f == key));
+    }
+}
@@ -0,0 +1,272 @@
+//! Caching of record list and aggregate responses, for read-heavy public
+//! sites. Collections opt in with `"cache": { "ttl": 60 }` in their schema.
+//!
+//! A response is cached under its collection and normalized query string,
+//! along with the `Authorization` header and the format it was shaped for,
+//! so clients sending different `Accept` headers for the same JSON share a
+//! response, and tagged with the collections it read: its own, those its
+//! relations point at and those the rules of the request looked up. Reads
+//! whose rules look up a collection that doesn't exist aren't cached. Every
+//! event published for a collection, i.e. every write through
+//! the API, drops the responses tagged with it. The cache reads the event bus
+//! itself before each lookup, so no background task has to keep up with it.
+
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode, Uri},
+    response::Response,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tinybase_core::{
+    clock,
+    events::{Event, EventBus},
+    relations::relation_fields,
+    schema::CollectionSchema,
+};
+use tokio::sync::broadcast::{error::TryRecvError, Receiver};
+use utoipa::ToSchema;
+
+use crate::csv;
+
+/// Responses kept at most; the ones closest to expiring go first.
+const MAX_ENTRIES: usize = 1024;
+
+struct Entry {
+    tags: Vec<i64>,
+    expires: SystemTime,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+struct Entries {
+    events: Receiver<Event>,
+    responses: HashMap<String, Entry>,
+    /// Invalidations per collection, so a read can tell whether one came
+    /// between its start and its caching.
+    versions: HashMap<i64, u64>,
+    /// Bumped when events were missed and everything was dropped.
+    epoch: u64,
+}
+
+impl Entries {
+    /// Applies the events published since the last call.
+    fn catch_up(&mut self, invalidations: &AtomicU64) {
+        loop {
+            match self.events.try_recv() {
+                Ok(event) => {
+                    *self.versions.entry(event.collection_id).or_default() += 1;
+                    let before = self.responses.len();
+                    self.responses
+                        .retain(|_, entry| !entry.tags.contains(&event.collection_id));
+                    let dropped = (before - self.responses.len()) as u64;
+                    invalidations.fetch_add(dropped, Ordering::Relaxed);
+                }
+                Err(TryRecvError::Lagged(_)) => {
+                    self.epoch += 1;
+                    let dropped = self.responses.len() as u64;
+                    invalidations.fetch_add(dropped, Ordering::Relaxed);
+                    self.responses.clear();
+                }
+                Err(TryRecvError::Empty | TryRecvError::Closed) => return,
+            }
+        }
+    }
+
+    fn versions_of(&self, tags: &[i64]) -> Vec<u64> {
+        tags.iter()
+            .map(|tag| self.versions.get(tag).copied().unwrap_or_default())
+            .collect()
+    }
+}
+
+/// The cached list and aggregate responses of the instance.
+pub struct QueryCache {
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+/// Counters of the query cache.
+#[derive(Serialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueryCacheStats {
+    /// Reads answered from the cache.
+    pub hits: u64,
+    /// Reads of caching collections that ran their query.
+    pub misses: u64,
+    /// Cached responses dropped because their collections were written to.
+    pub invalidations: u64,
+    /// Responses cached now.
+    pub entries: u64,
+}
+
+/// What the cache holds for a read.
+pub(crate) enum Lookup {
+    /// The collection isn't cached.
+    Uncached,
+    Hit(Response),
+    /// No response yet; cache the one the read produces with
+    /// [`QueryCache::store`].
+    Miss(Miss),
+}
+
+/// A read the cache had no response for, to be cached once it ran.
+pub(crate) struct Miss {
+    key: String,
+    tags: Vec<i64>,
+    versions: Vec<u64>,
+    epoch: u64,
+    ttl: Duration,
+}
+
+impl QueryCache {
+    /// A cache invalidated by the events published on `events`.
+    pub fn new(events: &EventBus) -> Self {
+        Self {
+            entries: Mutex::new(Entries {
+                events: events.subscribe(),
+                responses: HashMap::new(),
+                versions: HashMap::new(),
+                epoch: 0,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            invalidations: AtomicU64::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> QueryCacheStats {
+        let mut entries = self.entries.lock().unwrap();
+        entries.catch_up(&self.invalidations);
+        QueryCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+            entries: entries.responses.len() as u64,
+        }
+    }
+
+    /// Looks up the response to a read of `collection_id` through `uri`,
+    /// which depends on the collections in `looked_up` as well, see
+    /// [`Access::looked_up`](crate::access::Access::looked_up).
+    pub(crate) fn lookup(
+        &self,
+        collection_id: i64,
+        schema: Option<&CollectionSchema>,
+        looked_up: Option<&[i64]>,
+        uri: &Uri,
+        headers: &HeaderMap,
+    ) -> Lookup {
+        let Some(settings) = schema.and_then(|s| s.cache.as_ref()) else {
+            return Lookup::Uncached;
+        };
+        let Some(looked_up) = looked_up else {
+            return Lookup::Uncached;
+        };
+        let key = key(collection_id, uri, headers);
+        let mut entries = self.entries.lock().unwrap();
+        entries.catch_up(&self.invalidations);
+        let now = clock::now();
+        match entries.responses.get(&key) {
+            Some(entry) if entry.expires > now => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                let mut response = Response::new(Body::from(entry.body.clone()));
+                *response.headers_mut() = entry.headers.clone();
+                return Lookup::Hit(response);
+            }
+            Some(_) => {
+                entries.responses.remove(&key);
+            }
+            None => {}
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let mut tags = vec![collection_id];
+        if let Some(schema) = schema {
+            tags.extend(relation_fields(schema).map(|(_, target, _)| target));
+        }
+        tags.extend(looked_up);
+        Lookup::Miss(Miss {
+            key,
+            versions: entries.versions_of(&tags),
+            tags,
+            epoch: entries.epoch,
+            ttl: Duration::from_secs(settings.ttl),
+        })
+    }
+
+    /// Caches a successful `response` to the read that missed, unless its
+    /// collections were written to since it started, and returns it.
+    pub(crate) async fn store(&self, miss: Miss, response: Response) -> Response {
+        if response.status() != StatusCode::OK {
+            return response;
+        }
+        let (parts, body) = response.into_parts();
+        let Ok(body) = to_bytes(body, usize::MAX).await else {
+            return Response::from_parts(parts, Body::empty());
+        };
+        {
+            let mut entries = self.entries.lock().unwrap();
+            entries.catch_up(&self.invalidations);
+            if entries.epoch == miss.epoch && entries.versions_of(&miss.tags) == miss.versions {
+                let now = clock::now();
+                if entries.responses.len() >= MAX_ENTRIES {
+                    entries.responses.retain(|_, entry| entry.expires > now);
+                }
+                if entries.responses.len() >= MAX_ENTRIES {
+                    let first = entries
+                        .responses
+                        .iter()
+                        .min_by_key(|(_, entry)| entry.expires)
+                        .map(|(key, _)| key.clone());
+                    if let Some(first) = first {
+                        entries.responses.remove(&first);
+                    }
+                }
+                entries.responses.insert(
+                    miss.key,
+                    Entry {
+                        tags: miss.tags,
+                        expires: now + miss.ttl,
+                        headers: parts.headers.clone(),
+                        body: body.clone(),
+                    },
+                );
+            }
+        }
+        Response::from_parts(parts, Body::from(body))
+    }
+}
+
+/// The cache key of a read: the collection, the path below it with its
+/// query parameters in a fixed order, the credentials and the format.
+fn key(collection_id: i64, uri: &Uri, headers: &HeaderMap) -> String {
+    let endpoint = uri.path().rsplit('/').next().unwrap_or_default();
+    let mut query: Vec<&str> = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .collect();
+    query.sort_unstable();
+    let authorization = headers
+        .get(AUTHORIZATION)
+        .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+        .unwrap_or_default();
+    let format = if csv::wants_csv(headers) {
+        "csv"
+    } else {
+        "json"
+    };
+    format!(
+        "{} {}?{} {} {}",
+        collection_id,
+        endpoint,
+        query.join("&"),
+        authorization,
+        format
+    )
+}
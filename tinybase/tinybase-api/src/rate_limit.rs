@@ -0,0 +1,62 @@
+//! Per-client rate limits.
+//!
+//! With a limit of `n` requests a minute, each client, told apart by its IP
+//! address, may make `n` requests in every minute of the clock; the rest are
+//! answered `429 Too Many Requests` with a `Retry-After` header until the
+//! next minute starts. Counts live in the shared store, so instances sharing
+//! one, e.g. Redis, share the limits too.
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header::RETRY_AFTER, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use tinybase_core::clock;
+use tinybase_storage::store::DistributedStore;
+
+use crate::AppError;
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Counts the request against the limit of its client, refusing it once the
+/// limit is reached. A store that can't count lets requests through.
+pub(crate) async fn limit(
+    State((store, per_minute)): State<(Arc<dyn DistributedStore>, u32)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let now = clock::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let window = WINDOW.as_secs();
+    let key = format!("rate:{}:{}", client, now / window);
+    match store.increment(&key, WINDOW).await {
+        Ok(count) if count > i64::from(per_minute) => {
+            let retry = window - now % window;
+            let mut response = AppError::RateLimited(format!(
+                "More than {} requests a minute, retry in {} seconds",
+                per_minute, retry
+            ))
+            .into_response();
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(retry));
+            response
+        }
+        Ok(_) => next.run(request).await,
+        Err(e) => {
+            tracing::warn!(error = %e, "failed to count request against rate limit");
+            next.run(request).await
+        }
+    }
+}
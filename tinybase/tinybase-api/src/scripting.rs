@@ -0,0 +1,456 @@
+//! Script hooks: Lua chunks admins register per collection, run on record
+//! writes through the records API.
+//!
+//! A hook sees the data being written as the global table `record`, and for
+//! `beforeUpdate` the stored data as `previous`; `collection` names the
+//! collection and `id` is the id of the record, `nil` before it is created.
+//! `beforeCreate` and `beforeUpdate` hooks may change `record` in place or
+//! return a table to write instead, and refuse the write with
+//! `reject(message)`, which answers `422`. `afterCreate` hooks run once the
+//! record is stored and can't change or undo the write. Hooks of the same
+//! collection and event run in the order they were added, each seeing the
+//! data left by the one before.
+//!
+//! Scripts reach other collections through the `tinybase` table:
+//! `tinybase.get(collection, id)`, `tinybase.find(collection, field, value)`
+//! and `tinybase.create(collection, data)`, which validates the data but
+//! runs no hooks. `tinybase.kv.get(namespace, key)`,
+//! `tinybase.kv.set(namespace, key, value, ttl)` and
+//! `tinybase.kv.delete(namespace, key)` work on the key-value store, `get`
+//! giving the value or `nil`. `log(message)` writes to the server log. Scripts have no
+//! access to files, the OS or the network, and are stopped once they run
+//! longer than [`SCRIPT_TIME_LIMIT`] or use more than [`SCRIPT_MEMORY_LIMIT`].
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use mlua::{HookTriggers, Lua, LuaOptions, LuaSerdeExt, StdLib};
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tinybase_core::{
+    clock,
+    embedded::{prepare_record, Write},
+    events::{Event, EventAction},
+    lineage::{with_lineage, Lineage, LineageSource},
+    scripts::{HookEvent, ScriptHook, ScriptHookSettings},
+    Collection, Record,
+};
+use tokio::runtime::Handle;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::{
+    auth::RequireAdmin, db_error, kv::set_entry, resolve_collection, AppError, AppState,
+    ProblemDetail, RecordResponse,
+};
+
+/// How long one hook may run, its calls to other collections included.
+pub const SCRIPT_TIME_LIMIT: Duration = Duration::from_secs(1);
+
+/// Memory one hook may allocate.
+pub const SCRIPT_MEMORY_LIMIT: usize = 16 * 1024 * 1024;
+
+/// Lua instructions run between checks of the time limit.
+const CHECK_EVERY: u32 = 1000;
+
+/// A write refused by a hook through `reject`.
+#[derive(Debug)]
+struct Rejection(String);
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+/// The message of the `reject` call that `e` comes from, if it does.
+fn rejection(e: &mlua::Error) -> Option<&str> {
+    match e {
+        mlua::Error::CallbackError { cause, .. } => rejection(cause),
+        mlua::Error::ExternalError(e) => e.downcast_ref::<Rejection>().map(|r| r.0.as_str()),
+        _ => None,
+    }
+}
+
+/// Hands an API error to the script as a Lua error.
+fn script_error(e: AppError) -> mlua::Error {
+    let (_, problem) = e.into_problem();
+    match problem.details {
+        Some(details) => mlua::Error::external(format!("{} {}", problem.message, details)),
+        None => mlua::Error::external(problem.message),
+    }
+}
+
+fn record_value(record: Record) -> Value {
+    serde_json::to_value(RecordResponse::from(record)).unwrap_or_default()
+}
+
+/// Creates a record for a script, validated as through the API.
+async fn create(state: &AppState, key: &str, mut data: Value) -> Result<Value, AppError> {
+    let db = &state.db;
+    let id = resolve_collection(db.as_ref(), key).await?;
+    let collection = db
+        .get_collection(id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", id)))?;
+    if let Some(schema) = &collection.schema {
+        prepare_record(db.as_ref(), schema, &mut data, Write::Create).await?;
+    }
+    let lineage = Lineage::new(LineageSource::Hook);
+    let record = with_lineage(lineage, db.create_record(id, &data))
+        .await
+        .map_err(db_error)?;
+    state
+        .events
+        .publish(Event::new(EventAction::RecordCreated, id, Some(record.id)));
+    Ok(record_value(record))
+}
+
+async fn get(state: &AppState, key: &str, record_id: i64) -> Result<Value, AppError> {
+    let db = &state.db;
+    let id = resolve_collection(db.as_ref(), key).await?;
+    let record = db.get_record(id, record_id).await.map_err(db_error)?;
+    Ok(record.map(record_value).unwrap_or(Value::Null))
+}
+
+async fn find(state: &AppState, key: &str, field: &str, value: &Value) -> Result<Value, AppError> {
+    let db = &state.db;
+    let id = resolve_collection(db.as_ref(), key).await?;
+    let records = db
+        .find_records_by_field(id, field, value)
+        .await
+        .map_err(db_error)?;
+    Ok(Value::Array(
+        records.into_iter().map(record_value).collect(),
+    ))
+}
+
+/// Runs `future` for a script, on the runtime and clock of the request.
+fn block_on<T>(
+    handle: &Handle,
+    state: &AppState,
+    future: impl Future<Output = Result<T, AppError>>,
+) -> mlua::Result<T> {
+    handle
+        .block_on(clock::with_clock(state.clock.clone(), future))
+        .map_err(script_error)
+}
+
+/// A Lua state with the safe standard libraries, the limits and the
+/// functions scripts are given.
+fn sandbox(state: &AppState, handle: &Handle) -> mlua::Result<Lua> {
+    let lua = Lua::new_with(
+        StdLib::TABLE | StdLib::STRING | StdLib::MATH | StdLib::UTF8,
+        LuaOptions::default(),
+    )?;
+    lua.set_memory_limit(SCRIPT_MEMORY_LIMIT)?;
+    let started = Instant::now();
+    lua.set_hook(
+        HookTriggers::new().every_nth_instruction(CHECK_EVERY),
+        move |_, _| {
+            if started.elapsed() > SCRIPT_TIME_LIMIT {
+                return Err(mlua::Error::runtime(format!(
+                    "The script ran longer than {:?}",
+                    SCRIPT_TIME_LIMIT
+                )));
+            }
+            Ok(())
+        },
+    );
+    let globals = lua.globals();
+    for unsafe_global in ["dofile", "loadfile", "load", "print"] {
+        globals.set(unsafe_global, mlua::Nil)?;
+    }
+    globals.set(
+        "reject",
+        lua.create_function(|_, message: String| -> mlua::Result<()> {
+            Err(mlua::Error::external(Rejection(message)))
+        })?,
+    )?;
+    globals.set(
+        "log",
+        lua.create_function(|_, message: String| {
+            tracing::info!(target: "tinybase_api::scripting", "{}", message);
+            Ok(())
+        })?,
+    )?;
+
+    let tinybase = lua.create_table()?;
+    let (s, h) = (state.clone(), handle.clone());
+    tinybase.set(
+        "get",
+        lua.create_function(move |lua, (key, id): (String, i64)| {
+            lua.to_value(&block_on(&h, &s, get(&s, &key, id))?)
+        })?,
+    )?;
+    let (s, h) = (state.clone(), handle.clone());
+    tinybase.set(
+        "find",
+        lua.create_function(
+            move |lua, (key, field, value): (String, String, mlua::Value)| {
+                let value: Value = lua.from_value(value)?;
+                lua.to_value(&block_on(&h, &s, find(&s, &key, &field, &value))?)
+            },
+        )?,
+    )?;
+    let (s, h) = (state.clone(), handle.clone());
+    tinybase.set(
+        "create",
+        lua.create_function(move |lua, (key, data): (String, mlua::Value)| {
+            let data: Value = lua.from_value(data)?;
+            lua.to_value(&block_on(&h, &s, create(&s, &key, data))?)
+        })?,
+    )?;
+    let kv = lua.create_table()?;
+    let (s, h) = (state.clone(), handle.clone());
+    kv.set(
+        "get",
+        lua.create_function(move |lua, (namespace, key): (String, String)| {
+            let entry = block_on(&h, &s, async {
+                s.db.get_kv(&namespace, &key).await.map_err(db_error)
+            })?;
+            match entry {
+                Some(entry) => lua.to_value(&entry.value),
+                None => Ok(mlua::Nil),
+            }
+        })?,
+    )?;
+    let (s, h) = (state.clone(), handle.clone());
+    kv.set(
+        "set",
+        lua.create_function(
+            move |lua, (namespace, key, value, ttl): (String, String, mlua::Value, Option<u64>)| {
+                let value: Value = lua.from_value(value)?;
+                block_on(&h, &s, set_entry(&s, &namespace, &key, &value, ttl))?;
+                Ok(())
+            },
+        )?,
+    )?;
+    let (s, h) = (state.clone(), handle.clone());
+    kv.set(
+        "delete",
+        lua.create_function(move |_, (namespace, key): (String, String)| {
+            block_on(&h, &s, async {
+                Ok(s.db.delete_kv(&namespace, &key).await?)
+            })
+        })?,
+    )?;
+    tinybase.set("kv", kv)?;
+    globals.set("tinybase", tinybase)?;
+    drop(globals);
+    Ok(lua)
+}
+
+/// Runs `source` on `data`, returning the data the script leaves.
+fn run(
+    state: &AppState,
+    handle: &Handle,
+    source: &str,
+    collection: &str,
+    record_id: Option<i64>,
+    data: &Value,
+    previous: Option<&Value>,
+) -> mlua::Result<Value> {
+    let lua = sandbox(state, handle)?;
+    let globals = lua.globals();
+    let record = lua.to_value(data)?;
+    globals.set("record", record.clone())?;
+    globals.set("previous", lua.to_value(&previous)?)?;
+    globals.set("collection", collection)?;
+    globals.set("id", record_id)?;
+    let returned: mlua::Value = lua.load(source).set_name("hook").call(())?;
+    let result = if returned.is_nil() { record } else { returned };
+    lua.from_value(result)
+}
+
+/// Runs the hooks of `collection` registered for `event` on `data`, in
+/// order, each on the data left by the one before.
+pub(crate) async fn run_hooks(
+    state: &AppState,
+    collection: &Collection,
+    event: HookEvent,
+    record_id: Option<i64>,
+    data: &mut Value,
+    previous: Option<&Value>,
+) -> Result<(), AppError> {
+    let hooks: Vec<ScriptHook> = state
+        .db
+        .list_script_hooks()
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .filter(|h| h.settings.runs(event, collection.id))
+        .collect();
+    for hook in hooks {
+        let (state, handle) = (state.clone(), Handle::current());
+        let name = collection.name.clone();
+        let input = data.clone();
+        let previous = previous.cloned();
+        let result = tokio::task::spawn_blocking(move || {
+            run(
+                &state,
+                &handle,
+                &hook.settings.source,
+                &name,
+                record_id,
+                &input,
+                previous.as_ref(),
+            )
+        })
+        .await
+        .map_err(|e| AppError::UnknownError(e.to_string()))?;
+        match result {
+            Ok(output) => *data = output,
+            Err(e) => {
+                if let Some(message) = rejection(&e) {
+                    return Err(AppError::Rejected(message.to_string()));
+                }
+                return Err(AppError::UnknownError(format!(
+                    "The {} hook {} failed: {}",
+                    event.name(),
+                    hook.id,
+                    e
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs the `afterCreate` hooks of `collection` on a stored record. The
+/// write stands either way, so failures are only logged.
+pub(crate) async fn after_create(state: &AppState, collection: &Collection, record: &Record) {
+    let mut data = record.data.clone();
+    if let Err(e) = run_hooks(
+        state,
+        collection,
+        HookEvent::AfterCreate,
+        Some(record.id),
+        &mut data,
+        None,
+    )
+    .await
+    {
+        let (_, problem) = e.into_problem();
+        tracing::warn!(
+            collection = collection.id,
+            record = record.id,
+            error = problem.message,
+            "afterCreate hook failed"
+        );
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ScriptHookResponse {
+    id: i64,
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    settings: ScriptHookSettings,
+    created: String,
+    updated: String,
+}
+
+impl From<ScriptHook> for ScriptHookResponse {
+    fn from(h: ScriptHook) -> Self {
+        ScriptHookResponse {
+            id: h.id,
+            settings: h.settings,
+            created: h.created,
+            updated: h.updated,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/hooks",
+    responses(
+        (status = 200, description = "List the script hooks, in the order they run", body = Vec<ScriptHookResponse>),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn list_hooks(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ScriptHookResponse>>, AppError> {
+    let hooks = state.db.list_script_hooks().await.map_err(db_error)?;
+    Ok(Json(hooks.into_iter().map(Into::into).collect()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/hooks",
+    request_body(content = Object, description = "The `collection_id` to run on, the `event` to run at (`beforeCreate`, `afterCreate` or `beforeUpdate`) and the Lua `source` to run"),
+    responses(
+        (status = 201, description = "Add a script hook", body = ScriptHookResponse),
+        (status = 400, description = "The source does not compile", body = ProblemDetail),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Collection not found", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn create_hook(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Json(settings): Json<ScriptHookSettings>,
+) -> Result<(StatusCode, Json<ScriptHookResponse>), AppError> {
+    Lua::new()
+        .load(&settings.source)
+        .set_name("hook")
+        .into_function()
+        .map_err(|e| AppError::BadRequest(format!("The script does not compile: {}", e)))?;
+    state
+        .db
+        .get_collection(settings.collection_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| {
+            AppError::NotFound(format!("Collection {} not found", settings.collection_id))
+        })?;
+    let hook = state
+        .db
+        .create_script_hook(&settings)
+        .await
+        .map_err(db_error)?;
+    Ok((StatusCode::CREATED, Json(hook.into())))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/hooks/{id}",
+    params(
+        ("id" = i64, Path, description = "Script hook id")
+    ),
+    responses(
+        (status = 204, description = "Remove a script hook"),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Script hook not found", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn delete_hook(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, AppError> {
+    let db = &state.db;
+    db.get_script_hook(id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| AppError::NotFound(format!("Script hook {} not found", id)))?;
+    db.delete_script_hook(id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(list_hooks, create_hook, delete_hook),
+    components(schemas(ScriptHookResponse, ProblemDetail))
+)]
+pub(crate) struct ScriptingApiDoc;
@@ -0,0 +1,302 @@
+//! Full-text search of records through the [`SearchIndex`] of the instance:
+//! the built-in FTS5 one, or the configured `search` backend.
+//!
+//! The index follows the event bus. A task started by [`spawn`] indexes
+//! records as they are written, drops them when they are deleted, and
+//! reindexes a collection whose schema changed, so search results may lag a
+//! write by a moment. `POST /api/v1/admin/search/reindex` rebuilds the whole
+//! index, e.g. after switching backends.
+
+use axum::{
+    extract::{Path, Query, State},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+use tinybase_core::{
+    clock,
+    events::{Event, EventAction},
+    search::{self, SearchIndex},
+    stream_records,
+};
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    access::Access,
+    auth::RequireAdmin,
+    config::Config,
+    db_error,
+    jobs::{accepted, spawn_job},
+    resolve_collection,
+    shape::shape_records,
+    AppError, AppState, RecordResponse,
+};
+
+type BoxResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Records a search returns unless it asks for another number.
+const DEFAULT_LIMIT: usize = 20;
+/// Most records a search returns.
+const MAX_LIMIT: usize = 100;
+
+/// Where searchable collections are indexed.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchBackend {
+    /// The built-in FTS5 table in the database.
+    #[default]
+    Fts5,
+    /// An embedded Tantivy index, with the `tantivy` feature.
+    Tantivy,
+    /// A Meilisearch server, with the `meilisearch` feature.
+    Meilisearch,
+}
+
+/// The index of the configured `search` backend, or `None` for the
+/// built-in one: `tantivy` indexes into `search_dir`, `meilisearch` uses the
+/// server at `meilisearch_url` with `meilisearch_key`.
+pub fn backend(config: &Config) -> Result<Option<Arc<dyn SearchIndex>>, String> {
+    match config.search {
+        SearchBackend::Fts5 => Ok(None),
+        #[cfg(feature = "tantivy")]
+        SearchBackend::Tantivy => {
+            let dir = config
+                .search_dir
+                .as_ref()
+                .ok_or("search = \"tantivy\" needs a search_dir")?;
+            let index = crate::tantivy_search::TantivyIndex::open(dir)
+                .map_err(|e| format!("Failed to open the Tantivy index: {}", e))?;
+            Ok(Some(Arc::new(index)))
+        }
+        #[cfg(feature = "meilisearch")]
+        SearchBackend::Meilisearch => {
+            let url = config
+                .meilisearch_url
+                .clone()
+                .ok_or("search = \"meilisearch\" needs a meilisearch_url")?;
+            Ok(Some(Arc::new(crate::meilisearch::MeilisearchIndex::new(
+                url,
+                config.meilisearch_key.clone(),
+            ))))
+        }
+        #[allow(unreachable_patterns)]
+        backend => {
+            let name = format!("{:?}", backend).to_lowercase();
+            Err(format!(
+                "The {} search backend is configured, but this build lacks the {} feature",
+                name, name
+            ))
+        }
+    }
+}
+
+/// Brings the index of the record an event is about up to date.
+async fn apply(state: &AppState, event: &Event) -> BoxResult<()> {
+    let index = &state.search;
+    match (event.action, event.record_id) {
+        (EventAction::RecordCreated | EventAction::RecordUpdated, Some(record_id)) => {
+            index_record(state, event.collection_id, record_id).await
+        }
+        (EventAction::RecordDeleted, Some(record_id)) => {
+            index.remove(event.collection_id, record_id).await
+        }
+        (EventAction::CollectionUpdated, _) => {
+            reindex(state, event.collection_id).await?;
+            Ok(())
+        }
+        (EventAction::CollectionDeleted, _) => index.clear(event.collection_id).await,
+        _ => Ok(()),
+    }
+}
+
+async fn index_record(state: &AppState, collection_id: i64, record_id: i64) -> BoxResult<()> {
+    let db = &state.db;
+    let Some(schema) = db
+        .get_collection(collection_id)
+        .await?
+        .and_then(|c| c.schema)
+    else {
+        return Ok(());
+    };
+    let record = db.get_record(collection_id, record_id).await?;
+    let document = record.and_then(|r| search::document(&schema, &r.data));
+    match document {
+        Some(document) => {
+            state
+                .search
+                .index(collection_id, record_id, &document)
+                .await
+        }
+        None => state.search.remove(collection_id, record_id).await,
+    }
+}
+
+/// Rebuilds the index of a collection from its records, returning how many
+/// were indexed.
+async fn reindex(state: &AppState, collection_id: i64) -> BoxResult<u64> {
+    state.search.clear(collection_id).await?;
+    let Some(schema) = state
+        .db
+        .get_collection(collection_id)
+        .await?
+        .and_then(|c| c.schema)
+        .filter(|s| s.search.is_some())
+    else {
+        return Ok(0);
+    };
+    let mut records = stream_records(state.db.clone(), collection_id);
+    let mut indexed = 0;
+    while let Some(record) = records.try_next().await? {
+        if let Some(document) = search::document(&schema, &record.data) {
+            state
+                .search
+                .index(collection_id, record.id, &document)
+                .await?;
+            indexed += 1;
+        }
+    }
+    Ok(indexed)
+}
+
+/// Reindexes every searchable collection, returning how many collections
+/// and records were indexed.
+async fn reindex_all(state: &AppState) -> BoxResult<(u64, u64)> {
+    let collections = state.db.list_collections().await?;
+    let (mut searchable, mut records) = (0, 0);
+    for collection in collections {
+        if collection.schema.is_some_and(|s| s.search.is_some()) {
+            searchable += 1;
+            records += reindex(state, collection.id).await?;
+        }
+    }
+    Ok((searchable, records))
+}
+
+/// Starts keeping the search index in line with the records, in the
+/// background. Missed events rebuild the whole index; those queued when
+/// the server shuts down are still applied.
+pub fn spawn(state: AppState) {
+    let mut events = state.events.subscribe();
+    let shutdown = state.shutdown.clone();
+    shutdown.spawn(clock::with_clock(state.clock.clone(), async move {
+        loop {
+            let result = tokio::select! {
+                biased;
+                event = events.recv() => match event {
+                    Ok(event) => apply(&state, &event).await,
+                    Err(RecvError::Lagged(_)) => reindex_all(&state).await.map(drop),
+                    Err(RecvError::Closed) => break,
+                },
+                _ = state.shutdown.flushing() => break,
+            };
+            if let Err(e) = result {
+                tracing::error!(backend = state.search.name(), error = %e, "failed to update the search index");
+            }
+        }
+    }));
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct SearchQuery {
+    /// The words to look for.
+    q: String,
+    /// Most records to return (default 20, at most 100).
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SearchResult {
+    #[serde(flatten)]
+    record: RecordResponse,
+    /// How well the record matches, higher is better.
+    score: f64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/collections/{id}/records/search",
+    params(
+        ("id" = String, Path, description = "Collection id or name"),
+        SearchQuery
+    ),
+    responses(
+        (status = 200, description = "The records matching a full-text search, best match first", body = Vec<SearchResult>),
+        (status = 400, description = "The collection declares no search fields", body = ProblemDetail),
+        (status = 404, description = "Collection not found", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn search_records(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<SearchResult>>, AppError> {
+    let db = &state.db;
+    let collection_id = resolve_collection(db.as_ref(), &key).await?;
+    let collection = db
+        .get_collection(collection_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", collection_id)))?;
+    let access = Access::to(&state, &collection).await?;
+    if collection.schema.is_none_or(|s| s.search.is_none()) {
+        return Err(AppError::BadRequest(format!(
+            "Collection {} is not searchable; list the fields to search in its schema's search.fields",
+            collection.name
+        )));
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let hits = state
+        .search
+        .search(collection_id, &query.q, limit)
+        .await
+        .map_err(db_error)?;
+    let mut results = Vec::with_capacity(hits.len());
+    for hit in hits {
+        // The index may still hold a record deleted a moment ago.
+        if let Some(record) = db
+            .get_record(collection_id, hit.record_id)
+            .await
+            .map_err(db_error)?
+            .filter(|r| access.sees(r))
+        {
+            results.push(SearchResult {
+                record: record.into(),
+                score: hit.score,
+            });
+        }
+    }
+    shape_records(
+        &state,
+        collection_id,
+        results.iter_mut().map(|r| &mut r.record),
+    )
+    .await?;
+    Ok(Json(results))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/search/reindex",
+    responses(
+        (status = 202, description = "Rebuild the search index of every searchable collection in the background, as a job found at the `Location`", body = JobResponse),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn reindex_search(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+) -> Result<Response, AppError> {
+    let job = spawn_job(&state, "reindex", json!({}), {
+        let state = state.clone();
+        move |_| async move {
+            let (collections, records) = reindex_all(&state).await.map_err(db_error)?;
+            Ok(json!({ "collections": collections, "records": records }))
+        }
+    })
+    .await?;
+    Ok(accepted(job).into_response())
+}
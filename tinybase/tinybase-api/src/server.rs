@@ -0,0 +1,534 @@
+//! Running the server as the `tinybase-api` binary does: the configured
+//! database, replicas, backups and optional subsystems, then the router
+//! behind a listener.
+//!
+//! The listener is the socket systemd passes in when the server is started
+//! by socket activation, or else `addr` bound. With `admin_addr` set, or a
+//! second socket passed in, the admin API, dashboard and metrics are served
+//! on a listener of their own and answer 404 on the public one. Embedders
+//! and test harnesses running several instances at once can bind port 0,
+//! e.g. `127.0.0.1:0`, and learn the port the system picked from
+//! [`Server::listen`]:
+//!
+//! ```no_run
+//! # async fn example(config: tinybase_api::config::Config) -> Result<(), String> {
+//! use tinybase_api::server::Server;
+//!
+//! let server = Server::open(config).await?;
+//! let listeners = server.listen().await?;
+//! let addr = listeners.api.local_addr().map_err(|e| e.to_string())?;
+//! let shutdown = server.shutdown();
+//! let serving = tokio::spawn(server.serve(listeners, Vec::new()));
+//! println!("serving on {}", addr);
+//! shutdown.trigger();
+//! serving.await.map_err(|e| e.to_string())??;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The server stops gracefully when its [`Shutdown`] is triggered, which
+//! [`Server::run`] does on `SIGTERM` or `SIGINT`; see [`shutdown`](crate::shutdown).
+
+use axum::{
+    extract::Request,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    serve, Router,
+};
+use libsql::Database;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tinybase_core::{
+    a_new_database_connection,
+    pool::ConnectionPool,
+    read_replicas::ReadReplicas,
+    replica::{ReplicaConfig, ReplicaSync},
+    snapshot::{self, SnapshotVerifier},
+    views::AttachedDatabases,
+    Db,
+};
+use tinybase_storage::LocalStorage;
+use tokio::net::TcpListener;
+use tokio::time::Instant;
+
+#[cfg(feature = "jwt")]
+use crate::jwt;
+#[cfg(feature = "manifest")]
+use crate::manifest::{self, Manifest};
+use crate::{
+    access_log::{self, AccessLog},
+    app_router,
+    config::Config,
+    fixtures, meta,
+    namespaces::{self, Namespaces},
+    plugin::Tinybase,
+    shutdown::{self, Shutdown},
+    warmup, AppError, AppState,
+};
+
+/// The sockets a [`Server`] serves on.
+pub struct Listeners {
+    /// Serves the API, without the admin surface when `admin` is set.
+    pub api: TcpListener,
+    /// Serves everything, the admin API, dashboard and metrics included.
+    pub admin: Option<TcpListener>,
+}
+
+/// See [`Server::listen`].
+async fn bind(config: &Config) -> Result<Listeners, String> {
+    let bind = |addr: &str| {
+        let addr = addr.to_string();
+        async move {
+            TcpListener::bind(&addr)
+                .await
+                .map_err(|e| format!("Failed to bind to {}: {}", addr, e))
+        }
+    };
+    let api = match activated_listener(0)? {
+        Some(listener) => listener,
+        None => bind(&config.addr).await?,
+    };
+    let admin = match (activated_listener(1)?, &config.admin_addr) {
+        (Some(listener), _) => Some(listener),
+        (None, Some(addr)) => Some(bind(addr).await?),
+        (None, None) => None,
+    };
+    Ok(Listeners { api, admin })
+}
+
+/// The socket at `index` of those systemd passed in when it started the
+/// server by socket activation: `LISTEN_PID` names this process and
+/// `LISTEN_FDS` counts the sockets, the first of which is file descriptor 3.
+#[cfg(unix)]
+fn activated_listener(index: u32) -> Result<Option<TcpListener>, String> {
+    use std::os::fd::FromRawFd;
+
+    const SD_LISTEN_FDS_START: u32 = 3;
+    let var = |name| std::env::var(name).ok().and_then(|v| v.parse::<u32>().ok());
+    if var("LISTEN_PID") != Some(std::process::id()) || var("LISTEN_FDS").unwrap_or(0) <= index {
+        return Ok(None);
+    }
+    // SAFETY: systemd hands this process its listening sockets as the
+    // descriptors from 3 on, which nothing else in the process owns.
+    let listener =
+        unsafe { std::net::TcpListener::from_raw_fd((SD_LISTEN_FDS_START + index) as i32) };
+    listener
+        .set_nonblocking(true)
+        .and_then(|()| TcpListener::from_std(listener))
+        .map(Some)
+        .map_err(|e| format!("Failed to use the socket passed by systemd: {}", e))
+}
+
+#[cfg(not(unix))]
+fn activated_listener(_index: u32) -> Result<Option<TcpListener>, String> {
+    Ok(None)
+}
+
+/// Whether `path` belongs to the admin surface: the admin API and metrics
+/// under `/api/v1/admin`, in any namespace, and the dashboard under `/admin`.
+fn is_admin_path(path: &str) -> bool {
+    let path = match path.strip_prefix("/api/v1") {
+        Some(rest) => match rest.strip_prefix("/ns/") {
+            Some(scoped) => scoped.find('/').map_or("", |i| &scoped[i..]),
+            None => rest,
+        },
+        None => path,
+    };
+    path == "/admin" || path.starts_with("/admin/")
+}
+
+/// Answers requests for the admin surface as if it did not exist.
+async fn hide_admin(request: Request, next: Next) -> Response {
+    if is_admin_path(request.uri().path()) {
+        return AppError::NotFound("Not found".to_string()).into_response();
+    }
+    next.run(request).await
+}
+
+/// Serves `app` on `listener` until it fails, or until `shutdown` is
+/// triggered and the connections open then are done.
+async fn serve_on(listener: TcpListener, app: Router, shutdown: Shutdown) -> Result<(), String> {
+    serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(async move { shutdown.triggered().await })
+    .await
+    .map_err(|e| format!("Server error: {}", e))
+}
+
+/// Waits for `served`, the listeners serving until `shutdown`, then for the
+/// queued deliveries, and closes `pool`, giving it all `timeout` from the
+/// trigger.
+async fn drain(
+    served: impl std::future::Future<Output = Result<(), String>>,
+    shutdown: &Shutdown,
+    timeout: Duration,
+    pool: &ConnectionPool,
+) -> Result<(), String> {
+    tokio::pin!(served);
+    let deadline = tokio::select! {
+        result = &mut served => {
+            result?;
+            Instant::now() + timeout
+        }
+        deadline = async {
+            shutdown.triggered().await;
+            Instant::now() + timeout
+        } => {
+            match tokio::time::timeout_at(deadline, &mut served).await {
+                Ok(result) => result?,
+                Err(_) => tracing::warn!("requests still running at the drain timeout were dropped"),
+            }
+            deadline
+        }
+    };
+    if !shutdown.flush(deadline).await {
+        tracing::warn!("deliveries still running at the drain timeout were dropped");
+    }
+    match tokio::time::timeout_at(deadline, pool.close()).await {
+        Ok(result) => result.map_err(|e| format!("Failed to close the database: {}", e)),
+        Err(_) => {
+            tracing::warn!("the database was still in use at the drain timeout");
+            Ok(())
+        }
+    }
+}
+
+/// The outcome of the jobs a previous run left unfinished.
+fn interrupted() -> serde_json::Value {
+    serde_json::json!({
+        "error": "interrupted",
+        "message": "The server stopped before the job finished.",
+        "status": 500,
+    })
+}
+
+/// A configured instance with its database open, not serving yet.
+pub struct Server {
+    config: Config,
+    db: Arc<Database>,
+    replica: Option<ReplicaConfig>,
+    shutdown: Shutdown,
+}
+
+impl Server {
+    /// Opens the configured database, creating or upgrading its tables, and
+    /// fails the jobs a previous run left unfinished.
+    pub async fn open(config: Config) -> Result<Self, String> {
+        // TINYBASE_REPLICA_URL runs on an embedded replica of a remote libsql
+        // database, e.g. Turso, instead of a local file.
+        let replica = ReplicaConfig::from_env(&config.db_path);
+        let db = match &replica {
+            Some(config) => config.open().await,
+            None => a_new_database_connection(&config.db_path).await,
+        };
+        let db = Arc::new(db.map_err(|e| format!("Failed to connect to database: {}", e))?);
+
+        // Jobs a previous run left unfinished will not finish now.
+        db.fail_unfinished_jobs(&interrupted())
+            .await
+            .map_err(|e| format!("Failed to mark unfinished jobs as failed: {}", e))?;
+        Ok(Server {
+            config,
+            db,
+            replica,
+            shutdown: Shutdown::new(),
+        })
+    }
+
+    pub fn db(&self) -> &Arc<Database> {
+        &self.db
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// A handle stopping the server once it serves: see [`Shutdown::trigger`].
+    pub fn shutdown(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    /// The listeners to serve on: the sockets systemd passed in, if any, or
+    /// the configured `addr` and `admin_addr` bound. Their `local_addr` is
+    /// the address served, with the actual port when asked for port 0.
+    pub async fn listen(&self) -> Result<Listeners, String> {
+        bind(&self.config).await
+    }
+
+    /// Serves the API until the listener fails or the process is sent
+    /// `SIGTERM` or `SIGINT`. `args` go to the commands of plugins; when one
+    /// handles them it runs instead of the server.
+    pub async fn run(self, args: &[String]) -> Result<(), String> {
+        let shutdown = self.shutdown();
+        tokio::spawn(async move {
+            shutdown::signal().await;
+            tracing::info!("shutting down");
+            shutdown.trigger();
+        });
+        self.start(None, args).await
+    }
+
+    /// Serves the API on `listeners`, e.g. those from [`Server::listen`], as
+    /// [`Server::run`] does, until the listener fails or its
+    /// [`shutdown`](Server::shutdown) is triggered. Takes its arguments by
+    /// value so it can be spawned.
+    pub async fn serve(self, listeners: Listeners, args: Vec<String>) -> Result<(), String> {
+        self.start(Some(listeners), &args).await
+    }
+
+    /// Serves the API on `listeners`, or on [`Server::listen`] once
+    /// everything else is set up.
+    async fn start(self, listeners: Option<Listeners>, args: &[String]) -> Result<(), String> {
+        let Server {
+            config,
+            db,
+            replica,
+            shutdown,
+        } = self;
+
+        // Collections, indexes and webhooks declared in the manifest file.
+        #[cfg(feature = "manifest")]
+        if let Some(path) = &config.manifest {
+            let plan = match Manifest::load(path) {
+                Ok(manifest) => manifest::reconcile(db.as_ref(), &manifest, true).await,
+                Err(e) => Err(e.into()),
+            };
+            let plan =
+                plan.map_err(|e| format!("Failed to reconcile {}: {}", path.display(), e))?;
+            if config.logs("info") {
+                for line in plan.lines() {
+                    println!("{}", line);
+                }
+            }
+        }
+        #[cfg(not(feature = "manifest"))]
+        if config.manifest.is_some() {
+            return Err(
+                "A manifest is configured, but this build lacks the manifest feature".to_string(),
+            );
+        }
+
+        let storage = LocalStorage::new(&config.uploads_dir);
+
+        // Read-only reference databases, e.g. attach = { postcodes = "data/postcodes.db" }
+        let mut views = AttachedDatabases::new();
+        for (alias, path) in &config.attach {
+            views
+                .attach(alias, path)
+                .await
+                .map_err(|e| format!("Failed to attach database {}: {}", alias, e))?;
+        }
+
+        let conn = db
+            .connect()
+            .map_err(|e| format!("Failed to connect to database: {}", e))?;
+        let pool = ConnectionPool::new(db.clone(), config.db_pool_size)
+            .await
+            .map_err(|e| format!("Failed to open the connection pool: {}", e))?;
+        let pool = Arc::new(pool);
+        let state = AppState::new(pool.clone(), Arc::new(storage))
+            .with_views(views)
+            .with_config(&config)
+            .with_shutdown(shutdown.clone());
+        let state = match &replica {
+            Some(config) => {
+                let replica = ReplicaSync::new(db, config);
+                replica.clone().spawn();
+                state.with_replica(replica)
+            }
+            None => state,
+        };
+
+        // TINYBASE_READ_REPLICAS=eu=libsql://...,us=libsql://... serves reads
+        // from the fastest healthy replica.
+        let state = match ReadReplicas::from_env(state.db.clone()).await? {
+            Some(replicas) => {
+                replicas.clone().spawn();
+                state.with_read_replicas(replicas)
+            }
+            None => state,
+        };
+
+        // TINYBASE_JWT_ISSUER and TINYBASE_JWKS_URL trust the tokens of an
+        // identity provider such as Auth0 or Keycloak.
+        #[cfg(feature = "jwt")]
+        let state = match jwt::JwtSettings::from_env()? {
+            Some(settings) => state.with_jwt(jwt::JwtVerifier::new(settings)),
+            None => state,
+        };
+
+        // Share rate limits, sessions and idempotency keys between instances.
+        #[cfg(feature = "redis")]
+        let state = match &config.redis_url {
+            Some(url) => {
+                let store = tinybase_storage::store::RedisStore::connect(url, "tinybase:")
+                    .await
+                    .map_err(|e| format!("Failed to connect to Redis: {}", e))?;
+                state.with_store(Arc::new(store))
+            }
+            None => state,
+        };
+        #[cfg(not(feature = "redis"))]
+        if config.redis_url.is_some() {
+            return Err(
+                "A Redis URL is configured, but this build lacks the redis feature".to_string(),
+            );
+        }
+
+        // Let admins take and restore backups in backup_dir, take one every
+        // backup_interval_hours, keeping backup_keep, and regularly check
+        // that the latest one restores cleanly.
+        let state = match &config.backup_dir {
+            Some(dir) => {
+                if let Some(hours) = config.backup_interval_hours {
+                    snapshot::spawn_backups(
+                        state.db.clone(),
+                        dir,
+                        Duration::from_secs(hours * 3600),
+                        config.backup_keep,
+                    );
+                }
+                let verifier = SnapshotVerifier::new(dir).with_alerts(state.events.clone());
+                verifier
+                    .clone()
+                    .spawn(Duration::from_secs(config.verify_interval_hours * 3600));
+                state.with_backups(dir).with_snapshot_verifier(verifier)
+            }
+            None => state,
+        };
+
+        // search = "tantivy" or "meilisearch" indexes searchable collections
+        // outside the database.
+        let state = match crate::search::backend(&config)? {
+            Some(index) => state.with_search(index),
+            None => state,
+        };
+
+        let tinybase = Tinybase::new(state);
+        tinybase
+            .migrate(&conn)
+            .await
+            .map_err(|e| format!("Failed to run plugin migrations: {}", e))?;
+        // Left open, it would keep the write-ahead log from being
+        // checkpointed on shutdown.
+        drop(conn);
+        if let Some(result) = tinybase.run_command(args).await {
+            return result.map_err(|e| e.to_string());
+        }
+        tinybase.start();
+        #[cfg(feature = "notifications")]
+        crate::notify::spawn(tinybase.state().clone());
+        #[cfg(feature = "webhooks")]
+        crate::webhooks::spawn(tinybase.state().clone());
+        crate::search::spawn(tinybase.state().clone());
+
+        // namespaces = { shop = "data/shop.db", ... } serves isolated apps
+        // under /api/v1/ns/<name>.
+        let mut scoped = Namespaces::new();
+        for (name, state) in namespaces::open(tinybase.state(), &config).await? {
+            let state = state.with_config(&config);
+            state
+                .db
+                .fail_unfinished_jobs(&interrupted())
+                .await
+                .map_err(|e| format!("Failed to mark unfinished jobs as failed: {}", e))?;
+            #[cfg(feature = "notifications")]
+            crate::notify::spawn(state.clone());
+            #[cfg(feature = "webhooks")]
+            crate::webhooks::spawn(state.clone());
+            crate::search::spawn(state.clone());
+            scoped.add(&name, app_router(state))?;
+        }
+        let app = tinybase.router();
+        let app = if scoped.is_empty() {
+            app
+        } else {
+            namespaces::route(app, scoped)
+        };
+
+        // Fill the caches before listening, so the first requests after a
+        // deploy are served warm.
+        if !config.warm_queries.is_empty() {
+            for warmed in warmup::warm_up(tinybase.state(), &app, &config.warm_queries).await {
+                match warmed.result {
+                    Ok(status) if status.is_success() => {
+                        if config.logs("info") {
+                            println!(
+                                "warmed {} in {}ms",
+                                warmed.query,
+                                warmed.elapsed.as_millis()
+                            );
+                        }
+                    }
+                    Ok(status) => {
+                        tracing::warn!(query = %warmed.query, %status, "warm query failed")
+                    }
+                    Err(e) => {
+                        tracing::warn!(query = %warmed.query, error = %e, "warm query failed")
+                    }
+                }
+            }
+        }
+
+        // Development aid: record traffic into a fixture file for regression tests.
+        let app = match &config.record_fixtures {
+            Some(path) => {
+                println!("recording requests to {}", path.display());
+                fixtures::record(app, path)
+            }
+            None => app,
+        };
+
+        let app = match &config.access_log {
+            Some(path) => {
+                let log = AccessLog::open(
+                    path,
+                    config.access_log_format,
+                    config.access_log_max_bytes,
+                    config.access_log_files,
+                )
+                .await
+                .map_err(|e| format!("Failed to open access log {}: {}", path.display(), e))?;
+                access_log::log(app, log)
+            }
+            None => app,
+        };
+
+        let Listeners { api, admin } = match listeners {
+            Some(listeners) => listeners,
+            None => bind(&config).await?,
+        };
+        if config.logs("info") {
+            println!("{}", meta::Meta::new(tinybase.state()).banner());
+            if let Some(profile) = &config.profile {
+                println!("configured for {}", profile);
+            }
+            println!("listening on {}", api.local_addr().unwrap());
+            if let Some(admin) = &admin {
+                println!("admin listening on {}", admin.local_addr().unwrap());
+            }
+        }
+        let served = async {
+            match admin {
+                Some(admin) => {
+                    let public = app.clone().layer(middleware::from_fn(hide_admin));
+                    tokio::try_join!(
+                        serve_on(api, public, shutdown.clone()),
+                        serve_on(admin, app, shutdown.clone())
+                    )
+                    .map(|_| ())
+                }
+                None => serve_on(api, app, shutdown.clone()).await,
+            }
+        };
+        let timeout = Duration::from_millis(config.drain_timeout_ms);
+        drain(served, &shutdown, timeout, &pool).await?;
+        if config.logs("info") {
+            println!("stopped");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+//! Per-collection hooks that shape record data on its way out, so every
+//! endpoint returning records of a collection presents them the same way.
+//! Masked fields are hidden here too, see [`tinybase_core::masking`].
+
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use tinybase_core::{
+    masking::{has_masks, mask_record},
+    schema::CollectionSchema,
+    Collection,
+};
+
+use crate::{auth::viewer_roles, db_error, AppError, AppState, RecordResponse};
+
+type Hook = Arc<dyn Fn(&mut Map<String, Value>) + Send + Sync>;
+
+/// Response hooks, keyed by collection name so they survive schema syncs
+/// between instances. Hooks only see a record's `data`; `id`, `created` and
+/// `updated` are always returned as stored.
+#[derive(Clone, Default)]
+pub struct ResponseHooks {
+    hooks: HashMap<String, Vec<Hook>>,
+}
+
+impl ResponseHooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `hook` for the records of `collection`. Hooks run in the order
+    /// they are registered.
+    pub fn register(
+        &mut self,
+        collection: &str,
+        hook: impl Fn(&mut Map<String, Value>) + Send + Sync + 'static,
+    ) {
+        self.hooks
+            .entry(collection.to_string())
+            .or_default()
+            .push(Arc::new(hook));
+    }
+
+    /// Shortcut for a hook that renames the field `from` to `to`.
+    pub fn rename(&mut self, collection: &str, from: &str, to: &str) {
+        let (from, to) = (from.to_string(), to.to_string());
+        self.register(collection, move |data| {
+            if let Some(value) = data.remove(&from) {
+                data.insert(to.clone(), value);
+            }
+        });
+    }
+
+    /// Shortcut for a hook that removes internal fields from responses.
+    pub fn strip(&mut self, collection: &str, fields: &[&str]) {
+        let fields: Vec<String> = fields.iter().map(|f| f.to_string()).collect();
+        self.register(collection, move |data| {
+            for field in &fields {
+                data.remove(field);
+            }
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    fn apply(&self, collection: &str, data: &mut Value) {
+        let (Some(hooks), Some(map)) = (self.hooks.get(collection), data.as_object_mut()) else {
+            return;
+        };
+        for hook in hooks {
+            hook(map);
+        }
+    }
+}
+
+/// The schema and viewer roles to mask the records of `collection` with,
+/// unless nothing needs masking.
+async fn masks_for<'c>(
+    state: &AppState,
+    collection: &'c Collection,
+) -> Result<Option<(&'c CollectionSchema, Vec<String>)>, AppError> {
+    let Some(schema) = collection.schema.as_ref().filter(|s| has_masks(s)) else {
+        return Ok(None);
+    };
+    Ok(viewer_roles(state).await?.map(|roles| (schema, roles)))
+}
+
+/// Masks the fields of collection `collection_id` in record data the viewer
+/// of the request may not see, then applies the collection's hooks to
+/// `records`.
+pub(crate) fn shape_records<'a>(
+    state: &'a AppState,
+    collection_id: i64,
+    records: impl IntoIterator<Item = &'a mut RecordResponse>,
+) -> impl Future<Output = Result<(), AppError>> + Send + 'a {
+    // Collected up front, so the future doesn't hold the caller's iterator.
+    let data = records.into_iter().map(|r| &mut r.data).collect();
+    shape_data(state, collection_id, data)
+}
+
+/// [`shape_records`] for bare record data, e.g. of archived versions.
+pub(crate) async fn shape_data(
+    state: &AppState,
+    collection_id: i64,
+    data: Vec<&mut Value>,
+) -> Result<(), AppError> {
+    let Some(collection) = state
+        .db
+        .get_collection(collection_id)
+        .await
+        .map_err(db_error)?
+    else {
+        return Ok(());
+    };
+    let masks = masks_for(state, &collection).await?;
+    for data in data {
+        if let Some((schema, roles)) = &masks {
+            mask_record(schema, data, roles);
+        }
+        state.response_hooks.apply(&collection.name, data);
+    }
+    Ok(())
+}
@@ -0,0 +1,106 @@
+//! Stopping the server without dropping work on the floor.
+//!
+//! A [`Shutdown`] is shared by the server and the background dispatchers of
+//! its [`AppState`](crate::AppState)s. Triggering it, on `SIGTERM` or
+//! `SIGINT` when the server runs as the binary, stops the listeners from
+//! accepting connections while the requests in flight finish. Once they
+//! have, [`Shutdown::flush`] lets the webhook, notification and search
+//! dispatchers work through the events already queued and waits for the
+//! deliveries they started, then the database is checkpointed and closed.
+//! The whole drain is bounded by `drain_timeout_ms`; whatever is still
+//! running then is dropped.
+
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+
+/// How long the server waits for requests and deliveries when none is
+/// configured.
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Tells the server to stop, and its background work when to finish.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    requested: CancellationToken,
+    flushing: CancellationToken,
+    tasks: TaskTracker,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stops the server: it accepts no more connections and returns once
+    /// the requests in flight and the queued deliveries are done.
+    pub fn trigger(&self) {
+        self.requested.cancel();
+    }
+
+    /// Whether the server was told to stop.
+    pub fn is_triggered(&self) -> bool {
+        self.requested.is_cancelled()
+    }
+
+    /// Resolves once the server is told to stop.
+    pub async fn triggered(&self) {
+        self.requested.cancelled().await
+    }
+
+    /// Resolves once the requests are done and the dispatchers should finish
+    /// the events they have queued and stop.
+    pub async fn flushing(&self) {
+        self.flushing.cancelled().await
+    }
+
+    /// Runs `future`, a dispatcher or a delivery it started, in the
+    /// background; [`flush`](Self::flush) waits for it.
+    pub fn spawn<F>(&self, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.tasks.spawn(future);
+    }
+
+    /// Tells the dispatchers to finish and waits until they and their
+    /// deliveries are done, or until `deadline`. False if work was left
+    /// running.
+    pub async fn flush(&self, deadline: Instant) -> bool {
+        self.flushing.cancel();
+        self.tasks.close();
+        tokio::time::timeout_at(deadline, self.tasks.wait())
+            .await
+            .is_ok()
+    }
+}
+
+/// Resolves on `SIGINT` (Ctrl-C) or, on Unix, `SIGTERM`, as sent by
+/// `docker stop`, systemd and Kubernetes.
+pub async fn signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!(error = %e, "failed to listen for SIGINT");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
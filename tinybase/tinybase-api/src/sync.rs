@@ -0,0 +1,233 @@
+//! Differential sync of collection schemas from another Tinybase instance.
+//!
+//! Collections are matched by name, since ids differ between instances, and
+//! relation fields are re-pointed at the local id of their target collection.
+//! Local collections missing from the source are reported but never deleted.
+
+use axum::{extract::State, Json};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tinybase_core::{
+    events::{Event, EventAction},
+    schema::{CollectionSchema, FieldType},
+    Db,
+};
+use utoipa::{OpenApi, ToSchema};
+
+use crate::{auth::RequireAdmin, check_schema_rules, db_error, AppError, AppState, ProblemDetail};
+
+type BoxResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// A collection as listed by `GET /api/v1/collections` on the source.
+#[derive(Deserialize, Clone)]
+pub struct RemoteCollection {
+    pub id: i64,
+    pub name: String,
+    pub schema: Option<CollectionSchema>,
+}
+
+#[derive(Serialize, Default, Debug, ToSchema)]
+pub struct SyncPlan {
+    /// Collections that exist only on the source and will be created.
+    pub create: Vec<String>,
+    /// Collections whose schema differs and will be replaced.
+    pub update: Vec<String>,
+    pub unchanged: Vec<String>,
+    /// Collections that exist only locally; they are left untouched.
+    pub local_only: Vec<String>,
+}
+
+impl SyncPlan {
+    pub fn is_empty(&self) -> bool {
+        self.create.is_empty() && self.update.is_empty()
+    }
+}
+
+/// Fetches the collection definitions of the instance at `base_url`.
+pub async fn fetch_remote_collections(base_url: &str) -> BoxResult<Vec<RemoteCollection>> {
+    let url = format!("{}/api/v1/collections", base_url.trim_end_matches('/'));
+    let collections = reqwest::get(&url)
+        .await?
+        .error_for_status()?
+        .json::<Vec<RemoteCollection>>()
+        .await?;
+    Ok(collections)
+}
+
+/// Rewrites relation targets from source ids to local ids, by collection name.
+fn remap_relations(
+    schema: &CollectionSchema,
+    remote_names: &HashMap<i64, String>,
+    local_ids: &HashMap<String, i64>,
+) -> CollectionSchema {
+    let mut schema = schema.clone();
+    for field in schema.fields.values_mut() {
+        if let FieldType::Relation { collection_id, .. } = &mut field.r#type {
+            if let Some(local) = remote_names
+                .get(collection_id)
+                .and_then(|name| local_ids.get(name))
+            {
+                *collection_id = *local;
+            }
+        }
+    }
+    schema
+}
+
+/// Compares the source collections with the local ones.
+pub async fn plan_sync(db: &dyn Db, remote: &[RemoteCollection]) -> BoxResult<SyncPlan> {
+    let local = db.list_collections().await?;
+    let local_ids: HashMap<String, i64> = local.iter().map(|c| (c.name.clone(), c.id)).collect();
+    let remote_names: HashMap<i64, String> =
+        remote.iter().map(|c| (c.id, c.name.clone())).collect();
+
+    let mut plan = SyncPlan::default();
+    for collection in remote {
+        match local.iter().find(|c| c.name == collection.name) {
+            None => plan.create.push(collection.name.clone()),
+            Some(existing) => {
+                let wanted = collection
+                    .schema
+                    .as_ref()
+                    .map(|s| remap_relations(s, &remote_names, &local_ids));
+                if wanted.is_some() && wanted != existing.schema {
+                    plan.update.push(collection.name.clone());
+                } else {
+                    plan.unchanged.push(collection.name.clone());
+                }
+            }
+        }
+    }
+    plan.local_only = local
+        .iter()
+        .filter(|c| !remote.iter().any(|r| r.name == c.name))
+        .map(|c| c.name.clone())
+        .collect();
+    Ok(plan)
+}
+
+/// Applies `plan`, returning the ids of the created and updated collections.
+///
+/// Missing collections are created first so relations between new
+/// collections can be resolved when their schemas are written.
+pub async fn apply_sync(
+    db: &dyn Db,
+    remote: &[RemoteCollection],
+    plan: &SyncPlan,
+) -> BoxResult<(Vec<i64>, Vec<i64>)> {
+    let mut local_ids: HashMap<String, i64> = db
+        .list_collections()
+        .await?
+        .into_iter()
+        .map(|c| (c.name, c.id))
+        .collect();
+    let remote_names: HashMap<i64, String> =
+        remote.iter().map(|c| (c.id, c.name.clone())).collect();
+
+    let mut created = Vec::new();
+    for name in &plan.create {
+        let collection = db.create_collection(name, &None).await?;
+        local_ids.insert(name.clone(), collection.id);
+        created.push(collection.id);
+    }
+
+    let mut updated = Vec::new();
+    for collection in remote {
+        let is_new = plan.create.contains(&collection.name);
+        if !is_new && !plan.update.contains(&collection.name) {
+            continue;
+        }
+        let (Some(schema), Some(&id)) = (&collection.schema, local_ids.get(&collection.name))
+        else {
+            continue;
+        };
+        let schema = remap_relations(schema, &remote_names, &local_ids);
+        db.update_collection(id, None, Some(schema)).await?;
+        if !is_new {
+            updated.push(id);
+        }
+    }
+    Ok((created, updated))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SchemaSyncRequest {
+    /// Base URL of the source instance, e.g. `https://staging.example.com`.
+    from: String,
+    /// Only report the differences. Defaults to `true`; set to `false` to
+    /// apply them.
+    #[serde(default = "default_dry_run")]
+    dry_run: bool,
+}
+
+fn default_dry_run() -> bool {
+    true
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SchemaSyncResponse {
+    #[serde(flatten)]
+    plan: SyncPlan,
+    applied: bool,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/schema/sync",
+    request_body = SchemaSyncRequest,
+    responses(
+        (status = 200, description = "Differences with the source instance, applied unless `dry_run`", body = SchemaSyncResponse),
+        (status = 400, description = "Source unreachable or carrying invalid rules", body = ProblemDetail),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn sync_schema(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Json(payload): Json<SchemaSyncRequest>,
+) -> Result<Json<SchemaSyncResponse>, AppError> {
+    let remote = fetch_remote_collections(&payload.from).await.map_err(|e| {
+        AppError::BadRequest(format!(
+            "Failed to fetch collections from {}: {}",
+            payload.from, e
+        ))
+    })?;
+    for collection in &remote {
+        check_schema_rules(collection.schema.as_ref())?;
+    }
+    let plan = plan_sync(state.db.as_ref(), &remote)
+        .await
+        .map_err(db_error)?;
+    if payload.dry_run || plan.is_empty() {
+        return Ok(Json(SchemaSyncResponse {
+            plan,
+            applied: false,
+        }));
+    }
+
+    let (created, updated) = apply_sync(state.db.as_ref(), &remote, &plan)
+        .await
+        .map_err(db_error)?;
+    for id in created {
+        state
+            .events
+            .publish(Event::new(EventAction::CollectionCreated, id, None));
+    }
+    for id in updated {
+        state
+            .events
+            .publish(Event::new(EventAction::CollectionUpdated, id, None));
+    }
+    Ok(Json(SchemaSyncResponse {
+        plan,
+        applied: true,
+    }))
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(sync_schema),
+    components(schemas(SchemaSyncRequest, SchemaSyncResponse, SyncPlan, ProblemDetail))
+)]
+pub(crate) struct SyncApiDoc;
@@ -0,0 +1,184 @@
+//! A [`SearchIndex`] in an embedded Tantivy index, for typo-tolerant search
+//! without running a search server. Words match with one typo, and every
+//! word of a query must match; results are ranked by BM25.
+//!
+//! Writes are committed one at a time, so each is searchable as soon as it
+//! returns; the index lives in its own directory, outside the database.
+
+use axum::async_trait;
+use serde_json::{Map, Value};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tantivy::{
+    collector::TopDocs,
+    directory::MmapDirectory,
+    query::{BooleanQuery, Occur, Query, QueryParser, TermQuery},
+    schema::{Field, IndexRecordOption, Schema, Value as _, INDEXED, STORED, STRING, TEXT},
+    Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term,
+};
+use tinybase_core::search::{self, SearchHit, SearchIndex};
+
+type BoxResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Memory the index writer buffers documents in.
+const WRITER_MEMORY: usize = 50_000_000;
+
+pub struct TantivyIndex {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    index: Index,
+    reader: IndexReader,
+    writer: Mutex<IndexWriter>,
+    collection: Field,
+    record: Field,
+    /// `<collection id>:<record id>`, for replacing and removing a record.
+    key: Field,
+    body: Field,
+}
+
+fn schema() -> Schema {
+    let mut schema = Schema::builder();
+    schema.add_i64_field("collection", INDEXED);
+    schema.add_i64_field("record", INDEXED | STORED);
+    schema.add_text_field("key", STRING);
+    schema.add_text_field("body", TEXT);
+    schema.build()
+}
+
+impl TantivyIndex {
+    /// The index in `dir`, created if it doesn't exist yet.
+    pub fn open(dir: impl Into<PathBuf>) -> BoxResult<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Self::new(Index::open_or_create(MmapDirectory::open(&dir)?, schema())?)
+    }
+
+    /// An index kept in memory only, e.g. for tests.
+    pub fn in_memory() -> BoxResult<Self> {
+        Self::new(Index::create_in_ram(schema()))
+    }
+
+    fn new(index: Index) -> BoxResult<Self> {
+        let schema = index.schema();
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        let writer = index.writer(WRITER_MEMORY)?;
+        Ok(Self {
+            inner: Arc::new(Inner {
+                collection: schema.get_field("collection")?,
+                record: schema.get_field("record")?,
+                key: schema.get_field("key")?,
+                body: schema.get_field("body")?,
+                index,
+                reader,
+                writer: Mutex::new(writer),
+            }),
+        })
+    }
+
+    /// Runs `write` with the index writer, then commits it and makes the
+    /// change visible to searches, off the async threads.
+    async fn write<F>(&self, write: F) -> BoxResult<()>
+    where
+        F: FnOnce(&Inner, &IndexWriter) -> BoxResult<()> + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut writer = inner.writer.lock().unwrap();
+            write(&inner, &writer)?;
+            writer.commit()?;
+            inner.reader.reload()?;
+            Ok(())
+        })
+        .await?
+    }
+}
+
+fn key(collection_id: i64, record_id: i64) -> String {
+    format!("{}:{}", collection_id, record_id)
+}
+
+#[async_trait]
+impl SearchIndex for TantivyIndex {
+    fn name(&self) -> &'static str {
+        "tantivy"
+    }
+
+    async fn index(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        document: &Map<String, Value>,
+    ) -> BoxResult<()> {
+        let text = search::text(document);
+        self.write(move |inner, writer| {
+            let key = key(collection_id, record_id);
+            writer.delete_term(Term::from_field_text(inner.key, &key));
+            let mut document = TantivyDocument::new();
+            document.add_i64(inner.collection, collection_id);
+            document.add_i64(inner.record, record_id);
+            document.add_text(inner.key, &key);
+            document.add_text(inner.body, &text);
+            writer.add_document(document)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn remove(&self, collection_id: i64, record_id: i64) -> BoxResult<()> {
+        self.write(move |inner, writer| {
+            let key = key(collection_id, record_id);
+            writer.delete_term(Term::from_field_text(inner.key, &key));
+            Ok(())
+        })
+        .await
+    }
+
+    async fn clear(&self, collection_id: i64) -> BoxResult<()> {
+        self.write(move |inner, writer| {
+            writer.delete_term(Term::from_field_i64(inner.collection, collection_id));
+            Ok(())
+        })
+        .await
+    }
+
+    async fn search(
+        &self,
+        collection_id: i64,
+        query: &str,
+        limit: usize,
+    ) -> BoxResult<Vec<SearchHit>> {
+        let inner = self.inner.clone();
+        let query = query.to_string();
+        tokio::task::spawn_blocking(move || {
+            let mut parser = QueryParser::for_index(&inner.index, vec![inner.body]);
+            parser.set_conjunction_by_default();
+            parser.set_field_fuzzy(inner.body, false, 1, true);
+            // Lenient parsing drops what it can't read instead of failing
+            // on user input.
+            let (words, _) = parser.parse_query_lenient(&query);
+            let collection: Box<dyn Query> = Box::new(TermQuery::new(
+                Term::from_field_i64(inner.collection, collection_id),
+                IndexRecordOption::Basic,
+            ));
+            let query = BooleanQuery::new(vec![(Occur::Must, collection), (Occur::Must, words)]);
+            let searcher = inner.reader.searcher();
+            let mut hits = Vec::new();
+            for (score, address) in searcher.search(&query, &TopDocs::with_limit(limit))? {
+                let document: TantivyDocument = searcher.doc(address)?;
+                if let Some(record_id) = document.get_first(inner.record).and_then(|v| v.as_i64()) {
+                    hits.push(SearchHit {
+                        record_id,
+                        score: score as f64,
+                    });
+                }
+            }
+            Ok(hits)
+        })
+        .await?
+    }
+}
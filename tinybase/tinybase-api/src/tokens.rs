@@ -0,0 +1,225 @@
+//! API tokens for services, minted and revoked by admins at
+//! `/api/v1/admin/tokens`.
+//!
+//! A token is sent like an admin API key, as `Authorization: Bearer
+//! tbt_...`, but only reaches the records of the collections its scopes
+//! name: `read` scopes allow `GET` and `HEAD`, `write` scopes any method.
+//! Every other endpoint, introspection aside, answers `403` to it. Tokens
+//! don't expire; only the SHA-256 of each is stored, so a lost token can't
+//! be shown again, only revoked and replaced.
+
+use axum::{
+    extract::{Path, Request, State},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tinybase_core::tokens::{ApiToken, TokenScope};
+use utoipa::ToSchema;
+
+use crate::{
+    auth::{bearer, generate_key, RequireAdmin},
+    db_error, AppError, AppState,
+};
+
+/// What every API token starts with, telling it apart from admin API keys.
+pub(crate) const TOKEN_PREFIX: &str = "tbt_";
+
+/// The hex SHA-256 of `token`, kept in its place so a leaked database or
+/// store can't be replayed.
+pub(crate) fn hash(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// The unrevoked API token `token` is, if any.
+pub(crate) async fn resolve(state: &AppState, token: &str) -> Result<Option<ApiToken>, AppError> {
+    state
+        .db
+        .get_api_token_by_hash(&hash(token))
+        .await
+        .map_err(db_error)
+}
+
+/// Holds requests bearing an API token to the scopes of the token.
+pub(crate) async fn enforce(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(token) = bearer(request.headers()).filter(|t| t.starts_with(TOKEN_PREFIX)) else {
+        return next.run(request).await;
+    };
+    match authorize(&state, token, request.method(), request.uri().path()).await {
+        Ok(()) => next.run(request).await,
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Checks that `token` may send `method` to `path`, relative to `/api/v1`.
+async fn authorize(
+    state: &AppState,
+    token: &str,
+    method: &Method,
+    path: &str,
+) -> Result<(), AppError> {
+    let token = resolve(state, token)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("Invalid or revoked API token".to_string()))?;
+    if path == "/auth/introspect" {
+        return Ok(());
+    }
+    let Some(key) = path
+        .strip_prefix("/collections/")
+        .and_then(|rest| rest.split('/').next())
+        .filter(|key| !key.is_empty())
+    else {
+        return Err(AppError::Forbidden(
+            "API tokens only reach the records of their collections".to_string(),
+        ));
+    };
+    let collection = match key.parse::<i64>() {
+        Ok(id) => state.db.get_collection(id).await,
+        Err(_) => state.db.get_collection_by_name(key).await,
+    }
+    .map_err(db_error)?
+    .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", key)))?;
+    let write = !matches!(*method, Method::GET | Method::HEAD);
+    if !token.allows(&collection.name, write) {
+        return Err(AppError::Forbidden(format!(
+            "The API token may not {} the records of {}",
+            if write { "change" } else { "read" },
+            collection.name
+        )));
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct TokenRequest {
+    /// What the token is for, e.g. the service holding it.
+    name: String,
+    /// E.g. `[{"collection": "posts", "access": "read"}]`; `*` names every
+    /// collection, and `write` access includes reading.
+    #[schema(value_type = Vec<Object>)]
+    scopes: Vec<TokenScope>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TokenResponse {
+    id: i64,
+    name: String,
+    #[schema(value_type = Vec<Object>)]
+    scopes: Vec<TokenScope>,
+    /// The token, only shown when it is minted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+    created: String,
+    updated: String,
+}
+
+impl From<ApiToken> for TokenResponse {
+    fn from(t: ApiToken) -> Self {
+        TokenResponse {
+            id: t.id,
+            name: t.name,
+            scopes: t.scopes,
+            token: None,
+            created: t.created,
+            updated: t.updated,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/tokens",
+    responses(
+        (status = 200, description = "List the API tokens, without their secrets", body = Vec<TokenResponse>),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn list_tokens(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TokenResponse>>, AppError> {
+    let tokens = state.db.list_api_tokens().await.map_err(db_error)?;
+    Ok(Json(tokens.into_iter().map(Into::into).collect()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/tokens",
+    request_body = TokenRequest,
+    responses(
+        (status = 201, description = "Mint an API token; the response carries the `token`, which is not shown again", body = TokenResponse),
+        (status = 400, description = "Missing name or scopes", body = ProblemDetail),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "A scope names a collection that doesn't exist", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn create_token(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Json(request): Json<TokenRequest>,
+) -> Result<(StatusCode, Json<TokenResponse>), AppError> {
+    let name = request.name.trim();
+    if name.is_empty() {
+        return Err(AppError::BadRequest(
+            "An API token needs a name".to_string(),
+        ));
+    }
+    if request.scopes.is_empty() {
+        return Err(AppError::BadRequest(
+            "An API token needs at least one scope".to_string(),
+        ));
+    }
+    let db = &state.db;
+    for scope in request.scopes.iter().filter(|s| s.collection != "*") {
+        db.get_collection_by_name(&scope.collection)
+            .await
+            .map_err(db_error)?
+            .ok_or_else(|| {
+                AppError::NotFound(format!("Collection {} not found", scope.collection))
+            })?;
+    }
+    let token = format!("{}{}", TOKEN_PREFIX, generate_key());
+    let minted = db
+        .create_api_token(name, &request.scopes, &hash(&token))
+        .await
+        .map_err(db_error)?;
+    let mut response = TokenResponse::from(minted);
+    response.token = Some(token);
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/tokens/{id}",
+    params(
+        ("id" = i64, Path, description = "API token id")
+    ),
+    responses(
+        (status = 204, description = "Revoke an API token; requests bearing it answer 401 from now on"),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "API token not found", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn delete_token(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, AppError> {
+    let db = &state.db;
+    db.get_api_token(id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| AppError::NotFound(format!("API token {} not found", id)))?;
+    db.delete_api_token(id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
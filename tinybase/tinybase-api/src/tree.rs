@@ -0,0 +1,249 @@
+//! Trees of records, for collections whose schema names a `tree.parent`
+//! field: roots, children and whole subtrees read in one query each, and
+//! moving a record, with its subtree, under another parent or among its
+//! siblings.
+
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tinybase_core::tree::TreeNode;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    access::Access, db_error, files::RecordPayload, resolve_collection, shape::shape_records,
+    write_record, AppError, AppState, RecordResponse, WriteMode,
+};
+
+#[derive(Serialize, ToSchema)]
+pub struct TreeNodeResponse {
+    #[serde(flatten)]
+    record: RecordResponse,
+    /// The id of the parent record, `null` for roots.
+    parent: Option<i64>,
+    /// `0` for roots.
+    depth: u32,
+    /// The ids of the ancestors of the record, its root first.
+    path: Vec<i64>,
+    /// The order of the record among its siblings.
+    position: i64,
+}
+
+impl From<TreeNode> for TreeNodeResponse {
+    fn from(node: TreeNode) -> Self {
+        TreeNodeResponse {
+            parent: node.parent,
+            depth: node.depth,
+            path: node.ancestors(),
+            position: node.position,
+            record: node.record.into(),
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct SubtreeQuery {
+    /// How many levels below the record to include; all by default.
+    depth: Option<u32>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct MoveRequest {
+    /// The new parent, or `null` to make the record a root.
+    parent: Option<i64>,
+    /// The position among the new siblings, `0` for first; last by default.
+    position: Option<usize>,
+}
+
+/// The id and parent field of a tree collection.
+async fn tree_collection(state: &AppState, key: &str) -> Result<(i64, String), AppError> {
+    let id = resolve_collection(state.db.as_ref(), key).await?;
+    let collection = state
+        .db
+        .get_collection(id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", id)))?;
+    match collection.schema.and_then(|s| s.tree) {
+        Some(tree) => Ok((id, tree.parent)),
+        None => Err(AppError::BadRequest(format!(
+            "Collection {} is not a tree; set tree.parent in its schema",
+            collection.name
+        ))),
+    }
+}
+
+async fn respond(
+    state: &AppState,
+    collection_id: i64,
+    nodes: Vec<TreeNode>,
+) -> Result<Json<Vec<TreeNodeResponse>>, AppError> {
+    // Nodes the list rule hides are left out, not their descendants.
+    let access = Access::to_id(state, collection_id).await?;
+    let mut nodes: Vec<TreeNodeResponse> = nodes
+        .into_iter()
+        .filter(|n| access.sees(&n.record))
+        .map(Into::into)
+        .collect();
+    shape_records(
+        state,
+        collection_id,
+        nodes.iter_mut().map(|n| &mut n.record),
+    )
+    .await?;
+    Ok(Json(nodes))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/collections/{id}/tree",
+    params(("id" = String, Path, description = "Collection id or name")),
+    responses(
+        (status = 200, description = "List the roots of the tree, in order", body = Vec<TreeNodeResponse>),
+        (status = 400, description = "The collection is not a tree", body = ProblemDetail),
+        (status = 404, description = "Collection not found", body = ProblemDetail),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn list_roots(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<Json<Vec<TreeNodeResponse>>, AppError> {
+    let (collection_id, _) = tree_collection(&state, &key).await?;
+    let nodes = state
+        .db
+        .list_tree_children(collection_id, None)
+        .await
+        .map_err(db_error)?;
+    respond(&state, collection_id, nodes).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/collections/{id}/tree/{record_id}",
+    params(
+        ("id" = String, Path, description = "Collection id or name"),
+        ("record_id" = i64, Path, description = "Record id"),
+        SubtreeQuery
+    ),
+    responses(
+        (status = 200, description = "List a record and its descendants depth-first, siblings in order", body = Vec<TreeNodeResponse>),
+        (status = 400, description = "The collection is not a tree", body = ProblemDetail),
+        (status = 404, description = "Collection or record not found", body = ProblemDetail),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn get_subtree(
+    State(state): State<AppState>,
+    Path((key, record_id)): Path<(String, i64)>,
+    Query(query): Query<SubtreeQuery>,
+) -> Result<Json<Vec<TreeNodeResponse>>, AppError> {
+    let (collection_id, _) = tree_collection(&state, &key).await?;
+    let nodes = state
+        .db
+        .get_subtree(collection_id, record_id, query.depth)
+        .await
+        .map_err(db_error)?;
+    if nodes.is_empty() {
+        return Err(AppError::NotFound(format!(
+            "Record {} not found",
+            record_id
+        )));
+    }
+    respond(&state, collection_id, nodes).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/collections/{id}/tree/{record_id}/children",
+    params(
+        ("id" = String, Path, description = "Collection id or name"),
+        ("record_id" = i64, Path, description = "Record id")
+    ),
+    responses(
+        (status = 200, description = "List the children of a record, in order", body = Vec<TreeNodeResponse>),
+        (status = 400, description = "The collection is not a tree", body = ProblemDetail),
+        (status = 404, description = "Collection or record not found", body = ProblemDetail),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn list_children(
+    State(state): State<AppState>,
+    Path((key, record_id)): Path<(String, i64)>,
+) -> Result<Json<Vec<TreeNodeResponse>>, AppError> {
+    let (collection_id, _) = tree_collection(&state, &key).await?;
+    let db = &state.db;
+    if db
+        .get_record(collection_id, record_id)
+        .await
+        .map_err(db_error)?
+        .is_none()
+    {
+        return Err(AppError::NotFound(format!(
+            "Record {} not found",
+            record_id
+        )));
+    }
+    let nodes = db
+        .list_tree_children(collection_id, Some(record_id))
+        .await
+        .map_err(db_error)?;
+    respond(&state, collection_id, nodes).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/collections/{id}/tree/{record_id}/move",
+    params(
+        ("id" = String, Path, description = "Collection id or name"),
+        ("record_id" = i64, Path, description = "Record id")
+    ),
+    request_body = MoveRequest,
+    responses(
+        (status = 200, description = "Move a record, with its subtree, under another parent and to a position among its siblings; the record is updated as by PATCH", body = TreeNodeResponse),
+        (status = 400, description = "The collection is not a tree", body = ProblemDetail),
+        (status = 403, description = "A collection rule denies the request", body = ProblemDetail),
+        (status = 404, description = "Collection or record not found", body = ProblemDetail),
+        (status = 422, description = "The new parent is the record or one of its descendants", body = ProblemDetail),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn move_node(
+    State(state): State<AppState>,
+    Path((key, record_id)): Path<(String, i64)>,
+    Json(request): Json<MoveRequest>,
+) -> Result<Json<TreeNodeResponse>, AppError> {
+    let (collection_id, field) = tree_collection(&state, &key).await?;
+    // A merge patch removes the field for `null`, making the record a root.
+    let data = json!({ field: request.parent.map_or(Value::Null, Value::from) });
+    let payload = RecordPayload {
+        data,
+        files: Vec::new(),
+    };
+    let _ = write_record(
+        state.clone(),
+        &key,
+        record_id,
+        payload,
+        WriteMode::Merge,
+        None,
+    )
+    .await?;
+    let db = &state.db;
+    if let Some(position) = request.position {
+        db.set_tree_position(collection_id, record_id, position)
+            .await
+            .map_err(db_error)?;
+    }
+    let node = db
+        .get_subtree(collection_id, record_id, Some(0))
+        .await
+        .map_err(db_error)?
+        .pop()
+        .ok_or_else(|| AppError::NotFound(format!("Record {} not found", record_id)))?;
+    let mut response = TreeNodeResponse::from(node);
+    shape_records(&state, collection_id, [&mut response.record]).await?;
+    Ok(Json(response))
+}
@@ -0,0 +1,117 @@
+//! The build of this instance and the API version it speaks.
+//!
+//! The API version is `major.minor`: minors add endpoints and fields, majors
+//! break clients. A client written against API `1.4` works with any `1.x`
+//! server, but features added after `1.x` are missing when `x < 4`; SDKs
+//! fetch `/api/v1/version` and run [`check_compatibility`] to warn about it.
+
+use axum::Json;
+use serde::Serialize;
+use std::time::{Duration, UNIX_EPOCH};
+use utoipa::ToSchema;
+
+/// The API version this server speaks.
+pub const API_VERSION: &str = "1.0";
+
+#[derive(Serialize, ToSchema)]
+pub struct VersionInfo {
+    /// The server release.
+    #[schema(example = "0.1.0")]
+    pub version: &'static str,
+    /// The API version, `major.minor`.
+    #[schema(example = "1.0")]
+    pub api_version: &'static str,
+    /// The commit the server was built from, `unknown` outside a checkout.
+    pub git_hash: &'static str,
+    pub build_time: String,
+}
+
+impl VersionInfo {
+    pub fn new() -> Self {
+        let built = env!("TINYBASE_BUILD_TIME").parse().unwrap_or(0);
+        VersionInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            api_version: API_VERSION,
+            git_hash: env!("TINYBASE_GIT_HASH"),
+            build_time: tinybase_core::format_timestamp(UNIX_EPOCH + Duration::from_secs(built)),
+        }
+    }
+}
+
+impl Default for VersionInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How a client expecting one API version fares with a server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Compatibility {
+    Compatible,
+    /// Same major version, but the server is older than the client expects:
+    /// requests work, features of the newer minors are missing.
+    OlderServer {
+        client: String,
+        server: String,
+    },
+    /// Different major versions; requests may fail.
+    Incompatible {
+        client: String,
+        server: String,
+    },
+}
+
+impl Compatibility {
+    /// A warning for clients to show, unless compatible.
+    pub fn warning(&self) -> Option<String> {
+        match self {
+            Compatibility::Compatible => None,
+            Compatibility::OlderServer { client, server } => Some(format!(
+                "This client expects Tinybase API {} but the server speaks {}; newer features are unavailable",
+                client, server
+            )),
+            Compatibility::Incompatible { client, server } => Some(format!(
+                "This client expects Tinybase API {} but the server speaks the incompatible {}",
+                client, server
+            )),
+        }
+    }
+}
+
+/// Parses `major.minor`.
+fn parse(version: &str) -> Result<(u64, u64), String> {
+    version
+        .split_once('.')
+        .and_then(|(major, minor)| Some((major.parse().ok()?, minor.parse().ok()?)))
+        .ok_or_else(|| format!("'{}' is not an API version (major.minor)", version))
+}
+
+/// Compares the API version a client expects with the one a server reports.
+pub fn check_compatibility(client: &str, server: &str) -> Result<Compatibility, String> {
+    let (client_major, client_minor) = parse(client)?;
+    let (server_major, server_minor) = parse(server)?;
+    if client_major != server_major {
+        return Ok(Compatibility::Incompatible {
+            client: client.to_string(),
+            server: server.to_string(),
+        });
+    }
+    if client_minor > server_minor {
+        return Ok(Compatibility::OlderServer {
+            client: client.to_string(),
+            server: server.to_string(),
+        });
+    }
+    Ok(Compatibility::Compatible)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/version",
+    responses(
+        (status = 200, description = "The server release, API version and build", body = VersionInfo)
+    )
+)]
+pub(crate) async fn get_version() -> Json<VersionInfo> {
+    Json(VersionInfo::new())
+}
@@ -0,0 +1,178 @@
+//! Record version history. Every update and delete archives the previous
+//! state of the record, up to the collection's `max_versions`; archived
+//! versions can be listed and written back.
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use serde::Serialize;
+use tinybase_core::{
+    events::{Event, EventAction},
+    relations::check_relations,
+    validation::validate_record,
+    RecordVersion,
+};
+use utoipa::ToSchema;
+
+use crate::{
+    access::{record_value, Access, Operation},
+    db_error, resolve_collection,
+    shape::{shape_data, shape_records},
+    AppError, AppState, RecordResponse,
+};
+
+#[derive(Serialize, ToSchema)]
+pub struct RecordVersionResponse {
+    version: i64,
+    data: serde_json::Value,
+    created: String,
+    /// When this version was written.
+    updated: String,
+    /// When this version was replaced or deleted.
+    archived: String,
+    /// Whether the record was deleted rather than updated.
+    deleted: bool,
+}
+
+impl From<RecordVersion> for RecordVersionResponse {
+    fn from(v: RecordVersion) -> Self {
+        RecordVersionResponse {
+            version: v.version,
+            data: v.data,
+            created: v.created,
+            updated: v.updated,
+            archived: v.archived,
+            deleted: v.deleted,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/collections/{id}/records/{record_id}/versions",
+    params(
+        ("id" = String, Path, description = "Collection id or name"),
+        ("record_id" = i64, Path, description = "Record id")
+    ),
+    responses(
+        (status = 200, description = "List the archived versions of a record, oldest first; empty when it was never updated", body = Vec<RecordVersionResponse>),
+        (status = 403, description = "A collection rule denies the request", body = ProblemDetail),
+        (status = 404, description = "Collection not found", body = ProblemDetail),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn list_versions(
+    State(state): State<AppState>,
+    Path((key, record_id)): Path<(String, i64)>,
+) -> Result<Json<Vec<RecordVersionResponse>>, AppError> {
+    let collection_id = resolve_collection(state.db.as_ref(), &key).await?;
+    let versions = state
+        .db
+        .list_record_versions(collection_id, record_id)
+        .await
+        .map_err(db_error)?;
+    let access = Access::to_id(&state, collection_id).await?;
+    if access.is_ruled() {
+        // The view rule of a deleted record holds for its last version.
+        let current = state
+            .db
+            .get_record(collection_id, record_id)
+            .await
+            .map_err(db_error)?;
+        let record = match (current, versions.last()) {
+            (Some(current), _) => Some(record_value(&current)),
+            (None, Some(last)) => {
+                let mut record = last.data.clone();
+                if let Some(record) = record.as_object_mut() {
+                    record.insert("id".to_string(), record_id.into());
+                    record.insert("created".to_string(), last.created.clone().into());
+                    record.insert("updated".to_string(), last.updated.clone().into());
+                }
+                Some(record)
+            }
+            (None, None) => None,
+        };
+        if let Some(record) = record {
+            access.check(Operation::View, &record, &serde_json::Value::Null)?;
+        }
+    }
+    let mut versions: Vec<RecordVersionResponse> = versions.into_iter().map(Into::into).collect();
+    let data = versions.iter_mut().map(|v| &mut v.data).collect();
+    shape_data(&state, collection_id, data).await?;
+    Ok(Json(versions))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/collections/{id}/records/{record_id}/versions/{version}/restore",
+    params(
+        ("id" = String, Path, description = "Collection id or name"),
+        ("record_id" = i64, Path, description = "Record id"),
+        ("version" = i64, Path, description = "Version to restore")
+    ),
+    responses(
+        (status = 200, description = "Write an archived version back to the record, recreating it if it was deleted; the current data becomes a version itself", body = RecordResponse),
+        (status = 403, description = "A collection rule denies the request", body = ProblemDetail),
+        (status = 404, description = "Collection or version not found", body = ProblemDetail),
+        (status = 422, description = "The version no longer passes validation", body = ProblemDetail),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn restore_version(
+    State(state): State<AppState>,
+    Path((key, record_id, version)): Path<(String, i64, i64)>,
+) -> Result<Json<RecordResponse>, AppError> {
+    let db = &state.db;
+    let collection_id = resolve_collection(db.as_ref(), &key).await?;
+    let collection = db
+        .get_collection(collection_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| AppError::NotFound(format!("Collection {} not found", collection_id)))?;
+    let archived = db
+        .get_record_version(collection_id, record_id, version)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| {
+            AppError::NotFound(format!(
+                "Version {} of record {} not found",
+                version, record_id
+            ))
+        })?;
+    // The schema may have changed since the version was written.
+    if let Some(schema) = &collection.schema {
+        validate_record(schema, &archived.data).map_err(AppError::Validation)?;
+        let errors = check_relations(db.as_ref(), schema, &archived.data)
+            .await
+            .map_err(db_error)?;
+        if !errors.is_empty() {
+            return Err(AppError::Validation(errors));
+        }
+    }
+    let current = db
+        .get_record(collection_id, record_id)
+        .await
+        .map_err(db_error)?;
+    let access = Access::to(&state, &collection).await?;
+    match &current {
+        Some(current) => access.check(Operation::Update, &record_value(current), &archived.data)?,
+        None => access.check(Operation::Create, &serde_json::json!({}), &archived.data)?,
+    }
+    let exists = current.is_some();
+    let record = db
+        .restore_record_version(collection_id, record_id, &archived)
+        .await
+        .map_err(db_error)?;
+    let action = if exists {
+        EventAction::RecordUpdated
+    } else {
+        EventAction::RecordCreated
+    };
+    state
+        .events
+        .publish(Event::new(action, collection_id, Some(record_id)));
+    let mut response = RecordResponse::from(record);
+    shape_records(&state, collection_id, [&mut response]).await?;
+    Ok(Json(response))
+}
@@ -0,0 +1,130 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use tinybase_core::views::{ViewCollection, ViewRow};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{db_error, AppError, AppState};
+
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+#[derive(Serialize, ToSchema)]
+pub struct ViewCollectionResponse {
+    /// `alias.table` of an attached read-only database.
+    name: String,
+    columns: Vec<String>,
+}
+
+impl From<ViewCollection> for ViewCollectionResponse {
+    fn from(v: ViewCollection) -> Self {
+        ViewCollectionResponse {
+            name: v.name,
+            columns: v.columns,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ViewRecordResponse {
+    /// SQLite rowid of the row.
+    id: i64,
+    data: serde_json::Value,
+}
+
+impl From<ViewRow> for ViewRecordResponse {
+    fn from(r: ViewRow) -> Self {
+        ViewRecordResponse {
+            id: r.id,
+            data: r.data,
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct PageQuery {
+    /// Maximum number of rows to return (default 100, at most 1000).
+    limit: Option<u32>,
+    /// Number of rows to skip.
+    offset: Option<u32>,
+}
+
+fn view_not_found(name: &str) -> AppError {
+    AppError::NotFound(format!("View collection {} not found", name))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/views",
+    responses(
+        (status = 200, description = "List the read-only view collections of attached databases", body = Vec<ViewCollectionResponse>),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn list_views(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ViewCollectionResponse>>, AppError> {
+    let views = state.views.list_views().await.map_err(db_error)?;
+    Ok(Json(views.into_iter().map(Into::into).collect()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/views/{name}/records",
+    params(
+        ("name" = String, Path, description = "View collection name, `alias.table`"),
+        PageQuery
+    ),
+    responses(
+        (status = 200, description = "List rows of a view collection", body = Vec<ViewRecordResponse>),
+        (status = 404, description = "View collection not found", body = ProblemDetail),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn list_view_records(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(page): Query<PageQuery>,
+) -> Result<Json<Vec<ViewRecordResponse>>, AppError> {
+    let limit = page.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let rows = state
+        .views
+        .list_rows(&name, limit, page.offset.unwrap_or(0))
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| view_not_found(&name))?;
+    Ok(Json(rows.into_iter().map(Into::into).collect()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/views/{name}/records/{record_id}",
+    params(
+        ("name" = String, Path, description = "View collection name, `alias.table`"),
+        ("record_id" = i64, Path, description = "Row id")
+    ),
+    responses(
+        (status = 200, description = "Get a single row of a view collection", body = ViewRecordResponse),
+        (status = 404, description = "Row not found", body = ProblemDetail),
+        (status = 500, description = "Internal server error", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn get_view_record(
+    State(state): State<AppState>,
+    Path((name, record_id)): Path<(String, i64)>,
+) -> Result<Json<ViewRecordResponse>, AppError> {
+    let row = state
+        .views
+        .get_row(&name, record_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| {
+            AppError::NotFound(format!(
+                "Record {} not found in view collection {}",
+                record_id, name
+            ))
+        })?;
+    Ok(Json(row.into()))
+}
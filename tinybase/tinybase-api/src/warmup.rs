@@ -0,0 +1,61 @@
+//! Warming an instance up before it serves, so the first requests after a
+//! deploy don't pay for cold caches.
+//!
+//! The collections, with their schemas and rules, are read once, and each
+//! of the configured `warm_queries` is run through the router as an
+//! anonymous `GET`. Lists and aggregates of collections with a `cache`
+//! setting land in the [query cache](crate::query_cache), ready for the
+//! anonymous readers of a public site; every other query still leaves the
+//! pages it touched in SQLite's page cache.
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    Router,
+};
+use std::time::{Duration, Instant};
+use tower::ServiceExt;
+
+use crate::AppState;
+
+/// The outcome of one warm-up query.
+pub struct Warmed {
+    pub query: String,
+    /// The status the query answered, or why it couldn't run.
+    pub result: Result<StatusCode, String>,
+    pub elapsed: Duration,
+}
+
+/// Reads the collections of `state`, then runs `queries`, request paths
+/// such as `/api/v1/collections/posts/records?sort=-created`, through `app`
+/// one after the other.
+pub async fn warm_up(state: &AppState, app: &Router, queries: &[String]) -> Vec<Warmed> {
+    if let Err(e) = state.db.list_collections().await {
+        tracing::warn!(error = %e, "failed to load collections while warming up");
+    }
+    let mut warmed = Vec::with_capacity(queries.len());
+    for query in queries {
+        let started = Instant::now();
+        let result = match Request::get(query.as_str()).body(Body::empty()) {
+            Ok(request) => match app.clone().oneshot(request).await {
+                Ok(response) => {
+                    let status = response.status();
+                    // The body is read whole, as a client would, so the
+                    // response is cached.
+                    to_bytes(response.into_body(), usize::MAX)
+                        .await
+                        .map(|_| status)
+                        .map_err(|e| e.to_string())
+                }
+                Err(e) => Err(e.to_string()),
+            },
+            Err(e) => Err(e.to_string()),
+        };
+        warmed.push(Warmed {
+            query: query.clone(),
+            result,
+            elapsed: started.elapsed(),
+        });
+    }
+    warmed
+}
@@ -0,0 +1,486 @@
+//! Webhooks, managed by admins and POSTed the record changes of a
+//! collection.
+//!
+//! [`spawn`] starts the dispatcher. Each delivery is a JSON body signed with
+//! the webhook's secret: the `X-Tinybase-Signature` header carries
+//! `sha256=` and the hex HMAC-SHA256 of the body, for receivers to check.
+//! A delivery that fails, by a network error or a non-2xx answer, is retried
+//! with exponential backoff. Every attempt is kept in the delivery log of
+//! the webhook, with the payload sent and the start of the answer, which
+//! admins read to debug their receivers and from which they replay a
+//! delivery once the receiver is fixed.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use hmac::{Hmac, KeyInit, Mac};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::time::{Duration, Instant};
+use tinybase_core::{
+    clock,
+    events::{Event, EventAction},
+    webhooks::{
+        DeliveryAttempt, Webhook, WebhookDelivery, WebhookSettings, RESPONSE_BODY_LIMIT,
+        WEBHOOK_EVENTS,
+    },
+};
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::{
+    auth::{generate_key, RequireAdmin},
+    db_error, AppError, AppState, ProblemDetail, RecordResponse,
+};
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Attempts made at each delivery, the first included.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Wait before the first retry, doubled before each next one.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// The signature of `body` with `secret`, as sent in `X-Tinybase-Signature`.
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(body);
+    let digest: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("sha256={}", digest)
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WebhookResponse {
+    id: i64,
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    settings: WebhookSettings,
+    /// The signing secret, only shown when the webhook is created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret: Option<String>,
+    created: String,
+    updated: String,
+}
+
+impl From<Webhook> for WebhookResponse {
+    fn from(w: Webhook) -> Self {
+        WebhookResponse {
+            id: w.id,
+            settings: w.settings,
+            secret: None,
+            created: w.created,
+            updated: w.updated,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DeliveryResponse {
+    id: i64,
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    attempt: DeliveryAttempt,
+    created: String,
+}
+
+impl From<WebhookDelivery> for DeliveryResponse {
+    fn from(d: WebhookDelivery) -> Self {
+        DeliveryResponse {
+            id: d.id,
+            attempt: d.attempt,
+            created: d.created,
+        }
+    }
+}
+
+fn webhook_not_found(id: i64) -> AppError {
+    AppError::NotFound(format!("Webhook {} not found", id))
+}
+
+fn delivery_not_found(id: i64, delivery_id: i64) -> AppError {
+    AppError::NotFound(format!(
+        "Delivery {} of webhook {} not found",
+        delivery_id, id
+    ))
+}
+
+fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .build()
+        .unwrap_or_default()
+}
+
+/// The first [`RESPONSE_BODY_LIMIT`] bytes of `body`, as text.
+fn response_excerpt(body: &[u8]) -> String {
+    String::from_utf8_lossy(&body[..body.len().min(RESPONSE_BODY_LIMIT)]).into_owned()
+}
+
+/// The body delivered for `event`: the event and, unless the record was
+/// deleted, the record as it is when the event is dispatched.
+async fn payload(state: &AppState, event: &Event) -> Value {
+    let record = match (event.action, event.record_id) {
+        (EventAction::RecordDeleted, _) | (_, None) => None,
+        (_, Some(id)) => match state.db.get_record(event.collection_id, id).await {
+            Ok(record) => record.map(RecordResponse::from),
+            Err(e) => {
+                tracing::warn!(record = id, error = %e, "failed to load record for webhook");
+                None
+            }
+        },
+    };
+    json!({
+        "event": event.action.name(),
+        "collection_id": event.collection_id,
+        "record_id": event.record_id,
+        "record": record,
+        "timestamp": event.timestamp,
+    })
+}
+
+/// Makes one attempt at delivering `payload` to `webhook`.
+async fn attempt(
+    client: &reqwest::Client,
+    webhook: &Webhook,
+    event: &str,
+    payload: &Value,
+    attempt: u32,
+) -> DeliveryAttempt {
+    let body = payload.to_string();
+    let started = Instant::now();
+    let result = client
+        .post(&webhook.settings.url)
+        .header("content-type", "application/json")
+        .header("x-tinybase-event", event)
+        .header(
+            "x-tinybase-signature",
+            signature(&webhook.secret, body.as_bytes()),
+        )
+        .body(body)
+        .send()
+        .await;
+    let (status, error, response) = match result {
+        Ok(response) => {
+            let status = response.status();
+            let error = (!status.is_success()).then(|| format!("The receiver answered {}", status));
+            let body = response.bytes().await.unwrap_or_default();
+            (Some(status), error, Some(response_excerpt(&body)))
+        }
+        Err(e) => (None, Some(e.to_string()), None),
+    };
+    DeliveryAttempt {
+        webhook_id: webhook.id,
+        event: event.to_string(),
+        payload: payload.clone(),
+        attempt,
+        status: status.map(|s| s.as_u16()),
+        error,
+        response,
+        duration_ms: started.elapsed().as_millis() as u64,
+        replay_of: None,
+    }
+}
+
+/// Delivers `payload` to `webhook`, retrying until an attempt succeeds or
+/// none are left, and logs every attempt.
+async fn deliver(
+    state: AppState,
+    client: reqwest::Client,
+    webhook: Webhook,
+    event: &str,
+    payload: Value,
+) {
+    let mut delay = RETRY_DELAY;
+    for n in 1..=MAX_ATTEMPTS {
+        let result = attempt(&client, &webhook, event, &payload, n).await;
+        if let Err(e) = state.db.record_webhook_delivery(&result).await {
+            tracing::error!(webhook = webhook.id, error = %e, "failed to log webhook delivery");
+        }
+        if result.succeeded() {
+            return;
+        }
+        tracing::warn!(
+            webhook = webhook.id,
+            attempt = n,
+            error = result.error.as_deref().unwrap_or_default(),
+            "webhook delivery failed"
+        );
+        if n < MAX_ATTEMPTS {
+            // A server shutting down doesn't wait to retry; the failed
+            // attempts stay in the log for replaying.
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = state.shutdown.flushing() => return,
+            }
+            delay *= 2;
+        }
+    }
+}
+
+/// Starts delivering record changes to the webhooks subscribed to them, in
+/// the background. Deliveries run concurrently, so a slow or failing
+/// receiver does not hold up the others. When the server shuts down the
+/// events already queued are still delivered.
+pub fn spawn(state: AppState) {
+    let client = client();
+    let mut events = state.events.subscribe();
+    let shutdown = state.shutdown.clone();
+    shutdown.spawn(clock::with_clock(state.clock.clone(), async move {
+        loop {
+            let event = tokio::select! {
+                biased;
+                event = events.recv() => match event {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+                _ = state.shutdown.flushing() => break,
+            };
+            if !WEBHOOK_EVENTS.contains(&event.action) {
+                continue;
+            }
+            let webhooks = match state.db.list_webhooks().await {
+                Ok(webhooks) => webhooks,
+                Err(e) => {
+                    tracing::error!(error = %e, "failed to load webhooks");
+                    continue;
+                }
+            };
+            let mut subscribed = webhooks
+                .into_iter()
+                .filter(|w| w.settings.wants(event.action, event.collection_id))
+                .peekable();
+            if subscribed.peek().is_none() {
+                continue;
+            }
+            let payload = payload(&state, &event).await;
+            for webhook in subscribed {
+                state.shutdown.spawn(clock::with_clock(
+                    state.clock.clone(),
+                    deliver(
+                        state.clone(),
+                        client.clone(),
+                        webhook,
+                        event.action.name(),
+                        payload.clone(),
+                    ),
+                ));
+            }
+        }
+    }));
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/webhooks",
+    responses(
+        (status = 200, description = "List the webhooks", body = Vec<WebhookResponse>),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn list_webhooks(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<WebhookResponse>>, AppError> {
+    let webhooks = state.db.list_webhooks().await.map_err(db_error)?;
+    Ok(Json(webhooks.into_iter().map(Into::into).collect()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/webhooks",
+    request_body(content = Object, description = "The `url` to POST to, the `collection_id` to watch and the `events` to deliver: `record.created`, `record.updated` and/or `record.deleted`"),
+    responses(
+        (status = 201, description = "Add a webhook; the response carries its signing `secret`, which is not shown again", body = WebhookResponse),
+        (status = 400, description = "Invalid URL or events", body = ProblemDetail),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Collection not found", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn create_webhook(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Json(settings): Json<WebhookSettings>,
+) -> Result<(StatusCode, Json<WebhookResponse>), AppError> {
+    let url = reqwest::Url::parse(&settings.url)
+        .map_err(|_| AppError::BadRequest(format!("'{}' is not a URL", settings.url)))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(AppError::BadRequest(format!(
+            "'{}' is not an http(s) URL",
+            settings.url
+        )));
+    }
+    if settings.events.is_empty() {
+        return Err(AppError::BadRequest(
+            "A webhook must subscribe to at least one event".to_string(),
+        ));
+    }
+    if let Some(event) = settings
+        .events
+        .iter()
+        .find(|e| !WEBHOOK_EVENTS.iter().any(|w| w.name() == e.as_str()))
+    {
+        return Err(AppError::BadRequest(format!(
+            "'{}' is not one of record.created, record.updated or record.deleted",
+            event
+        )));
+    }
+    state
+        .db
+        .get_collection(settings.collection_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| {
+            AppError::NotFound(format!("Collection {} not found", settings.collection_id))
+        })?;
+    let secret = generate_key();
+    let webhook = state
+        .db
+        .create_webhook(&settings, &secret)
+        .await
+        .map_err(db_error)?;
+    let mut response = WebhookResponse::from(webhook);
+    response.secret = Some(secret);
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/webhooks/{id}",
+    params(
+        ("id" = i64, Path, description = "Webhook id")
+    ),
+    responses(
+        (status = 204, description = "Remove a webhook and its delivery log"),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Webhook not found", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn delete_webhook(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, AppError> {
+    let db = &state.db;
+    db.get_webhook(id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| webhook_not_found(id))?;
+    db.delete_webhook(id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/webhooks/{id}/deliveries",
+    params(
+        ("id" = i64, Path, description = "Webhook id")
+    ),
+    responses(
+        (status = 200, description = "The latest delivery attempts of a webhook, newest first, with the payload sent and the status or error received", body = Vec<DeliveryResponse>),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Webhook not found", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn list_deliveries(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<Vec<DeliveryResponse>>, AppError> {
+    let db = &state.db;
+    db.get_webhook(id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| webhook_not_found(id))?;
+    let deliveries = db.list_webhook_deliveries(id).await.map_err(db_error)?;
+    Ok(Json(deliveries.into_iter().map(Into::into).collect()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/webhooks/{id}/deliveries/{delivery_id}",
+    params(
+        ("id" = i64, Path, description = "Webhook id"),
+        ("delivery_id" = i64, Path, description = "Delivery id")
+    ),
+    responses(
+        (status = 200, description = "One delivery attempt, with the payload sent and the status, error and start of the body received", body = DeliveryResponse),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Webhook or delivery not found", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn get_delivery(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Path((id, delivery_id)): Path<(i64, i64)>,
+) -> Result<Json<DeliveryResponse>, AppError> {
+    let delivery = state
+        .db
+        .get_webhook_delivery(id, delivery_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| delivery_not_found(id, delivery_id))?;
+    Ok(Json(delivery.into()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/webhooks/{id}/deliveries/{delivery_id}/replay",
+    params(
+        ("id" = i64, Path, description = "Webhook id"),
+        ("delivery_id" = i64, Path, description = "Delivery id")
+    ),
+    responses(
+        (status = 200, description = "Send the payload of a delivery again, once, to the webhook's current URL and signed with its secret; the attempt is logged, and returned, whether the receiver accepts it or not", body = DeliveryResponse),
+        (status = 401, description = "Admin API key missing or invalid", body = ProblemDetail),
+        (status = 404, description = "Webhook or delivery not found", body = ProblemDetail)
+    )
+)]
+pub(crate) async fn replay_delivery(
+    _: RequireAdmin,
+    State(state): State<AppState>,
+    Path((id, delivery_id)): Path<(i64, i64)>,
+) -> Result<Json<DeliveryResponse>, AppError> {
+    let db = &state.db;
+    let webhook = db
+        .get_webhook(id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| webhook_not_found(id))?;
+    let delivery = db
+        .get_webhook_delivery(id, delivery_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| delivery_not_found(id, delivery_id))?;
+    let original = delivery.attempt;
+    let mut result = attempt(&client(), &webhook, &original.event, &original.payload, 1).await;
+    result.replay_of = Some(delivery_id);
+    let replayed = db
+        .record_webhook_delivery(&result)
+        .await
+        .map_err(db_error)?;
+    Ok(Json(replayed.into()))
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        list_webhooks,
+        create_webhook,
+        delete_webhook,
+        list_deliveries,
+        get_delivery,
+        replay_delivery
+    ),
+    components(schemas(WebhookResponse, DeliveryResponse, ProblemDetail))
+)]
+pub(crate) struct WebhooksApiDoc;
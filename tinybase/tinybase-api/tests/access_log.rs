@@ -0,0 +1,84 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use serde_json::Value;
+use tinybase_api::{
+    access_log::{self, AccessLog, AccessLogFormat},
+    app_router,
+};
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state, temp_path};
+
+async fn get(app: &axum::Router, uri: &str) -> StatusCode {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(uri)
+                .header("user-agent", "curl/8.5.0")
+                .header("referer", "https://app.example.com/")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    response.status()
+}
+
+#[tokio::test]
+async fn test_combined_access_log() {
+    let path = temp_path("access.log");
+    let log = AccessLog::open(&path, AccessLogFormat::Combined, 1 << 20, 1)
+        .await
+        .unwrap();
+    let app = access_log::log(setup_test_app().await, log);
+
+    assert_eq!(
+        get(&app, "/api/v1/collections?page=1").await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        get(&app, "/api/v1/collections/999").await,
+        StatusCode::NOT_FOUND
+    );
+
+    let text = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("- - - ["));
+    assert!(lines[0].contains(
+        "] \"GET /api/v1/collections?page=1 HTTP/1.1\" 200 2 \"https://app.example.com/\" \"curl/8.5.0\""
+    ));
+    assert!(lines[1].contains("\"GET /api/v1/collections/999 HTTP/1.1\" 404 "));
+}
+
+#[tokio::test]
+async fn test_json_access_log_rotates() {
+    let path = temp_path("access.jsonl");
+    let log = AccessLog::open(&path, AccessLogFormat::Json, 300, 2)
+        .await
+        .unwrap();
+    let app = access_log::log(app_router(setup_test_state().await), log);
+
+    for _ in 0..6 {
+        get(&app, "/api/v1/collections").await;
+    }
+
+    let rotated = |n: usize| {
+        let mut name = path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        std::path::PathBuf::from(name)
+    };
+    assert!(rotated(1).is_file() && rotated(2).is_file());
+    assert!(!rotated(3).exists());
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert!(text.len() <= 300);
+    let entry: Value = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+    assert_eq!(entry["method"], "GET");
+    assert_eq!(entry["uri"], "/api/v1/collections");
+    assert_eq!(entry["status"], 200);
+    assert_eq!(entry["user_agent"], "curl/8.5.0");
+}
@@ -0,0 +1,512 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+};
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state, temp_path};
+use tinybase_api::app_router;
+use tinybase_core::{setup_database, snapshot::SnapshotVerifier, Db};
+
+#[tokio::test]
+async fn test_rule_test_endpoint() {
+    let app = setup_test_app().await;
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/admin/rules/test")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    r#"{ "rule": "@request.auth.id = owner && status = 'published'", "auth": { "id": "u1" }, "record": { "owner": "u2", "status": "published" } }"#,
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(result["allowed"], false);
+    let trace = result["trace"].as_array().unwrap();
+    assert_eq!(
+        trace[0],
+        r#"@request.auth.id = owner -> "u1" = "u2" -> false"#
+    );
+}
+
+#[tokio::test]
+async fn test_rule_test_endpoint_invalid_rule() {
+    let app = setup_test_app().await;
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/admin/rules/test")
+                .header("content-type", "application/json")
+                .body(Body::from(r#"{ "rule": "owner = (" }"#))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_rule_functions_and_collection_lookups() {
+    let app = setup_test_app().await;
+    let test = |body: serde_json::Value| {
+        app.clone().oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/admin/rules/test")
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+    };
+    let allowed = |response: axum::response::Response| async move {
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+        let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        result["allowed"].as_bool().unwrap()
+    };
+    let context = serde_json::json!({
+        "auth": { "id": "u1", "email": "Ann@Example.com" },
+        "record": {
+            "team": "t1",
+            "tags": ["a", "b"],
+            "lat": 52.52, "lon": 13.405,
+            "published": "2024-01-01T00:00:00.000Z"
+        },
+        "collections": { "memberships": [
+            { "user": "u1", "team": "t1", "role": "editor" },
+            { "user": "u2", "team": "t1", "role": "admin" }
+        ] }
+    });
+    let cases = [
+        ("lower(@request.auth.email) = 'ann@example.com'", true),
+        ("in(@request.auth.id, 'u1', 'u3') && !in('c', tags)", true),
+        ("len(tags) = 2 && len(@request.auth.id) > 2", false),
+        // Berlin to Paris is about 878 km.
+        ("geo_distance(lat, lon, 48.8566, 2.3522) < 900", true),
+        ("date_diff(date_add(published, 2, 'days'), published, 'hours') = 48", true),
+        ("date_add(published, 30, 'days') > now()", false),
+        (
+            "len(@collection.memberships.filter(user = @request.auth.id && team = @record.team && in(role, 'editor', 'admin'))) > 0",
+            true,
+        ),
+        ("@collection.memberships.filter(user = @request.auth.id && role = 'admin')", false),
+    ];
+    for (rule, expected) in cases {
+        let mut body = context.clone();
+        body["rule"] = rule.into();
+        let response = test(body).await.unwrap();
+        assert_eq!(allowed(response).await, expected, "{}", rule);
+    }
+
+    for rule in ["sqrt(2) > 1", "lower('a', 'b') = 'a'"] {
+        let response = test(serde_json::json!({ "rule": rule })).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", rule);
+    }
+}
+
+#[tokio::test]
+async fn test_render_template() {
+    let app = setup_test_app().await;
+    let render = |body: &'static str| {
+        app.clone().oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/admin/templates/render")
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap(),
+        )
+    };
+
+    let response = render(
+        r#"{
+            "template": {
+                "text": "New post *{{ record.data.title }}* by {{record.data.author}}{{ record.data.missing }}",
+                "blocks": [{ "id": "{{ record.id }}", "tags": "{{ record.data.tags }}", "first": "{{ record.data.tags.0 }}" }],
+                "event": "{{ event.action }}",
+                "missing": "{{ record.data.missing }}",
+                "static": true
+            },
+            "context": {
+                "event": { "action": "record.created" },
+                "record": { "id": 7, "data": { "title": "Hello", "author": "ann", "tags": ["a", "b"] } }
+            }
+        }"#,
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        result["payload"],
+        serde_json::json!({
+            "text": "New post *Hello* by ann",
+            "blocks": [{ "id": 7, "tags": ["a", "b"], "first": "a" }],
+            "event": "record.created",
+            "missing": null,
+            "static": true
+        })
+    );
+
+    let response = render(r#"{ "template": { "text": "{{ record.id" } }"#)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_policy_export_and_import() {
+    let app = setup_test_app().await;
+
+    app.clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/collections")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    r#"{ "name": "posts", "schema": { "fields": {}, "rules": { "list": "" } } }"#,
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("PUT")
+                .uri("/api/v1/admin/policy")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    r#"{ "version": 1, "collections": [ { "name": "posts", "rules": { "list": "", "view": "@request.auth.id != null" } } ] }"#,
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("GET")
+                .uri("/api/v1/admin/policy")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let policy: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(policy["version"], 1);
+    assert_eq!(policy["collections"][0]["name"], "posts");
+    assert_eq!(policy["collections"][0]["rules"]["list"], "");
+    assert_eq!(
+        policy["collections"][0]["rules"]["view"],
+        "@request.auth.id != null"
+    );
+    assert!(policy["collections"][0]["rules"]["delete"].is_null());
+}
+
+#[tokio::test]
+async fn test_policy_import_rejects_invalid_rule() {
+    let app = setup_test_app().await;
+
+    app.clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/collections")
+                .header("content-type", "application/json")
+                .body(Body::from(r#"{ "name": "posts" }"#))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("PUT")
+                .uri("/api/v1/admin/policy")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    r#"{ "version": 1, "collections": [ { "name": "posts", "rules": { "list": "owner = " } } ] }"#,
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_activity_feed_streams_events() {
+    let app = setup_test_app().await;
+
+    let feed = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/api/v1/admin/activity")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(feed.status(), StatusCode::OK);
+    assert_eq!(feed.headers()["content-type"], "text/event-stream");
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/collections")
+                .header("content-type", "application/json")
+                .body(Body::from(r#"{ "name": "posts" }"#))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let mut body = feed.into_body();
+    let frame = tokio::time::timeout(std::time::Duration::from_secs(5), body.frame())
+        .await
+        .expect("no activity event received")
+        .unwrap()
+        .unwrap();
+    let chunk = String::from_utf8(frame.into_data().unwrap().to_vec()).unwrap();
+    assert!(chunk.starts_with("event: activity"));
+    assert!(chunk.contains(r#""action":"collection.created""#));
+}
+
+#[tokio::test]
+async fn test_activity_feed_is_for_admins_and_reports_sign_ins() {
+    let app = setup_test_app().await;
+    let admin = r#"{ "email": "admin@example.com", "password": "correct horse" }"#;
+    let (_, created) = call(&app, "POST", "/api/v1/admin/admins", admin).await;
+    let (_, auth) = call(&app, "POST", "/api/v1/admin/auth", admin).await;
+    let key = auth["key"].as_str().unwrap();
+
+    let feed = |key: Option<&str>| {
+        let mut request = Request::builder().uri("/api/v1/admin/activity");
+        if let Some(key) = key {
+            request = request.header("authorization", format!("Bearer {}", key));
+        }
+        app.clone().oneshot(request.body(Body::empty()).unwrap())
+    };
+    let response = feed(None).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = feed(Some(key)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    call(&app, "POST", "/api/v1/admin/auth", admin).await;
+    let mut body = response.into_body();
+    let frame = tokio::time::timeout(std::time::Duration::from_secs(5), body.frame())
+        .await
+        .expect("no activity event received")
+        .unwrap()
+        .unwrap();
+    let chunk = String::from_utf8(frame.into_data().unwrap().to_vec()).unwrap();
+    assert!(chunk.contains(r#""action":"admin.authenticated""#));
+    assert!(chunk.contains(&format!(r#""admin_id":{}"#, created["id"])));
+}
+
+async fn verify(app: &axum::Router) -> serde_json::Value {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/admin/backups/verification")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn test_snapshot_verification() {
+    let backup_dir = temp_path("backups");
+    std::fs::create_dir_all(&backup_dir).unwrap();
+    let db = libsql::Builder::new_local(backup_dir.join("snapshot-1.db"))
+        .build()
+        .await
+        .unwrap();
+    setup_database(&db.connect().unwrap()).await.unwrap();
+    let collection = db.create_collection("posts", &None).await.unwrap();
+    db.create_record(collection.id, &serde_json::json!({ "title": "Hello" }))
+        .await
+        .unwrap();
+    drop(db);
+
+    let app = app_router(
+        setup_test_state()
+            .await
+            .with_snapshot_verifier(SnapshotVerifier::new(&backup_dir)),
+    );
+    let report = verify(&app).await;
+    assert_eq!(report["ok"], true, "{}", report);
+    assert_eq!(report["integrity"], "ok");
+    assert_eq!(report["collections"], 1);
+    assert_eq!(report["records"], 1);
+
+    std::fs::write(backup_dir.join("snapshot-2.db"), b"not a database").unwrap();
+    let report = verify(&app).await;
+    assert_eq!(report["ok"], false);
+    assert!(report["snapshot"]
+        .as_str()
+        .unwrap()
+        .ends_with("snapshot-2.db"));
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/api/v1/admin/backups/verification")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let last: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(last["checked_at"], report["checked_at"]);
+}
+
+#[tokio::test]
+async fn test_snapshot_verification_not_configured() {
+    let app = setup_test_app().await;
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/api/v1/admin/backups/verification")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+async fn call(
+    app: &axum::Router,
+    method: &str,
+    uri: &str,
+    body: &str,
+) -> (StatusCode, serde_json::Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+#[tokio::test]
+async fn test_backup_and_restore() {
+    let backup_dir = temp_path("backups");
+    let app = app_router(setup_test_state().await.with_backups(&backup_dir));
+    call(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        r#"{ "name": "posts" }"#,
+    )
+    .await;
+    call(
+        &app,
+        "POST",
+        "/api/v1/collections/posts/records",
+        r#"{ "data": { "title": "kept" } }"#,
+    )
+    .await;
+
+    let (status, backup) = call(&app, "POST", "/api/v1/admin/backup", "").await;
+    assert_eq!(status, StatusCode::CREATED);
+    let name = backup["name"].as_str().unwrap().to_string();
+    assert!(name.starts_with("tinybase-") && name.ends_with(".db"));
+    assert!(backup_dir.join(&name).is_file());
+
+    let (_, backups) = call(&app, "GET", "/api/v1/admin/backups", "").await;
+    assert_eq!(backups.as_array().unwrap().len(), 1);
+    assert_eq!(backups[0]["name"], name.as_str());
+
+    call(
+        &app,
+        "POST",
+        "/api/v1/collections/posts/records",
+        r#"{ "data": { "title": "lost" } }"#,
+    )
+    .await;
+    call(&app, "POST", "/api/v1/collections", r#"{ "name": "tags" }"#).await;
+
+    let (status, _) = call(
+        &app,
+        "POST",
+        &format!("/api/v1/admin/backups/{}/restore", name),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, records) = call(&app, "GET", "/api/v1/collections/posts/records", "").await;
+    assert_eq!(records.as_array().unwrap().len(), 1);
+    assert_eq!(records[0]["data"]["title"], "kept");
+    let (status, _) = call(&app, "GET", "/api/v1/collections/tags", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Ids handed out after the snapshot are not reused.
+    let (_, record) = call(
+        &app,
+        "POST",
+        "/api/v1/collections/posts/records",
+        r#"{ "data": { "title": "new" } }"#,
+    )
+    .await;
+    assert_eq!(record["id"], 3);
+
+    let (status, _) = call(&app, "POST", "/api/v1/admin/backups/missing.db/restore", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_backups_not_configured() {
+    let app = setup_test_app().await;
+    let (status, _) = call(&app, "POST", "/api/v1/admin/backup", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = call(&app, "GET", "/api/v1/admin/backups", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
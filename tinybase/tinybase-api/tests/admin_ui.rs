@@ -0,0 +1,151 @@
+#![cfg(feature = "admin-ui")]
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{header, Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+mod common;
+use common::setup_test_app;
+
+async fn send(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    key: Option<&str>,
+    body: &str,
+) -> (StatusCode, Option<String>, Vec<u8>) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json");
+    if let Some(key) = key {
+        request = request.header("authorization", format!("Bearer {}", key));
+    }
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::from(body.to_string())).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .map(|v| v.to_str().unwrap().to_string());
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, content_type, body.to_vec())
+}
+
+async fn get_json(app: &Router, uri: &str, key: Option<&str>) -> (StatusCode, Value) {
+    let (status, _, body) = send(app, "GET", uri, key, "").await;
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn test_dashboard_is_served() {
+    let app = setup_test_app().await;
+    let (status, _, _) = send(&app, "GET", "/admin", None, "").await;
+    assert_eq!(status, StatusCode::PERMANENT_REDIRECT);
+
+    let (status, content_type, body) = send(&app, "GET", "/admin/", None, "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(content_type.unwrap().starts_with("text/html"));
+    assert!(String::from_utf8(body).unwrap().contains("/admin/admin.js"));
+
+    let (status, content_type, _) = send(&app, "GET", "/admin/admin.js", None, "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(content_type.unwrap().starts_with("text/javascript"));
+    let (status, _, _) = send(&app, "GET", "/admin/admin.css", None, "").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, meta) = get_json(&app, "/api/v1/meta", None).await;
+    assert!(meta["features"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("admin-ui")));
+}
+
+#[tokio::test]
+async fn test_dashboard_endpoints() {
+    let app = setup_test_app().await;
+    for (name, records) in [("posts", 2), ("authors", 1), ("tags", 0)] {
+        send(
+            &app,
+            "POST",
+            "/api/v1/collections",
+            None,
+            &json!({ "name": name }).to_string(),
+        )
+        .await;
+        for _ in 0..records {
+            send(
+                &app,
+                "POST",
+                &format!("/api/v1/collections/{}/records", name),
+                None,
+                r#"{ "data": { "title": "Hello" } }"#,
+            )
+            .await;
+        }
+    }
+
+    let (status, collections) = get_json(&app, "/api/v1/admin/collections", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let counts: Vec<_> = collections
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| (c["name"].as_str().unwrap(), c["records"].as_i64().unwrap()))
+        .collect();
+    assert_eq!(counts, [("authors", 1), ("posts", 2), ("tags", 0)]);
+
+    let (status, types) = get_json(&app, "/api/v1/admin/field-types", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let string = types
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|t| t["name"] == "string")
+        .unwrap();
+    assert_eq!(
+        string["constraints"],
+        json!(["min_length", "max_length", "pattern"])
+    );
+    assert!(types
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|t| t["name"] == "relation"));
+
+    // Once there is an admin, the endpoints want their key; the page itself
+    // stays public, as it signs in.
+    send(
+        &app,
+        "POST",
+        "/api/v1/admin/admins",
+        None,
+        r#"{ "email": "admin@example.com", "password": "correct horse" }"#,
+    )
+    .await;
+    let (status, _) = get_json(&app, "/api/v1/admin/collections", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = get_json(&app, "/api/v1/admin/field-types", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (_, _, auth) = send(
+        &app,
+        "POST",
+        "/api/v1/admin/auth",
+        None,
+        r#"{ "email": "admin@example.com", "password": "correct horse" }"#,
+    )
+    .await;
+    let auth: Value = serde_json::from_slice(&auth).unwrap();
+    let key = auth["key"].as_str().unwrap();
+    let (status, _) = get_json(&app, "/api/v1/admin/collections", Some(key)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = send(&app, "GET", "/admin/", None, "").await;
+    assert_eq!(status, StatusCode::OK);
+}
@@ -0,0 +1,190 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+mod common;
+use common::setup_test_app;
+
+async fn send(app: &Router, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+async fn create_post(app: &Router, data: Value) -> String {
+    send(
+        app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "posts" }),
+    )
+    .await;
+    let (_, post) = send(
+        app,
+        "POST",
+        "/api/v1/collections/posts/records",
+        json!({ "data": data }),
+    )
+    .await;
+    format!("/api/v1/collections/posts/records/{}/array", post["id"])
+}
+
+#[tokio::test]
+async fn test_push_remove_and_add_unique() {
+    let app = setup_test_app().await;
+    let uri = create_post(&app, json!({ "title": "Hello" })).await;
+
+    // A missing field counts as an empty array.
+    let (status, post) = send(
+        &app,
+        "POST",
+        &uri,
+        json!({ "field": "tags", "op": "push", "value": "rust" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", post);
+    assert_eq!(post["data"]["tags"], json!(["rust"]));
+    assert_eq!(post["data"]["title"], "Hello");
+
+    for value in [json!("rust"), json!({ "a": 1 }), json!(true), json!(2)] {
+        send(
+            &app,
+            "POST",
+            &uri,
+            json!({ "field": "tags", "op": "push", "value": value }),
+        )
+        .await;
+    }
+    let (_, post) = send(
+        &app,
+        "POST",
+        &uri,
+        json!({ "field": "tags", "op": "add_unique", "value": { "a": 1 } }),
+    )
+    .await;
+    assert_eq!(
+        post["data"]["tags"],
+        json!(["rust", "rust", { "a": 1 }, true, 2])
+    );
+    let (_, post) = send(
+        &app,
+        "POST",
+        &uri,
+        json!({ "field": "tags", "op": "add_unique", "value": "db" }),
+    )
+    .await;
+    assert_eq!(
+        post["data"]["tags"],
+        json!(["rust", "rust", { "a": 1 }, true, 2, "db"])
+    );
+
+    // Every equal element goes, and nothing else.
+    let (_, post) = send(
+        &app,
+        "POST",
+        &uri,
+        json!({ "field": "tags", "op": "remove", "value": "rust" }),
+    )
+    .await;
+    assert_eq!(post["data"]["tags"], json!([{ "a": 1 }, true, 2, "db"]));
+    let (_, post) = send(
+        &app,
+        "POST",
+        &uri,
+        json!({ "field": "tags", "op": "remove", "value": 1 }),
+    )
+    .await;
+    assert_eq!(post["data"]["tags"], json!([{ "a": 1 }, true, 2, "db"]));
+}
+
+#[tokio::test]
+async fn test_array_update_errors() {
+    let app = setup_test_app().await;
+    let uri = create_post(&app, json!({ "title": "Hello" })).await;
+
+    let (status, _) = send(
+        &app,
+        "POST",
+        &uri,
+        json!({ "field": "title", "op": "push", "value": "x" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (_, post) = send(&app, "GET", &uri.replace("/array", ""), Value::Null).await;
+    assert_eq!(post["data"]["title"], "Hello");
+
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections/posts/records/999/array",
+        json!({ "field": "tags", "op": "push", "value": "x" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "typed", "schema": { "fields": {
+            "name": { "type": "string", "required": false },
+            "sizes": { "type": { "array": { "items": "number" } }, "required": false }
+        } } }),
+    )
+    .await;
+    let (_, record) = send(
+        &app,
+        "POST",
+        "/api/v1/collections/typed/records",
+        json!({ "data": { "name": "a" } }),
+    )
+    .await;
+    let (status, _) = send(
+        &app,
+        "POST",
+        &format!("/api/v1/collections/typed/records/{}/array", record["id"]),
+        json!({ "field": "name", "op": "push", "value": "x" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    // Typed arrays only take items of their type.
+    let uri = format!("/api/v1/collections/typed/records/{}/array", record["id"]);
+    let (status, post) = send(
+        &app,
+        "POST",
+        &uri,
+        json!({ "field": "sizes", "op": "push", "value": 42 }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(post["data"]["sizes"], json!([42]));
+    let (status, problem) = send(
+        &app,
+        "POST",
+        &uri,
+        json!({ "field": "sizes", "op": "push", "value": "large" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let detail = &problem["details"][0];
+    assert_eq!(detail["path"], "/sizes/-");
+    assert_eq!(detail["code"], "invalid_type");
+    assert_eq!(detail["expected"], "number");
+    assert_eq!(detail["value"], "large");
+}
@@ -0,0 +1,544 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use tinybase_api::app_router;
+use tinybase_core::clock::ManualClock;
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state};
+
+async fn send(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    key: Option<&str>,
+    body: &str,
+) -> (StatusCode, Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json");
+    if let Some(key) = key {
+        request = request.header("authorization", format!("Bearer {}", key));
+    }
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::from(body.to_string())).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+const ADMIN: &str = r#"{ "email": "admin@example.com", "password": "correct horse" }"#;
+
+#[tokio::test]
+async fn test_admin_authorization() {
+    let app = setup_test_app().await;
+
+    // Until an admin exists the instance can be set up without a key.
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        None,
+        r#"{ "name": "posts" }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, admin) = send(&app, "POST", "/api/v1/admin/admins", None, ADMIN).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(admin["email"], "admin@example.com");
+    assert!(admin.get("password_hash").is_none());
+
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        None,
+        r#"{ "name": "tags" }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        Some("nope"),
+        r#"{ "name": "tags" }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = send(&app, "DELETE", "/api/v1/collections/posts", None, "").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/admin/admins",
+        None,
+        r#"{ "email": "eve@example.com", "password": "12345678" }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/admin/auth",
+        None,
+        r#"{ "email": "admin@example.com", "password": "wrong" }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, auth) = send(&app, "POST", "/api/v1/admin/auth", None, ADMIN).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(auth["admin"]["email"], "admin@example.com");
+    let key = auth["key"].as_str().unwrap();
+
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        Some(key),
+        r#"{ "name": "tags" }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, _) = send(
+        &app,
+        "PATCH",
+        "/api/v1/collections/tags",
+        Some(key),
+        r#"{ "name": "labels" }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, "POST", "/api/v1/admin/admins", Some(key), ADMIN).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // Reads stay open and record CRUD is governed by collection rules, which
+    // `labels` has none of, so only admins may write its records.
+    let (status, _) = send(&app, "GET", "/api/v1/collections/labels", None, "").await;
+    assert_eq!(status, StatusCode::OK);
+    let label = r#"{ "data": { "name": "rust" } }"#;
+    let records = "/api/v1/collections/labels/records";
+    let (status, _) = send(&app, "POST", records, None, label).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send(&app, "POST", records, Some(key), label).await;
+    assert_eq!(status, StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn test_collection_rules_govern_records() {
+    let app = setup_test_app().await;
+    send(&app, "POST", "/api/v1/admin/admins", None, ADMIN).await;
+    let (_, auth) = send(&app, "POST", "/api/v1/admin/auth", None, ADMIN).await;
+    let key = auth["key"].as_str().unwrap();
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        Some(key),
+        r#"{ "name": "notes", "schema": {
+            "fields": {
+                "owner": { "type": "string", "required": true },
+                "likes": { "type": "number", "required": false }
+            },
+            "rules": { "list": "owner = @request.auth.id", "view": "owner = @request.auth.id", "create": "" }
+        } }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let records = "/api/v1/collections/notes/records";
+    let (status, public) = send(
+        &app,
+        "POST",
+        records,
+        None,
+        r#"{ "data": { "owner": "", "likes": 0 } }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, private) = send(
+        &app,
+        "POST",
+        records,
+        None,
+        r#"{ "data": { "owner": "bob", "likes": 0 } }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // The list rule narrows lists; admins see every record.
+    let (_, list) = send(&app, "GET", records, None, "").await;
+    assert_eq!(list.as_array().unwrap().len(), 1);
+    assert_eq!(list[0]["id"], public["id"]);
+    let (_, list) = send(&app, "GET", records, Some(key), "").await;
+    assert_eq!(list.as_array().unwrap().len(), 2);
+
+    let uri = format!("{}/{}", records, private["id"]);
+    let (status, _) = send(&app, "GET", &uri, None, "").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send(&app, "GET", &uri, Some(key), "").await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, "GET", &format!("{}/versions", uri), None, "").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Without update and delete rules, only admins write.
+    let uri = format!("{}/{}", records, public["id"]);
+    let (status, denied) = send(&app, "PATCH", &uri, None, r#"{ "data": { "likes": 1 } }"#).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        denied["message"],
+        "Only admins may update records of collection 'notes'"
+    );
+    let increment = r#"{ "field": "likes" }"#;
+    let (status, _) = send(&app, "POST", &format!("{}/increment", uri), None, increment).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send(&app, "DELETE", &uri, None, "").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let batch = format!(
+        r#"{{ "operations": [ {{ "op": "create", "data": {{ "owner": "" }} }}, {{ "op": "delete", "id": {} }} ] }}"#,
+        public["id"]
+    );
+    let (status, _) = send(&app, "POST", &format!("{}/batch", records), None, &batch).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send(&app, "DELETE", &uri, Some(key), "").await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, list) = send(&app, "GET", records, Some(key), "").await;
+    assert_eq!(list.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_rules_look_up_other_collections() {
+    let app = setup_test_app().await;
+    send(&app, "POST", "/api/v1/admin/admins", None, ADMIN).await;
+    let (_, auth) = send(&app, "POST", "/api/v1/admin/auth", None, ADMIN).await;
+    let key = auth["key"].as_str().unwrap();
+    for collection in [
+        r#"{ "name": "memberships" }"#,
+        r#"{ "name": "docs", "schema": {
+            "fields": { "team": { "type": "string", "required": true } },
+            "rules": { "view": "len(@collection.memberships.filter(user = @request.auth.id && team = @record.team)) > 0" }
+        } }"#,
+    ] {
+        let (status, _) = send(&app, "POST", "/api/v1/collections", Some(key), collection).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+    // Anonymous requests are `@request.auth.id = ""`.
+    let membership = r#"{ "data": { "user": "", "team": "red" } }"#;
+    let memberships = "/api/v1/collections/memberships/records";
+    send(&app, "POST", memberships, Some(key), membership).await;
+    let docs = "/api/v1/collections/docs/records";
+    let (_, red) = send(
+        &app,
+        "POST",
+        docs,
+        Some(key),
+        r#"{ "data": { "team": "red" } }"#,
+    )
+    .await;
+    let (_, blue) = send(
+        &app,
+        "POST",
+        docs,
+        Some(key),
+        r#"{ "data": { "team": "blue" } }"#,
+    )
+    .await;
+
+    let (status, _) = send(&app, "GET", &format!("{}/{}", docs, red["id"]), None, "").await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, "GET", &format!("{}/{}", docs, blue["id"]), None, "").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let membership = r#"{ "data": { "user": "", "team": "blue" } }"#;
+    send(&app, "POST", memberships, Some(key), membership).await;
+    let (status, _) = send(&app, "GET", &format!("{}/{}", docs, blue["id"]), None, "").await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Past the lookup limit the rule can't see every membership, and denies.
+    let operations = vec![r#"{ "op": "create", "data": { "user": "bob", "team": "red" } }"#; 1000];
+    let (status, _) = send(
+        &app,
+        "POST",
+        &format!("{}/batch", memberships),
+        Some(key),
+        &format!(r#"{{ "operations": [{}] }}"#, operations.join(",")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, "GET", &format!("{}/{}", docs, blue["id"]), None, "").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_expansion_follows_the_view_rule() {
+    let app = setup_test_app().await;
+    send(&app, "POST", "/api/v1/admin/admins", None, ADMIN).await;
+    let (_, auth) = send(&app, "POST", "/api/v1/admin/auth", None, ADMIN).await;
+    let key = auth["key"].as_str().unwrap();
+    let (_, users) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        Some(key),
+        r#"{ "name": "users", "schema": {
+            "fields": { "email": { "type": "string", "required": true } },
+            "rules": { "view": "id = 0" }
+        } }"#,
+    )
+    .await;
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        Some(key),
+        &format!(
+            r#"{{ "name": "posts", "schema": {{
+                "fields": {{ "author": {{ "type": {{ "relation": {{ "collection_id": {} }} }}, "required": true }} }},
+                "rules": {{ "list": "", "view": "" }}
+            }} }}"#,
+            users["id"]
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (_, user) = send(
+        &app,
+        "POST",
+        "/api/v1/collections/users/records",
+        Some(key),
+        r#"{ "data": { "email": "ada@example.com" } }"#,
+    )
+    .await;
+    let posts = "/api/v1/collections/posts/records";
+    let (_, post) = send(
+        &app,
+        "POST",
+        posts,
+        Some(key),
+        &format!(r#"{{ "data": {{ "author": {} }} }}"#, user["id"]),
+    )
+    .await;
+
+    // The users rule hides the author, which stays a bare id.
+    let uri = format!("{}/{}?expand=author", posts, post["id"]);
+    let (status, post) = send(&app, "GET", &uri, None, "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(post["expand"]["author"], user["id"]);
+    let (_, list) = send(&app, "GET", &format!("{}?expand=author", posts), None, "").await;
+    assert_eq!(list[0]["expand"]["author"], user["id"]);
+    let (_, post) = send(&app, "GET", &uri, Some(key), "").await;
+    assert_eq!(post["expand"]["author"]["data"]["email"], "ada@example.com");
+}
+
+#[tokio::test]
+async fn test_admin_endpoints_require_a_key() {
+    let app = setup_test_app().await;
+    send(&app, "POST", "/api/v1/admin/admins", None, ADMIN).await;
+    let (_, auth) = send(&app, "POST", "/api/v1/admin/auth", None, ADMIN).await;
+    let key = auth["key"].as_str().unwrap();
+
+    let endpoints = [
+        ("POST", "/api/v1/admin/rules/test", "{}"),
+        ("POST", "/api/v1/admin/templates/render", "{}"),
+        ("GET", "/api/v1/admin/policy", ""),
+        ("GET", "/api/v1/admin/metrics", ""),
+        ("GET", "/api/v1/admin/backups/verification", ""),
+        ("POST", "/api/v1/admin/backups/verification", ""),
+        ("GET", "/api/v1/admin/replicas", ""),
+        ("PUT", "/api/v1/admin/replicas/pin", "{}"),
+    ];
+    for (method, uri, body) in endpoints {
+        let (status, _) = send(&app, method, uri, None, body).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{} {}", method, uri);
+        let (status, _) = send(&app, method, uri, Some(key), body).await;
+        assert_ne!(status, StatusCode::UNAUTHORIZED, "{} {}", method, uri);
+    }
+}
+
+#[tokio::test]
+async fn test_key_expiry_on_manual_clock() {
+    // 2024-05-01T00:00:00Z
+    let clock = Arc::new(ManualClock::new(
+        UNIX_EPOCH + Duration::from_secs(1_714_521_600),
+    ));
+    let app = app_router(setup_test_state().await.with_clock(clock.clone()));
+    send(&app, "POST", "/api/v1/admin/admins", None, ADMIN).await;
+    let (_, auth) = send(&app, "POST", "/api/v1/admin/auth", None, ADMIN).await;
+    let key = auth["key"].as_str().unwrap();
+
+    let (status, collection) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        Some(key),
+        r#"{ "name": "posts" }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(collection["created"], "2024-05-01T00:00:00.000Z");
+
+    clock.advance(Duration::from_secs(24 * 60 * 60 - 1));
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        Some(key),
+        r#"{ "name": "tags" }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    clock.advance(Duration::from_secs(1));
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        Some(key),
+        r#"{ "name": "links" }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_keys_are_stored_hashed() {
+    let state = setup_test_state().await;
+    let app = app_router(state.clone());
+    send(&app, "POST", "/api/v1/admin/admins", None, ADMIN).await;
+    let (_, auth) = send(&app, "POST", "/api/v1/admin/auth", None, ADMIN).await;
+    let key = auth["key"].as_str().unwrap();
+
+    let entry = state
+        .store
+        .get(&format!("admin_key:{}", key))
+        .await
+        .unwrap();
+    assert!(entry.is_none());
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        Some(key),
+        r#"{ "name": "posts" }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn test_token_introspection() {
+    // 2024-05-01T00:00:00Z
+    let clock = Arc::new(ManualClock::new(
+        UNIX_EPOCH + Duration::from_secs(1_714_521_600),
+    ));
+    let app = app_router(setup_test_state().await.with_clock(clock.clone()));
+    let (_, admin) = send(&app, "POST", "/api/v1/admin/admins", None, ADMIN).await;
+    let (_, auth) = send(&app, "POST", "/api/v1/admin/auth", None, ADMIN).await;
+    let key = auth["key"].as_str().unwrap();
+
+    clock.advance(Duration::from_secs(60));
+    let uri = "/api/v1/auth/introspect";
+    let (status, token) = send(&app, "GET", uri, Some(key), "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(token["subject"], format!("admin:{}", admin["id"]));
+    assert_eq!(token["scopes"], serde_json::json!(["admin"]));
+    assert_eq!(token["expires"], "2024-05-02T00:00:00.000Z");
+    assert_eq!(token["expires_in"], 24 * 60 * 60 - 60);
+    assert_eq!(token["admin"]["email"], "admin@example.com");
+
+    let (status, _) = send(&app, "GET", uri, None, "").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = send(&app, "GET", uri, Some("nope"), "").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    clock.advance(Duration::from_secs(24 * 60 * 60));
+    let (status, _) = send(&app, "GET", uri, Some(key), "").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_export_and_import_admins() {
+    let source = setup_test_app().await;
+    send(&source, "POST", "/api/v1/admin/admins", None, ADMIN).await;
+    let (_, auth) = send(&source, "POST", "/api/v1/admin/auth", None, ADMIN).await;
+    let key = auth["key"].as_str().unwrap();
+    let (status, export) = send(&source, "GET", "/api/v1/admin/admins/export", Some(key), "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(export["format"], "tinybase.users");
+    assert_eq!(export["version"], 1);
+    assert_eq!(export["users"][0]["email"], "admin@example.com");
+    assert!(export["users"][0]["password_hash"]
+        .as_str()
+        .unwrap()
+        .starts_with("$argon2"));
+
+    // A fresh instance takes the export and a user from a bcrypt system.
+    let mut document = export.clone();
+    document["users"]
+        .as_array_mut()
+        .unwrap()
+        .push(serde_json::json!({
+            "email": "grace@example.com",
+            "password_hash": bcrypt::hash("from elsewhere", 4).unwrap(),
+        }));
+    let target = setup_test_app().await;
+    let (status, imported) = send(
+        &target,
+        "POST",
+        "/api/v1/admin/admins/import",
+        None,
+        &document.to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        imported["imported"],
+        serde_json::json!(["admin@example.com", "grace@example.com"])
+    );
+    let (status, _) = send(&target, "POST", "/api/v1/admin/auth", None, ADMIN).await;
+    assert_eq!(status, StatusCode::OK);
+    let grace = r#"{ "email": "grace@example.com", "password": "from elsewhere" }"#;
+    let (status, auth) = send(&target, "POST", "/api/v1/admin/auth", None, grace).await;
+    assert_eq!(status, StatusCode::OK);
+    let key = auth["key"].as_str().unwrap();
+    let wrong = r#"{ "email": "grace@example.com", "password": "from nowhere" }"#;
+    let (status, _) = send(&target, "POST", "/api/v1/admin/auth", None, wrong).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Taken emails are skipped, and a bad account stops the whole import.
+    let (_, again) = send(
+        &target,
+        "POST",
+        "/api/v1/admin/admins/import",
+        Some(key),
+        &document.to_string(),
+    )
+    .await;
+    assert_eq!(again["imported"], serde_json::json!([]));
+    assert_eq!(again["skipped"].as_array().unwrap().len(), 2);
+    let bad = serde_json::json!({ "format": "tinybase.users", "version": 1, "users": [
+        { "email": "new@example.com", "password_hash": "$argon2id$v=19$m=19456,t=2,p=1$c2FsdHNhbHQ$aGFzaGhhc2hoYXNoaGFzaA" },
+        { "email": "md5@example.com", "password_hash": "5f4dcc3b5aa765d61d8327deb882cf99" }
+    ] });
+    let (status, _) = send(
+        &target,
+        "POST",
+        "/api/v1/admin/admins/import",
+        Some(key),
+        &bad.to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (_, export) = send(&target, "GET", "/api/v1/admin/admins/export", Some(key), "").await;
+    assert_eq!(export["users"].as_array().unwrap().len(), 2);
+    let (status, _) = send(&target, "GET", "/api/v1/admin/admins/export", None, "").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
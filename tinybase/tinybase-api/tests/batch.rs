@@ -0,0 +1,193 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+
+mod common;
+use common::setup_test_app;
+
+async fn send(
+    app: &axum::Router,
+    method: &str,
+    uri: &str,
+    body: &str,
+) -> (StatusCode, serde_json::Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(if body.is_empty() {
+                    Body::empty()
+                } else {
+                    Body::from(body.to_string())
+                })
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let value = serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null);
+    (status, value)
+}
+
+async fn create_test_collection(app: &axum::Router) -> i64 {
+    let (_, collection) = send(
+        app,
+        "POST",
+        "/api/v1/collections",
+        r#"{ "name": "posts", "schema": { "fields": { "title": { "type": "string", "required": true } } } }"#,
+    )
+    .await;
+    collection["id"].as_i64().unwrap()
+}
+
+#[tokio::test]
+async fn test_batch_operations() {
+    let app = setup_test_app().await;
+    let id = create_test_collection(&app).await;
+    let batch_uri = format!("/api/v1/collections/{}/records/batch", id);
+
+    let (status, results) = send(
+        &app,
+        "POST",
+        &batch_uri,
+        r#"{ "operations": [
+            { "op": "create", "data": { "title": "First" } },
+            { "op": "create", "data": { "title": "Second" } }
+        ] }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(results[0]["status"], 201);
+    let first = results[0]["record"]["id"].as_i64().unwrap();
+    let second = results[1]["record"]["id"].as_i64().unwrap();
+
+    let (status, results) = send(
+        &app,
+        "POST",
+        &batch_uri,
+        &format!(
+            r#"{{ "operations": [
+                {{ "op": "update", "id": {}, "data": {{ "title": "Updated" }} }},
+                {{ "op": "delete", "id": {} }}
+            ] }}"#,
+            first, second
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(results[0]["record"]["data"]["title"], "Updated");
+    assert_eq!(results[1]["op"], "delete");
+    assert_eq!(results[1]["status"], 204);
+
+    let (_, records) = send(
+        &app,
+        "GET",
+        &format!("/api/v1/collections/{}/records", id),
+        "",
+    )
+    .await;
+    assert_eq!(records.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_batch_updates_patch_records() {
+    let app = setup_test_app().await;
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        r#"{ "name": "notes" }"#,
+    )
+    .await;
+    let batch_uri = "/api/v1/collections/notes/records/batch";
+    let (_, results) = send(
+        &app,
+        "POST",
+        batch_uri,
+        r#"{ "operations": [
+            { "op": "create", "data": { "title": "First", "views": 3, "tags": ["a"] } }
+        ] }"#,
+    )
+    .await;
+    let note = results[0]["record"]["id"].as_i64().unwrap();
+
+    // Fields an update leaves out are kept, `null` removes one, and a later
+    // update of the same record patches the earlier one.
+    let (status, results) = send(
+        &app,
+        "POST",
+        batch_uri,
+        &format!(
+            r#"{{ "operations": [
+                {{ "op": "update", "id": {0}, "data": {{ "title": "Updated" }} }},
+                {{ "op": "update", "id": {0}, "data": {{ "views": null }} }}
+            ] }}"#,
+            note
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        results[1]["record"]["data"],
+        serde_json::json!({ "title": "Updated", "tags": ["a"] })
+    );
+    let (_, record) = send(
+        &app,
+        "GET",
+        &format!("/api/v1/collections/notes/records/{}", note),
+        "",
+    )
+    .await;
+    assert_eq!(
+        record["data"],
+        serde_json::json!({ "title": "Updated", "tags": ["a"] })
+    );
+}
+
+#[tokio::test]
+async fn test_batch_rolls_back_on_failure() {
+    let app = setup_test_app().await;
+    let id = create_test_collection(&app).await;
+    let batch_uri = format!("/api/v1/collections/{}/records/batch", id);
+
+    let (status, problem) = send(
+        &app,
+        "POST",
+        &batch_uri,
+        r#"{ "operations": [
+            { "op": "create", "data": { "title": "Kept?" } },
+            { "op": "delete", "id": 999 }
+        ] }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(problem["details"]["index"], 1);
+
+    let (status, problem) = send(
+        &app,
+        "POST",
+        &batch_uri,
+        r#"{ "operations": [
+            { "op": "create", "data": { "title": "Fine" } },
+            { "op": "create", "data": { "title": 42 } }
+        ] }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(problem["details"]["index"], 1);
+
+    let (_, records) = send(
+        &app,
+        "GET",
+        &format!("/api/v1/collections/{}/records", id),
+        "",
+    )
+    .await;
+    assert_eq!(records.as_array().unwrap().len(), 0);
+}
@@ -0,0 +1,162 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{header::ACCEPT, HeaderMap, Request, StatusCode},
+    routing::get,
+    Router,
+};
+use futures_util::future::join_all;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tinybase_api::coalesce::{self, Coalescer, CoalescingStats};
+use tower::ServiceExt;
+
+mod common;
+use common::setup_test_app;
+
+fn get_request(uri: &str) -> Request<Body> {
+    Request::builder().uri(uri).body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn test_identical_reads_run_once() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let router = Router::new().route(
+        "/collections/:id",
+        get(move || {
+            let counter = counter.clone();
+            async move {
+                let call = counter.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                format!("call {}", call)
+            }
+        })
+        .post(|| async { StatusCode::OK }),
+    );
+    let coalescer = Arc::new(Coalescer::new("/collections"));
+    let app = coalesce::layer(router, coalescer.clone());
+
+    let responses =
+        join_all((0..10).map(|_| app.clone().oneshot(get_request("/collections/1")))).await;
+    for response in responses {
+        let response = response.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+        assert_eq!(&body[..], b"call 0");
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        coalescer.stats(),
+        CoalescingStats {
+            executed: 1,
+            coalesced: 9
+        }
+    );
+
+    // Different filters are different reads.
+    join_all([
+        app.clone().oneshot(get_request("/collections/1?sort=id")),
+        app.clone().oneshot(get_request("/collections/1?sort=-id")),
+    ])
+    .await;
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+    // A read in flight when a write completes is not shared with later reads.
+    let before = app.clone().oneshot(get_request("/collections/1"));
+    let after = async {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        app.clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/collections/1")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        app.clone().oneshot(get_request("/collections/1")).await
+    };
+    let (before, after) = tokio::join!(before, after);
+    let before = to_bytes(before.unwrap().into_body(), 1_048_576)
+        .await
+        .unwrap();
+    let after = to_bytes(after.unwrap().into_body(), 1_048_576)
+        .await
+        .unwrap();
+    assert_ne!(before, after);
+    assert_eq!(calls.load(Ordering::SeqCst), 5);
+}
+
+#[tokio::test]
+async fn test_formats_and_streams_are_not_shared() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let handler = |calls: Arc<AtomicUsize>| {
+        get(move |headers: HeaderMap| {
+            let calls = calls.clone();
+            async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                let accept = headers.get(ACCEPT).map(|v| v.to_str().unwrap().to_owned());
+                accept.unwrap_or_default()
+            }
+        })
+    };
+    let router = Router::new()
+        .route("/collections/:id/records", handler(calls.clone()))
+        .route("/collections/:id/export", handler(calls.clone()));
+    let coalescer = Arc::new(Coalescer::new("/collections").except("/export"));
+    let app = coalesce::layer(router, coalescer.clone());
+
+    // A CSV list and a JSON list of the same URI each get their own.
+    let accepting = |accept: &str| {
+        Request::builder()
+            .uri("/collections/1/records")
+            .header(ACCEPT, accept)
+            .body(Body::empty())
+            .unwrap()
+    };
+    let responses = join_all([
+        app.clone().oneshot(accepting("text/csv")),
+        app.clone().oneshot(accepting("application/json")),
+    ])
+    .await;
+    let mut bodies = Vec::new();
+    for response in responses {
+        let body = to_bytes(response.unwrap().into_body(), 1_048_576)
+            .await
+            .unwrap();
+        bodies.push(body);
+    }
+    assert_eq!(&bodies[0][..], b"text/csv");
+    assert_eq!(&bodies[1][..], b"application/json");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    // Streamed exports are left alone.
+    join_all((0..3).map(|_| app.clone().oneshot(get_request("/collections/1/export")))).await;
+    assert_eq!(calls.load(Ordering::SeqCst), 5);
+    assert_eq!(coalescer.stats().coalesced, 0);
+}
+
+#[tokio::test]
+async fn test_metrics() {
+    let app = setup_test_app().await;
+
+    let response = app
+        .clone()
+        .oneshot(get_request("/api/v1/collections"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .oneshot(get_request("/api/v1/admin/metrics"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let metrics: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(metrics["coalescing"]["executed"], 1);
+    assert_eq!(metrics["coalescing"]["coalesced"], 0);
+}
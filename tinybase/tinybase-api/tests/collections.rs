@@ -18,7 +18,7 @@ async fn test_create_collection() {
                 .uri("/api/v1/collections")
                 .header("content-type", "application/json")
                 .body(Body::from(
-                    r#"{ "name": "Users", "schema": { "fields": { "name": { "type": "string", "required": true } } } }"#,
+                    r#"{ "name": "users", "schema": { "fields": { "name": { "type": "string", "required": true } } } }"#,
                 ))
                 .unwrap(),
         )
@@ -39,7 +39,7 @@ async fn test_list_collections() {
                 .method("POST")
                 .uri("/api/v1/collections")
                 .header("content-type", "application/json")
-                .body(Body::from(r#"{ "name": "Users" }"#))
+                .body(Body::from(r#"{ "name": "users" }"#))
                 .unwrap(),
         )
         .await
@@ -74,7 +74,7 @@ async fn test_get_collection() {
                 .method("POST")
                 .uri("/api/v1/collections")
                 .header("content-type", "application/json")
-                .body(Body::from(r#"{ "name": "Users" }"#))
+                .body(Body::from(r#"{ "name": "users" }"#))
                 .unwrap(),
         )
         .await
@@ -97,7 +97,7 @@ async fn test_get_collection() {
     assert_eq!(response.status(), StatusCode::OK);
     let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
     let collection: serde_json::Value = serde_json::from_slice(&body).unwrap();
-    assert_eq!(collection["name"], "Users");
+    assert_eq!(collection["name"], "users");
 }
 
 #[tokio::test]
@@ -112,7 +112,7 @@ async fn test_update_collection() {
                 .method("POST")
                 .uri("/api/v1/collections")
                 .header("content-type", "application/json")
-                .body(Body::from(r#"{ "name": "Users" }"#))
+                .body(Body::from(r#"{ "name": "users" }"#))
                 .unwrap(),
         )
         .await
@@ -127,7 +127,7 @@ async fn test_update_collection() {
                 .method("PATCH")
                 .uri(format!("/api/v1/collections/{}", collection_id))
                 .header("content-type", "application/json")
-                .body(Body::from(r#"{ "name": "New Users" }"#))
+                .body(Body::from(r#"{ "name": "new_users" }"#))
                 .unwrap(),
         )
         .await
@@ -136,7 +136,7 @@ async fn test_update_collection() {
     assert_eq!(response.status(), StatusCode::OK);
     let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
     let collection: serde_json::Value = serde_json::from_slice(&body).unwrap();
-    assert_eq!(collection["name"], "New Users");
+    assert_eq!(collection["name"], "new_users");
 }
 
 #[tokio::test]
@@ -151,7 +151,7 @@ async fn test_delete_collection() {
                 .method("POST")
                 .uri("/api/v1/collections")
                 .header("content-type", "application/json")
-                .body(Body::from(r#"{ "name": "Users" }"#))
+                .body(Body::from(r#"{ "name": "users" }"#))
                 .unwrap(),
         )
         .await
@@ -188,3 +188,584 @@ async fn test_delete_collection() {
 
     assert_eq!(response.status(), StatusCode::NOT_FOUND);
 }
+
+#[tokio::test]
+async fn test_delete_collection_with_records() {
+    let app = setup_test_app().await;
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/collections")
+                .header("content-type", "application/json")
+                .body(Body::from(r#"{ "name": "users" }"#))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let collection: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let collection_id = collection["id"].as_i64().unwrap();
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri(format!("/api/v1/collections/{}/records", collection_id))
+                .header("content-type", "application/json")
+                .body(Body::from(r#"{ "data": { "name": "Ada" } }"#))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let record: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let record_id = record["id"].as_i64().unwrap();
+
+    let delete = |uri: String| {
+        app.clone().oneshot(
+            Request::builder()
+                .method("DELETE")
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+    let response = delete(format!("/api/v1/collections/{}", collection_id))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let response = delete(format!("/api/v1/collections/{}?force=true", collection_id))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    // The records went with the collection
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("GET")
+                .uri(format!(
+                    "/api/v1/collections/{}/records/{}",
+                    collection_id, record_id
+                ))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_openapi_document() {
+    let app = setup_test_app().await;
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/api-docs/openapi.json")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let doc: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert!(doc["paths"]["/api/v1/collections"].is_object());
+    // Endpoints of disabled features are left out of the document.
+    assert_eq!(
+        doc["paths"]["/api/v1/admin/schema/sync"].is_object(),
+        cfg!(feature = "schema-sync")
+    );
+}
+
+#[tokio::test]
+async fn test_collection_openapi_document() {
+    let app = setup_test_app().await;
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/collections")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    r#"{ "name": "blog_posts", "schema": { "fields": {
+                        "title": { "type": "string", "required": true, "max_length": 120,
+                                   "description": "Headline shown in lists", "example": "Hello" },
+                        "published": { "type": "datetime", "required": false },
+                        "views": { "type": "number", "required": false, "min": 0, "default": 0 },
+                        "slug": { "type": "string", "required": false, "compute": "slugify(title)" }
+                    } } }"#,
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let collection: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let id = collection["id"].as_i64().unwrap();
+
+    let get = |uri: String| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+            (
+                status,
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            )
+        }
+    };
+
+    let (status, doc) = get(format!("/api-docs/collections/{}/openapi.json", id)).await;
+    assert_eq!(status, StatusCode::OK);
+    let data = &doc["components"]["schemas"]["BlogPostsData"];
+    assert_eq!(data["required"], serde_json::json!(["title"]));
+    assert_eq!(data["properties"]["title"]["maxLength"], 120);
+    assert_eq!(
+        data["properties"]["title"]["description"],
+        "Headline shown in lists"
+    );
+    assert_eq!(
+        data["properties"]["title"]["examples"],
+        serde_json::json!(["Hello"])
+    );
+    assert_eq!(data["properties"]["published"]["format"], "date-time");
+    assert_eq!(data["properties"]["views"]["minimum"], 0.0);
+    assert_eq!(data["properties"]["views"]["default"], 0);
+    assert_eq!(data["properties"]["slug"]["readOnly"], true);
+    let record = &doc["components"]["schemas"]["BlogPostsRecord"];
+    assert_eq!(
+        record["required"],
+        serde_json::json!(["id", "created", "updated", "data"])
+    );
+    assert_eq!(record["properties"]["updated"]["format"], "date-time");
+    let records = format!("/api/v1/collections/{}/records", id);
+    assert_eq!(
+        doc["paths"][&records]["post"]["requestBody"]["content"]["application/json"]["schema"]
+            ["$ref"],
+        "#/components/schemas/BlogPostsWrite"
+    );
+
+    let (status, _) = get("/api-docs/collections/999/openapi.json".to_string()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_api_client_exports() {
+    let app = setup_test_app().await;
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/collections")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    r#"{ "name": "blog_posts", "schema": { "fields": {
+                        "title": { "type": "string", "required": true, "example": "Hello" },
+                        "views": { "type": "number", "required": false, "default": 0 },
+                        "slug": { "type": "string", "required": false, "compute": "slugify(title)" }
+                    } } }"#,
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let get = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri(uri)
+                        .header("host", "qa.example.com:3000")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let disposition = response
+                .headers()
+                .get("content-disposition")
+                .map(|d| d.to_str().unwrap().to_string());
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (
+                status,
+                disposition,
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap_or_default(),
+            )
+        }
+    };
+
+    let (status, disposition, postman) = get("/api-docs/clients/postman").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        disposition.as_deref(),
+        Some("attachment; filename=\"tinybase.postman_collection.json\"")
+    );
+    assert_eq!(
+        postman["variable"][0],
+        serde_json::json!({ "key": "baseUrl", "value": "http://qa.example.com:3000" })
+    );
+    assert_eq!(postman["auth"]["bearer"][0]["value"], "{{token}}");
+    let folders = postman["item"].as_array().unwrap();
+    let folder = |name: &str| {
+        folders
+            .iter()
+            .find(|f| f["name"] == name)
+            .unwrap_or_else(|| panic!("no {} folder", name))["item"]
+            .as_array()
+            .unwrap()
+            .clone()
+    };
+    assert!(!folder("collections").is_empty());
+    assert!(!folder("admin/webhooks").is_empty());
+    // Collections get a folder of typed requests, with bodies sketched from
+    // their schema.
+    let blog_posts = folder("blog_posts");
+    let create = blog_posts
+        .iter()
+        .find(|r| r["name"] == "createBlogPosts")
+        .unwrap();
+    assert_eq!(create["request"]["method"], "POST");
+    let body: serde_json::Value =
+        serde_json::from_str(create["request"]["body"]["raw"].as_str().unwrap()).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({ "data": { "title": "Hello", "views": 0 } })
+    );
+    let get_post = blog_posts
+        .iter()
+        .find(|r| r["name"] == "getBlogPosts")
+        .unwrap();
+    assert!(get_post["request"]["url"]["raw"]
+        .as_str()
+        .unwrap()
+        .starts_with("{{baseUrl}}/api/v1/collections/"));
+    assert_eq!(
+        get_post["request"]["url"]["variable"][0]["key"],
+        "record_id"
+    );
+
+    let (_, _, postman) = get("/api-docs/clients/postman?base_url=https://api.example.com/").await;
+    assert_eq!(postman["variable"][0]["value"], "https://api.example.com");
+
+    let (status, disposition, insomnia) = get("/api-docs/clients/insomnia").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        disposition.as_deref(),
+        Some("attachment; filename=\"tinybase.insomnia.json\"")
+    );
+    assert_eq!(insomnia["__export_format"], 4);
+    let resources = insomnia["resources"].as_array().unwrap();
+    let environment = resources
+        .iter()
+        .find(|r| r["_type"] == "environment")
+        .unwrap();
+    assert_eq!(
+        environment["data"],
+        serde_json::json!({ "base_url": "http://qa.example.com:3000", "token": "" })
+    );
+    let create = resources
+        .iter()
+        .find(|r| r["name"] == "createBlogPosts")
+        .unwrap();
+    assert_eq!(create["authentication"]["token"], "{{ _.token }}");
+    assert!(create["url"]
+        .as_str()
+        .unwrap()
+        .starts_with("{{ _.base_url }}/api/v1/collections/"));
+    let folder = resources
+        .iter()
+        .find(|r| r["_id"] == create["parentId"])
+        .unwrap();
+    assert_eq!(folder["name"], "blog_posts");
+
+    let (status, _, _) = get("/api-docs/clients/bruno").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_collection_by_name() {
+    let app = setup_test_app().await;
+    let send = |method: &str, uri: &str, body: &'static str| {
+        app.clone().oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap(),
+        )
+    };
+
+    let response = send("POST", "/api/v1/collections", r#"{ "name": "posts" }"#)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let response = send("GET", "/api/v1/collections/posts", "").await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let collection: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(collection["name"], "posts");
+
+    let response = send(
+        "POST",
+        "/api/v1/collections/posts/records",
+        r#"{ "data": { "title": "Hello" } }"#,
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let record: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let uri = format!("/api/v1/collections/posts/records/{}", record["id"]);
+
+    let response = send("GET", &uri, "").await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = send("GET", "/api/v1/collections/posts/records", "")
+        .await
+        .unwrap();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let records: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(records.as_array().unwrap().len(), 1);
+
+    let response = send("GET", "/api/v1/collections/missing/records", "")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Names are unique and must not be mistaken for ids.
+    let response = send("POST", "/api/v1/collections", r#"{ "name": "posts" }"#)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let response = send("POST", "/api/v1/collections", r#"{ "name": "42" }"#)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response = send("DELETE", "/api/v1/collections/posts?force=true", "")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = send("GET", "/api/v1/collections/posts", "").await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_collection_naming_rules() {
+    let app = setup_test_app().await;
+    let send = |method: &'static str, uri: &'static str, name: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method(method)
+                        .uri(uri)
+                        .header("content-type", "application/json")
+                        .body(Body::from(serde_json::json!({ "name": name }).to_string()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+            let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            (status, problem)
+        }
+    };
+
+    for (name, code, suggestion) in [
+        ("", "too_short", None),
+        ("Blog Posts!", "invalid_name", Some("blog_posts")),
+        ("2024_reports", "invalid_name", Some("_2024_reports")),
+        ("Admin", "invalid_name", Some("admin_1")),
+        ("drafts-v2", "invalid_name", Some("drafts_v2")),
+        ("---", "invalid_name", None),
+        ("admin", "reserved_name", None),
+        ("import", "reserved_name", None),
+    ] {
+        let (status, problem) = send("POST", "/api/v1/collections", name).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", name);
+        let detail = &problem["details"][0];
+        assert_eq!(detail["path"], "/name");
+        assert_eq!(detail["code"], code, "{}", name);
+        assert_eq!(detail["value"], name);
+        assert_eq!(detail["suggestion"].as_str(), suggestion, "{}", name);
+    }
+
+    let (status, _) = send("POST", "/api/v1/collections", "blog_posts_2").await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, problem) = send("PATCH", "/api/v1/collections/blog_posts_2", "Blog").await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(problem["details"][0]["message"]
+        .as_str()
+        .unwrap()
+        .ends_with("try 'blog'"));
+}
+
+#[tokio::test]
+async fn test_update_and_delete_missing_collection() {
+    let app = setup_test_app().await;
+
+    for (method, body) in [
+        ("PATCH", Body::from(r#"{ "name": "ghosts" }"#)),
+        ("DELETE", Body::empty()),
+    ] {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri("/api/v1/collections/999")
+                    .header("content-type", "application/json")
+                    .body(body)
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", method);
+        let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem["status"], 404);
+    }
+}
+
+#[tokio::test]
+async fn test_create_collection_from_json_schema() {
+    let app = setup_test_app().await;
+    let create = |body: serde_json::Value| {
+        app.clone().oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/collections")
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+    };
+
+    let response = create(serde_json::json!({
+        "name": "people",
+        "json_schema": {
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": "Person",
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "minLength": 1, "maxLength": 80 },
+                "email": { "type": "string", "format": "email" },
+                "born": { "type": "string", "format": "date", "examples": ["1990-04-01"] },
+                "age": { "type": "integer", "minimum": 0, "description": "In years" },
+                "tags": { "type": "array", "maxItems": 5 },
+                "active": { "type": "boolean", "default": true },
+                "address": { "type": "object", "properties": {
+                    "zip": { "type": "string", "pattern": "^[0-9]{5}$" }
+                }, "required": ["zip"] },
+                "scores": { "type": "array", "items": { "type": "number" } }
+            },
+            "required": ["name", "email"]
+        }
+    }))
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let collection: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let fields = &collection["schema"]["fields"];
+    assert_eq!(fields["name"]["type"], "string");
+    assert_eq!(fields["name"]["required"], true);
+    assert_eq!(fields["name"]["max_length"], 80);
+    assert_eq!(fields["email"]["type"], "email");
+    assert_eq!(fields["born"]["type"], "date");
+    assert_eq!(fields["born"]["example"], "1990-04-01");
+    assert_eq!(fields["age"]["type"], "number");
+    assert_eq!(fields["age"]["min"], 0.0);
+    assert_eq!(fields["age"]["required"], false);
+    assert_eq!(fields["tags"]["type"], "json");
+    assert_eq!(fields["tags"]["max_items"], 5);
+    assert_eq!(fields["active"]["default"], true);
+    let zip = &fields["address"]["type"]["object"]["fields"]["zip"];
+    assert_eq!(zip["type"], "string");
+    assert_eq!(zip["required"], true);
+    assert_eq!(zip["pattern"], "^[0-9]{5}$");
+    assert_eq!(fields["scores"]["type"]["array"]["items"], "number");
+
+    for (json_schema, error) in [
+        (
+            serde_json::json!({ "type": "object", "properties": {
+                "kind": { "type": "string", "enum": ["a", "b"] }
+            } }),
+            "Property 'kind': unsupported keyword `enum`",
+        ),
+        (
+            serde_json::json!({ "type": "object", "properties": {
+                "owner": { "$ref": "#/$defs/user" }
+            } }),
+            "Property 'owner': unsupported keyword `$ref`",
+        ),
+        (
+            serde_json::json!({ "type": "object", "properties": {
+                "score": { "type": ["number", "null"] }
+            } }),
+            "Property 'score': `type` must name a single type",
+        ),
+        (
+            serde_json::json!({ "type": "object", "properties": {}, "required": ["id"] }),
+            "`required` lists unknown property 'id'",
+        ),
+        (
+            serde_json::json!({ "type": "object", "properties": {
+                "address": { "type": "object", "properties": {
+                    "zip": { "type": "string", "format": "postal" }
+                } }
+            } }),
+            "Property 'address': Property 'zip': unsupported format `postal`",
+        ),
+        (
+            serde_json::json!({ "type": "object", "properties": {
+                "scores": { "type": "array", "items": { "type": "number", "minimum": 0 } }
+            } }),
+            "Property 'scores': `items` may only give a type, not `minimum`",
+        ),
+        (
+            serde_json::json!({ "type": "array" }),
+            "must describe an object",
+        ),
+    ] {
+        let response = create(serde_json::json!({ "name": "invalid", "json_schema": json_schema }))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let message = problem["message"].as_str().unwrap();
+        assert!(message.contains(error), "{}", message);
+    }
+
+    let response = create(serde_json::json!({
+        "name": "both",
+        "schema": { "fields": {} },
+        "json_schema": { "type": "object", "properties": {} }
+    }))
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
@@ -1,34 +1,36 @@
 use axum::Router;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use tinybase_api::app_router;
-use tinybase_core::Db;
+use tinybase_api::{app_router, AppState};
+use tinybase_core::{setup_database, Db};
+use tinybase_storage::LocalStorage;
 use tokio::sync::Mutex;
 
-pub async fn setup_test_app() -> Router {
+static NEXT_STORAGE_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// A fresh path under the system temp dir, unique per test.
+pub fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!(
+        "tinybase-test-{}-{}-{}",
+        std::process::id(),
+        NEXT_STORAGE_DIR.fetch_add(1, Ordering::SeqCst),
+        name
+    ))
+}
+
+pub async fn setup_test_state() -> AppState {
     let db = libsql::Builder::new_local(":memory:")
         .build()
         .await
         .unwrap();
     let conn = db.connect().unwrap();
 
-    conn.execute_batch(
-        "
-        CREATE TABLE collections (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT NOT NULL,
-            schema JSON
-        );
-
-        CREATE TABLE records (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            collection_id INTEGER NOT NULL,
-            data JSON NOT NULL
-        );
-    ",
-    )
-    .await
-    .unwrap();
+    setup_database(&conn).await.unwrap();
 
     let db: Arc<dyn Db> = Arc::new(Mutex::new(conn));
-    app_router(db)
+    AppState::new(db, Arc::new(LocalStorage::new(temp_path("uploads"))))
+}
+
+pub async fn setup_test_app() -> Router {
+    app_router(setup_test_state().await)
 }
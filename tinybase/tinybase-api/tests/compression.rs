@@ -0,0 +1,142 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+mod common;
+use common::setup_test_app;
+
+async fn send(app: &Router, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(if body.is_null() {
+                    Body::empty()
+                } else {
+                    Body::from(body.to_string())
+                })
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+/// The storage stats of the `documents` collection.
+async fn documents_storage(app: &Router) -> Value {
+    let (status, storage) = send(app, "GET", "/api/v1/admin/storage", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    storage["collections"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|c| c["collection"] == "documents")
+        .unwrap()
+        .clone()
+}
+
+#[tokio::test]
+async fn test_large_records_are_compressed() {
+    let app = setup_test_app().await;
+    let (status, collection) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({
+            "name": "documents",
+            "schema": {
+                "fields": {
+                    "title": { "type": "string", "required": true },
+                    "body": { "type": "text", "required": false },
+                    "tags": { "type": "json", "required": false }
+                },
+                "compress": { "min_bytes": 1024 }
+            }
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", collection);
+    let body = "All work and no play makes Jack a dull boy. ".repeat(100);
+    let tags: Vec<String> = (0..40).map(|n| format!("tag-{}", n)).collect();
+    let data = json!({ "title": "Shining", "body": body, "tags": tags });
+    let (status, record) = send(
+        &app,
+        "POST",
+        "/api/v1/collections/documents/records",
+        json!({ "data": data }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", record);
+    assert_eq!(record["data"], data);
+    let uri = format!("/api/v1/collections/documents/records/{}", record["id"]);
+    // Small records are left alone.
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections/documents/records",
+        json!({ "data": { "title": "Abstract", "body": "Short." } }),
+    )
+    .await;
+
+    let storage = documents_storage(&app).await;
+    assert_eq!(storage["records"], 2);
+    assert_eq!(storage["compressed"], 1);
+    assert!(storage["saved_bytes"].as_u64().unwrap() > 0, "{}", storage);
+    assert!(storage["data_bytes"].as_u64().unwrap() < body.len() as u64);
+
+    // Reads see the whole record, and picked fields come out compressed or
+    // not.
+    let (_, read) = send(&app, "GET", &uri, Value::Null).await;
+    assert_eq!(read["data"], data);
+    let (_, records) = send(
+        &app,
+        "GET",
+        "/api/v1/collections/documents/records?fields=title,body",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(
+        records[0]["data"],
+        json!({ "title": "Shining", "body": body })
+    );
+    assert_eq!(
+        records[1]["data"],
+        json!({ "title": "Abstract", "body": "Short." })
+    );
+
+    // Changing a compressed member in place keeps what it held.
+    let (status, pushed) = send(
+        &app,
+        "POST",
+        &format!("{}/array", uri),
+        json!({ "field": "tags", "op": "push", "value": "horror" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", pushed);
+    assert_eq!(pushed["data"]["tags"].as_array().unwrap().len(), 41);
+    assert_eq!(pushed["data"]["body"], body);
+
+    // Earlier versions are kept compressed too.
+    let (status, _) = send(
+        &app,
+        "PUT",
+        &uri,
+        json!({ "data": { "title": "Shining", "body": format!("{}The end.", body) } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(documents_storage(&app).await["compressed"], 1);
+    let (_, versions) = send(&app, "GET", &format!("{}/versions", uri), Value::Null).await;
+    let versions = versions.as_array().unwrap();
+    assert_eq!(versions[0]["data"], data);
+    assert_eq!(versions[1]["data"]["tags"].as_array().unwrap().len(), 41);
+}
@@ -0,0 +1,436 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use tinybase_api::{
+    access_log::AccessLogFormat,
+    app_router,
+    config::{Config, LogFormat},
+    envelope::ListFormat,
+    search::SearchBackend,
+    server::Server,
+};
+use tinybase_core::checksums::ChecksumMode;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state, temp_path};
+
+#[test]
+fn test_config_file_and_env() {
+    let mut config = Config::from_toml(
+        r#"
+            db_path = "data/tinybase.db"
+            log_level = "warn"
+            cors_origins = ["https://app.example.com"]
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.db_path.to_str(), Some("data/tinybase.db"));
+    assert_eq!(config.addr, "0.0.0.0:3000");
+    assert_eq!(config.uploads_dir, PathBuf::from("uploads"));
+    assert!(config.logs("error") && !config.logs("info"));
+
+    let env = HashMap::from([
+        ("TINYBASE_ADDR", "127.0.0.1:8080"),
+        ("TINYBASE_ADMIN_ADDR", "127.0.0.1:8081"),
+        ("TINYBASE_MAX_BODY_BYTES", "1024"),
+        ("TINYBASE_LOG_FORMAT", "json"),
+        ("TINYBASE_QUERY_TIMEOUT_MS", "0"),
+        ("TINYBASE_DB_POOL_SIZE", "16"),
+        ("TINYBASE_UPLOADS_DIR", "/var/lib/tinybase/uploads"),
+        ("TINYBASE_ACCESS_LOG", "logs/access.log"),
+        ("TINYBASE_ACCESS_LOG_FORMAT", "json"),
+        (
+            "TINYBASE_CORS_ORIGINS",
+            "https://a.example.com, https://b.example.com",
+        ),
+        (
+            "TINYBASE_WARM_QUERIES",
+            "/api/v1/collections/posts/records?sort=-created,id /api/v1/meta",
+        ),
+        ("TINYBASE_CHECKSUMS", "enforce"),
+        ("TINYBASE_DRAIN_TIMEOUT_MS", "1500"),
+        (
+            "TINYBASE_ATTACH",
+            "postcodes=data/postcodes.db, geo = data/geo.db",
+        ),
+        ("TINYBASE_LIST_FORMAT", "envelope"),
+        ("TINYBASE_BACKUP_DIR", "backups"),
+        ("TINYBASE_BACKUP_INTERVAL_HOURS", "6"),
+        ("TINYBASE_BACKUP_KEEP", "4"),
+        ("TINYBASE_SEARCH", "meilisearch"),
+        ("TINYBASE_MEILISEARCH_URL", "http://127.0.0.1:7700"),
+        ("TINYBASE_NAMESPACES", "shop=data/shop.db"),
+    ]);
+    config
+        .apply_env(|name| env.get(name).map(|v| v.to_string()))
+        .unwrap();
+    assert_eq!(config.addr, "127.0.0.1:8080");
+    assert_eq!(config.admin_addr.as_deref(), Some("127.0.0.1:8081"));
+    assert_eq!(config.max_body_bytes, 1024);
+    assert_eq!(config.log_format, LogFormat::Json);
+    assert_eq!(config.query_timeout_ms, 0);
+    assert_eq!(config.db_pool_size, 16);
+    assert_eq!(
+        config.uploads_dir,
+        PathBuf::from("/var/lib/tinybase/uploads")
+    );
+    assert_eq!(
+        config.access_log.as_deref().and_then(|p| p.to_str()),
+        Some("logs/access.log")
+    );
+    assert_eq!(config.access_log_format, AccessLogFormat::Json);
+    assert_eq!(
+        config.cors_origins,
+        ["https://a.example.com", "https://b.example.com"]
+    );
+    assert_eq!(
+        config.warm_queries,
+        [
+            "/api/v1/collections/posts/records?sort=-created,id",
+            "/api/v1/meta"
+        ]
+    );
+    assert_eq!(config.checksums, ChecksumMode::Enforce);
+    assert_eq!(config.drain_timeout_ms, 1500);
+    assert_eq!(
+        config.attach,
+        BTreeMap::from([
+            ("geo".to_string(), PathBuf::from("data/geo.db")),
+            ("postcodes".to_string(), PathBuf::from("data/postcodes.db")),
+        ])
+    );
+    assert_eq!(config.list_format, ListFormat::Envelope);
+    assert_eq!(config.backup_dir, Some(PathBuf::from("backups")));
+    assert_eq!(config.backup_interval_hours, Some(6));
+    assert_eq!(config.backup_keep, Some(4));
+    assert_eq!(config.verify_interval_hours, 24);
+    assert_eq!(config.search, SearchBackend::Meilisearch);
+    assert_eq!(config.namespaces["shop"], PathBuf::from("data/shop.db"));
+    assert_eq!(
+        config.meilisearch_url.as_deref(),
+        Some("http://127.0.0.1:7700")
+    );
+    assert_eq!(config.db_path.to_str(), Some("data/tinybase.db"));
+
+    let file = temp_path("tinybase.toml");
+    std::fs::write(&file, "addr = \"127.0.0.1:4000\"\n").unwrap();
+    assert_eq!(Config::from_file(&file).unwrap().addr, "127.0.0.1:4000");
+}
+
+#[test]
+fn test_invalid_config() {
+    assert!(Config::from_toml("port = 3000").is_err());
+    assert!(Config::from_toml(r#"log_level = "loud""#).is_err());
+    assert!(Config::from_toml(r#"cors_origins = ["app.example.com"]"#).is_err());
+    assert!(Config::from_toml("job_workers = 0").is_err());
+    assert!(Config::from_toml("db_pool_size = 0").is_err());
+    assert!(Config::from_toml(r#"uploads_dir = """#).is_err());
+    assert!(Config::from_toml(r#"log_format = "xml""#).is_err());
+    assert!(Config::from_toml(r#"access_log_format = "common""#).is_err());
+    assert!(Config::from_toml("admin_addr = \"0.0.0.0:3000\"").is_err());
+    assert!(Config::from_toml(r#"warm_queries = ["posts/records"]"#).is_err());
+    assert!(Config::from_toml(r#"checksums = "strict""#).is_err());
+    assert!(Config::from_toml(r#"list_format = "xml""#).is_err());
+    assert!(Config::from_toml("backup_interval_hours = 6").is_err());
+    assert!(Config::from_toml("backup_dir = \"backups\"\nbackup_keep = 0").is_err());
+    assert!(Config::from_toml("verify_interval_hours = 0").is_err());
+    assert!(Config::from_toml(r#"search = "tantivy""#).is_err());
+    assert!(Config::from_toml(r#"search = "elastic""#).is_err());
+    assert!(Config::from_toml(r#"namespaces = { "my shop" = "shop.db" }"#).is_err());
+    for (var, value) in [
+        ("TINYBASE_MAX_BODY_BYTES", "lots"),
+        ("TINYBASE_ATTACH", "data/postcodes.db"),
+        ("TINYBASE_LIST_FORMAT", "paged"),
+        ("TINYBASE_BACKUP_KEEP", "all"),
+        ("TINYBASE_VERIFY_INTERVAL_HOURS", "daily"),
+        ("TINYBASE_SEARCH", "elastic"),
+        ("TINYBASE_NAMESPACES", "shop"),
+    ] {
+        let mut config = Config::default();
+        let err = config
+            .apply_env(|name| (name == var).then(|| value.to_string()))
+            .unwrap_err();
+        assert!(err.contains(var), "{}", err);
+    }
+}
+
+#[tokio::test]
+async fn test_config_shapes_the_api() {
+    let config = Config::from_toml(
+        r#"
+            max_body_bytes = 64
+            cors_origins = ["https://app.example.com"]
+        "#,
+    )
+    .unwrap();
+    let app = app_router(setup_test_state().await.with_config(&config));
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/collections")
+                .header("content-type", "application/json")
+                .body(Body::from(format!(
+                    r#"{{ "name": "{}" }}"#,
+                    "x".repeat(100)
+                )))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let preflight = |app: axum::Router, origin: &'static str| async move {
+        app.oneshot(
+            Request::builder()
+                .method("OPTIONS")
+                .uri("/api/v1/collections")
+                .header("origin", origin)
+                .header("access-control-request-method", "POST")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+    };
+    let response = preflight(app.clone(), "https://app.example.com").await;
+    assert_eq!(
+        response.headers()["access-control-allow-origin"],
+        "https://app.example.com"
+    );
+    let response = preflight(app, "https://evil.example.com").await;
+    assert!(response
+        .headers()
+        .get("access-control-allow-origin")
+        .is_none());
+
+    // Without origins there are no CORS headers at all.
+    let response = preflight(setup_test_app().await, "https://app.example.com").await;
+    assert!(response
+        .headers()
+        .get("access-control-allow-origin")
+        .is_none());
+}
+
+const PROFILES: &str = r#"
+    cors_origins = ["https://app.example.com"]
+    rate_limit_per_minute = 600
+
+    [profiles.development]
+    cors_origins = ["*"]
+    rate_limit_per_minute = 0
+    log_level = "debug"
+
+    [profiles.staging]
+    inherits = "production"
+    cors_origins = ["https://staging.example.com"]
+
+    [profiles.production]
+    log_format = "json"
+"#;
+
+#[test]
+fn test_config_profiles() {
+    let base = Config::from_toml(PROFILES).unwrap();
+    assert_eq!(base.cors_origins, ["https://app.example.com"]);
+    assert_eq!(base.rate_limit_per_minute, 600);
+    assert_eq!(base.profile, None);
+    assert!(!base.developer_mode);
+
+    let development = Config::from_toml_profile(PROFILES, Some("development")).unwrap();
+    assert_eq!(development.cors_origins, ["*"]);
+    assert_eq!(development.rate_limit_per_minute, 0);
+    assert!(development.logs("debug"));
+    assert!(development.developer_mode);
+    assert_eq!(development.profile.as_deref(), Some("development"));
+
+    // Staging takes production's settings, then its own.
+    let staging = Config::from_toml_profile(PROFILES, Some("staging")).unwrap();
+    assert_eq!(staging.cors_origins, ["https://staging.example.com"]);
+    assert_eq!(staging.log_format, LogFormat::Json);
+    assert_eq!(staging.rate_limit_per_minute, 600);
+    assert!(!staging.developer_mode);
+
+    // Without profiles any environment runs on the top settings, and
+    // development still reports error internals unless told not to.
+    let plain = Config::from_toml_profile("", Some("development")).unwrap();
+    assert!(plain.developer_mode);
+    let quiet = Config::from_toml_profile("developer_mode = false", Some("development")).unwrap();
+    assert!(!quiet.developer_mode);
+
+    assert!(Config::from_toml_profile(PROFILES, Some("qa"))
+        .unwrap_err()
+        .contains("qa"));
+    // A broken profile fails whichever one is picked.
+    let broken = format!("{}\n[profiles.demo]\nport = 80\n", PROFILES);
+    assert!(Config::from_toml_profile(&broken, Some("production")).is_err());
+    let cycle = "[profiles.a]\ninherits = \"b\"\n[profiles.b]\ninherits = \"a\"\n";
+    assert!(Config::from_toml(cycle).is_err());
+    let orphan = "[profiles.a]\ninherits = \"z\"\n";
+    assert!(Config::from_toml(orphan).is_err());
+
+    // The file, the profile and the overrides all come from the environment.
+    let file = temp_path("profiles.toml");
+    std::fs::write(&file, PROFILES).unwrap();
+    let env = HashMap::from([
+        ("TINYBASE_CONFIG", file.to_str().unwrap()),
+        ("TINYBASE_ENV", "staging"),
+        ("TINYBASE_RATE_LIMIT_PER_MINUTE", "60"),
+    ]);
+    let staging = Config::load_from(|name| env.get(name).map(|v| v.to_string())).unwrap();
+    assert_eq!(staging.profile.as_deref(), Some("staging"));
+    assert_eq!(staging.cors_origins, ["https://staging.example.com"]);
+    assert_eq!(staging.rate_limit_per_minute, 60);
+    let qa = Config::load_from(|name| match name {
+        "TINYBASE_ENV" => Some("qa".to_string()),
+        _ => env.get(name).map(|v| v.to_string()),
+    });
+    assert!(qa.unwrap_err().contains("qa"));
+}
+
+#[tokio::test]
+async fn test_rate_limit() {
+    let config = Config::from_toml("rate_limit_per_minute = 2").unwrap();
+    let app = app_router(setup_test_state().await.with_config(&config));
+    let get = || {
+        Request::builder()
+            .uri("/api/v1/collections")
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    for _ in 0..2 {
+        let response = app.clone().oneshot(get()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    let response = app.clone().oneshot(get()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry: u64 = response.headers()["retry-after"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=60).contains(&retry));
+}
+
+/// A server on a database of its own, listening on ports the system picks,
+/// with a separate admin listener if `admin`.
+async fn start_server(name: &str, admin: bool) -> (SocketAddr, Option<SocketAddr>) {
+    let config = Config {
+        db_path: temp_path(name),
+        addr: "127.0.0.1:0".to_string(),
+        admin_addr: admin.then(|| "127.0.0.1:0".to_string()),
+        log_level: "warn".to_string(),
+        ..Config::default()
+    };
+    let server = Server::open(config).await.unwrap();
+    let listeners = server.listen().await.unwrap();
+    let addr = listeners.api.local_addr().unwrap();
+    let admin = listeners.admin.as_ref().map(|l| l.local_addr().unwrap());
+    tokio::spawn(server.serve(listeners, Vec::new()));
+    (addr, admin)
+}
+
+/// The status line of a GET of `path` from the server at `addr`.
+async fn status_line(addr: SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, addr
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response.lines().next().unwrap_or_default().to_string()
+}
+
+#[tokio::test]
+async fn test_servers_on_port_zero() {
+    let (first, _) = start_server("first.db", false).await;
+    let (second, _) = start_server("second.db", false).await;
+    assert_ne!(first.port(), 0);
+    assert_ne!(first.port(), second.port());
+
+    for addr in [first, second] {
+        assert_eq!(status_line(addr, "/api/v1/health").await, "HTTP/1.1 200 OK");
+    }
+    // Without an admin listener the admin surface is on the only one.
+    assert_ne!(
+        status_line(first, "/api/v1/admin/metrics").await,
+        "HTTP/1.1 404 Not Found"
+    );
+}
+
+#[tokio::test]
+async fn test_admin_listener() {
+    let (api, admin) = start_server("admin.db", true).await;
+    let admin = admin.unwrap();
+    assert_ne!(api.port(), admin.port());
+
+    for path in [
+        "/api/v1/admin/metrics",
+        "/api/v1/admin/backups",
+        "/api/v1/ns/shop/admin/metrics",
+        "/admin/",
+    ] {
+        assert_eq!(
+            status_line(api, path).await,
+            "HTTP/1.1 404 Not Found",
+            "{}",
+            path
+        );
+    }
+    assert_eq!(status_line(api, "/api/v1/health").await, "HTTP/1.1 200 OK");
+    assert_ne!(
+        status_line(admin, "/api/v1/admin/metrics").await,
+        "HTTP/1.1 404 Not Found"
+    );
+    assert_eq!(
+        status_line(admin, "/api/v1/health").await,
+        "HTTP/1.1 200 OK"
+    );
+}
+
+#[tokio::test]
+async fn test_graceful_shutdown() {
+    let db_path = temp_path("shutdown.db");
+    let config = Config {
+        db_path: db_path.clone(),
+        addr: "127.0.0.1:0".to_string(),
+        log_level: "warn".to_string(),
+        drain_timeout_ms: 5000,
+        ..Config::default()
+    };
+    let server = Server::open(config).await.unwrap();
+    let listeners = server.listen().await.unwrap();
+    let addr = listeners.api.local_addr().unwrap();
+    let shutdown = server.shutdown();
+    let serving = tokio::spawn(server.serve(listeners, Vec::new()));
+    assert_eq!(status_line(addr, "/api/v1/health").await, "HTTP/1.1 200 OK");
+
+    // A connection left idle doesn't hold up the shutdown.
+    let _idle = TcpStream::connect(addr).await.unwrap();
+    let started = std::time::Instant::now();
+    shutdown.trigger();
+    let result = tokio::time::timeout(Duration::from_secs(10), serving)
+        .await
+        .expect("the server stopped")
+        .unwrap();
+    assert_eq!(result, Ok(()));
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(TcpStream::connect(addr).await.is_err());
+
+    // The write-ahead log was checkpointed into the database file.
+    let wal = format!("{}-wal", db_path.display());
+    assert_eq!(std::fs::metadata(wal).map(|m| m.len()).unwrap_or(0), 0);
+}
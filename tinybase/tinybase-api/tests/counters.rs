@@ -0,0 +1,124 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+mod common;
+use common::setup_test_app;
+
+async fn send(app: &Router, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+async fn create_product(app: &Router) -> String {
+    send(
+        app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "products", "schema": { "fields": {
+            "name": { "type": "string", "required": true },
+            "stock": { "type": "number", "required": false, "min": 0 },
+            "price": { "type": "number", "required": false },
+            "gross": { "type": "number", "required": false, "compute": "price * 1.2" }
+        } } }),
+    )
+    .await;
+    let (_, product) = send(
+        app,
+        "POST",
+        "/api/v1/collections/products/records",
+        json!({ "data": { "name": "mug", "stock": 2 } }),
+    )
+    .await;
+    format!(
+        "/api/v1/collections/products/records/{}/increment",
+        product["id"]
+    )
+}
+
+#[tokio::test]
+async fn test_increment_and_decrement() {
+    let app = setup_test_app().await;
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "posts" }),
+    )
+    .await;
+    let (_, post) = send(
+        &app,
+        "POST",
+        "/api/v1/collections/posts/records",
+        json!({ "data": { "title": "Hello" } }),
+    )
+    .await;
+    let uri = format!("/api/v1/collections/posts/records/{}/increment", post["id"]);
+
+    // A missing field counts as 0, and the delta defaults to 1.
+    let (status, post) = send(&app, "POST", &uri, json!({ "field": "likes" })).await;
+    assert_eq!(status, StatusCode::OK, "{}", post);
+    assert_eq!(post["data"]["likes"], 1);
+    let (_, post) = send(&app, "POST", &uri, json!({ "field": "likes", "delta": 10 })).await;
+    assert_eq!(post["data"]["likes"], 11);
+    let (_, post) = send(
+        &app,
+        "POST",
+        &uri,
+        json!({ "field": "likes", "delta": -2.5 }),
+    )
+    .await;
+    assert_eq!(post["data"]["likes"], 8.5);
+    assert_eq!(post["data"]["title"], "Hello");
+
+    // Only numbers can be incremented.
+    let (status, _) = send(&app, "POST", &uri, json!({ "field": "title" })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections/posts/records/999/increment",
+        json!({ "field": "likes" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_increments_respect_the_schema() {
+    let app = setup_test_app().await;
+    let uri = create_product(&app).await;
+
+    let (status, product) =
+        send(&app, "POST", &uri, json!({ "field": "stock", "delta": -2 })).await;
+    assert_eq!(status, StatusCode::OK, "{}", product);
+    assert_eq!(product["data"]["stock"], 0);
+    // Stock can't go below its minimum, and the refused decrement changes nothing.
+    let (status, error) = send(&app, "POST", &uri, json!({ "field": "stock", "delta": -1 })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", error);
+    let (_, product) = send(&app, "POST", &uri, json!({ "field": "stock", "delta": 0 })).await;
+    assert_eq!(product["data"]["stock"], 0);
+
+    for field in ["name", "price", "gross"] {
+        let (status, _) = send(&app, "POST", &uri, json!({ "field": field })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", field);
+    }
+}
@@ -0,0 +1,96 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+
+mod common;
+use common::setup_test_app;
+
+async fn send(
+    app: &axum::Router,
+    method: &str,
+    uri: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> (StatusCode, String) {
+    let mut request = Request::builder().method(method).uri(uri);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::from(body.to_string())).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn test_csv_import_and_export() {
+    let app = setup_test_app().await;
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        &[("content-type", "application/json")],
+        r#"{ "name": "books", "schema": { "fields": {
+            "title": { "type": "string", "required": true },
+            "pages": { "type": "number", "required": false },
+            "read": { "type": "boolean", "required": false }
+        } } }"#,
+    )
+    .await;
+
+    let csv = "title,pages,read\r\n\
+               \"Dune, Part One\",412,yes\n\
+               \"Multi\nline\",,\n\
+               Broken,many,no\n\
+               ,10,true\n";
+    let (status, report) = send(
+        &app,
+        "POST",
+        "/api/v1/collections/books/records/import",
+        &[("content-type", "text/csv")],
+        csv,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let report: serde_json::Value = serde_json::from_str(&report).unwrap();
+    assert_eq!(report["created"].as_array().unwrap().len(), 2);
+    let failed = report["failed"].as_array().unwrap();
+    assert_eq!(failed.len(), 2);
+    assert_eq!(failed[0]["line"], 5);
+    assert!(failed[0]["errors"][0]
+        .as_str()
+        .unwrap()
+        .contains("not a number"));
+    assert_eq!(failed[1]["line"], 6);
+    assert!(failed[1]["errors"][0].as_str().unwrap().contains("title"));
+
+    let (status, body) = send(
+        &app,
+        "POST",
+        "/api/v1/collections/books/records/import",
+        &[("content-type", "text/csv")],
+        "title,author\nDune,Herbert\n",
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+
+    let (status, body) = send(
+        &app,
+        "GET",
+        "/api/v1/collections/books/records",
+        &[("accept", "text/csv")],
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let lines: Vec<&str> = body.split("\r\n").collect();
+    assert_eq!(lines[0], "id,created,updated,pages,read,title");
+    assert!(lines[1].ends_with(",412,true,\"Dune, Part One\""));
+    assert!(lines[2].ends_with(",,,\"Multi\nline\""));
+}
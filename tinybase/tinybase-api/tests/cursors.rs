@@ -0,0 +1,178 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use tinybase_api::{app_router, envelope::ListFormat};
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state};
+
+async fn send(app: &Router, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+/// Adds a `posts` collection holding posts 1 to 5.
+async fn add_posts(app: &Router) {
+    send(
+        app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "posts" }),
+    )
+    .await;
+    for n in 1..=5 {
+        send(
+            app,
+            "POST",
+            "/api/v1/collections/posts/records",
+            json!({ "data": { "n": n } }),
+        )
+        .await;
+    }
+}
+
+/// An enveloped app with posts 1 to 5.
+async fn app_with_posts() -> Router {
+    let state = setup_test_state().await;
+    let app = app_router(state.with_list_format(ListFormat::Envelope));
+    add_posts(&app).await;
+    app
+}
+
+/// Follows `next_cursor` from `query` to the end, collecting `n` of every
+/// item.
+async fn follow(app: &Router, query: &str) -> Vec<Vec<i64>> {
+    let mut pages = Vec::new();
+    let mut uri = format!("/api/v1/collections/posts/records?{}", query);
+    loop {
+        let (status, page) = send(app, "GET", &uri, Value::Null).await;
+        assert_eq!(status, StatusCode::OK, "{}", page);
+        assert_eq!(page["total"], 5);
+        pages.push(
+            page["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["data"]["n"].as_i64().unwrap())
+                .collect(),
+        );
+        let Some(cursor) = page["next_cursor"].as_str() else {
+            assert_eq!(page["links"]["next"], Value::Null);
+            return pages;
+        };
+        // Pages asked for with a cursor link to the next one with a cursor.
+        let next = page["links"]["next"].as_str().unwrap();
+        assert_eq!(next.contains("cursor="), uri.contains("cursor="));
+        uri = format!(
+            "/api/v1/collections/posts/records?{}&cursor={}",
+            query, cursor
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_cursor_pagination() {
+    let app = app_with_posts().await;
+
+    assert_eq!(
+        follow(&app, "per_page=2").await,
+        vec![vec![1, 2], vec![3, 4], vec![5]]
+    );
+    assert_eq!(
+        follow(&app, "per_page=2&sort=-id").await,
+        vec![vec![5, 4], vec![3, 2], vec![1]]
+    );
+    // Records created in the same millisecond are told apart by id.
+    assert_eq!(
+        follow(&app, "per_page=3&sort=-created").await,
+        vec![vec![5, 4, 3], vec![2, 1]]
+    );
+    assert_eq!(follow(&app, "per_page=5").await, vec![vec![1, 2, 3, 4, 5]]);
+}
+
+#[tokio::test]
+async fn test_invalid_cursors() {
+    let app = app_with_posts().await;
+    let (_, page) = send(
+        &app,
+        "GET",
+        "/api/v1/collections/posts/records?per_page=2",
+        Value::Null,
+    )
+    .await;
+    let cursor = page["next_cursor"].as_str().unwrap();
+
+    for query in [
+        format!("per_page=2&sort=-id&cursor={}", cursor),
+        format!("per_page=2&sort=updated&cursor={}", cursor),
+        format!("per_page=2&page=2&cursor={}", cursor),
+        "per_page=2&cursor=zz".to_string(),
+    ] {
+        let (status, _) = send(
+            &app,
+            "GET",
+            &format!("/api/v1/collections/posts/records?{}", query),
+            Value::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", query);
+    }
+
+    // Lists in other orders have no cursor.
+    let (_, page) = send(
+        &app,
+        "GET",
+        "/api/v1/collections/posts/records?per_page=2&sort=updated",
+        Value::Null,
+    )
+    .await;
+    assert!(page.get("next_cursor").is_none());
+}
+
+#[tokio::test]
+async fn test_bare_lists_take_cursors() {
+    let enveloped = app_with_posts().await;
+    let (_, page) = send(
+        &enveloped,
+        "GET",
+        "/api/v1/collections/posts/records?per_page=2",
+        Value::Null,
+    )
+    .await;
+    let cursor = page["next_cursor"].as_str().unwrap();
+
+    let app = setup_test_app().await;
+    add_posts(&app).await;
+    let (status, records) = send(
+        &app,
+        "GET",
+        &format!("/api/v1/collections/posts/records?cursor={}", cursor),
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", records);
+    let ns: Vec<_> = records
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["data"]["n"].clone())
+        .collect();
+    assert_eq!(ns, vec![json!(3), json!(4), json!(5)]);
+}
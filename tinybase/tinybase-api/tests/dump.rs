@@ -0,0 +1,238 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+mod common;
+use common::setup_test_app;
+
+async fn send(
+    app: &axum::Router,
+    method: &str,
+    uri: &str,
+    content_type: &str,
+    body: String,
+) -> (StatusCode, String) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", content_type)
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+async fn send_json(
+    app: &axum::Router,
+    method: &str,
+    uri: &str,
+    body: Value,
+) -> (StatusCode, Value) {
+    let (status, body) = send(app, method, uri, "application/json", body.to_string()).await;
+    (status, serde_json::from_str(&body).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn test_export_and_import() {
+    let app = setup_test_app().await;
+    let schema = json!({ "fields": { "title": { "type": "string", "required": true } } });
+    send_json(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "posts", "schema": schema }),
+    )
+    .await;
+    let mut ids = Vec::new();
+    for title in ["one", "two", "three"] {
+        let (_, record) = send_json(
+            &app,
+            "POST",
+            "/api/v1/collections/posts/records",
+            json!({ "data": { "title": title } }),
+        )
+        .await;
+        ids.push(record["id"].clone());
+    }
+
+    let (status, dump) = send(
+        &app,
+        "GET",
+        "/api/v1/collections/posts/export",
+        "",
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let dump: Value = serde_json::from_str(&dump).unwrap();
+    assert_eq!(dump["name"], "posts");
+    assert_eq!(dump["schema"]["fields"]["title"]["type"], "string");
+    assert_eq!(dump["records"].as_array().unwrap().len(), 3);
+    assert_eq!(dump["records"][1]["data"]["title"], "two");
+
+    let (status, ndjson) = send(
+        &app,
+        "GET",
+        "/api/v1/collections/posts/export?format=ndjson",
+        "",
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let lines: Vec<Value> = ndjson
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0]["name"], "posts");
+    assert_eq!(lines[3]["id"], ids[2]);
+
+    // The collection exists, so the default strategy refuses the import.
+    let (status, _) = send_json(&app, "POST", "/api/v1/collections/import", dump.clone()).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (_, report) = send_json(
+        &app,
+        "POST",
+        "/api/v1/collections/import?on_conflict=skip",
+        dump.clone(),
+    )
+    .await;
+    assert_eq!(report["skipped"], 3);
+
+    let mut changed = dump.clone();
+    changed["records"][0]["data"]["title"] = json!("uno");
+    let (_, report) = send_json(
+        &app,
+        "POST",
+        "/api/v1/collections/import?on_conflict=overwrite",
+        changed,
+    )
+    .await;
+    assert_eq!(report["overwritten"], 3);
+    let (_, record) = send_json(
+        &app,
+        "GET",
+        &format!("/api/v1/collections/posts/records/{}", ids[0]),
+        Value::Null,
+    )
+    .await;
+    assert_eq!(record["data"]["title"], "uno");
+
+    // Restoring into an empty instance keeps ids and timestamps.
+    send_json(
+        &app,
+        "DELETE",
+        "/api/v1/collections/posts?force=true",
+        Value::Null,
+    )
+    .await;
+    let (status, report) =
+        send_json(&app, "POST", "/api/v1/collections/import", dump.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["created"], 3);
+    let (_, record) = send_json(
+        &app,
+        "GET",
+        &format!("/api/v1/collections/posts/records/{}", ids[1]),
+        Value::Null,
+    )
+    .await;
+    assert_eq!(record["created"], dump["records"][1]["created"]);
+
+    // NDJSON is imported line by line; invalid records are reported.
+    let ndjson = ndjson.replacen("posts", "archive", 1)
+        + &json!({ "id": 999, "data": {}, "created": "2024-05-01", "updated": "2024-05-01" })
+            .to_string();
+    let (status, report) = send(
+        &app,
+        "POST",
+        "/api/v1/collections/import",
+        "application/x-ndjson",
+        ndjson,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let report: Value = serde_json::from_str(&report).unwrap();
+    assert_eq!(report["collection"]["name"], "archive");
+    // The ids are taken by `posts`, so the records get new ones.
+    assert_eq!(report["created"], 3);
+    assert_eq!(report["invalid"][0]["id"], 999);
+}
+
+#[tokio::test]
+async fn test_background_import() {
+    let app = setup_test_app().await;
+    let mut ndjson = json!({ "name": "events", "schema": null }).to_string();
+    for id in 1..=250 {
+        let record = json!({
+            "id": id,
+            "data": { "n": id },
+            "created": "2024-05-01",
+            "updated": "2024-05-01"
+        });
+        ndjson.push('\n');
+        ndjson.push_str(&record.to_string());
+    }
+
+    let (status, job) = send(
+        &app,
+        "POST",
+        "/api/v1/collections/import?background=true",
+        "application/x-ndjson",
+        ndjson.clone(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let job: Value = serde_json::from_str(&job).unwrap();
+    assert_eq!(job["kind"], "import");
+    assert_eq!(job["progress"]["total"], 250);
+
+    let uri = format!("/api/v1/jobs/{}", job["id"]);
+    let mut job = Value::Null;
+    for _ in 0..500 {
+        let (status, body) = send_json(&app, "GET", &uri, Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        job = body;
+        if job["state"] != "queued" && job["state"] != "running" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert_eq!(job["state"], "completed");
+    assert_eq!(job["progress"]["processed"], 250);
+    assert_eq!(job["progress"]["created"], 250);
+    assert_eq!(job["result"]["created"], 250);
+    let (_, record) = send_json(
+        &app,
+        "GET",
+        "/api/v1/collections/events/records/250",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(record["data"]["n"], 250);
+    assert_eq!(
+        record["lineage"],
+        json!({ "source": "import", "job": job["id"] })
+    );
+
+    // Conflicts are reported before the import goes to the background.
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections/import?background=true",
+        "application/x-ndjson",
+        ndjson,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
@@ -0,0 +1,194 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{header::ETAG, HeaderMap, Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+mod common;
+use common::setup_test_app;
+
+/// Sends a request with `If-Match: if_match` if given, returning the status,
+/// headers and JSON body of the response.
+async fn send(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    if_match: Option<&str>,
+    body: Value,
+) -> (StatusCode, HeaderMap, Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json");
+    if let Some(tag) = if_match {
+        request = request.header("if-match", tag);
+    }
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::from(body.to_string())).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let body = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    (status, headers, body)
+}
+
+/// An app with a `docs` collection, locking optimistically if `locking`,
+/// holding one doc; returns the doc's URI too.
+async fn app_with_doc(locking: bool) -> (Router, String) {
+    let app = setup_test_app().await;
+    let (status, _, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        None,
+        json!({ "name": "docs", "schema": {
+            "fields": { "text": { "type": "string", "required": true } },
+            "optimistic_locking": locking
+        } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (_, _, doc) = send(
+        &app,
+        "POST",
+        "/api/v1/collections/docs/records",
+        None,
+        json!({ "data": { "text": "draft" } }),
+    )
+    .await;
+    let uri = format!("/api/v1/collections/docs/records/{}", doc["id"]);
+    (app, uri)
+}
+
+#[tokio::test]
+async fn test_reads_and_writes_carry_the_record_version() {
+    let (app, uri) = app_with_doc(false).await;
+
+    let (status, headers, _) = send(&app, "GET", &uri, None, Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[ETAG], "\"1\"");
+
+    let (status, headers, _) = send(
+        &app,
+        "PATCH",
+        &uri,
+        None,
+        json!({ "data": { "text": "one" } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[ETAG], "\"2\"");
+
+    let (_, headers, _) = send(
+        &app,
+        "PUT",
+        &uri,
+        None,
+        json!({ "data": { "text": "two" } }),
+    )
+    .await;
+    assert_eq!(headers[ETAG], "\"3\"");
+    let (_, headers, _) = send(&app, "GET", &uri, None, Value::Null).await;
+    assert_eq!(headers[ETAG], "\"3\"");
+}
+
+#[tokio::test]
+async fn test_if_match_refuses_stale_writes() {
+    let (app, uri) = app_with_doc(false).await;
+
+    let (status, _, _) = send(
+        &app,
+        "PATCH",
+        &uri,
+        Some("\"1\""),
+        json!({ "data": { "text": "mine" } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Another client still holding version 1 loses.
+    let (status, _, problem) = send(
+        &app,
+        "PATCH",
+        &uri,
+        Some("\"1\""),
+        json!({ "data": { "text": "theirs" } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    assert_eq!(problem["error"], "precondition_failed");
+    let (_, _, doc) = send(&app, "GET", &uri, None, Value::Null).await;
+    assert_eq!(doc["data"]["text"], "mine");
+
+    // Any of several tags may match, and `*` matches every version.
+    let (status, _, _) = send(
+        &app,
+        "PUT",
+        &uri,
+        Some("\"1\", \"2\""),
+        json!({ "data": { "text": "listed" } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = send(
+        &app,
+        "PATCH",
+        &uri,
+        Some("*"),
+        json!({ "data": { "text": "any" } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = send(
+        &app,
+        "PATCH",
+        &uri,
+        Some("W/\"4\""),
+        json!({ "data": { "text": "weak" } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+
+    let (status, _, _) = send(&app, "DELETE", &uri, Some("\"3\""), Value::Null).await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    let (status, _, _) = send(&app, "DELETE", &uri, Some("\"4\""), Value::Null).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _, _) = send(&app, "GET", &uri, None, Value::Null).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_locking_collections_require_if_match() {
+    let (app, uri) = app_with_doc(true).await;
+
+    let (status, _, problem) = send(
+        &app,
+        "PATCH",
+        &uri,
+        None,
+        json!({ "data": { "text": "blind" } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::PRECONDITION_REQUIRED);
+    assert_eq!(problem["error"], "precondition_required");
+    let (status, _, _) = send(&app, "DELETE", &uri, None, Value::Null).await;
+    assert_eq!(status, StatusCode::PRECONDITION_REQUIRED);
+
+    let (status, headers, _) = send(
+        &app,
+        "PATCH",
+        &uri,
+        Some("\"1\""),
+        json!({ "data": { "text": "seen" } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let etag = headers[ETAG].to_str().unwrap().to_string();
+    let (status, _, _) = send(&app, "DELETE", &uri, Some(&etag), Value::Null).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
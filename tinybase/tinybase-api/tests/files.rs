@@ -0,0 +1,267 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+};
+use tinybase_api::app_router;
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state};
+
+const BOUNDARY: &str = "tinybase-test-boundary";
+
+async fn create_test_collection(app: &axum::Router) -> i64 {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/collections")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    r#"{ "name": "documents", "schema": { "fields": { "title": { "type": "string", "required": true }, "attachment": { "type": "file", "required": true } } } }"#,
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let collection: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    collection["id"].as_i64().unwrap()
+}
+
+fn multipart_body(file_field: &str) -> String {
+    multipart_file(file_field, "report.txt")
+}
+
+fn multipart_file(file_field: &str, filename: &str) -> String {
+    format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"data\"\r\n\r\n{{\"title\": \"Report\"}}\r\n\
+         --{b}\r\nContent-Disposition: form-data; name=\"{f}\"; filename=\"{n}\"\r\nContent-Type: text/plain\r\n\r\nhello file\r\n\
+         --{b}--\r\n",
+        b = BOUNDARY,
+        f = file_field,
+        n = filename
+    )
+}
+
+async fn send_multipart(
+    app: &axum::Router,
+    method: &str,
+    uri: &str,
+    body: String,
+) -> serde_json::Value {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={}", BOUNDARY),
+                )
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn test_upload_and_download_file() {
+    let app = setup_test_app().await;
+    let collection_id = create_test_collection(&app).await;
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri(format!("/api/v1/collections/{}/records", collection_id))
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={}", BOUNDARY),
+                )
+                .body(Body::from(multipart_body("attachment")))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let record: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(record["data"]["title"], "Report");
+    assert_eq!(record["data"]["attachment"]["filename"], "report.txt");
+    assert_eq!(record["data"]["attachment"]["size"], 10);
+    assert_eq!(record["data"]["attachment"]["mime"], "text/plain");
+    let record_id = record["id"].as_i64().unwrap();
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("GET")
+                .uri(format!(
+                    "/api/v1/files/{}/{}/attachment/report.txt",
+                    collection_id, record_id
+                ))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/plain");
+    assert_eq!(response.headers()["x-content-type-options"], "nosniff");
+    assert_eq!(response.headers()["content-disposition"], "attachment");
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    assert_eq!(&body[..], b"hello file");
+}
+
+#[tokio::test]
+async fn test_upload_to_non_file_field() {
+    let app = setup_test_app().await;
+    let collection_id = create_test_collection(&app).await;
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri(format!("/api/v1/collections/{}/records", collection_id))
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={}", BOUNDARY),
+                )
+                .body(Body::from(multipart_body("title")))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_replacing_a_file_removes_the_old_one() {
+    let state = setup_test_state().await;
+    let app = app_router(state.clone());
+    let collection_id = create_test_collection(&app).await;
+    let records = format!("/api/v1/collections/{}/records", collection_id);
+    let record = send_multipart(&app, "POST", &records, multipart_body("attachment")).await;
+    let record_id = record["id"].as_i64().unwrap();
+    let key = |filename: &str| format!("{}/{}/attachment/{}", collection_id, record_id, filename);
+    assert!(state
+        .storage
+        .get(&key("report.txt"))
+        .await
+        .unwrap()
+        .is_some());
+
+    let uri = format!("{}/{}", records, record_id);
+    let body = multipart_file("attachment", "summary.txt");
+    let updated = send_multipart(&app, "PATCH", &uri, body).await;
+    assert_eq!(updated["data"]["attachment"]["filename"], "summary.txt");
+    assert!(state
+        .storage
+        .get(&key("report.txt"))
+        .await
+        .unwrap()
+        .is_none());
+    assert!(state
+        .storage
+        .get(&key("summary.txt"))
+        .await
+        .unwrap()
+        .is_some());
+
+    // Uploading under the same name keeps the file.
+    let body = multipart_file("attachment", "summary.txt");
+    send_multipart(&app, "PUT", &uri, body).await;
+    assert!(state
+        .storage
+        .get(&key("summary.txt"))
+        .await
+        .unwrap()
+        .is_some());
+}
+
+#[tokio::test]
+async fn test_images_are_served_inline() {
+    let app = setup_test_app().await;
+    let collection_id = create_test_collection(&app).await;
+    let records = format!("/api/v1/collections/{}/records", collection_id);
+    let body = multipart_file("attachment", "logo.png").replace("text/plain", "image/png");
+    let record = send_multipart(&app, "POST", &records, body).await;
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri(format!(
+                    "/api/v1/files/{}/{}/attachment/logo.png",
+                    collection_id, record["id"]
+                ))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "image/png");
+    assert_eq!(response.headers()["x-content-type-options"], "nosniff");
+    assert_eq!(response.headers()["content-disposition"], "inline");
+}
+
+#[tokio::test]
+async fn test_fields_keep_files_of_the_same_name_apart() {
+    let app = setup_test_app().await;
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/collections")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    r#"{ "name": "scans", "schema": { "fields": { "front": { "type": "file", "required": true }, "back": { "type": "file", "required": true } } } }"#,
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"front\"; filename=\"scan\"\r\nContent-Type: image/png\r\n\r\nfront side\r\n\
+         --{b}\r\nContent-Disposition: form-data; name=\"back\"; filename=\"scan\"\r\nContent-Type: text/plain\r\n\r\nback side\r\n\
+         --{b}--\r\n",
+        b = BOUNDARY
+    );
+    let record = send_multipart(&app, "POST", "/api/v1/collections/scans/records", body).await;
+
+    for (field, mime, content) in [
+        ("front", "image/png", "front side"),
+        ("back", "text/plain", "back side"),
+    ] {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!(
+                        "/api/v1/files/scans/{}/{}/scan",
+                        record["id"], field
+                    ))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], mime);
+        let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+        assert_eq!(&body[..], content.as_bytes());
+    }
+}
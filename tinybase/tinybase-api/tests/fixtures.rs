@@ -0,0 +1,125 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+};
+use tinybase_api::{app_router, fixtures};
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state, temp_path};
+
+async fn send(app: &axum::Router, method: &str, uri: &str, body: &str) -> StatusCode {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    status
+}
+
+#[tokio::test]
+async fn test_record_and_replay_fixtures() {
+    let path = temp_path("fixture.jsonl");
+    let app = fixtures::record(app_router(setup_test_state().await), &path);
+
+    send(&app, "POST", "/api/v1/collections", r#"{"name": "posts"}"#).await;
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections/1/records",
+        r#"{"data": {"title": "Hello"}}"#,
+    )
+    .await;
+    send(&app, "GET", "/api/v1/collections/1/records/1", "").await;
+    send(&app, "GET", "/api/v1/collections/1/records/2", "").await;
+
+    let mut exchanges = fixtures::load(&path).await.unwrap();
+    assert_eq!(exchanges.len(), 4);
+    assert_eq!(exchanges[1].request["data"]["title"], "Hello");
+    assert_eq!(exchanges[2].response["data"]["title"], "Hello");
+    assert_eq!(exchanges[3].status, 404);
+
+    let fresh = setup_test_app().await;
+    assert!(fixtures::replay(&fresh, &exchanges).await.is_empty());
+
+    // A regression shows up as a mismatch on the affected exchange.
+    exchanges[2].response["data"]["title"] = "Goodbye".into();
+    let fresh = app_router(
+        fixtures::in_memory_state(temp_path("uploads"))
+            .await
+            .unwrap(),
+    );
+    let mismatches = fixtures::replay(&fresh, &exchanges).await;
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].index, 2);
+    assert_eq!(mismatches[0].response["data"]["title"], "Hello");
+}
+
+#[tokio::test]
+async fn test_large_chunked_bodies_pass_through_unrecorded() {
+    let path = temp_path("fixture.jsonl");
+    let app = fixtures::record(app_router(setup_test_state().await), &path);
+    let collection = r#"{"name": "documents", "schema": {"fields": {"attachment": {"type": "file", "required": true}}}}"#;
+    send(&app, "POST", "/api/v1/collections", collection).await;
+
+    // Chunks without a Content-Length, adding up to over the recording limit.
+    let boundary = "tinybase-test-boundary";
+    let head = format!(
+        "--{}\r\nContent-Disposition: form-data; name=\"attachment\"; filename=\"big.bin\"\r\n\r\n",
+        boundary
+    );
+    let tail = format!("\r\n--{}--\r\n", boundary);
+    let chunks = [
+        head.into_bytes(),
+        vec![b'x'; 1536 * 1024],
+        tail.into_bytes(),
+    ]
+    .map(Ok::<_, std::io::Error>);
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/collections/documents/records")
+                .header(
+                    "content-type",
+                    format!("multipart/form-data; boundary={}", boundary),
+                )
+                .body(Body::from_stream(futures_util::stream::iter(chunks)))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let record: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(record["data"]["attachment"]["size"], 1536 * 1024);
+
+    let exchanges = fixtures::load(&path).await.unwrap();
+    assert_eq!(exchanges.len(), 1);
+}
+
+#[tokio::test]
+async fn test_unsendable_requests_are_mismatches() {
+    let exchanges = vec![fixtures::Exchange {
+        method: "GET".to_string(),
+        uri: "not a uri".to_string(),
+        content_type: None,
+        request: serde_json::Value::Null,
+        status: 200,
+        response: serde_json::Value::Null,
+    }];
+    let mismatches = fixtures::replay(&setup_test_app().await, &exchanges).await;
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].status, 0);
+    assert!(mismatches[0].response.is_string());
+}
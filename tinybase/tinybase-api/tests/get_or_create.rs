@@ -0,0 +1,121 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+mod common;
+use common::setup_test_app;
+
+async fn send(app: &Router, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn test_get_or_create_returns_the_existing_record() {
+    let app = setup_test_app().await;
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "settings" }),
+    )
+    .await;
+    let uri = "/api/v1/collections/settings/records/by/key/theme";
+
+    let (status, created) = send(&app, "PUT", uri, json!({ "data": { "value": "dark" } })).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", created);
+    assert_eq!(created["data"], json!({ "key": "theme", "value": "dark" }));
+
+    // The second call gets the first record, not the data it sent.
+    let (status, existing) = send(&app, "PUT", uri, json!({ "data": { "value": "light" } })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(existing["id"], created["id"]);
+    assert_eq!(existing["data"]["value"], "dark");
+
+    let (_, records) = send(
+        &app,
+        "GET",
+        "/api/v1/collections/settings/records",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(records.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_get_or_create_parses_and_validates_values() {
+    let app = setup_test_app().await;
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "seats", "schema": { "fields": {
+            "number": { "type": "number", "required": true },
+            "label": { "type": "string", "required": true }
+        } } }),
+    )
+    .await;
+
+    let (status, seat) = send(
+        &app,
+        "PUT",
+        "/api/v1/collections/seats/records/by/number/12",
+        json!({ "data": { "label": "window" } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", seat);
+    assert_eq!(seat["data"]["number"], 12);
+
+    let (status, _) = send(
+        &app,
+        "PUT",
+        "/api/v1/collections/seats/records/by/number/twelve",
+        json!({ "data": { "label": "aisle" } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Invalid data is refused only when the record would be created.
+    let (status, _) = send(
+        &app,
+        "PUT",
+        "/api/v1/collections/seats/records/by/number/13",
+        json!({ "data": {} }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = send(
+        &app,
+        "PUT",
+        "/api/v1/collections/seats/records/by/number/12",
+        json!({ "data": {} }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(
+        &app,
+        "PUT",
+        "/api/v1/collections/missing/records/by/number/12",
+        json!({ "data": {} }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
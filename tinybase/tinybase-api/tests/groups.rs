@@ -0,0 +1,151 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+
+mod common;
+use common::setup_test_app;
+
+async fn send(
+    app: &axum::Router,
+    method: &str,
+    uri: &str,
+    body: &str,
+) -> (StatusCode, serde_json::Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(if body.is_empty() {
+                    Body::empty()
+                } else {
+                    Body::from(body.to_string())
+                })
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let value = serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null);
+    (status, value)
+}
+
+fn names(collections: &serde_json::Value) -> Vec<&str> {
+    collections
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["name"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_collection_groups() {
+    let app = setup_test_app().await;
+    let (status, posts) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        r#"{ "name": "posts", "group": " content " }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(posts["group"], "content");
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        r#"{ "name": "pages", "group": "content" }"#,
+    )
+    .await;
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        r#"{ "name": "orders", "group": "shop" }"#,
+    )
+    .await;
+    let (_, misc) = send(&app, "POST", "/api/v1/collections", r#"{ "name": "misc" }"#).await;
+    assert!(misc.get("group").is_none());
+
+    let (_, collections) = send(&app, "GET", "/api/v1/collections?group=content", "").await;
+    assert_eq!(names(&collections), ["posts", "pages"]);
+    let (_, collections) = send(&app, "GET", "/api/v1/collections?group=", "").await;
+    assert_eq!(names(&collections), ["misc"]);
+
+    let (_, groups) = send(&app, "GET", "/api/v1/collection-groups", "").await;
+    assert_eq!(
+        groups,
+        serde_json::json!([
+            { "name": "content", "collections": ["pages", "posts"] },
+            { "name": "shop", "collections": ["orders"] }
+        ])
+    );
+
+    // Moving and ungrouping single collections.
+    send(
+        &app,
+        "PATCH",
+        "/api/v1/collections/misc",
+        r#"{ "group": "shop" }"#,
+    )
+    .await;
+    let (_, orders) = send(
+        &app,
+        "PATCH",
+        "/api/v1/collections/orders",
+        r#"{ "group": "" }"#,
+    )
+    .await;
+    assert!(orders.get("group").is_none());
+
+    // Renaming into an existing group merges them.
+    let (status, group) = send(
+        &app,
+        "PATCH",
+        "/api/v1/collection-groups/shop",
+        r#"{ "name": "content" }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        group["collections"],
+        serde_json::json!(["misc", "pages", "posts"])
+    );
+
+    let (status, _) = send(&app, "DELETE", "/api/v1/collection-groups/content", "").await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, groups) = send(&app, "GET", "/api/v1/collection-groups", "").await;
+    assert_eq!(groups, serde_json::json!([]));
+    let (_, collections) = send(&app, "GET", "/api/v1/collections", "").await;
+    assert_eq!(collections.as_array().unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn test_collection_group_errors() {
+    let app = setup_test_app().await;
+    let long = "g".repeat(65);
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        &format!(r#"{{ "name": "posts", "group": "{}" }}"#, long),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(
+        &app,
+        "PATCH",
+        "/api/v1/collection-groups/none",
+        r#"{ "name": "other" }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(&app, "DELETE", "/api/v1/collection-groups/none", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
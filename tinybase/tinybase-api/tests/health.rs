@@ -0,0 +1,218 @@
+use axum::{
+    async_trait,
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tinybase_api::app_router;
+use tinybase_core::{
+    read_replicas::ReadReplicas,
+    replica::{ReplicaConfig, ReplicaSync},
+    setup_database, Db,
+};
+use tinybase_storage::{Storage, StorageError};
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state, temp_path};
+
+async fn get_health(app: axum::Router) -> (StatusCode, serde_json::Value) {
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/api/v1/health")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+async fn send(app: &axum::Router, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn test_health() {
+    let (status, health) = get_health(setup_test_app().await).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(health, serde_json::json!({ "status": "ok" }));
+}
+
+/// Upload storage whose bucket can't be reached.
+struct UnreachableStorage;
+
+#[async_trait]
+impl Storage for UnreachableStorage {
+    async fn put(&self, _: &str, _: &[u8]) -> Result<(), StorageError> {
+        Err(StorageError::Backend("bucket unreachable".to_string()))
+    }
+    async fn get(&self, _: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        Err(StorageError::Backend("bucket unreachable".to_string()))
+    }
+    async fn delete(&self, _: &str) -> Result<(), StorageError> {
+        Err(StorageError::Backend("bucket unreachable".to_string()))
+    }
+    async fn delete_prefix(&self, _: &str) -> Result<(), StorageError> {
+        Err(StorageError::Backend("bucket unreachable".to_string()))
+    }
+}
+
+#[tokio::test]
+async fn test_verbose_health_checks_dependencies() {
+    let app = setup_test_app().await;
+    let uri = "/api/v1/health?verbose=true";
+    let (status, health) = send(&app, "GET", uri, Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(health["status"], "ok");
+    let dependencies = health["dependencies"].as_array().unwrap();
+    let names: Vec<_> = dependencies.iter().map(|d| d["name"].clone()).collect();
+    assert_eq!(names, vec!["database", "storage", "store", "search"]);
+    for dependency in dependencies {
+        assert_eq!(dependency["status"], "ok");
+        assert!(dependency["latency_ms"].is_number());
+    }
+
+    let mut state = setup_test_state().await;
+    state.storage = Arc::new(UnreachableStorage);
+    let app = app_router(state);
+    let (status, health) = send(&app, "GET", uri, Value::Null).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(health["status"], "degraded");
+    let storage = &health["dependencies"][1];
+    assert_eq!(storage["status"], "failed");
+    assert!(storage["error"]
+        .as_str()
+        .unwrap()
+        .contains("bucket unreachable"));
+    // Plain health checks stay cheap and leave dependencies alone.
+    let (status, health) = get_health(app).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(health.get("dependencies").is_none());
+}
+
+#[tokio::test]
+async fn test_health_reports_failed_replica_sync() {
+    // A plain local database cannot sync, like a replica whose primary is
+    // unreachable.
+    let path = temp_path("replica.db");
+    let db = libsql::Builder::new_local(&path).build().await.unwrap();
+    let config = ReplicaConfig::new(&path, "libsql://example.turso.io", "token");
+    let replica = ReplicaSync::new(Arc::new(db), &config);
+    let app = app_router(setup_test_state().await.with_replica(replica.clone()));
+
+    let (status, health) = get_health(app.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(health["replication"]["url"], "libsql://example.turso.io");
+    assert_eq!(health["replication"]["sync_interval_secs"], 60);
+
+    let synced = replica.sync_once().await;
+    assert!(!synced.ok);
+    let (status, health) = get_health(app).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(health["status"], "degraded");
+    assert_eq!(health["replication"]["ok"], false);
+    assert!(health["replication"]["error"].is_string());
+    assert!(health["replication"]["last_attempt"].is_string());
+    assert!(health["replication"]["last_sync"].is_null());
+}
+
+#[tokio::test]
+async fn test_reads_go_to_the_fastest_healthy_replica() {
+    // Routing status and reads share locks, so a deadlock would hang the
+    // test rather than fail it.
+    tokio::time::timeout(Duration::from_secs(30), route_reads_to_replicas())
+        .await
+        .expect("read routing hung");
+}
+
+async fn route_reads_to_replicas() {
+    let state = setup_test_state().await;
+    // An empty database stands in for a replica that has not caught up, so
+    // reads served by it are easy to tell apart.
+    let conn = libsql::Builder::new_local(":memory:")
+        .build()
+        .await
+        .unwrap()
+        .connect()
+        .unwrap();
+    setup_database(&conn).await.unwrap();
+    let eu: Arc<dyn Db> = Arc::new(tokio::sync::Mutex::new(conn));
+    let down = libsql::Builder::new_remote("http://127.0.0.1:1".to_string(), String::new())
+        .build()
+        .await
+        .unwrap();
+    let replicas = ReadReplicas::new(
+        state.db.clone(),
+        vec![("down".to_string(), Arc::new(down)), ("eu".to_string(), eu)],
+    );
+    let app = app_router(state.with_read_replicas(replicas.clone()));
+
+    let (_, posts) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "posts" }),
+    )
+    .await;
+    let uri = format!("/api/v1/collections/{}", posts["id"]);
+    // Until the replicas are probed, reads stay on the primary.
+    let (status, _) = send(&app, "GET", &uri, Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+
+    replicas.probe().await;
+    let (status, _) = send(&app, "GET", &uri, Value::Null).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, routing) = send(&app, "GET", "/api/v1/admin/replicas", Value::Null).await;
+    assert_eq!(routing["selected"], "eu");
+    assert_eq!(routing["pinned"], Value::Null);
+    let down = &routing["replicas"][0];
+    assert_eq!(down["healthy"], false);
+    assert!(down["error"].is_string());
+    let eu = &routing["replicas"][1];
+    assert_eq!(eu["healthy"], true);
+    assert!(eu["latency_ms"].is_number());
+    assert_eq!(eu["reads"], 1);
+    let primary_reads = routing["primary_reads"].as_u64().unwrap();
+
+    let pin = "/api/v1/admin/replicas/pin";
+    let (status, routing) = send(&app, "PUT", pin, json!({ "replica": "primary" })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(routing["selected"], "primary");
+    let (status, _) = send(&app, "GET", &uri, Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, "PUT", pin, json!({ "replica": "mars" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (_, routing) = send(&app, "PUT", pin, json!({ "replica": null })).await;
+    assert_eq!(routing["selected"], "eu");
+
+    let (_, metrics) = send(&app, "GET", "/api/v1/admin/metrics", Value::Null).await;
+    let read_routing = &metrics["read_routing"];
+    assert!(read_routing["primary_reads"].as_u64().unwrap() > primary_reads);
+}
+
+#[tokio::test]
+async fn test_replica_routing_needs_replicas() {
+    let app = setup_test_app().await;
+    let (status, _) = send(&app, "GET", "/api/v1/admin/replicas", Value::Null).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
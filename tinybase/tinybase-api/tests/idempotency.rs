@@ -0,0 +1,198 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use tinybase_api::app_router;
+use tinybase_core::clock::ManualClock;
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state};
+
+/// Sends `body` with `Idempotency-Key: key` when given, returning the status,
+/// the `Idempotent-Replayed` header and the body.
+async fn send(
+    app: &Router,
+    uri: &str,
+    key: Option<&str>,
+    body: Value,
+) -> (StatusCode, Option<String>, Value) {
+    let mut request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("content-type", "application/json");
+    if let Some(key) = key {
+        request = request.header("idempotency-key", key);
+    }
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::from(body.to_string())).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let replayed = response
+        .headers()
+        .get("idempotent-replayed")
+        .map(|v| v.to_str().unwrap().to_string());
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (
+        status,
+        replayed,
+        serde_json::from_slice(&body).unwrap_or(Value::Null),
+    )
+}
+
+async fn create_collection(app: &Router, name: &str) {
+    let (status, _, _) = send(
+        app,
+        "/api/v1/collections",
+        None,
+        json!({ "name": name, "schema": { "fields": {
+            "amount": { "type": "number", "required": true }
+        } } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+}
+
+async fn count(app: &Router, collection: &str) -> usize {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/api/v1/collections/{}/records", collection))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let records: Value = serde_json::from_slice(&body).unwrap();
+    records.as_array().unwrap().len()
+}
+
+#[tokio::test]
+async fn test_retries_with_the_same_key_return_the_first_record() {
+    let clock = Arc::new(ManualClock::new(
+        UNIX_EPOCH + Duration::from_secs(1_714_521_600),
+    ));
+    let app = app_router(setup_test_state().await.with_clock(clock.clone()));
+    create_collection(&app, "payments").await;
+    create_collection(&app, "refunds").await;
+    let uri = "/api/v1/collections/payments/records";
+
+    let (status, replayed, first) = send(
+        &app,
+        uri,
+        Some("pay-1"),
+        json!({ "data": { "amount": 10 } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(replayed, None);
+
+    let (status, replayed, retry) = send(
+        &app,
+        uri,
+        Some("pay-1"),
+        json!({ "data": { "amount": 10 } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(replayed.as_deref(), Some("true"));
+    assert_eq!(retry["id"], first["id"]);
+    assert_eq!(count(&app, "payments").await, 1);
+
+    // Another key, or none, creates another record.
+    send(
+        &app,
+        uri,
+        Some("pay-2"),
+        json!({ "data": { "amount": 10 } }),
+    )
+    .await;
+    send(&app, uri, None, json!({ "data": { "amount": 10 } })).await;
+    assert_eq!(count(&app, "payments").await, 3);
+
+    // A key belongs to the collection it was first used on.
+    let (status, _, _) = send(
+        &app,
+        "/api/v1/collections/refunds/records",
+        Some("pay-1"),
+        json!({ "data": { "amount": 10 } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Keys are forgotten after a day.
+    clock.advance(Duration::from_secs(24 * 60 * 60 + 1));
+    let (status, replayed, later) = send(
+        &app,
+        uri,
+        Some("pay-1"),
+        json!({ "data": { "amount": 10 } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(replayed, None);
+    assert_ne!(later["id"], first["id"]);
+}
+
+#[tokio::test]
+async fn test_failed_creates_free_their_key() {
+    let app = setup_test_app().await;
+    create_collection(&app, "payments").await;
+    let uri = "/api/v1/collections/payments/records";
+
+    let (status, _, _) = send(&app, uri, Some("pay-1"), json!({ "data": {} })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, replayed, _) =
+        send(&app, uri, Some("pay-1"), json!({ "data": { "amount": 5 } })).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(replayed, None);
+    assert_eq!(count(&app, "payments").await, 1);
+
+    let (status, _, _) = send(&app, uri, Some(" "), json!({ "data": { "amount": 5 } })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_deleted_collections_free_their_keys() {
+    let app = setup_test_app().await;
+    create_collection(&app, "payments").await;
+    let (status, _, _) = send(
+        &app,
+        "/api/v1/collections/payments/records",
+        Some("pay-1"),
+        json!({ "data": { "amount": 10 } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("DELETE")
+                .uri("/api/v1/collections/payments?force=true")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    create_collection(&app, "refunds").await;
+    let (status, replayed, _) = send(
+        &app,
+        "/api/v1/collections/refunds/records",
+        Some("pay-1"),
+        json!({ "data": { "amount": 10 } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(replayed, None);
+}
@@ -0,0 +1,234 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state, temp_path};
+use tinybase_api::app_router;
+use tinybase_core::checksums::ChecksumMode;
+
+async fn send(app: &axum::Router, method: &str, uri: &str) -> (StatusCode, Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+/// Polls the job at `uri` until it finishes.
+async fn wait_for(app: &axum::Router, uri: &str) -> Value {
+    for _ in 0..500 {
+        let (status, job) = send(app, "GET", uri).await;
+        assert_eq!(status, StatusCode::OK);
+        if job["state"] != "queued" && job["state"] != "running" {
+            return job;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    panic!("job {} did not finish", uri);
+}
+
+#[tokio::test]
+async fn test_background_backup_job() {
+    let backup_dir = temp_path("backups");
+    let app = app_router(setup_test_state().await.with_backups(&backup_dir));
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/admin/backup?background=true")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let location = response.headers()["location"].to_str().unwrap().to_string();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let job: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(job["kind"], "backup");
+    assert_eq!(location, format!("/api/v1/jobs/{}", job["id"]));
+
+    let job = wait_for(&app, &location).await;
+    assert_eq!(job["state"], "completed");
+    let name = job["result"]["name"].as_str().unwrap();
+    assert!(backup_dir.join(name).is_file());
+
+    // Finished jobs cannot be cancelled.
+    let (status, _) = send(&app, "POST", &format!("{}/cancel", location)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn test_cancel_queued_job() {
+    let state = setup_test_state().await;
+    let job = state.db.create_job("import", &json!({})).await.unwrap();
+    let app = app_router(state);
+    let uri = format!("/api/v1/jobs/{}", job.id);
+
+    let (status, job) = send(&app, "GET", &uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(job["state"], "queued");
+
+    let (status, job) = send(&app, "POST", &format!("{}/cancel", uri)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(job["state"], "cancelled");
+    let (_, job) = send(&app, "GET", &uri).await;
+    assert_eq!(job["state"], "cancelled");
+}
+
+#[tokio::test]
+async fn test_unknown_job() {
+    let app = setup_test_app().await;
+    let (status, _) = send(&app, "GET", "/api/v1/jobs/999").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(&app, "POST", "/api/v1/jobs/999/cancel").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_storage_upgrade_job() {
+    let path = temp_path("formats.db");
+    let db = libsql::Builder::new_local(&path).build().await.unwrap();
+    let conn = db.connect().unwrap();
+    tinybase_core::setup_database(&conn).await.unwrap();
+    let state = tinybase_api::AppState {
+        db: std::sync::Arc::new(tokio::sync::Mutex::new(db.connect().unwrap())),
+        ..setup_test_state().await
+    };
+    let app = app_router(state.clone());
+    let notes = state.db.create_collection("notes", &None).await.unwrap();
+    for i in 0..3 {
+        let data = json!({ "title": format!("Note {}", i) });
+        state.db.create_record(notes.id, &data).await.unwrap();
+    }
+    let formats = || async {
+        let mut rows = conn
+            .query("SELECT format FROM records ORDER BY id", ())
+            .await
+            .unwrap();
+        let mut formats = Vec::new();
+        while let Some(row) = rows.next().await.unwrap() {
+            formats.push(row.get::<i64>(0).unwrap());
+        }
+        formats
+    };
+    assert_eq!(formats().await, [1, 1, 1]);
+
+    // Rows from before formats were recorded are read as they are and
+    // rewritten when read by id, without a new version.
+    conn.execute("UPDATE records SET format = 0", ())
+        .await
+        .unwrap();
+    let (status, record) = send(&app, "GET", "/api/v1/collections/notes/records/1").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(record["data"]["title"], "Note 0");
+    assert_eq!(formats().await, [1, 0, 0]);
+    let version = conn
+        .query("SELECT version FROM records WHERE id = 1", ())
+        .await
+        .unwrap()
+        .next()
+        .await
+        .unwrap()
+        .unwrap()
+        .get::<i64>(0)
+        .unwrap();
+    assert_eq!(version, 1);
+
+    // A format from a newer server is refused rather than misread.
+    conn.execute("UPDATE records SET format = 99 WHERE id = 1", ())
+        .await
+        .unwrap();
+    let (status, _) = send(&app, "GET", "/api/v1/collections/notes/records/1").await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    conn.execute("UPDATE records SET format = 1 WHERE id = 1", ())
+        .await
+        .unwrap();
+
+    let (status, job) = send(&app, "POST", "/api/v1/admin/storage/upgrade").await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(job["kind"], "upgrade");
+    let job = wait_for(&app, &format!("/api/v1/jobs/{}", job["id"])).await;
+    assert_eq!(job["state"], "completed");
+    assert_eq!(job["result"]["records"], 2);
+    assert_eq!(formats().await, [1, 1, 1]);
+    let (_, records) = send(&app, "GET", "/api/v1/collections/notes/records").await;
+    assert_eq!(records.as_array().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_checksum_scrub_job() {
+    let path = temp_path("checksums.db");
+    let db = libsql::Builder::new_local(&path).build().await.unwrap();
+    let conn = db.connect().unwrap();
+    tinybase_core::setup_database(&conn).await.unwrap();
+    let state = tinybase_api::AppState {
+        db: std::sync::Arc::new(tokio::sync::Mutex::new(db.connect().unwrap())),
+        ..setup_test_state().await
+    };
+    let app = app_router(state.clone());
+    let strict = app_router(state.clone().with_checksum_mode(ChecksumMode::Enforce));
+    let notes = state.db.create_collection("notes", &None).await.unwrap();
+    for i in 0..3 {
+        let data = json!({ "title": format!("Note {}", i) });
+        state.db.create_record(notes.id, &data).await.unwrap();
+    }
+    // Changes made in place keep the checksum current.
+    state
+        .db
+        .increment_field(notes.id, 1, "views", &1.into(), (None, None))
+        .await
+        .unwrap();
+    let (status, _) = send(&strict, "GET", "/api/v1/collections/notes/records/1").await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Data changed behind the server's back is logged, or refused.
+    conn.execute(
+        r#"UPDATE records SET data = '{"title":"Nope"}' WHERE id = 2"#,
+        (),
+    )
+    .await
+    .unwrap();
+    conn.execute("UPDATE records SET checksum = NULL WHERE id = 3", ())
+        .await
+        .unwrap();
+    let (status, record) = send(&app, "GET", "/api/v1/collections/notes/records/2").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(record["data"]["title"], "Nope");
+    let (status, _) = send(&strict, "GET", "/api/v1/collections/notes/records/2").await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    let (status, _) = send(&strict, "GET", "/api/v1/collections/notes/records/3").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, job) = send(&app, "POST", "/api/v1/admin/checksums/scrub").await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(job["kind"], "scrub");
+    let job = wait_for(&app, &format!("/api/v1/jobs/{}", job["id"])).await;
+    assert_eq!(job["state"], "completed");
+    assert_eq!(
+        job["result"],
+        json!({ "checked": 3, "sealed": 1, "mismatches": 1 })
+    );
+    let (status, mismatches) = send(&app, "GET", "/api/v1/admin/checksums").await;
+    assert_eq!(status, StatusCode::OK);
+    let mismatches = mismatches.as_array().unwrap();
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0]["collection"], "notes");
+    assert_eq!(mismatches[0]["record_id"], 2);
+    assert_ne!(mismatches[0]["stored"], mismatches[0]["computed"]);
+}
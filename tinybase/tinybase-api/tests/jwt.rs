@@ -0,0 +1,191 @@
+#![cfg(feature = "jwt")]
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    routing::get,
+    Json, Router,
+};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tinybase_api::{
+    app_router,
+    jwt::{JwtSettings, JwtVerifier},
+};
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state};
+
+const SECRET: &[u8] = b"a shared secret for the tests!!!";
+
+async fn send(app: &Router, method: &str, uri: &str, token: Option<&str>) -> (StatusCode, Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json");
+    if let Some(token) = token {
+        request = request.header("authorization", format!("Bearer {}", token));
+    }
+    let body = if method == "POST" {
+        r#"{ "name": "posts" }"#
+    } else {
+        ""
+    };
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::from(body)).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+/// Serves a key set holding the test secret, standing in for the provider.
+async fn serve_jwks() -> String {
+    let jwks = json!({ "keys": [{
+        "kty": "oct",
+        "kid": "test",
+        "alg": "HS256",
+        "k": "YSBzaGFyZWQgc2VjcmV0IGZvciB0aGUgdGVzdHMhISE"
+    }] });
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = Router::new().route("/jwks", get(move || async move { Json(jwks) }));
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{}/jwks", addr)
+}
+
+fn token(kid: &str, claims: Value) -> String {
+    let header = Header {
+        kid: Some(kid.to_string()),
+        ..Header::default()
+    };
+    encode(&header, &claims, &EncodingKey::from_secret(SECRET)).unwrap()
+}
+
+#[tokio::test]
+async fn test_external_tokens() {
+    let settings = JwtSettings {
+        issuer: "https://idp.example.com/".to_string(),
+        jwks_url: serve_jwks().await,
+        audience: Some("tinybase".to_string()),
+        roles_claim: "realm_access.roles".to_string(),
+        admin_role: "tinybase-admin".to_string(),
+    };
+    let app = app_router(
+        setup_test_state()
+            .await
+            .with_jwt(JwtVerifier::new(settings)),
+    );
+    let exp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        + 3600;
+    let claims = |roles: Value| {
+        json!({
+            "iss": "https://idp.example.com/",
+            "aud": "tinybase",
+            "sub": "auth0|alice",
+            "exp": exp,
+            "realm_access": { "roles": roles }
+        })
+    };
+
+    // Trusting a provider ends setup mode, even without local admins.
+    let (status, _) = send(&app, "POST", "/api/v1/collections", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let reader = token("test", claims(json!(["reader"])));
+    let (status, _) = send(&app, "POST", "/api/v1/collections", Some(&reader)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let admin = token("test", claims(json!(["reader", "tinybase-admin"])));
+    let (status, _) = send(&app, "POST", "/api/v1/collections", Some(&admin)).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, introspection) = send(&app, "GET", "/api/v1/auth/introspect", Some(&reader)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(introspection["subject"], "auth0|alice");
+    assert_eq!(introspection["scopes"], json!(["reader"]));
+    assert!(introspection["expires_in"].as_u64().unwrap() > 3500);
+    assert_eq!(introspection["claims"]["aud"], "tinybase");
+    assert!(introspection.get("admin").is_none());
+
+    let mut wrong_issuer = claims(json!(["tinybase-admin"]));
+    wrong_issuer["iss"] = json!("https://evil.example.com/");
+    let mut wrong_audience = claims(json!(["tinybase-admin"]));
+    wrong_audience["aud"] = json!("other");
+    let mut expired = claims(json!(["tinybase-admin"]));
+    expired["exp"] = json!(exp - 2 * 3600);
+    for token in [
+        token("test", wrong_issuer),
+        token("test", wrong_audience),
+        token("test", expired),
+        token("unknown", claims(json!(["tinybase-admin"]))),
+        format!("{}x", admin),
+    ] {
+        let (status, _) = send(&app, "GET", "/api/v1/auth/introspect", Some(&token)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", token);
+    }
+
+    // Without a provider configured its tokens mean nothing.
+    let app = setup_test_app().await;
+    let (status, _) = send(&app, "GET", "/api/v1/auth/introspect", Some(&admin)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_masks_reveal_fields_by_role() {
+    let settings = JwtSettings {
+        issuer: "https://idp.example.com/".to_string(),
+        jwks_url: serve_jwks().await,
+        audience: None,
+        roles_claim: "roles".to_string(),
+        admin_role: "admin".to_string(),
+    };
+    let state = setup_test_state()
+        .await
+        .with_jwt(JwtVerifier::new(settings));
+    let schema = serde_json::from_value(json!({ "fields": {
+        "card": {
+            "type": "string",
+            "required": true,
+            "mask": { "reveal_to": ["billing"], "keep_last": 4 }
+        }
+    }, "rules": { "view": "" } }))
+    .unwrap();
+    let collection = state
+        .db
+        .create_collection("payments", &Some(schema))
+        .await
+        .unwrap();
+    let record = state
+        .db
+        .create_record(collection.id, &json!({ "card": "4242424242421234" }))
+        .await
+        .unwrap();
+    let app = app_router(state);
+    let uri = format!("/api/v1/collections/payments/records/{}", record.id);
+    let exp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        + 3600;
+    let card = |roles: Value| {
+        let token = token(
+            "test",
+            json!({ "iss": "https://idp.example.com/", "sub": "bob", "exp": exp, "roles": roles }),
+        );
+        let (app, uri) = (app.clone(), uri.clone());
+        async move { send(&app, "GET", &uri, Some(&token)).await.1["data"]["card"].clone() }
+    };
+
+    assert_eq!(card(json!(["viewer"])).await, "************1234");
+    assert_eq!(card(json!(["viewer", "billing"])).await, "4242424242421234");
+    assert_eq!(card(json!(["admin"])).await, "4242424242421234");
+    let (_, anonymous) = send(&app, "GET", &uri, None).await;
+    assert_eq!(anonymous["data"]["card"], "************1234");
+}
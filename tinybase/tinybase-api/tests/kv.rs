@@ -0,0 +1,92 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use tinybase_api::app_router;
+use tinybase_core::clock::ManualClock;
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state};
+
+async fn send(app: &Router, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn test_set_get_and_delete_entries() {
+    let app = setup_test_app().await;
+    let uri = "/api/v1/kv/flags/dark-mode";
+
+    let (status, _) = send(&app, "GET", uri, Value::Null).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, entry) = send(&app, "PUT", uri, json!({ "value": { "on": true } })).await;
+    assert_eq!(status, StatusCode::OK, "{}", entry);
+    assert_eq!(entry["namespace"], "flags");
+    assert_eq!(entry["key"], "dark-mode");
+    assert_eq!(entry["expires"], Value::Null);
+
+    // Setting again replaces the value but keeps the creation time.
+    let (_, updated) = send(&app, "PUT", uri, json!({ "value": { "on": false } })).await;
+    assert_eq!(updated["created"], entry["created"]);
+    let (status, entry) = send(&app, "GET", uri, Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(entry["value"], json!({ "on": false }));
+
+    // Namespaces keep their keys apart.
+    let (status, _) = send(&app, "GET", "/api/v1/kv/settings/dark-mode", Value::Null).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = send(&app, "DELETE", uri, Value::Null).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send(&app, "DELETE", uri, Value::Null).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = send(&app, "PUT", uri, json!({ "value": 1, "ttl": 0 })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_entries_expire_after_their_ttl() {
+    let clock = Arc::new(ManualClock::new(
+        UNIX_EPOCH + Duration::from_secs(1_714_521_600),
+    ));
+    let app = app_router(setup_test_state().await.with_clock(clock.clone()));
+    let uri = "/api/v1/kv/sessions/abc";
+
+    let (_, entry) = send(&app, "PUT", uri, json!({ "value": "ann", "ttl": 60 })).await;
+    assert_eq!(entry["expires"], "2024-05-01T00:01:00.000Z");
+    clock.advance(Duration::from_secs(59));
+    let (status, _) = send(&app, "GET", uri, Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+
+    clock.advance(Duration::from_secs(1));
+    let (status, _) = send(&app, "GET", uri, Value::Null).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(&app, "DELETE", uri, Value::Null).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Set again, an expired key starts afresh.
+    let (_, entry) = send(&app, "PUT", uri, json!({ "value": "bob" })).await;
+    assert_eq!(entry["created"], "2024-05-01T00:01:00.000Z");
+    assert_eq!(entry["expires"], Value::Null);
+}
@@ -0,0 +1,170 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use tinybase_api::app_router;
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state};
+
+const BOUNDARY: &str = "tinybase-test-boundary";
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+fn post_json(uri: &str, body: &Value) -> Request<Body> {
+    Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// `app` with a `documents` collection.
+async fn with_documents(app: Router) -> Router {
+    let (status, _) = send(
+        &app,
+        post_json(
+            "/api/v1/collections",
+            &json!({ "name": "documents", "schema": { "fields": {
+                "title": { "type": "string", "required": false },
+                "tags": { "type": "json", "required": false },
+                "attachment": { "type": "file", "required": false }
+            } } }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    app
+}
+
+/// An app with the given limits and a `documents` collection.
+async fn setup(max_body_bytes: usize, max_json_bytes: usize) -> Router {
+    let mut state = setup_test_state().await;
+    state.max_body_bytes = max_body_bytes;
+    state.max_json_bytes = max_json_bytes;
+    with_documents(app_router(state)).await
+}
+
+#[tokio::test]
+async fn test_json_bodies_over_the_limit_are_refused() {
+    let app = setup(4096, 512).await;
+
+    let (status, _) = send(
+        &app,
+        post_json(
+            "/api/v1/collections/documents/records",
+            &json!({ "data": { "title": "x".repeat(400) } }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, body) = send(
+        &app,
+        post_json(
+            "/api/v1/collections/documents/records",
+            &json!({ "data": { "title": "x".repeat(600) } }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(body["error"], "payload_too_large");
+    assert!(body["message"].as_str().unwrap().contains("512"));
+
+    // A body announcing more than the limit is refused unread.
+    let (status, body) = send(
+        &app,
+        Request::builder()
+            .method("POST")
+            .uri("/api/v1/collections/documents/records")
+            .header("content-type", "application/json")
+            .header("content-length", "100000")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(body["error"], "payload_too_large");
+}
+
+#[tokio::test]
+async fn test_deeply_nested_json_is_refused() {
+    let app = with_documents(setup_test_app().await).await;
+
+    let mut tags = json!("deep");
+    for _ in 0..40 {
+        tags = json!([tags]);
+    }
+    let (status, body) = send(
+        &app,
+        post_json(
+            "/api/v1/collections/documents/records",
+            &json!({ "data": { "tags": tags } }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "bad_request");
+    assert!(body["message"].as_str().unwrap().contains("32"));
+    let (_, records) = send(
+        &app,
+        Request::builder()
+            .uri("/api/v1/collections/documents/records")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(records.as_array().unwrap().len(), 0);
+
+    // Brackets in strings don't nest.
+    let (status, _) = send(
+        &app,
+        post_json(
+            "/api/v1/collections/documents/records",
+            &json!({ "data": { "title": "[{".repeat(100) } }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+}
+
+fn upload(size: usize) -> Request<Body> {
+    let body = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"data\"\r\n\r\n{{\"title\": \"Report\"}}\r\n\
+         --{b}\r\nContent-Disposition: form-data; name=\"attachment\"; filename=\"report.txt\"\r\nContent-Type: text/plain\r\n\r\n{f}\r\n\
+         --{b}--\r\n",
+        b = BOUNDARY,
+        f = "x".repeat(size)
+    );
+    Request::builder()
+        .method("POST")
+        .uri("/api/v1/collections/documents/records")
+        .header(
+            "content-type",
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        )
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_uploads_have_their_own_limit() {
+    let app = setup(4096, 256).await;
+
+    let (status, body) = send(&app, upload(2000)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(body["data"]["attachment"]["size"], 2000);
+
+    let (status, body) = send(&app, upload(5000)).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(body["error"], "payload_too_large");
+}
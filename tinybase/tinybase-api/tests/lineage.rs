@@ -0,0 +1,133 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+mod common;
+use common::setup_test_app;
+
+async fn send(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    key: Option<&str>,
+    body: String,
+) -> (StatusCode, Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json");
+    if let Some(key) = key {
+        request = request.header("authorization", format!("Bearer {}", key));
+    }
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::from(body)).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+async fn ids(app: &Router, query: &str) -> Vec<i64> {
+    let uri = format!("/api/v1/collections/books/records?{}", query);
+    let (status, body) = send(app, "GET", &uri, None, String::new()).await;
+    assert_eq!(status, StatusCode::OK);
+    body.as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["id"].as_i64().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_records_keep_their_lineage() {
+    let app = setup_test_app().await;
+    let records = "/api/v1/collections/books/records";
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        None,
+        json!({
+            "name": "books",
+            "schema": {
+                "fields": { "title": { "type": "string", "required": false } },
+                "rules": { "list": "", "view": "", "create": "", "update": "" }
+            }
+        })
+        .to_string(),
+    )
+    .await;
+    let book = json!({ "data": { "title": "Dune" } }).to_string();
+    let (status, anonymous) = send(&app, "POST", records, None, book.clone()).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(anonymous["lineage"], json!({ "source": "api" }));
+
+    let admin = json!({ "email": "admin@example.com", "password": "correct horse" });
+    send(
+        &app,
+        "POST",
+        "/api/v1/admin/admins",
+        None,
+        admin.to_string(),
+    )
+    .await;
+    let (_, auth) = send(&app, "POST", "/api/v1/admin/auth", None, admin.to_string()).await;
+    let key = auth["key"].as_str().unwrap();
+    let actor = format!("admin:{}", auth["admin"]["id"]);
+    let (_, created) = send(&app, "POST", records, Some(key), book).await;
+    assert_eq!(
+        created["lineage"],
+        json!({ "source": "api", "actor": actor })
+    );
+    let (status, imported) = send(
+        &app,
+        "POST",
+        "/api/v1/collections/books/records/import",
+        Some(key),
+        "title\nEmma\nIvanhoe\n".to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let imported: Vec<i64> = serde_json::from_value(imported["created"].clone()).unwrap();
+
+    // Updates keep the lineage of the record.
+    let uri = format!("{}/{}", records, imported[0]);
+    let (status, updated) = send(
+        &app,
+        "PATCH",
+        &uri,
+        None,
+        json!({ "data": { "title": "Emma!" } }).to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        updated["lineage"],
+        json!({ "source": "import", "actor": actor })
+    );
+
+    assert_eq!(ids(&app, "source=import").await, imported);
+    assert_eq!(
+        ids(&app, "source=api").await,
+        vec![
+            anonymous["id"].as_i64().unwrap(),
+            created["id"].as_i64().unwrap()
+        ]
+    );
+    assert_eq!(
+        ids(&app, &format!("source=api&source_actor={}", actor))
+            .await
+            .len(),
+        1
+    );
+    assert!(ids(&app, "source_job=1").await.is_empty());
+    let uri = format!("{}?source=sync", records);
+    let (status, _) = send(&app, "GET", &uri, None, String::new()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
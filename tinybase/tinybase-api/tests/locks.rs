@@ -0,0 +1,162 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use tinybase_api::app_router;
+use tinybase_core::clock::ManualClock;
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state};
+
+async fn send(
+    app: &axum::Router,
+    method: &str,
+    uri: &str,
+    body: &str,
+) -> (StatusCode, serde_json::Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(if body.is_empty() {
+                    Body::empty()
+                } else {
+                    Body::from(body.to_string())
+                })
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let value = serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null);
+    (status, value)
+}
+
+async fn create_record(app: &axum::Router) -> String {
+    send(app, "POST", "/api/v1/collections", r#"{ "name": "docs" }"#).await;
+    let (_, record) = send(
+        app,
+        "POST",
+        "/api/v1/collections/docs/records",
+        r#"{ "data": { "title": "draft" } }"#,
+    )
+    .await;
+    format!("/api/v1/collections/docs/records/{}", record["id"])
+}
+
+#[tokio::test]
+async fn test_lock_and_unlock() {
+    let app = setup_test_app().await;
+    let uri = create_record(&app).await;
+
+    let (status, lock) = send(
+        &app,
+        "POST",
+        &format!("{}/lock", uri),
+        r#"{ "owner": "ann" }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(lock["owner"], "ann");
+
+    let (_, record) = send(&app, "GET", &uri, "").await;
+    assert_eq!(record["lock"]["owner"], "ann");
+    let (_, list) = send(&app, "GET", "/api/v1/collections/docs/records", "").await;
+    assert_eq!(list[0]["lock"]["owner"], "ann");
+
+    // Someone else cannot take or release the lock, the owner can renew it.
+    let (status, _) = send(
+        &app,
+        "POST",
+        &format!("{}/lock", uri),
+        r#"{ "owner": "bob" }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = send(&app, "DELETE", &format!("{}/lock?owner=bob", uri), "").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = send(
+        &app,
+        "POST",
+        &format!("{}/lock", uri),
+        r#"{ "owner": "ann" }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(&app, "DELETE", &format!("{}/lock?owner=ann", uri), "").await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, record) = send(&app, "GET", &uri, "").await;
+    assert!(record.get("lock").is_none());
+    let (status, _) = send(&app, "DELETE", &format!("{}/lock?owner=ann", uri), "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_lock_errors() {
+    let app = setup_test_app().await;
+    let uri = create_record(&app).await;
+
+    let (status, _) = send(
+        &app,
+        "POST",
+        &format!("{}/lock", uri),
+        r#"{ "owner": " " }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(
+        &app,
+        "POST",
+        &format!("{}/lock", uri),
+        r#"{ "owner": "ann", "ttl": 7200 }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections/docs/records/999/lock",
+        r#"{ "owner": "ann" }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_lock_expiry() {
+    // 2024-05-01T00:00:00Z
+    let clock = Arc::new(ManualClock::new(
+        UNIX_EPOCH + Duration::from_secs(1_714_521_600),
+    ));
+    let app = app_router(setup_test_state().await.with_clock(clock.clone()));
+    let uri = create_record(&app).await;
+
+    let (_, lock) = send(
+        &app,
+        "POST",
+        &format!("{}/lock", uri),
+        r#"{ "owner": "ann", "ttl": 60 }"#,
+    )
+    .await;
+    assert_eq!(lock["expires"], "2024-05-01T00:01:00.000Z");
+
+    clock.advance(Duration::from_secs(60));
+    let (_, record) = send(&app, "GET", &uri, "").await;
+    assert!(record.get("lock").is_none());
+    let (status, _) = send(
+        &app,
+        "POST",
+        &format!("{}/lock", uri),
+        r#"{ "owner": "bob" }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
@@ -0,0 +1,213 @@
+#![cfg(feature = "manifest")]
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use tinybase_api::{
+    app_router,
+    manifest::{reconcile, Manifest},
+};
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state};
+
+async fn send(app: &Router, method: &str, uri: &str, body: &str) -> (StatusCode, Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+const MANIFEST: &str = r#"
+[[collections]]
+name = "users"
+schema.fields.email = { type = "email", required = true }
+
+[[collections]]
+name = "posts"
+group = "blog"
+indexes = ["author"]
+schema.rules = { list = "true" }
+schema.fields.title = { type = "string", required = true }
+schema.fields.author = { type = { relation = { collection = "users" } }, required = true }
+
+[[webhooks]]
+collection = "posts"
+url = "https://hooks.example.com/posts"
+events = ["record.created"]
+"#;
+
+#[tokio::test]
+async fn test_reconcile_manifest() {
+    let state = setup_test_state().await;
+    let app = app_router(state.clone());
+    let db = state.db.as_ref();
+    let manifest = Manifest::from_toml(MANIFEST).unwrap();
+
+    let plan = reconcile(db, &manifest, false).await.unwrap();
+    assert_eq!(plan.create, ["users", "posts"]);
+    assert_eq!(plan.create_indexes, ["posts.author"]);
+    assert_eq!(
+        plan.create_webhooks,
+        ["posts https://hooks.example.com/posts"]
+    );
+    let (_, collections) = send(&app, "GET", "/api/v1/collections", "").await;
+    assert_eq!(collections, json!([]), "checking must not change anything");
+
+    let plan = reconcile(db, &manifest, true).await.unwrap();
+    assert!(!plan.is_empty());
+    let (_, users) = send(&app, "GET", "/api/v1/collections/users", "").await;
+    let (_, posts) = send(&app, "GET", "/api/v1/collections/posts", "").await;
+    assert_eq!(posts["group"], "blog");
+    assert_eq!(posts["schema"]["rules"]["list"], "true");
+    assert_eq!(
+        posts["schema"]["fields"]["author"]["type"]["relation"]["collection_id"],
+        users["id"]
+    );
+    let posts_id = posts["id"].as_i64().unwrap();
+    assert_eq!(db.list_record_indexes(posts_id).await.unwrap(), ["author"]);
+    let (_, webhooks) = send(&app, "GET", "/api/v1/admin/webhooks", "").await;
+    assert_eq!(webhooks[0]["collection_id"], posts_id);
+
+    // Indexed lookups still find records.
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections/users/records",
+        r#"{ "data": { "email": "ada@example.com" } }"#,
+    )
+    .await;
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections/posts/records",
+        &json!({ "data": { "title": "Hello", "author": 1 } }).to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let found = db
+        .find_records_by_field(posts_id, "author", &json!(1))
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 1);
+
+    let plan = reconcile(db, &manifest, true).await.unwrap();
+    assert!(plan.is_empty(), "{:?}", plan);
+    assert_eq!(plan.unchanged, ["users", "posts"]);
+
+    // Drift: the same collections in YAML, without the index, with another
+    // event and a stricter rule, next to a collection made by hand.
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        r#"{ "name": "scratch" }"#,
+    )
+    .await;
+    let changed = Manifest::from_yaml(
+        r#"
+collections:
+  - name: users
+    schema:
+      fields:
+        email: { type: email, required: true }
+  - name: posts
+    schema:
+      rules: { list: "false" }
+      fields:
+        title: { type: string, required: true }
+        author: { type: { relation: { collection: users } }, required: true }
+webhooks:
+  - collection: posts
+    url: https://hooks.example.com/posts
+    events: [record.created, record.deleted]
+"#,
+    )
+    .unwrap();
+    let plan = reconcile(db, &changed, false).await.unwrap();
+    assert_eq!(plan.update, ["posts"]);
+    assert_eq!(plan.drop_indexes, ["posts.author"]);
+    assert_eq!(
+        plan.update_webhooks,
+        ["posts https://hooks.example.com/posts"]
+    );
+    assert_eq!(plan.undeclared, ["scratch"]);
+    reconcile(db, &changed, true).await.unwrap();
+    assert!(db.list_record_indexes(posts_id).await.unwrap().is_empty());
+    let (_, posts) = send(&app, "GET", "/api/v1/collections/posts", "").await;
+    assert_eq!(posts["schema"]["rules"]["list"], "false");
+    assert_eq!(posts["group"], "blog", "undeclared groups are left alone");
+    let (_, webhooks) = send(&app, "GET", "/api/v1/admin/webhooks", "").await;
+    assert_eq!(webhooks.as_array().unwrap().len(), 1);
+    assert_eq!(
+        webhooks[0]["events"],
+        json!(["record.created", "record.deleted"])
+    );
+    assert!(reconcile(db, &changed, false).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_invalid_manifest_changes_nothing() {
+    let state = setup_test_state().await;
+    let db = state.db.as_ref();
+    for (manifest, error) in [
+        (
+            r#"[[collections]]
+name = "posts"
+schema.fields.author = { type = { relation = { collection = "people" } }, required = true }"#,
+            "unknown collection 'people'",
+        ),
+        (
+            r#"[[collections]]
+name = "posts"
+indexes = ["title"]
+schema.fields.body = { type = "text", required = false }"#,
+            "indexes unknown field 'title'",
+        ),
+        (
+            r#"[[collections]]
+name = "posts"
+schema.fields = {}
+schema.rules = { list = "status ==" }"#,
+            "invalid list rule",
+        ),
+        (
+            r#"[[collections]]
+name = "posts"
+
+[[webhooks]]
+collection = "posts"
+url = "https://hooks.example.com/posts"
+events = ["record.read"]"#,
+            "'record.read' is not one of",
+        ),
+    ] {
+        let manifest = Manifest::from_toml(manifest).unwrap();
+        let e = reconcile(db, &manifest, true).await.unwrap_err();
+        assert!(e.to_string().contains(error), "{}", e);
+    }
+    assert!(db.list_collections().await.unwrap().is_empty());
+    assert!(Manifest::from_toml("[[tables]]\nname = \"posts\"").is_err());
+
+    let app = setup_test_app().await;
+    let (_, meta) = send(&app, "GET", "/api/v1/meta", "").await;
+    assert!(meta["features"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("manifest")));
+}
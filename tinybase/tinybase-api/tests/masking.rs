@@ -0,0 +1,115 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+mod common;
+use common::setup_test_app;
+
+async fn send(
+    app: &axum::Router,
+    method: &str,
+    uri: &str,
+    key: Option<&str>,
+    body: Value,
+) -> (StatusCode, Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json");
+    if let Some(key) = key {
+        request = request.header("authorization", format!("Bearer {}", key));
+    }
+    let body = if body.is_null() {
+        Body::empty()
+    } else {
+        Body::from(body.to_string())
+    };
+    let response = app
+        .clone()
+        .oneshot(request.body(body).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn test_masked_fields() {
+    let app = setup_test_app().await;
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        None,
+        json!({
+            "name": "payments",
+            "schema": { "fields": {
+                "card": {
+                    "type": "string",
+                    "required": true,
+                    "mask": { "reveal_to": ["billing"], "keep_last": 4 }
+                },
+                "pin": { "type": "number", "required": false, "mask": {} },
+                "note": { "type": "string", "required": false }
+            }, "rules": { "list": "", "view": "" } }
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (_, record) = send(
+        &app,
+        "POST",
+        "/api/v1/collections/payments/records",
+        None,
+        json!({ "data": { "card": "4242424242421234", "pin": 1234, "note": "hi" } }),
+    )
+    .await;
+    let uri = format!("/api/v1/collections/payments/records/{}", record["id"]);
+    send(
+        &app,
+        "PATCH",
+        &uri,
+        None,
+        json!({ "data": { "note": "bye" } }),
+    )
+    .await;
+
+    // Setup mode sees everything; once an admin exists, only admins do.
+    let credentials = json!({ "email": "admin@example.com", "password": "correct horse" });
+    send(
+        &app,
+        "POST",
+        "/api/v1/admin/admins",
+        None,
+        credentials.clone(),
+    )
+    .await;
+    let (_, auth) = send(&app, "POST", "/api/v1/admin/auth", None, credentials).await;
+    let key = auth["key"].as_str().unwrap();
+
+    let (status, masked) = send(&app, "GET", &uri, None, Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(masked["data"]["card"], "************1234");
+    assert_eq!(masked["data"]["pin"], "****");
+    assert_eq!(masked["data"]["note"], "bye");
+    let (_, full) = send(&app, "GET", &uri, Some(key), Value::Null).await;
+    assert_eq!(full["data"]["card"], "4242424242421234");
+    assert_eq!(full["data"]["pin"], 1234);
+
+    let (_, list) = send(
+        &app,
+        "GET",
+        "/api/v1/collections/payments/records",
+        Some("not a key"),
+        Value::Null,
+    )
+    .await;
+    assert_eq!(list[0]["data"]["card"], "************1234");
+    let (_, versions) = send(&app, "GET", &format!("{}/versions", uri), None, Value::Null).await;
+    assert_eq!(versions[0]["data"]["note"], "hi");
+    assert_eq!(versions[0]["data"]["card"], "************1234");
+}
@@ -0,0 +1,111 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+};
+use tinybase_api::{
+    meta::Meta,
+    plugin::{Tinybase, TinybasePlugin},
+    version::{check_compatibility, Compatibility, API_VERSION},
+};
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state};
+
+async fn get_meta(app: axum::Router) -> serde_json::Value {
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/api/v1/meta")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+struct AuditPlugin;
+
+impl TinybasePlugin for AuditPlugin {
+    fn name(&self) -> &'static str {
+        "audit"
+    }
+}
+
+#[tokio::test]
+async fn test_meta() {
+    let meta = get_meta(setup_test_app().await).await;
+    assert_eq!(meta["name"], "tinybase");
+    assert_eq!(meta["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(meta["plugins"], serde_json::json!([]));
+    assert_eq!(meta["list_format"], "bare");
+    assert_eq!(meta["limits"]["max_per_page"], 500);
+    assert_eq!(meta["limits"]["max_body_bytes"], 2 * 1024 * 1024);
+    assert_eq!(meta["limits"]["max_json_bytes"], 1024 * 1024);
+    assert_eq!(meta["limits"]["max_json_depth"], 32);
+    let requests = meta["content_types"]["requests"].as_array().unwrap();
+    assert!(requests.contains(&serde_json::json!("text/csv")));
+    assert!(!meta["features"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!("backups")));
+}
+
+#[tokio::test]
+async fn test_meta_lists_plugins_and_features() {
+    let tinybase =
+        Tinybase::new(setup_test_state().await.with_backups("backups")).plugin(AuditPlugin);
+    let meta = get_meta(tinybase.router()).await;
+    assert_eq!(meta["plugins"], serde_json::json!(["audit"]));
+    assert!(meta["features"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!("backups")));
+
+    let banner = Meta::new(tinybase.state()).banner();
+    assert!(banner.starts_with(&format!("tinybase {}", env!("CARGO_PKG_VERSION"))));
+    assert!(banner.contains("plugins:  audit"));
+}
+
+#[tokio::test]
+async fn test_version() {
+    let response = setup_test_app()
+        .await
+        .oneshot(
+            Request::builder()
+                .uri("/api/v1/version")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let version: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(version["api_version"], API_VERSION);
+    assert!(!version["git_hash"].as_str().unwrap().is_empty());
+    assert!(version["build_time"].as_str().unwrap().ends_with('Z'));
+}
+
+#[test]
+fn test_api_version_compatibility() {
+    assert_eq!(
+        check_compatibility("1.2", "1.3"),
+        Ok(Compatibility::Compatible)
+    );
+    let older = check_compatibility("1.4", "1.3").unwrap();
+    assert!(matches!(older, Compatibility::OlderServer { .. }));
+    assert!(older
+        .warning()
+        .unwrap()
+        .contains("expects Tinybase API 1.4"));
+    assert!(matches!(
+        check_compatibility("2.0", "1.3"),
+        Ok(Compatibility::Incompatible { .. })
+    ));
+    assert!(check_compatibility("v1", "1.3").is_err());
+}
@@ -0,0 +1,170 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use tinybase_api::{
+    app_router,
+    namespaces::{self, Namespaces},
+};
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state};
+
+async fn send(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    namespace: Option<&str>,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json");
+    if let Some(namespace) = namespace {
+        request = request.header("x-tinybase-namespace", namespace);
+    }
+    let body = body.map_or_else(Body::empty, |b| Body::from(b.to_string()));
+    let response = app
+        .clone()
+        .oneshot(request.body(body).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+/// The default app plus namespaces `shop` and `blog`, each on its own
+/// in-memory database.
+async fn setup() -> Router {
+    let default = setup_test_app().await;
+    let mut scoped = Namespaces::new();
+    for name in ["shop", "blog"] {
+        let base = setup_test_state().await;
+        let state = base.for_namespace(name, base.db.clone(), base.storage.clone());
+        scoped.add(name, app_router(state)).unwrap();
+    }
+    namespaces::route(default, scoped)
+}
+
+fn collection(name: &str) -> Value {
+    json!({ "name": name, "schema": { "fields": {
+        "title": { "type": "string", "required": true }
+    } } })
+}
+
+async fn names(app: &Router, uri: &str, namespace: Option<&str>) -> Vec<String> {
+    let (status, body) = send(app, "GET", uri, namespace, None).await;
+    assert_eq!(status, StatusCode::OK);
+    body.as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["name"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_namespaces_are_isolated() {
+    let app = setup().await;
+
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/ns/shop/collections",
+        None,
+        Some(collection("products")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        Some("blog"),
+        Some(collection("posts")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        None,
+        Some(collection("notes")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // The prefix and the header reach the same namespace.
+    assert_eq!(
+        names(&app, "/api/v1/collections", Some("shop")).await,
+        vec!["products"]
+    );
+    assert_eq!(
+        names(&app, "/api/v1/ns/blog/collections", None).await,
+        vec!["posts"]
+    );
+    assert_eq!(
+        names(&app, "/api/v1/collections", None).await,
+        vec!["notes"]
+    );
+
+    // Records, query strings included, stay in their namespace.
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/ns/shop/collections/products/records",
+        None,
+        Some(json!({ "data": { "title": "Lamp" } })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, body) = send(
+        &app,
+        "GET",
+        "/api/v1/ns/shop/collections/products/records?limit=10",
+        None,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    let (status, _) = send(
+        &app,
+        "GET",
+        "/api/v1/collections/products/records",
+        None,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(
+        &app,
+        "GET",
+        "/api/v1/ns/blog/collections/products/records",
+        None,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_unknown_namespace_is_not_found() {
+    let app = setup().await;
+
+    let (status, body) = send(&app, "GET", "/api/v1/ns/nope/collections", None, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(body["message"].as_str().unwrap().contains("nope"));
+    let (status, _) = send(&app, "GET", "/api/v1/collections", Some("nope"), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let mut scoped = Namespaces::new();
+    assert!(scoped.add("../etc", setup_test_app().await).is_err());
+    scoped.add("shop", setup_test_app().await).unwrap();
+    assert!(scoped.add("shop", setup_test_app().await).is_err());
+}
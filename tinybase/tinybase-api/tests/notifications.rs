@@ -0,0 +1,193 @@
+#![cfg(feature = "notifications")]
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::State,
+    http::{Request, StatusCode},
+    routing::post,
+    Json, Router,
+};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tinybase_api::{app_router, notify};
+use tinybase_core::events::Alert;
+use tokio::net::TcpListener;
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state};
+
+type Received = Arc<Mutex<Vec<Value>>>;
+
+/// A stand-in for a Slack or Discord webhook, returning its URL.
+async fn receiver(received: Received) -> String {
+    let app = Router::new()
+        .route(
+            "/hook",
+            post(
+                |State(received): State<Received>, Json(body): Json<Value>| async move {
+                    received.lock().unwrap().push(body);
+                },
+            ),
+        )
+        .with_state(received);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    url
+}
+
+async fn send(app: &Router, method: &str, uri: &str, body: String) -> (StatusCode, Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+async fn wait_for(received: &Received, count: usize) -> Vec<Value> {
+    for _ in 0..100 {
+        if received.lock().unwrap().len() >= count {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    received.lock().unwrap().clone()
+}
+
+#[tokio::test]
+async fn test_notification_channels() {
+    let received = Received::default();
+    let url = receiver(received.clone()).await;
+    let state = setup_test_state().await;
+    notify::spawn(state.clone());
+    let app = app_router(state.clone());
+
+    let (_, posts) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        r#"{ "name": "posts" }"#.into(),
+    )
+    .await;
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        r#"{ "name": "tags" }"#.into(),
+    )
+    .await;
+    let slack = json!({
+        "name": "ops",
+        "kind": "slack",
+        "url": url,
+        "events": ["record.created", "backup.failed"],
+        "collection_id": posts["id"]
+    });
+    let (status, channel) = send(
+        &app,
+        "POST",
+        "/api/v1/admin/notifications",
+        slack.to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(channel["kind"], "slack");
+    let discord = json!({
+        "name": "community",
+        "kind": "discord",
+        "url": url,
+        "events": ["record.created"],
+        "template": { "content": "New post: {{ collection.name }} #{{ record_id }}", "tts": false }
+    });
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/admin/notifications",
+        discord.to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections/tags/records",
+        r#"{ "data": {} }"#.into(),
+    )
+    .await;
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections/posts/records",
+        r#"{ "data": {} }"#.into(),
+    )
+    .await;
+    state
+        .events
+        .raise(Alert::new("backup.failed", "No snapshot found".to_string()));
+
+    let mut messages = wait_for(&received, 4).await;
+    messages.sort_by_key(|m| m.to_string());
+    assert_eq!(
+        messages,
+        vec![
+            json!({ "content": "New post: posts #2", "tts": false }),
+            json!({ "content": "New post: tags #1", "tts": false }),
+            json!({ "text": "No snapshot found" }),
+            json!({ "text": "record.created in posts: record 2" }),
+        ]
+    );
+
+    let (status, channels) = send(&app, "GET", "/api/v1/admin/notifications", String::new()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(channels.as_array().unwrap().len(), 2);
+    let uri = format!("/api/v1/admin/notifications/{}", channel["id"]);
+    let (status, _) = send(&app, "POST", &format!("{}/test", uri), String::new()).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send(&app, "DELETE", &uri, String::new()).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send(&app, "DELETE", &uri, String::new()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_notification_channel_validation() {
+    let app = setup_test_app().await;
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/admin/notifications",
+        r#"{ "name": "x", "kind": "slack", "url": "not a url", "events": ["*"] }"#.into(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/admin/notifications",
+        r#"{ "name": "x", "kind": "slack", "url": "https://hooks.slack.com/x", "events": [] }"#
+            .into(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/admin/notifications",
+        r#"{ "name": "x", "kind": "teams", "url": "https://example.com", "events": ["*"] }"#.into(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
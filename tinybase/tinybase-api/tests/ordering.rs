@@ -0,0 +1,161 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+mod common;
+use common::setup_test_app;
+
+async fn send(app: &axum::Router, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(if body.is_null() {
+                    Body::empty()
+                } else {
+                    Body::from(body.to_string())
+                })
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    (status, value)
+}
+
+async fn create_tasks(app: &axum::Router, count: usize) -> Vec<i64> {
+    let mut ids = Vec::new();
+    for n in 0..count {
+        let (status, record) = send(
+            app,
+            "POST",
+            "/api/v1/collections/tasks/records",
+            json!({ "data": { "title": format!("task {}", n), "position": 1 } }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        ids.push(record["id"].as_i64().unwrap());
+    }
+    ids
+}
+
+/// The ids of the tasks in their manual order, checking the positions
+/// increase.
+async fn order(app: &axum::Router) -> Vec<i64> {
+    let (status, records) = send(
+        app,
+        "GET",
+        "/api/v1/collections/tasks/records?sort=position",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let records = records.as_array().unwrap();
+    let positions: Vec<i64> = records
+        .iter()
+        .map(|r| r["data"]["position"].as_i64().unwrap())
+        .collect();
+    assert!(positions.windows(2).all(|w| w[0] < w[1]), "{:?}", positions);
+    records.iter().map(|r| r["id"].as_i64().unwrap()).collect()
+}
+
+async fn move_task(app: &axum::Router, id: i64, body: Value) -> (StatusCode, Value) {
+    send(
+        app,
+        "POST",
+        &format!("/api/v1/collections/tasks/records/{}/move", id),
+        body,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_move_records() {
+    let app = setup_test_app().await;
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({
+            "name": "tasks",
+            "schema": {
+                "fields": { "title": { "type": "string", "required": true } },
+                "order": {}
+            }
+        }),
+    )
+    .await;
+    // New records go last, whatever position they are sent with.
+    let ids = create_tasks(&app, 3).await;
+    let (a, b, c) = (ids[0], ids[1], ids[2]);
+    assert_eq!(order(&app).await, [a, b, c]);
+
+    let (status, moved) = move_task(&app, c, json!({ "before": a })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(moved["id"], c);
+    assert_eq!(order(&app).await, [c, a, b]);
+    move_task(&app, c, json!({ "after": b })).await;
+    assert_eq!(order(&app).await, [a, b, c]);
+    move_task(&app, a, json!({ "after": b })).await;
+    assert_eq!(order(&app).await, [b, a, c]);
+
+    // Updates keep the position.
+    let (_, updated) = send(
+        &app,
+        "PATCH",
+        &format!("/api/v1/collections/tasks/records/{}", a),
+        json!({ "data": { "title": "renamed", "position": -5 } }),
+    )
+    .await;
+    assert_ne!(updated["data"]["position"], -5);
+    assert_eq!(order(&app).await, [b, a, c]);
+
+    for body in [json!({}), json!({ "before": a, "after": c })] {
+        let (status, _) = move_task(&app, b, body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+    let (status, _) = move_task(&app, b, json!({ "before": 999 })).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_used_up_gaps_are_rebalanced() {
+    let app = setup_test_app().await;
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "tasks", "schema": { "fields": {} } }),
+    )
+    .await;
+    let ids = create_tasks(&app, 14).await;
+    let (status, _) = move_task(&app, ids[1], json!({ "after": ids[0] })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Ordering a collection renumbers its records, ties in id order.
+    send(
+        &app,
+        "PATCH",
+        "/api/v1/collections/tasks",
+        json!({ "schema": { "fields": {}, "order": { "field": "position" } } }),
+    )
+    .await;
+    assert_eq!(order(&app).await, ids);
+
+    // Each move halves the gap after the first record, until it is used up.
+    for &id in &ids[2..] {
+        let (status, _) = move_task(&app, id, json!({ "after": ids[0] })).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let mut expected = vec![ids[0]];
+    expected.extend(ids[1..].iter().rev());
+    assert_eq!(order(&app).await, expected);
+}
@@ -0,0 +1,258 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    routing::get,
+    Router,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tinybase_api::{
+    plugin::{Command, Tinybase, TinybasePlugin},
+    shape::ResponseHooks,
+    AppState,
+};
+use tinybase_core::events::{Event, EventAction};
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state, temp_path};
+
+async fn send(
+    app: &axum::Router,
+    method: &str,
+    uri: &str,
+    body: &str,
+) -> (StatusCode, serde_json::Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+#[derive(Default)]
+struct CounterPlugin {
+    collections_created: Arc<AtomicUsize>,
+}
+
+#[axum::async_trait]
+impl TinybasePlugin for CounterPlugin {
+    fn name(&self) -> &'static str {
+        "counter"
+    }
+
+    fn routes(&self) -> Router<AppState> {
+        Router::new().route("/counter/ping", get(|| async { "pong" }))
+    }
+
+    fn migrations(&self) -> Vec<&'static str> {
+        vec![
+            "CREATE TABLE counter_totals (name TEXT PRIMARY KEY, total INTEGER NOT NULL)",
+            "INSERT INTO counter_totals (name, total) VALUES ('collections', 0)",
+        ]
+    }
+
+    async fn on_event(&self, _state: &AppState, event: &Event) {
+        if event.action == EventAction::CollectionCreated {
+            self.collections_created.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn commands(&self) -> Vec<Command> {
+        vec![Command {
+            name: "counter-reset",
+            about: "Resets the counter",
+        }]
+    }
+
+    async fn run_command(
+        &self,
+        _state: &AppState,
+        command: &str,
+        args: &[String],
+    ) -> Result<(), String> {
+        match (command, args) {
+            ("counter-reset", []) => {
+                self.collections_created.store(0, Ordering::SeqCst);
+                Ok(())
+            }
+            _ => Err(format!("Unexpected arguments {:?}", args)),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_plugin_routes_and_hooks() {
+    let plugin = CounterPlugin::default();
+    let created = plugin.collections_created.clone();
+    let tinybase = Tinybase::new(setup_test_state().await).plugin(plugin);
+    tinybase.start();
+    let app = tinybase.router();
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/api/v1/counter/ping")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), 1024).await.unwrap();
+    assert_eq!(&body[..], b"pong");
+
+    // Core routes are still served alongside the plugin's.
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/collections")
+                .header("Content-Type", "application/json")
+                .body(Body::from(r#"{"name": "posts"}"#))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    for _ in 0..50 {
+        if created.load(Ordering::SeqCst) > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(created.load(Ordering::SeqCst), 1);
+
+    let args = vec!["counter-reset".to_string()];
+    assert_eq!(tinybase.run_command(&args).await, Some(Ok(())));
+    assert_eq!(created.load(Ordering::SeqCst), 0);
+    assert!(tinybase.run_command(&["serve".to_string()]).await.is_none());
+    assert_eq!(tinybase.commands()[0].name, "counter-reset");
+
+    // The plain app has no plugin routes.
+    let response = setup_test_app()
+        .await
+        .oneshot(
+            Request::builder()
+                .uri("/api/v1/counter/ping")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_plugin_migrations_run_once() {
+    let path = temp_path("plugins.db");
+    let db = libsql::Builder::new_local(&path).build().await.unwrap();
+    let conn = db.connect().unwrap();
+    let tinybase = Tinybase::new(setup_test_state().await).plugin(CounterPlugin::default());
+
+    tinybase.migrate(&conn).await.unwrap();
+    tinybase.migrate(&conn).await.unwrap();
+
+    let mut rows = conn
+        .query("SELECT COUNT(*) FROM counter_totals", ())
+        .await
+        .unwrap();
+    let count: i64 = rows.next().await.unwrap().unwrap().get(0).unwrap();
+    assert_eq!(count, 1);
+
+    let mut rows = conn
+        .query(
+            "SELECT MAX(version) FROM plugin_migrations WHERE plugin = 'counter'",
+            (),
+        )
+        .await
+        .unwrap();
+    let version: i64 = rows.next().await.unwrap().unwrap().get(0).unwrap();
+    assert_eq!(version, 2);
+}
+
+struct PeoplePlugin;
+
+impl TinybasePlugin for PeoplePlugin {
+    fn name(&self) -> &'static str {
+        "people"
+    }
+
+    fn response_hooks(&self, hooks: &mut ResponseHooks) {
+        hooks.rename("people", "fname", "first_name");
+        hooks.strip("people", &["password_hash"]);
+        hooks.register("people", |data| {
+            let initial = data["first_name"].as_str().and_then(|n| n.chars().next());
+            if let Some(initial) = initial {
+                data.insert("initial".to_string(), initial.to_string().into());
+            }
+        });
+    }
+}
+
+#[tokio::test]
+async fn test_response_hooks() {
+    let app = Tinybase::new(setup_test_state().await)
+        .plugin(PeoplePlugin)
+        .router();
+    let (_, people) = send(&app, "POST", "/api/v1/collections", r#"{"name": "people"}"#).await;
+    let (_, notes) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        &format!(
+            r#"{{"name": "notes", "schema": {{"fields": {{"author": {{"type": {{"relation": {{"collection_id": {}}}}}, "required": true}}}}}}}}"#,
+            people["id"]
+        ),
+    )
+    .await;
+    let people = format!("/api/v1/collections/{}/records", people["id"]);
+    let notes = format!("/api/v1/collections/{}/records", notes["id"]);
+
+    let expected = serde_json::json!({ "first_name": "Ada", "initial": "A" });
+    let (status, person) = send(
+        &app,
+        "POST",
+        &people,
+        r#"{"data": {"fname": "Ada", "password_hash": "x"}}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(person["data"], expected);
+
+    let (_, fetched) = send(&app, "GET", &format!("{}/{}", people, person["id"]), "").await;
+    assert_eq!(fetched["data"], expected);
+    let (_, listed) = send(&app, "GET", &people, "").await;
+    assert_eq!(listed[0]["data"], expected);
+
+    let (_, note) = send(
+        &app,
+        "POST",
+        &notes,
+        &format!(r#"{{"data": {{"author": {}}}}}"#, person["id"]),
+    )
+    .await;
+    // Other collections are untouched, but their expansions are shaped.
+    assert_eq!(note["data"]["author"], person["id"]);
+    let (_, note) = send(
+        &app,
+        "GET",
+        &format!("{}/{}?expand=author", notes, note["id"]),
+        "",
+    )
+    .await;
+    assert_eq!(note["expand"]["author"]["data"], expected);
+}
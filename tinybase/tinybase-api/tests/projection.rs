@@ -0,0 +1,141 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+mod common;
+use common::setup_test_app;
+
+async fn send(app: &Router, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+/// Creates `authors` and `posts` with a post by Ada, returning the post's id.
+async fn create_post(app: &Router) -> Value {
+    let (_, authors) = send(
+        app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "authors" }),
+    )
+    .await;
+    send(
+        app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "posts", "schema": { "fields": {
+            "title": { "type": "string", "required": true },
+            "body": { "type": "text", "required": false },
+            "draft": { "type": "boolean", "required": false },
+            "tags": { "type": "json", "required": false },
+            "author": { "type": { "relation": { "collection_id": authors["id"] } }, "required": false }
+        } } }),
+    )
+    .await;
+    let (_, author) = send(
+        app,
+        "POST",
+        "/api/v1/collections/authors/records",
+        json!({ "data": { "name": "Ada", "email": "ada@example.com" } }),
+    )
+    .await;
+    let (_, post) = send(
+        app,
+        "POST",
+        "/api/v1/collections/posts/records",
+        json!({ "data": {
+            "title": "Hello", "body": "A long body", "draft": false,
+            "tags": ["a", { "b": 1 }], "author": author["id"]
+        } }),
+    )
+    .await;
+    post["id"].clone()
+}
+
+#[tokio::test]
+async fn test_fields_select_data_on_get_and_list() {
+    let app = setup_test_app().await;
+    let id = create_post(&app).await;
+
+    let (status, post) = send(
+        &app,
+        "GET",
+        &format!(
+            "/api/v1/collections/posts/records/{}?fields=title,draft",
+            id
+        ),
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", post);
+    assert_eq!(post["data"], json!({ "title": "Hello", "draft": false }));
+    assert_eq!(post["id"], id);
+
+    // Lists select in SQL; types must survive it.
+    let (status, posts) = send(
+        &app,
+        "GET",
+        "/api/v1/collections/posts/records?fields=draft,tags,missing",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", posts);
+    assert_eq!(
+        posts[0]["data"],
+        json!({ "draft": false, "tags": ["a", { "b": 1 }] })
+    );
+    assert!(posts[0]["created"].is_string());
+}
+
+#[tokio::test]
+async fn test_fields_select_from_expanded_relations() {
+    let app = setup_test_app().await;
+    let id = create_post(&app).await;
+
+    for uri in [
+        format!(
+            "/api/v1/collections/posts/records/{}?fields=title,author.name",
+            id
+        ),
+        "/api/v1/collections/posts/records?fields=title,author.name".to_string(),
+    ] {
+        let (status, body) = send(&app, "GET", &uri, Value::Null).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let post = if body.is_array() { &body[0] } else { &body };
+        assert_eq!(post["data"]["title"], "Hello");
+        assert!(post["data"]["author"].is_i64());
+        assert!(post["data"].get("body").is_none());
+        assert_eq!(post["expand"]["author"]["data"], json!({ "name": "Ada" }));
+    }
+
+    // A relation expanded but not selected is still found, and dropped.
+    let (_, post) = send(
+        &app,
+        "GET",
+        "/api/v1/collections/posts/records?fields=title&expand=author",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(post[0]["data"], json!({ "title": "Hello" }));
+    assert_eq!(
+        post[0]["expand"]["author"]["data"]["email"],
+        "ada@example.com"
+    );
+}
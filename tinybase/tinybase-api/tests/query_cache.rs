@@ -0,0 +1,268 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use tinybase_api::{app_router, warmup::warm_up};
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state};
+
+async fn send(app: &Router, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+async fn cache_stats(app: &Router) -> Value {
+    let (status, metrics) = send(app, "GET", "/api/v1/admin/metrics", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    metrics["query_cache"].clone()
+}
+
+/// An app with a cached `products` collection holding one product.
+async fn app_with_products() -> Router {
+    with_products(setup_test_app().await).await
+}
+
+/// `app` with a cached `products` collection holding one product.
+async fn with_products(app: Router) -> Router {
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "products", "schema": {
+            "fields": {
+                "name": { "type": "string", "required": true },
+                "price": { "type": "number", "required": false }
+            },
+            "cache": { "ttl": 60 }
+        } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections/products/records",
+        json!({ "data": { "name": "pen", "price": 2 } }),
+    )
+    .await;
+    app
+}
+
+#[tokio::test]
+async fn test_repeated_list_is_served_from_cache() {
+    let app = app_with_products().await;
+
+    let (status, first) = send(
+        &app,
+        "GET",
+        "/api/v1/collections/products/records",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, second) = send(
+        &app,
+        "GET",
+        "/api/v1/collections/products/records",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(first, second);
+
+    let stats = cache_stats(&app).await;
+    assert_eq!(stats["misses"], 1);
+    assert_eq!(stats["hits"], 1);
+    assert_eq!(stats["entries"], 1);
+
+    // Query parameters in another order are the same read.
+    send(
+        &app,
+        "GET",
+        "/api/v1/collections/products/records?sort=id&desc=true",
+        Value::Null,
+    )
+    .await;
+    send(
+        &app,
+        "GET",
+        "/api/v1/collections/products/records?desc=true&sort=id",
+        Value::Null,
+    )
+    .await;
+    let stats = cache_stats(&app).await;
+    assert_eq!(stats["misses"], 2);
+    assert_eq!(stats["hits"], 2);
+}
+
+#[tokio::test]
+async fn test_writes_invalidate_cached_reads() {
+    let app = app_with_products().await;
+    let list = "/api/v1/collections/products/records";
+    let sums = "/api/v1/collections/products/records/aggregate?sum=price";
+
+    let (_, records) = send(&app, "GET", list, Value::Null).await;
+    assert_eq!(records.as_array().unwrap().len(), 1);
+    let (status, totals) = send(&app, "GET", sums, Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(cache_stats(&app).await["entries"], 2);
+
+    send(
+        &app,
+        "POST",
+        list,
+        json!({ "data": { "name": "ink", "price": 3 } }),
+    )
+    .await;
+
+    let (_, records) = send(&app, "GET", list, Value::Null).await;
+    assert_eq!(records.as_array().unwrap().len(), 2);
+    let (_, after) = send(&app, "GET", sums, Value::Null).await;
+    assert_ne!(totals, after);
+
+    let stats = cache_stats(&app).await;
+    assert_eq!(stats["invalidations"], 2);
+    assert_eq!(stats["hits"], 0);
+    assert_eq!(stats["misses"], 4);
+}
+
+#[tokio::test]
+async fn test_writes_to_looked_up_collections_invalidate_cached_reads() {
+    let app = setup_test_app().await;
+    let admin = json!({ "email": "admin@example.com", "password": "correct horse" });
+    send(&app, "POST", "/api/v1/admin/admins", admin.clone()).await;
+    let (_, auth) = send(&app, "POST", "/api/v1/admin/auth", admin).await;
+    let key = auth["key"].as_str().unwrap();
+    let as_admin = |method: &str, uri: &str, body: Value| {
+        app.clone().oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("authorization", format!("Bearer {}", key))
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+    };
+    as_admin(
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "memberships" }),
+    )
+    .await
+    .unwrap();
+    as_admin(
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "docs", "schema": {
+            "fields": { "team": { "type": "string", "required": true } },
+            "rules": { "list": "len(@collection.memberships.filter(user = @request.auth.id && team = @record.team)) > 0" },
+            "cache": { "ttl": 60 }
+        } }),
+    )
+    .await
+    .unwrap();
+    let docs = "/api/v1/collections/docs/records";
+    as_admin("POST", docs, json!({ "data": { "team": "red" } }))
+        .await
+        .unwrap();
+
+    let (_, records) = send(&app, "GET", docs, Value::Null).await;
+    assert_eq!(records, json!([]));
+    as_admin(
+        "POST",
+        "/api/v1/collections/memberships/records",
+        json!({ "data": { "user": "", "team": "red" } }),
+    )
+    .await
+    .unwrap();
+    let (_, records) = send(&app, "GET", docs, Value::Null).await;
+    assert_eq!(records.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_collections_without_cache_settings_are_not_cached() {
+    let app = setup_test_app().await;
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "posts" }),
+    )
+    .await;
+
+    send(
+        &app,
+        "GET",
+        "/api/v1/collections/posts/records",
+        Value::Null,
+    )
+    .await;
+    send(
+        &app,
+        "GET",
+        "/api/v1/collections/posts/records",
+        Value::Null,
+    )
+    .await;
+
+    let stats = cache_stats(&app).await;
+    assert_eq!(stats["hits"], 0);
+    assert_eq!(stats["misses"], 0);
+    assert_eq!(stats["entries"], 0);
+}
+
+#[tokio::test]
+async fn test_warm_up_fills_the_cache() {
+    let state = setup_test_state().await;
+    let app = with_products(app_router(state.clone())).await;
+    let list = "/api/v1/collections/products/records?sort=-created";
+
+    let warmed = warm_up(
+        &state,
+        &app,
+        &[
+            list.to_string(),
+            "/api/v1/collections/missing/records".to_string(),
+        ],
+    )
+    .await;
+    assert_eq!(warmed.len(), 2);
+    assert_eq!(warmed[0].result, Ok(StatusCode::OK));
+    assert_eq!(warmed[1].result, Ok(StatusCode::NOT_FOUND));
+    let stats = cache_stats(&app).await;
+    assert_eq!(stats["misses"], 1);
+    assert_eq!(stats["entries"], 1);
+
+    // The first reader, whatever JSON it accepts, is served warm.
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(list)
+                .header("accept", "application/json, */*")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(cache_stats(&app).await["hits"], 1);
+}
@@ -2,10 +2,11 @@ use axum::{
     body::{to_bytes, Body},
     http::{Request, StatusCode},
 };
+use tinybase_api::{app_router, envelope::ListFormat};
 use tower::ServiceExt;
 
 mod common;
-use common::setup_test_app;
+use common::{setup_test_app, setup_test_state};
 
 async fn create_test_collection(app: &axum::Router) -> i64 {
     let response = app
@@ -16,7 +17,7 @@ async fn create_test_collection(app: &axum::Router) -> i64 {
                 .uri("/api/v1/collections")
                 .header("content-type", "application/json")
                 .body(Body::from(
-                    r#"{ "name": "Posts", "schema": { "fields": { "title": { "type": "string", "required": true } } } }"#,
+                    r#"{ "name": "posts", "schema": { "fields": { "title": { "type": "string", "required": true } } } }"#,
                 ))
                 .unwrap(),
         )
@@ -67,6 +68,64 @@ async fn test_create_record_validation_error() {
     assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
 }
 
+#[tokio::test]
+async fn test_reserved_fields() {
+    let app = setup_test_app().await;
+    let post = |uri: String, body: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri(uri)
+                        .header("content-type", "application/json")
+                        .body(Body::from(body))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+            (
+                status,
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            )
+        }
+    };
+
+    let (status, problem) = post(
+        "/api/v1/collections".to_string(),
+        r#"{ "name": "posts", "schema": { "fields": { "created": { "type": "string", "required": false } } } }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(problem["message"].as_str().unwrap().contains("'created'"));
+
+    // Fields of objects may use the names.
+    let (status, _) = post(
+        "/api/v1/collections".to_string(),
+        r#"{ "name": "lines", "schema": { "fields": { "item": { "type": { "object": { "fields": {
+            "id": { "type": "string", "required": true } } } }, "required": true } } } }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let collection_id = create_test_collection(&app).await;
+    let (status, problem) = post(
+        format!("/api/v1/collections/{}/records", collection_id),
+        r#"{ "data": { "title": "Hello!", "id": 7, "collection_id": 2 } }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let details = problem["details"].as_array().unwrap();
+    assert_eq!(details.len(), 2);
+    assert_eq!(details[0]["path"], "/id");
+    assert_eq!(details[0]["code"], "reserved_field");
+    assert_eq!(details[0]["value"], 7);
+    assert_eq!(details[1]["path"], "/collection_id");
+}
+
 #[tokio::test]
 async fn test_list_records() {
     let app = setup_test_app().await;
@@ -243,3 +302,1049 @@ async fn test_delete_record() {
 
     assert_eq!(response.status(), StatusCode::NOT_FOUND);
 }
+
+#[tokio::test]
+async fn test_record_timestamps_and_sorting() {
+    let app = setup_test_app().await;
+    let collection_id = create_test_collection(&app).await;
+
+    for title in ["First", "Second"] {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri(format!("/api/v1/collections/{}/records", collection_id))
+                    .header("content-type", "application/json")
+                    .body(Body::from(format!(
+                        r#"{{ "data": {{ "title": "{}" }} }}"#,
+                        title
+                    )))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+        let record: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let created = record["created"].as_str().unwrap();
+        assert!(created.ends_with('Z'));
+        assert_eq!(record["updated"], created);
+    }
+
+    let list = |query: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri(format!(
+                            "/api/v1/collections/{}/records?{}",
+                            collection_id, query
+                        ))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+            (
+                status,
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            )
+        }
+    };
+
+    let (status, records) = list("sort=-created").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(records[0]["data"]["title"], "Second");
+    assert_eq!(records[1]["data"]["title"], "First");
+
+    let (_, records) = list("created_after=2000-01-01").await;
+    assert_eq!(records.as_array().unwrap().len(), 2);
+    let (_, records) = list("updated_after=2999-01-01").await;
+    assert_eq!(records.as_array().unwrap().len(), 0);
+
+    let (status, _) = list("sort=title").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_create_record_applies_defaults() {
+    let app = setup_test_app().await;
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/collections")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    r#"{ "name": "tasks", "schema": { "fields": {
+                        "title": { "type": "string", "required": true, "default": "Untitled" },
+                        "status": { "type": "string", "required": false, "default": "open" },
+                        "priority": { "type": "number", "required": false, "default": 1 }
+                    } } }"#,
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let collection: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let collection_id = collection["id"].as_i64().unwrap();
+
+    let create = |data: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri(format!("/api/v1/collections/{}/records", collection_id))
+                        .header("content-type", "application/json")
+                        .body(Body::from(format!(r#"{{ "data": {} }}"#, data)))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+            (
+                status,
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            )
+        }
+    };
+
+    let (status, record) = create(r#"{ "title": "Write docs", "priority": 3 }"#).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(
+        record["data"],
+        serde_json::json!({ "title": "Write docs", "status": "open", "priority": 3 })
+    );
+
+    // Defaults never stand in for required fields.
+    let (status, _) = create(r#"{ "status": "done" }"#).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn test_create_record_format_validation() {
+    let app = setup_test_app().await;
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/collections")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    r#"{ "name": "contacts", "schema": { "fields": {
+                        "email": { "type": "email", "required": true },
+                        "website": { "type": "url", "required": false },
+                        "birthday": { "type": "date", "required": false },
+                        "last_seen": { "type": "datetime", "required": false }
+                    } } }"#,
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let collection: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let collection_id = collection["id"].as_i64().unwrap();
+
+    let create = |data: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri(format!("/api/v1/collections/{}/records", collection_id))
+                        .header("content-type", "application/json")
+                        .body(Body::from(format!(r#"{{ "data": {} }}"#, data)))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+            (
+                status,
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            )
+        }
+    };
+
+    let (status, _) = create(
+        r#"{ "email": "ada+tinybase@example.com", "website": "https://example.com/ada",
+             "birthday": "1815-12-10", "last_seen": "2024-05-01T12:30:00+02:00" }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    for (data, field) in [
+        (r#"{ "email": "ada@" }"#, "email"),
+        (r#"{ "email": "ada example.com" }"#, "email"),
+        (
+            r#"{ "email": "a@b.c", "website": "example.com" }"#,
+            "website",
+        ),
+        (
+            r#"{ "email": "a@b.c", "website": "ftp://example.com" }"#,
+            "website",
+        ),
+        (
+            r#"{ "email": "a@b.c", "birthday": "1815-13-10" }"#,
+            "birthday",
+        ),
+        (
+            r#"{ "email": "a@b.c", "last_seen": "2024-05-01 12:30" }"#,
+            "last_seen",
+        ),
+    ] {
+        let (status, problem) = create(data).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", data);
+        assert_eq!(
+            problem["details"][0]["path"],
+            format!("/{}", field),
+            "{}",
+            data
+        );
+        assert_eq!(problem["details"][0]["code"], "invalid_format", "{}", data);
+    }
+}
+
+#[tokio::test]
+async fn test_record_transforms() {
+    let app = setup_test_app().await;
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/collections")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    r#"{ "name": "members", "schema": {
+                        "fields": {
+                            "email": { "type": "email", "required": true },
+                            "name": { "type": "string", "required": false },
+                            "nickname": { "type": "string", "required": false },
+                            "profile": { "type": "json", "required": false }
+                        },
+                        "transforms": ["trim", "lowercase_emails", "strip_nulls", "normalize_unicode"]
+                    } }"#,
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let collection: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let collection_id = collection["id"].as_i64().unwrap();
+    assert_eq!(collection["schema"]["transforms"][1], "lowercase_emails");
+
+    let write = |method: &'static str, uri: String, data: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method(method)
+                        .uri(uri)
+                        .header("content-type", "application/json")
+                        .body(Body::from(format!(r#"{{ "data": {} }}"#, data)))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+            (
+                status,
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            )
+        }
+    };
+
+    let records = format!("/api/v1/collections/{}/records", collection_id);
+    let (status, record) = write(
+        "POST",
+        records.clone(),
+        r#"{ "email": "  Ada@Example.COM ", "name": "Cafe\u0301 ", "nickname": null,
+             "profile": { "bio": " untouched " } }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(
+        record["data"],
+        serde_json::json!({
+            "email": "ada@example.com",
+            "name": "Caf\u{e9}",
+            "profile": { "bio": " untouched " }
+        })
+    );
+
+    let (status, record) = write(
+        "PATCH",
+        format!("{}/{}", records, record["id"]),
+        r#"{ "email": "GRACE@example.com\t" }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(record["data"]["email"], "grace@example.com");
+}
+
+#[tokio::test]
+async fn test_computed_fields() {
+    let app = setup_test_app().await;
+    let create_collection = |fields: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/api/v1/collections")
+                        .header("content-type", "application/json")
+                        .body(Body::from(format!(
+                            r#"{{ "name": "orders", "schema": {{ "fields": {} }} }}"#,
+                            fields
+                        )))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+            (
+                status,
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            )
+        }
+    };
+
+    for fields in [
+        r#"{ "total": { "type": "number", "required": false, "compute": "price * qty" } }"#,
+        r#"{ "a": { "type": "string", "required": false, "compute": "lower(b)" },
+             "b": { "type": "string", "required": false, "compute": "upper(a)" } }"#,
+        r#"{ "a": { "type": "string", "required": false, "compute": "shout(a)" } }"#,
+        r#"{ "a": { "type": "string", "required": false, "compute": "1 +" } }"#,
+    ] {
+        let (status, _) = create_collection(fields).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", fields);
+    }
+
+    // `label` reads the computed `slug` and `total`, so it is computed last.
+    let (status, collection) = create_collection(
+        r#"{
+            "title": { "type": "string", "required": true },
+            "price": { "type": "number", "required": true },
+            "qty": { "type": "number", "required": false, "default": 1 },
+            "label": { "type": "string", "required": false, "compute": "slug + ':' + total" },
+            "slug": { "type": "string", "required": false, "compute": "slugify(title)" },
+            "total": { "type": "number", "required": false, "compute": "price * qty" }
+        }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let write = |method: &'static str, uri: String, data: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method(method)
+                        .uri(uri)
+                        .header("content-type", "application/json")
+                        .body(Body::from(format!(r#"{{ "data": {} }}"#, data)))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+            (
+                status,
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            )
+        }
+    };
+
+    let records = format!("/api/v1/collections/{}/records", collection["id"]);
+    let (status, record) = write(
+        "POST",
+        records.clone(),
+        r#"{ "title": "Blue Mug!", "price": 3, "slug": "ignored" }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(record["data"]["slug"], "blue-mug");
+    assert_eq!(record["data"]["total"], 3);
+    assert_eq!(record["data"]["label"], "blue-mug:3");
+
+    // Changing a source field recomputes the fields derived from it.
+    let (status, record) = write(
+        "PATCH",
+        format!("{}/{}", records, record["id"]),
+        r#"{ "qty": 4 }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(record["data"]["slug"], "blue-mug");
+    assert_eq!(record["data"]["total"], 12);
+    assert_eq!(record["data"]["label"], "blue-mug:12");
+}
+
+#[tokio::test]
+async fn test_field_constraints() {
+    let app = setup_test_app().await;
+    let create_collection = |schema: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/api/v1/collections")
+                        .header("content-type", "application/json")
+                        .body(Body::from(format!(
+                            r#"{{ "name": "products", "schema": {{ "fields": {} }} }}"#,
+                            schema
+                        )))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+            (
+                status,
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            )
+        }
+    };
+
+    let (status, _) = create_collection(
+        r#"{ "sku": { "type": "string", "required": true, "pattern": "([A-Z" } }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = create_collection(
+        r#"{ "price": { "type": "number", "required": true, "min": 10, "max": 1 } }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = create_collection(
+        r#"{ "price": { "type": "number", "required": true, "example": "cheap" } }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, collection) = create_collection(
+        r#"{
+            "sku": { "type": "string", "required": true, "pattern": "^[A-Z]{3}-[0-9]+$",
+                     "min_length": 5, "max_length": 10 },
+            "price": { "type": "number", "required": true, "min": 0, "max": 1000 },
+            "tags": { "type": "json", "required": false, "min_items": 1, "max_items": 2 }
+        }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let collection_id = collection["id"].as_i64().unwrap();
+
+    let create = |data: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri(format!("/api/v1/collections/{}/records", collection_id))
+                        .header("content-type", "application/json")
+                        .body(Body::from(format!(r#"{{ "data": {} }}"#, data)))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+            (
+                status,
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            )
+        }
+    };
+
+    let (status, _) = create(r#"{ "sku": "ABC-12", "price": 9.5, "tags": ["new"] }"#).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    for (data, code) in [
+        (r#"{ "sku": "ABC-12", "price": -1 }"#, "below_minimum"),
+        (r#"{ "sku": "ABC-12", "price": 1000.5 }"#, "above_maximum"),
+        (r#"{ "sku": "A-1", "price": 1 }"#, "too_short"),
+        (r#"{ "sku": "ABC-1234567", "price": 1 }"#, "too_long"),
+        (r#"{ "sku": "abc-12", "price": 1 }"#, "pattern_mismatch"),
+        (
+            r#"{ "sku": "ABC-12", "price": 1, "tags": [] }"#,
+            "too_few_items",
+        ),
+        (
+            r#"{ "sku": "ABC-12", "price": 1, "tags": [1, 2, 3] }"#,
+            "too_many_items",
+        ),
+    ] {
+        let (status, problem) = create(data).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", data);
+        assert_eq!(problem["details"][0]["code"], code, "{}", data);
+    }
+
+    // Details point at the value, and carry it and the broken constraint.
+    let (_, problem) = create(r#"{ "sku": "ABC-12", "price": -1 }"#).await;
+    assert_eq!(
+        problem["details"][0],
+        serde_json::json!({
+            "path": "/price",
+            "code": "below_minimum",
+            "min": 0.0,
+            "message": "Field 'price' must be at least 0",
+            "value": -1
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_nested_fields() {
+    let app = setup_test_app().await;
+    let send = |uri: String, body: String| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri(uri)
+                        .header("content-type", "application/json")
+                        .body(Body::from(body))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+            (
+                status,
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            )
+        }
+    };
+    let create_collection = |fields: &'static str| {
+        send(
+            "/api/v1/collections".to_string(),
+            format!(
+                r#"{{ "name": "orders", "schema": {{ "fields": {} }} }}"#,
+                fields
+            ),
+        )
+    };
+
+    // Only the fields of records can be relations or files.
+    let (status, _) = create_collection(
+        r#"{ "lines": { "type": { "array": { "items": "file" } }, "required": true } }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, collection) = create_collection(
+        r#"{
+            "address": { "type": { "object": { "fields": {
+                "street": { "type": "string", "required": true },
+                "zip": { "type": "string", "required": true, "pattern": "^[0-9]{5}$" }
+            } } }, "required": true },
+            "lines": { "type": { "array": { "items": { "object": { "fields": {
+                "qty": { "type": "number", "required": true, "min": 1 }
+            } } } } }, "required": false, "max_items": 3 },
+            "tags": { "type": { "array": { "items": "string" } }, "required": false }
+        }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let uri = format!("/api/v1/collections/{}/records", collection["id"]);
+
+    let (status, _) = send(
+        uri.clone(),
+        r#"{ "data": { "address": { "street": "Main St", "zip": "12345" },
+                       "lines": [{ "qty": 2 }], "tags": ["gift"] } }"#
+            .to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, problem) = send(
+        uri,
+        r#"{ "data": { "address": { "zip": 12345 },
+                       "lines": [{ "qty": 2 }, { "qty": 0 }, {}], "tags": ["gift", 7] } }"#
+            .to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let mut details: Vec<(&str, &str)> = problem["details"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|d| (d["path"].as_str().unwrap(), d["message"].as_str().unwrap()))
+        .collect();
+    details.sort();
+    assert_eq!(
+        details,
+        [
+            ("/address/street", "Missing required field: address.street"),
+            (
+                "/address/zip",
+                "Invalid type for field 'address.zip': expected string, got number"
+            ),
+            ("/lines/1/qty", "Field 'lines[1].qty' must be at least 1"),
+            ("/lines/2/qty", "Missing required field: lines[2].qty"),
+            (
+                "/tags/1",
+                "Invalid type for field 'tags[1]': expected string, got number"
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_list_pagination_and_envelope() {
+    let state = setup_test_state().await;
+    let bare = app_router(state.clone());
+    let enveloped = app_router(state.with_list_format(ListFormat::Envelope));
+    let collection_id = create_test_collection(&bare).await;
+    for title in ["First", "Second", "Third"] {
+        bare.clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri(format!("/api/v1/collections/{}/records", collection_id))
+                    .header("content-type", "application/json")
+                    .body(Body::from(format!(
+                        r#"{{ "data": {{ "title": "{}" }} }}"#,
+                        title
+                    )))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+    }
+    let list = |app: &axum::Router, query: &str| {
+        let request = Request::builder()
+            .uri(format!(
+                "/api/v1/collections/{}/records?{}",
+                collection_id, query
+            ))
+            .body(Body::empty())
+            .unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+            (
+                status,
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            )
+        }
+    };
+
+    // Bare arrays stay the default and only page when asked to.
+    let (_, records) = list(&bare, "").await;
+    assert_eq!(records.as_array().unwrap().len(), 3);
+    let (_, records) = list(&bare, "page=2&per_page=2").await;
+    assert_eq!(records.as_array().unwrap().len(), 1);
+    assert_eq!(records[0]["data"]["title"], "Third");
+    let (status, _) = list(&bare, "page=0").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, page) = list(&enveloped, "sort=-id&per_page=2").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["items"].as_array().unwrap().len(), 2);
+    assert_eq!(page["items"][0]["data"]["title"], "Third");
+    assert_eq!(page["page"], 1);
+    assert_eq!(page["per_page"], 2);
+    assert_eq!(page["total"], 3);
+    let records_uri = format!("/api/v1/collections/{}/records", collection_id);
+    assert_eq!(
+        page["links"]["next"],
+        format!("{}?sort=-id&page=2&per_page=2", records_uri)
+    );
+    assert_eq!(page["links"]["prev"], serde_json::Value::Null);
+    assert_eq!(
+        page["links"]["last"],
+        format!("{}?sort=-id&page=2&per_page=2", records_uri)
+    );
+
+    let (_, page) = list(&enveloped, "updated_after=2999-01-01").await;
+    assert_eq!(page["total"], 0);
+    assert_eq!(page["per_page"], 30);
+    assert_eq!(page["items"].as_array().unwrap().len(), 0);
+
+    let response = enveloped
+        .oneshot(
+            Request::builder()
+                .uri("/api/v1/collections")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let page: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(page["total"], 1);
+    assert_eq!(
+        page["links"]["self"],
+        "/api/v1/collections?page=1&per_page=30"
+    );
+}
+
+#[tokio::test]
+async fn test_merge_patch_and_replace() {
+    let app = setup_test_app().await;
+    let collection_id = create_test_collection(&app).await;
+    let send = |method: &'static str, uri: String, body: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method(method)
+                        .uri(uri)
+                        .header("content-type", "application/json")
+                        .body(Body::from(body))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+            let value = serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null);
+            (status, value)
+        }
+    };
+
+    let (_, record) = send(
+        "POST",
+        format!("/api/v1/collections/{}/records", collection_id),
+        r#"{ "data": { "title": "Hello", "body": "Text", "meta": { "a": 1, "b": 2 } } }"#,
+    )
+    .await;
+    let uri = format!(
+        "/api/v1/collections/{}/records/{}",
+        collection_id, record["id"]
+    );
+
+    // PATCH only touches the fields it names; null removes one.
+    let (status, record) = send(
+        "PATCH",
+        uri.clone(),
+        r#"{ "data": { "body": null, "meta": { "b": 3, "c": 4 } } }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        record["data"],
+        serde_json::json!({ "title": "Hello", "meta": { "a": 1, "b": 3, "c": 4 } })
+    );
+
+    // Validation runs on the merged result.
+    let (status, _) = send("PATCH", uri.clone(), r#"{ "data": { "title": null } }"#).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    // PUT replaces the data as a whole.
+    let (status, _) = send("PUT", uri.clone(), r#"{ "data": { "body": "Only" } }"#).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, record) = send("PUT", uri.clone(), r#"{ "data": { "title": "New" } }"#).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(record["data"], serde_json::json!({ "title": "New" }));
+
+    let (status, _) = send(
+        "PATCH",
+        format!("/api/v1/collections/{}/records/999", collection_id),
+        r#"{ "data": { "title": "Nope" } }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_developer_mode_error_details() {
+    let path = common::temp_path("errors.db");
+    let db = libsql::Builder::new_local(&path).build().await.unwrap();
+    let conn = db.connect().unwrap();
+    tinybase_core::setup_database(&conn).await.unwrap();
+    let state = setup_test_state().await;
+    let state = tinybase_api::AppState {
+        db: std::sync::Arc::new(tokio::sync::Mutex::new(db.connect().unwrap())),
+        ..state
+    };
+    let app = app_router(state.clone());
+    let collection_id = create_test_collection(&app).await;
+    conn.execute("DROP TABLE records", ()).await.unwrap();
+
+    let list = |app: axum::Router| async move {
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/v1/collections/{}/records", collection_id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+        serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+    };
+
+    // Production responses do not leak internals.
+    let problem = list(app).await;
+    assert_eq!(problem["error"], "database_error");
+    assert!(problem["details"].is_null());
+
+    let problem = list(app_router(state.with_developer_mode(true))).await;
+    let details = &problem["details"];
+    assert!(details["db_error"]
+        .as_str()
+        .unwrap()
+        .contains("no such table"));
+    assert!(details["sql"].as_str().unwrap().starts_with("SELECT"));
+    assert!(details["chain"].is_array());
+}
+
+#[tokio::test]
+async fn test_list_records_query_timeout() {
+    let state = setup_test_state().await;
+    let db = state.db.clone();
+    let app = app_router(state.with_query_timeout(Some(std::time::Duration::from_millis(1))));
+    let collection_id = create_test_collection(&app).await;
+    for i in 0..5000 {
+        db.create_record(
+            collection_id,
+            &serde_json::json!({ "title": format!("Post {}", i) }),
+        )
+        .await
+        .unwrap();
+    }
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri(format!("/api/v1/collections/{}/records", collection_id))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(problem["error"], "query_timeout");
+}
+
+#[tokio::test]
+async fn test_prefer_return() {
+    let app = setup_test_app().await;
+    let collection_id = create_test_collection(&app).await;
+    let records = format!("/api/v1/collections/{}/records", collection_id);
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri(&records)
+                .header("content-type", "application/json")
+                .header("prefer", "handling=lenient, return=minimal")
+                .body(Body::from(r#"{ "data": { "title": "Quiet" } }"#))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(response.headers()["preference-applied"], "return=minimal");
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    assert!(body.is_empty());
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(&records)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let listed: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(listed[0]["data"]["title"], "Quiet");
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("PATCH")
+                .uri(format!("{}/{}", records, listed[0]["id"]))
+                .header("content-type", "application/json")
+                .header("prefer", "return=representation")
+                .body(Body::from(r#"{ "data": { "title": "Loud" } }"#))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["preference-applied"],
+        "return=representation"
+    );
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let record: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(record["data"]["title"], "Loud");
+    assert!(record["updated"].is_string());
+}
+
+#[tokio::test]
+async fn test_aggregate_records() {
+    let app = setup_test_app().await;
+    let request = |method: &'static str, uri: String, body: String| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method(method)
+                        .uri(uri)
+                        .header("content-type", "application/json")
+                        .body(Body::from(body))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+            (
+                status,
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            )
+        }
+    };
+
+    let (_, collection) = request(
+        "POST",
+        "/api/v1/collections".to_string(),
+        r#"{ "name": "orders", "schema": { "fields": {
+            "status": { "type": "string", "required": true },
+            "price": { "type": "number", "required": false }
+        } } }"#
+            .to_string(),
+    )
+    .await;
+    let records = format!("/api/v1/collections/{}/records", collection["id"]);
+    for data in [
+        r#"{ "status": "paid", "price": 10 }"#,
+        r#"{ "status": "paid", "price": 2.5 }"#,
+        r#"{ "status": "open", "price": 4 }"#,
+        r#"{ "status": "open" }"#,
+        r#"{ "status": "void" }"#,
+    ] {
+        let body = format!(r#"{{ "data": {} }}"#, data);
+        let (status, _) = request("POST", records.clone(), body).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    let aggregate = |query: &str| {
+        request(
+            "GET",
+            format!("{}/aggregate?{}", records, query),
+            String::new(),
+        )
+    };
+    let (status, totals) = aggregate("sum=price&avg=price").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        totals,
+        serde_json::json!([{
+            "group": {},
+            "count": 5,
+            "sum": { "price": 16.5 },
+            "avg": { "price": 5.5 }
+        }])
+    );
+
+    let (_, groups) = aggregate("group_by=status&sum=price&min=price&max=price").await;
+    assert_eq!(
+        groups,
+        serde_json::json!([
+            { "group": { "status": "open" }, "count": 2, "sum": { "price": 4 },
+              "min": { "price": 4 }, "max": { "price": 4 } },
+            { "group": { "status": "paid" }, "count": 2, "sum": { "price": 12.5 },
+              "min": { "price": 2.5 }, "max": { "price": 10 } },
+            { "group": { "status": "void" }, "count": 1, "sum": { "price": 0 },
+              "min": { "price": null }, "max": { "price": null } }
+        ])
+    );
+
+    let (_, none) = aggregate("created_after=2999-01-01").await;
+    assert_eq!(none, serde_json::json!([{ "group": {}, "count": 0 }]));
+
+    for query in ["sum=status", "max=missing", "group_by=missing"] {
+        let (status, _) = aggregate(query).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", query);
+    }
+    let (status, _) = request(
+        "GET",
+        "/api/v1/collections/999/records/aggregate".to_string(),
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_update_and_delete_missing_record() {
+    let app = setup_test_app().await;
+    let collection_id = create_test_collection(&app).await;
+
+    let cases = [
+        ("PATCH", collection_id, r#"{ "data": { "title": "Nope" } }"#),
+        ("PUT", collection_id, r#"{ "data": { "title": "Nope" } }"#),
+        ("DELETE", collection_id, ""),
+        ("PATCH", 999, r#"{ "data": { "title": "Nope" } }"#),
+        ("DELETE", 999, ""),
+    ];
+    for (method, collection, body) in cases {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(format!("/api/v1/collections/{}/records/999", collection))
+                    .header("content-type", "application/json")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            response.status(),
+            StatusCode::NOT_FOUND,
+            "{} in collection {}",
+            method,
+            collection
+        );
+        let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem["status"], 404);
+    }
+}
@@ -0,0 +1,219 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state};
+use tinybase_api::app_router;
+
+async fn send(
+    app: &axum::Router,
+    method: &str,
+    uri: &str,
+    body: &str,
+) -> (StatusCode, serde_json::Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(if body.is_empty() {
+                    Body::empty()
+                } else {
+                    Body::from(body.to_string())
+                })
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let value = serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null);
+    (status, value)
+}
+
+/// Creates an `authors` collection and a `posts` collection whose `author`
+/// field points at it, returning both ids.
+async fn create_related_collections(app: &axum::Router, cascade_delete: bool) -> (i64, i64) {
+    let (_, authors) = send(
+        app,
+        "POST",
+        "/api/v1/collections",
+        r#"{ "name": "authors", "schema": { "fields": { "name": { "type": "string", "required": true } } } }"#,
+    )
+    .await;
+    let authors_id = authors["id"].as_i64().unwrap();
+    let (_, posts) = send(
+        app,
+        "POST",
+        "/api/v1/collections",
+        &format!(
+            r#"{{ "name": "posts", "schema": {{ "fields": {{ "author": {{ "type": {{ "relation": {{ "collection_id": {}, "cascade_delete": {} }} }}, "required": true }} }} }} }}"#,
+            authors_id, cascade_delete
+        ),
+    )
+    .await;
+    (authors_id, posts["id"].as_i64().unwrap())
+}
+
+#[tokio::test]
+async fn test_expand_relation() {
+    let app = setup_test_app().await;
+    let (authors_id, posts_id) = create_related_collections(&app, false).await;
+
+    let (_, author) = send(
+        &app,
+        "POST",
+        &format!("/api/v1/collections/{}/records", authors_id),
+        r#"{ "data": { "name": "Ada" } }"#,
+    )
+    .await;
+    let (status, post) = send(
+        &app,
+        "POST",
+        &format!("/api/v1/collections/{}/records", posts_id),
+        &format!(r#"{{ "data": {{ "author": {} }} }}"#, author["id"]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, record) = send(
+        &app,
+        "GET",
+        &format!(
+            "/api/v1/collections/{}/records/{}?expand=author",
+            posts_id, post["id"]
+        ),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(record["expand"]["author"]["data"]["name"], "Ada");
+
+    let (status, records) = send(
+        &app,
+        "GET",
+        &format!("/api/v1/collections/{}/records?expand=author", posts_id),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(records[0]["expand"]["author"]["id"], author["id"]);
+}
+
+#[tokio::test]
+async fn test_relation_to_missing_record() {
+    let app = setup_test_app().await;
+    let (_, posts_id) = create_related_collections(&app, false).await;
+
+    let (status, _) = send(
+        &app,
+        "POST",
+        &format!("/api/v1/collections/{}/records", posts_id),
+        r#"{ "data": { "author": 999 } }"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn test_cascade_delete_relation() {
+    let app = setup_test_app().await;
+    let (authors_id, posts_id) = create_related_collections(&app, true).await;
+
+    let (_, author) = send(
+        &app,
+        "POST",
+        &format!("/api/v1/collections/{}/records", authors_id),
+        r#"{ "data": { "name": "Ada" } }"#,
+    )
+    .await;
+    let (_, post) = send(
+        &app,
+        "POST",
+        &format!("/api/v1/collections/{}/records", posts_id),
+        &format!(r#"{{ "data": {{ "author": {} }} }}"#, author["id"]),
+    )
+    .await;
+
+    let (status, _) = send(
+        &app,
+        "DELETE",
+        &format!(
+            "/api/v1/collections/{}/records/{}",
+            authors_id, author["id"]
+        ),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _) = send(
+        &app,
+        "GET",
+        &format!("/api/v1/collections/{}/records/{}", posts_id, post["id"]),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_deleting_a_collection_cascades() {
+    let state = setup_test_state().await;
+    let mut events = state.events.subscribe();
+    let app = app_router(state);
+    let (authors_id, posts_id) = create_related_collections(&app, true).await;
+    for name in ["Ada", "Grace"] {
+        let (_, author) = send(
+            &app,
+            "POST",
+            &format!("/api/v1/collections/{}/records", authors_id),
+            &format!(r#"{{ "data": {{ "name": "{}" }} }}"#, name),
+        )
+        .await;
+        send(
+            &app,
+            "POST",
+            &format!("/api/v1/collections/{}/records", posts_id),
+            &format!(r#"{{ "data": {{ "author": {} }} }}"#, author["id"]),
+        )
+        .await;
+    }
+    while events.try_recv().is_ok() {}
+
+    let (status, _) = send(
+        &app,
+        "DELETE",
+        &format!("/api/v1/collections/{}?force=true", authors_id),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, posts) = send(
+        &app,
+        "GET",
+        &format!("/api/v1/collections/{}/records", posts_id),
+        "",
+    )
+    .await;
+    assert_eq!(posts, serde_json::json!([]));
+
+    // The authors go with one event; the posts cascading from them with one
+    // each.
+    let events: Vec<_> = std::iter::from_fn(|| events.try_recv().ok())
+        .map(|event| (event.action.name(), event.collection_id))
+        .collect();
+    assert_eq!(
+        events,
+        [
+            ("record.deleted", posts_id),
+            ("record.deleted", posts_id),
+            ("collection.deleted", authors_id)
+        ]
+    );
+}
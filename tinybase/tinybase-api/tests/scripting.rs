@@ -0,0 +1,240 @@
+#![cfg(feature = "scripting")]
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+mod common;
+use common::setup_test_app;
+
+async fn send(app: &Router, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+async fn add_hook(app: &Router, collection: &Value, event: &str, source: &str) -> Value {
+    let (status, hook) = send(
+        app,
+        "POST",
+        "/api/v1/admin/hooks",
+        json!({ "collection_id": collection["id"], "event": event, "source": source }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", hook);
+    hook
+}
+
+#[tokio::test]
+async fn test_script_hooks() {
+    let app = setup_test_app().await;
+    let (_, posts) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "posts", "schema": { "fields": {
+            "title": { "type": "string", "required": true },
+            "slug": { "type": "string", "required": true }
+        } } }),
+    )
+    .await;
+    let (_, audit) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "audit" }),
+    )
+    .await;
+    add_hook(
+        &app,
+        &posts,
+        "beforeCreate",
+        r#"if record.title == "spam" then reject("No spam, please") end
+           record.slug = (record.title:gsub(" ", "-")):lower()"#,
+    )
+    .await;
+    // Hooks run in order: this one sees the slug set by the first.
+    add_hook(
+        &app,
+        &posts,
+        "beforeCreate",
+        r#"return { title = record.title, slug = record.slug .. "-" .. #tinybase.find("posts", "title", record.title) }"#,
+    )
+    .await;
+    add_hook(
+        &app,
+        &posts,
+        "afterCreate",
+        r#"tinybase.create("audit", { post = id, by = collection })"#,
+    )
+    .await;
+    add_hook(
+        &app,
+        &posts,
+        "beforeUpdate",
+        r#"if previous.slug ~= record.slug then reject("The slug can't change") end"#,
+    )
+    .await;
+
+    // The slug is filled in before the record is validated.
+    let records = format!("/api/v1/collections/{}/records", posts["id"]);
+    let (status, post) = send(
+        &app,
+        "POST",
+        &records,
+        json!({ "data": { "title": "Hello World" } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", post);
+    assert_eq!(post["data"]["slug"], "hello-world-0");
+    let (_, second) = send(
+        &app,
+        "POST",
+        &records,
+        json!({ "data": { "title": "Hello World" } }),
+    )
+    .await;
+    assert_eq!(second["data"]["slug"], "hello-world-1");
+
+    let (status, problem) = send(
+        &app,
+        "POST",
+        &records,
+        json!({ "data": { "title": "spam" } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(problem["error"], "rejected");
+    assert_eq!(problem["message"], "No spam, please");
+
+    let (_, logged) = send(
+        &app,
+        "GET",
+        &format!("/api/v1/collections/{}/records", audit["id"]),
+        Value::Null,
+    )
+    .await;
+    assert_eq!(logged.as_array().unwrap().len(), 2);
+    assert_eq!(
+        logged[0]["data"],
+        json!({ "post": post["id"], "by": "posts" })
+    );
+
+    let record = format!("{}/{}", records, post["id"]);
+    let (status, _) = send(
+        &app,
+        "PATCH",
+        &record,
+        json!({ "data": { "slug": "other" } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, updated) =
+        send(&app, "PATCH", &record, json!({ "data": { "title": "Hi" } })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(updated["data"]["slug"], "hello-world-0");
+
+    let (_, hooks) = send(&app, "GET", "/api/v1/admin/hooks", Value::Null).await;
+    assert_eq!(hooks.as_array().unwrap().len(), 4);
+    for hook in hooks.as_array().unwrap() {
+        let uri = format!("/api/v1/admin/hooks/{}", hook["id"]);
+        let (status, _) = send(&app, "DELETE", &uri, Value::Null).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+    let (status, _) = send(
+        &app,
+        "POST",
+        &records,
+        json!({ "data": { "title": "spam", "slug": "spam" } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn test_failing_script_hooks() {
+    let app = setup_test_app().await;
+    let (_, posts) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "posts" }),
+    )
+    .await;
+
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/admin/hooks",
+        json!({ "collection_id": posts["id"], "event": "beforeCreate", "source": "if then" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/admin/hooks",
+        json!({ "collection_id": 999, "event": "beforeCreate", "source": "" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Runaway scripts are stopped, and scripts can't reach the OS.
+    let records = format!("/api/v1/collections/{}/records", posts["id"]);
+    for source in ["while true do end", "os.exit(1)", "error('boom')"] {
+        let hook = add_hook(&app, &posts, "beforeCreate", source).await;
+        let (status, _) = send(&app, "POST", &records, json!({ "data": {} })).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR, "{}", source);
+        let uri = format!("/api/v1/admin/hooks/{}", hook["id"]);
+        send(&app, "DELETE", &uri, Value::Null).await;
+    }
+
+    // A failing afterCreate hook doesn't undo the write.
+    add_hook(&app, &posts, "afterCreate", "error('boom')").await;
+    let (status, _) = send(&app, "POST", &records, json!({ "data": {} })).await;
+    assert_eq!(status, StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn test_script_hooks_use_the_kv_store() {
+    let app = setup_test_app().await;
+    let (_, posts) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "posts" }),
+    )
+    .await;
+    add_hook(
+        &app,
+        &posts,
+        "beforeCreate",
+        r#"local count = (tinybase.kv.get("counters", "posts") or 0) + 1
+           tinybase.kv.set("counters", "posts", count)
+           record.number = count"#,
+    )
+    .await;
+
+    let records = format!("/api/v1/collections/{}/records", posts["id"]);
+    send(&app, "POST", &records, json!({ "data": {} })).await;
+    let (_, post) = send(&app, "POST", &records, json!({ "data": {} })).await;
+    assert_eq!(post["data"]["number"], 2, "{}", post);
+    let (_, entry) = send(&app, "GET", "/api/v1/kv/counters/posts", Value::Null).await;
+    assert_eq!(entry["value"], 2);
+}
@@ -0,0 +1,238 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tinybase_api::{app_router, search, AppState};
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state};
+
+async fn send(app: &Router, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+/// An app whose search index follows its records, with an `articles`
+/// collection searching titles and bodies.
+async fn app_with_articles(state: AppState) -> Router {
+    search::spawn(state.clone());
+    let app = app_router(state);
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "articles", "schema": {
+            "fields": {
+                "title": { "type": "string", "required": true },
+                "body": { "type": "string", "required": false },
+                "secret": { "type": "string", "required": false }
+            },
+            "search": { "fields": ["title", "body"] }
+        } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    app
+}
+
+async fn create(app: &Router, data: Value) -> i64 {
+    let (status, record) = send(
+        app,
+        "POST",
+        "/api/v1/collections/articles/records",
+        json!({ "data": data }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    record["id"].as_i64().unwrap()
+}
+
+/// The ids of the articles matching `q`, best first, once the index holds
+/// `expected` of them.
+async fn search_for(app: &Router, q: &str, expected: usize) -> Vec<i64> {
+    let uri = format!("/api/v1/collections/articles/records/search?q={}", q);
+    let mut ids = Vec::new();
+    for _ in 0..200 {
+        let (status, hits) = send(app, "GET", &uri, Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        ids = hits
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|hit| hit["id"].as_i64().unwrap())
+            .collect();
+        if ids.len() == expected {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    ids
+}
+
+#[tokio::test]
+async fn test_search_ranks_stemmed_matches() {
+    let app = app_with_articles(setup_test_state().await).await;
+    let mentions = create(
+        &app,
+        json!({ "title": "Weekly notes", "body": "We ran the usual checks and a database migration." }),
+    )
+    .await;
+    let about = create(
+        &app,
+        json!({ "title": "Running database migrations", "body": "How migrations run, and why." }),
+    )
+    .await;
+    create(
+        &app,
+        json!({ "title": "Unrelated", "body": "Nothing here", "secret": "migration" }),
+    )
+    .await;
+
+    // "migrations" finds "migration" too, best match first; fields outside
+    // search.fields aren't searched.
+    assert_eq!(
+        search_for(&app, "migrations", 2).await,
+        vec![about, mentions]
+    );
+    assert_eq!(search_for(&app, "running+database", 1).await, vec![about]);
+
+    let (status, hits) = send(
+        &app,
+        "GET",
+        "/api/v1/collections/articles/records/search?q=migrations&limit=1",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(hits.as_array().unwrap().len(), 1);
+    assert_eq!(hits[0]["data"]["title"], "Running database migrations");
+    assert!(hits[0]["score"].as_f64().unwrap() > 0.0);
+}
+
+#[tokio::test]
+async fn test_search_follows_updates_and_deletes() {
+    let app = app_with_articles(setup_test_state().await).await;
+    let id = create(&app, json!({ "title": "Draft about apples" })).await;
+    assert_eq!(search_for(&app, "apples", 1).await, vec![id]);
+
+    let uri = format!("/api/v1/collections/articles/records/{}", id);
+    let (status, _) = send(
+        &app,
+        "PATCH",
+        &uri,
+        json!({ "data": { "title": "Final about pears" } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(search_for(&app, "pears", 1).await, vec![id]);
+    assert!(search_for(&app, "apples", 0).await.is_empty());
+
+    let (status, _) = send(&app, "DELETE", &uri, Value::Null).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(search_for(&app, "pears", 0).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_search_needs_search_fields() {
+    let app = setup_test_app().await;
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "plain", "schema": { "fields": { "title": { "type": "string", "required": false } } } }),
+    )
+    .await;
+    let (status, problem) = send(
+        &app,
+        "GET",
+        "/api/v1/collections/plain/records/search?q=anything",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(problem["message"]
+        .as_str()
+        .unwrap()
+        .contains("search.fields"));
+
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "typo", "schema": {
+            "fields": { "title": { "type": "string", "required": false } },
+            "search": { "fields": ["titel"] }
+        } }),
+    )
+    .await;
+    assert_ne!(status, StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn test_reindex_rebuilds_the_index() {
+    // Records written while nothing indexes them are found after a reindex.
+    let state = setup_test_state().await;
+    let app = app_router(state.clone());
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "articles", "schema": {
+            "fields": { "title": { "type": "string", "required": false } },
+            "search": { "fields": ["title"] }
+        } }),
+    )
+    .await;
+    let id = create(&app, json!({ "title": "Lost and found" })).await;
+    assert!(search_for(&app, "found", 0).await.is_empty());
+
+    let (status, job) = send(&app, "POST", "/api/v1/admin/search/reindex", Value::Null).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(job["kind"], "reindex");
+    let uri = format!("/api/v1/jobs/{}", job["id"]);
+    let mut job = Value::Null;
+    for _ in 0..200 {
+        (_, job) = send(&app, "GET", &uri, Value::Null).await;
+        if job["state"] != "queued" && job["state"] != "running" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(job["state"], "completed");
+    assert_eq!(job["result"], json!({ "collections": 1, "records": 1 }));
+    assert_eq!(search_for(&app, "found", 1).await, vec![id]);
+}
+
+#[cfg(feature = "tantivy")]
+#[tokio::test]
+async fn test_tantivy_search_tolerates_typos() {
+    use std::sync::Arc;
+    use tinybase_api::tantivy_search::TantivyIndex;
+
+    let state = setup_test_state().await;
+    let state = state.with_search(Arc::new(TantivyIndex::in_memory().unwrap()));
+    let app = app_with_articles(state).await;
+    let id = create(&app, json!({ "title": "Database migrations" })).await;
+    create(&app, json!({ "title": "Gardening" })).await;
+
+    assert_eq!(search_for(&app, "databse", 1).await, vec![id]);
+    let (_, meta) = send(&app, "GET", "/api/v1/meta", Value::Null).await;
+    assert_eq!(meta["search"], "tantivy");
+}
@@ -0,0 +1,120 @@
+#![cfg(feature = "schema-sync")]
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+
+mod common;
+use common::setup_test_app;
+
+async fn send(
+    app: &axum::Router,
+    method: &str,
+    uri: &str,
+    body: &str,
+) -> (StatusCode, serde_json::Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(if body.is_empty() {
+                    Body::empty()
+                } else {
+                    Body::from(body.to_string())
+                })
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let value = serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null);
+    (status, value)
+}
+
+/// Serves `app` on an ephemeral local port and returns its base URL.
+async fn serve(app: axum::Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{}", addr)
+}
+
+#[tokio::test]
+async fn test_schema_sync() {
+    let source = setup_test_app().await;
+    let (_, authors) = send(
+        &source,
+        "POST",
+        "/api/v1/collections",
+        r#"{ "name": "authors", "schema": { "fields": { "name": { "type": "string", "required": true } } } }"#,
+    )
+    .await;
+    send(
+        &source,
+        "POST",
+        "/api/v1/collections",
+        &format!(
+            r#"{{ "name": "posts", "schema": {{ "fields": {{ "author": {{ "type": {{ "relation": {{ "collection_id": {} }} }}, "required": true }} }} }} }}"#,
+            authors["id"]
+        ),
+    )
+    .await;
+    let source_url = serve(source).await;
+
+    let target = setup_test_app().await;
+    // Shift ids so relation targets have to be remapped by name.
+    send(
+        &target,
+        "POST",
+        "/api/v1/collections",
+        r#"{ "name": "legacy" }"#,
+    )
+    .await;
+    send(
+        &target,
+        "POST",
+        "/api/v1/collections",
+        r#"{ "name": "authors" }"#,
+    )
+    .await;
+
+    let request =
+        |dry_run: bool| format!(r#"{{ "from": "{}", "dry_run": {} }}"#, source_url, dry_run);
+    let (status, plan) = send(&target, "POST", "/api/v1/admin/schema/sync", &request(true)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(plan["create"], serde_json::json!(["posts"]));
+    assert_eq!(plan["update"], serde_json::json!(["authors"]));
+    assert_eq!(plan["local_only"], serde_json::json!(["legacy"]));
+    assert_eq!(plan["applied"], false);
+
+    let (_, collections) = send(&target, "GET", "/api/v1/collections", "").await;
+    assert_eq!(collections.as_array().unwrap().len(), 2);
+
+    let (_, result) = send(
+        &target,
+        "POST",
+        "/api/v1/admin/schema/sync",
+        &request(false),
+    )
+    .await;
+    assert_eq!(result["applied"], true);
+
+    let (_, collections) = send(&target, "GET", "/api/v1/collections", "").await;
+    let collections = collections.as_array().unwrap();
+    let local_authors = collections.iter().find(|c| c["name"] == "authors").unwrap();
+    let posts = collections.iter().find(|c| c["name"] == "posts").unwrap();
+    assert_eq!(
+        posts["schema"]["fields"]["author"]["type"]["relation"]["collection_id"],
+        local_authors["id"]
+    );
+
+    let (_, plan) = send(&target, "POST", "/api/v1/admin/schema/sync", &request(true)).await;
+    assert_eq!(plan["create"], serde_json::json!([]));
+    assert_eq!(plan["update"], serde_json::json!([]));
+}
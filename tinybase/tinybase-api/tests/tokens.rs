@@ -0,0 +1,174 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+mod common;
+use common::setup_test_app;
+
+async fn send(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    key: Option<&str>,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json");
+    if let Some(key) = key {
+        request = request.header("authorization", format!("Bearer {}", key));
+    }
+    let body = body.map_or_else(Body::empty, |b| Body::from(b.to_string()));
+    let response = app
+        .clone()
+        .oneshot(request.body(body).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+/// An app with collections `posts` and `notes` and an admin, whose API key
+/// is returned.
+async fn setup() -> (Router, String) {
+    let app = setup_test_app().await;
+    for name in ["posts", "notes"] {
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/api/v1/collections",
+            None,
+            Some(json!({ "name": name })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+    let admin = json!({ "email": "admin@example.com", "password": "correct horse" });
+    send(
+        &app,
+        "POST",
+        "/api/v1/admin/admins",
+        None,
+        Some(admin.clone()),
+    )
+    .await;
+    let (_, auth) = send(&app, "POST", "/api/v1/admin/auth", None, Some(admin)).await;
+    let key = auth["key"].as_str().unwrap().to_string();
+    (app, key)
+}
+
+#[tokio::test]
+async fn test_token_scopes() {
+    let (app, key) = setup().await;
+    let (status, minted) = send(
+        &app,
+        "POST",
+        "/api/v1/admin/tokens",
+        Some(&key),
+        Some(json!({ "name": "search indexer", "scopes": [
+            { "collection": "posts", "access": "read" },
+            { "collection": "notes", "access": "write" }
+        ] })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let token = minted["token"].as_str().unwrap();
+    assert!(token.starts_with("tbt_"));
+
+    let note = json!({ "data": { "text": "hello" } });
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/collections/notes/records",
+        Some(token),
+        Some(note.clone()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, _) = send(
+        &app,
+        "GET",
+        "/api/v1/collections/posts/records",
+        Some(token),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = send(
+        &app,
+        "POST",
+        "/api/v1/collections/posts/records",
+        Some(token),
+        Some(note),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"], "forbidden");
+    let (status, _) = send(&app, "GET", "/api/v1/admin/tokens", Some(token), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send(&app, "GET", "/api/v1/collections", Some(token), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, introspected) =
+        send(&app, "GET", "/api/v1/auth/introspect", Some(token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(introspected["subject"], format!("token:{}", minted["id"]));
+    assert_eq!(introspected["scopes"], json!(["posts:read", "notes:write"]));
+
+    // Listing never shows the token again; revoking it locks it out.
+    let (status, listed) = send(&app, "GET", "/api/v1/admin/tokens", Some(&key), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(listed[0]["name"], "search indexer");
+    assert!(listed[0].get("token").is_none());
+    let uri = format!("/api/v1/admin/tokens/{}", minted["id"]);
+    let (status, _) = send(&app, "DELETE", &uri, Some(&key), None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send(&app, "DELETE", &uri, Some(&key), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(
+        &app,
+        "GET",
+        "/api/v1/collections/posts/records",
+        Some(token),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_token_validation() {
+    let (app, key) = setup().await;
+    for (body, expected) in [
+        (
+            json!({ "name": "", "scopes": [{ "collection": "*", "access": "read" }] }),
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            json!({ "name": "indexer", "scopes": [] }),
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            json!({ "name": "indexer", "scopes": [{ "collection": "nope", "access": "read" }] }),
+            StatusCode::NOT_FOUND,
+        ),
+    ] {
+        let (status, _) = send(&app, "POST", "/api/v1/admin/tokens", Some(&key), Some(body)).await;
+        assert_eq!(status, expected);
+    }
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/admin/tokens",
+        None,
+        Some(json!({ "name": "indexer", "scopes": [{ "collection": "*", "access": "write" }] })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
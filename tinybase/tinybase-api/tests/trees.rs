@@ -0,0 +1,228 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+mod common;
+use common::setup_test_app;
+
+async fn send(app: &axum::Router, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(if body.is_null() {
+                    Body::empty()
+                } else {
+                    Body::from(body.to_string())
+                })
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    (status, value)
+}
+
+async fn create(app: &axum::Router, collection: &str, data: Value) -> i64 {
+    let (status, record) = send(
+        app,
+        "POST",
+        &format!("/api/v1/collections/{}/records", collection),
+        json!({ "data": data }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    record["id"].as_i64().unwrap()
+}
+
+/// The `name`s of a list of tree nodes.
+fn names(nodes: &Value) -> Vec<&str> {
+    nodes
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|n| n["data"]["name"].as_str().unwrap())
+        .collect()
+}
+
+fn category_schema(tree: bool) -> Value {
+    let mut schema = json!({
+        "fields": {
+            "name": { "type": "string", "required": true },
+            "parent": { "type": "number", "required": false }
+        }
+    });
+    if tree {
+        schema["tree"] = json!({ "parent": "parent" });
+    }
+    schema
+}
+
+#[tokio::test]
+async fn test_tree_of_records() {
+    let app = setup_test_app().await;
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "categories", "schema": category_schema(true) }),
+    )
+    .await;
+    let books = create(&app, "categories", json!({ "name": "Books" })).await;
+    let fiction = create(
+        &app,
+        "categories",
+        json!({ "name": "Fiction", "parent": books }),
+    )
+    .await;
+    create(
+        &app,
+        "categories",
+        json!({ "name": "History", "parent": books }),
+    )
+    .await;
+    let crime = create(
+        &app,
+        "categories",
+        json!({ "name": "Crime", "parent": fiction }),
+    )
+    .await;
+    let music = create(&app, "categories", json!({ "name": "Music" })).await;
+    let tree = "/api/v1/collections/categories/tree";
+
+    let (status, roots) = send(&app, "GET", tree, Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(names(&roots), ["Books", "Music"]);
+    assert_eq!(roots[0]["parent"], Value::Null);
+    assert_eq!(roots[0]["depth"], 0);
+
+    let (_, children) = send(
+        &app,
+        "GET",
+        &format!("{}/{}/children", tree, books),
+        Value::Null,
+    )
+    .await;
+    assert_eq!(names(&children), ["Fiction", "History"]);
+
+    let (status, subtree) = send(&app, "GET", &format!("{}/{}", tree, books), Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(names(&subtree), ["Books", "Fiction", "Crime", "History"]);
+    assert_eq!(subtree[2]["depth"], 2);
+    assert_eq!(subtree[2]["parent"], fiction);
+    assert_eq!(subtree[2]["path"], json!([books, fiction]));
+    let (_, shallow) = send(
+        &app,
+        "GET",
+        &format!("{}/{}?depth=1", tree, books),
+        Value::Null,
+    )
+    .await;
+    assert_eq!(names(&shallow), ["Books", "Fiction", "History"]);
+
+    // Moving a record takes its subtree along.
+    let (status, moved) = send(
+        &app,
+        "POST",
+        &format!("{}/{}/move", tree, fiction),
+        json!({ "parent": music }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(moved["data"]["parent"], music);
+    assert_eq!(moved["depth"], 1);
+    let (_, subtree) = send(&app, "GET", &format!("{}/{}", tree, music), Value::Null).await;
+    assert_eq!(names(&subtree), ["Music", "Fiction", "Crime"]);
+    assert_eq!(subtree[2]["path"], json!([music, fiction]));
+
+    // A record can't move under itself or its descendants.
+    for parent in [music, crime] {
+        let (status, _) = send(
+            &app,
+            "PATCH",
+            &format!("/api/v1/collections/categories/records/{}", music),
+            json!({ "data": { "parent": parent } }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    // Moving to no parent makes a root, here the first one.
+    let (status, moved) = send(
+        &app,
+        "POST",
+        &format!("{}/{}/move", tree, crime),
+        json!({ "parent": null, "position": 0 }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(moved["data"].get("parent"), None);
+    let (_, roots) = send(&app, "GET", tree, Value::Null).await;
+    assert_eq!(names(&roots), ["Crime", "Books", "Music"]);
+
+    // The children of a deleted record become roots.
+    send(
+        &app,
+        "DELETE",
+        &format!("/api/v1/collections/categories/records/{}", books),
+        Value::Null,
+    )
+    .await;
+    let (_, roots) = send(&app, "GET", tree, Value::Null).await;
+    assert_eq!(names(&roots), ["Crime", "Music", "History"]);
+
+    let (status, _) = send(&app, "GET", &format!("{}/{}", tree, books), Value::Null).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_declaring_a_tree_lays_out_existing_records() {
+    let app = setup_test_app().await;
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "places", "schema": category_schema(false) }),
+    )
+    .await;
+    let (status, _) = send(&app, "GET", "/api/v1/collections/places/tree", Value::Null).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let europe = create(&app, "places", json!({ "name": "Europe" })).await;
+    let france = create(
+        &app,
+        "places",
+        json!({ "name": "France", "parent": europe }),
+    )
+    .await;
+    create(&app, "places", json!({ "name": "Paris", "parent": france })).await;
+    create(&app, "places", json!({ "name": "Atlantis", "parent": 999 })).await;
+
+    let (status, _) = send(
+        &app,
+        "PATCH",
+        "/api/v1/collections/places",
+        json!({ "schema": category_schema(true) }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, roots) = send(&app, "GET", "/api/v1/collections/places/tree", Value::Null).await;
+    assert_eq!(names(&roots), ["Europe", "Atlantis"]);
+    let (_, subtree) = send(
+        &app,
+        "GET",
+        &format!("/api/v1/collections/places/tree/{}", europe),
+        Value::Null,
+    )
+    .await;
+    assert_eq!(names(&subtree), ["Europe", "France", "Paris"]);
+    assert_eq!(subtree[2]["depth"], 2);
+}
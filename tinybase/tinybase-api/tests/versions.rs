@@ -0,0 +1,104 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+
+mod common;
+use common::setup_test_app;
+
+async fn send(
+    app: &axum::Router,
+    method: &str,
+    uri: &str,
+    body: &str,
+) -> (StatusCode, serde_json::Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(if body.is_empty() {
+                    Body::empty()
+                } else {
+                    Body::from(body.to_string())
+                })
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    let value = serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null);
+    (status, value)
+}
+
+#[tokio::test]
+async fn test_record_versions() {
+    let app = setup_test_app().await;
+    send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        r#"{ "name": "pages", "schema": { "fields": { "title": { "type": "string", "required": true } }, "max_versions": 2 } }"#,
+    )
+    .await;
+    let (_, record) = send(
+        &app,
+        "POST",
+        "/api/v1/collections/pages/records",
+        r#"{ "data": { "title": "v1" } }"#,
+    )
+    .await;
+    let uri = format!("/api/v1/collections/pages/records/{}", record["id"]);
+
+    let (status, versions) = send(&app, "GET", &format!("{}/versions", uri), "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(versions, serde_json::json!([]));
+
+    for title in ["v2", "v3", "v4"] {
+        let body = format!(r#"{{ "data": {{ "title": "{}" }} }}"#, title);
+        send(&app, "PATCH", &uri, &body).await;
+    }
+    // Only the latest two earlier states are kept.
+    let (_, versions) = send(&app, "GET", &format!("{}/versions", uri), "").await;
+    let versions = versions.as_array().unwrap();
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions[0]["version"], 2);
+    assert_eq!(versions[0]["data"]["title"], "v2");
+    assert_eq!(versions[1]["data"]["title"], "v3");
+    assert_eq!(versions[1]["deleted"], false);
+
+    let (status, restored) = send(&app, "POST", &format!("{}/versions/2/restore", uri), "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(restored["data"]["title"], "v2");
+    let (_, versions) = send(&app, "GET", &format!("{}/versions", uri), "").await;
+    assert_eq!(versions[1]["data"]["title"], "v4");
+
+    let (status, _) = send(&app, "POST", &format!("{}/versions/1/restore", uri), "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // A deleted record keeps its history and can be brought back.
+    send(&app, "DELETE", &uri, "").await;
+    let (status, versions) = send(&app, "GET", &format!("{}/versions", uri), "").await;
+    assert_eq!(status, StatusCode::OK);
+    let latest = versions.as_array().unwrap().last().unwrap().clone();
+    assert_eq!(latest["deleted"], true);
+    assert_eq!(latest["data"]["title"], "v2");
+
+    let (status, restored) = send(
+        &app,
+        "POST",
+        &format!("{}/versions/{}/restore", uri, latest["version"]),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(restored["id"], record["id"]);
+    assert_eq!(restored["created"], record["created"]);
+    let (status, current) = send(&app, "GET", &uri, "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(current["data"]["title"], "v2");
+}
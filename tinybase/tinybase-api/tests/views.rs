@@ -0,0 +1,77 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+};
+use tinybase_api::app_router;
+use tinybase_core::views::AttachedDatabases;
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state, temp_path};
+
+async fn setup_views_app() -> axum::Router {
+    let path = temp_path("postcodes.db");
+    let db = libsql::Builder::new_local(&path).build().await.unwrap();
+    db.connect()
+        .unwrap()
+        .execute_batch(
+            "
+            CREATE TABLE codes (code TEXT NOT NULL, city TEXT NOT NULL);
+            INSERT INTO codes (code, city) VALUES ('10115', 'Berlin'), ('80331', 'Munich');
+        ",
+        )
+        .await
+        .unwrap();
+    drop(db);
+
+    let mut views = AttachedDatabases::new();
+    views.attach("postcodes", &path).await.unwrap();
+    app_router(setup_test_state().await.with_views(views))
+}
+
+async fn get(app: &axum::Router, uri: &str) -> (StatusCode, serde_json::Value) {
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn test_view_collections() {
+    let app = setup_views_app().await;
+
+    let (status, views) = get(&app, "/api/v1/views").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(views[0]["name"], "postcodes.codes");
+    assert_eq!(views[0]["columns"], serde_json::json!(["code", "city"]));
+
+    let (status, rows) = get(
+        &app,
+        "/api/v1/views/postcodes.codes/records?limit=1&offset=1",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(rows.as_array().unwrap().len(), 1);
+    assert_eq!(rows[0]["id"], 2);
+    assert_eq!(rows[0]["data"]["city"], "Munich");
+
+    let (status, row) = get(&app, "/api/v1/views/postcodes.codes/records/1").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(row["data"]["code"], "10115");
+
+    let (status, _) = get(&app, "/api/v1/views/postcodes.missing/records").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_no_view_collections_attached() {
+    let app = setup_test_app().await;
+
+    let (status, views) = get(&app, "/api/v1/views").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(views, serde_json::json!([]));
+}
@@ -0,0 +1,306 @@
+#![cfg(feature = "webhooks")]
+
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::State,
+    http::{HeaderMap, Request, StatusCode},
+    routing::post,
+    Router,
+};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tinybase_api::{app_router, webhooks};
+use tokio::net::TcpListener;
+use tokio::time::Instant;
+use tower::ServiceExt;
+
+mod common;
+use common::{setup_test_app, setup_test_state};
+
+/// The signature header and body of each delivery received.
+type Received = Arc<Mutex<Vec<(String, Bytes)>>>;
+
+/// A receiver that fails the first delivery and accepts the rest, returning
+/// its URL.
+async fn receiver(received: Received) -> String {
+    let app = Router::new()
+        .route(
+            "/hook",
+            post(
+                |State(received): State<Received>, headers: HeaderMap, body: Bytes| async move {
+                    let signature = headers["x-tinybase-signature"]
+                        .to_str()
+                        .unwrap()
+                        .to_string();
+                    let mut received = received.lock().unwrap();
+                    received.push((signature, body));
+                    if received.len() == 1 {
+                        (StatusCode::INTERNAL_SERVER_ERROR, "not now")
+                    } else {
+                        (StatusCode::OK, "thanks")
+                    }
+                },
+            ),
+        )
+        .with_state(received);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    url
+}
+
+async fn send(app: &Router, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1_048_576).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn test_webhook_delivery_with_retry() {
+    let received = Received::default();
+    let url = receiver(received.clone()).await;
+    let state = setup_test_state().await;
+    webhooks::spawn(state.clone());
+    let app = app_router(state);
+
+    let (_, posts) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "posts" }),
+    )
+    .await;
+    let (status, webhook) = send(
+        &app,
+        "POST",
+        "/api/v1/admin/webhooks",
+        json!({ "url": url, "collection_id": posts["id"], "events": ["record.created"] }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let secret = webhook["secret"].as_str().unwrap().to_string();
+    let (_, listed) = send(&app, "GET", "/api/v1/admin/webhooks", Value::Null).await;
+    assert!(listed[0].get("secret").is_none());
+
+    let records = format!("/api/v1/collections/{}/records", posts["id"]);
+    send(&app, "POST", &records, json!({ "data": { "title": "Hi" } })).await;
+    // Other collections are not watched.
+    let (_, drafts) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "drafts" }),
+    )
+    .await;
+    let drafts = format!("/api/v1/collections/{}/records", drafts["id"]);
+    send(
+        &app,
+        "POST",
+        &drafts,
+        json!({ "data": { "title": "Draft" } }),
+    )
+    .await;
+
+    // The first attempt fails and is retried after a second.
+    for _ in 0..150 {
+        if received.lock().unwrap().len() >= 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let received = received.lock().unwrap().clone();
+    assert_eq!(received.len(), 2);
+    let (signature, body) = &received[1];
+    assert_eq!(*signature, webhooks::signature(&secret, body));
+    let payload: Value = serde_json::from_slice(body).unwrap();
+    assert_eq!(payload["event"], "record.created");
+    assert_eq!(payload["record"]["data"]["title"], "Hi");
+
+    let deliveries = format!("/api/v1/admin/webhooks/{}/deliveries", webhook["id"]);
+    let mut log = Value::Null;
+    for _ in 0..50 {
+        (_, log) = send(&app, "GET", &deliveries, Value::Null).await;
+        if log.as_array().unwrap().len() == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(log[0]["attempt"], 2);
+    assert_eq!(log[0]["status"], 200);
+    assert_eq!(log[0]["error"], Value::Null);
+    assert_eq!(log[1]["attempt"], 1);
+    assert_eq!(log[1]["status"], 500);
+    assert_eq!(log[1]["payload"], payload);
+
+    let webhook_uri = format!("/api/v1/admin/webhooks/{}", webhook["id"]);
+    let (status, _) = send(&app, "DELETE", &webhook_uri, Value::Null).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send(&app, "GET", &deliveries, Value::Null).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_inspect_and_replay_delivery() {
+    let received = Received::default();
+    let url = receiver(received.clone()).await;
+    let state = setup_test_state().await;
+    webhooks::spawn(state.clone());
+    let app = app_router(state);
+
+    let (_, posts) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "posts" }),
+    )
+    .await;
+    let (_, webhook) = send(
+        &app,
+        "POST",
+        "/api/v1/admin/webhooks",
+        json!({ "url": url, "collection_id": posts["id"], "events": ["record.created"] }),
+    )
+    .await;
+    let records = format!("/api/v1/collections/{}/records", posts["id"]);
+    send(&app, "POST", &records, json!({ "data": { "title": "Hi" } })).await;
+
+    let deliveries = format!("/api/v1/admin/webhooks/{}/deliveries", webhook["id"]);
+    let mut log = Value::Null;
+    for _ in 0..150 {
+        (_, log) = send(&app, "GET", &deliveries, Value::Null).await;
+        if log.as_array().unwrap().len() == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(log.as_array().unwrap().len(), 2);
+
+    // The failed attempt keeps what the receiver answered.
+    let failed = format!("{}/{}", deliveries, log[1]["id"]);
+    let (status, delivery) = send(&app, "GET", &failed, Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(delivery["status"], 500);
+    assert_eq!(delivery["response"], "not now");
+    assert_eq!(delivery["payload"]["record"]["data"]["title"], "Hi");
+
+    let (status, replayed) = send(&app, "POST", &format!("{}/replay", failed), Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(replayed["replay_of"], log[1]["id"]);
+    assert_eq!(replayed["status"], 200);
+    assert_eq!(replayed["response"], "thanks");
+    assert_eq!(replayed["payload"], delivery["payload"]);
+    {
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 3);
+        assert_eq!(received[2].1, received[0].1);
+    }
+    let (_, log) = send(&app, "GET", &deliveries, Value::Null).await;
+    assert_eq!(log[0]["id"], replayed["id"]);
+
+    let (status, _) = send(&app, "GET", &format!("{}/999", deliveries), Value::Null).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(
+        &app,
+        "POST",
+        &format!(
+            "/api/v1/admin/webhooks/999/deliveries/{}/replay",
+            log[1]["id"]
+        ),
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_invalid_webhooks() {
+    let app = setup_test_app().await;
+    let (_, posts) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "posts" }),
+    )
+    .await;
+
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/admin/webhooks",
+        json!({ "url": "https://example.com/hook", "collection_id": posts["id"], "events": ["record.locked"] }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/admin/webhooks",
+        json!({ "url": "ftp://example.com", "collection_id": posts["id"], "events": ["record.created"] }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/v1/admin/webhooks",
+        json!({ "url": "https://example.com/hook", "collection_id": 999, "events": ["record.created"] }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_shutdown_flushes_deliveries() {
+    let received = Received::default();
+    let url = receiver(received.clone()).await;
+    let state = setup_test_state().await;
+    webhooks::spawn(state.clone());
+    let app = app_router(state.clone());
+
+    let (_, posts) = send(
+        &app,
+        "POST",
+        "/api/v1/collections",
+        json!({ "name": "posts" }),
+    )
+    .await;
+    send(
+        &app,
+        "POST",
+        "/api/v1/admin/webhooks",
+        json!({ "url": url, "collection_id": posts["id"], "events": ["record.created"] }),
+    )
+    .await;
+    let records = format!("/api/v1/collections/{}/records", posts["id"]);
+    for title in ["One", "Two"] {
+        send(
+            &app,
+            "POST",
+            &records,
+            json!({ "data": { "title": title } }),
+        )
+        .await;
+    }
+
+    // Both queued events are delivered before the flush returns, and the
+    // one that fails is not retried.
+    state.shutdown.trigger();
+    let started = Instant::now();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    assert!(state.shutdown.flush(deadline).await);
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert_eq!(received.lock().unwrap().len(), 2);
+}
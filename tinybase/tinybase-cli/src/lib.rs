@@ -0,0 +1,371 @@
+//! The operations behind the `tinybase` command. They work on the database
+//! directly, through [`Db`], so operators can script against a database
+//! file without a server running.
+//!
+//! Records are written the way the REST API writes them, transforms,
+//! defaults, computed fields, validation and relation checks included, but
+//! without script hooks or change events, which need a running server.
+//!
+//! [`check`] tells whether a server would start, changing nothing, so CI
+//! can catch a bad configuration or manifest before a deploy does.
+
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
+    Argon2,
+};
+use futures_util::TryStreamExt;
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::sync::Arc;
+use tinybase_api::{
+    config::Config,
+    manifest::{self, Manifest},
+};
+use tinybase_core::{
+    embedded::{self, prepare_record},
+    fake::{self, Faker},
+    pending_migrations,
+    rules::check_rule,
+    schema::CollectionSchema,
+    setup_database, stream_records,
+    validation::check_schema,
+    Admin, Collection, Db,
+};
+use tokio::sync::Mutex;
+
+type BoxResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// The shortest password `admin create` accepts, as the API does.
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Finds a collection by id or name.
+pub async fn find_collection(db: &dyn Db, key: &str) -> BoxResult<Collection> {
+    let collection = match key.parse::<i64>() {
+        Ok(id) => db.get_collection(id).await?,
+        Err(_) => db.get_collection_by_name(key).await?,
+    };
+    collection.ok_or_else(|| format!("Collection {} not found", key).into())
+}
+
+/// Every collection with its record count, by name.
+pub async fn list_collections(db: &dyn Db) -> BoxResult<Vec<(Collection, i64)>> {
+    let mut collections = db.list_collections().await?;
+    collections.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut counted = Vec::with_capacity(collections.len());
+    for collection in collections {
+        let records = db.count_records(collection.id).await?;
+        counted.push((collection, records));
+    }
+    Ok(counted)
+}
+
+/// Creates a collection, checking its name and schema like the API does.
+pub async fn create_collection(
+    db: &dyn Db,
+    name: &str,
+    schema: Option<CollectionSchema>,
+    group: Option<&str>,
+) -> BoxResult<Collection> {
+    if name.trim().is_empty() || name.parse::<i64>().is_ok() {
+        return Err(format!("'{}' is not a valid collection name", name).into());
+    }
+    if db.get_collection_by_name(name).await?.is_some() {
+        return Err(format!("A collection named '{}' already exists", name).into());
+    }
+    if let Some(schema) = &schema {
+        check_schema(schema)?;
+        for (operation, rule) in schema.rules.iter() {
+            check_rule(rule).map_err(|e| format!("Invalid {} rule: {}", operation, e))?;
+        }
+    }
+    let collection = db.create_collection(name, &schema).await?;
+    match group.map(str::trim).filter(|g| !g.is_empty()) {
+        Some(group) => db.set_collection_group(collection.id, Some(group)).await,
+        None => Ok(collection),
+    }
+}
+
+/// Writes every record of a collection to `out` as NDJSON, one
+/// `{"id", "data", "created", "updated"}` object per line, returning how many
+/// were written.
+pub async fn export_records(
+    db: Arc<dyn Db>,
+    collection: &str,
+    out: &mut impl Write,
+) -> BoxResult<usize> {
+    let collection = find_collection(db.as_ref(), collection).await?;
+    let mut records = stream_records(db, collection.id);
+    let mut count = 0;
+    while let Some(record) = records.try_next().await? {
+        let line = json!({
+            "id": record.id,
+            "data": record.data,
+            "created": record.created,
+            "updated": record.updated,
+        });
+        writeln!(out, "{}", line)?;
+        count += 1;
+    }
+    out.flush()?;
+    Ok(count)
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub created: usize,
+    /// The 1-based line numbers of the records left out, and why.
+    pub invalid: Vec<(usize, String)>,
+}
+
+/// Creates a record for each NDJSON line of `input`, as written by
+/// [`export_records`] or just `{"data": {...}}`. Records get new ids; lines
+/// that aren't valid records are left out and reported.
+pub async fn import_records(
+    db: &dyn Db,
+    collection: &str,
+    input: impl BufRead,
+) -> BoxResult<ImportReport> {
+    let collection = find_collection(db, collection).await?;
+    let mut report = ImportReport::default();
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut data = match serde_json::from_str::<Value>(&line) {
+            Ok(mut record) if record["data"].is_object() => record["data"].take(),
+            Ok(_) => {
+                report.invalid.push((
+                    index + 1,
+                    "expected an object with a `data` object".to_string(),
+                ));
+                continue;
+            }
+            Err(e) => {
+                report.invalid.push((index + 1, e.to_string()));
+                continue;
+            }
+        };
+        if let Some(schema) = &collection.schema {
+            match prepare_record(db, schema, &mut data, embedded::Write::Create).await {
+                Ok(()) => {}
+                Err(embedded::Error::Validation(errors)) => {
+                    let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+                    report.invalid.push((index + 1, errors.join("; ")));
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        db.create_record(collection.id, &data).await?;
+        report.created += 1;
+    }
+    Ok(report)
+}
+
+/// Creates `count` records of made-up data in a collection, see
+/// [`tinybase_core::fake`], returning how many were created.
+pub async fn fake_records(
+    db: &dyn Db,
+    collection: &str,
+    count: usize,
+    faker: &mut Faker,
+) -> BoxResult<usize> {
+    let collection = find_collection(db, collection).await?;
+    Ok(fake::fake_records(db, &collection, count, faker).await?)
+}
+
+/// Creates an admin who can sign in to the API and the dashboard.
+pub async fn create_admin(db: &dyn Db, email: &str, password: &str) -> BoxResult<Admin> {
+    let email = email.trim();
+    if !email.contains('@') {
+        return Err(format!("'{}' is not an email address", email).into());
+    }
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(format!(
+            "Passwords must be at least {} characters long",
+            MIN_PASSWORD_LENGTH
+        )
+        .into());
+    }
+    if db.get_admin_by_email(email).await?.is_some() {
+        return Err(format!("An admin with email '{}' already exists", email).into());
+    }
+    // Hashed like the API hashes passwords, so the admin can sign in there.
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| e.to_string())?
+        .to_string();
+    db.create_admin(email, &hash).await
+}
+
+/// How an item of [`check`] went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Ok,
+    /// Starting works, but changes something or needs a look.
+    Warning,
+    /// Starting would fail.
+    Failed,
+}
+
+impl CheckStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            CheckStatus::Ok => "ok",
+            CheckStatus::Warning => "warning",
+            CheckStatus::Failed => "failed",
+        }
+    }
+}
+
+/// An item of the report of `tinybase check`.
+#[derive(Debug)]
+pub struct Check {
+    /// What was checked: `config`, `database`, `migrations` or `manifest`.
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// Further lines, e.g. what reconciling the manifest would change.
+    pub notes: Vec<String>,
+}
+
+impl Check {
+    pub fn new(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+            notes: Vec::new(),
+        }
+    }
+}
+
+/// Checks what starting a server with `config` needs, changing nothing:
+/// that the database opens and answers, which migrations opening it would
+/// apply, and that the manifest, if any, is valid, with what reconciling it
+/// would change. A missing database file is reported, not created.
+pub async fn check(config: &Config) -> Vec<Check> {
+    let mut checks = Vec::new();
+    let path = &config.db_path;
+    let db = if !path.exists() {
+        checks.push(Check::new(
+            "database",
+            CheckStatus::Warning,
+            format!(
+                "{} does not exist; it is created at startup",
+                path.display()
+            ),
+        ));
+        None
+    } else {
+        match open_database(path).await {
+            Ok(db) => {
+                checks.push(Check::new(
+                    "database",
+                    CheckStatus::Ok,
+                    format!("{} answers", path.display()),
+                ));
+                Some(db)
+            }
+            Err(e) => {
+                checks.push(Check::new(
+                    "database",
+                    CheckStatus::Failed,
+                    format!("Cannot open {}: {}", path.display(), e),
+                ));
+                None
+            }
+        }
+    };
+
+    let mut current = None;
+    if let Some(db) = db {
+        let pending = pending_migrations(&*db.lock().await).await;
+        checks.push(match pending {
+            Ok(pending) if pending.is_empty() => {
+                current = Some(db);
+                Check::new("migrations", CheckStatus::Ok, "The database is up to date")
+            }
+            Ok(pending) => Check::new(
+                "migrations",
+                CheckStatus::Warning,
+                format!(
+                    "Starting creates or upgrades {} tables and indexes: {}",
+                    pending.len(),
+                    pending.join(", ")
+                ),
+            ),
+            Err(e) => Check::new(
+                "migrations",
+                CheckStatus::Failed,
+                format!(
+                    "Cannot compare the database with the current version: {}",
+                    e
+                ),
+            ),
+        });
+    }
+
+    if let Some(path) = &config.manifest {
+        checks.push(check_manifest(path, current).await);
+    }
+    checks
+}
+
+async fn open_database(path: &Path) -> BoxResult<Mutex<libsql::Connection>> {
+    let conn = libsql::Builder::new_local(path).build().await?.connect()?;
+    conn.query("SELECT 1", ()).await?;
+    Ok(Mutex::new(conn))
+}
+
+/// Plans reconciling the manifest at `path` against `db`, or against an
+/// empty database when there is no up-to-date one to compare with.
+async fn check_manifest(path: &Path, db: Option<Mutex<libsql::Connection>>) -> Check {
+    let manifest = match Manifest::load(path) {
+        Ok(manifest) => manifest,
+        Err(e) => return Check::new("manifest", CheckStatus::Failed, e),
+    };
+    let (db, against) = match db {
+        Some(db) => (db, "the database"),
+        None => {
+            let empty = async {
+                let conn = libsql::Builder::new_local(":memory:")
+                    .build()
+                    .await?
+                    .connect()?;
+                setup_database(&conn).await?;
+                Ok::<_, libsql::Error>(Mutex::new(conn))
+            };
+            match empty.await {
+                Ok(db) => (db, "an empty database"),
+                Err(e) => return Check::new("manifest", CheckStatus::Failed, e.to_string()),
+            }
+        }
+    };
+    match manifest::reconcile(&db, &manifest, false).await {
+        Ok(plan) => Check {
+            detail: match plan.is_empty() {
+                true => format!("{} is valid and matches {}", path.display(), against),
+                false => format!(
+                    "{} is valid; starting reconciles {} with it",
+                    path.display(),
+                    against
+                ),
+            },
+            notes: plan
+                .lines()
+                .iter()
+                .map(|line| line.trim_start().to_string())
+                .collect(),
+            ..Check::new("manifest", CheckStatus::Ok, "")
+        },
+        Err(e) => Check::new(
+            "manifest",
+            CheckStatus::Failed,
+            format!("Invalid {}: {}", path.display(), e),
+        ),
+    }
+}
@@ -0,0 +1,404 @@
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand};
+use serde_json::json;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tinybase_api::{
+    api_clients::{self, ClientFormat},
+    config::Config,
+    logging,
+    manifest::{self, Manifest},
+    server::Server,
+    sync,
+};
+use tinybase_cli::{self as cli, Check, CheckStatus};
+use tinybase_core::{
+    a_new_database_connection, fake::Faker, schema::CollectionSchema, snapshot, Db,
+};
+
+/// Runs a Tinybase server and manages its data.
+///
+/// Every command but `serve` works on the database file directly, so it
+/// needs no server running.
+#[derive(Parser)]
+#[command(name = "tinybase", version)]
+struct Cli {
+    /// The database file; the configured `db_path` by default.
+    #[arg(long, global = true)]
+    db: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Serves the API, as `tinybase-api` does.
+    Serve,
+    /// Checks the configuration, the database and the manifest the server
+    /// would start with, without starting it or changing anything. Fails
+    /// if starting would.
+    Check {
+        /// Prints the report as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Creates or upgrades the tables of the database.
+    Migrate,
+    /// Lists or creates collections.
+    #[command(subcommand)]
+    Collections(CollectionsCommand),
+    /// Imports or exports the records of a collection as NDJSON, or fills
+    /// it with fake ones.
+    #[command(subcommand)]
+    Records(RecordsCommand),
+    /// Snapshots the database into a timestamped file.
+    Backup {
+        /// Where to write the snapshot; the configured `backup_dir`, or
+        /// `backups`, by default.
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+    /// Manages admins.
+    #[command(subcommand)]
+    Admin(AdminCommand),
+    /// Writes the API, the record endpoints of every collection included,
+    /// as a Postman collection or Insomnia export for manual testing.
+    ApiClient {
+        /// `postman` or `insomnia`.
+        format: ClientFormat,
+        /// The URL the requests go to; the configured `addr` by default.
+        #[arg(long)]
+        base_url: Option<String>,
+        /// Where to write the export; stdout by default.
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Shows how the collections differ from those of another instance.
+    SyncSchema {
+        /// The URL of the instance to copy the schemas of.
+        #[arg(long)]
+        from: String,
+        /// Makes the changes instead of only listing them.
+        #[arg(long)]
+        apply: bool,
+    },
+    /// Reconciles the database against a manifest. Fails if `--check`
+    /// finds anything to change, for CI pipelines.
+    Reconcile {
+        /// The manifest; the configured `manifest` by default.
+        file: Option<PathBuf>,
+        /// Lists the changes without making them.
+        #[arg(long)]
+        check: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CollectionsCommand {
+    /// Lists the collections with their record counts.
+    List,
+    /// Creates a collection.
+    Create {
+        name: String,
+        /// A JSON file with the schema of the collection.
+        #[arg(long)]
+        schema: Option<PathBuf>,
+        #[arg(long)]
+        group: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum RecordsCommand {
+    /// Creates records from NDJSON lines, from a file or `-` for stdin.
+    Import { collection: String, file: PathBuf },
+    /// Writes every record as NDJSON, to stdout unless `--output` is given.
+    Export {
+        collection: String,
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Creates records of made-up data fitting the schema of the
+    /// collection, for load tests and demos.
+    Fake {
+        collection: String,
+        count: usize,
+        /// Fakes the same records again for the same seed; a random one by
+        /// default.
+        #[arg(long)]
+        seed: Option<u64>,
+        /// The earliest date to make up, a year ago by default.
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// The latest date to make up, today by default.
+        #[arg(long)]
+        to: Option<NaiveDate>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AdminCommand {
+    /// Creates an admin. The password is read from `--password`,
+    /// `TINYBASE_ADMIN_PASSWORD` or the first line of stdin.
+    Create {
+        email: String,
+        #[arg(long)]
+        password: Option<String>,
+    },
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    // tinybase.toml, or the file named by TINYBASE_CONFIG, overridden by
+    // TINYBASE_* variables.
+    let config = Config::load().map(|mut config| {
+        if let Some(db) = cli.db {
+            config.db_path = db;
+        }
+        config
+    });
+    if let Command::Check { json } = cli.command {
+        return check(config, json).await;
+    }
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let result = match cli.command {
+        Command::Serve => {
+            logging::init(&config);
+            match Server::open(config).await {
+                Ok(server) => server.run(&[]).await,
+                Err(e) => Err(e),
+            }
+            .map_err(Into::into)
+        }
+        command => run(command, &config).await,
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Prints the report of `tinybase check`, failing if any check failed.
+async fn check(config: Result<Config, String>, json: bool) -> ExitCode {
+    let checks = match config {
+        Ok(config) => {
+            let detail = match &config.profile {
+                Some(profile) => format!("Valid, profile {}", profile),
+                None => "Valid".to_string(),
+            };
+            let mut checks = vec![Check::new("config", CheckStatus::Ok, detail)];
+            checks.extend(cli::check(&config).await);
+            checks
+        }
+        Err(e) => vec![Check::new("config", CheckStatus::Failed, e)],
+    };
+    let ok = checks.iter().all(|c| c.status != CheckStatus::Failed);
+    if json {
+        let checks: Vec<_> = checks
+            .iter()
+            .map(|c| {
+                json!({
+                    "name": c.name,
+                    "status": c.status.name(),
+                    "detail": c.detail,
+                    "notes": c.notes,
+                })
+            })
+            .collect();
+        println!("{:#}", json!({ "ok": ok, "checks": checks }));
+    } else {
+        for c in &checks {
+            println!("{:<8} {:<11} {}", c.status.name(), c.name, c.detail);
+            for note in &c.notes {
+                println!("{:<20} {}", "", note);
+            }
+        }
+    }
+    match ok {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    }
+}
+
+async fn run(
+    command: Command,
+    config: &Config,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db_path = &config.db_path;
+    // Opening the database creates or upgrades its tables.
+    let db: Arc<dyn Db> = Arc::new(a_new_database_connection(db_path).await?);
+    match command {
+        Command::Serve | Command::Check { .. } => unreachable!("run by main"),
+        Command::Migrate => println!("{} is up to date.", db_path.display()),
+        Command::Collections(CollectionsCommand::List) => {
+            for (collection, records) in cli::list_collections(db.as_ref()).await? {
+                println!(
+                    "{:>6}  {:<24} {:<16} {:>8} records",
+                    collection.id,
+                    collection.name,
+                    collection.group.as_deref().unwrap_or("-"),
+                    records
+                );
+            }
+        }
+        Command::Collections(CollectionsCommand::Create {
+            name,
+            schema,
+            group,
+        }) => {
+            let schema = match schema {
+                Some(path) => Some(serde_json::from_reader::<_, CollectionSchema>(
+                    BufReader::new(File::open(path)?),
+                )?),
+                None => None,
+            };
+            let collection =
+                cli::create_collection(db.as_ref(), &name, schema, group.as_deref()).await?;
+            println!(
+                "Created collection {} ({}).",
+                collection.name, collection.id
+            );
+        }
+        Command::Records(RecordsCommand::Import { collection, file }) => {
+            let input: Box<dyn BufRead> = if file == Path::new("-") {
+                Box::new(io::stdin().lock())
+            } else {
+                Box::new(BufReader::new(File::open(file)?))
+            };
+            let report = cli::import_records(db.as_ref(), &collection, input).await?;
+            for (line, error) in &report.invalid {
+                eprintln!("line {}: {}", line, error);
+            }
+            println!(
+                "Created {} records, left out {}.",
+                report.created,
+                report.invalid.len()
+            );
+        }
+        Command::Records(RecordsCommand::Export { collection, output }) => {
+            let mut out: Box<dyn Write> = match output {
+                Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+                None => Box::new(io::stdout().lock()),
+            };
+            let count = cli::export_records(db, &collection, &mut out).await?;
+            eprintln!("Exported {} records.", count);
+        }
+        Command::Records(RecordsCommand::Fake {
+            collection,
+            count,
+            seed,
+            from,
+            to,
+        }) => {
+            let seed = seed.unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_nanos() as u64)
+            });
+            let faker = Faker::new(seed);
+            let (first, last) = faker.dates();
+            let mut faker = faker.with_dates(from.unwrap_or(first), to.unwrap_or(last));
+            let created = cli::fake_records(db.as_ref(), &collection, count, &mut faker).await?;
+            println!("Created {} fake records (seed {}).", created, seed);
+        }
+        Command::Backup { dir } => {
+            let dir = dir
+                .or_else(|| config.backup_dir.clone())
+                .unwrap_or_else(|| PathBuf::from("backups"));
+            let snapshot = snapshot::take_snapshot(db.as_ref(), &dir).await?;
+            println!(
+                "Wrote {} ({} bytes).",
+                dir.join(&snapshot.name).display(),
+                snapshot.size
+            );
+        }
+        Command::Admin(AdminCommand::Create { email, password }) => {
+            let password = match password.or_else(|| std::env::var("TINYBASE_ADMIN_PASSWORD").ok())
+            {
+                Some(password) => password,
+                None => {
+                    eprint!("Password: ");
+                    let mut line = String::new();
+                    io::stdin().read_line(&mut line)?;
+                    line.trim_end_matches(['\r', '\n']).to_string()
+                }
+            };
+            let admin = cli::create_admin(db.as_ref(), &email, &password).await?;
+            println!("Created admin {} ({}).", admin.email, admin.id);
+        }
+        Command::ApiClient {
+            format,
+            base_url,
+            output,
+        } => {
+            let base_url = base_url.unwrap_or_else(|| {
+                format!("http://{}", config.addr.replace("0.0.0.0", "localhost"))
+            });
+            let export = api_clients::export(db.as_ref(), format, &base_url).await?;
+            let mut out: Box<dyn Write> = match output {
+                Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+                None => Box::new(io::stdout().lock()),
+            };
+            serde_json::to_writer_pretty(&mut out, &export)?;
+            writeln!(out)?;
+        }
+        Command::SyncSchema { from, apply } => {
+            let remote = sync::fetch_remote_collections(&from)
+                .await
+                .map_err(|e| format!("Failed to fetch collections from {}: {}", from, e))?;
+            let plan = sync::plan_sync(db.as_ref(), &remote).await?;
+            for (label, names) in [
+                ("create", &plan.create),
+                ("update", &plan.update),
+                ("unchanged", &plan.unchanged),
+                ("local only", &plan.local_only),
+            ] {
+                for name in names {
+                    println!("{:>10}  {}", label, name);
+                }
+            }
+            if plan.is_empty() {
+                println!("Schemas are already in sync.");
+            } else if !apply {
+                println!("Dry run; re-run with --apply to make these changes.");
+            } else {
+                sync::apply_sync(db.as_ref(), &remote, &plan).await?;
+                println!("Applied.");
+            }
+        }
+        Command::Reconcile { file, check } => {
+            let Some(path) = file.or_else(|| config.manifest.clone()) else {
+                return Err("Name a manifest file or configure `manifest`".into());
+            };
+            let manifest = Manifest::load(&path)?;
+            let plan = manifest::reconcile(db.as_ref(), &manifest, !check)
+                .await
+                .map_err(|e| format!("Failed to reconcile {}: {}", path.display(), e))?;
+            for line in plan.lines() {
+                println!("{}", line);
+            }
+            if plan.is_empty() {
+                println!("The database matches {}.", path.display());
+            } else if check {
+                return Err("Drift found; run without --check to apply.".into());
+            } else {
+                println!("Applied.");
+            }
+        }
+    }
+    Ok(())
+}
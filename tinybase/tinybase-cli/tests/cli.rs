@@ -0,0 +1,317 @@
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tinybase_api::config::Config;
+use tinybase_cli::{
+    check, create_admin, create_collection, export_records, fake_records, import_records,
+    list_collections, CheckStatus,
+};
+use tinybase_core::{
+    a_new_database_connection, fake::Faker, schema::CollectionSchema, setup_database, Db,
+    ListOptions,
+};
+use tokio::sync::Mutex;
+
+async fn setup_db() -> Arc<dyn Db> {
+    let db = libsql::Builder::new_local(":memory:")
+        .build()
+        .await
+        .unwrap();
+    let conn = db.connect().unwrap();
+    setup_database(&conn).await.unwrap();
+    Arc::new(Mutex::new(conn))
+}
+
+fn schema(value: Value) -> CollectionSchema {
+    serde_json::from_value(value).unwrap()
+}
+
+#[tokio::test]
+async fn test_collections_and_records() {
+    let db = setup_db().await;
+    let users = create_collection(db.as_ref(), "users", None, None)
+        .await
+        .unwrap();
+    let posts = schema(json!({
+        "fields": {
+            "title": { "type": "string", "required": true, "max_length": 20 },
+            "status": { "type": "string", "required": false, "default": "draft" },
+            "author": { "type": { "relation": { "collection_id": users.id } }, "required": false }
+        }
+    }));
+    let posts = create_collection(db.as_ref(), "posts", Some(posts), Some("blog"))
+        .await
+        .unwrap();
+    assert_eq!(posts.group.as_deref(), Some("blog"));
+    for (name, schema) in [
+        ("posts", None),
+        ("42", None),
+        (
+            "drafts",
+            Some(self::schema(
+                json!({ "fields": {}, "rules": { "list": "status ==" } }),
+            )),
+        ),
+    ] {
+        assert!(create_collection(db.as_ref(), name, schema, None)
+            .await
+            .is_err());
+    }
+
+    let input = [
+        r#"{"data": {"name": "Ada"}}"#,
+        "",
+        r#"{"id": 7, "data": {"name": "Grace"}, "created": "", "updated": ""}"#,
+    ]
+    .join("\n");
+    let report = import_records(db.as_ref(), "users", input.as_bytes())
+        .await
+        .unwrap();
+    assert_eq!(report.created, 2);
+    assert!(report.invalid.is_empty());
+
+    let input = [
+        r#"{"data": {"title": "Hello", "author": 1}}"#,
+        r#"{"data": {"title": "A title far too long to be valid"}}"#,
+        r#"{"data": {"title": "Orphan", "author": 99}}"#,
+        r#"{"title": "No data"}"#,
+        "not json",
+    ]
+    .join("\n");
+    let report = import_records(db.as_ref(), &posts.id.to_string(), input.as_bytes())
+        .await
+        .unwrap();
+    assert_eq!(report.created, 1);
+    let lines: Vec<usize> = report.invalid.iter().map(|(line, _)| *line).collect();
+    assert_eq!(lines, [2, 3, 4, 5]);
+    assert!(report.invalid[1].1.contains("Related record 99"));
+
+    let mut out = Vec::new();
+    assert_eq!(
+        export_records(db.clone(), "posts", &mut out).await.unwrap(),
+        1
+    );
+    let exported: Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(
+        exported["data"],
+        json!({ "title": "Hello", "status": "draft", "author": 1 })
+    );
+
+    // An export imports back, as new records.
+    let report = import_records(db.as_ref(), "posts", out.as_slice())
+        .await
+        .unwrap();
+    assert_eq!(report.created, 1);
+    let counts: Vec<(String, i64)> = list_collections(db.as_ref())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(c, records)| (c.name, records))
+        .collect();
+    assert_eq!(counts, [("posts".to_string(), 2), ("users".to_string(), 2)]);
+    assert!(import_records(db.as_ref(), "comments", &b""[..])
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_create_admin() {
+    let db = setup_db().await;
+    let admin = create_admin(db.as_ref(), " ada@example.com ", "correct horse")
+        .await
+        .unwrap();
+    assert_eq!(admin.email, "ada@example.com");
+    let hash = PasswordHash::new(&admin.password_hash).unwrap();
+    assert!(Argon2::default()
+        .verify_password(b"correct horse", &hash)
+        .is_ok());
+
+    for (email, password) in [
+        ("ada@example.com", "correct horse"),
+        ("grace", "correct horse"),
+        ("grace@example.com", "short"),
+    ] {
+        assert!(create_admin(db.as_ref(), email, password).await.is_err());
+    }
+    assert_eq!(db.count_admins().await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_fake_records() {
+    let db = setup_db().await;
+    let users = schema(json!({
+        "fields": {
+            "name": { "type": "string", "required": true },
+            "email": { "type": "email", "required": true },
+            "joined": { "type": "date", "required": true }
+        }
+    }));
+    let users = create_collection(db.as_ref(), "users", Some(users), None)
+        .await
+        .unwrap();
+    let posts = schema(json!({
+        "fields": {
+            "title": { "type": "string", "required": true, "max_length": 20 },
+            "score": { "type": "number", "required": true, "min": 1, "max": 5 },
+            "published": { "type": "datetime", "required": false },
+            "author": { "type": { "relation": { "collection_id": users.id } }, "required": true }
+        }
+    }));
+    create_collection(db.as_ref(), "posts", Some(posts.clone()), None)
+        .await
+        .unwrap();
+
+    // Posts need authors to relate to.
+    let mut faker = Faker::new(7);
+    assert!(fake_records(db.as_ref(), "posts", 10, &mut faker)
+        .await
+        .is_err());
+
+    let from = "2024-01-01".parse().unwrap();
+    let to = "2024-01-31".parse().unwrap();
+    let mut faker = Faker::new(7).with_dates(from, to);
+    assert_eq!(
+        fake_records(db.as_ref(), "users", 20, &mut faker)
+            .await
+            .unwrap(),
+        20
+    );
+    // More than a batch.
+    let mut faker = Faker::new(7).with_dates(from, to);
+    assert_eq!(
+        fake_records(db.as_ref(), "posts", 600, &mut faker)
+            .await
+            .unwrap(),
+        600
+    );
+
+    let users = db
+        .list_records(users.id, &ListOptions::default())
+        .await
+        .unwrap();
+    let ids: Vec<i64> = users.iter().map(|user| user.id).collect();
+    for user in &users {
+        assert!(user.data["name"].as_str().unwrap().contains(' '));
+        let joined = user.data["joined"].as_str().unwrap();
+        assert!(
+            ("2024-01-01"..="2024-01-31").contains(&joined),
+            "{}",
+            joined
+        );
+    }
+    let posts_id = db
+        .get_collection_by_name("posts")
+        .await
+        .unwrap()
+        .unwrap()
+        .id;
+    let posts_list = db
+        .list_records(posts_id, &ListOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(posts_list.len(), 600);
+    for post in &posts_list {
+        assert!(ids.contains(&post.data["author"].as_i64().unwrap()));
+        let score = post.data["score"].as_i64().unwrap();
+        assert!((1..=5).contains(&score));
+    }
+
+    // The same seed fakes the same data.
+    let (mut a, mut b) = (Faker::new(42), Faker::new(42));
+    a.relate_to(1, ids.clone());
+    b.relate_to(1, ids);
+    assert_eq!(a.record(&posts), b.record(&posts));
+}
+
+#[tokio::test]
+async fn test_check() {
+    let dir = std::env::temp_dir().join(format!("tinybase-cli-check-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let manifest = dir.join("collections.toml");
+    std::fs::write(
+        &manifest,
+        r#"
+            [[collections]]
+            name = "posts"
+            schema.fields.title = { type = "string", required = true }
+        "#,
+    )
+    .unwrap();
+    let mut config = Config {
+        db_path: dir.join("data.db"),
+        manifest: Some(manifest.clone()),
+        ..Config::default()
+    };
+    let statuses = |checks: &[tinybase_cli::Check]| {
+        checks
+            .iter()
+            .map(|c| (c.name, c.status))
+            .collect::<Vec<_>>()
+    };
+
+    // A missing database is left for the server to create.
+    let checks = check(&config).await;
+    assert_eq!(
+        statuses(&checks),
+        [
+            ("database", CheckStatus::Warning),
+            ("manifest", CheckStatus::Ok)
+        ]
+    );
+    assert!(!config.db_path.exists());
+    assert!(checks[1].notes.iter().any(|n| n.contains("create  posts")));
+
+    // So is upgrading an old one.
+    let old = libsql::Builder::new_local(&config.db_path)
+        .build()
+        .await
+        .unwrap();
+    old.connect()
+        .unwrap()
+        .execute(
+            "CREATE TABLE collections (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL, schema JSON)",
+            (),
+        )
+        .await
+        .unwrap();
+    let checks = check(&config).await;
+    assert_eq!(
+        statuses(&checks),
+        [
+            ("database", CheckStatus::Ok),
+            ("migrations", CheckStatus::Warning),
+            ("manifest", CheckStatus::Ok)
+        ]
+    );
+    assert!(checks[1].detail.contains("records"));
+    std::fs::remove_file(&config.db_path).unwrap();
+
+    let db = a_new_database_connection(&config.db_path).await.unwrap();
+    create_collection(&db, "posts", None, None).await.unwrap();
+    let checks = check(&config).await;
+    assert_eq!(
+        statuses(&checks),
+        [
+            ("database", CheckStatus::Ok),
+            ("migrations", CheckStatus::Ok),
+            ("manifest", CheckStatus::Ok)
+        ]
+    );
+    assert!(checks[2].notes.iter().any(|n| n.contains("update  posts")));
+
+    std::fs::write(
+        &manifest,
+        r#"
+            [[collections]]
+            name = "posts"
+            schema.fields.author = { type = { relation = { collection = "users" } }, required = true }
+        "#,
+    )
+    .unwrap();
+    let checks = check(&config).await;
+    assert_eq!(checks[2].status, CheckStatus::Failed);
+    assert!(checks[2].detail.contains("users"), "{}", checks[2].detail);
+    config.manifest = Some(dir.join("missing.toml"));
+    assert_eq!(check(&config).await[2].status, CheckStatus::Failed);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
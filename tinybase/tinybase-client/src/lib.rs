@@ -0,0 +1,254 @@
+//! A typed client for the Tinybase REST API.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), tinybase_client::Error> {
+//! use serde::{Deserialize, Serialize};
+//! use tinybase_client::Client;
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Post {
+//!     title: String,
+//! }
+//!
+//! let client = Client::new("http://localhost:8000");
+//! client.authenticate("admin@example.com", "secret").await?;
+//! let posts = client.records::<Post>("posts");
+//! let post = posts.create(&Post { title: "Hello".into() }).await?;
+//! println!("{} {}", post.id, post.data.title);
+//! # Ok(())
+//! # }
+//! ```
+
+mod records;
+mod subscription;
+
+use reqwest::{Method, RequestBuilder, Response};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use std::sync::{Arc, RwLock};
+use tinybase_api::version::{check_compatibility, Compatibility, API_VERSION};
+use tinybase_core::schema::CollectionSchema;
+
+pub use records::{ListParams, Record, Records};
+pub use subscription::Subscription;
+pub use tinybase_core::events::{Event, EventAction};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    /// The server answered with an error status and a problem detail.
+    #[error("{status} {error}: {message}")]
+    Api {
+        status: u16,
+        error: String,
+        message: String,
+        details: Option<Value>,
+    },
+    #[error("unexpected response: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// The body of error responses.
+#[derive(Deserialize)]
+struct ProblemDetail {
+    error: String,
+    message: String,
+    #[serde(default)]
+    details: Option<Value>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Collection {
+    pub id: i64,
+    pub name: String,
+    pub schema: Option<CollectionSchema>,
+    pub created: String,
+    pub updated: String,
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Admin {
+    pub id: i64,
+    pub email: String,
+    pub created: String,
+    pub updated: String,
+}
+
+/// The API key an admin signed in with.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Session {
+    pub key: String,
+    /// Seconds until the key expires.
+    pub expires_in: u64,
+    pub admin: Admin,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct VersionInfo {
+    pub version: String,
+    pub api_version: String,
+    pub git_hash: String,
+    pub build_time: String,
+}
+
+/// A connection to one Tinybase instance. Clones share the token.
+#[derive(Clone)]
+pub struct Client {
+    base_url: String,
+    http: reqwest::Client,
+    token: Arc<RwLock<Option<String>>>,
+}
+
+impl Client {
+    /// A client for the instance at `base_url`, e.g. `http://localhost:8000`.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let base_url = base_url.into().trim_end_matches('/').to_string();
+        Client {
+            base_url,
+            http: reqwest::Client::new(),
+            token: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// A client sending `token` as its bearer token, an API key or a token
+    /// of the identity provider.
+    pub fn with_token(base_url: impl Into<String>, token: impl Into<String>) -> Self {
+        let client = Self::new(base_url);
+        client.set_token(Some(token.into()));
+        client
+    }
+
+    pub fn set_token(&self, token: Option<String>) {
+        *self.token.write().unwrap() = token;
+    }
+
+    pub fn token(&self) -> Option<String> {
+        self.token.read().unwrap().clone()
+    }
+
+    /// Signs in as an admin and sends the API key issued with every later
+    /// request.
+    pub async fn authenticate(&self, email: &str, password: &str) -> Result<Session> {
+        let session: Session = self
+            .send_json(
+                self.request(Method::POST, "/api/v1/admin/auth")
+                    .json(&json!({ "email": email, "password": password })),
+            )
+            .await?;
+        self.set_token(Some(session.key.clone()));
+        Ok(session)
+    }
+
+    pub async fn version(&self) -> Result<VersionInfo> {
+        self.send_json(self.request(Method::GET, "/api/v1/version"))
+            .await
+    }
+
+    /// How the API version of the server fits the one this client was built
+    /// for; show [`Compatibility::warning`] to users.
+    pub async fn check_version(&self) -> Result<Compatibility> {
+        let info = self.version().await?;
+        Ok(
+            check_compatibility(API_VERSION, &info.api_version).unwrap_or(
+                Compatibility::Incompatible {
+                    client: API_VERSION.to_string(),
+                    server: info.api_version,
+                },
+            ),
+        )
+    }
+
+    pub async fn list_collections(&self) -> Result<Vec<Collection>> {
+        self.send_json(self.request(Method::GET, "/api/v1/collections"))
+            .await
+    }
+
+    /// A collection by id or name.
+    pub async fn get_collection(&self, key: &str) -> Result<Collection> {
+        self.send_json(self.request(Method::GET, &format!("/api/v1/collections/{}", key)))
+            .await
+    }
+
+    pub async fn create_collection(
+        &self,
+        name: &str,
+        schema: Option<CollectionSchema>,
+    ) -> Result<Collection> {
+        self.send_json(
+            self.request(Method::POST, "/api/v1/collections")
+                .json(&json!({ "name": name, "schema": schema })),
+        )
+        .await
+    }
+
+    /// Deletes a collection; `force` also deletes its records, without which
+    /// a collection that has any is kept.
+    pub async fn delete_collection(&self, key: &str, force: bool) -> Result<()> {
+        self.send(
+            self.request(Method::DELETE, &format!("/api/v1/collections/{}", key))
+                .query(&[("force", force)]),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// The records of a collection, by id or name, as `T`.
+    pub fn records<T>(&self, collection: &str) -> Records<T> {
+        Records::new(self.clone(), collection)
+    }
+
+    /// Follows the activity of the instance as it happens.
+    pub async fn subscribe(&self) -> Result<Subscription> {
+        let response = self
+            .send(
+                self.request(Method::GET, "/api/v1/admin/activity")
+                    .header("accept", "text/event-stream"),
+            )
+            .await?;
+        Ok(Subscription::new(response, None))
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self
+            .http
+            .request(method, format!("{}{}", self.base_url, path));
+        match self.token() {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// Sends a request, turning error statuses into [`Error::Api`].
+    async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.bytes().await?;
+        Err(match serde_json::from_slice::<ProblemDetail>(&body) {
+            Ok(problem) => Error::Api {
+                status: status.as_u16(),
+                error: problem.error,
+                message: problem.message,
+                details: problem.details,
+            },
+            Err(_) => Error::Api {
+                status: status.as_u16(),
+                error: status.canonical_reason().unwrap_or("Error").to_string(),
+                message: String::from_utf8_lossy(&body).into_owned(),
+                details: None,
+            },
+        })
+    }
+
+    async fn send_json<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T> {
+        let body = self.send(request).await?.bytes().await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+}
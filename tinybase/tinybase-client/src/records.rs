@@ -0,0 +1,123 @@
+use reqwest::Method;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+use std::marker::PhantomData;
+
+use crate::{Client, Result, Subscription};
+
+/// A record whose data is a `T`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Record<T> {
+    pub id: i64,
+    pub data: T,
+    pub created: String,
+    pub updated: String,
+}
+
+#[derive(Serialize, Default, Debug, Clone)]
+pub struct ListParams {
+    /// Pages the list when set, as does `per_page`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub per_page: Option<u32>,
+    /// `id`, `created`, `updated` or `position`, descending with a leading `-`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+}
+
+/// List responses come bare or in the envelope, depending on the format the
+/// instance is set to.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ListBody<T> {
+    Bare(Vec<Record<T>>),
+    Envelope { items: Vec<Record<T>> },
+}
+
+/// The records of one collection, from [`Client::records`].
+pub struct Records<T> {
+    client: Client,
+    path: String,
+    collection: String,
+    data: PhantomData<fn() -> T>,
+}
+
+impl<T> Records<T> {
+    pub(crate) fn new(client: Client, collection: &str) -> Self {
+        Records {
+            client,
+            path: format!("/api/v1/collections/{}/records", collection),
+            collection: collection.to_string(),
+            data: PhantomData,
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Records<T> {
+    pub async fn list(&self, params: &ListParams) -> Result<Vec<Record<T>>> {
+        let body: ListBody<T> = self
+            .client
+            .send_json(self.client.request(Method::GET, &self.path).query(params))
+            .await?;
+        Ok(match body {
+            ListBody::Bare(items) | ListBody::Envelope { items } => items,
+        })
+    }
+
+    pub async fn get(&self, id: i64) -> Result<Record<T>> {
+        self.client
+            .send_json(self.client.request(Method::GET, &self.record_path(id)))
+            .await
+    }
+
+    pub async fn create(&self, data: &T) -> Result<Record<T>> {
+        self.client
+            .send_json(
+                self.client
+                    .request(Method::POST, &self.path)
+                    .json(&json!({ "data": data })),
+            )
+            .await
+    }
+
+    /// Replaces the data of a record.
+    pub async fn replace(&self, id: i64, data: &T) -> Result<Record<T>> {
+        self.client
+            .send_json(
+                self.client
+                    .request(Method::PUT, &self.record_path(id))
+                    .json(&json!({ "data": data })),
+            )
+            .await
+    }
+
+    /// Merges `patch` into the data of a record; `null` fields are removed.
+    pub async fn update(&self, id: i64, patch: &impl Serialize) -> Result<Record<T>> {
+        self.client
+            .send_json(
+                self.client
+                    .request(Method::PATCH, &self.record_path(id))
+                    .json(&json!({ "data": patch })),
+            )
+            .await
+    }
+
+    pub async fn delete(&self, id: i64) -> Result<()> {
+        self.client
+            .send(self.client.request(Method::DELETE, &self.record_path(id)))
+            .await?;
+        Ok(())
+    }
+
+    /// Follows the changes to the records of the collection.
+    pub async fn subscribe(&self) -> Result<Subscription> {
+        let collection = self.client.get_collection(&self.collection).await?;
+        let subscription = self.client.subscribe().await?;
+        Ok(subscription.only(collection.id))
+    }
+
+    fn record_path(&self, id: i64) -> String {
+        format!("{}/{}", self.path, id)
+    }
+}
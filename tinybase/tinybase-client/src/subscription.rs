@@ -0,0 +1,72 @@
+use reqwest::Response;
+
+use crate::{Event, Result};
+
+/// The activity feed of an instance, read from its server-sent events.
+pub struct Subscription {
+    response: Response,
+    buffer: Vec<u8>,
+    collection_id: Option<i64>,
+}
+
+impl Subscription {
+    pub(crate) fn new(response: Response, collection_id: Option<i64>) -> Self {
+        Subscription {
+            response,
+            buffer: Vec::new(),
+            collection_id,
+        }
+    }
+
+    /// Keeps only the events of one collection.
+    pub(crate) fn only(self, collection_id: i64) -> Self {
+        Subscription {
+            collection_id: Some(collection_id),
+            ..self
+        }
+    }
+
+    /// The next event, or `None` once the server closes the stream.
+    pub async fn next(&mut self) -> Option<Result<Event>> {
+        loop {
+            while let Some(end) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+                let message: Vec<u8> = self.buffer.drain(..end + 2).collect();
+                match parse_message(&String::from_utf8_lossy(&message)) {
+                    Some(Ok(event)) if self.wants(&event) => return Some(Ok(event)),
+                    Some(Err(e)) => return Some(Err(e.into())),
+                    _ => {}
+                }
+            }
+            match self.response.chunk().await {
+                Ok(Some(chunk)) => self
+                    .buffer
+                    .extend(chunk.iter().filter(|&&byte| byte != b'\r')),
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+    }
+
+    fn wants(&self, event: &Event) -> bool {
+        self.collection_id
+            .is_none_or(|id| id == event.collection_id)
+    }
+}
+
+/// The event in one message of the stream; `None` for keep-alive comments
+/// and other events.
+fn parse_message(message: &str) -> Option<serde_json::Result<Event>> {
+    let mut name = None;
+    let mut data = Vec::new();
+    for line in message.lines() {
+        if let Some(value) = line.strip_prefix("event:") {
+            name = Some(value.trim());
+        } else if let Some(value) = line.strip_prefix("data:") {
+            data.push(value.strip_prefix(' ').unwrap_or(value));
+        }
+    }
+    if name != Some("activity") || data.is_empty() {
+        return None;
+    }
+    Some(serde_json::from_str(&data.join("\n")))
+}
@@ -0,0 +1,168 @@
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use tinybase_api::{app_router, fixtures::in_memory_state};
+use tinybase_client::{Client, Error, EventAction, ListParams};
+use tokio::net::TcpListener;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Post {
+    title: String,
+    views: i64,
+}
+
+/// Serves a fresh instance on a free port, returning its base URL.
+async fn serve() -> String {
+    let state = in_memory_state(std::env::temp_dir().join("tinybase-client-uploads"))
+        .await
+        .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(
+            listener,
+            app_router(state).into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+        .unwrap();
+    });
+    format!("http://{}/", addr)
+}
+
+fn schema() -> tinybase_core::schema::CollectionSchema {
+    serde_json::from_value(serde_json::json!({
+        "fields": {
+            "title": { "type": "string", "required": true },
+            "views": { "type": "number", "required": false }
+        }
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_typed_records() {
+    let client = Client::new(serve().await);
+    assert!(client.check_version().await.unwrap().warning().is_none());
+
+    let collection = client
+        .create_collection("posts", Some(schema()))
+        .await
+        .unwrap();
+    assert_eq!(collection.name, "posts");
+    let names: Vec<String> = client
+        .list_collections()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|c| c.name)
+        .collect();
+    assert_eq!(names, ["posts"]);
+
+    let posts = client.records::<Post>("posts");
+    let hello = Post {
+        title: "Hello".to_string(),
+        views: 1,
+    };
+    let created = posts.create(&hello).await.unwrap();
+    assert_eq!(created.data, hello);
+    assert_eq!(posts.get(created.id).await.unwrap().data, hello);
+
+    let updated = posts
+        .update(created.id, &serde_json::json!({ "views": 2 }))
+        .await
+        .unwrap();
+    assert_eq!(updated.data.views, 2);
+    posts
+        .create(&Post {
+            title: "Second".to_string(),
+            views: 0,
+        })
+        .await
+        .unwrap();
+    let params = ListParams {
+        sort: Some("-id".to_string()),
+        ..Default::default()
+    };
+    let titles: Vec<String> = posts
+        .list(&params)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.data.title)
+        .collect();
+    assert_eq!(titles, ["Second", "Hello"]);
+
+    posts.delete(created.id).await.unwrap();
+    match posts.get(created.id).await {
+        Err(Error::Api { status, .. }) => assert_eq!(status, 404),
+        other => panic!("expected a 404, got {:?}", other.map(|r| r.id)),
+    }
+    // Records missing a required field fail validation.
+    let result = client
+        .records::<serde_json::Value>("posts")
+        .create(&serde_json::json!({ "views": 3 }))
+        .await;
+    match result {
+        Err(Error::Api { status, .. }) => assert_eq!(status, 422),
+        other => panic!("expected a 422, got {:?}", other.map(|r| r.id)),
+    }
+}
+
+#[tokio::test]
+async fn test_subscribe_to_records() {
+    let client = Client::new(serve().await);
+    client
+        .create_collection("posts", Some(schema()))
+        .await
+        .unwrap();
+    client.create_collection("other", None).await.unwrap();
+    let posts = client.records::<Post>("posts");
+    let mut subscription = posts.subscribe().await.unwrap();
+
+    client
+        .records::<serde_json::Value>("other")
+        .create(&serde_json::json!({}))
+        .await
+        .unwrap();
+    let created = posts
+        .create(&Post {
+            title: "Hello".to_string(),
+            views: 0,
+        })
+        .await
+        .unwrap();
+    let event = subscription.next().await.unwrap().unwrap();
+    assert_eq!(event.action, EventAction::RecordCreated);
+    assert_eq!(event.record_id, Some(created.id));
+}
+
+#[tokio::test]
+async fn test_authenticate() {
+    let url = serve().await;
+    let client = Client::new(url.clone());
+    // The first admin is created in setup mode, after which admin endpoints
+    // take a key.
+    let admin = serde_json::json!({ "email": "admin@example.com", "password": "correct horse" });
+    reqwest::Client::new()
+        .post(format!("{}api/v1/admin/admins", url))
+        .json(&admin)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    match client.create_collection("posts", None).await {
+        Err(Error::Api { status, .. }) => assert_eq!(status, 401),
+        other => panic!("expected a 401, got {:?}", other.map(|c| c.id)),
+    }
+
+    let session = client
+        .authenticate("admin@example.com", "correct horse")
+        .await
+        .unwrap();
+    assert_eq!(session.admin.email, "admin@example.com");
+    assert_eq!(client.token(), Some(session.key));
+    client.create_collection("posts", None).await.unwrap();
+
+    let other = Client::with_token(url, "not a key");
+    assert!(other.create_collection("other", None).await.is_err());
+}
@@ -0,0 +1,85 @@
+//! Compares the throughput of the `Db` implementations under concurrent
+//! requests: `cargo run --release -p tinybase-core --example pool_bench`.
+//!
+//! Each request reads a record and lists a page of records, as a typical
+//! API call does; every tenth also updates a record.
+
+use serde_json::json;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tinybase_core::{a_new_database_connection, pool::ConnectionPool, Db, ListOptions};
+use tokio::sync::Mutex;
+
+const RECORDS: i64 = 1000;
+const CLIENTS: usize = 32;
+const RUN_FOR: Duration = Duration::from_secs(3);
+
+async fn request(db: &dyn Db, collection_id: i64, n: i64) {
+    let id = n % RECORDS + 1;
+    db.get_record(collection_id, id).await.unwrap();
+    let page = ListOptions {
+        limit: Some(30),
+        offset: (n % 30) as u64 * 30,
+        ..Default::default()
+    };
+    db.list_records(collection_id, &page).await.unwrap();
+    if n % 10 == 0 {
+        db.update_record(collection_id, id, &json!({ "n": n }))
+            .await
+            .unwrap();
+    }
+}
+
+/// Requests per second `db` answers for [`CLIENTS`] concurrent clients.
+async fn throughput(db: Arc<dyn Db>, collection_id: i64) -> f64 {
+    let start = Instant::now();
+    let clients: Vec<_> = (0..CLIENTS)
+        .map(|client| {
+            let db = db.clone();
+            tokio::spawn(async move {
+                let mut done = 0;
+                while start.elapsed() < RUN_FOR {
+                    request(db.as_ref(), collection_id, (client * 7919 + done) as i64).await;
+                    done += 1;
+                }
+                done
+            })
+        })
+        .collect();
+    let mut total = 0;
+    for client in clients {
+        total += client.await.unwrap();
+    }
+    total as f64 / start.elapsed().as_secs_f64()
+}
+
+#[tokio::main]
+async fn main() {
+    let path = std::env::temp_dir().join(format!("tinybase-bench-{}.db", std::process::id()));
+    let db = Arc::new(a_new_database_connection(&path).await.unwrap());
+    let collection = db.create_collection("bench", &None).await.unwrap();
+    for n in 0..RECORDS {
+        db.create_record(collection.id, &json!({ "n": n }))
+            .await
+            .unwrap();
+    }
+
+    let mutex: Arc<dyn Db> = Arc::new(Mutex::new(db.connect().unwrap()));
+    println!(
+        "Mutex<Connection>:     {:>8.0} requests/s",
+        throughput(mutex, collection.id).await
+    );
+    println!(
+        "Database:              {:>8.0} requests/s",
+        throughput(db.clone(), collection.id).await
+    );
+    for size in [4, 8, 16] {
+        let pool: Arc<dyn Db> = Arc::new(ConnectionPool::new(db.clone(), size).await.unwrap());
+        println!(
+            "ConnectionPool({:>2}):    {:>8.0} requests/s",
+            size,
+            throughput(pool, collection.id).await
+        );
+    }
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,73 @@
+//! Summaries of the records of a collection computed by the database, e.g.
+//! the number of orders and their total per status, so clients don't have to
+//! load every record for a dashboard number.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// What an [`Aggregation`] computes over the values of a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateFunction {
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl AggregateFunction {
+    pub const ALL: [AggregateFunction; 4] = [Self::Sum, Self::Avg, Self::Min, Self::Max];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sum => "sum",
+            Self::Avg => "avg",
+            Self::Min => "min",
+            Self::Max => "max",
+        }
+    }
+
+    /// Whether the function only makes sense over numbers.
+    pub fn numeric(self) -> bool {
+        matches!(self, Self::Sum | Self::Avg)
+    }
+
+    /// The SQL computing the function over `column`.
+    pub(crate) fn sql(self, column: &str) -> String {
+        match self {
+            // A sum over no numbers is 0, not NULL.
+            Self::Sum => format!("COALESCE(SUM({}), 0)", column),
+            Self::Avg => format!("AVG({})", column),
+            Self::Min => format!("MIN({})", column),
+            Self::Max => format!("MAX({})", column),
+        }
+    }
+}
+
+/// A function applied to a top-level field of the record data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregation {
+    pub function: AggregateFunction,
+    pub field: String,
+}
+
+/// The aggregations to compute, per group of records sharing the values of
+/// the `group_by` fields, or over all records when there are none.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AggregateQuery {
+    pub aggregations: Vec<Aggregation>,
+    pub group_by: Vec<String>,
+}
+
+/// The aggregations over one group of records.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AggregateGroup {
+    /// The values of the `group_by` fields shared by the group; a missing
+    /// field is `null`.
+    pub group: Map<String, Value>,
+    /// Number of records in the group.
+    pub count: i64,
+    /// The results by function and field, e.g. `{"sum": {"price": 12.5}}`;
+    /// `null` when no record of the group has the field.
+    #[serde(flatten)]
+    pub values: Map<String, Value>,
+}
@@ -0,0 +1,17 @@
+//! In-place changes to array fields of records, done with SQLite's JSON
+//! functions in a single update so concurrent writers adding tags to the same
+//! record don't overwrite each other's lists.
+
+use serde::{Deserialize, Serialize};
+
+/// What [`Db::update_array`](crate::Db::update_array) does with the value.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArrayOp {
+    /// Appends the value to the end of the array.
+    Push,
+    /// Removes every element equal to the value.
+    Remove,
+    /// Appends the value unless the array already holds an equal element.
+    AddUnique,
+}
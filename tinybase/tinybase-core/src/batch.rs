@@ -0,0 +1,30 @@
+use crate::Record;
+use serde_json::Value;
+use thiserror::Error;
+
+/// A single write in a [`Db::batch`](crate::Db::batch) call.
+#[derive(Debug, Clone)]
+pub enum BatchOperation {
+    Create { data: Value },
+    Update { id: i64, data: Value },
+    Delete { id: i64 },
+}
+
+/// The outcome of a [`BatchOperation`], in the order the operations were given.
+#[derive(Debug)]
+pub enum BatchResult {
+    Created(Record),
+    Updated(Record),
+    /// The record as it was before deletion.
+    Deleted(Record),
+}
+
+/// Why a batch was rolled back. The index is the position of the failing
+/// operation.
+#[derive(Error, Debug)]
+pub enum BatchError {
+    #[error("Operation {0}: record {1} not found")]
+    RecordNotFound(usize, i64),
+    #[error("Operation {0}: {1}")]
+    Failed(usize, Box<dyn std::error::Error + Send + Sync>),
+}
@@ -0,0 +1,106 @@
+//! Checksums of stored records, to catch data that changed on disk without
+//! being written, e.g. through a faulty drive or a copy gone wrong.
+//!
+//! Every write stores the CRC-32 of a record's `data`, as stored, in its
+//! `checksum` column, and reads compare it with the data they get back.
+//! [`with_checksum_mode`] sets what a mismatch does for the reads a future
+//! runs, the way [`timeouts::with_query_timeout`](crate::timeouts::with_query_timeout)
+//! sets their time limit; outside any mode mismatches are logged.
+//!
+//! [`Db::scrub_records`](crate::Db::scrub_records) checks every record in
+//! batches, listing the mismatches it finds for
+//! [`Db::list_checksum_mismatches`](crate::Db::list_checksum_mismatches),
+//! and gives rows written before checksums were kept one.
+
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+
+tokio::task_local! {
+    static CHECKSUM_MODE: ChecksumMode;
+}
+
+/// What reading a record whose data doesn't match its checksum does.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumMode {
+    /// Checksums are stored but not checked.
+    Off,
+    /// The record is read as it is and the mismatch logged as an error.
+    #[default]
+    Log,
+    /// The read fails with [`ChecksumMismatch`].
+    Enforce,
+}
+
+/// Runs `future` with its record reads checking checksums in `mode`.
+pub async fn with_checksum_mode<F: Future>(mode: ChecksumMode, future: F) -> F::Output {
+    CHECKSUM_MODE.scope(mode, future).await
+}
+
+/// The mode in scope.
+pub fn mode() -> ChecksumMode {
+    CHECKSUM_MODE.try_with(|mode| *mode).unwrap_or_default()
+}
+
+/// The checksum of `data` as stored.
+pub fn checksum(data: &str) -> i64 {
+    crc32fast::hash(data.as_bytes()).into()
+}
+
+/// A record whose data doesn't match its checksum.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error(
+    "Record {record_id} doesn't match its checksum: stored {stored:08x}, computed {computed:08x}"
+)]
+pub struct ChecksumMismatch {
+    pub record_id: i64,
+    pub stored: i64,
+    pub computed: i64,
+}
+
+/// Checks `data`, stored as record `record_id` with `stored`, in the mode in
+/// scope. Rows without a checksum pass.
+pub fn verify(record_id: i64, stored: Option<i64>, data: &str) -> Result<(), ChecksumMismatch> {
+    let Some(stored) = stored else {
+        return Ok(());
+    };
+    let mode = mode();
+    if mode == ChecksumMode::Off {
+        return Ok(());
+    }
+    let computed = checksum(data);
+    if computed == stored {
+        return Ok(());
+    }
+    let mismatch = ChecksumMismatch {
+        record_id,
+        stored,
+        computed,
+    };
+    if mode == ChecksumMode::Enforce {
+        return Err(mismatch);
+    }
+    tracing::error!(record = record_id, %mismatch, "record checksum mismatch");
+    Ok(())
+}
+
+/// A record a scrub found not matching its checksum.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MismatchReport {
+    pub collection_id: i64,
+    pub mismatch: ChecksumMismatch,
+    /// RFC 3339 UTC timestamp of the scrub that found it.
+    pub detected: String,
+}
+
+/// What one batch of a scrub did.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScrubBatch {
+    /// The last record checked; the next batch starts after it. `None` once
+    /// there are no records left.
+    pub last_id: Option<i64>,
+    pub checked: u64,
+    /// Rows without a checksum that were given one.
+    pub sealed: u64,
+    pub mismatches: u64,
+}
@@ -0,0 +1,473 @@
+//! Computed fields: fields whose value is derived from the other fields of a
+//! record by an expression declared in the schema, e.g. `price * qty` or
+//! `slugify(title)`.
+//!
+//! Expressions combine numbers, strings, `true`, `false`, `null` and field
+//! names (dot-separated to reach into `json` fields) with `+ - * / %` and
+//! parentheses. `+` adds numbers and joins text. Functions are called by
+//! name: `slugify`, `lower`, `upper`, `trim`, `length`, `concat`,
+//! `coalesce`, `round`, `floor`, `ceil` and `abs`. Operations on missing or
+//! mismatched values give `null`, and a field computed as `null` is left out
+//! of the record.
+
+use crate::schema::CollectionSchema;
+use serde_json::{Number, Value};
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Errors raised while parsing an expression or checking a schema's.
+#[derive(Error, Debug, PartialEq)]
+pub enum ComputeError {
+    #[error("Unexpected character '{0}' at position {1}")]
+    UnexpectedCharacter(char, usize),
+    #[error("Unterminated string literal starting at position {0}")]
+    UnterminatedString(usize),
+    #[error("Unexpected token '{0}'")]
+    UnexpectedToken(String),
+    #[error("Unexpected end of expression")]
+    UnexpectedEnd,
+    #[error("Unknown function '{0}'")]
+    UnknownFunction(String),
+    #[error("Unknown field '{0}'")]
+    UnknownField(String),
+    #[error("Computed field '{0}' depends on itself")]
+    Cycle(String),
+}
+
+const FUNCTIONS: [&str; 11] = [
+    "slugify", "lower", "upper", "trim", "length", "concat", "coalesce", "round", "floor", "ceil",
+    "abs",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Str(String),
+    Num(Number),
+    Ident(String),
+    Op(char),
+    Comma,
+    LParen,
+    RParen,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Str(s) => write!(f, "\"{}\"", s),
+            Token::Num(n) => write!(f, "{}", n),
+            Token::Ident(i) => write!(f, "{}", i),
+            Token::Op(op) => write!(f, "{}", op),
+            Token::Comma => write!(f, ","),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Literal(Value),
+    Field(String),
+    Negate(Box<Expr>),
+    Binary(Box<Expr>, char, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, ComputeError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                i += 1;
+            }
+            '+' | '-' | '*' | '/' | '%' => {
+                tokens.push(Token::Op(c));
+                i += 1;
+            }
+            '"' | '\'' => {
+                let start = i;
+                i += 1;
+                let mut s = String::new();
+                loop {
+                    match chars.get(i) {
+                        None => return Err(ComputeError::UnterminatedString(start)),
+                        Some('\\') if chars.get(i + 1).is_some() => {
+                            s.push(chars[i + 1]);
+                            i += 2;
+                        }
+                        Some(&q) if q == c => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&other) => {
+                            s.push(other);
+                            i += 1;
+                        }
+                    }
+                }
+                tokens.push(Token::Str(s));
+            }
+            c if c.is_ascii_digit() => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let number = match text.parse::<i64>() {
+                    Ok(n) => Some(Number::from(n)),
+                    Err(_) => text.parse::<f64>().ok().and_then(Number::from_f64),
+                };
+                tokens.push(Token::Num(
+                    number.ok_or(ComputeError::UnexpectedToken(text))?,
+                ));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | '.'))
+                {
+                    i += 1;
+                }
+                tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            }
+            other => return Err(ComputeError::UnexpectedCharacter(other, i)),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ComputeError> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(ComputeError::UnexpectedToken(token.to_string())),
+            None => Err(ComputeError::UnexpectedEnd),
+        }
+    }
+
+    fn parse_sum(&mut self) -> Result<Expr, ComputeError> {
+        let mut left = self.parse_product()?;
+        while let Some(Token::Op(op @ ('+' | '-'))) = self.peek() {
+            let op = *op;
+            self.next();
+            let right = self.parse_product()?;
+            left = Expr::Binary(Box::new(left), op, Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_product(&mut self) -> Result<Expr, ComputeError> {
+        let mut left = self.parse_unary()?;
+        while let Some(Token::Op(op @ ('*' | '/' | '%'))) = self.peek() {
+            let op = *op;
+            self.next();
+            let right = self.parse_unary()?;
+            left = Expr::Binary(Box::new(left), op, Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ComputeError> {
+        if self.peek() == Some(&Token::Op('-')) {
+            self.next();
+            return Ok(Expr::Negate(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ComputeError> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(Expr::Literal(Value::String(s))),
+            Some(Token::Num(n)) => Ok(Expr::Literal(Value::Number(n))),
+            Some(Token::LParen) => {
+                let expr = self.parse_sum()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::Ident(ident)) if self.peek() == Some(&Token::LParen) => {
+                if !FUNCTIONS.contains(&ident.as_str()) {
+                    return Err(ComputeError::UnknownFunction(ident));
+                }
+                self.next();
+                let mut args = Vec::new();
+                if self.peek() != Some(&Token::RParen) {
+                    args.push(self.parse_sum()?);
+                    while self.peek() == Some(&Token::Comma) {
+                        self.next();
+                        args.push(self.parse_sum()?);
+                    }
+                }
+                self.expect(Token::RParen)?;
+                Ok(Expr::Call(ident, args))
+            }
+            Some(Token::Ident(ident)) => Ok(match ident.as_str() {
+                "true" => Expr::Literal(Value::Bool(true)),
+                "false" => Expr::Literal(Value::Bool(false)),
+                "null" => Expr::Literal(Value::Null),
+                _ => Expr::Field(ident),
+            }),
+            Some(token) => Err(ComputeError::UnexpectedToken(token.to_string())),
+            None => Err(ComputeError::UnexpectedEnd),
+        }
+    }
+}
+
+/// A parsed compute expression.
+#[derive(Debug, Clone)]
+pub struct Expression(Expr);
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, ComputeError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            pos: 0,
+        };
+        let expr = parser.parse_sum()?;
+        match parser.next() {
+            None => Ok(Self(expr)),
+            Some(token) => Err(ComputeError::UnexpectedToken(token.to_string())),
+        }
+    }
+
+    /// The top-level fields the expression reads.
+    pub fn fields(&self) -> Vec<&str> {
+        fn walk<'a>(expr: &'a Expr, fields: &mut Vec<&'a str>) {
+            match expr {
+                Expr::Literal(_) => {}
+                Expr::Field(path) => fields.push(path.split('.').next().unwrap_or(path)),
+                Expr::Negate(inner) => walk(inner, fields),
+                Expr::Binary(left, _, right) => {
+                    walk(left, fields);
+                    walk(right, fields);
+                }
+                Expr::Call(_, args) => args.iter().for_each(|arg| walk(arg, fields)),
+            }
+        }
+        let mut fields = Vec::new();
+        walk(&self.0, &mut fields);
+        fields
+    }
+
+    /// The value of the expression for the record `data`.
+    pub fn evaluate(&self, data: &Value) -> Value {
+        eval(&self.0, data)
+    }
+}
+
+fn lookup<'a>(root: &'a Value, path: &str) -> &'a Value {
+    path.split('.').fold(root, |value, key| match value {
+        Value::Object(map) => map.get(key).unwrap_or(&Value::Null),
+        Value::Array(items) => key
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| items.get(i))
+            .unwrap_or(&Value::Null),
+        _ => &Value::Null,
+    })
+}
+
+/// `value` as text, for joining.
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+fn number(n: f64) -> Value {
+    Number::from_f64(n).map_or(Value::Null, Value::Number)
+}
+
+fn arithmetic(left: &Value, op: char, right: &Value) -> Value {
+    if op == '+' && (left.is_string() || right.is_string()) {
+        return match (text(left), text(right)) {
+            (Some(l), Some(r)) => Value::String(l + &r),
+            _ => Value::Null,
+        };
+    }
+    if let (Some(l), Some(r), true) = (left.as_i64(), right.as_i64(), op != '/') {
+        let result = match op {
+            '+' => l.checked_add(r),
+            '-' => l.checked_sub(r),
+            '*' => l.checked_mul(r),
+            _ => l.checked_rem(r),
+        };
+        return result.map_or(Value::Null, Value::from);
+    }
+    let (Some(l), Some(r)) = (left.as_f64(), right.as_f64()) else {
+        return Value::Null;
+    };
+    match op {
+        '+' => number(l + r),
+        '-' => number(l - r),
+        '*' => number(l * r),
+        '/' if r != 0.0 => number(l / r),
+        '%' if r != 0.0 => number(l % r),
+        _ => Value::Null,
+    }
+}
+
+fn slugify(s: &str) -> String {
+    let mut slug = String::with_capacity(s.len());
+    for c in s.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+fn call(name: &str, args: &[Value]) -> Value {
+    let first = args.first().unwrap_or(&Value::Null);
+    let string = |f: fn(&str) -> String| first.as_str().map_or(Value::Null, |s| f(s).into());
+    let rounded = |f: fn(f64) -> f64| first.as_f64().map_or(Value::Null, |n| number(f(n)));
+    match name {
+        "slugify" => string(slugify),
+        "lower" => string(str::to_lowercase),
+        "upper" => string(str::to_uppercase),
+        "trim" => string(|s| s.trim().to_string()),
+        "length" => match first {
+            Value::String(s) => s.chars().count().into(),
+            Value::Array(items) => items.len().into(),
+            _ => Value::Null,
+        },
+        "concat" => Value::String(args.iter().filter_map(text).collect()),
+        "coalesce" => args
+            .iter()
+            .find(|arg| !arg.is_null())
+            .cloned()
+            .unwrap_or(Value::Null),
+        "round" => {
+            let digits = args.get(1).and_then(Value::as_i64).unwrap_or(0) as i32;
+            let scale = 10f64.powi(digits);
+            match (first.as_f64(), digits) {
+                (Some(n), 0) => number(n.round()),
+                (Some(n), _) => number((n * scale).round() / scale),
+                _ => Value::Null,
+            }
+        }
+        "floor" => rounded(f64::floor),
+        "ceil" => rounded(f64::ceil),
+        "abs" => rounded(f64::abs),
+        _ => Value::Null,
+    }
+}
+
+fn eval(expr: &Expr, data: &Value) -> Value {
+    match expr {
+        Expr::Literal(value) => value.clone(),
+        Expr::Field(path) => lookup(data, path).clone(),
+        Expr::Negate(inner) => arithmetic(&Value::from(0), '-', &eval(inner, data)),
+        Expr::Binary(left, op, right) => arithmetic(&eval(left, data), *op, &eval(right, data)),
+        Expr::Call(name, args) => {
+            let args: Vec<Value> = args.iter().map(|arg| eval(arg, data)).collect();
+            call(name, &args)
+        }
+    }
+}
+
+/// The computed fields of `schema` with their expressions, ordered so each
+/// comes after the computed fields it reads.
+fn computed_fields(schema: &CollectionSchema) -> Result<Vec<(&str, Expression)>, ComputeError> {
+    let mut pending: HashMap<&str, Expression> = HashMap::new();
+    for (name, field) in &schema.fields {
+        if let Some(source) = &field.compute {
+            pending.insert(name, Expression::parse(source)?);
+        }
+    }
+    let mut ordered = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let ready: Vec<&str> = pending
+            .iter()
+            .filter(|(_, expr)| expr.fields().iter().all(|f| !pending.contains_key(f)))
+            .map(|(name, _)| *name)
+            .collect();
+        if ready.is_empty() {
+            let mut stuck: Vec<&&str> = pending.keys().collect();
+            stuck.sort();
+            return Err(ComputeError::Cycle(stuck[0].to_string()));
+        }
+        for name in ready {
+            let expr = pending.remove(name).expect("ready fields are pending");
+            ordered.push((name, expr));
+        }
+    }
+    Ok(ordered)
+}
+
+/// Checks that the compute expressions of `schema` parse, only read fields
+/// of the schema and don't depend on themselves.
+pub fn check_computed(schema: &CollectionSchema) -> Result<(), ComputeError> {
+    for (_, expr) in computed_fields(schema)? {
+        if let Some(field) = expr
+            .fields()
+            .into_iter()
+            .find(|f| !schema.fields.contains_key(*f))
+        {
+            return Err(ComputeError::UnknownField(field.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Whether `field` is computed, or read by the expression of a computed
+/// field of `schema`.
+pub fn affects_computed(schema: &CollectionSchema, field: &str) -> bool {
+    schema.fields.iter().any(|(name, definition)| {
+        definition.compute.as_deref().is_some_and(|source| {
+            name == field
+                || Expression::parse(source).is_ok_and(|expr| expr.fields().contains(&field))
+        })
+    })
+}
+
+/// Sets every computed field of `schema` in `data`, replacing any value
+/// given for it.
+pub fn apply_computed(schema: &CollectionSchema, data: &mut Value) {
+    // Expressions are checked when the schema is saved, see `check_computed`.
+    let Ok(fields) = computed_fields(schema) else {
+        return;
+    };
+    for (name, expr) in fields {
+        let value = expr.evaluate(data);
+        let Some(map) = data.as_object_mut() else {
+            return;
+        };
+        if value.is_null() {
+            map.remove(name);
+        } else {
+            map.insert(name.to_string(), value);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! Tinybase as a library: the store without the HTTP server, for Rust apps
+//! that embed it in-process.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), tinybase_core::embedded::Error> {
+//! use serde_json::json;
+//! use tinybase_core::Tinybase;
+//!
+//! let tinybase = Tinybase::open("data.db").await?;
+//! tinybase.create_collection("posts", None).await?;
+//! let post = tinybase
+//!     .collection("posts")
+//!     .create(json!({ "title": "Hello" }))
+//!     .await?;
+//! println!("created post {}", post.id);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Writes go through [`prepare_record`], as those of the HTTP API do, so
+//! transforms, defaults, computed fields, validation and relation checks are
+//! the same either way, and changes are published on [`Tinybase::events`].
+//! Lua script hooks are run by the server only.
+
+use serde_json::Value;
+use std::path::Path;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::events::{Event, EventAction, EventBus};
+use crate::patch::merge_patch;
+use crate::relations::{check_relations, delete_record_cascade};
+use crate::schema::CollectionSchema;
+use crate::validation::{check_collection_name, check_schema, validate_record, ValidationError};
+use crate::{a_new_database_connection, Collection, Db, ListOptions, Record};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0} not found")]
+    NotFound(String),
+    #[error("Invalid schema: {0}")]
+    InvalidSchema(String),
+    /// The data doesn't fit the collection's schema, or the name of a new
+    /// collection breaks the naming rules.
+    #[error("Invalid record: {0:?}")]
+    Validation(Vec<ValidationError>),
+    #[error(transparent)]
+    Database(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl From<Box<dyn std::error::Error + Send + Sync>> for Error {
+    fn from(e: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        Error::Database(e)
+    }
+}
+
+impl From<libsql::Error> for Error {
+    fn from(e: libsql::Error) -> Self {
+        Error::Database(Box::new(e))
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Which write [`prepare_record`] prepares data for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Write {
+    Create,
+    Update,
+}
+
+/// Readies `data` to be written to a collection with `schema`: runs its
+/// transforms, fills in defaults when creating, sets computed fields, then
+/// validates the result and checks its relations.
+pub async fn prepare_record(
+    db: &dyn Db,
+    schema: &CollectionSchema,
+    data: &mut Value,
+    write: Write,
+) -> Result<()> {
+    schema.apply_transforms(data);
+    if write == Write::Create {
+        schema.apply_defaults(data);
+    }
+    schema.apply_computed(data);
+    validate_record(schema, data).map_err(Error::Validation)?;
+    let errors = check_relations(db, schema, data).await?;
+    if !errors.is_empty() {
+        return Err(Error::Validation(errors));
+    }
+    Ok(())
+}
+
+/// An embedded Tinybase store.
+#[derive(Clone)]
+pub struct Tinybase {
+    db: Arc<dyn Db>,
+    events: EventBus,
+}
+
+impl Tinybase {
+    /// Opens the database file at `path`, creating it if needed.
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let db = a_new_database_connection(path).await?;
+        Ok(Self::from_db(Arc::new(db)))
+    }
+
+    /// A store over `db`, e.g. the one an HTTP server shares.
+    pub fn from_db(db: Arc<dyn Db>) -> Self {
+        Tinybase {
+            db,
+            events: EventBus::new(),
+        }
+    }
+
+    /// Publishes the changes made through this store on `events` rather
+    /// than a bus of its own, e.g. the server's.
+    pub fn with_events(self, events: EventBus) -> Self {
+        Tinybase { events, ..self }
+    }
+
+    pub fn db(&self) -> &Arc<dyn Db> {
+        &self.db
+    }
+
+    /// The changes made through this store.
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    pub async fn create_collection(
+        &self,
+        name: &str,
+        schema: Option<CollectionSchema>,
+    ) -> Result<Collection> {
+        check_collection_name(name).map_err(|e| Error::Validation(vec![e]))?;
+        if let Some(schema) = &schema {
+            check_schema(schema).map_err(Error::InvalidSchema)?;
+        }
+        let collection = self.db.create_collection(name, &schema).await?;
+        self.publish(EventAction::CollectionCreated, collection.id, None);
+        Ok(collection)
+    }
+
+    pub async fn collections(&self) -> Result<Vec<Collection>> {
+        Ok(self.db.list_collections().await?)
+    }
+
+    /// The records of the collection with name or id `name`.
+    pub fn collection(&self, name: &str) -> Records {
+        Records {
+            tinybase: self.clone(),
+            name: name.to_string(),
+        }
+    }
+
+    fn publish(&self, action: EventAction, collection_id: i64, record_id: Option<i64>) {
+        self.events
+            .publish(Event::new(action, collection_id, record_id));
+    }
+}
+
+/// The records of one collection, from [`Tinybase::collection`].
+pub struct Records {
+    tinybase: Tinybase,
+    name: String,
+}
+
+impl Records {
+    async fn resolve(&self) -> Result<Collection> {
+        let db = &self.tinybase.db;
+        let collection = match self.name.parse::<i64>() {
+            Ok(id) => db.get_collection(id).await?,
+            Err(_) => db.get_collection_by_name(&self.name).await?,
+        };
+        collection.ok_or_else(|| Error::NotFound(format!("Collection {}", self.name)))
+    }
+
+    pub async fn create(&self, mut data: Value) -> Result<Record> {
+        let collection = self.resolve().await?;
+        let db = self.tinybase.db.as_ref();
+        if let Some(schema) = &collection.schema {
+            prepare_record(db, schema, &mut data, Write::Create).await?;
+        }
+        let record = db.create_record(collection.id, &data).await?;
+        self.tinybase
+            .publish(EventAction::RecordCreated, collection.id, Some(record.id));
+        Ok(record)
+    }
+
+    pub async fn get(&self, id: i64) -> Result<Option<Record>> {
+        let collection = self.resolve().await?;
+        Ok(self.tinybase.db.get_record(collection.id, id).await?)
+    }
+
+    pub async fn list(&self, options: &ListOptions) -> Result<Vec<Record>> {
+        let collection = self.resolve().await?;
+        Ok(self
+            .tinybase
+            .db
+            .list_records(collection.id, options)
+            .await?)
+    }
+
+    /// Replaces the data of record `id`.
+    pub async fn replace(&self, id: i64, data: Value) -> Result<Record> {
+        self.write(id, |_| data).await
+    }
+
+    /// Applies `patch` to the data of record `id` as a JSON Merge Patch:
+    /// given fields are set, `null` removes a field.
+    pub async fn update(&self, id: i64, patch: Value) -> Result<Record> {
+        self.write(id, |mut data| {
+            merge_patch(&mut data, &patch);
+            data
+        })
+        .await
+    }
+
+    async fn write(&self, id: i64, change: impl FnOnce(Value) -> Value) -> Result<Record> {
+        let collection = self.resolve().await?;
+        let db = self.tinybase.db.as_ref();
+        let current = db
+            .get_record(collection.id, id)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("Record {}", id)))?;
+        let mut data = change(current.data);
+        if let Some(schema) = &collection.schema {
+            prepare_record(db, schema, &mut data, Write::Update).await?;
+        }
+        let record = db
+            .update_record(collection.id, id, &data)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("Record {}", id)))?;
+        self.tinybase
+            .publish(EventAction::RecordUpdated, collection.id, Some(id));
+        Ok(record)
+    }
+
+    /// Deletes record `id` and the records cascading from it, returning
+    /// whether it existed.
+    pub async fn delete(&self, id: i64) -> Result<bool> {
+        let collection = self.resolve().await?;
+        let removed = delete_record_cascade(self.tinybase.db.as_ref(), collection.id, id).await?;
+        for (collection_id, record) in &removed {
+            self.tinybase
+                .publish(EventAction::RecordDeleted, *collection_id, Some(record.id));
+        }
+        Ok(!removed.is_empty())
+    }
+}
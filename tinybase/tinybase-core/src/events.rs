@@ -0,0 +1,155 @@
+use crate::clock;
+use serde::{Deserialize, Serialize};
+use std::time::UNIX_EPOCH;
+use tokio::sync::broadcast;
+
+/// Number of events a slow subscriber may fall behind before it starts
+/// missing entries.
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum EventAction {
+    #[serde(rename = "collection.created")]
+    CollectionCreated,
+    #[serde(rename = "collection.updated")]
+    CollectionUpdated,
+    #[serde(rename = "collection.deleted")]
+    CollectionDeleted,
+    #[serde(rename = "record.created")]
+    RecordCreated,
+    #[serde(rename = "record.updated")]
+    RecordUpdated,
+    #[serde(rename = "record.deleted")]
+    RecordDeleted,
+    #[serde(rename = "record.locked")]
+    RecordLocked,
+    #[serde(rename = "record.unlocked")]
+    RecordUnlocked,
+    #[serde(rename = "admin.authenticated")]
+    AdminAuthenticated,
+}
+
+impl EventAction {
+    /// The dotted name the action is serialized as, e.g. `record.created`.
+    pub fn name(self) -> &'static str {
+        match self {
+            EventAction::CollectionCreated => "collection.created",
+            EventAction::CollectionUpdated => "collection.updated",
+            EventAction::CollectionDeleted => "collection.deleted",
+            EventAction::RecordCreated => "record.created",
+            EventAction::RecordUpdated => "record.updated",
+            EventAction::RecordDeleted => "record.deleted",
+            EventAction::RecordLocked => "record.locked",
+            EventAction::RecordUnlocked => "record.unlocked",
+            EventAction::AdminAuthenticated => "admin.authenticated",
+        }
+    }
+}
+
+/// A change that happened in the instance, as seen by activity feeds and
+/// other subscribers.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Event {
+    pub action: EventAction,
+    /// 0 for events about an admin rather than a collection.
+    pub collection_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record_id: Option<i64>,
+    /// The admin an `admin.*` event is about.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_id: Option<i64>,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+fn now_millis() -> u64 {
+    clock::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+impl Event {
+    pub fn new(action: EventAction, collection_id: i64, record_id: Option<i64>) -> Self {
+        Self {
+            action,
+            collection_id,
+            record_id,
+            admin_id: None,
+            timestamp: now_millis(),
+        }
+    }
+
+    /// An event about an admin account, such as a sign-in.
+    pub fn admin(action: EventAction, admin_id: i64) -> Self {
+        Self {
+            admin_id: Some(admin_id),
+            ..Self::new(action, 0, None)
+        }
+    }
+
+    /// The collection the event concerns; `None` for events about admins.
+    pub fn collection(&self) -> Option<i64> {
+        Some(self.collection_id).filter(|_| self.admin_id.is_none())
+    }
+}
+
+/// An operational problem admins should hear about, such as a backup that
+/// no longer restores.
+#[derive(Serialize, Clone, Debug)]
+pub struct Alert {
+    /// Dotted name, e.g. `backup.failed`.
+    pub name: &'static str,
+    pub message: String,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl Alert {
+    pub fn new(name: &'static str, message: String) -> Self {
+        Self {
+            name,
+            message,
+            timestamp: now_millis(),
+        }
+    }
+}
+
+/// In-process fan-out of `Event`s and `Alert`s to any number of subscribers.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+    alerts: broadcast::Sender<Alert>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        let (alerts, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        Self { sender, alerts }
+    }
+
+    /// Publishes an event; it is dropped when nobody is subscribed.
+    pub fn publish(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    /// Raises an alert; it is dropped when nobody is subscribed.
+    pub fn raise(&self, alert: Alert) {
+        let _ = self.alerts.send(alert);
+    }
+
+    pub fn subscribe_alerts(&self) -> broadcast::Receiver<Alert> {
+        self.alerts.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,377 @@
+//! Fake records for load tests and demos, made up from a collection's
+//! schema: names for name-like string fields, words and sentences for other
+//! text, addresses for emails, dates within a range, numbers within the
+//! field's bounds, and relations to records that exist.
+//!
+//! Records are prepared with [`prepare_record`] like any other write, so
+//! transforms, defaults and computed fields apply and invalid ones are never
+//! written. Fields with a `pattern` are filled from their `example`, as no
+//! faker can guess what a pattern wants.
+
+use chrono::{Duration, NaiveDate, NaiveTime, Utc};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+use crate::batch::{BatchOperation, BatchResult};
+use crate::embedded::{prepare_record, Error, Result, Write};
+use crate::schema::{CollectionSchema, FieldDefinition, FieldType};
+use crate::{Collection, Db, ListOptions};
+
+/// Records written per [`Db::batch`] call.
+const BATCH_SIZE: usize = 500;
+
+/// Ids of a related collection loaded to pick relations from.
+const RELATION_SAMPLE: u64 = 1000;
+
+const FIRST_NAMES: &[&str] = &[
+    "Ada", "Alan", "Amara", "Ben", "Chen", "Dana", "Elif", "Emma", "Farah", "Grace", "Hugo",
+    "Ines", "Ivan", "Jonas", "Kofi", "Lena", "Liam", "Maya", "Mateo", "Nadia", "Noah", "Olga",
+    "Omar", "Priya", "Quinn", "Rosa", "Sami", "Sofia", "Tariq", "Uma", "Victor", "Wanjiru", "Yuki",
+    "Zoe",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "Abebe", "Becker", "Costa", "Dubois", "Erikson", "Fischer", "Garcia", "Haddad", "Ito",
+    "Jensen", "Kamau", "Kowalski", "Lopez", "Moreau", "Nakamura", "Novak", "Okafor", "Patel",
+    "Quispe", "Rossi", "Santos", "Schmidt", "Silva", "Tanaka", "Usman", "Varga", "Wang",
+    "Williams", "Yilmaz", "Zhang",
+];
+
+const CITIES: &[&str] = &[
+    "Accra", "Berlin", "Bogota", "Cairo", "Dublin", "Hanoi", "Lagos", "Lima", "Lisbon", "Nairobi",
+    "Osaka", "Oslo", "Paris", "Quito", "Seoul", "Sydney", "Toronto", "Vienna",
+];
+
+const COUNTRIES: &[&str] = &[
+    "Brazil", "Canada", "Egypt", "France", "Germany", "Ghana", "India", "Japan", "Kenya", "Mexico",
+    "Nigeria", "Norway", "Peru", "Portugal", "Vietnam",
+];
+
+const WORDS: &[&str] = &[
+    "amber", "anchor", "autumn", "beacon", "bright", "cedar", "cloud", "coral", "crystal", "delta",
+    "ember", "field", "forest", "garden", "harbor", "island", "lantern", "maple", "meadow",
+    "mellow", "morning", "nimble", "ocean", "orbit", "pebble", "quiet", "river", "rocket",
+    "silver", "spark", "stone", "summer", "swift", "timber", "valley", "velvet", "willow",
+    "winter",
+];
+
+/// Makes up values for schema fields from a seeded generator, so the same
+/// seed fakes the same records.
+pub struct Faker {
+    state: u64,
+    dates: (NaiveDate, NaiveDate),
+    /// Record ids to relate to, by collection id.
+    relations: HashMap<i64, Vec<i64>>,
+}
+
+impl Faker {
+    /// A faker seeded with `seed`, making up dates within the last year.
+    pub fn new(seed: u64) -> Self {
+        let today = Utc::now().date_naive();
+        Faker {
+            state: seed,
+            dates: (today - Duration::days(365), today),
+            relations: HashMap::new(),
+        }
+    }
+
+    /// Makes up dates and date-times between `from` and `to`, both included.
+    pub fn with_dates(self, from: NaiveDate, to: NaiveDate) -> Self {
+        Faker {
+            dates: (from.min(to), from.max(to)),
+            ..self
+        }
+    }
+
+    /// The first and last dates made up.
+    pub fn dates(&self) -> (NaiveDate, NaiveDate) {
+        self.dates
+    }
+
+    /// Lets relations to collection `collection_id` pick from `ids`.
+    pub fn relate_to(&mut self, collection_id: i64, ids: impl IntoIterator<Item = i64>) {
+        self.relations.entry(collection_id).or_default().extend(ids);
+    }
+
+    /// SplitMix64.
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `low..=high`.
+    fn between(&mut self, low: i64, high: i64) -> i64 {
+        let span = high.abs_diff(low).wrapping_add(1);
+        match span {
+            0 => self.next() as i64,
+            span => low.wrapping_add((self.next() % span) as i64),
+        }
+    }
+
+    fn chance(&mut self, numerator: u64, denominator: u64) -> bool {
+        self.next() % denominator < numerator
+    }
+
+    fn pick(&mut self, items: &[&'static str]) -> &'static str {
+        items[(self.next() % items.len() as u64) as usize]
+    }
+
+    fn words(&mut self, count: usize) -> String {
+        let words: Vec<&str> = (0..count).map(|_| self.pick(WORDS)).collect();
+        words.join(" ")
+    }
+
+    fn sentence(&mut self) -> String {
+        let count = self.between(4, 10) as usize;
+        let mut sentence = self.words(count);
+        sentence[..1].make_ascii_uppercase();
+        sentence.push('.');
+        sentence
+    }
+
+    fn full_name(&mut self) -> String {
+        format!("{} {}", self.pick(FIRST_NAMES), self.pick(LAST_NAMES))
+    }
+
+    fn email(&mut self) -> String {
+        format!(
+            "{}.{}{}@example.com",
+            self.pick(FIRST_NAMES).to_lowercase(),
+            self.pick(LAST_NAMES).to_lowercase(),
+            self.between(1, 999)
+        )
+    }
+
+    fn url(&mut self) -> String {
+        format!(
+            "https://example.com/{}-{}",
+            self.pick(WORDS),
+            self.pick(WORDS)
+        )
+    }
+
+    fn date(&mut self) -> NaiveDate {
+        let (from, to) = self.dates;
+        from + Duration::days(self.between(0, (to - from).num_days()))
+    }
+
+    /// Text for a string field, guessed from its name.
+    fn string_for(&mut self, name: &str) -> String {
+        let name = name.to_lowercase();
+        let has = |parts: &[&str]| parts.iter().any(|part| name.contains(part));
+        if has(&["email"]) {
+            self.email()
+        } else if has(&["first"]) {
+            self.pick(FIRST_NAMES).to_string()
+        } else if has(&["last", "surname"]) {
+            self.pick(LAST_NAMES).to_string()
+        } else if has(&["name", "author", "owner", "user"]) {
+            self.full_name()
+        } else if has(&["city"]) {
+            self.pick(CITIES).to_string()
+        } else if has(&["country"]) {
+            self.pick(COUNTRIES).to_string()
+        } else if has(&["phone"]) {
+            format!("+1555{:07}", self.between(0, 9_999_999))
+        } else if has(&["url", "website", "link"]) {
+            self.url()
+        } else if has(&["title", "subject", "summary"]) {
+            let count = self.between(2, 5) as usize;
+            let mut title = self.words(count);
+            title[..1].make_ascii_uppercase();
+            title
+        } else {
+            let count = self.between(1, 3) as usize;
+            self.words(count)
+        }
+    }
+
+    /// Pads or cuts `text` to the length bounds of `field`.
+    fn fit_length(&mut self, field: &FieldDefinition, mut text: String) -> String {
+        if let Some(min) = field.min_length {
+            while text.chars().count() < min {
+                text.push(' ');
+                text.push_str(self.pick(WORDS));
+            }
+        }
+        if let Some(max) = field.max_length {
+            text = text
+                .chars()
+                .take(max)
+                .collect::<String>()
+                .trim_end()
+                .to_string();
+        }
+        text
+    }
+
+    fn number(&mut self, field: &FieldDefinition) -> Value {
+        let min = field.min.unwrap_or(0.0);
+        let max = field.max.unwrap_or(min.max(0.0) + 1000.0);
+        if min.fract() == 0.0 && max.fract() == 0.0 {
+            json!(self.between(min as i64, max as i64))
+        } else {
+            let ratio = (self.next() >> 11) as f64 / (1u64 << 53) as f64;
+            let value = ((min + (max - min) * ratio) * 100.0).round() / 100.0;
+            json!(value.clamp(min, max))
+        }
+    }
+
+    /// A value for the field `name`, or `None` to leave it out.
+    pub fn value(&mut self, name: &str, field: &FieldDefinition) -> Option<Value> {
+        if field.compute.is_some() {
+            return None;
+        }
+        if !field.required && !self.chance(3, 4) {
+            return None;
+        }
+        if field.pattern.is_some() {
+            return field.example.clone();
+        }
+        let value = match &field.r#type {
+            FieldType::String => {
+                let text = self.string_for(name);
+                json!(self.fit_length(field, text))
+            }
+            FieldType::Text => {
+                let count = self.between(1, 4);
+                let text: Vec<String> = (0..count).map(|_| self.sentence()).collect();
+                json!(self.fit_length(field, text.join(" ")))
+            }
+            FieldType::Number => self.number(field),
+            FieldType::Boolean => json!(self.chance(1, 2)),
+            FieldType::Email => json!(self.email()),
+            FieldType::Url => json!(self.url()),
+            FieldType::Date => json!(self.date().format("%Y-%m-%d").to_string()),
+            FieldType::DateTime => {
+                let time = NaiveTime::from_num_seconds_from_midnight_opt(
+                    self.between(0, 86_399) as u32,
+                    0,
+                )?;
+                json!(self
+                    .date()
+                    .and_time(time)
+                    .format("%Y-%m-%dT%H:%M:%SZ")
+                    .to_string())
+            }
+            FieldType::Json => match &field.example {
+                Some(example) => example.clone(),
+                None => {
+                    let low = field.min_items.unwrap_or(0);
+                    let high = field.max_items.unwrap_or(low.max(3)).max(low);
+                    let count = self.between(low as i64, high as i64);
+                    json!((0..count).map(|_| self.pick(WORDS)).collect::<Vec<_>>())
+                }
+            },
+            FieldType::Object { fields } => match &field.example {
+                Some(example) => example.clone(),
+                None => self.object(fields),
+            },
+            FieldType::Array { items } => match &field.example {
+                Some(example) => example.clone(),
+                None => {
+                    let item = FieldDefinition {
+                        required: true,
+                        ..FieldDefinition::new(items.as_ref().clone())
+                    };
+                    let low = field.min_items.unwrap_or(0);
+                    let high = field.max_items.unwrap_or(low.max(3)).max(low);
+                    let count = self.between(low as i64, high as i64);
+                    let items: Option<Vec<Value>> =
+                        (0..count).map(|_| self.value(name, &item)).collect();
+                    json!(items?)
+                }
+            },
+            FieldType::File => field.example.clone()?,
+            FieldType::Relation { collection_id, .. } => {
+                let ids = self.relations.get(collection_id).map_or(0, Vec::len);
+                if ids == 0 {
+                    return None;
+                }
+                let index = (self.next() % ids as u64) as usize;
+                json!(self.relations[collection_id][index])
+            }
+        };
+        Some(value)
+    }
+
+    /// Record data for a collection with `schema`.
+    pub fn record(&mut self, schema: &CollectionSchema) -> Value {
+        self.object(&schema.fields)
+    }
+
+    /// An object with values for `fields`.
+    fn object(&mut self, fields: &HashMap<String, FieldDefinition>) -> Value {
+        // Sorted, so a seed fakes the same records whatever the map order.
+        let mut fields: Vec<_> = fields.iter().collect();
+        fields.sort_by_key(|(name, _)| *name);
+        let mut data = Map::new();
+        for (name, field) in fields {
+            if let Some(value) = self.value(name, field) {
+                data.insert(name.clone(), value);
+            }
+        }
+        Value::Object(data)
+    }
+}
+
+/// Creates `count` fake records in `collection`, returning how many were
+/// created. Relations pick from up to [`RELATION_SAMPLE`] records of the
+/// related collection, or of the records created so far when a collection
+/// relates to itself.
+pub async fn fake_records(
+    db: &dyn Db,
+    collection: &Collection,
+    count: usize,
+    faker: &mut Faker,
+) -> Result<usize> {
+    let schema = collection.schema.clone().unwrap_or_default();
+    for (name, field) in &schema.fields {
+        let FieldType::Relation { collection_id, .. } = field.r#type else {
+            continue;
+        };
+        if faker.relations.contains_key(&collection_id) {
+            continue;
+        }
+        let options = ListOptions {
+            limit: Some(RELATION_SAMPLE),
+            ..Default::default()
+        };
+        let ids: Vec<i64> = db
+            .list_records(collection_id, &options)
+            .await?
+            .iter()
+            .map(|record| record.id)
+            .collect();
+        if ids.is_empty() && field.required && collection_id != collection.id {
+            return Err(Error::NotFound(format!(
+                "A record of collection {} for relation {}",
+                collection_id, name
+            )));
+        }
+        faker.relate_to(collection_id, ids);
+    }
+
+    let mut created = 0;
+    while created < count {
+        let mut operations = Vec::new();
+        for _ in 0..BATCH_SIZE.min(count - created) {
+            let mut data = faker.record(&schema);
+            prepare_record(db, &schema, &mut data, Write::Create).await?;
+            operations.push(BatchOperation::Create { data });
+        }
+        let results = db.batch(collection.id, &operations).await?;
+        created += results.len();
+        faker.relate_to(
+            collection.id,
+            results.iter().filter_map(|result| match result {
+                BatchResult::Created(record) => Some(record.id),
+                _ => None,
+            }),
+        );
+    }
+    Ok(created)
+}
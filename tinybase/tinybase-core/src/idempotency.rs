@@ -0,0 +1,20 @@
+//! Idempotency keys of record creation. A client retrying a create with the
+//! `Idempotency-Key` of an earlier attempt is answered with the record that
+//! attempt created rather than creating another.
+//!
+//! A request claims its key before writing the record and completes it with
+//! the record's id afterwards, or releases it if the write failed so the key
+//! can be retried. Keys expire after a while and are dropped by the next
+//! claim, so there is no sweeper to run.
+
+/// What claiming an idempotency key found.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IdempotencyClaim {
+    /// The key was free and is now held by the caller, who should create
+    /// the record and complete the key.
+    Claimed,
+    /// Another request holds the key and hasn't created its record yet.
+    InProgress { collection_id: i64 },
+    /// A record was created under the key.
+    Completed { collection_id: i64, record_id: i64 },
+}
@@ -0,0 +1,60 @@
+//! Jobs: long-running operations such as imports and backups, run in the
+//! background and tracked in the `jobs` table so their progress and outcome
+//! can be looked up while and after they run.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    /// Waiting for a free worker.
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobState::Queued => "queued",
+            JobState::Running => "running",
+            JobState::Completed => "completed",
+            JobState::Failed => "failed",
+            JobState::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn parse(state: &str) -> Option<Self> {
+        [
+            JobState::Queued,
+            JobState::Running,
+            JobState::Completed,
+            JobState::Failed,
+            JobState::Cancelled,
+        ]
+        .into_iter()
+        .find(|s| s.as_str() == state)
+    }
+
+    /// Whether the job is done, one way or another.
+    pub fn is_finished(self) -> bool {
+        !matches!(self, JobState::Queued | JobState::Running)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: i64,
+    /// What the job does, e.g. `import` or `backup`.
+    pub kind: String,
+    pub state: JobState,
+    /// How far the job got, in a shape of its kind's choosing.
+    pub progress: Value,
+    /// The result of a completed job, or the error of a failed one.
+    pub outcome: Option<Value>,
+    pub created: String,
+    pub updated: String,
+}
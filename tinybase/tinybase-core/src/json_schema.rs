@@ -0,0 +1,149 @@
+//! Conversion of collection schemas into JSON Schema, as used by OpenAPI 3.1
+//! documents and client generators.
+
+use crate::schema::{CollectionSchema, FieldDefinition, FieldType};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+/// A field every record has, maintained by Tinybase rather than declared in
+/// the collection schema.
+pub struct SystemField {
+    pub name: &'static str,
+    /// JSON Schema `type` and `format` of the value.
+    pub r#type: &'static str,
+    pub format: Option<&'static str>,
+    pub description: &'static str,
+}
+
+/// The system fields of a record, in the order they are presented. Every
+/// generated schema, client or export takes them from here.
+pub const SYSTEM_FIELDS: &[SystemField] = &[
+    SystemField {
+        name: "id",
+        r#type: "integer",
+        format: None,
+        description: "Unique id of the record",
+    },
+    SystemField {
+        name: "created",
+        r#type: "string",
+        format: Some("date-time"),
+        description: "When the record was created (RFC 3339, UTC)",
+    },
+    SystemField {
+        name: "updated",
+        r#type: "string",
+        format: Some("date-time"),
+        description: "When the record was last written (RFC 3339, UTC)",
+    },
+];
+
+/// The JSON Schema of a whole record: the system fields and a `data` object
+/// described by `data`, typically a `$ref` to [`data_schema`].
+pub fn record_schema(data: Value) -> Value {
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    for field in SYSTEM_FIELDS {
+        let mut schema = json!({ "type": field.r#type, "description": field.description });
+        if let Some(format) = field.format {
+            schema["format"] = format.into();
+        }
+        properties.insert(field.name.to_string(), schema);
+        required.push(Value::from(field.name));
+    }
+    properties.insert("data".to_string(), data);
+    required.push("data".into());
+    json!({ "type": "object", "properties": properties, "required": required })
+}
+
+/// The JSON Schema of a record's `data` object. Collections without a schema
+/// accept any object.
+pub fn data_schema(schema: Option<&CollectionSchema>) -> Value {
+    let Some(schema) = schema else {
+        return json!({ "type": "object" });
+    };
+    object_schema(&schema.fields)
+}
+
+/// The JSON Schema of an object with `fields`.
+fn object_schema(fields: &HashMap<String, FieldDefinition>) -> Value {
+    // Sorted so the generated document is stable.
+    let mut names: Vec<&String> = fields.keys().collect();
+    names.sort();
+
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    for name in names {
+        let field = &fields[name];
+        properties.insert(name.clone(), field_schema(field));
+        if field.required {
+            required.push(Value::String(name.clone()));
+        }
+    }
+    let mut object = json!({ "type": "object", "properties": properties });
+    if !required.is_empty() {
+        object["required"] = Value::Array(required);
+    }
+    object
+}
+
+/// The JSON Schema of a single field, including its constraints, default and
+/// documentation.
+pub fn field_schema(field: &FieldDefinition) -> Value {
+    let mut schema = type_schema(&field.r#type);
+    let constraints = [
+        ("minimum", field.min.map(Value::from)),
+        ("maximum", field.max.map(Value::from)),
+        ("minLength", field.min_length.map(Value::from)),
+        ("maxLength", field.max_length.map(Value::from)),
+        ("pattern", field.pattern.clone().map(Value::from)),
+        ("minItems", field.min_items.map(Value::from)),
+        ("maxItems", field.max_items.map(Value::from)),
+        ("default", field.default.clone()),
+        ("description", field.description.clone().map(Value::from)),
+        (
+            "examples",
+            field.example.clone().map(|e| Value::Array(vec![e])),
+        ),
+        (
+            "readOnly",
+            field.compute.as_ref().map(|_| Value::Bool(true)),
+        ),
+    ];
+    for (keyword, value) in constraints {
+        if let Some(value) = value {
+            schema[keyword] = value;
+        }
+    }
+    schema
+}
+
+/// The JSON Schema of the values of `field_type`, without constraints.
+fn type_schema(field_type: &FieldType) -> Value {
+    match field_type {
+        FieldType::String | FieldType::Text => json!({ "type": "string" }),
+        FieldType::Number => json!({ "type": "number" }),
+        FieldType::Boolean => json!({ "type": "boolean" }),
+        FieldType::Json => json!({ "type": ["object", "array"] }),
+        FieldType::Object { fields } => object_schema(fields),
+        FieldType::Array { items } => json!({ "type": "array", "items": type_schema(items) }),
+        FieldType::File => json!({
+            "type": "object",
+            "properties": {
+                "filename": { "type": "string" },
+                "size": { "type": "integer" },
+                "mime": { "type": "string" }
+            },
+            "required": ["filename"]
+        }),
+        FieldType::Email => json!({ "type": "string", "format": "email" }),
+        FieldType::Url => json!({ "type": "string", "format": "uri" }),
+        FieldType::Date => json!({ "type": "string", "format": "date" }),
+        FieldType::DateTime => json!({ "type": "string", "format": "date-time" }),
+        FieldType::Relation { collection_id, .. } => json!({
+            "type": "integer",
+            "description": format!("Id of a record in collection {}", collection_id),
+            "x-relation-collection": collection_id
+        }),
+    }
+}
@@ -0,0 +1,19 @@
+//! A key-value store beside the collections, for app settings, feature flags
+//! and counters that don't deserve a collection of their own. Entries are
+//! grouped in namespaces, hold any JSON value and may expire.
+//!
+//! Expired entries read as missing and are dropped by the next write to
+//! their namespace, so there is no sweeper to run.
+
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KvEntry {
+    pub namespace: String,
+    pub key: String,
+    pub value: Value,
+    /// When the entry expires; never when `None`.
+    pub expires: Option<String>,
+    pub created: String,
+    pub updated: String,
+}
@@ -1,24 +1,249 @@
+use crate::aggregate::{AggregateGroup, AggregateQuery};
+use crate::arrays::ArrayOp;
+use crate::batch::{BatchOperation, BatchResult};
+use crate::checksums::{MismatchReport, ScrubBatch};
+use crate::idempotency::IdempotencyClaim;
+use crate::jobs::{Job, JobState};
+use crate::kv::KvEntry;
+use crate::lineage::{Lineage, LineageSource};
+use crate::notifications::{ChannelSettings, NotificationChannel};
+use crate::ordering::Placement;
+use crate::record_format::StorageStats;
 use crate::schema::CollectionSchema;
+use crate::scripts::{ScriptHook, ScriptHookSettings};
+use crate::search::SearchHit;
+use crate::tokens::{ApiToken, TokenScope};
+use crate::tree::TreeNode;
+use crate::webhooks::{DeliveryAttempt, Webhook, WebhookDelivery, WebhookSettings};
 use async_trait::async_trait;
-use libsql::{params, Builder, Connection, Database, Result, Row};
+use chrono::{DateTime, Utc};
+use futures_util::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use libsql::{Builder, Connection, Database, Result};
 use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tokio::sync::Mutex;
 
+pub use embedded::Tinybase;
+pub use tinybase_storage::clock;
+
+pub mod aggregate;
+pub mod arrays;
+pub mod batch;
+pub mod checksums;
+pub mod compute;
+pub mod embedded;
+pub mod events;
+pub mod fake;
+pub mod idempotency;
+pub mod jobs;
+pub mod json_schema;
+pub mod kv;
+pub mod lineage;
+pub mod masking;
 pub mod models;
+pub mod notifications;
+pub mod ordering;
+pub mod patch;
+pub mod pool;
+mod queries;
+pub mod read_replicas;
+pub mod record_format;
+pub mod relations;
+pub mod replica;
+pub mod rules;
 pub mod schema;
+pub mod scripts;
+pub mod search;
+pub mod snapshot;
+pub mod template;
+pub mod timeouts;
+pub mod tokens;
+pub mod transform;
+pub mod tree;
 pub mod validation;
+pub mod views;
+pub mod webhooks;
 
 #[derive(Debug)]
 pub struct Collection {
     pub id: i64,
     pub name: String,
     pub schema: Option<CollectionSchema>,
+    pub created: String,
+    pub updated: String,
+    /// The group the collection is filed under, if any.
+    pub group: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct Record {
     pub id: i64,
     pub data: Value,
+    /// RFC 3339 UTC timestamp of when the record was inserted.
+    pub created: String,
+    /// RFC 3339 UTC timestamp of the last write to the record.
+    pub updated: String,
+    /// Counts the writes to the record, starting at 1, for clients to tell
+    /// whether it changed since they read it.
+    pub version: i64,
+    /// How and by whom the record was created, if known.
+    pub lineage: Option<Lineage>,
+}
+
+/// An earlier state of a record, archived when the record was updated or
+/// deleted.
+#[derive(Debug)]
+pub struct RecordVersion {
+    pub record_id: i64,
+    /// Numbered from 1 per record, in the order the versions were archived.
+    pub version: i64,
+    pub data: Value,
+    pub created: String,
+    /// When this version was written.
+    pub updated: String,
+    /// When this version was replaced or deleted.
+    pub archived: String,
+    /// Whether the record was deleted rather than updated.
+    pub deleted: bool,
+}
+
+/// An administrator account. Admins manage collections; they are not app
+/// users and are never stored as records.
+#[derive(Debug)]
+pub struct Admin {
+    pub id: i64,
+    pub email: String,
+    /// PHC string of the password hash.
+    pub password_hash: String,
+    pub created: String,
+    pub updated: String,
+}
+
+/// A failed statement together with its SQL, kept for developer-mode error
+/// reports. Returned by the queries whose SQL is built at runtime.
+#[derive(Debug, thiserror::Error)]
+#[error("{error}")]
+pub struct QueryError {
+    pub sql: String,
+    pub error: libsql::Error,
+}
+
+/// A write refused because the record is no longer at the version the
+/// client read, see [`Db::update_record_at`].
+#[derive(Debug, thiserror::Error)]
+#[error("Record {record_id} is at version {current}, not {expected}")]
+pub struct VersionMismatch {
+    pub record_id: i64,
+    pub expected: i64,
+    pub current: i64,
+}
+
+/// What [`Db::delete_collection`] did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollectionDeletion {
+    /// There was no such collection.
+    Missing,
+    /// The collection still holds this many records, and deleting them
+    /// wasn't asked for; nothing was deleted.
+    NotEmpty(i64),
+    /// The collection was deleted, together with this many records.
+    Deleted(i64),
+}
+
+/// Column [`Db::list_records`] orders by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortField {
+    #[default]
+    Id,
+    Created,
+    Updated,
+    /// The managed position of ordered collections, see [`ordering`]; `Id`
+    /// for other collections.
+    Position,
+}
+
+/// Where keyset paging resumes: the sort key of the last record of the
+/// previous page. The next page holds the records after it in the sort
+/// order, found through the index rather than by skipping rows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cursor {
+    /// After the record with this id, sorting by id.
+    Id(i64),
+    /// After the record created at this time with this id, sorting by
+    /// creation time.
+    Created(String, i64),
+}
+
+/// Ordering, timestamp bounds and paging for [`Db::list_records`].
+///
+/// Bounds are exclusive and compared as strings against the stored RFC 3339
+/// timestamps, so both full timestamps and plain dates (`2024-05-01`) work.
+#[derive(Debug, Clone, Default)]
+pub struct ListOptions {
+    pub sort: SortField,
+    pub descending: bool,
+    pub created_after: Option<String>,
+    pub created_before: Option<String>,
+    pub updated_after: Option<String>,
+    pub updated_before: Option<String>,
+    /// Maximum number of records to return; all of them when `None`.
+    pub limit: Option<u64>,
+    /// Number of matching records to skip.
+    pub offset: u64,
+    /// Only records with a greater id, for paging through a collection by id.
+    pub after_id: Option<i64>,
+    /// Only the records after this one in the sort order.
+    pub cursor: Option<Cursor>,
+    /// The top-level fields of the data to load, left out of it when absent;
+    /// the whole data when `None`.
+    pub fields: Option<Vec<String>>,
+    /// Only records created this way.
+    pub source: Option<LineageSource>,
+    /// Only records created by this job.
+    pub source_job: Option<i64>,
+    /// Only records created by this actor, e.g. `admin:1`.
+    pub source_actor: Option<String>,
+}
+
+/// `time` as an RFC 3339 UTC timestamp with millisecond precision, the format
+/// of the `created` and `updated` timestamps.
+pub fn format_timestamp(time: SystemTime) -> String {
+    let time: DateTime<Utc> = time.into();
+    time.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+/// Records loaded per query by [`stream_records`].
+const STREAM_PAGE_SIZE: u64 = 500;
+
+/// Streams every record of a collection in id order, loading them a page at a
+/// time rather than all at once.
+pub fn stream_records(
+    db: Arc<dyn Db>,
+    collection_id: i64,
+) -> BoxStream<'static, std::result::Result<Record, Box<dyn std::error::Error + Send + Sync>>> {
+    let pages = stream::try_unfold(Some(0), move |after| {
+        let db = db.clone();
+        async move {
+            let Some(after) = after else {
+                return Ok::<_, Box<dyn std::error::Error + Send + Sync>>(None);
+            };
+            let options = ListOptions {
+                after_id: Some(after),
+                limit: Some(STREAM_PAGE_SIZE),
+                ..Default::default()
+            };
+            let page = db.list_records(collection_id, &options).await?;
+            let next = match page.last() {
+                Some(last) if page.len() as u64 == STREAM_PAGE_SIZE => Some(last.id),
+                _ => None,
+            };
+            Ok(Some((stream::iter(page.into_iter().map(Ok)), next)))
+        }
+    });
+    pages.try_flatten().boxed()
 }
 
 #[async_trait]
@@ -27,68 +252,437 @@ pub trait Db: Send + Sync {
         &self,
         name: &str,
         schema: &Option<CollectionSchema>,
-    ) -> std::result::Result<i64, Box<dyn std::error::Error + Send + Sync>>;
+    ) -> std::result::Result<Collection, Box<dyn std::error::Error + Send + Sync>>;
     async fn get_collection(
         &self,
         id: i64,
     ) -> std::result::Result<Option<Collection>, Box<dyn std::error::Error + Send + Sync>>;
+    async fn get_collection_by_name(
+        &self,
+        name: &str,
+    ) -> std::result::Result<Option<Collection>, Box<dyn std::error::Error + Send + Sync>>;
     async fn list_collections(
         &self,
     ) -> std::result::Result<Vec<Collection>, Box<dyn std::error::Error + Send + Sync>>;
+    /// Renames a collection or replaces its schema. `None` when there is no
+    /// such collection.
     async fn update_collection(
         &self,
         id: i64,
         name: Option<String>,
         schema: Option<CollectionSchema>,
+    ) -> std::result::Result<Option<Collection>, Box<dyn std::error::Error + Send + Sync>>;
+    /// Files a collection under `group`, or under no group for `None`.
+    async fn set_collection_group(
+        &self,
+        id: i64,
+        group: Option<&str>,
     ) -> std::result::Result<Collection, Box<dyn std::error::Error + Send + Sync>>;
-    async fn delete_collection(&self, id: i64) -> Result<()>;
+    /// Deletes a collection, refusing if it has records unless `force` is
+    /// set, in which case they are deleted along with it. The records are
+    /// counted and deleted in one transaction, so none written meanwhile
+    /// are lost unseen.
+    async fn delete_collection(&self, id: i64, force: bool) -> Result<CollectionDeletion>;
+    /// The fields of a collection's records that are indexed, see
+    /// [`create_record_index`](Db::create_record_index).
+    async fn list_record_indexes(
+        &self,
+        collection_id: i64,
+    ) -> std::result::Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>>;
+    /// Indexes a top-level field of a collection's records, so lookups by
+    /// that field no longer scan the collection.
+    async fn create_record_index(&self, collection_id: i64, field: &str) -> Result<()>;
+    async fn drop_record_index(&self, collection_id: i64, field: &str) -> Result<()>;
     async fn create_record(
         &self,
         collection_id: i64,
         data: &Value,
-    ) -> std::result::Result<i64, Box<dyn std::error::Error + Send + Sync>>;
+    ) -> std::result::Result<Record, Box<dyn std::error::Error + Send + Sync>>;
+    /// Inserts a record keeping its id and timestamps, e.g. from a dump.
+    /// Returns `false`, inserting nothing, if the id is taken in any
+    /// collection.
+    async fn insert_record(
+        &self,
+        collection_id: i64,
+        record: &Record,
+    ) -> std::result::Result<bool, Box<dyn std::error::Error + Send + Sync>>;
     async fn list_records(
         &self,
         collection_id: i64,
+        options: &ListOptions,
+    ) -> std::result::Result<Vec<Record>, Box<dyn std::error::Error + Send + Sync>>;
+    async fn count_records(
+        &self,
+        collection_id: i64,
+    ) -> std::result::Result<i64, Box<dyn std::error::Error + Send + Sync>>;
+    /// Counts the records [`list_records`](Self::list_records) would return
+    /// for `options` without paging.
+    async fn count_matching_records(
+        &self,
+        collection_id: i64,
+        options: &ListOptions,
+    ) -> std::result::Result<i64, Box<dyn std::error::Error + Send + Sync>>;
+    /// Computes the aggregations of `query` over the records matching
+    /// `options`, ignoring its sort order and paging.
+    async fn aggregate_records(
+        &self,
+        collection_id: i64,
+        query: &AggregateQuery,
+        options: &ListOptions,
+    ) -> std::result::Result<Vec<AggregateGroup>, Box<dyn std::error::Error + Send + Sync>>;
+    /// Lists the records of a collection whose top-level `field` equals `value`.
+    async fn find_records_by_field(
+        &self,
+        collection_id: i64,
+        field: &str,
+        value: &Value,
     ) -> std::result::Result<Vec<Record>, Box<dyn std::error::Error + Send + Sync>>;
+    /// The record of a collection whose top-level `field` equals `value`,
+    /// the oldest if there are several, or a new one created from `data`
+    /// when there is none. The lookup and the insert hold the write lock
+    /// together, so concurrent calls create only one record. The flag tells
+    /// whether the record was created.
+    async fn get_or_create_record(
+        &self,
+        collection_id: i64,
+        field: &str,
+        value: &Value,
+        data: &Value,
+    ) -> std::result::Result<(Record, bool), Box<dyn std::error::Error + Send + Sync>>;
     async fn get_record(
         &self,
         collection_id: i64,
         record_id: i64,
     ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>>;
+    /// Replaces the data of a record. `None` when there is no such record.
     async fn update_record(
         &self,
         collection_id: i64,
         record_id: i64,
         data: &Value,
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>>;
+    /// Deletes a record. Returns how many records were deleted, `0` when
+    /// there was none.
+    async fn delete_record(&self, collection_id: i64, record_id: i64) -> Result<u64>;
+    /// Sets the searchable text of a record for [`Fts5Index`](search::Fts5Index),
+    /// or removes it with `None`.
+    async fn set_search_text(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        text: Option<&str>,
+    ) -> Result<()>;
+    /// Removes the searchable text of every record of a collection.
+    async fn clear_search_text(&self, collection_id: i64) -> Result<()>;
+    /// The records of a collection whose searchable text holds every word of
+    /// `query`, best match first.
+    async fn search_text(
+        &self,
+        collection_id: i64,
+        query: &str,
+        limit: usize,
+    ) -> std::result::Result<Vec<SearchHit>, Box<dyn std::error::Error + Send + Sync>>;
+    /// Replaces the data of a record like [`update_record`](Db::update_record)
+    /// if it is still at `version`, failing with [`VersionMismatch`] when
+    /// another write came first.
+    async fn update_record_at(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        version: i64,
+        data: &Value,
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>>;
+    /// Deletes a record like [`delete_record`](Db::delete_record) if it is
+    /// still at `version`, failing with [`VersionMismatch`] otherwise.
+    async fn delete_record_at(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        version: i64,
+    ) -> std::result::Result<u64, Box<dyn std::error::Error + Send + Sync>>;
+    /// Moves a record of an ordered collection before or after another
+    /// record, see [`ordering`].
+    async fn move_record(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        placement: Placement,
     ) -> std::result::Result<Record, Box<dyn std::error::Error + Send + Sync>>;
-    async fn delete_record(&self, collection_id: i64, record_id: i64) -> Result<()>;
-}
-
-fn row_to_collection(
-    row: &Row,
-) -> std::result::Result<Collection, Box<dyn std::error::Error + Send + Sync>> {
-    let schema_str: Option<String> = row.get(2)?;
-    let schema = match schema_str {
-        Some(s) => serde_json::from_str(&s)?,
-        None => None,
-    };
-    Ok(Collection {
-        id: row.get(0)?,
-        name: row.get(1)?,
-        schema,
-    })
-}
-
-fn row_to_record(
-    row: &Row,
-) -> std::result::Result<Record, Box<dyn std::error::Error + Send + Sync>> {
-    let data_str: String = row.get(1)?;
-    let data = serde_json::from_str(&data_str)?;
-    Ok(Record {
-        id: row.get(0)?,
-        data,
-    })
+    /// Adds `delta` to the number in `field` of a record in a single
+    /// statement, a missing field counting as `0`, so concurrent increments
+    /// don't overwrite each other. Fails with a [`ValidationError`] when the
+    /// field holds something else or the sum falls outside `min` and `max`.
+    /// `None` when there is no such record.
+    ///
+    /// [`ValidationError`]: validation::ValidationError
+    async fn increment_field(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        field: &str,
+        delta: &serde_json::Number,
+        bounds: (Option<f64>, Option<f64>),
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>>;
+    /// Pushes `value` onto the array in `field` of a record, removes it, or
+    /// adds it when absent, in a single statement; a missing field counts as
+    /// an empty array. Fails with a [`ValidationError`] when the field holds
+    /// something else. `None` when there is no such record.
+    ///
+    /// [`ValidationError`]: validation::ValidationError
+    async fn update_array(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        field: &str,
+        op: ArrayOp,
+        value: &Value,
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>>;
+    /// The archived versions of a record, oldest first. Also available once
+    /// the record is deleted.
+    async fn list_record_versions(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+    ) -> std::result::Result<Vec<RecordVersion>, Box<dyn std::error::Error + Send + Sync>>;
+    async fn get_record_version(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        version: i64,
+    ) -> std::result::Result<Option<RecordVersion>, Box<dyn std::error::Error + Send + Sync>>;
+    /// Writes the data of `version` back to the record, archiving its current
+    /// data, or recreates the record with its id if it was deleted.
+    async fn restore_record_version(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        version: &RecordVersion,
+    ) -> std::result::Result<Record, Box<dyn std::error::Error + Send + Sync>>;
+    /// Applies `operations` to a collection inside a single transaction.
+    ///
+    /// Either every operation succeeds or none is kept; the error is then a
+    /// [`BatchError`](crate::batch::BatchError) naming the failing operation.
+    async fn batch(
+        &self,
+        collection_id: i64,
+        operations: &[BatchOperation],
+    ) -> std::result::Result<Vec<BatchResult>, Box<dyn std::error::Error + Send + Sync>>;
+    /// The children of `parent` in the tree of a collection, or its roots
+    /// for `None`, in order. See [`tree`].
+    async fn list_tree_children(
+        &self,
+        collection_id: i64,
+        parent: Option<i64>,
+    ) -> std::result::Result<Vec<TreeNode>, Box<dyn std::error::Error + Send + Sync>>;
+    /// A record and its descendants down to `max_depth` levels below it,
+    /// depth-first; empty if the record isn't in a tree.
+    async fn get_subtree(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        max_depth: Option<u32>,
+    ) -> std::result::Result<Vec<TreeNode>, Box<dyn std::error::Error + Send + Sync>>;
+    /// Moves a record to `position` among its siblings, renumbering them.
+    async fn set_tree_position(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        position: usize,
+    ) -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>>;
+    async fn create_admin(
+        &self,
+        email: &str,
+        password_hash: &str,
+    ) -> std::result::Result<Admin, Box<dyn std::error::Error + Send + Sync>>;
+    async fn get_admin(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<Admin>, Box<dyn std::error::Error + Send + Sync>>;
+    async fn get_admin_by_email(
+        &self,
+        email: &str,
+    ) -> std::result::Result<Option<Admin>, Box<dyn std::error::Error + Send + Sync>>;
+    async fn count_admins(
+        &self,
+    ) -> std::result::Result<i64, Box<dyn std::error::Error + Send + Sync>>;
+    /// Every admin account, oldest first.
+    async fn list_admins(
+        &self,
+    ) -> std::result::Result<Vec<Admin>, Box<dyn std::error::Error + Send + Sync>>;
+    async fn create_notification_channel(
+        &self,
+        settings: &ChannelSettings,
+    ) -> std::result::Result<NotificationChannel, Box<dyn std::error::Error + Send + Sync>>;
+    async fn get_notification_channel(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<NotificationChannel>, Box<dyn std::error::Error + Send + Sync>>;
+    async fn list_notification_channels(
+        &self,
+    ) -> std::result::Result<Vec<NotificationChannel>, Box<dyn std::error::Error + Send + Sync>>;
+    async fn delete_notification_channel(&self, id: i64) -> Result<()>;
+    async fn create_webhook(
+        &self,
+        settings: &WebhookSettings,
+        secret: &str,
+    ) -> std::result::Result<Webhook, Box<dyn std::error::Error + Send + Sync>>;
+    async fn get_webhook(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<Webhook>, Box<dyn std::error::Error + Send + Sync>>;
+    async fn list_webhooks(
+        &self,
+    ) -> std::result::Result<Vec<Webhook>, Box<dyn std::error::Error + Send + Sync>>;
+    /// Stores a token by the hash of its secret.
+    async fn create_api_token(
+        &self,
+        name: &str,
+        scopes: &[TokenScope],
+        token_hash: &str,
+    ) -> std::result::Result<ApiToken, Box<dyn std::error::Error + Send + Sync>>;
+    async fn get_api_token(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<ApiToken>, Box<dyn std::error::Error + Send + Sync>>;
+    /// The token whose secret hashes to `token_hash`, unless revoked.
+    async fn get_api_token_by_hash(
+        &self,
+        token_hash: &str,
+    ) -> std::result::Result<Option<ApiToken>, Box<dyn std::error::Error + Send + Sync>>;
+    async fn list_api_tokens(
+        &self,
+    ) -> std::result::Result<Vec<ApiToken>, Box<dyn std::error::Error + Send + Sync>>;
+    /// Revokes a token.
+    async fn delete_api_token(&self, id: i64) -> Result<()>;
+    /// Deletes a webhook and its delivery log.
+    async fn delete_webhook(&self, id: i64) -> Result<()>;
+    /// Replaces the settings of a webhook, keeping its secret.
+    async fn update_webhook(
+        &self,
+        id: i64,
+        settings: &WebhookSettings,
+    ) -> std::result::Result<Webhook, Box<dyn std::error::Error + Send + Sync>>;
+    /// Adds an attempt to the delivery log of its webhook, dropping the
+    /// oldest beyond [`DELIVERY_LOG_SIZE`](webhooks::DELIVERY_LOG_SIZE), and
+    /// returns it as logged.
+    async fn record_webhook_delivery(
+        &self,
+        attempt: &DeliveryAttempt,
+    ) -> std::result::Result<WebhookDelivery, Box<dyn std::error::Error + Send + Sync>>;
+    /// The delivery log of a webhook, newest first.
+    async fn list_webhook_deliveries(
+        &self,
+        webhook_id: i64,
+    ) -> std::result::Result<Vec<WebhookDelivery>, Box<dyn std::error::Error + Send + Sync>>;
+    /// Delivery `id` of webhook `webhook_id`, while it is in the log.
+    async fn get_webhook_delivery(
+        &self,
+        webhook_id: i64,
+        id: i64,
+    ) -> std::result::Result<Option<WebhookDelivery>, Box<dyn std::error::Error + Send + Sync>>;
+    async fn create_script_hook(
+        &self,
+        settings: &ScriptHookSettings,
+    ) -> std::result::Result<ScriptHook, Box<dyn std::error::Error + Send + Sync>>;
+    async fn get_script_hook(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<ScriptHook>, Box<dyn std::error::Error + Send + Sync>>;
+    /// All script hooks, in the order they run.
+    async fn list_script_hooks(
+        &self,
+    ) -> std::result::Result<Vec<ScriptHook>, Box<dyn std::error::Error + Send + Sync>>;
+    async fn delete_script_hook(&self, id: i64) -> Result<()>;
+    /// Records a new job of `kind`, queued.
+    async fn create_job(
+        &self,
+        kind: &str,
+        progress: &Value,
+    ) -> std::result::Result<Job, Box<dyn std::error::Error + Send + Sync>>;
+    async fn get_job(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<Job>, Box<dyn std::error::Error + Send + Sync>>;
+    /// Moves an unfinished job to `state`, replacing its progress and
+    /// outcome when given. Returns false, changing nothing, when the job is
+    /// finished, e.g. because it was cancelled.
+    async fn update_job(
+        &self,
+        id: i64,
+        state: JobState,
+        progress: Option<&Value>,
+        outcome: Option<&Value>,
+    ) -> Result<bool>;
+    /// Fails every queued or running job with `error`, for jobs left behind
+    /// by a server that stopped. Returns how many there were.
+    async fn fail_unfinished_jobs(&self, error: &Value) -> Result<u64>;
+    /// Rewrites up to `limit` records stored in an older
+    /// [format](record_format) in the current one. Returns how many were
+    /// rewritten, 0 once every record is current.
+    async fn upgrade_record_formats(
+        &self,
+        limit: usize,
+    ) -> std::result::Result<u64, Box<dyn std::error::Error + Send + Sync>>;
+    /// How much room the records of each collection take, and how much
+    /// [compression](record_format) saves.
+    async fn storage_stats(
+        &self,
+    ) -> std::result::Result<Vec<StorageStats>, Box<dyn std::error::Error + Send + Sync>>;
+    /// Checks the checksums of up to `limit` records after record
+    /// `after_id`, in id order, noting mismatches for
+    /// [`list_checksum_mismatches`](Db::list_checksum_mismatches); see
+    /// [`checksums`]. A scrub starting from 0 first forgets the mismatches
+    /// the previous one found.
+    async fn scrub_records(
+        &self,
+        after_id: i64,
+        limit: usize,
+    ) -> std::result::Result<ScrubBatch, Box<dyn std::error::Error + Send + Sync>>;
+    /// The records the last scrub found not matching their checksums.
+    async fn list_checksum_mismatches(
+        &self,
+    ) -> std::result::Result<Vec<MismatchReport>, Box<dyn std::error::Error + Send + Sync>>;
+    /// The unexpired entry under `key` in `namespace`, see [`kv`].
+    async fn get_kv(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> std::result::Result<Option<KvEntry>, Box<dyn std::error::Error + Send + Sync>>;
+    /// Sets the entry under `key` in `namespace`, expiring after `ttl` if
+    /// given, and drops the expired entries of the namespace.
+    async fn set_kv(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &Value,
+        ttl: Option<Duration>,
+    ) -> std::result::Result<KvEntry, Box<dyn std::error::Error + Send + Sync>>;
+    /// Deletes the entry under `key` in `namespace`. Returns false when
+    /// there was none, or it had expired.
+    async fn delete_kv(&self, namespace: &str, key: &str) -> Result<bool>;
+    /// Claims the idempotency `key` for creating a record in a collection,
+    /// holding it for `ttl`, and drops expired keys; see [`idempotency`].
+    async fn claim_idempotency_key(
+        &self,
+        key: &str,
+        collection_id: i64,
+        ttl: Duration,
+    ) -> std::result::Result<IdempotencyClaim, Box<dyn std::error::Error + Send + Sync>>;
+    /// Records that the request holding `key` created record `record_id`.
+    async fn complete_idempotency_key(&self, key: &str, record_id: i64) -> Result<()>;
+    /// Frees a claimed `key` whose record couldn't be created.
+    async fn release_idempotency_key(&self, key: &str) -> Result<()>;
+    /// Runs a trivial query, to check that the database answers.
+    async fn ping(&self) -> Result<()>;
+    /// Writes a consistent copy of the whole database to `path`, which must
+    /// not exist yet.
+    async fn backup_into(&self, path: &Path) -> Result<()>;
+    /// Replaces the contents of every table with those of the same table in
+    /// the database file at `path`, in one transaction.
+    async fn restore_from(
+        &self,
+        path: &Path,
+    ) -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>>;
 }
 
 #[async_trait]
@@ -97,15 +691,9 @@ impl Db for Database {
         &self,
         name: &str,
         schema: &Option<CollectionSchema>,
-    ) -> std::result::Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+    ) -> std::result::Result<Collection, Box<dyn std::error::Error + Send + Sync>> {
         let conn = self.connect()?;
-        let schema_str = serde_json::to_string(&schema)?;
-        conn.execute(
-            "INSERT INTO collections (name, schema) VALUES (?1, ?2)",
-            params![name, schema_str],
-        )
-        .await?;
-        Ok(conn.last_insert_rowid())
+        queries::create_collection(&conn, name, schema).await
     }
 
     async fn get_collection(
@@ -113,28 +701,22 @@ impl Db for Database {
         id: i64,
     ) -> std::result::Result<Option<Collection>, Box<dyn std::error::Error + Send + Sync>> {
         let conn = self.connect()?;
-        let mut rows = conn
-            .query("SELECT id, name, schema FROM collections WHERE id = ?1", params![id])
-            .await?;
-        let row = match rows.next().await? {
-            Some(row) => row,
-            None => return Ok(None),
-        };
-        Ok(Some(row_to_collection(&row)?))
+        queries::get_collection(&conn, id).await
+    }
+
+    async fn get_collection_by_name(
+        &self,
+        name: &str,
+    ) -> std::result::Result<Option<Collection>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::get_collection_by_name(&conn, name).await
     }
 
     async fn list_collections(
         &self,
     ) -> std::result::Result<Vec<Collection>, Box<dyn std::error::Error + Send + Sync>> {
         let conn = self.connect()?;
-        let mut rows = conn
-            .query("SELECT id, name, schema FROM collections", ())
-            .await?;
-        let mut collections = Vec::new();
-        while let Some(row) = rows.next().await? {
-            collections.push(row_to_collection(&row)?);
-        }
-        Ok(collections)
+        queries::list_collections(&conn).await
     }
 
     async fn update_collection(
@@ -142,230 +724,116 @@ impl Db for Database {
         id: i64,
         name: Option<String>,
         schema: Option<CollectionSchema>,
+    ) -> std::result::Result<Option<Collection>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::update_collection(&conn, id, name, schema).await
+    }
+
+    async fn set_collection_group(
+        &self,
+        id: i64,
+        group: Option<&str>,
     ) -> std::result::Result<Collection, Box<dyn std::error::Error + Send + Sync>> {
         let conn = self.connect()?;
-        if let Some(name) = name {
-            conn.execute(
-                "UPDATE collections SET name = ?1 WHERE id = ?2",
-                params![name, id],
-            )
-            .await?;
-        }
-        if let Some(schema) = schema {
-            let schema_str = serde_json::to_string(&schema)?;
-            conn.execute(
-                "UPDATE collections SET schema = ?1 WHERE id = ?2",
-                params![schema_str, id],
-            )
-            .await?;
-        }
-        let collection = self.get_collection(id).await?.ok_or("Collection not found")?;
-        Ok(collection)
+        queries::set_collection_group(&conn, id, group).await
     }
 
-    async fn delete_collection(&self, id: i64) -> Result<()> {
+    async fn delete_collection(&self, id: i64, force: bool) -> Result<CollectionDeletion> {
         let conn = self.connect()?;
-        conn.execute("DELETE FROM collections WHERE id = ?1", params![id])
-            .await?;
-        Ok(())
+        queries::delete_collection(&conn, id, force).await
     }
 
-    async fn create_record(
+    async fn list_record_indexes(
         &self,
         collection_id: i64,
-        data: &Value,
-    ) -> std::result::Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+    ) -> std::result::Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
         let conn = self.connect()?;
-        let data_str = serde_json::to_string(data)?;
-        conn.execute(
-            "INSERT INTO records (collection_id, data) VALUES (?1, ?2)",
-            params![collection_id, data_str],
-        )
-        .await?;
-        Ok(conn.last_insert_rowid())
+        queries::list_record_indexes(&conn, collection_id).await
     }
 
-    async fn list_records(
-        &self,
-        collection_id: i64,
-    ) -> std::result::Result<Vec<Record>, Box<dyn std::error::Error + Send + Sync>> {
+    async fn create_record_index(&self, collection_id: i64, field: &str) -> Result<()> {
         let conn = self.connect()?;
-        let mut rows = conn
-            .query(
-                "SELECT id, data FROM records WHERE collection_id = ?1",
-                params![collection_id],
-            )
-            .await?;
-        let mut records = Vec::new();
-        while let Some(row) = rows.next().await? {
-            records.push(row_to_record(&row)?);
-        }
-        Ok(records)
+        queries::create_record_index(&conn, collection_id, field).await
     }
 
-    async fn get_record(
-        &self,
-        collection_id: i64,
-        record_id: i64,
-    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>> {
+    async fn drop_record_index(&self, collection_id: i64, field: &str) -> Result<()> {
         let conn = self.connect()?;
-        let mut rows = conn
-            .query(
-                "SELECT id, data FROM records WHERE collection_id = ?1 AND id = ?2",
-                params![collection_id, record_id],
-            )
-            .await?;
-        let row = match rows.next().await? {
-            Some(row) => row,
-            None => return Ok(None),
-        };
-        Ok(Some(row_to_record(&row)?))
+        queries::drop_record_index(&conn, collection_id, field).await
     }
 
-    async fn update_record(
+    async fn create_record(
         &self,
         collection_id: i64,
-        record_id: i64,
         data: &Value,
     ) -> std::result::Result<Record, Box<dyn std::error::Error + Send + Sync>> {
         let conn = self.connect()?;
-        let data_str = serde_json::to_string(data)?;
-        conn.execute(
-            "UPDATE records SET data = ?1 WHERE collection_id = ?2 AND id = ?3",
-            params![data_str, collection_id, record_id],
-        )
-        .await?;
-        let record = self
-            .get_record(collection_id, record_id)
-            .await?
-            .ok_or("Record not found")?;
-        Ok(record)
+        queries::create_record(&conn, collection_id, data).await
     }
 
-    async fn delete_record(&self, collection_id: i64, record_id: i64) -> Result<()> {
+    async fn insert_record(
+        &self,
+        collection_id: i64,
+        record: &Record,
+    ) -> std::result::Result<bool, Box<dyn std::error::Error + Send + Sync>> {
         let conn = self.connect()?;
-        conn.execute(
-            "DELETE FROM records WHERE collection_id = ?1 AND id = ?2",
-            params![collection_id, record_id],
-        )
-        .await?;
-        Ok(())
+        queries::insert_record(&conn, collection_id, record).await
     }
-}
 
-#[async_trait]
-impl Db for Mutex<Connection> {
-    async fn create_collection(
+    async fn list_records(
         &self,
-        name: &str,
-        schema: &Option<CollectionSchema>,
-    ) -> std::result::Result<i64, Box<dyn std::error::Error + Send + Sync>> {
-        let conn = self.lock().await;
-        let schema_str = serde_json::to_string(&schema)?;
-        conn.execute(
-            "INSERT INTO collections (name, schema) VALUES (?1, ?2)",
-            params![name, schema_str],
-        )
-        .await?;
-        Ok(conn.last_insert_rowid())
+        collection_id: i64,
+        options: &ListOptions,
+    ) -> std::result::Result<Vec<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::list_records(&conn, collection_id, options).await
     }
 
-    async fn get_collection(
+    async fn count_records(
         &self,
-        id: i64,
-    ) -> std::result::Result<Option<Collection>, Box<dyn std::error::Error + Send + Sync>> {
-        let conn = self.lock().await;
-        let mut rows = conn
-            .query("SELECT id, name, schema FROM collections WHERE id = ?1", params![id])
-            .await?;
-        let row = match rows.next().await? {
-            Some(row) => row,
-            None => return Ok(None),
-        };
-        Ok(Some(row_to_collection(&row)?))
+        collection_id: i64,
+    ) -> std::result::Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::count_records(&conn, collection_id).await
     }
 
-    async fn list_collections(
+    async fn count_matching_records(
         &self,
-    ) -> std::result::Result<Vec<Collection>, Box<dyn std::error::Error + Send + Sync>> {
-        let conn = self.lock().await;
-        let mut rows = conn
-            .query("SELECT id, name, schema FROM collections", ())
-            .await?;
-        let mut collections = Vec::new();
-        while let Some(row) = rows.next().await? {
-            collections.push(row_to_collection(&row)?);
-        }
-        Ok(collections)
+        collection_id: i64,
+        options: &ListOptions,
+    ) -> std::result::Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::count_matching_records(&conn, collection_id, options).await
     }
 
-    async fn update_collection(
+    async fn aggregate_records(
         &self,
-        id: i64,
-        name: Option<String>,
-        schema: Option<CollectionSchema>,
-    ) -> std::result::Result<Collection, Box<dyn std::error::Error + Send + Sync>> {
-        let conn = self.lock().await;
-        if let Some(name) = name {
-            conn.execute(
-                "UPDATE collections SET name = ?1 WHERE id = ?2",
-                params![name, id],
-            )
-            .await?;
-        }
-        if let Some(schema) = schema {
-            let schema_str = serde_json::to_string(&schema)?;
-            conn.execute(
-                "UPDATE collections SET schema = ?1 WHERE id = ?2",
-                params![schema_str, id],
-            )
-            .await?;
-        }
-        let mut rows = conn
-            .query("SELECT id, name, schema FROM collections WHERE id = ?1", params![id])
-            .await?;
-        let row = rows.next().await?.ok_or("Collection not found")?;
-        Ok(row_to_collection(&row)?)
-    }
-
-    async fn delete_collection(&self, id: i64) -> Result<()> {
-        let conn = self.lock().await;
-        conn.execute("DELETE FROM collections WHERE id = ?1", params![id])
-            .await?;
-        Ok(())
+        collection_id: i64,
+        query: &AggregateQuery,
+        options: &ListOptions,
+    ) -> std::result::Result<Vec<AggregateGroup>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::aggregate_records(&conn, collection_id, query, options).await
     }
 
-    async fn create_record(
+    async fn find_records_by_field(
         &self,
         collection_id: i64,
-        data: &Value,
-    ) -> std::result::Result<i64, Box<dyn std::error::Error + Send + Sync>> {
-        let conn = self.lock().await;
-        let data_str = serde_json::to_string(data)?;
-        conn.execute(
-            "INSERT INTO records (collection_id, data) VALUES (?1, ?2)",
-            params![collection_id, data_str],
-        )
-        .await?;
-        Ok(conn.last_insert_rowid())
+        field: &str,
+        value: &Value,
+    ) -> std::result::Result<Vec<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::find_records_by_field(&conn, collection_id, field, value).await
     }
 
-    async fn list_records(
+    async fn get_or_create_record(
         &self,
         collection_id: i64,
-    ) -> std::result::Result<Vec<Record>, Box<dyn std::error::Error + Send + Sync>> {
-        let conn = self.lock().await;
-        let mut rows = conn
-            .query(
-                "SELECT id, data FROM records WHERE collection_id = ?1",
-                params![collection_id],
-            )
-            .await?;
-        let mut records = Vec::new();
-        while let Some(row) = rows.next().await? {
-            records.push(row_to_record(&row)?);
-        }
-        Ok(records)
+        field: &str,
+        value: &Value,
+        data: &Value,
+    ) -> std::result::Result<(Record, bool), Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::get_or_create_record(&conn, collection_id, field, value, data).await
     }
 
     async fn get_record(
@@ -373,18 +841,8 @@ impl Db for Mutex<Connection> {
         collection_id: i64,
         record_id: i64,
     ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>> {
-        let conn = self.lock().await;
-        let mut rows = conn
-            .query(
-                "SELECT id, data FROM records WHERE collection_id = ?1 AND id = ?2",
-                params![collection_id, record_id],
-            )
-            .await?;
-        let row = match rows.next().await? {
-            Some(row) => row,
-            None => return Ok(None),
-        };
-        Ok(Some(row_to_record(&row)?))
+        let conn = self.connect()?;
+        queries::get_record(&conn, collection_id, record_id).await
     }
 
     async fn update_record(
@@ -392,49 +850,1251 @@ impl Db for Mutex<Connection> {
         collection_id: i64,
         record_id: i64,
         data: &Value,
-    ) -> std::result::Result<Record, Box<dyn std::error::Error + Send + Sync>> {
-        let conn = self.lock().await;
-        let data_str = serde_json::to_string(data)?;
-        conn.execute(
-            "UPDATE records SET data = ?1 WHERE collection_id = ?2 AND id = ?3",
-            params![data_str, collection_id, record_id],
-        )
-        .await?;
-        let record = self
-            .get_record(collection_id, record_id)
-            .await?
-            .ok_or("Record not found")?;
-        Ok(record)
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::update_record(&conn, collection_id, record_id, data).await
     }
 
-    async fn delete_record(&self, collection_id: i64, record_id: i64) -> Result<()> {
+    async fn delete_record(&self, collection_id: i64, record_id: i64) -> Result<u64> {
+        let conn = self.connect()?;
+        queries::delete_record(&conn, collection_id, record_id).await
+    }
+
+    async fn set_search_text(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        text: Option<&str>,
+    ) -> Result<()> {
+        let conn = self.connect()?;
+        queries::set_search_text(&conn, collection_id, record_id, text).await
+    }
+
+    async fn clear_search_text(&self, collection_id: i64) -> Result<()> {
+        let conn = self.connect()?;
+        queries::clear_search_text(&conn, collection_id).await
+    }
+
+    async fn search_text(
+        &self,
+        collection_id: i64,
+        query: &str,
+        limit: usize,
+    ) -> std::result::Result<Vec<SearchHit>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::search_text(&conn, collection_id, query, limit).await
+    }
+
+    async fn update_record_at(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        version: i64,
+        data: &Value,
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::update_record_at(&conn, collection_id, record_id, version, data).await
+    }
+
+    async fn delete_record_at(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        version: i64,
+    ) -> std::result::Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::delete_record_at(&conn, collection_id, record_id, version).await
+    }
+
+    async fn move_record(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        placement: Placement,
+    ) -> std::result::Result<Record, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::move_record(&conn, collection_id, record_id, placement).await
+    }
+
+    async fn increment_field(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        field: &str,
+        delta: &serde_json::Number,
+        bounds: (Option<f64>, Option<f64>),
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::increment_field(&conn, collection_id, record_id, field, delta, bounds).await
+    }
+
+    async fn update_array(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        field: &str,
+        op: ArrayOp,
+        value: &Value,
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::update_array(&conn, collection_id, record_id, field, op, value).await
+    }
+
+    async fn list_record_versions(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+    ) -> std::result::Result<Vec<RecordVersion>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::list_record_versions(&conn, collection_id, record_id).await
+    }
+
+    async fn get_record_version(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        version: i64,
+    ) -> std::result::Result<Option<RecordVersion>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::get_record_version(&conn, collection_id, record_id, version).await
+    }
+
+    async fn restore_record_version(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        version: &RecordVersion,
+    ) -> std::result::Result<Record, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::restore_record_version(&conn, collection_id, record_id, version).await
+    }
+
+    async fn batch(
+        &self,
+        collection_id: i64,
+        operations: &[BatchOperation],
+    ) -> std::result::Result<Vec<BatchResult>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::batch(&conn, collection_id, operations).await
+    }
+
+    async fn list_tree_children(
+        &self,
+        collection_id: i64,
+        parent: Option<i64>,
+    ) -> std::result::Result<Vec<TreeNode>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::list_tree_children(&conn, collection_id, parent).await
+    }
+
+    async fn get_subtree(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        max_depth: Option<u32>,
+    ) -> std::result::Result<Vec<TreeNode>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::get_subtree(&conn, collection_id, record_id, max_depth).await
+    }
+
+    async fn set_tree_position(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        position: usize,
+    ) -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::set_tree_position(&conn, collection_id, record_id, position).await
+    }
+
+    async fn create_admin(
+        &self,
+        email: &str,
+        password_hash: &str,
+    ) -> std::result::Result<Admin, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::create_admin(&conn, email, password_hash).await
+    }
+
+    async fn get_admin(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<Admin>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::get_admin(&conn, id).await
+    }
+
+    async fn get_admin_by_email(
+        &self,
+        email: &str,
+    ) -> std::result::Result<Option<Admin>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::get_admin_by_email(&conn, email).await
+    }
+
+    async fn count_admins(
+        &self,
+    ) -> std::result::Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::count_admins(&conn).await
+    }
+
+    async fn list_admins(
+        &self,
+    ) -> std::result::Result<Vec<Admin>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::list_admins(&conn).await
+    }
+
+    async fn create_notification_channel(
+        &self,
+        settings: &ChannelSettings,
+    ) -> std::result::Result<NotificationChannel, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::create_notification_channel(&conn, settings).await
+    }
+
+    async fn get_notification_channel(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<NotificationChannel>, Box<dyn std::error::Error + Send + Sync>>
+    {
+        let conn = self.connect()?;
+        queries::get_notification_channel(&conn, id).await
+    }
+
+    async fn list_notification_channels(
+        &self,
+    ) -> std::result::Result<Vec<NotificationChannel>, Box<dyn std::error::Error + Send + Sync>>
+    {
+        let conn = self.connect()?;
+        queries::list_notification_channels(&conn).await
+    }
+
+    async fn delete_notification_channel(&self, id: i64) -> Result<()> {
+        let conn = self.connect()?;
+        queries::delete_notification_channel(&conn, id).await
+    }
+
+    async fn create_webhook(
+        &self,
+        settings: &WebhookSettings,
+        secret: &str,
+    ) -> std::result::Result<Webhook, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::create_webhook(&conn, settings, secret).await
+    }
+
+    async fn get_webhook(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<Webhook>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::get_webhook(&conn, id).await
+    }
+
+    async fn list_webhooks(
+        &self,
+    ) -> std::result::Result<Vec<Webhook>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::list_webhooks(&conn).await
+    }
+
+    async fn create_api_token(
+        &self,
+        name: &str,
+        scopes: &[TokenScope],
+        token_hash: &str,
+    ) -> std::result::Result<ApiToken, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::create_api_token(&conn, name, scopes, token_hash).await
+    }
+
+    async fn get_api_token(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<ApiToken>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::get_api_token(&conn, id).await
+    }
+
+    async fn get_api_token_by_hash(
+        &self,
+        token_hash: &str,
+    ) -> std::result::Result<Option<ApiToken>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::get_api_token_by_hash(&conn, token_hash).await
+    }
+
+    async fn list_api_tokens(
+        &self,
+    ) -> std::result::Result<Vec<ApiToken>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::list_api_tokens(&conn).await
+    }
+
+    async fn delete_api_token(&self, id: i64) -> Result<()> {
+        let conn = self.connect()?;
+        queries::delete_api_token(&conn, id).await
+    }
+
+    async fn delete_webhook(&self, id: i64) -> Result<()> {
+        let conn = self.connect()?;
+        queries::delete_webhook(&conn, id).await
+    }
+
+    async fn update_webhook(
+        &self,
+        id: i64,
+        settings: &WebhookSettings,
+    ) -> std::result::Result<Webhook, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::update_webhook(&conn, id, settings).await
+    }
+
+    async fn record_webhook_delivery(
+        &self,
+        attempt: &DeliveryAttempt,
+    ) -> std::result::Result<WebhookDelivery, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::record_webhook_delivery(&conn, attempt).await
+    }
+
+    async fn list_webhook_deliveries(
+        &self,
+        webhook_id: i64,
+    ) -> std::result::Result<Vec<WebhookDelivery>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::list_webhook_deliveries(&conn, webhook_id).await
+    }
+
+    async fn get_webhook_delivery(
+        &self,
+        webhook_id: i64,
+        id: i64,
+    ) -> std::result::Result<Option<WebhookDelivery>, Box<dyn std::error::Error + Send + Sync>>
+    {
+        let conn = self.connect()?;
+        queries::get_webhook_delivery(&conn, webhook_id, id).await
+    }
+
+    async fn create_script_hook(
+        &self,
+        settings: &ScriptHookSettings,
+    ) -> std::result::Result<ScriptHook, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::create_script_hook(&conn, settings).await
+    }
+
+    async fn get_script_hook(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<ScriptHook>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::get_script_hook(&conn, id).await
+    }
+
+    async fn list_script_hooks(
+        &self,
+    ) -> std::result::Result<Vec<ScriptHook>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::list_script_hooks(&conn).await
+    }
+
+    async fn delete_script_hook(&self, id: i64) -> Result<()> {
+        let conn = self.connect()?;
+        queries::delete_script_hook(&conn, id).await
+    }
+    async fn create_job(
+        &self,
+        kind: &str,
+        progress: &Value,
+    ) -> std::result::Result<Job, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::create_job(&conn, kind, progress).await
+    }
+    async fn get_job(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<Job>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::get_job(&conn, id).await
+    }
+    async fn update_job(
+        &self,
+        id: i64,
+        state: JobState,
+        progress: Option<&Value>,
+        outcome: Option<&Value>,
+    ) -> Result<bool> {
+        let conn = self.connect()?;
+        queries::update_job(&conn, id, state, progress, outcome).await
+    }
+    async fn fail_unfinished_jobs(&self, error: &Value) -> Result<u64> {
+        let conn = self.connect()?;
+        queries::fail_unfinished_jobs(&conn, error).await
+    }
+    async fn upgrade_record_formats(
+        &self,
+        limit: usize,
+    ) -> std::result::Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::upgrade_record_formats(&conn, limit).await
+    }
+    async fn storage_stats(
+        &self,
+    ) -> std::result::Result<Vec<StorageStats>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::storage_stats(&conn).await
+    }
+    async fn scrub_records(
+        &self,
+        after_id: i64,
+        limit: usize,
+    ) -> std::result::Result<ScrubBatch, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::scrub_records(&conn, after_id, limit).await
+    }
+    async fn list_checksum_mismatches(
+        &self,
+    ) -> std::result::Result<Vec<MismatchReport>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::list_checksum_mismatches(&conn).await
+    }
+    async fn get_kv(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> std::result::Result<Option<KvEntry>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::get_kv(&conn, namespace, key).await
+    }
+    async fn set_kv(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &Value,
+        ttl: Option<Duration>,
+    ) -> std::result::Result<KvEntry, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::set_kv(&conn, namespace, key, value, ttl).await
+    }
+    async fn delete_kv(&self, namespace: &str, key: &str) -> Result<bool> {
+        let conn = self.connect()?;
+        queries::delete_kv(&conn, namespace, key).await
+    }
+    async fn claim_idempotency_key(
+        &self,
+        key: &str,
+        collection_id: i64,
+        ttl: Duration,
+    ) -> std::result::Result<IdempotencyClaim, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        queries::claim_idempotency_key(&conn, key, collection_id, ttl).await
+    }
+    async fn complete_idempotency_key(&self, key: &str, record_id: i64) -> Result<()> {
+        let conn = self.connect()?;
+        queries::complete_idempotency_key(&conn, key, record_id).await
+    }
+    async fn release_idempotency_key(&self, key: &str) -> Result<()> {
+        let conn = self.connect()?;
+        queries::release_idempotency_key(&conn, key).await
+    }
+    async fn ping(&self) -> Result<()> {
+        let conn = self.connect()?;
+        queries::ping(&conn).await
+    }
+
+    async fn backup_into(&self, path: &Path) -> Result<()> {
+        let conn = self.connect()?;
+        snapshot::backup_into(&conn, path).await
+    }
+
+    async fn restore_from(
+        &self,
+        path: &Path,
+    ) -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.connect()?;
+        snapshot::restore_from(&conn, path).await
+    }
+}
+
+#[async_trait]
+impl Db for Mutex<Connection> {
+    async fn create_collection(
+        &self,
+        name: &str,
+        schema: &Option<CollectionSchema>,
+    ) -> std::result::Result<Collection, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::create_collection(&conn, name, schema).await
+    }
+
+    async fn get_collection(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<Collection>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::get_collection(&conn, id).await
+    }
+
+    async fn get_collection_by_name(
+        &self,
+        name: &str,
+    ) -> std::result::Result<Option<Collection>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::get_collection_by_name(&conn, name).await
+    }
+
+    async fn list_collections(
+        &self,
+    ) -> std::result::Result<Vec<Collection>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::list_collections(&conn).await
+    }
+
+    async fn update_collection(
+        &self,
+        id: i64,
+        name: Option<String>,
+        schema: Option<CollectionSchema>,
+    ) -> std::result::Result<Option<Collection>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::update_collection(&conn, id, name, schema).await
+    }
+
+    async fn set_collection_group(
+        &self,
+        id: i64,
+        group: Option<&str>,
+    ) -> std::result::Result<Collection, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::set_collection_group(&conn, id, group).await
+    }
+
+    async fn delete_collection(&self, id: i64, force: bool) -> Result<CollectionDeletion> {
+        let conn = self.lock().await;
+        queries::delete_collection(&conn, id, force).await
+    }
+
+    async fn list_record_indexes(
+        &self,
+        collection_id: i64,
+    ) -> std::result::Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::list_record_indexes(&conn, collection_id).await
+    }
+
+    async fn create_record_index(&self, collection_id: i64, field: &str) -> Result<()> {
+        let conn = self.lock().await;
+        queries::create_record_index(&conn, collection_id, field).await
+    }
+
+    async fn drop_record_index(&self, collection_id: i64, field: &str) -> Result<()> {
+        let conn = self.lock().await;
+        queries::drop_record_index(&conn, collection_id, field).await
+    }
+
+    async fn create_record(
+        &self,
+        collection_id: i64,
+        data: &Value,
+    ) -> std::result::Result<Record, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::create_record(&conn, collection_id, data).await
+    }
+
+    async fn insert_record(
+        &self,
+        collection_id: i64,
+        record: &Record,
+    ) -> std::result::Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::insert_record(&conn, collection_id, record).await
+    }
+
+    async fn list_records(
+        &self,
+        collection_id: i64,
+        options: &ListOptions,
+    ) -> std::result::Result<Vec<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::list_records(&conn, collection_id, options).await
+    }
+
+    async fn count_records(
+        &self,
+        collection_id: i64,
+    ) -> std::result::Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::count_records(&conn, collection_id).await
+    }
+
+    async fn count_matching_records(
+        &self,
+        collection_id: i64,
+        options: &ListOptions,
+    ) -> std::result::Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::count_matching_records(&conn, collection_id, options).await
+    }
+
+    async fn aggregate_records(
+        &self,
+        collection_id: i64,
+        query: &AggregateQuery,
+        options: &ListOptions,
+    ) -> std::result::Result<Vec<AggregateGroup>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::aggregate_records(&conn, collection_id, query, options).await
+    }
+
+    async fn find_records_by_field(
+        &self,
+        collection_id: i64,
+        field: &str,
+        value: &Value,
+    ) -> std::result::Result<Vec<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::find_records_by_field(&conn, collection_id, field, value).await
+    }
+
+    async fn get_or_create_record(
+        &self,
+        collection_id: i64,
+        field: &str,
+        value: &Value,
+        data: &Value,
+    ) -> std::result::Result<(Record, bool), Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::get_or_create_record(&conn, collection_id, field, value, data).await
+    }
+
+    async fn get_record(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::get_record(&conn, collection_id, record_id).await
+    }
+
+    async fn update_record(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        data: &Value,
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::update_record(&conn, collection_id, record_id, data).await
+    }
+
+    async fn delete_record(&self, collection_id: i64, record_id: i64) -> Result<u64> {
+        let conn = self.lock().await;
+        queries::delete_record(&conn, collection_id, record_id).await
+    }
+
+    async fn set_search_text(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        text: Option<&str>,
+    ) -> Result<()> {
+        let conn = self.lock().await;
+        queries::set_search_text(&conn, collection_id, record_id, text).await
+    }
+
+    async fn clear_search_text(&self, collection_id: i64) -> Result<()> {
+        let conn = self.lock().await;
+        queries::clear_search_text(&conn, collection_id).await
+    }
+
+    async fn search_text(
+        &self,
+        collection_id: i64,
+        query: &str,
+        limit: usize,
+    ) -> std::result::Result<Vec<SearchHit>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::search_text(&conn, collection_id, query, limit).await
+    }
+
+    async fn update_record_at(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        version: i64,
+        data: &Value,
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::update_record_at(&conn, collection_id, record_id, version, data).await
+    }
+
+    async fn delete_record_at(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        version: i64,
+    ) -> std::result::Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::delete_record_at(&conn, collection_id, record_id, version).await
+    }
+
+    async fn move_record(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        placement: Placement,
+    ) -> std::result::Result<Record, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::move_record(&conn, collection_id, record_id, placement).await
+    }
+
+    async fn increment_field(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        field: &str,
+        delta: &serde_json::Number,
+        bounds: (Option<f64>, Option<f64>),
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::increment_field(&conn, collection_id, record_id, field, delta, bounds).await
+    }
+
+    async fn update_array(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        field: &str,
+        op: ArrayOp,
+        value: &Value,
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::update_array(&conn, collection_id, record_id, field, op, value).await
+    }
+
+    async fn list_record_versions(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+    ) -> std::result::Result<Vec<RecordVersion>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::list_record_versions(&conn, collection_id, record_id).await
+    }
+
+    async fn get_record_version(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        version: i64,
+    ) -> std::result::Result<Option<RecordVersion>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::get_record_version(&conn, collection_id, record_id, version).await
+    }
+
+    async fn restore_record_version(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        version: &RecordVersion,
+    ) -> std::result::Result<Record, Box<dyn std::error::Error + Send + Sync>> {
         let conn = self.lock().await;
-        conn.execute(
-            "DELETE FROM records WHERE collection_id = ?1 AND id = ?2",
-            params![collection_id, record_id],
-        )
-        .await?;
-        Ok(())
+        queries::restore_record_version(&conn, collection_id, record_id, version).await
+    }
+
+    async fn batch(
+        &self,
+        collection_id: i64,
+        operations: &[BatchOperation],
+    ) -> std::result::Result<Vec<BatchResult>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::batch(&conn, collection_id, operations).await
+    }
+
+    async fn list_tree_children(
+        &self,
+        collection_id: i64,
+        parent: Option<i64>,
+    ) -> std::result::Result<Vec<TreeNode>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::list_tree_children(&conn, collection_id, parent).await
+    }
+
+    async fn get_subtree(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        max_depth: Option<u32>,
+    ) -> std::result::Result<Vec<TreeNode>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::get_subtree(&conn, collection_id, record_id, max_depth).await
+    }
+
+    async fn set_tree_position(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        position: usize,
+    ) -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::set_tree_position(&conn, collection_id, record_id, position).await
+    }
+
+    async fn create_admin(
+        &self,
+        email: &str,
+        password_hash: &str,
+    ) -> std::result::Result<Admin, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::create_admin(&conn, email, password_hash).await
+    }
+
+    async fn get_admin(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<Admin>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::get_admin(&conn, id).await
+    }
+
+    async fn get_admin_by_email(
+        &self,
+        email: &str,
+    ) -> std::result::Result<Option<Admin>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::get_admin_by_email(&conn, email).await
+    }
+
+    async fn count_admins(
+        &self,
+    ) -> std::result::Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::count_admins(&conn).await
+    }
+
+    async fn list_admins(
+        &self,
+    ) -> std::result::Result<Vec<Admin>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::list_admins(&conn).await
+    }
+
+    async fn create_notification_channel(
+        &self,
+        settings: &ChannelSettings,
+    ) -> std::result::Result<NotificationChannel, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::create_notification_channel(&conn, settings).await
+    }
+
+    async fn get_notification_channel(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<NotificationChannel>, Box<dyn std::error::Error + Send + Sync>>
+    {
+        let conn = self.lock().await;
+        queries::get_notification_channel(&conn, id).await
+    }
+
+    async fn list_notification_channels(
+        &self,
+    ) -> std::result::Result<Vec<NotificationChannel>, Box<dyn std::error::Error + Send + Sync>>
+    {
+        let conn = self.lock().await;
+        queries::list_notification_channels(&conn).await
+    }
+
+    async fn delete_notification_channel(&self, id: i64) -> Result<()> {
+        let conn = self.lock().await;
+        queries::delete_notification_channel(&conn, id).await
+    }
+
+    async fn create_webhook(
+        &self,
+        settings: &WebhookSettings,
+        secret: &str,
+    ) -> std::result::Result<Webhook, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::create_webhook(&conn, settings, secret).await
+    }
+
+    async fn get_webhook(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<Webhook>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::get_webhook(&conn, id).await
+    }
+
+    async fn list_webhooks(
+        &self,
+    ) -> std::result::Result<Vec<Webhook>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::list_webhooks(&conn).await
+    }
+
+    async fn create_api_token(
+        &self,
+        name: &str,
+        scopes: &[TokenScope],
+        token_hash: &str,
+    ) -> std::result::Result<ApiToken, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::create_api_token(&conn, name, scopes, token_hash).await
+    }
+
+    async fn get_api_token(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<ApiToken>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::get_api_token(&conn, id).await
+    }
+
+    async fn get_api_token_by_hash(
+        &self,
+        token_hash: &str,
+    ) -> std::result::Result<Option<ApiToken>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::get_api_token_by_hash(&conn, token_hash).await
+    }
+
+    async fn list_api_tokens(
+        &self,
+    ) -> std::result::Result<Vec<ApiToken>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::list_api_tokens(&conn).await
+    }
+
+    async fn delete_api_token(&self, id: i64) -> Result<()> {
+        let conn = self.lock().await;
+        queries::delete_api_token(&conn, id).await
+    }
+
+    async fn delete_webhook(&self, id: i64) -> Result<()> {
+        let conn = self.lock().await;
+        queries::delete_webhook(&conn, id).await
+    }
+
+    async fn update_webhook(
+        &self,
+        id: i64,
+        settings: &WebhookSettings,
+    ) -> std::result::Result<Webhook, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::update_webhook(&conn, id, settings).await
+    }
+
+    async fn record_webhook_delivery(
+        &self,
+        attempt: &DeliveryAttempt,
+    ) -> std::result::Result<WebhookDelivery, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::record_webhook_delivery(&conn, attempt).await
+    }
+
+    async fn list_webhook_deliveries(
+        &self,
+        webhook_id: i64,
+    ) -> std::result::Result<Vec<WebhookDelivery>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::list_webhook_deliveries(&conn, webhook_id).await
+    }
+
+    async fn get_webhook_delivery(
+        &self,
+        webhook_id: i64,
+        id: i64,
+    ) -> std::result::Result<Option<WebhookDelivery>, Box<dyn std::error::Error + Send + Sync>>
+    {
+        let conn = self.lock().await;
+        queries::get_webhook_delivery(&conn, webhook_id, id).await
+    }
+
+    async fn create_script_hook(
+        &self,
+        settings: &ScriptHookSettings,
+    ) -> std::result::Result<ScriptHook, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::create_script_hook(&conn, settings).await
+    }
+
+    async fn get_script_hook(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<ScriptHook>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::get_script_hook(&conn, id).await
+    }
+
+    async fn list_script_hooks(
+        &self,
+    ) -> std::result::Result<Vec<ScriptHook>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::list_script_hooks(&conn).await
+    }
+
+    async fn delete_script_hook(&self, id: i64) -> Result<()> {
+        let conn = self.lock().await;
+        queries::delete_script_hook(&conn, id).await
+    }
+    async fn create_job(
+        &self,
+        kind: &str,
+        progress: &Value,
+    ) -> std::result::Result<Job, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::create_job(&conn, kind, progress).await
+    }
+    async fn get_job(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<Job>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::get_job(&conn, id).await
+    }
+    async fn update_job(
+        &self,
+        id: i64,
+        state: JobState,
+        progress: Option<&Value>,
+        outcome: Option<&Value>,
+    ) -> Result<bool> {
+        let conn = self.lock().await;
+        queries::update_job(&conn, id, state, progress, outcome).await
+    }
+    async fn fail_unfinished_jobs(&self, error: &Value) -> Result<u64> {
+        let conn = self.lock().await;
+        queries::fail_unfinished_jobs(&conn, error).await
+    }
+    async fn upgrade_record_formats(
+        &self,
+        limit: usize,
+    ) -> std::result::Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::upgrade_record_formats(&conn, limit).await
+    }
+    async fn storage_stats(
+        &self,
+    ) -> std::result::Result<Vec<StorageStats>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::storage_stats(&conn).await
+    }
+    async fn scrub_records(
+        &self,
+        after_id: i64,
+        limit: usize,
+    ) -> std::result::Result<ScrubBatch, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::scrub_records(&conn, after_id, limit).await
+    }
+    async fn list_checksum_mismatches(
+        &self,
+    ) -> std::result::Result<Vec<MismatchReport>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::list_checksum_mismatches(&conn).await
+    }
+    async fn get_kv(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> std::result::Result<Option<KvEntry>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::get_kv(&conn, namespace, key).await
+    }
+    async fn set_kv(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &Value,
+        ttl: Option<Duration>,
+    ) -> std::result::Result<KvEntry, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::set_kv(&conn, namespace, key, value, ttl).await
+    }
+    async fn delete_kv(&self, namespace: &str, key: &str) -> Result<bool> {
+        let conn = self.lock().await;
+        queries::delete_kv(&conn, namespace, key).await
+    }
+    async fn claim_idempotency_key(
+        &self,
+        key: &str,
+        collection_id: i64,
+        ttl: Duration,
+    ) -> std::result::Result<IdempotencyClaim, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        queries::claim_idempotency_key(&conn, key, collection_id, ttl).await
+    }
+    async fn complete_idempotency_key(&self, key: &str, record_id: i64) -> Result<()> {
+        let conn = self.lock().await;
+        queries::complete_idempotency_key(&conn, key, record_id).await
+    }
+    async fn release_idempotency_key(&self, key: &str) -> Result<()> {
+        let conn = self.lock().await;
+        queries::release_idempotency_key(&conn, key).await
+    }
+    async fn ping(&self) -> Result<()> {
+        let conn = self.lock().await;
+        queries::ping(&conn).await
+    }
+
+    async fn backup_into(&self, path: &Path) -> Result<()> {
+        let conn = self.lock().await;
+        snapshot::backup_into(&conn, path).await
+    }
+
+    async fn restore_from(
+        &self,
+        path: &Path,
+    ) -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.lock().await;
+        snapshot::restore_from(&conn, path).await
     }
 }
 
-pub async fn a_new_database_connection() -> Result<Database> {
-    let db = Builder::new_local("local.db").build().await?;
-    setup_database(&db).await?;
+pub async fn a_new_database_connection(path: impl AsRef<Path>) -> Result<Database> {
+    let db = Builder::new_local(path.as_ref()).build().await?;
+    setup_database(&db.connect()?).await?;
     Ok(db)
 }
 
-async fn setup_database(db: &Database) -> Result<()> {
-    let conn = db.connect()?;
+/// Creates the tables Tinybase needs, upgrading tables from older versions
+/// in place.
+pub async fn setup_database(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS collections (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL, schema JSON, created TEXT NOT NULL, updated TEXT NOT NULL)",
+        (),
+    )
+    .await?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS records (id INTEGER PRIMARY KEY AUTOINCREMENT, collection_id INTEGER NOT NULL, data TEXT NOT NULL, created TEXT NOT NULL, updated TEXT NOT NULL)",
+        (),
+    )
+    .await?;
+    queries::add_timestamp_columns(conn, "collections").await?;
+    queries::add_timestamp_columns(conn, "records").await?;
+    queries::add_group_column(conn).await?;
+    queries::add_version_column(conn).await?;
+    queries::add_lineage_column(conn).await?;
+    queries::add_checksum_column(conn).await?;
     conn.execute(
-        "CREATE TABLE IF NOT EXISTS collections (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL, schema JSON)",
+        "CREATE VIRTUAL TABLE IF NOT EXISTS record_search USING fts5(collection_id UNINDEXED, record_id UNINDEXED, body, tokenize = 'porter unicode61')",
         (),
     )
     .await?;
     conn.execute(
-        "CREATE TABLE IF NOT EXISTS records (id INTEGER PRIMARY KEY AUTOINCREMENT, collection_id INTEGER NOT NULL, data TEXT NOT NULL)",
+        "CREATE TABLE IF NOT EXISTS admins (id INTEGER PRIMARY KEY AUTOINCREMENT, email TEXT NOT NULL UNIQUE, password_hash TEXT NOT NULL, created TEXT NOT NULL, updated TEXT NOT NULL)",
+        (),
+    )
+    .await?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS record_versions (collection_id INTEGER NOT NULL, record_id INTEGER NOT NULL, version INTEGER NOT NULL, data TEXT NOT NULL, created TEXT NOT NULL, updated TEXT NOT NULL, archived TEXT NOT NULL, deleted INTEGER NOT NULL, PRIMARY KEY (collection_id, record_id, version))",
+        (),
+    )
+    .await?;
+    queries::add_format_column(conn).await?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS notification_channels (id INTEGER PRIMARY KEY AUTOINCREMENT, settings TEXT NOT NULL, created TEXT NOT NULL, updated TEXT NOT NULL)",
+        (),
+    )
+    .await?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS webhooks (id INTEGER PRIMARY KEY AUTOINCREMENT, settings TEXT NOT NULL, secret TEXT NOT NULL, created TEXT NOT NULL, updated TEXT NOT NULL)",
+        (),
+    )
+    .await?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS webhook_deliveries (id INTEGER PRIMARY KEY AUTOINCREMENT, webhook_id INTEGER NOT NULL, event TEXT NOT NULL, payload TEXT NOT NULL, attempt INTEGER NOT NULL, status INTEGER, error TEXT, duration_ms INTEGER NOT NULL, created TEXT NOT NULL, response TEXT, replay_of INTEGER)",
+        (),
+    )
+    .await?;
+    queries::add_delivery_columns(conn).await?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS api_tokens (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL, token_hash TEXT NOT NULL UNIQUE, scopes TEXT NOT NULL, created TEXT NOT NULL, updated TEXT NOT NULL)",
+        (),
+    )
+    .await?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS script_hooks (id INTEGER PRIMARY KEY AUTOINCREMENT, settings TEXT NOT NULL, created TEXT NOT NULL, updated TEXT NOT NULL)",
+        (),
+    )
+    .await?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS jobs (id INTEGER PRIMARY KEY AUTOINCREMENT, kind TEXT NOT NULL, state TEXT NOT NULL, progress TEXT NOT NULL, outcome TEXT, created TEXT NOT NULL, updated TEXT NOT NULL)",
+        (),
+    )
+    .await?;
+    // The place of each record in the tree of its collection, see `tree`.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS record_tree (collection_id INTEGER NOT NULL, record_id INTEGER NOT NULL, parent_id INTEGER, path TEXT NOT NULL, depth INTEGER NOT NULL, position INTEGER NOT NULL, PRIMARY KEY (collection_id, record_id))",
+        (),
+    )
+    .await?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS record_tree_path ON record_tree (collection_id, path)",
+        (),
+    )
+    .await?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS record_tree_parent ON record_tree (collection_id, parent_id, position)",
+        (),
+    )
+    .await?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS kv (namespace TEXT NOT NULL, key TEXT NOT NULL, value TEXT NOT NULL, expires TEXT, created TEXT NOT NULL, updated TEXT NOT NULL, PRIMARY KEY (namespace, key))",
+        (),
+    )
+    .await?;
+    // The record created under each idempotency key, NULL while in progress.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS idempotency_keys (key TEXT PRIMARY KEY, collection_id INTEGER NOT NULL, record_id INTEGER, expires TEXT NOT NULL, created TEXT NOT NULL)",
+        (),
+    )
+    .await?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS checksum_mismatches (record_id INTEGER PRIMARY KEY, collection_id INTEGER NOT NULL, stored INTEGER NOT NULL, computed INTEGER NOT NULL, detected TEXT NOT NULL)",
+        (),
+    )
+    .await?;
+    // Collections can be addressed by name, so names must be unique.
+    conn.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS collections_name ON collections (name)",
         (),
     )
     .await?;
     Ok(())
 }
+
+/// The tables and indexes [`setup_database`] would create or change, by
+/// name, found by running it in a transaction that is rolled back. Empty
+/// when the database is up to date.
+pub async fn pending_migrations(conn: &Connection) -> Result<Vec<String>> {
+    let before = schema_objects(conn).await?;
+    conn.execute("BEGIN", ()).await?;
+    let after = match setup_database(conn).await {
+        Ok(()) => schema_objects(conn).await,
+        Err(e) => Err(e),
+    };
+    conn.execute("ROLLBACK", ()).await?;
+    Ok(after?
+        .into_iter()
+        .filter(|(name, sql)| before.get(name) != Some(sql))
+        .map(|(name, _)| name)
+        .collect())
+}
+
+/// The definition of every table and index of the database, by name.
+async fn schema_objects(conn: &Connection) -> Result<BTreeMap<String, String>> {
+    let mut rows = conn
+        .query(
+            "SELECT name, COALESCE(sql, '') FROM sqlite_master WHERE type IN ('table', 'index')",
+            (),
+        )
+        .await?;
+    let mut objects = BTreeMap::new();
+    while let Some(row) = rows.next().await? {
+        objects.insert(row.get::<String>(0)?, row.get::<String>(1)?);
+    }
+    Ok(objects)
+}
@@ -0,0 +1,93 @@
+//! Where records come from: every record keeps the [`Lineage`] it was
+//! created with, for audits to answer where a row came from.
+//!
+//! [`with_lineage`] sets the lineage of the records a future creates, the way
+//! [`with_query_timeout`](crate::timeouts::with_query_timeout) sets its time
+//! limit. Records created outside any lineage, e.g. by an embedding app,
+//! have none. Updates keep the lineage of the record.
+
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+
+/// How a record was created.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LineageSource {
+    /// A request to the records API.
+    Api,
+    /// A dump or CSV import.
+    Import,
+    /// A script hook, through `tinybase.create`.
+    Hook,
+}
+
+impl LineageSource {
+    pub fn name(self) -> &'static str {
+        match self {
+            LineageSource::Api => "api",
+            LineageSource::Import => "import",
+            LineageSource::Hook => "hook",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        [Self::Api, Self::Import, Self::Hook]
+            .into_iter()
+            .find(|source| source.name() == name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Lineage {
+    pub source: LineageSource,
+    /// The background job that created the record, e.g. an import.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job: Option<i64>,
+    /// Who created the record, e.g. `admin:1` or `token:2`; absent for
+    /// anonymous callers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+}
+
+impl Lineage {
+    pub fn new(source: LineageSource) -> Self {
+        Self {
+            source,
+            job: None,
+            actor: None,
+        }
+    }
+
+    /// A lineage of `source` keeping the actor of the current one, for work
+    /// a request starts on behalf of whoever sent it.
+    pub fn inherit(source: LineageSource) -> Self {
+        Self {
+            actor: current().and_then(|lineage| lineage.actor),
+            ..Self::new(source)
+        }
+    }
+
+    pub fn with_actor(mut self, actor: Option<String>) -> Self {
+        self.actor = actor;
+        self
+    }
+
+    pub fn with_job(mut self, job: i64) -> Self {
+        self.job = Some(job);
+        self
+    }
+}
+
+tokio::task_local! {
+    static LINEAGE: Lineage;
+}
+
+/// Runs `future` with the records it creates attributed to `lineage`.
+pub async fn with_lineage<F: Future>(lineage: Lineage, future: F) -> F::Output {
+    LINEAGE.scope(lineage, future).await
+}
+
+/// The lineage of records created now, if any.
+pub fn current() -> Option<Lineage> {
+    LINEAGE.try_with(Clone::clone).ok()
+}
@@ -0,0 +1,69 @@
+//! Masking of sensitive field values in responses. A field whose definition
+//! sets `mask` is shown as asterisks to viewers holding none of its
+//! `reveal_to` roles, keeping the last `keep_last` characters, e.g.
+//! `****1234` for a card number. Admins always see the full value.
+//!
+//! Masks apply where records leave the server, not to stored data: writes,
+//! rules, hooks and filters see the real values.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::schema::CollectionSchema;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MaskSettings {
+    /// Roles that see the value unmasked, besides admins.
+    #[serde(default)]
+    pub reveal_to: Vec<String>,
+    /// How many trailing characters stay visible; values no longer than
+    /// that are masked whole.
+    #[serde(default)]
+    pub keep_last: usize,
+}
+
+impl MaskSettings {
+    /// Whether a viewer with `roles` sees the value masked.
+    pub fn hides_from(&self, roles: &[String]) -> bool {
+        !roles.iter().any(|role| self.reveal_to.contains(role))
+    }
+
+    /// The masked form of `value`. Values other than strings are masked as
+    /// their JSON text; `null` stays `null`.
+    pub fn mask(&self, value: &Value) -> Value {
+        let text = match value {
+            Value::Null => return Value::Null,
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        let chars: Vec<char> = text.chars().collect();
+        let keep = if chars.len() > self.keep_last {
+            self.keep_last
+        } else {
+            0
+        };
+        let hidden = chars.len() - keep;
+        let visible: String = chars[hidden..].iter().collect();
+        Value::String("*".repeat(hidden) + &visible)
+    }
+}
+
+/// Whether any field of `schema` is masked.
+pub fn has_masks(schema: &CollectionSchema) -> bool {
+    schema.fields.values().any(|field| field.mask.is_some())
+}
+
+/// Masks the fields of `data` that `schema` hides from a viewer with `roles`.
+pub fn mask_record(schema: &CollectionSchema, data: &mut Value, roles: &[String]) {
+    let Some(map) = data.as_object_mut() else {
+        return;
+    };
+    for (name, field) in &schema.fields {
+        let Some(mask) = field.mask.as_ref().filter(|mask| mask.hides_from(roles)) else {
+            continue;
+        };
+        if let Some(value) = map.get_mut(name) {
+            *value = mask.mask(value);
+        }
+    }
+}
@@ -6,6 +6,13 @@ use serde_json::Value;
 pub struct Collection {
     pub name: String,
     pub schema: Option<CollectionSchema>,
+    /// A JSON Schema of the record data to take the schema from instead,
+    /// see [`CollectionSchema::from_json_schema`].
+    #[serde(default)]
+    pub json_schema: Option<Value>,
+    /// Files the collection under a group, for navigation.
+    #[serde(default)]
+    pub group: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -0,0 +1,65 @@
+//! Notification channels: chat webhooks (Slack, Discord) told about
+//! collection events and admin alerts.
+
+use crate::template::PayloadTemplate;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelKind {
+    Slack,
+    Discord,
+}
+
+/// What a channel is told about and where, as configured by an admin.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChannelSettings {
+    pub name: String,
+    pub kind: ChannelKind,
+    /// Incoming webhook URL of the Slack or Discord channel.
+    pub url: String,
+    /// Event names to deliver, e.g. `record.created` or `backup.failed`;
+    /// `*` matches every event.
+    pub events: Vec<String>,
+    /// Only deliver collection events of this collection.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collection_id: Option<i64>,
+    /// Shapes the message; defaults to the event summary as plain text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<PayloadTemplate>,
+}
+
+#[derive(Debug)]
+pub struct NotificationChannel {
+    pub id: i64,
+    pub settings: ChannelSettings,
+    pub created: String,
+    pub updated: String,
+}
+
+impl ChannelSettings {
+    /// Whether an event named `event`, concerning `collection_id` if it is a
+    /// collection event, should be delivered to this channel.
+    pub fn wants(&self, event: &str, collection_id: Option<i64>) -> bool {
+        let named = self.events.iter().any(|e| e == "*" || e == event);
+        let in_scope = match (self.collection_id, collection_id) {
+            (Some(wanted), Some(id)) => wanted == id,
+            _ => true,
+        };
+        named && in_scope
+    }
+
+    /// The request body for a notification with `context`, which carries at
+    /// least the event name as `event` and a one-line summary as `text`.
+    pub fn payload(&self, context: &Value) -> Value {
+        if let Some(template) = &self.template {
+            return template.render(context);
+        }
+        let text = context["text"].clone();
+        match self.kind {
+            ChannelKind::Slack => json!({ "text": text }),
+            ChannelKind::Discord => json!({ "content": text }),
+        }
+    }
+}
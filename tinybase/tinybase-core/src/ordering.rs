@@ -0,0 +1,53 @@
+//! Manual ordering of records. A collection whose schema sets `order` keeps a
+//! position in a number field of each record, `position` by default, which
+//! the server manages: new records go last, values clients send for it are
+//! ignored, and records are moved before or after another record.
+//!
+//! Positions are integers spaced [`POSITION_GAP`] apart, so a move usually
+//! rewrites only the moved record, taking the middle of the gap it lands in;
+//! once a gap is used up the whole collection is renumbered.
+
+use serde::{Deserialize, Serialize};
+
+/// The distance between neighbouring positions after renumbering.
+pub const POSITION_GAP: i64 = 1024;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OrderSettings {
+    /// The field holding the position of each record.
+    #[serde(default = "default_field")]
+    pub field: String,
+}
+
+fn default_field() -> String {
+    "position".to_string()
+}
+
+/// Where [`Db::move_record`](crate::Db::move_record) puts a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// Right before the record with this id.
+    Before(i64),
+    /// Right after the record with this id.
+    After(i64),
+}
+
+impl Placement {
+    /// The record moved next to.
+    pub fn anchor(&self) -> i64 {
+        match self {
+            Placement::Before(id) | Placement::After(id) => *id,
+        }
+    }
+}
+
+/// A free position strictly between `low` and `high`, either of which may be
+/// open, or `None` when the gap is used up.
+pub(crate) fn position_between(low: Option<i64>, high: Option<i64>) -> Option<i64> {
+    match (low, high) {
+        (Some(low), Some(high)) => (high - low > 1).then(|| low + (high - low) / 2),
+        (Some(low), None) => Some(low + POSITION_GAP),
+        (None, Some(high)) => Some(high - POSITION_GAP),
+        (None, None) => Some(POSITION_GAP),
+    }
+}
@@ -0,0 +1,26 @@
+//! JSON Merge Patch (RFC 7396), used for partial record updates.
+
+use serde_json::{Map, Value};
+
+/// Applies `patch` to `target`: members of a patch object replace those of
+/// the target, recursively for nested objects, and `null` members remove
+/// them. Any other patch value replaces the target as a whole.
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let Value::Object(map) = target else {
+        return;
+    };
+    for (key, value) in patch {
+        if value.is_null() {
+            map.remove(key);
+        } else {
+            merge_patch(map.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
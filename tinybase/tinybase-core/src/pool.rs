@@ -0,0 +1,788 @@
+//! A pool of connections to one libsql database, so concurrent requests each
+//! get a connection of their own instead of queueing for one, as they do on
+//! a `Mutex<Connection>`, or paying for a new one per operation, as they do
+//! on a bare `Database`.
+//!
+//! Connections are opened as needed, up to the size of the pool, and kept
+//! for reuse; a request finding them all in use waits for one to be
+//! returned. Connections are set to wait [`BUSY_TIMEOUT`] for locks held by
+//! others and the database is switched to write-ahead logging, so readers
+//! and a writer don't block each other.
+//!
+//! Every connection to an in-memory database opens a database of its own,
+//! so pool file databases only.
+
+use async_trait::async_trait;
+use libsql::{Connection, Database, Result};
+use serde_json::Value;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::aggregate::{AggregateGroup, AggregateQuery};
+use crate::arrays::ArrayOp;
+use crate::batch::{BatchOperation, BatchResult};
+use crate::checksums::{MismatchReport, ScrubBatch};
+use crate::idempotency::IdempotencyClaim;
+use crate::jobs::{Job, JobState};
+use crate::kv::KvEntry;
+use crate::notifications::{ChannelSettings, NotificationChannel};
+use crate::ordering::Placement;
+use crate::record_format::StorageStats;
+use crate::schema::CollectionSchema;
+use crate::scripts::{ScriptHook, ScriptHookSettings};
+use crate::search::SearchHit;
+use crate::tokens::{ApiToken, TokenScope};
+use crate::tree::TreeNode;
+use crate::webhooks::{DeliveryAttempt, Webhook, WebhookDelivery, WebhookSettings};
+use crate::{
+    queries, snapshot, Admin, Collection, CollectionDeletion, Db, ListOptions, Record,
+    RecordVersion,
+};
+
+/// How long a connection waits for a lock another one holds before failing
+/// with `SQLITE_BUSY`.
+pub const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The pool size used when none is configured.
+pub const DEFAULT_POOL_SIZE: usize = 8;
+
+pub struct ConnectionPool {
+    db: Arc<Database>,
+    idle: Mutex<Vec<Connection>>,
+    permits: Semaphore,
+    size: usize,
+}
+
+impl ConnectionPool {
+    /// A pool of up to `size` connections to `db`, switching it to
+    /// write-ahead logging.
+    pub async fn new(db: Arc<Database>, size: usize) -> Result<Self> {
+        let size = size.max(1);
+        let pool = ConnectionPool {
+            db,
+            idle: Mutex::new(Vec::new()),
+            permits: Semaphore::new(size),
+            size,
+        };
+        let conn = pool.get().await?;
+        conn.query("PRAGMA journal_mode = WAL", ()).await?;
+        drop(conn);
+        Ok(pool)
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn database(&self) -> &Arc<Database> {
+        &self.db
+    }
+
+    /// A connection for the caller's use, returned to the pool when dropped.
+    pub async fn get(&self) -> Result<PooledConnection<'_>> {
+        let permit = self
+            .permits
+            .acquire()
+            .await
+            .expect("the pool's semaphore is never closed");
+        let idle = self.idle.lock().unwrap().pop();
+        let conn = match idle {
+            Some(conn) => conn,
+            None => {
+                let conn = self.db.connect()?;
+                conn.busy_timeout(BUSY_TIMEOUT)?;
+                conn
+            }
+        };
+        Ok(PooledConnection {
+            conn: Some(conn),
+            pool: self,
+            _permit: permit,
+        })
+    }
+
+    /// Closes the pool on shutdown: waits for the connections in use to be
+    /// returned, checkpoints the write-ahead log into the database file and
+    /// closes the idle connections. Connections asked for later are opened
+    /// anew.
+    pub async fn close(&self) -> Result<()> {
+        let _all = self
+            .permits
+            .acquire_many(self.size as u32)
+            .await
+            .expect("the pool's semaphore is never closed");
+        let idle = std::mem::take(&mut *self.idle.lock().unwrap());
+        let conn = match idle.into_iter().next() {
+            Some(conn) => conn,
+            None => self.db.connect()?,
+        };
+        conn.query("PRAGMA wal_checkpoint(TRUNCATE)", ()).await?;
+        Ok(())
+    }
+}
+
+/// A connection borrowed from a [`ConnectionPool`].
+pub struct PooledConnection<'a> {
+    conn: Option<Connection>,
+    pool: &'a ConnectionPool,
+    _permit: SemaphorePermit<'a>,
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().expect("taken only on drop")
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        // A connection dropped mid-transaction, e.g. by a query timeout
+        // cancelling its request, is closed rather than handed out again
+        // with the transaction open.
+        if let Some(conn) = self.conn.take().filter(Connection::is_autocommit) {
+            self.pool.idle.lock().unwrap().push(conn);
+        }
+    }
+}
+
+#[async_trait]
+impl Db for ConnectionPool {
+    async fn create_collection(
+        &self,
+        name: &str,
+        schema: &Option<CollectionSchema>,
+    ) -> std::result::Result<Collection, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::create_collection(&conn, name, schema).await
+    }
+
+    async fn get_collection(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<Collection>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::get_collection(&conn, id).await
+    }
+
+    async fn get_collection_by_name(
+        &self,
+        name: &str,
+    ) -> std::result::Result<Option<Collection>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::get_collection_by_name(&conn, name).await
+    }
+
+    async fn list_collections(
+        &self,
+    ) -> std::result::Result<Vec<Collection>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::list_collections(&conn).await
+    }
+
+    async fn update_collection(
+        &self,
+        id: i64,
+        name: Option<String>,
+        schema: Option<CollectionSchema>,
+    ) -> std::result::Result<Option<Collection>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::update_collection(&conn, id, name, schema).await
+    }
+
+    async fn set_collection_group(
+        &self,
+        id: i64,
+        group: Option<&str>,
+    ) -> std::result::Result<Collection, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::set_collection_group(&conn, id, group).await
+    }
+
+    async fn delete_collection(&self, id: i64, force: bool) -> Result<CollectionDeletion> {
+        let conn = self.get().await?;
+        queries::delete_collection(&conn, id, force).await
+    }
+
+    async fn list_record_indexes(
+        &self,
+        collection_id: i64,
+    ) -> std::result::Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::list_record_indexes(&conn, collection_id).await
+    }
+
+    async fn create_record_index(&self, collection_id: i64, field: &str) -> Result<()> {
+        let conn = self.get().await?;
+        queries::create_record_index(&conn, collection_id, field).await
+    }
+
+    async fn drop_record_index(&self, collection_id: i64, field: &str) -> Result<()> {
+        let conn = self.get().await?;
+        queries::drop_record_index(&conn, collection_id, field).await
+    }
+
+    async fn create_record(
+        &self,
+        collection_id: i64,
+        data: &Value,
+    ) -> std::result::Result<Record, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::create_record(&conn, collection_id, data).await
+    }
+
+    async fn insert_record(
+        &self,
+        collection_id: i64,
+        record: &Record,
+    ) -> std::result::Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::insert_record(&conn, collection_id, record).await
+    }
+
+    async fn list_records(
+        &self,
+        collection_id: i64,
+        options: &ListOptions,
+    ) -> std::result::Result<Vec<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::list_records(&conn, collection_id, options).await
+    }
+
+    async fn count_records(
+        &self,
+        collection_id: i64,
+    ) -> std::result::Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::count_records(&conn, collection_id).await
+    }
+
+    async fn count_matching_records(
+        &self,
+        collection_id: i64,
+        options: &ListOptions,
+    ) -> std::result::Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::count_matching_records(&conn, collection_id, options).await
+    }
+
+    async fn aggregate_records(
+        &self,
+        collection_id: i64,
+        query: &AggregateQuery,
+        options: &ListOptions,
+    ) -> std::result::Result<Vec<AggregateGroup>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::aggregate_records(&conn, collection_id, query, options).await
+    }
+
+    async fn find_records_by_field(
+        &self,
+        collection_id: i64,
+        field: &str,
+        value: &Value,
+    ) -> std::result::Result<Vec<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::find_records_by_field(&conn, collection_id, field, value).await
+    }
+
+    async fn get_or_create_record(
+        &self,
+        collection_id: i64,
+        field: &str,
+        value: &Value,
+        data: &Value,
+    ) -> std::result::Result<(Record, bool), Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::get_or_create_record(&conn, collection_id, field, value, data).await
+    }
+
+    async fn get_record(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::get_record(&conn, collection_id, record_id).await
+    }
+
+    async fn update_record(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        data: &Value,
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::update_record(&conn, collection_id, record_id, data).await
+    }
+
+    async fn delete_record(&self, collection_id: i64, record_id: i64) -> Result<u64> {
+        let conn = self.get().await?;
+        queries::delete_record(&conn, collection_id, record_id).await
+    }
+
+    async fn set_search_text(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        text: Option<&str>,
+    ) -> Result<()> {
+        let conn = self.get().await?;
+        queries::set_search_text(&conn, collection_id, record_id, text).await
+    }
+
+    async fn clear_search_text(&self, collection_id: i64) -> Result<()> {
+        let conn = self.get().await?;
+        queries::clear_search_text(&conn, collection_id).await
+    }
+
+    async fn search_text(
+        &self,
+        collection_id: i64,
+        query: &str,
+        limit: usize,
+    ) -> std::result::Result<Vec<SearchHit>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::search_text(&conn, collection_id, query, limit).await
+    }
+
+    async fn update_record_at(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        version: i64,
+        data: &Value,
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::update_record_at(&conn, collection_id, record_id, version, data).await
+    }
+
+    async fn delete_record_at(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        version: i64,
+    ) -> std::result::Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::delete_record_at(&conn, collection_id, record_id, version).await
+    }
+
+    async fn move_record(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        placement: Placement,
+    ) -> std::result::Result<Record, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::move_record(&conn, collection_id, record_id, placement).await
+    }
+
+    async fn increment_field(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        field: &str,
+        delta: &serde_json::Number,
+        bounds: (Option<f64>, Option<f64>),
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::increment_field(&conn, collection_id, record_id, field, delta, bounds).await
+    }
+
+    async fn update_array(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        field: &str,
+        op: ArrayOp,
+        value: &Value,
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::update_array(&conn, collection_id, record_id, field, op, value).await
+    }
+
+    async fn list_record_versions(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+    ) -> std::result::Result<Vec<RecordVersion>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::list_record_versions(&conn, collection_id, record_id).await
+    }
+
+    async fn get_record_version(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        version: i64,
+    ) -> std::result::Result<Option<RecordVersion>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::get_record_version(&conn, collection_id, record_id, version).await
+    }
+
+    async fn restore_record_version(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        version: &RecordVersion,
+    ) -> std::result::Result<Record, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::restore_record_version(&conn, collection_id, record_id, version).await
+    }
+
+    async fn batch(
+        &self,
+        collection_id: i64,
+        operations: &[BatchOperation],
+    ) -> std::result::Result<Vec<BatchResult>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::batch(&conn, collection_id, operations).await
+    }
+
+    async fn list_tree_children(
+        &self,
+        collection_id: i64,
+        parent: Option<i64>,
+    ) -> std::result::Result<Vec<TreeNode>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::list_tree_children(&conn, collection_id, parent).await
+    }
+
+    async fn get_subtree(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        max_depth: Option<u32>,
+    ) -> std::result::Result<Vec<TreeNode>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::get_subtree(&conn, collection_id, record_id, max_depth).await
+    }
+
+    async fn set_tree_position(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        position: usize,
+    ) -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::set_tree_position(&conn, collection_id, record_id, position).await
+    }
+
+    async fn create_admin(
+        &self,
+        email: &str,
+        password_hash: &str,
+    ) -> std::result::Result<Admin, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::create_admin(&conn, email, password_hash).await
+    }
+
+    async fn get_admin(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<Admin>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::get_admin(&conn, id).await
+    }
+
+    async fn get_admin_by_email(
+        &self,
+        email: &str,
+    ) -> std::result::Result<Option<Admin>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::get_admin_by_email(&conn, email).await
+    }
+
+    async fn count_admins(
+        &self,
+    ) -> std::result::Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::count_admins(&conn).await
+    }
+
+    async fn list_admins(
+        &self,
+    ) -> std::result::Result<Vec<Admin>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::list_admins(&conn).await
+    }
+
+    async fn create_notification_channel(
+        &self,
+        settings: &ChannelSettings,
+    ) -> std::result::Result<NotificationChannel, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::create_notification_channel(&conn, settings).await
+    }
+
+    async fn get_notification_channel(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<NotificationChannel>, Box<dyn std::error::Error + Send + Sync>>
+    {
+        let conn = self.get().await?;
+        queries::get_notification_channel(&conn, id).await
+    }
+
+    async fn list_notification_channels(
+        &self,
+    ) -> std::result::Result<Vec<NotificationChannel>, Box<dyn std::error::Error + Send + Sync>>
+    {
+        let conn = self.get().await?;
+        queries::list_notification_channels(&conn).await
+    }
+
+    async fn delete_notification_channel(&self, id: i64) -> Result<()> {
+        let conn = self.get().await?;
+        queries::delete_notification_channel(&conn, id).await
+    }
+
+    async fn create_webhook(
+        &self,
+        settings: &WebhookSettings,
+        secret: &str,
+    ) -> std::result::Result<Webhook, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::create_webhook(&conn, settings, secret).await
+    }
+
+    async fn get_webhook(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<Webhook>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::get_webhook(&conn, id).await
+    }
+
+    async fn list_webhooks(
+        &self,
+    ) -> std::result::Result<Vec<Webhook>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::list_webhooks(&conn).await
+    }
+
+    async fn create_api_token(
+        &self,
+        name: &str,
+        scopes: &[TokenScope],
+        token_hash: &str,
+    ) -> std::result::Result<ApiToken, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::create_api_token(&conn, name, scopes, token_hash).await
+    }
+
+    async fn get_api_token(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<ApiToken>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::get_api_token(&conn, id).await
+    }
+
+    async fn get_api_token_by_hash(
+        &self,
+        token_hash: &str,
+    ) -> std::result::Result<Option<ApiToken>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::get_api_token_by_hash(&conn, token_hash).await
+    }
+
+    async fn list_api_tokens(
+        &self,
+    ) -> std::result::Result<Vec<ApiToken>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::list_api_tokens(&conn).await
+    }
+
+    async fn delete_api_token(&self, id: i64) -> Result<()> {
+        let conn = self.get().await?;
+        queries::delete_api_token(&conn, id).await
+    }
+
+    async fn delete_webhook(&self, id: i64) -> Result<()> {
+        let conn = self.get().await?;
+        queries::delete_webhook(&conn, id).await
+    }
+
+    async fn update_webhook(
+        &self,
+        id: i64,
+        settings: &WebhookSettings,
+    ) -> std::result::Result<Webhook, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::update_webhook(&conn, id, settings).await
+    }
+
+    async fn record_webhook_delivery(
+        &self,
+        attempt: &DeliveryAttempt,
+    ) -> std::result::Result<WebhookDelivery, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::record_webhook_delivery(&conn, attempt).await
+    }
+
+    async fn list_webhook_deliveries(
+        &self,
+        webhook_id: i64,
+    ) -> std::result::Result<Vec<WebhookDelivery>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::list_webhook_deliveries(&conn, webhook_id).await
+    }
+
+    async fn get_webhook_delivery(
+        &self,
+        webhook_id: i64,
+        id: i64,
+    ) -> std::result::Result<Option<WebhookDelivery>, Box<dyn std::error::Error + Send + Sync>>
+    {
+        let conn = self.get().await?;
+        queries::get_webhook_delivery(&conn, webhook_id, id).await
+    }
+
+    async fn create_script_hook(
+        &self,
+        settings: &ScriptHookSettings,
+    ) -> std::result::Result<ScriptHook, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::create_script_hook(&conn, settings).await
+    }
+
+    async fn get_script_hook(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<ScriptHook>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::get_script_hook(&conn, id).await
+    }
+
+    async fn list_script_hooks(
+        &self,
+    ) -> std::result::Result<Vec<ScriptHook>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::list_script_hooks(&conn).await
+    }
+
+    async fn delete_script_hook(&self, id: i64) -> Result<()> {
+        let conn = self.get().await?;
+        queries::delete_script_hook(&conn, id).await
+    }
+    async fn create_job(
+        &self,
+        kind: &str,
+        progress: &Value,
+    ) -> std::result::Result<Job, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::create_job(&conn, kind, progress).await
+    }
+    async fn get_job(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<Job>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::get_job(&conn, id).await
+    }
+    async fn update_job(
+        &self,
+        id: i64,
+        state: JobState,
+        progress: Option<&Value>,
+        outcome: Option<&Value>,
+    ) -> Result<bool> {
+        let conn = self.get().await?;
+        queries::update_job(&conn, id, state, progress, outcome).await
+    }
+    async fn fail_unfinished_jobs(&self, error: &Value) -> Result<u64> {
+        let conn = self.get().await?;
+        queries::fail_unfinished_jobs(&conn, error).await
+    }
+    async fn upgrade_record_formats(
+        &self,
+        limit: usize,
+    ) -> std::result::Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::upgrade_record_formats(&conn, limit).await
+    }
+    async fn storage_stats(
+        &self,
+    ) -> std::result::Result<Vec<StorageStats>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::storage_stats(&conn).await
+    }
+    async fn scrub_records(
+        &self,
+        after_id: i64,
+        limit: usize,
+    ) -> std::result::Result<ScrubBatch, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::scrub_records(&conn, after_id, limit).await
+    }
+    async fn list_checksum_mismatches(
+        &self,
+    ) -> std::result::Result<Vec<MismatchReport>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::list_checksum_mismatches(&conn).await
+    }
+    async fn get_kv(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> std::result::Result<Option<KvEntry>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::get_kv(&conn, namespace, key).await
+    }
+    async fn set_kv(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &Value,
+        ttl: Option<Duration>,
+    ) -> std::result::Result<KvEntry, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::set_kv(&conn, namespace, key, value, ttl).await
+    }
+    async fn delete_kv(&self, namespace: &str, key: &str) -> Result<bool> {
+        let conn = self.get().await?;
+        queries::delete_kv(&conn, namespace, key).await
+    }
+
+    async fn claim_idempotency_key(
+        &self,
+        key: &str,
+        collection_id: i64,
+        ttl: Duration,
+    ) -> std::result::Result<IdempotencyClaim, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        queries::claim_idempotency_key(&conn, key, collection_id, ttl).await
+    }
+
+    async fn complete_idempotency_key(&self, key: &str, record_id: i64) -> Result<()> {
+        let conn = self.get().await?;
+        queries::complete_idempotency_key(&conn, key, record_id).await
+    }
+
+    async fn release_idempotency_key(&self, key: &str) -> Result<()> {
+        let conn = self.get().await?;
+        queries::release_idempotency_key(&conn, key).await
+    }
+    async fn ping(&self) -> Result<()> {
+        let conn = self.get().await?;
+        queries::ping(&conn).await
+    }
+
+    async fn backup_into(&self, path: &Path) -> Result<()> {
+        let conn = self.get().await?;
+        snapshot::backup_into(&conn, path).await
+    }
+
+    async fn restore_from(
+        &self,
+        path: &Path,
+    ) -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.get().await?;
+        snapshot::restore_from(&conn, path).await
+    }
+}
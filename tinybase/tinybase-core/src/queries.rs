@@ -0,0 +1,3044 @@
+//! SQL shared by the `Db` implementations, written against a plain
+//! `Connection` so each implementation only decides where the connection
+//! comes from.
+//!
+//! Each query runs in a `debug` span named after it, carrying the ids it was
+//! given, so traces time it and record its error if it fails.
+
+use crate::aggregate::{AggregateGroup, AggregateQuery};
+use crate::arrays::ArrayOp;
+use crate::batch::{BatchError, BatchOperation, BatchResult};
+use crate::checksums::{self, ChecksumMismatch, MismatchReport, ScrubBatch};
+use crate::idempotency::IdempotencyClaim;
+use crate::jobs::{Job, JobState};
+use crate::kv::KvEntry;
+use crate::lineage;
+use crate::notifications::{ChannelSettings, NotificationChannel};
+use crate::ordering::{self, Placement, POSITION_GAP};
+use crate::record_format::{self, StorageStats};
+use crate::schema::{CollectionSchema, DEFAULT_MAX_VERSIONS};
+use crate::scripts::{ScriptHook, ScriptHookSettings};
+use crate::search::{self, SearchHit};
+use crate::tokens::{ApiToken, TokenScope};
+use crate::tree::{self, TreeNode};
+use crate::validation::{get_value_type, ValidationError, ValidationErrorKind};
+use crate::webhooks::{
+    DeliveryAttempt, Webhook, WebhookDelivery, WebhookSettings, DELIVERY_LOG_SIZE,
+};
+use crate::{
+    clock, format_timestamp, timeouts, Admin, Collection, CollectionDeletion, Cursor, ListOptions,
+    QueryError, Record, RecordVersion, SortField, VersionMismatch,
+};
+use libsql::{params, params_from_iter, Connection, Result, Row};
+use serde_json::{Map, Value};
+use std::time::Duration;
+
+type BoxResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Current time on the [`clock`] as stored in the timestamp columns.
+fn clock_timestamp() -> String {
+    format_timestamp(clock::now())
+}
+
+/// [`clock_timestamp`] as a SQL literal.
+fn now() -> String {
+    format!("'{}'", clock_timestamp())
+}
+
+const COLLECTION_COLUMNS: &str = "id, name, schema, created, updated, group_name";
+const RECORD_COLUMNS: &str = "id, data, created, updated, version, lineage, format, checksum";
+const VERSION_COLUMNS: &str =
+    "record_id, version, data, created, updated, archived, deleted, format";
+const ADMIN_COLUMNS: &str = "id, email, password_hash, created, updated";
+const CHANNEL_COLUMNS: &str = "id, settings, created, updated";
+const JOB_COLUMNS: &str = "id, kind, state, progress, outcome, created, updated";
+const WEBHOOK_COLUMNS: &str = "id, settings, secret, created, updated";
+const TOKEN_COLUMNS: &str = "id, name, scopes, created, updated";
+const HOOK_COLUMNS: &str = "id, settings, created, updated";
+const KV_COLUMNS: &str = "namespace, key, value, expires, created, updated";
+const DELIVERY_COLUMNS: &str =
+    "id, webhook_id, event, payload, attempt, status, error, duration_ms, created, response, replay_of";
+
+/// Attaches `sql` to the error of running it.
+fn with_sql(sql: &str) -> impl FnOnce(libsql::Error) -> QueryError + '_ {
+    move |error| QueryError {
+        sql: sql.to_string(),
+        error,
+    }
+}
+
+fn row_to_collection(row: &Row) -> BoxResult<Collection> {
+    let schema_str: Option<String> = row.get(2)?;
+    let schema = match schema_str {
+        Some(s) => serde_json::from_str(&s)?,
+        None => None,
+    };
+    Ok(Collection {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        schema,
+        created: row.get(3)?,
+        updated: row.get(4)?,
+        group: row.get(5)?,
+    })
+}
+
+fn row_to_record(row: &Row) -> BoxResult<Record> {
+    let data_str: String = row.get(1)?;
+    checksums::verify(row.get(0)?, row.get(7)?, &data_str)?;
+    let data = record_format::decode(row.get(6)?, &data_str)?;
+    Ok(Record {
+        id: row.get(0)?,
+        data,
+        created: row.get(2)?,
+        updated: row.get(3)?,
+        version: row.get(4)?,
+        lineage: row
+            .get::<Option<String>>(5)?
+            .map(|lineage| serde_json::from_str(&lineage))
+            .transpose()?,
+    })
+}
+
+fn row_to_version(row: &Row) -> BoxResult<RecordVersion> {
+    let data: String = row.get(2)?;
+    Ok(RecordVersion {
+        record_id: row.get(0)?,
+        version: row.get(1)?,
+        data: record_format::decode(row.get(7)?, &data)?,
+        created: row.get(3)?,
+        updated: row.get(4)?,
+        archived: row.get(5)?,
+        deleted: row.get::<i64>(6)? != 0,
+    })
+}
+
+fn row_to_admin(row: &Row) -> BoxResult<Admin> {
+    Ok(Admin {
+        id: row.get(0)?,
+        email: row.get(1)?,
+        password_hash: row.get(2)?,
+        created: row.get(3)?,
+        updated: row.get(4)?,
+    })
+}
+
+fn row_to_channel(row: &Row) -> BoxResult<NotificationChannel> {
+    let settings: String = row.get(1)?;
+    Ok(NotificationChannel {
+        id: row.get(0)?,
+        settings: serde_json::from_str(&settings)?,
+        created: row.get(2)?,
+        updated: row.get(3)?,
+    })
+}
+
+fn row_to_webhook(row: &Row) -> BoxResult<Webhook> {
+    let settings: String = row.get(1)?;
+    Ok(Webhook {
+        id: row.get(0)?,
+        settings: serde_json::from_str(&settings)?,
+        secret: row.get(2)?,
+        created: row.get(3)?,
+        updated: row.get(4)?,
+    })
+}
+
+fn row_to_token(row: &Row) -> BoxResult<ApiToken> {
+    let scopes: String = row.get(2)?;
+    Ok(ApiToken {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        scopes: serde_json::from_str(&scopes)?,
+        created: row.get(3)?,
+        updated: row.get(4)?,
+    })
+}
+
+fn row_to_hook(row: &Row) -> BoxResult<ScriptHook> {
+    let settings: String = row.get(1)?;
+    Ok(ScriptHook {
+        id: row.get(0)?,
+        settings: serde_json::from_str(&settings)?,
+        created: row.get(2)?,
+        updated: row.get(3)?,
+    })
+}
+
+fn row_to_delivery(row: &Row) -> BoxResult<WebhookDelivery> {
+    let payload: String = row.get(3)?;
+    let status: Option<i64> = row.get(5)?;
+    let duration_ms: i64 = row.get(7)?;
+    Ok(WebhookDelivery {
+        id: row.get(0)?,
+        attempt: DeliveryAttempt {
+            webhook_id: row.get(1)?,
+            event: row.get(2)?,
+            payload: serde_json::from_str(&payload)?,
+            attempt: row.get::<i64>(4)? as u32,
+            status: status.map(|s| s as u16),
+            error: row.get(6)?,
+            response: row.get(9)?,
+            duration_ms: duration_ms as u64,
+            replay_of: row.get(10)?,
+        },
+        created: row.get(8)?,
+    })
+}
+
+fn row_to_job(row: &Row) -> BoxResult<Job> {
+    let state: String = row.get(2)?;
+    let progress: String = row.get(3)?;
+    let outcome: Option<String> = row.get(4)?;
+    Ok(Job {
+        id: row.get(0)?,
+        kind: row.get(1)?,
+        state: JobState::parse(&state).ok_or_else(|| format!("Unknown job state '{}'", state))?,
+        progress: serde_json::from_str(&progress)?,
+        outcome: outcome.map(|o| serde_json::from_str(&o)).transpose()?,
+        created: row.get(5)?,
+        updated: row.get(6)?,
+    })
+}
+
+fn row_to_kv(row: &Row) -> BoxResult<KvEntry> {
+    let value: String = row.get(2)?;
+    Ok(KvEntry {
+        namespace: row.get(0)?,
+        key: row.get(1)?,
+        value: serde_json::from_str(&value)?,
+        expires: row.get(3)?,
+        created: row.get(4)?,
+        updated: row.get(5)?,
+    })
+}
+
+/// Builds a JSON path addressing a top-level field of the record data.
+fn json_path(field: &str) -> String {
+    format!("$.\"{}\"", field.replace('"', "\\\""))
+}
+
+/// `json_extract` of a top-level field with the path written inline, the
+/// expression record indexes are built on: SQLite only uses an expression
+/// index for a query repeating the same expression, not a bound path.
+fn field_sql(field: &str) -> String {
+    format!(
+        "json_extract(data, '{}')",
+        json_path(field).replace('\'', "''")
+    )
+}
+
+/// Prefix of the names of the record indexes of a collection.
+fn record_index_prefix(collection_id: i64) -> String {
+    format!("records_field_{}_", collection_id)
+}
+
+/// The name of the index on `field`; the field is hex-encoded so any name
+/// makes a valid identifier and can be read back.
+fn record_index_name(collection_id: i64, field: &str) -> String {
+    let hex: String = field.bytes().map(|b| format!("{:02x}", b)).collect();
+    format!("{}{}", record_index_prefix(collection_id), hex)
+}
+
+/// Converts a JSON scalar into the value `json_extract` yields for it.
+fn json_to_sql(value: &Value) -> libsql::Value {
+    match value {
+        Value::Null => libsql::Value::Null,
+        Value::Bool(b) => libsql::Value::Integer(*b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => libsql::Value::Integer(i),
+            None => libsql::Value::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => libsql::Value::Text(s.clone()),
+        other => libsql::Value::Text(other.to_string()),
+    }
+}
+
+/// Adds the `created` and `updated` columns to a table created before they
+/// existed, stamping existing rows with the current time.
+async fn table_columns(conn: &Connection, table: &str) -> Result<Vec<String>> {
+    let mut rows = conn
+        .query(
+            &format!("SELECT name FROM pragma_table_info('{}')", table),
+            (),
+        )
+        .await?;
+    let mut columns = Vec::new();
+    while let Some(row) = rows.next().await? {
+        columns.push(row.get::<String>(0)?);
+    }
+    Ok(columns)
+}
+
+pub(crate) async fn add_timestamp_columns(conn: &Connection, table: &str) -> Result<()> {
+    let columns = table_columns(conn, table).await?;
+    for column in ["created", "updated"] {
+        if columns.iter().any(|c| c == column) {
+            continue;
+        }
+        conn.execute(
+            &format!(
+                "ALTER TABLE {} ADD COLUMN {} TEXT NOT NULL DEFAULT ''",
+                table, column
+            ),
+            (),
+        )
+        .await?;
+        conn.execute(&format!("UPDATE {} SET {} = {}", table, column, now()), ())
+            .await?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip(conn, name, schema), fields(collection = name), err)]
+pub(crate) async fn create_collection(
+    conn: &Connection,
+    name: &str,
+    schema: &Option<CollectionSchema>,
+) -> BoxResult<Collection> {
+    let schema_str = serde_json::to_string(&schema)?;
+    conn.execute(
+        &format!(
+            "INSERT INTO collections (name, schema, created, updated) VALUES (?1, ?2, {0}, {0})",
+            now()
+        ),
+        params![name, schema_str],
+    )
+    .await?;
+    let collection = get_collection(conn, conn.last_insert_rowid())
+        .await?
+        .ok_or("Collection not found")?;
+    Ok(collection)
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn get_collection(conn: &Connection, id: i64) -> BoxResult<Option<Collection>> {
+    let mut rows = conn
+        .query(
+            &format!(
+                "SELECT {} FROM collections WHERE id = ?1",
+                COLLECTION_COLUMNS
+            ),
+            params![id],
+        )
+        .await?;
+    let row = match rows.next().await? {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    Ok(Some(row_to_collection(&row)?))
+}
+
+#[tracing::instrument(level = "debug", skip(conn, name), fields(collection = name), err)]
+pub(crate) async fn get_collection_by_name(
+    conn: &Connection,
+    name: &str,
+) -> BoxResult<Option<Collection>> {
+    let mut rows = conn
+        .query(
+            &format!(
+                "SELECT {} FROM collections WHERE name = ?1",
+                COLLECTION_COLUMNS
+            ),
+            params![name],
+        )
+        .await?;
+    let row = match rows.next().await? {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    Ok(Some(row_to_collection(&row)?))
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn list_collections(conn: &Connection) -> BoxResult<Vec<Collection>> {
+    let mut rows = conn
+        .query(
+            &format!("SELECT {} FROM collections", COLLECTION_COLUMNS),
+            (),
+        )
+        .await?;
+    let mut collections = Vec::new();
+    while let Some(row) = rows.next().await? {
+        collections.push(row_to_collection(&row)?);
+    }
+    Ok(collections)
+}
+
+#[tracing::instrument(level = "debug", skip(conn, name, schema), err)]
+pub(crate) async fn update_collection(
+    conn: &Connection,
+    id: i64,
+    name: Option<String>,
+    schema: Option<CollectionSchema>,
+) -> BoxResult<Option<Collection>> {
+    if let Some(name) = name {
+        let updated = conn
+            .execute(
+                &format!(
+                    "UPDATE collections SET name = ?1, updated = {} WHERE id = ?2",
+                    now()
+                ),
+                params![name, id],
+            )
+            .await?;
+        if updated == 0 {
+            return Ok(None);
+        }
+    }
+    if let Some(schema) = schema {
+        let tree_before = tree_field(conn, id).await?;
+        let order_before = order_field(conn, id).await?;
+        let schema_str = serde_json::to_string(&schema)?;
+        let updated = conn
+            .execute(
+                &format!(
+                    "UPDATE collections SET schema = ?1, updated = {} WHERE id = ?2",
+                    now()
+                ),
+                params![schema_str, id],
+            )
+            .await?;
+        if updated == 0 {
+            return Ok(None);
+        }
+        let tree = schema.tree.map(|t| t.parent);
+        if tree != tree_before {
+            rebuild_tree(conn, id, tree.as_deref()).await?;
+        }
+        // Records from before the collection was ordered get positions.
+        if let Some(order) = schema
+            .order
+            .filter(|o| Some(&o.field) != order_before.as_ref())
+        {
+            number_records(conn, id, &order.field).await?;
+        }
+    }
+    get_collection(conn, id).await
+}
+
+/// Adds the `version` column to record tables from before optimistic
+/// locking, numbering every existing record's current state 1.
+pub(crate) async fn add_version_column(conn: &Connection) -> Result<()> {
+    if !table_columns(conn, "records")
+        .await?
+        .iter()
+        .any(|c| c == "version")
+    {
+        conn.execute(
+            "ALTER TABLE records ADD COLUMN version INTEGER NOT NULL DEFAULT 1",
+            (),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Adds the `format` column to records and their versions from before
+/// formats were recorded, marking the rows already there as
+/// [`record_format::RecordFormat::Legacy`].
+pub(crate) async fn add_format_column(conn: &Connection) -> Result<()> {
+    for table in ["records", "record_versions"] {
+        if !table_columns(conn, table)
+            .await?
+            .iter()
+            .any(|c| c == "format")
+        {
+            conn.execute(
+                &format!(
+                    "ALTER TABLE {} ADD COLUMN format INTEGER NOT NULL DEFAULT 0",
+                    table
+                ),
+                (),
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Adds the `lineage` column to record tables from before records kept
+/// their lineage; existing records have none.
+pub(crate) async fn add_lineage_column(conn: &Connection) -> Result<()> {
+    if !table_columns(conn, "records")
+        .await?
+        .iter()
+        .any(|c| c == "lineage")
+    {
+        conn.execute("ALTER TABLE records ADD COLUMN lineage TEXT", ())
+            .await?;
+    }
+    Ok(())
+}
+
+/// Adds the `checksum` column to records from before checksums were kept;
+/// existing records have none until they are written or scrubbed.
+pub(crate) async fn add_checksum_column(conn: &Connection) -> Result<()> {
+    if !table_columns(conn, "records")
+        .await?
+        .iter()
+        .any(|c| c == "checksum")
+    {
+        conn.execute("ALTER TABLE records ADD COLUMN checksum INTEGER", ())
+            .await?;
+    }
+    Ok(())
+}
+
+/// Adds the `response` and `replay_of` columns to delivery logs from before
+/// deliveries could be inspected and replayed.
+pub(crate) async fn add_delivery_columns(conn: &Connection) -> Result<()> {
+    let columns = table_columns(conn, "webhook_deliveries").await?;
+    for (column, kind) in [("response", "TEXT"), ("replay_of", "INTEGER")] {
+        if !columns.iter().any(|c| c == column) {
+            conn.execute(
+                &format!(
+                    "ALTER TABLE webhook_deliveries ADD COLUMN {} {}",
+                    column, kind
+                ),
+                (),
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Adds the `group_name` column to collections created before groups.
+pub(crate) async fn add_group_column(conn: &Connection) -> Result<()> {
+    if !table_columns(conn, "collections")
+        .await?
+        .iter()
+        .any(|c| c == "group_name")
+    {
+        conn.execute("ALTER TABLE collections ADD COLUMN group_name TEXT", ())
+            .await?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn set_collection_group(
+    conn: &Connection,
+    id: i64,
+    group: Option<&str>,
+) -> BoxResult<Collection> {
+    conn.execute(
+        &format!(
+            "UPDATE collections SET group_name = ?1, updated = {} WHERE id = ?2",
+            now()
+        ),
+        params![group, id],
+    )
+    .await?;
+    let collection = get_collection(conn, id)
+        .await?
+        .ok_or("Collection not found")?;
+    Ok(collection)
+}
+
+/// Deletes a collection together with its records, atomically.
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn delete_collection(
+    conn: &Connection,
+    id: i64,
+    force: bool,
+) -> Result<CollectionDeletion> {
+    conn.execute("BEGIN IMMEDIATE", ()).await?;
+    let result = async {
+        let mut rows = conn
+            .query(
+                "SELECT COUNT(*) FROM records WHERE collection_id = ?1",
+                params![id],
+            )
+            .await?;
+        let count: i64 = match rows.next().await? {
+            Some(row) => row.get(0)?,
+            None => 0,
+        };
+        if count > 0 && !force {
+            return Ok(CollectionDeletion::NotEmpty(count));
+        }
+        conn.execute("DELETE FROM records WHERE collection_id = ?1", params![id])
+            .await?;
+        conn.execute(
+            "DELETE FROM record_versions WHERE collection_id = ?1",
+            params![id],
+        )
+        .await?;
+        for table in [
+            "record_tree",
+            "record_search",
+            "checksum_mismatches",
+            "idempotency_keys",
+        ] {
+            conn.execute(
+                &format!("DELETE FROM {} WHERE collection_id = ?1", table),
+                params![id],
+            )
+            .await?;
+        }
+        for index in record_index_names(conn, id).await? {
+            conn.execute(&format!("DROP INDEX IF EXISTS \"{}\"", index), ())
+                .await?;
+        }
+        let deleted = conn
+            .execute("DELETE FROM collections WHERE id = ?1", params![id])
+            .await?;
+        Ok(match deleted {
+            0 => CollectionDeletion::Missing,
+            _ => CollectionDeletion::Deleted(count),
+        })
+    }
+    .await;
+    match result {
+        Ok(CollectionDeletion::Deleted(count)) => {
+            conn.execute("COMMIT", ()).await?;
+            Ok(CollectionDeletion::Deleted(count))
+        }
+        Ok(outcome) => {
+            conn.execute("ROLLBACK", ()).await?;
+            Ok(outcome)
+        }
+        Err(e) => {
+            conn.execute("ROLLBACK", ()).await?;
+            Err(e)
+        }
+    }
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn count_records(conn: &Connection, collection_id: i64) -> BoxResult<i64> {
+    let mut rows = conn
+        .query(
+            "SELECT COUNT(*) FROM records WHERE collection_id = ?1",
+            params![collection_id],
+        )
+        .await?;
+    let row = rows.next().await?.ok_or("COUNT returned no row")?;
+    Ok(row.get(0)?)
+}
+
+#[tracing::instrument(level = "debug", skip(conn, options), err)]
+pub(crate) async fn count_matching_records(
+    conn: &Connection,
+    collection_id: i64,
+    options: &ListOptions,
+) -> BoxResult<i64> {
+    let (filter, values) = record_filter(collection_id, options);
+    let sql = format!("SELECT COUNT(*) FROM records {}", filter);
+    timeouts::limited(conn, async {
+        let mut rows = conn
+            .query(&sql, params_from_iter(values))
+            .await
+            .map_err(with_sql(&sql))?;
+        let row = rows.next().await?.ok_or("COUNT returned no row")?;
+        Ok(row.get(0)?)
+    })
+    .await
+}
+
+/// Converts a column of an aggregate query into JSON.
+fn sql_to_json(value: libsql::Value) -> Value {
+    match value {
+        libsql::Value::Null => Value::Null,
+        libsql::Value::Integer(i) => Value::from(i),
+        libsql::Value::Real(f) => Value::from(f),
+        libsql::Value::Text(s) => Value::String(s),
+        libsql::Value::Blob(b) => Value::from(b),
+    }
+}
+
+#[tracing::instrument(level = "debug", skip(conn, query, options), err)]
+pub(crate) async fn aggregate_records(
+    conn: &Connection,
+    collection_id: i64,
+    query: &AggregateQuery,
+    options: &ListOptions,
+) -> BoxResult<Vec<AggregateGroup>> {
+    // Groups are keyed by the JSON text of their fields, so `true` and `1`
+    // or `"1"` and `1` stay apart.
+    let mut columns = vec!["COUNT(*)".to_string()];
+    let mut values = Vec::new();
+    for field in &query.group_by {
+        columns.push("data -> ?".to_string());
+        values.push(libsql::Value::Text(json_path(field)));
+    }
+    for aggregation in &query.aggregations {
+        columns.push(aggregation.function.sql("json_extract(data, ?)"));
+        values.push(libsql::Value::Text(json_path(&aggregation.field)));
+    }
+    let (filter, filter_values) = record_filter(collection_id, options);
+    values.extend(filter_values);
+    let mut sql = format!("SELECT {} FROM records {}", columns.join(", "), filter);
+    if !query.group_by.is_empty() {
+        let keys: Vec<String> = (2..query.group_by.len() + 2)
+            .map(|i| i.to_string())
+            .collect();
+        sql.push_str(&format!(" GROUP BY {0} ORDER BY {0}", keys.join(", ")));
+    }
+    timeouts::limited(conn, async {
+        let mut rows = conn
+            .query(&sql, params_from_iter(values))
+            .await
+            .map_err(with_sql(&sql))?;
+        let mut groups = Vec::new();
+        while let Some(row) = rows.next().await? {
+            let mut group = Map::new();
+            for (i, field) in query.group_by.iter().enumerate() {
+                let key = match row.get::<Option<String>>(i as i32 + 1)? {
+                    Some(json) => serde_json::from_str(&json)?,
+                    None => Value::Null,
+                };
+                group.insert(field.clone(), key);
+            }
+            let mut results = Map::new();
+            let offset = query.group_by.len() + 1;
+            for (i, aggregation) in query.aggregations.iter().enumerate() {
+                let value = sql_to_json(row.get_value((offset + i) as i32)?);
+                results
+                    .entry(aggregation.function.name())
+                    .or_insert_with(|| Value::Object(Map::new()))
+                    .as_object_mut()
+                    .expect("results are objects")
+                    .insert(aggregation.field.clone(), value);
+            }
+            groups.push(AggregateGroup {
+                group,
+                count: row.get(0)?,
+                values: results,
+            });
+        }
+        Ok(groups)
+    })
+    .await
+}
+
+async fn record_index_names(conn: &Connection, collection_id: i64) -> Result<Vec<String>> {
+    let prefix = record_index_prefix(collection_id);
+    let mut rows = conn
+        .query(
+            "SELECT name FROM sqlite_master WHERE type = 'index' AND tbl_name = 'records'",
+            (),
+        )
+        .await?;
+    let mut names = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let name: String = row.get(0)?;
+        if name.starts_with(&prefix) {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn list_record_indexes(
+    conn: &Connection,
+    collection_id: i64,
+) -> BoxResult<Vec<String>> {
+    let prefix = record_index_prefix(collection_id);
+    let mut fields = Vec::new();
+    for name in record_index_names(conn, collection_id).await? {
+        let hex = &name[prefix.len()..];
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2).unwrap_or_default(), 16))
+            .collect::<std::result::Result<Vec<u8>, _>>()?;
+        fields.push(String::from_utf8(bytes)?);
+    }
+    fields.sort();
+    Ok(fields)
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn create_record_index(
+    conn: &Connection,
+    collection_id: i64,
+    field: &str,
+) -> Result<()> {
+    let sql = format!(
+        "CREATE INDEX IF NOT EXISTS \"{}\" ON records (collection_id, {})",
+        record_index_name(collection_id, field),
+        field_sql(field)
+    );
+    conn.execute(&sql, ()).await?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn drop_record_index(
+    conn: &Connection,
+    collection_id: i64,
+    field: &str,
+) -> Result<()> {
+    let sql = format!(
+        "DROP INDEX IF EXISTS \"{}\"",
+        record_index_name(collection_id, field)
+    );
+    conn.execute(&sql, ()).await?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip(conn, data), err)]
+pub(crate) async fn create_record(
+    conn: &Connection,
+    collection_id: i64,
+    data: &Value,
+) -> BoxResult<Record> {
+    let placed = place_in_order(conn, collection_id, None, data).await?;
+    let (data_str, format) = record_format::encode(
+        placed.as_ref().unwrap_or(data),
+        compress_from(conn, collection_id).await?,
+    )?;
+    let lineage = lineage::current()
+        .map(|lineage| serde_json::to_string(&lineage))
+        .transpose()?;
+    conn.execute(
+        &format!(
+            "INSERT INTO records (collection_id, data, created, updated, lineage, format, checksum) VALUES (?1, ?2, {0}, {0}, ?3, ?4, ?5)",
+            now()
+        ),
+        params![
+            collection_id,
+            data_str.clone(),
+            lineage,
+            format.number(),
+            checksums::checksum(&data_str)
+        ],
+    )
+    .await?;
+    let id = conn.last_insert_rowid();
+    if let Some(field) = tree_field(conn, collection_id).await? {
+        sync_tree_node(conn, collection_id, id, tree::parent_of(data, &field)).await?;
+    }
+    let record = get_record(conn, collection_id, id)
+        .await?
+        .ok_or("Record not found")?;
+    Ok(record)
+}
+
+/// The `WHERE` clause selecting the records of a collection within the
+/// timestamp bounds and lineage filters of `options`, and its parameters.
+fn record_filter(collection_id: i64, options: &ListOptions) -> (String, Vec<libsql::Value>) {
+    let mut sql = "WHERE collection_id = ?".to_string();
+    let mut values = vec![libsql::Value::Integer(collection_id)];
+    if let Some(after) = options.after_id {
+        sql.push_str(" AND id > ?");
+        values.push(libsql::Value::Integer(after));
+    }
+    let after = if options.descending { "<" } else { ">" };
+    match &options.cursor {
+        Some(Cursor::Id(id)) => {
+            sql.push_str(&format!(" AND id {} ?", after));
+            values.push(libsql::Value::Integer(*id));
+        }
+        Some(Cursor::Created(created, id)) => {
+            sql.push_str(&format!(" AND (created, id) {} (?, ?)", after));
+            values.push(libsql::Value::Text(created.clone()));
+            values.push(libsql::Value::Integer(*id));
+        }
+        None => {}
+    }
+    let bounds = [
+        ("created >", &options.created_after),
+        ("created <", &options.created_before),
+        ("updated >", &options.updated_after),
+        ("updated <", &options.updated_before),
+    ];
+    for (condition, bound) in bounds {
+        if let Some(bound) = bound {
+            sql.push_str(&format!(" AND {} ?", condition));
+            values.push(libsql::Value::Text(bound.clone()));
+        }
+    }
+    if let Some(source) = options.source {
+        sql.push_str(" AND lineage ->> '$.source' = ?");
+        values.push(libsql::Value::Text(source.name().to_string()));
+    }
+    if let Some(job) = options.source_job {
+        sql.push_str(" AND lineage ->> '$.job' = ?");
+        values.push(libsql::Value::Integer(job));
+    }
+    if let Some(actor) = &options.source_actor {
+        sql.push_str(" AND lineage ->> '$.actor' = ?");
+        values.push(libsql::Value::Text(actor.clone()));
+    }
+    (sql, values)
+}
+
+#[tracing::instrument(level = "debug", skip(conn, options), err)]
+pub(crate) async fn list_records(
+    conn: &Connection,
+    collection_id: i64,
+    options: &ListOptions,
+) -> BoxResult<Vec<Record>> {
+    let (filter, filter_values) = record_filter(collection_id, options);
+    let (columns, mut values) = match &options.fields {
+        // Rebuilds the data from the wanted members only; `json(data -> ..)`
+        // keeps each member's JSON type, which `value` loses for booleans.
+        // Compressed members come along, to be picked from once unpacked.
+        Some(fields) => (
+            format!(
+                "id, (SELECT json_group_object(e.key, json(data -> e.fullkey)) \
+                 FROM json_each(data) AS e WHERE e.key IN ({})), created, updated, version, lineage, format, NULL",
+                vec!["?"; fields.len() + 1].join(", ")
+            ),
+            fields
+                .iter()
+                .map(String::as_str)
+                .chain([record_format::PACKED_KEY])
+                .map(|f| libsql::Value::Text(f.to_string()))
+                .collect(),
+        ),
+        None => (RECORD_COLUMNS.to_string(), Vec::new()),
+    };
+    values.extend(filter_values);
+    let mut sql = format!("SELECT {} FROM records {}", columns, filter);
+    let column = match options.sort {
+        SortField::Id => "id".to_string(),
+        SortField::Created => "created".to_string(),
+        SortField::Updated => "updated".to_string(),
+        SortField::Position => match order_field(conn, collection_id).await? {
+            Some(field) => field_sql(&field),
+            None => "id".to_string(),
+        },
+    };
+    let direction = if options.descending { "DESC" } else { "ASC" };
+    sql.push_str(&format!(" ORDER BY {0} {1}, id {1}", column, direction));
+    if let Some(limit) = options.limit {
+        sql.push_str(" LIMIT ? OFFSET ?");
+        values.push(libsql::Value::Integer(limit as i64));
+        values.push(libsql::Value::Integer(options.offset as i64));
+    } else if options.offset > 0 {
+        sql.push_str(" LIMIT -1 OFFSET ?");
+        values.push(libsql::Value::Integer(options.offset as i64));
+    }
+    timeouts::limited(conn, async {
+        let mut rows = conn
+            .query(&sql, params_from_iter(values))
+            .await
+            .map_err(with_sql(&sql))?;
+        let mut records = Vec::new();
+        while let Some(row) = rows.next().await? {
+            let mut record = row_to_record(&row)?;
+            if let (Some(fields), Value::Object(data)) = (&options.fields, &mut record.data) {
+                data.retain(|key, _| fields.contains(key));
+            }
+            records.push(record);
+        }
+        Ok(records)
+    })
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip(conn, value), err)]
+pub(crate) async fn find_records_by_field(
+    conn: &Connection,
+    collection_id: i64,
+    field: &str,
+    value: &Value,
+) -> BoxResult<Vec<Record>> {
+    let sql = format!(
+        "SELECT {} FROM records WHERE collection_id = ?1 AND {} = ?2",
+        RECORD_COLUMNS,
+        field_sql(field)
+    );
+    timeouts::limited(conn, async {
+        let mut rows = conn
+            .query(&sql, params![collection_id, json_to_sql(value)])
+            .await
+            .map_err(with_sql(&sql))?;
+        let mut records = Vec::new();
+        while let Some(row) = rows.next().await? {
+            records.push(row_to_record(&row)?);
+        }
+        Ok(records)
+    })
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip(conn, value, data), err)]
+pub(crate) async fn get_or_create_record(
+    conn: &Connection,
+    collection_id: i64,
+    field: &str,
+    value: &Value,
+    data: &Value,
+) -> BoxResult<(Record, bool)> {
+    // IMMEDIATE takes the write lock before the lookup, so no other
+    // connection can create the record between it and the insert.
+    conn.execute("BEGIN IMMEDIATE", ()).await?;
+    let result = async {
+        let existing = find_records_by_field(conn, collection_id, field, value)
+            .await?
+            .into_iter()
+            .min_by_key(|r| r.id);
+        match existing {
+            Some(record) => Ok((record, false)),
+            None => Ok((create_record(conn, collection_id, data).await?, true)),
+        }
+    }
+    .await;
+    conn.execute(if result.is_ok() { "COMMIT" } else { "ROLLBACK" }, ())
+        .await?;
+    result
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn get_record(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+) -> BoxResult<Option<Record>> {
+    let mut rows = conn
+        .query(
+            &format!(
+                "SELECT {} FROM records WHERE collection_id = ?1 AND id = ?2",
+                RECORD_COLUMNS
+            ),
+            params![collection_id, record_id],
+        )
+        .await?;
+    let row = match rows.next().await? {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let record = row_to_record(&row)?;
+    if row.get::<i64>(6)? < record_format::CURRENT.number() {
+        // Best effort: a read replica, for one, can't be written to.
+        if let Err(e) = upgrade_record_format(conn, &record).await {
+            tracing::debug!(record = record.id, error = %e, "failed to upgrade a record format");
+        }
+    }
+    Ok(Some(record))
+}
+
+/// Rewrites the data of `record`, as read, in the current format, leaving
+/// its version and timestamps alone.
+async fn upgrade_record_format(conn: &Connection, record: &Record) -> BoxResult<()> {
+    let (data, format) = record_format::encode(&record.data, None)?;
+    conn.execute(
+        "UPDATE records SET data = ?1, format = ?2, checksum = ?4 WHERE id = ?3 AND format < ?2",
+        params![
+            data.clone(),
+            format.number(),
+            record.id,
+            checksums::checksum(&data)
+        ],
+    )
+    .await?;
+    Ok(())
+}
+
+/// How much room the records of each collection take, in collection order.
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn storage_stats(conn: &Connection) -> BoxResult<Vec<StorageStats>> {
+    let mut rows = conn
+        .query(
+            "SELECT collection_id, COUNT(*), SUM(format = ?1), SUM(length(CAST(data AS BLOB))), \
+             COALESCE(SUM(CASE WHEN format = ?1 THEN \
+             json_extract(data, ?2) - length(json_extract(data, ?3)) END), 0) \
+             FROM records GROUP BY collection_id ORDER BY collection_id",
+            params![
+                record_format::RecordFormat::Packed.number(),
+                format!("$.{}.size", record_format::PACKED_KEY),
+                format!("$.{}.zstd", record_format::PACKED_KEY)
+            ],
+        )
+        .await?;
+    let mut stats = Vec::new();
+    while let Some(row) = rows.next().await? {
+        stats.push(StorageStats {
+            collection_id: row.get(0)?,
+            records: row.get::<i64>(1)? as u64,
+            packed: row.get::<i64>(2)? as u64,
+            data_bytes: row.get::<i64>(3)? as u64,
+            saved_bytes: row.get::<i64>(4)?.max(0) as u64,
+        });
+    }
+    Ok(stats)
+}
+
+/// Checks the checksums of up to `limit` records after `after_id`, in id
+/// order, see [`checksums`]. A scrub starting from 0 first forgets the
+/// mismatches the previous one found.
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn scrub_records(
+    conn: &Connection,
+    after_id: i64,
+    limit: usize,
+) -> BoxResult<ScrubBatch> {
+    if after_id == 0 {
+        conn.execute("DELETE FROM checksum_mismatches", ()).await?;
+    }
+    let mut rows = conn
+        .query(
+            "SELECT id, collection_id, data, checksum FROM records WHERE id > ?1 ORDER BY id LIMIT ?2",
+            params![after_id, limit as i64],
+        )
+        .await?;
+    let mut records = Vec::new();
+    while let Some(row) = rows.next().await? {
+        records.push((
+            row.get::<i64>(0)?,
+            row.get::<i64>(1)?,
+            row.get::<String>(2)?,
+            row.get::<Option<i64>>(3)?,
+        ));
+    }
+    drop(rows);
+    let mut batch = ScrubBatch::default();
+    for (id, collection_id, data, stored) in records {
+        batch.last_id = Some(id);
+        batch.checked += 1;
+        let computed = checksums::checksum(&data);
+        match stored {
+            None => {
+                batch.sealed += conn
+                    .execute(
+                        "UPDATE records SET checksum = ?1 WHERE id = ?2 AND checksum IS NULL",
+                        params![computed, id],
+                    )
+                    .await?;
+            }
+            Some(stored) if stored != computed => {
+                conn.execute(
+                    &format!(
+                        "INSERT OR REPLACE INTO checksum_mismatches (record_id, collection_id, stored, computed, detected) VALUES (?1, ?2, ?3, ?4, {})",
+                        now()
+                    ),
+                    params![id, collection_id, stored, computed],
+                )
+                .await?;
+                batch.mismatches += 1;
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(batch)
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn list_checksum_mismatches(conn: &Connection) -> BoxResult<Vec<MismatchReport>> {
+    let mut rows = conn
+        .query(
+            "SELECT record_id, collection_id, stored, computed, detected FROM checksum_mismatches ORDER BY record_id",
+            (),
+        )
+        .await?;
+    let mut reports = Vec::new();
+    while let Some(row) = rows.next().await? {
+        reports.push(MismatchReport {
+            collection_id: row.get(1)?,
+            mismatch: ChecksumMismatch {
+                record_id: row.get(0)?,
+                stored: row.get(2)?,
+                computed: row.get(3)?,
+            },
+            detected: row.get(4)?,
+        });
+    }
+    Ok(reports)
+}
+
+/// Rewrites up to `limit` records stored in an older format than the
+/// current one. Returns how many were rewritten.
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn upgrade_record_formats(conn: &Connection, limit: usize) -> BoxResult<u64> {
+    let mut rows = conn
+        .query(
+            &format!(
+                "SELECT {} FROM records WHERE format < ?1 ORDER BY id LIMIT ?2",
+                RECORD_COLUMNS
+            ),
+            params![record_format::CURRENT.number(), limit as i64],
+        )
+        .await?;
+    let mut records = Vec::new();
+    while let Some(row) = rows.next().await? {
+        records.push(row_to_record(&row)?);
+    }
+    for record in &records {
+        upgrade_record_format(conn, record).await?;
+    }
+    Ok(records.len() as u64)
+}
+
+#[tracing::instrument(level = "debug", skip(conn, data), err)]
+pub(crate) async fn update_record(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+    data: &Value,
+) -> BoxResult<Option<Record>> {
+    // Clients can't write the position of ordered records, only move them.
+    let placed = place_in_order(conn, collection_id, Some(record_id), data).await?;
+    let (data_str, format) = record_format::encode(
+        placed.as_ref().unwrap_or(data),
+        compress_from(conn, collection_id).await?,
+    )?;
+    let tree_field = tree_field(conn, collection_id).await?;
+    if let Some(field) = &tree_field {
+        check_tree_parent(conn, collection_id, record_id, field, data).await?;
+    }
+    archive_record(conn, collection_id, record_id, false).await?;
+    let updated = conn
+        .execute(
+            &format!(
+                "UPDATE records SET data = ?1, format = ?4, checksum = ?5, updated = {}, version = version + 1 WHERE collection_id = ?2 AND id = ?3",
+                now()
+            ),
+            params![
+                data_str.clone(),
+                collection_id,
+                record_id,
+                format.number(),
+                checksums::checksum(&data_str)
+            ],
+        )
+        .await?;
+    if updated == 0 {
+        return Ok(None);
+    }
+    if let Some(field) = &tree_field {
+        sync_tree_node(conn, collection_id, record_id, tree::parent_of(data, field)).await?;
+    }
+    get_record(conn, collection_id, record_id).await
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn increment_field(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+    field: &str,
+    delta: &serde_json::Number,
+    (min, max): (Option<f64>, Option<f64>),
+) -> BoxResult<Option<Record>> {
+    let path = json_path(field);
+    let delta_value = json_to_sql(&Value::Number(delta.clone()));
+    // The sum is computed by SQLite, so no other write can come between
+    // reading the old value and storing the new one.
+    let sum = "COALESCE(json_extract(data, ?3), 0) + ?4";
+    let updated = update_in_place(
+        conn,
+        collection_id,
+        record_id,
+        &format!(
+            "UPDATE records SET data = json_set(data, ?3, {sum}), updated = {now}, version = version + 1 \
+             WHERE collection_id = ?1 AND id = ?2 \
+             AND COALESCE(json_type(data, ?3), 'null') IN ('integer', 'real', 'null') \
+             AND (?5 IS NULL OR {sum} >= ?5) AND (?6 IS NULL OR {sum} <= ?6)",
+            sum = sum,
+            now = now()
+        ),
+        params![collection_id, record_id, path, delta_value, min, max],
+    )
+    .await?;
+    let Some(record) = get_record(conn, collection_id, record_id).await? else {
+        return Ok(None);
+    };
+    if updated {
+        return Ok(Some(record));
+    }
+    // Refused: tell why from the value the record holds now.
+    let current = record.data.get(field).unwrap_or(&Value::Null);
+    let (kind, value) = match current {
+        Value::Number(_) | Value::Null => {
+            let sum = current.as_f64().unwrap_or(0.0) + delta.as_f64().unwrap_or(0.0);
+            let kind = match min.filter(|min| sum < *min) {
+                Some(min) => ValidationErrorKind::BelowMinimum { min },
+                None => ValidationErrorKind::AboveMaximum {
+                    max: max.unwrap_or(sum),
+                },
+            };
+            (kind, serde_json::Number::from_f64(sum).map(Value::Number))
+        }
+        other => (
+            ValidationErrorKind::InvalidType {
+                expected: "number".to_string(),
+                got: get_value_type(other),
+            },
+            Some(other.clone()),
+        ),
+    };
+    Err(Box::new(ValidationError::field(field, kind, value)))
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn update_array(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+    field: &str,
+    op: ArrayOp,
+    value: &Value,
+) -> BoxResult<Option<Record>> {
+    let path = json_path(field);
+    let value_json = serde_json::to_string(value)?;
+    // Elements are compared by their JSON text, which `->` gives for any type.
+    let array = "CASE json_type(data, ?3) WHEN 'array' THEN data -> ?3 ELSE json_array() END";
+    let pushed = format!("json_insert({}, '$[#]', json(?4))", array);
+    let new_array = match op {
+        ArrayOp::Push => pushed,
+        ArrayOp::AddUnique => format!(
+            "CASE WHEN json_type(data, ?3) = 'array' AND EXISTS \
+             (SELECT 1 FROM json_each(data, ?3) AS e WHERE data -> e.fullkey = json(?4)) \
+             THEN json({}) ELSE {} END",
+            array, pushed
+        ),
+        ArrayOp::Remove => "CASE json_type(data, ?3) WHEN 'array' THEN \
+             (SELECT json_group_array(json(data -> e.fullkey)) FROM json_each(data, ?3) AS e \
+             WHERE data -> e.fullkey <> json(?4)) ELSE json_array() END"
+            .to_string(),
+    };
+    let updated = update_in_place(
+        conn,
+        collection_id,
+        record_id,
+        &format!(
+            "UPDATE records SET data = json_set(data, ?3, {}), updated = {}, version = version + 1 \
+             WHERE collection_id = ?1 AND id = ?2 \
+             AND COALESCE(json_type(data, ?3), 'null') IN ('array', 'null')",
+            new_array,
+            now()
+        ),
+        params![collection_id, record_id, path, value_json],
+    )
+    .await?;
+    let Some(record) = get_record(conn, collection_id, record_id).await? else {
+        return Ok(None);
+    };
+    if updated {
+        return Ok(Some(record));
+    }
+    let current = record.data.get(field).unwrap_or(&Value::Null);
+    Err(Box::new(ValidationError::field(
+        field,
+        ValidationErrorKind::InvalidType {
+            expected: "array".to_string(),
+            got: get_value_type(current),
+        },
+        Some(current.clone()),
+    )))
+}
+
+/// Archives a record and runs `sql`, an update of it in place, in one
+/// transaction that is only committed when the update changed the record.
+/// Whether it did.
+async fn update_in_place(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+    sql: &str,
+    params: impl libsql::params::IntoParams,
+) -> BoxResult<bool> {
+    conn.execute("BEGIN", ()).await?;
+    let result = async {
+        unpack_record(conn, collection_id, record_id).await?;
+        archive_record(conn, collection_id, record_id, false).await?;
+        let updated = conn.execute(sql, params).await?;
+        if updated > 0 {
+            seal_record(conn, record_id).await?;
+        }
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(updated)
+    }
+    .await;
+    match result {
+        Ok(updated) => {
+            conn.execute(if updated > 0 { "COMMIT" } else { "ROLLBACK" }, ())
+                .await?;
+            Ok(updated > 0)
+        }
+        Err(e) => {
+            conn.execute("ROLLBACK", ()).await?;
+            Err(e)
+        }
+    }
+}
+
+/// Rewrites a record stored packed as plain JSON, so SQL changing its data
+/// in place sees the members that were compressed. Its next full write
+/// packs it again.
+async fn unpack_record(conn: &Connection, collection_id: i64, record_id: i64) -> BoxResult<()> {
+    let packed = record_format::RecordFormat::Packed.number();
+    let raw = conn
+        .query(
+            "SELECT data FROM records WHERE collection_id = ?1 AND id = ?2 AND format = ?3",
+            params![collection_id, record_id, packed],
+        )
+        .await?
+        .next()
+        .await?
+        .map(|row| row.get::<String>(0))
+        .transpose()?;
+    if let Some(raw) = raw {
+        let (data, format) = record_format::encode(&record_format::decode(packed, &raw)?, None)?;
+        conn.execute(
+            "UPDATE records SET data = ?1, format = ?2, checksum = ?3 WHERE id = ?4",
+            params![
+                data.clone(),
+                format.number(),
+                checksums::checksum(&data),
+                record_id
+            ],
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Stores the checksum of a record whose data SQL changed in place.
+async fn seal_record(conn: &Connection, record_id: i64) -> Result<()> {
+    let data = conn
+        .query("SELECT data FROM records WHERE id = ?1", params![record_id])
+        .await?
+        .next()
+        .await?
+        .map(|row| row.get::<String>(0))
+        .transpose()?;
+    if let Some(data) = data {
+        conn.execute(
+            "UPDATE records SET checksum = ?1 WHERE id = ?2",
+            params![checksums::checksum(&data), record_id],
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn delete_record(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+) -> Result<u64> {
+    archive_record(conn, collection_id, record_id, true).await?;
+    let deleted = conn
+        .execute(
+            "DELETE FROM records WHERE collection_id = ?1 AND id = ?2",
+            params![collection_id, record_id],
+        )
+        .await?;
+    remove_tree_node(conn, collection_id, record_id).await?;
+    Ok(deleted)
+}
+
+/// Runs `write` in an `IMMEDIATE` transaction if the record is at `version`,
+/// so no other write can come between the check and `write`. `None` when
+/// there is no such record.
+async fn at_version<T>(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+    version: i64,
+    write: impl std::future::Future<Output = BoxResult<T>>,
+) -> BoxResult<Option<T>> {
+    conn.execute("BEGIN IMMEDIATE", ()).await?;
+    let result = async {
+        let mut rows = conn
+            .query(
+                "SELECT version FROM records WHERE collection_id = ?1 AND id = ?2",
+                params![collection_id, record_id],
+            )
+            .await?;
+        let current: i64 = match rows.next().await? {
+            Some(row) => row.get(0)?,
+            None => return Ok(None),
+        };
+        if current != version {
+            return Err(VersionMismatch {
+                record_id,
+                expected: version,
+                current,
+            }
+            .into());
+        }
+        write.await.map(Some)
+    }
+    .await;
+    conn.execute(if result.is_ok() { "COMMIT" } else { "ROLLBACK" }, ())
+        .await?;
+    result
+}
+
+#[tracing::instrument(level = "debug", skip(conn, data), err)]
+pub(crate) async fn update_record_at(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+    version: i64,
+    data: &Value,
+) -> BoxResult<Option<Record>> {
+    let write = update_record(conn, collection_id, record_id, data);
+    Ok(at_version(conn, collection_id, record_id, version, write)
+        .await?
+        .flatten())
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn delete_record_at(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+    version: i64,
+) -> BoxResult<u64> {
+    let write = async { Ok(delete_record(conn, collection_id, record_id).await?) };
+    Ok(at_version(conn, collection_id, record_id, version, write)
+        .await?
+        .unwrap_or(0))
+}
+
+/// Copies the current state of a record into `record_versions`, then drops
+/// the versions beyond the collection's `max_versions`.
+async fn archive_record(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+    deleted: bool,
+) -> Result<()> {
+    let latest =
+        "SELECT MAX(version) FROM record_versions WHERE collection_id = ?1 AND record_id = ?2";
+    conn.execute(
+        &format!(
+            "INSERT INTO record_versions ({}, collection_id) SELECT id, COALESCE(({}), 0) + 1, data, created, updated, {}, ?3, format, collection_id FROM records WHERE collection_id = ?1 AND id = ?2",
+            VERSION_COLUMNS, latest, now()
+        ),
+        params![collection_id, record_id, deleted],
+    )
+    .await?;
+    conn.execute(
+        &format!(
+            "DELETE FROM record_versions WHERE collection_id = ?1 AND record_id = ?2 AND version <= ({}) - COALESCE((SELECT json_extract(schema, '$.max_versions') FROM collections WHERE id = ?1), ?3)",
+            latest
+        ),
+        params![collection_id, record_id, DEFAULT_MAX_VERSIONS],
+    )
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn list_record_versions(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+) -> BoxResult<Vec<RecordVersion>> {
+    let mut rows = conn
+        .query(
+            &format!(
+                "SELECT {} FROM record_versions WHERE collection_id = ?1 AND record_id = ?2 ORDER BY version",
+                VERSION_COLUMNS
+            ),
+            params![collection_id, record_id],
+        )
+        .await?;
+    let mut versions = Vec::new();
+    while let Some(row) = rows.next().await? {
+        versions.push(row_to_version(&row)?);
+    }
+    Ok(versions)
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn get_record_version(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+    version: i64,
+) -> BoxResult<Option<RecordVersion>> {
+    let mut rows = conn
+        .query(
+            &format!(
+                "SELECT {} FROM record_versions WHERE collection_id = ?1 AND record_id = ?2 AND version = ?3",
+                VERSION_COLUMNS
+            ),
+            params![collection_id, record_id, version],
+        )
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some(row_to_version(&row)?)),
+        None => Ok(None),
+    }
+}
+
+#[tracing::instrument(level = "debug", skip(conn, version), err)]
+pub(crate) async fn restore_record_version(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+    version: &RecordVersion,
+) -> BoxResult<Record> {
+    if let Some(record) = update_record(conn, collection_id, record_id, &version.data).await? {
+        return Ok(record);
+    }
+    let record = Record {
+        id: record_id,
+        data: version.data.clone(),
+        created: version.created.clone(),
+        updated: clock_timestamp(),
+        // Past any version the record had before it was deleted.
+        version: version.version + 1,
+        lineage: lineage::current(),
+    };
+    if !insert_record(conn, collection_id, &record).await? {
+        return Err(format!("Record id {} is taken", record_id).into());
+    }
+    Ok(record)
+}
+
+/// Inserts `record` with its id and timestamps. Returns `false`, inserting
+/// nothing, if a record of any collection already has the id.
+#[tracing::instrument(level = "debug", skip(conn, record), fields(record_id = record.id), err)]
+pub(crate) async fn insert_record(
+    conn: &Connection,
+    collection_id: i64,
+    record: &Record,
+) -> BoxResult<bool> {
+    let (data, format) =
+        record_format::encode(&record.data, compress_from(conn, collection_id).await?)?;
+    let inserted = conn
+        .execute(
+            "INSERT OR IGNORE INTO records (id, collection_id, data, created, updated, version, lineage, format, checksum) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                record.id,
+                collection_id,
+                data.clone(),
+                record.created.clone(),
+                record.updated.clone(),
+                record.version,
+                record
+                    .lineage
+                    .as_ref()
+                    .map(serde_json::to_string)
+                    .transpose()?,
+                format.number(),
+                checksums::checksum(&data)
+            ],
+        )
+        .await?;
+    if inserted == 0 {
+        return Ok(false);
+    }
+    if let Some(field) = tree_field(conn, collection_id).await? {
+        let parent = tree::parent_of(&record.data, &field);
+        sync_tree_node(conn, collection_id, record.id, parent).await?;
+    }
+    Ok(true)
+}
+
+/// The field the records of a collection keep their position in, if the
+/// collection is ordered.
+async fn order_field(conn: &Connection, collection_id: i64) -> Result<Option<String>> {
+    let mut rows = conn
+        .query(
+            "SELECT json_extract(schema, '$.order.field') FROM collections WHERE id = ?1",
+            params![collection_id],
+        )
+        .await?;
+    match rows.next().await? {
+        Some(row) => row.get(0),
+        None => Ok(None),
+    }
+}
+
+/// The position after the last record of a collection.
+async fn next_position(conn: &Connection, collection_id: i64, field: &str) -> Result<i64> {
+    let mut rows = conn
+        .query(
+            "SELECT CAST(MAX(json_extract(data, ?2)) AS INTEGER) FROM records WHERE collection_id = ?1",
+            params![collection_id, json_path(field)],
+        )
+        .await?;
+    let last = match rows.next().await? {
+        Some(row) => row.get::<Option<i64>>(0)?,
+        None => None,
+    };
+    Ok(ordering::position_between(last, None).unwrap_or(POSITION_GAP))
+}
+
+/// `data` with the position the server manages in ordered collections: the
+/// stored one of `record_id`, or after the last record for a new record.
+/// `None` if the collection isn't ordered.
+async fn place_in_order(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: Option<i64>,
+    data: &Value,
+) -> Result<Option<Value>> {
+    let Some(field) = order_field(conn, collection_id).await? else {
+        return Ok(None);
+    };
+    let stored = match record_id {
+        Some(record_id) => position_of(conn, collection_id, record_id, &field).await?,
+        None => None,
+    };
+    let position = match stored {
+        Some(position) => position,
+        None => next_position(conn, collection_id, &field).await?,
+    };
+    let mut data = data.clone();
+    if let Some(map) = data.as_object_mut() {
+        map.insert(field, position.into());
+    }
+    Ok(Some(data))
+}
+
+async fn position_of(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+    field: &str,
+) -> Result<Option<i64>> {
+    let mut rows = conn
+        .query(
+            "SELECT CAST(json_extract(data, ?3) AS INTEGER) FROM records WHERE collection_id = ?1 AND id = ?2",
+            params![collection_id, record_id, json_path(field)],
+        )
+        .await?;
+    match rows.next().await? {
+        Some(row) => row.get(0),
+        None => Ok(None),
+    }
+}
+
+/// Spaces the positions of a collection's records [`POSITION_GAP`] apart,
+/// keeping their order; records without one go last, in id order.
+async fn renumber_records(conn: &Connection, collection_id: i64, field: &str) -> Result<()> {
+    let path = json_path(field);
+    let mut rows = conn
+        .query(
+            "SELECT id FROM records WHERE collection_id = ?1 ORDER BY json_extract(data, ?2) IS NULL, json_extract(data, ?2), id",
+            params![collection_id, path.clone()],
+        )
+        .await?;
+    let mut ids = Vec::new();
+    while let Some(row) = rows.next().await? {
+        ids.push(row.get::<i64>(0)?);
+    }
+    drop(rows);
+    for (index, id) in ids.into_iter().enumerate() {
+        conn.execute(
+            &format!(
+                "UPDATE records SET data = json_set(data, ?1, ?2), updated = {}, version = version + 1 WHERE id = ?3 AND json_extract(data, ?1) IS NOT ?2",
+                now()
+            ),
+            params![path.clone(), (index as i64 + 1) * POSITION_GAP, id],
+        )
+        .await?;
+        seal_record(conn, id).await?;
+    }
+    Ok(())
+}
+
+/// [`renumber_records`] in a transaction.
+async fn number_records(conn: &Connection, collection_id: i64, field: &str) -> Result<()> {
+    conn.execute("BEGIN", ()).await?;
+    match renumber_records(conn, collection_id, field).await {
+        Ok(()) => conn.execute("COMMIT", ()).await.map(|_| ()),
+        Err(e) => {
+            conn.execute("ROLLBACK", ()).await?;
+            Err(e)
+        }
+    }
+}
+
+/// The positions of the records around the gap `placement` names, leaving
+/// out the record being moved.
+async fn gap_at(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+    field: &str,
+    placement: Placement,
+) -> BoxResult<(Option<i64>, Option<i64>)> {
+    let anchor = placement.anchor();
+    let position = position_of(conn, collection_id, anchor, field)
+        .await?
+        .ok_or_else(|| format!("Record {} not found", anchor))?;
+    let (neighbour, bound) = match placement {
+        Placement::Before(_) => ("MAX", "<"),
+        Placement::After(_) => ("MIN", ">"),
+    };
+    let sql = format!(
+        "SELECT CAST({0}(json_extract(data, ?3)) AS INTEGER) FROM records WHERE collection_id = ?1 AND id != ?2 AND json_extract(data, ?3) {1} ?4",
+        neighbour, bound
+    );
+    let mut rows = conn
+        .query(
+            &sql,
+            params![collection_id, record_id, json_path(field), position],
+        )
+        .await?;
+    let neighbour = match rows.next().await? {
+        Some(row) => row.get::<Option<i64>>(0)?,
+        None => None,
+    };
+    Ok(match placement {
+        Placement::Before(_) => (neighbour, Some(position)),
+        Placement::After(_) => (Some(position), neighbour),
+    })
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn move_record(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+    placement: Placement,
+) -> BoxResult<Record> {
+    let field = order_field(conn, collection_id)
+        .await?
+        .ok_or("Collection is not ordered")?;
+    if placement.anchor() != record_id {
+        conn.execute("BEGIN", ()).await?;
+        let result = async {
+            // A used-up gap takes renumbering, which always opens it.
+            let (low, high) = gap_at(conn, collection_id, record_id, &field, placement).await?;
+            let position = match ordering::position_between(low, high) {
+                Some(position) => position,
+                None => {
+                    renumber_records(conn, collection_id, &field).await?;
+                    let (low, high) =
+                        gap_at(conn, collection_id, record_id, &field, placement).await?;
+                    ordering::position_between(low, high).ok_or("No free position")?
+                }
+            };
+            conn.execute(
+                &format!(
+                    "UPDATE records SET data = json_set(data, ?1, ?2), updated = {}, version = version + 1 WHERE collection_id = ?3 AND id = ?4",
+                    now()
+                ),
+                params![json_path(&field), position, collection_id, record_id],
+            )
+            .await?;
+            seal_record(conn, record_id).await?;
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+        }
+        .await;
+        match result {
+            Ok(()) => {
+                conn.execute("COMMIT", ()).await?;
+            }
+            Err(e) => {
+                conn.execute("ROLLBACK", ()).await?;
+                return Err(e);
+            }
+        }
+    }
+    let record = get_record(conn, collection_id, record_id)
+        .await?
+        .ok_or("Record not found")?;
+    Ok(record)
+}
+
+/// The columns of a [`TreeNode`], read from `record_tree t JOIN records r`.
+const TREE_COLUMNS: &str = "r.id, r.data, r.created, r.updated, r.version, r.lineage, r.format, r.checksum, CASE WHEN t.depth = 0 THEN NULL ELSE t.parent_id END, t.path, t.depth, t.position";
+
+fn row_to_tree_node(row: &Row) -> BoxResult<TreeNode> {
+    Ok(TreeNode {
+        record: row_to_record(row)?,
+        parent: row.get(8)?,
+        path: row.get(9)?,
+        depth: row.get::<u32>(10)?,
+        position: row.get(11)?,
+    })
+}
+
+/// The field the records of a collection name their parent with, if the
+/// collection is a tree.
+async fn tree_field(conn: &Connection, collection_id: i64) -> Result<Option<String>> {
+    let mut rows = conn
+        .query(
+            "SELECT json_extract(schema, '$.tree.parent') FROM collections WHERE id = ?1",
+            params![collection_id],
+        )
+        .await?;
+    match rows.next().await? {
+        Some(row) => row.get(0),
+        None => Ok(None),
+    }
+}
+
+/// The size from which the records of a collection are packed, if the
+/// collection compresses them.
+async fn compress_from(conn: &Connection, collection_id: i64) -> Result<Option<usize>> {
+    let mut rows = conn
+        .query(
+            "SELECT CASE WHEN json_type(schema, '$.compress') = 'object' \
+             THEN COALESCE(json_extract(schema, '$.compress.min_bytes'), ?2) END \
+             FROM collections WHERE id = ?1",
+            params![collection_id, record_format::DEFAULT_MIN_BYTES as i64],
+        )
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(row
+            .get::<Option<i64>>(0)?
+            .map(|bytes| bytes.max(0) as usize)),
+        None => Ok(None),
+    }
+}
+
+/// The path and depth of a record in its tree.
+async fn tree_place(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+) -> Result<Option<(String, u32)>> {
+    let mut rows = conn
+        .query(
+            "SELECT path, depth FROM record_tree WHERE collection_id = ?1 AND record_id = ?2",
+            params![collection_id, record_id],
+        )
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some((row.get(0)?, row.get(1)?))),
+        None => Ok(None),
+    }
+}
+
+/// The `WHERE` condition selecting the nodes of collection `?1` that are
+/// children of `?2`, or roots when the parent is `None`; `table` qualifies
+/// the columns, e.g. `t.`.
+fn tree_level(table: &str, parent: Option<i64>) -> String {
+    match parent {
+        Some(_) => format!(
+            "{0}collection_id = ?1 AND {0}depth > 0 AND {0}parent_id = ?2",
+            table
+        ),
+        None => format!(
+            "{0}collection_id = ?1 AND {0}depth = 0 AND ?2 IS NULL",
+            table
+        ),
+    }
+}
+
+/// The position after the last child of `parent`, or of the roots.
+async fn next_tree_position(
+    conn: &Connection,
+    collection_id: i64,
+    parent: Option<i64>,
+) -> Result<i64> {
+    let sql = format!(
+        "SELECT COALESCE(MAX(position) + 1, 0) FROM record_tree WHERE {}",
+        tree_level("", parent)
+    );
+    let mut rows = conn.query(&sql, params![collection_id, parent]).await?;
+    match rows.next().await? {
+        Some(row) => row.get(0),
+        None => Ok(0),
+    }
+}
+
+/// Refuses `data` for `record_id` if it names a parent in the record's own
+/// subtree.
+async fn check_tree_parent(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+    field: &str,
+    data: &Value,
+) -> BoxResult<()> {
+    let Some(parent) = tree::parent_of(data, field) else {
+        return Ok(());
+    };
+    let cycle = parent == record_id
+        || tree_place(conn, collection_id, parent)
+            .await?
+            .is_some_and(|(path, _)| tree::is_under(&path, record_id));
+    if cycle {
+        return Err(Box::new(ValidationError::field(
+            field,
+            ValidationErrorKind::AncestorCycle,
+            Some(Value::from(parent)),
+        )));
+    }
+    Ok(())
+}
+
+/// Moves a record and its subtree from `from` to `to`, as `(path, depth)`,
+/// making it the last child of its new parent.
+async fn relocate_tree_node(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+    parent: Option<i64>,
+    from: (&str, u32),
+    to: (&str, u32),
+) -> Result<()> {
+    let attached_to = (to.1 > 0).then_some(parent).flatten();
+    let position = next_tree_position(conn, collection_id, attached_to).await?;
+    conn.execute(
+        "UPDATE record_tree SET path = ?1 || substr(path, ?2), depth = depth + ?3 WHERE collection_id = ?4 AND path >= ?5 AND path < ?6",
+        params![
+            to.0,
+            from.0.len() as i64 + 1,
+            to.1 as i64 - from.1 as i64,
+            collection_id,
+            from.0,
+            tree::path_upper_bound(from.0)
+        ],
+    )
+    .await?;
+    conn.execute(
+        "UPDATE record_tree SET parent_id = ?1, position = ?2 WHERE collection_id = ?3 AND record_id = ?4",
+        params![parent, position, collection_id, record_id],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Places a record written with `parent` in the tree of its collection,
+/// moving its subtree along if the parent changed.
+async fn sync_tree_node(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+    parent: Option<i64>,
+) -> Result<()> {
+    let target = match parent {
+        Some(parent) if parent != record_id => tree_place(conn, collection_id, parent).await?,
+        _ => None,
+    };
+    let path = tree::child_path(target.as_ref().map(|(p, _)| p.as_str()), record_id);
+    let depth = target.as_ref().map_or(0, |(_, d)| d + 1);
+    let mut rows = conn
+        .query(
+            "SELECT parent_id, path, depth FROM record_tree WHERE collection_id = ?1 AND record_id = ?2",
+            params![collection_id, record_id],
+        )
+        .await?;
+    let current = match rows.next().await? {
+        Some(row) => Some((
+            row.get::<Option<i64>>(0)?,
+            row.get::<String>(1)?,
+            row.get::<u32>(2)?,
+        )),
+        None => None,
+    };
+    drop(rows);
+    match current {
+        Some((old_parent, old_path, old_depth)) => {
+            if old_parent == parent && old_path == path {
+                return Ok(());
+            }
+            relocate_tree_node(
+                conn,
+                collection_id,
+                record_id,
+                parent,
+                (&old_path, old_depth),
+                (&path, depth),
+            )
+            .await
+        }
+        None => {
+            let attached_to = target.is_some().then_some(parent).flatten();
+            let position = next_tree_position(conn, collection_id, attached_to).await?;
+            conn.execute(
+                "INSERT INTO record_tree (collection_id, record_id, parent_id, path, depth, position) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![collection_id, record_id, parent, path.clone(), depth, position],
+            )
+            .await?;
+            // Roots naming this record as their parent before it existed,
+            // e.g. when it is restored, go back under it.
+            let mut rows = conn
+                .query(
+                    "SELECT record_id, path FROM record_tree WHERE collection_id = ?1 AND depth = 0 AND parent_id = ?2 ORDER BY position, record_id",
+                    params![collection_id, record_id],
+                )
+                .await?;
+            let mut orphans = Vec::new();
+            while let Some(row) = rows.next().await? {
+                orphans.push((row.get::<i64>(0)?, row.get::<String>(1)?));
+            }
+            drop(rows);
+            for (orphan, orphan_path) in orphans {
+                if orphan == record_id || tree::is_under(&path, orphan) {
+                    continue;
+                }
+                let to = tree::child_path(Some(&path), orphan);
+                relocate_tree_node(
+                    conn,
+                    collection_id,
+                    orphan,
+                    Some(record_id),
+                    (&orphan_path, 0),
+                    (&to, depth + 1),
+                )
+                .await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Takes a deleted record out of its tree; its children become roots.
+async fn remove_tree_node(conn: &Connection, collection_id: i64, record_id: i64) -> Result<()> {
+    if tree_place(conn, collection_id, record_id).await?.is_none() {
+        return Ok(());
+    }
+    conn.execute(
+        "DELETE FROM record_tree WHERE collection_id = ?1 AND record_id = ?2",
+        params![collection_id, record_id],
+    )
+    .await?;
+    let mut rows = conn
+        .query(
+            "SELECT record_id, path, depth, parent_id FROM record_tree WHERE collection_id = ?1 AND depth > 0 AND parent_id = ?2 ORDER BY position, record_id",
+            params![collection_id, record_id],
+        )
+        .await?;
+    let mut children = Vec::new();
+    while let Some(row) = rows.next().await? {
+        children.push((
+            row.get::<i64>(0)?,
+            row.get::<String>(1)?,
+            row.get::<u32>(2)?,
+        ));
+    }
+    drop(rows);
+    for (child, path, depth) in children {
+        relocate_tree_node(
+            conn,
+            collection_id,
+            child,
+            Some(record_id),
+            (&path, depth),
+            (&tree::child_path(None, child), 0),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Lays out the tree of a collection from scratch, atomically, e.g. when its
+/// schema starts or stops declaring one.
+async fn rebuild_tree(conn: &Connection, collection_id: i64, field: Option<&str>) -> Result<()> {
+    conn.execute("BEGIN", ()).await?;
+    match lay_out_tree(conn, collection_id, field).await {
+        Ok(()) => conn.execute("COMMIT", ()).await.map(|_| ()),
+        Err(e) => {
+            conn.execute("ROLLBACK", ()).await?;
+            Err(e)
+        }
+    }
+}
+
+async fn lay_out_tree(conn: &Connection, collection_id: i64, field: Option<&str>) -> Result<()> {
+    conn.execute(
+        "DELETE FROM record_tree WHERE collection_id = ?1",
+        params![collection_id],
+    )
+    .await?;
+    let Some(field) = field else {
+        return Ok(());
+    };
+    let sql = format!(
+        "SELECT id, {} FROM records WHERE collection_id = ?1",
+        field_sql(field)
+    );
+    let mut rows = conn.query(&sql, params![collection_id]).await?;
+    let mut records = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let parent = match row.get_value(1)? {
+            libsql::Value::Integer(id) => Some(id),
+            _ => None,
+        };
+        records.push((row.get::<i64>(0)?, parent));
+    }
+    drop(rows);
+    for (record_id, parent, path, depth, position) in tree::layout(&records) {
+        conn.execute(
+            "INSERT INTO record_tree (collection_id, record_id, parent_id, path, depth, position) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![collection_id, record_id, parent, path, depth, position],
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn list_tree_children(
+    conn: &Connection,
+    collection_id: i64,
+    parent: Option<i64>,
+) -> BoxResult<Vec<TreeNode>> {
+    let sql = format!(
+        "SELECT {} FROM record_tree t JOIN records r ON r.id = t.record_id WHERE {} ORDER BY t.position, t.record_id",
+        TREE_COLUMNS,
+        tree_level("t.", parent)
+    );
+    let mut rows = conn
+        .query(&sql, params![collection_id, parent])
+        .await
+        .map_err(with_sql(&sql))?;
+    let mut nodes = Vec::new();
+    while let Some(row) = rows.next().await? {
+        nodes.push(row_to_tree_node(&row)?);
+    }
+    Ok(nodes)
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn get_subtree(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+    max_depth: Option<u32>,
+) -> BoxResult<Vec<TreeNode>> {
+    let Some((path, depth)) = tree_place(conn, collection_id, record_id).await? else {
+        return Ok(Vec::new());
+    };
+    let sql = format!(
+        "SELECT {} FROM record_tree t JOIN records r ON r.id = t.record_id WHERE t.collection_id = ?1 AND t.path >= ?2 AND t.path < ?3 AND t.depth <= ?4",
+        TREE_COLUMNS
+    );
+    let deepest = max_depth.map_or(i64::MAX, |max| depth as i64 + max as i64);
+    let mut rows = conn
+        .query(
+            &sql,
+            params![
+                collection_id,
+                path.clone(),
+                tree::path_upper_bound(&path),
+                deepest
+            ],
+        )
+        .await
+        .map_err(with_sql(&sql))?;
+    let mut nodes = Vec::new();
+    while let Some(row) = rows.next().await? {
+        nodes.push(row_to_tree_node(&row)?);
+    }
+    Ok(tree::depth_first(nodes, record_id))
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn set_tree_position(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+    position: usize,
+) -> BoxResult<()> {
+    let mut rows = conn
+        .query(
+            "SELECT CASE WHEN depth = 0 THEN NULL ELSE parent_id END FROM record_tree WHERE collection_id = ?1 AND record_id = ?2",
+            params![collection_id, record_id],
+        )
+        .await?;
+    let parent: Option<i64> = match rows.next().await? {
+        Some(row) => row.get(0)?,
+        None => return Err(format!("Record {} is not in a tree", record_id).into()),
+    };
+    drop(rows);
+    let sql = format!(
+        "SELECT record_id FROM record_tree WHERE {} ORDER BY position, record_id",
+        tree_level("", parent)
+    );
+    let mut rows = conn.query(&sql, params![collection_id, parent]).await?;
+    let mut siblings = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let id: i64 = row.get(0)?;
+        if id != record_id {
+            siblings.push(id);
+        }
+    }
+    drop(rows);
+    siblings.insert(position.min(siblings.len()), record_id);
+    conn.execute("BEGIN", ()).await?;
+    let result = async {
+        for (position, id) in siblings.iter().enumerate() {
+            conn.execute(
+                "UPDATE record_tree SET position = ?1 WHERE collection_id = ?2 AND record_id = ?3",
+                params![position as i64, collection_id, *id],
+            )
+            .await?;
+        }
+        Ok::<_, libsql::Error>(())
+    }
+    .await;
+    match result {
+        Ok(()) => {
+            conn.execute("COMMIT", ()).await?;
+            Ok(())
+        }
+        Err(e) => {
+            conn.execute("ROLLBACK", ()).await?;
+            Err(e.into())
+        }
+    }
+}
+
+#[tracing::instrument(level = "debug", skip(conn, operations), err)]
+pub(crate) async fn batch(
+    conn: &Connection,
+    collection_id: i64,
+    operations: &[BatchOperation],
+) -> BoxResult<Vec<BatchResult>> {
+    conn.execute("BEGIN", ()).await?;
+    match apply_batch(conn, collection_id, operations).await {
+        Ok(results) => {
+            conn.execute("COMMIT", ()).await?;
+            Ok(results)
+        }
+        Err(e) => {
+            conn.execute("ROLLBACK", ()).await?;
+            Err(Box::new(e))
+        }
+    }
+}
+
+async fn apply_batch(
+    conn: &Connection,
+    collection_id: i64,
+    operations: &[BatchOperation],
+) -> std::result::Result<Vec<BatchResult>, BatchError> {
+    let mut results = Vec::with_capacity(operations.len());
+    for (index, operation) in operations.iter().enumerate() {
+        let failed = |e| BatchError::Failed(index, e);
+        let result = match operation {
+            BatchOperation::Create { data } => BatchResult::Created(
+                create_record(conn, collection_id, data)
+                    .await
+                    .map_err(failed)?,
+            ),
+            BatchOperation::Update { id, data } => BatchResult::Updated(
+                update_record(conn, collection_id, *id, data)
+                    .await
+                    .map_err(failed)?
+                    .ok_or(BatchError::RecordNotFound(index, *id))?,
+            ),
+            BatchOperation::Delete { id } => {
+                let record = get_record(conn, collection_id, *id)
+                    .await
+                    .map_err(failed)?
+                    .ok_or(BatchError::RecordNotFound(index, *id))?;
+                delete_record(conn, collection_id, *id)
+                    .await
+                    .map_err(|e| failed(Box::new(e)))?;
+                BatchResult::Deleted(record)
+            }
+        };
+        results.push(result);
+    }
+    Ok(results)
+}
+
+#[tracing::instrument(level = "debug", skip(conn, text), err)]
+pub(crate) async fn set_search_text(
+    conn: &Connection,
+    collection_id: i64,
+    record_id: i64,
+    text: Option<&str>,
+) -> Result<()> {
+    conn.execute(
+        "DELETE FROM record_search WHERE collection_id = ?1 AND record_id = ?2",
+        params![collection_id, record_id],
+    )
+    .await?;
+    if let Some(text) = text {
+        conn.execute(
+            "INSERT INTO record_search (collection_id, record_id, body) VALUES (?1, ?2, ?3)",
+            params![collection_id, record_id, text],
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn clear_search_text(conn: &Connection, collection_id: i64) -> Result<()> {
+    conn.execute(
+        "DELETE FROM record_search WHERE collection_id = ?1",
+        params![collection_id],
+    )
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn search_text(
+    conn: &Connection,
+    collection_id: i64,
+    query: &str,
+    limit: usize,
+) -> BoxResult<Vec<SearchHit>> {
+    let query = search::fts5_query(query);
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    // bm25() is lower for better matches.
+    let sql = "SELECT record_id, -bm25(record_search) FROM record_search \
+               WHERE record_search MATCH ?2 AND collection_id = ?1 \
+               ORDER BY bm25(record_search) LIMIT ?3";
+    timeouts::limited(conn, async {
+        let mut rows = conn
+            .query(sql, params![collection_id, query, limit as i64])
+            .await
+            .map_err(with_sql(sql))?;
+        let mut hits = Vec::new();
+        while let Some(row) = rows.next().await? {
+            hits.push(SearchHit {
+                record_id: row.get(0)?,
+                score: row.get(1)?,
+            });
+        }
+        Ok(hits)
+    })
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip(conn, password_hash), err)]
+pub(crate) async fn create_admin(
+    conn: &Connection,
+    email: &str,
+    password_hash: &str,
+) -> BoxResult<Admin> {
+    conn.execute(
+        &format!(
+            "INSERT INTO admins (email, password_hash, created, updated) VALUES (?1, ?2, {0}, {0})",
+            now()
+        ),
+        params![email, password_hash],
+    )
+    .await?;
+    let admin = get_admin(conn, conn.last_insert_rowid())
+        .await?
+        .ok_or("Admin not found")?;
+    Ok(admin)
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn get_admin(conn: &Connection, id: i64) -> BoxResult<Option<Admin>> {
+    let mut rows = conn
+        .query(
+            &format!("SELECT {} FROM admins WHERE id = ?1", ADMIN_COLUMNS),
+            params![id],
+        )
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some(row_to_admin(&row)?)),
+        None => Ok(None),
+    }
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn get_admin_by_email(conn: &Connection, email: &str) -> BoxResult<Option<Admin>> {
+    let mut rows = conn
+        .query(
+            &format!("SELECT {} FROM admins WHERE email = ?1", ADMIN_COLUMNS),
+            params![email],
+        )
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some(row_to_admin(&row)?)),
+        None => Ok(None),
+    }
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn ping(conn: &Connection) -> Result<()> {
+    conn.query("SELECT 1", ()).await?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn list_admins(conn: &Connection) -> BoxResult<Vec<Admin>> {
+    let mut rows = conn
+        .query(
+            &format!("SELECT {} FROM admins ORDER BY id", ADMIN_COLUMNS),
+            (),
+        )
+        .await?;
+    let mut admins = Vec::new();
+    while let Some(row) = rows.next().await? {
+        admins.push(row_to_admin(&row)?);
+    }
+    Ok(admins)
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn count_admins(conn: &Connection) -> BoxResult<i64> {
+    let mut rows = conn.query("SELECT COUNT(*) FROM admins", ()).await?;
+    match rows.next().await? {
+        Some(row) => Ok(row.get(0)?),
+        None => Ok(0),
+    }
+}
+
+#[tracing::instrument(level = "debug", skip(conn, settings), err)]
+pub(crate) async fn create_notification_channel(
+    conn: &Connection,
+    settings: &ChannelSettings,
+) -> BoxResult<NotificationChannel> {
+    let settings = serde_json::to_string(settings)?;
+    conn.execute(
+        &format!(
+            "INSERT INTO notification_channels (settings, created, updated) VALUES (?1, {0}, {0})",
+            now()
+        ),
+        params![settings],
+    )
+    .await?;
+    let channel = get_notification_channel(conn, conn.last_insert_rowid())
+        .await?
+        .ok_or("Notification channel not found")?;
+    Ok(channel)
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn get_notification_channel(
+    conn: &Connection,
+    id: i64,
+) -> BoxResult<Option<NotificationChannel>> {
+    let mut rows = conn
+        .query(
+            &format!(
+                "SELECT {} FROM notification_channels WHERE id = ?1",
+                CHANNEL_COLUMNS
+            ),
+            params![id],
+        )
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some(row_to_channel(&row)?)),
+        None => Ok(None),
+    }
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn list_notification_channels(
+    conn: &Connection,
+) -> BoxResult<Vec<NotificationChannel>> {
+    let mut rows = conn
+        .query(
+            &format!(
+                "SELECT {} FROM notification_channels ORDER BY id",
+                CHANNEL_COLUMNS
+            ),
+            (),
+        )
+        .await?;
+    let mut channels = Vec::new();
+    while let Some(row) = rows.next().await? {
+        channels.push(row_to_channel(&row)?);
+    }
+    Ok(channels)
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn delete_notification_channel(conn: &Connection, id: i64) -> Result<()> {
+    conn.execute(
+        "DELETE FROM notification_channels WHERE id = ?1",
+        params![id],
+    )
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip(conn, settings, secret), err)]
+pub(crate) async fn create_webhook(
+    conn: &Connection,
+    settings: &WebhookSettings,
+    secret: &str,
+) -> BoxResult<Webhook> {
+    let settings = serde_json::to_string(settings)?;
+    conn.execute(
+        &format!(
+            "INSERT INTO webhooks (settings, secret, created, updated) VALUES (?1, ?2, {0}, {0})",
+            now()
+        ),
+        params![settings, secret],
+    )
+    .await?;
+    let webhook = get_webhook(conn, conn.last_insert_rowid())
+        .await?
+        .ok_or("Webhook not found")?;
+    Ok(webhook)
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn get_webhook(conn: &Connection, id: i64) -> BoxResult<Option<Webhook>> {
+    let mut rows = conn
+        .query(
+            &format!("SELECT {} FROM webhooks WHERE id = ?1", WEBHOOK_COLUMNS),
+            params![id],
+        )
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some(row_to_webhook(&row)?)),
+        None => Ok(None),
+    }
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn list_webhooks(conn: &Connection) -> BoxResult<Vec<Webhook>> {
+    let mut rows = conn
+        .query(
+            &format!("SELECT {} FROM webhooks ORDER BY id", WEBHOOK_COLUMNS),
+            (),
+        )
+        .await?;
+    let mut webhooks = Vec::new();
+    while let Some(row) = rows.next().await? {
+        webhooks.push(row_to_webhook(&row)?);
+    }
+    Ok(webhooks)
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn delete_webhook(conn: &Connection, id: i64) -> Result<()> {
+    conn.execute(
+        "DELETE FROM webhook_deliveries WHERE webhook_id = ?1",
+        params![id],
+    )
+    .await?;
+    conn.execute("DELETE FROM webhooks WHERE id = ?1", params![id])
+        .await?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip(conn, scopes, token_hash), err)]
+pub(crate) async fn create_api_token(
+    conn: &Connection,
+    name: &str,
+    scopes: &[TokenScope],
+    token_hash: &str,
+) -> BoxResult<ApiToken> {
+    let scopes = serde_json::to_string(scopes)?;
+    conn.execute(
+        &format!(
+            "INSERT INTO api_tokens (name, token_hash, scopes, created, updated) VALUES (?1, ?2, ?3, {0}, {0})",
+            now()
+        ),
+        params![name, token_hash, scopes],
+    )
+    .await?;
+    let token = get_api_token(conn, conn.last_insert_rowid())
+        .await?
+        .ok_or("API token not found")?;
+    Ok(token)
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn get_api_token(conn: &Connection, id: i64) -> BoxResult<Option<ApiToken>> {
+    let mut rows = conn
+        .query(
+            &format!("SELECT {} FROM api_tokens WHERE id = ?1", TOKEN_COLUMNS),
+            params![id],
+        )
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some(row_to_token(&row)?)),
+        None => Ok(None),
+    }
+}
+
+#[tracing::instrument(level = "debug", skip(conn, token_hash), err)]
+pub(crate) async fn get_api_token_by_hash(
+    conn: &Connection,
+    token_hash: &str,
+) -> BoxResult<Option<ApiToken>> {
+    let mut rows = conn
+        .query(
+            &format!(
+                "SELECT {} FROM api_tokens WHERE token_hash = ?1",
+                TOKEN_COLUMNS
+            ),
+            params![token_hash],
+        )
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some(row_to_token(&row)?)),
+        None => Ok(None),
+    }
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn list_api_tokens(conn: &Connection) -> BoxResult<Vec<ApiToken>> {
+    let mut rows = conn
+        .query(
+            &format!("SELECT {} FROM api_tokens ORDER BY id", TOKEN_COLUMNS),
+            (),
+        )
+        .await?;
+    let mut tokens = Vec::new();
+    while let Some(row) = rows.next().await? {
+        tokens.push(row_to_token(&row)?);
+    }
+    Ok(tokens)
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn delete_api_token(conn: &Connection, id: i64) -> Result<()> {
+    conn.execute("DELETE FROM api_tokens WHERE id = ?1", params![id])
+        .await?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip(conn, settings), err)]
+pub(crate) async fn update_webhook(
+    conn: &Connection,
+    id: i64,
+    settings: &WebhookSettings,
+) -> BoxResult<Webhook> {
+    let settings = serde_json::to_string(settings)?;
+    conn.execute(
+        &format!(
+            "UPDATE webhooks SET settings = ?1, updated = {} WHERE id = ?2",
+            now()
+        ),
+        params![settings, id],
+    )
+    .await?;
+    let webhook = get_webhook(conn, id).await?.ok_or("Webhook not found")?;
+    Ok(webhook)
+}
+
+#[tracing::instrument(level = "debug", skip(conn, settings), err)]
+pub(crate) async fn create_script_hook(
+    conn: &Connection,
+    settings: &ScriptHookSettings,
+) -> BoxResult<ScriptHook> {
+    let settings = serde_json::to_string(settings)?;
+    conn.execute(
+        &format!(
+            "INSERT INTO script_hooks (settings, created, updated) VALUES (?1, {0}, {0})",
+            now()
+        ),
+        params![settings],
+    )
+    .await?;
+    let hook = get_script_hook(conn, conn.last_insert_rowid())
+        .await?
+        .ok_or("Script hook not found")?;
+    Ok(hook)
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn get_script_hook(conn: &Connection, id: i64) -> BoxResult<Option<ScriptHook>> {
+    let mut rows = conn
+        .query(
+            &format!("SELECT {} FROM script_hooks WHERE id = ?1", HOOK_COLUMNS),
+            params![id],
+        )
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some(row_to_hook(&row)?)),
+        None => Ok(None),
+    }
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn list_script_hooks(conn: &Connection) -> BoxResult<Vec<ScriptHook>> {
+    let mut rows = conn
+        .query(
+            &format!("SELECT {} FROM script_hooks ORDER BY id", HOOK_COLUMNS),
+            (),
+        )
+        .await?;
+    let mut hooks = Vec::new();
+    while let Some(row) = rows.next().await? {
+        hooks.push(row_to_hook(&row)?);
+    }
+    Ok(hooks)
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn delete_script_hook(conn: &Connection, id: i64) -> Result<()> {
+    conn.execute("DELETE FROM script_hooks WHERE id = ?1", params![id])
+        .await?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip(conn, attempt), fields(webhook_id = attempt.webhook_id), err)]
+pub(crate) async fn record_webhook_delivery(
+    conn: &Connection,
+    attempt: &DeliveryAttempt,
+) -> BoxResult<WebhookDelivery> {
+    conn.execute(
+        &format!(
+            "INSERT INTO webhook_deliveries (webhook_id, event, payload, attempt, status, error, duration_ms, response, replay_of, created) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, {})",
+            now()
+        ),
+        params![
+            attempt.webhook_id,
+            attempt.event.as_str(),
+            attempt.payload.to_string(),
+            attempt.attempt as i64,
+            attempt.status.map(i64::from),
+            attempt.error.as_deref(),
+            attempt.duration_ms as i64,
+            attempt.response.as_deref(),
+            attempt.replay_of
+        ],
+    )
+    .await?;
+    let delivery = get_webhook_delivery(conn, attempt.webhook_id, conn.last_insert_rowid())
+        .await?
+        .ok_or("Delivery not found")?;
+    conn.execute(
+        "DELETE FROM webhook_deliveries WHERE webhook_id = ?1 AND id NOT IN \
+         (SELECT id FROM webhook_deliveries WHERE webhook_id = ?1 ORDER BY id DESC LIMIT ?2)",
+        params![attempt.webhook_id, DELIVERY_LOG_SIZE],
+    )
+    .await?;
+    Ok(delivery)
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn list_webhook_deliveries(
+    conn: &Connection,
+    webhook_id: i64,
+) -> BoxResult<Vec<WebhookDelivery>> {
+    let mut rows = conn
+        .query(
+            &format!(
+                "SELECT {} FROM webhook_deliveries WHERE webhook_id = ?1 ORDER BY id DESC",
+                DELIVERY_COLUMNS
+            ),
+            params![webhook_id],
+        )
+        .await?;
+    let mut deliveries = Vec::new();
+    while let Some(row) = rows.next().await? {
+        deliveries.push(row_to_delivery(&row)?);
+    }
+    Ok(deliveries)
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn get_webhook_delivery(
+    conn: &Connection,
+    webhook_id: i64,
+    id: i64,
+) -> BoxResult<Option<WebhookDelivery>> {
+    let mut rows = conn
+        .query(
+            &format!(
+                "SELECT {} FROM webhook_deliveries WHERE webhook_id = ?1 AND id = ?2",
+                DELIVERY_COLUMNS
+            ),
+            params![webhook_id, id],
+        )
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some(row_to_delivery(&row)?)),
+        None => Ok(None),
+    }
+}
+
+#[tracing::instrument(level = "debug", skip(conn, progress), err)]
+pub(crate) async fn create_job(conn: &Connection, kind: &str, progress: &Value) -> BoxResult<Job> {
+    conn.execute(
+        &format!(
+            "INSERT INTO jobs (kind, state, progress, created, updated) VALUES (?1, ?2, ?3, {0}, {0})",
+            now()
+        ),
+        params![kind, JobState::Queued.as_str(), progress.to_string()],
+    )
+    .await?;
+    let job = get_job(conn, conn.last_insert_rowid())
+        .await?
+        .ok_or("Job not found")?;
+    Ok(job)
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn get_job(conn: &Connection, id: i64) -> BoxResult<Option<Job>> {
+    let mut rows = conn
+        .query(
+            &format!("SELECT {} FROM jobs WHERE id = ?1", JOB_COLUMNS),
+            params![id],
+        )
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some(row_to_job(&row)?)),
+        None => Ok(None),
+    }
+}
+
+#[tracing::instrument(level = "debug", skip(conn, progress, outcome), err)]
+pub(crate) async fn update_job(
+    conn: &Connection,
+    id: i64,
+    state: JobState,
+    progress: Option<&Value>,
+    outcome: Option<&Value>,
+) -> Result<bool> {
+    let updated = conn
+        .execute(
+            &format!(
+                "UPDATE jobs SET state = ?2, progress = COALESCE(?3, progress), \
+                 outcome = COALESCE(?4, outcome), updated = {} \
+                 WHERE id = ?1 AND state IN ('queued', 'running')",
+                now()
+            ),
+            params![
+                id,
+                state.as_str(),
+                progress.map(Value::to_string),
+                outcome.map(Value::to_string)
+            ],
+        )
+        .await?;
+    Ok(updated > 0)
+}
+
+#[tracing::instrument(level = "debug", skip(conn, error), err)]
+pub(crate) async fn fail_unfinished_jobs(conn: &Connection, error: &Value) -> Result<u64> {
+    conn.execute(
+        &format!(
+            "UPDATE jobs SET state = 'failed', outcome = ?1, updated = {} \
+             WHERE state IN ('queued', 'running')",
+            now()
+        ),
+        params![error.to_string()],
+    )
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn get_kv(
+    conn: &Connection,
+    namespace: &str,
+    key: &str,
+) -> BoxResult<Option<KvEntry>> {
+    let mut rows = conn
+        .query(
+            &format!(
+                "SELECT {} FROM kv WHERE namespace = ?1 AND key = ?2 \
+                 AND (expires IS NULL OR expires > {})",
+                KV_COLUMNS,
+                now()
+            ),
+            params![namespace, key],
+        )
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some(row_to_kv(&row)?)),
+        None => Ok(None),
+    }
+}
+
+#[tracing::instrument(level = "debug", skip(conn, value), err)]
+pub(crate) async fn set_kv(
+    conn: &Connection,
+    namespace: &str,
+    key: &str,
+    value: &Value,
+    ttl: Option<Duration>,
+) -> BoxResult<KvEntry> {
+    let now = clock::now();
+    let expires = ttl.map(|ttl| format_timestamp(now + ttl));
+    let now = format_timestamp(now);
+    conn.execute(
+        "DELETE FROM kv WHERE namespace = ?1 AND expires IS NOT NULL AND expires <= ?2",
+        params![namespace, now.as_str()],
+    )
+    .await?;
+    // An expired entry was just dropped, so a key set again starts afresh.
+    conn.execute(
+        "INSERT INTO kv (namespace, key, value, expires, created, updated) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?5) \
+         ON CONFLICT (namespace, key) DO UPDATE SET \
+         value = excluded.value, expires = excluded.expires, updated = excluded.updated",
+        params![namespace, key, value.to_string(), expires, now],
+    )
+    .await?;
+    let mut rows = conn
+        .query(
+            &format!(
+                "SELECT {} FROM kv WHERE namespace = ?1 AND key = ?2",
+                KV_COLUMNS
+            ),
+            params![namespace, key],
+        )
+        .await?;
+    let row = rows.next().await?.ok_or("Entry not found")?;
+    row_to_kv(&row)
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn delete_kv(conn: &Connection, namespace: &str, key: &str) -> Result<bool> {
+    let deleted = conn
+        .execute(
+            &format!(
+                "DELETE FROM kv WHERE namespace = ?1 AND key = ?2 \
+                 AND (expires IS NULL OR expires > {})",
+                now()
+            ),
+            params![namespace, key],
+        )
+        .await?;
+    Ok(deleted > 0)
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn claim_idempotency_key(
+    conn: &Connection,
+    key: &str,
+    collection_id: i64,
+    ttl: Duration,
+) -> BoxResult<IdempotencyClaim> {
+    let now = clock::now();
+    let expires = format_timestamp(now + ttl);
+    let now = format_timestamp(now);
+    conn.execute(
+        "DELETE FROM idempotency_keys WHERE expires <= ?1",
+        params![now.as_str()],
+    )
+    .await?;
+    let claimed = conn
+        .execute(
+            "INSERT INTO idempotency_keys (key, collection_id, record_id, expires, created) \
+             VALUES (?1, ?2, NULL, ?3, ?4) ON CONFLICT (key) DO NOTHING",
+            params![key, collection_id, expires, now],
+        )
+        .await?;
+    if claimed > 0 {
+        return Ok(IdempotencyClaim::Claimed);
+    }
+    let mut rows = conn
+        .query(
+            "SELECT collection_id, record_id FROM idempotency_keys WHERE key = ?1",
+            params![key],
+        )
+        .await?;
+    let row = rows.next().await?.ok_or("Idempotency key not found")?;
+    let collection_id = row.get(0)?;
+    Ok(match row.get::<Option<i64>>(1)? {
+        Some(record_id) => IdempotencyClaim::Completed {
+            collection_id,
+            record_id,
+        },
+        None => IdempotencyClaim::InProgress { collection_id },
+    })
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn complete_idempotency_key(
+    conn: &Connection,
+    key: &str,
+    record_id: i64,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE idempotency_keys SET record_id = ?2 WHERE key = ?1",
+        params![key, record_id],
+    )
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn release_idempotency_key(conn: &Connection, key: &str) -> Result<()> {
+    conn.execute(
+        "DELETE FROM idempotency_keys WHERE key = ?1 AND record_id IS NULL",
+        params![key],
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,882 @@
+//! Spreading reads over several read replicas of the database, e.g. Turso
+//! replicas in different regions, by how fast each answers.
+//!
+//! [`ReadReplicas`] is a [`Db`] that sends collection and record reads to the
+//! fastest healthy replica and everything else, writes and reads of admins,
+//! webhooks, hooks and jobs included, to the primary. Replicas lag the
+//! primary, so a record read right after it is written may be stale.
+//! [`ReadReplicas::probe`] times a trivial query on every replica, which
+//! [`ReadReplicas::spawn`] does on an interval; a replica that fails or times
+//! out is unhealthy until it answers again. [`ReadReplicas::pin`] overrides
+//! the choice, e.g. to drain a region.
+
+use crate::aggregate::{AggregateGroup, AggregateQuery};
+use crate::arrays::ArrayOp;
+use crate::batch::{BatchOperation, BatchResult};
+use crate::checksums::{MismatchReport, ScrubBatch};
+use crate::idempotency::IdempotencyClaim;
+use crate::jobs::{Job, JobState};
+use crate::kv::KvEntry;
+use crate::notifications::{ChannelSettings, NotificationChannel};
+use crate::ordering::Placement;
+use crate::record_format::StorageStats;
+use crate::schema::CollectionSchema;
+use crate::scripts::{ScriptHook, ScriptHookSettings};
+use crate::search::SearchHit;
+use crate::tokens::{ApiToken, TokenScope};
+use crate::tree::TreeNode;
+use crate::webhooks::{DeliveryAttempt, Webhook, WebhookDelivery, WebhookSettings};
+use crate::{
+    clock, format_timestamp, Admin, Collection, CollectionDeletion, Db, ListOptions, Record,
+    RecordVersion,
+};
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use libsql::{Builder, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often replicas are probed unless set otherwise.
+pub const DEFAULT_PROBE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A replica taking longer than this to answer a probe is unhealthy.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Weight of the latest probe in a replica's smoothed latency.
+const SMOOTHING: f64 = 0.3;
+
+/// What [`ReadReplicas::pin`] calls the primary.
+pub const PRIMARY: &str = "primary";
+
+/// The measurements of one replica.
+#[derive(Serialize, Clone, Debug)]
+pub struct ReplicaStats {
+    pub name: String,
+    /// Whether the replica answered its last probe in time.
+    pub healthy: bool,
+    /// Smoothed probe round trip, once the replica has answered one.
+    pub latency_ms: Option<f64>,
+    /// Reads routed to the replica since startup.
+    pub reads: u64,
+    /// When the replica was last probed.
+    pub last_probe: Option<String>,
+    /// Why the last probe failed.
+    pub error: Option<String>,
+}
+
+/// Where reads go and why, for admins.
+#[derive(Serialize, Clone, Debug)]
+pub struct RoutingStatus {
+    /// The replica reads are pinned to, or [`PRIMARY`], if any.
+    pub pinned: Option<String>,
+    /// Where reads go now: a replica name or [`PRIMARY`].
+    pub selected: String,
+    /// Reads served by the primary since startup.
+    pub primary_reads: u64,
+    pub replicas: Vec<ReplicaStats>,
+}
+
+struct Replica {
+    db: Arc<dyn Db>,
+    stats: Mutex<ReplicaStats>,
+}
+
+/// Routes reads to the fastest healthy replica, see the [module docs](self).
+#[derive(Clone)]
+pub struct ReadReplicas {
+    primary: Arc<dyn Db>,
+    replicas: Arc<Vec<Replica>>,
+    pinned: Arc<Mutex<Option<String>>>,
+    primary_reads: Arc<AtomicU64>,
+    interval: Duration,
+}
+
+impl ReadReplicas {
+    /// Reads go to the primary until the replicas have been probed.
+    pub fn new(primary: Arc<dyn Db>, replicas: Vec<(String, Arc<dyn Db>)>) -> Self {
+        let replicas = replicas
+            .into_iter()
+            .map(|(name, db)| Replica {
+                db,
+                stats: Mutex::new(ReplicaStats {
+                    name,
+                    healthy: false,
+                    latency_ms: None,
+                    reads: 0,
+                    last_probe: None,
+                    error: None,
+                }),
+            })
+            .collect();
+        Self {
+            primary,
+            replicas: Arc::new(replicas),
+            pinned: Arc::new(Mutex::new(None)),
+            primary_reads: Arc::new(AtomicU64::new(0)),
+            interval: DEFAULT_PROBE_INTERVAL,
+        }
+    }
+
+    pub fn with_probe_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Connects to the replicas listed in `TINYBASE_READ_REPLICAS` as
+    /// `name=url` pairs separated by commas, with the token in
+    /// `TINYBASE_REPLICA_AUTH_TOKEN`. Reads are pinned to
+    /// `TINYBASE_READ_REPLICA` if set, and replicas probed every
+    /// `TINYBASE_REPLICA_PROBE_SECS`. `Ok(None)` unless replicas are listed.
+    pub async fn from_env(primary: Arc<dyn Db>) -> std::result::Result<Option<Self>, String> {
+        let Ok(list) = std::env::var("TINYBASE_READ_REPLICAS") else {
+            return Ok(None);
+        };
+        let auth_token = std::env::var("TINYBASE_REPLICA_AUTH_TOKEN").unwrap_or_default();
+        let mut replicas: Vec<(String, Arc<dyn Db>)> = Vec::new();
+        for entry in list.split(',').filter(|e| !e.trim().is_empty()) {
+            let Some((name, url)) = entry.split_once('=') else {
+                return Err(format!(
+                    "Invalid TINYBASE_READ_REPLICAS entry '{}', expected name=url",
+                    entry
+                ));
+            };
+            let db = Builder::new_remote(url.trim().to_string(), auth_token.clone())
+                .build()
+                .await
+                .map_err(|e| format!("Failed to open read replica {}: {}", name, e))?;
+            replicas.push((name.trim().to_string(), Arc::new(db)));
+        }
+        let mut router = Self::new(primary, replicas);
+        if let Some(secs) = std::env::var("TINYBASE_REPLICA_PROBE_SECS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+        {
+            router = router.with_probe_interval(Duration::from_secs(secs.max(1)));
+        }
+        if let Ok(name) = std::env::var("TINYBASE_READ_REPLICA") {
+            router.pin(Some(&name))?;
+        }
+        Ok(Some(router))
+    }
+
+    /// Sends every read to the replica named `target`, or to the primary for
+    /// [`PRIMARY`], whatever the measurements; `None` goes back to picking
+    /// the fastest healthy replica.
+    pub fn pin(&self, target: Option<&str>) -> std::result::Result<(), String> {
+        if let Some(name) = target {
+            let known = name == PRIMARY
+                || self
+                    .replicas
+                    .iter()
+                    .any(|r| r.stats.lock().unwrap().name == name);
+            if !known {
+                return Err(format!("Unknown read replica '{}'", name));
+            }
+        }
+        *self.pinned.lock().unwrap() = target.map(str::to_string);
+        Ok(())
+    }
+
+    /// The replica reads go to now, or `None` for the primary.
+    fn select(&self) -> Option<&Replica> {
+        if let Some(pinned) = self.pinned.lock().unwrap().as_deref() {
+            return self
+                .replicas
+                .iter()
+                .find(|r| r.stats.lock().unwrap().name == pinned);
+        }
+        self.replicas
+            .iter()
+            .filter_map(|r| {
+                let stats = r.stats.lock().unwrap();
+                stats.latency_ms.filter(|_| stats.healthy).map(|ms| (r, ms))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(r, _)| r)
+    }
+
+    /// The database the next read goes to, counting the read.
+    fn reader(&self) -> &dyn Db {
+        match self.select() {
+            Some(replica) => {
+                replica.stats.lock().unwrap().reads += 1;
+                replica.db.as_ref()
+            }
+            None => {
+                self.primary_reads.fetch_add(1, Ordering::Relaxed);
+                self.primary.as_ref()
+            }
+        }
+    }
+
+    pub fn status(&self) -> RoutingStatus {
+        // Cloned first: `select` locks `pinned` again.
+        let pinned = self.pinned.lock().unwrap().clone();
+        RoutingStatus {
+            pinned,
+            selected: self.select().map_or(PRIMARY.to_string(), |r| {
+                r.stats.lock().unwrap().name.clone()
+            }),
+            primary_reads: self.primary_reads.load(Ordering::Relaxed),
+            replicas: self
+                .replicas
+                .iter()
+                .map(|r| r.stats.lock().unwrap().clone())
+                .collect(),
+        }
+    }
+
+    /// Times a query on every replica, concurrently, and updates their
+    /// health and latency.
+    pub async fn probe(&self) {
+        join_all(self.replicas.iter().map(|replica| async move {
+            let started = Instant::now();
+            let result = tokio::time::timeout(PROBE_TIMEOUT, replica.db.ping()).await;
+            let elapsed = started.elapsed().as_secs_f64() * 1000.0;
+            let mut stats = replica.stats.lock().unwrap();
+            stats.last_probe = Some(format_timestamp(clock::now()));
+            let error = match result {
+                Ok(Ok(())) => None,
+                Ok(Err(e)) => Some(e.to_string()),
+                Err(_) => Some(format!("No answer within {:?}", PROBE_TIMEOUT)),
+            };
+            stats.healthy = error.is_none();
+            if stats.healthy {
+                stats.latency_ms = Some(match stats.latency_ms {
+                    Some(ms) => ms + SMOOTHING * (elapsed - ms),
+                    None => elapsed,
+                });
+            }
+            stats.error = error;
+        }))
+        .await;
+    }
+
+    /// Runs [`probe`](Self::probe) every interval, starting now.
+    pub fn spawn(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.interval);
+            loop {
+                ticker.tick().await;
+                self.probe().await;
+                for stats in self.status().replicas {
+                    if let Some(error) = stats.error {
+                        tracing::warn!(replica = stats.name, %error, "read replica probe failed");
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl Db for ReadReplicas {
+    async fn create_collection(
+        &self,
+        name: &str,
+        schema: &Option<CollectionSchema>,
+    ) -> std::result::Result<Collection, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.create_collection(name, schema).await
+    }
+
+    async fn get_collection(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<Collection>, Box<dyn std::error::Error + Send + Sync>> {
+        self.reader().get_collection(id).await
+    }
+
+    async fn get_collection_by_name(
+        &self,
+        name: &str,
+    ) -> std::result::Result<Option<Collection>, Box<dyn std::error::Error + Send + Sync>> {
+        self.reader().get_collection_by_name(name).await
+    }
+
+    async fn list_collections(
+        &self,
+    ) -> std::result::Result<Vec<Collection>, Box<dyn std::error::Error + Send + Sync>> {
+        self.reader().list_collections().await
+    }
+
+    async fn update_collection(
+        &self,
+        id: i64,
+        name: Option<String>,
+        schema: Option<CollectionSchema>,
+    ) -> std::result::Result<Option<Collection>, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.update_collection(id, name, schema).await
+    }
+
+    async fn set_collection_group(
+        &self,
+        id: i64,
+        group: Option<&str>,
+    ) -> std::result::Result<Collection, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.set_collection_group(id, group).await
+    }
+
+    async fn delete_collection(&self, id: i64, force: bool) -> Result<CollectionDeletion> {
+        self.primary.delete_collection(id, force).await
+    }
+
+    async fn list_record_indexes(
+        &self,
+        collection_id: i64,
+    ) -> std::result::Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.list_record_indexes(collection_id).await
+    }
+
+    async fn create_record_index(&self, collection_id: i64, field: &str) -> Result<()> {
+        self.primary.create_record_index(collection_id, field).await
+    }
+
+    async fn drop_record_index(&self, collection_id: i64, field: &str) -> Result<()> {
+        self.primary.drop_record_index(collection_id, field).await
+    }
+
+    async fn create_record(
+        &self,
+        collection_id: i64,
+        data: &Value,
+    ) -> std::result::Result<Record, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.create_record(collection_id, data).await
+    }
+
+    async fn insert_record(
+        &self,
+        collection_id: i64,
+        record: &Record,
+    ) -> std::result::Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.insert_record(collection_id, record).await
+    }
+
+    async fn list_records(
+        &self,
+        collection_id: i64,
+        options: &ListOptions,
+    ) -> std::result::Result<Vec<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        self.reader().list_records(collection_id, options).await
+    }
+
+    async fn count_records(
+        &self,
+        collection_id: i64,
+    ) -> std::result::Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        self.reader().count_records(collection_id).await
+    }
+
+    async fn count_matching_records(
+        &self,
+        collection_id: i64,
+        options: &ListOptions,
+    ) -> std::result::Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        self.reader()
+            .count_matching_records(collection_id, options)
+            .await
+    }
+
+    async fn aggregate_records(
+        &self,
+        collection_id: i64,
+        query: &AggregateQuery,
+        options: &ListOptions,
+    ) -> std::result::Result<Vec<AggregateGroup>, Box<dyn std::error::Error + Send + Sync>> {
+        self.reader()
+            .aggregate_records(collection_id, query, options)
+            .await
+    }
+
+    async fn find_records_by_field(
+        &self,
+        collection_id: i64,
+        field: &str,
+        value: &Value,
+    ) -> std::result::Result<Vec<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        self.reader()
+            .find_records_by_field(collection_id, field, value)
+            .await
+    }
+
+    async fn get_or_create_record(
+        &self,
+        collection_id: i64,
+        field: &str,
+        value: &Value,
+        data: &Value,
+    ) -> std::result::Result<(Record, bool), Box<dyn std::error::Error + Send + Sync>> {
+        self.primary
+            .get_or_create_record(collection_id, field, value, data)
+            .await
+    }
+
+    async fn get_record(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        self.reader().get_record(collection_id, record_id).await
+    }
+
+    async fn update_record(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        data: &Value,
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary
+            .update_record(collection_id, record_id, data)
+            .await
+    }
+
+    async fn delete_record(&self, collection_id: i64, record_id: i64) -> Result<u64> {
+        self.primary.delete_record(collection_id, record_id).await
+    }
+
+    async fn set_search_text(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        text: Option<&str>,
+    ) -> Result<()> {
+        self.primary
+            .set_search_text(collection_id, record_id, text)
+            .await
+    }
+
+    async fn clear_search_text(&self, collection_id: i64) -> Result<()> {
+        self.primary.clear_search_text(collection_id).await
+    }
+
+    async fn search_text(
+        &self,
+        collection_id: i64,
+        query: &str,
+        limit: usize,
+    ) -> std::result::Result<Vec<SearchHit>, Box<dyn std::error::Error + Send + Sync>> {
+        self.reader().search_text(collection_id, query, limit).await
+    }
+
+    async fn update_record_at(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        version: i64,
+        data: &Value,
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary
+            .update_record_at(collection_id, record_id, version, data)
+            .await
+    }
+
+    async fn delete_record_at(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        version: i64,
+    ) -> std::result::Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary
+            .delete_record_at(collection_id, record_id, version)
+            .await
+    }
+
+    async fn move_record(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        placement: Placement,
+    ) -> std::result::Result<Record, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary
+            .move_record(collection_id, record_id, placement)
+            .await
+    }
+
+    async fn increment_field(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        field: &str,
+        delta: &serde_json::Number,
+        bounds: (Option<f64>, Option<f64>),
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary
+            .increment_field(collection_id, record_id, field, delta, bounds)
+            .await
+    }
+
+    async fn update_array(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        field: &str,
+        op: ArrayOp,
+        value: &Value,
+    ) -> std::result::Result<Option<Record>, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary
+            .update_array(collection_id, record_id, field, op, value)
+            .await
+    }
+
+    async fn list_record_versions(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+    ) -> std::result::Result<Vec<RecordVersion>, Box<dyn std::error::Error + Send + Sync>> {
+        self.reader()
+            .list_record_versions(collection_id, record_id)
+            .await
+    }
+
+    async fn get_record_version(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        version: i64,
+    ) -> std::result::Result<Option<RecordVersion>, Box<dyn std::error::Error + Send + Sync>> {
+        self.reader()
+            .get_record_version(collection_id, record_id, version)
+            .await
+    }
+
+    async fn restore_record_version(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        version: &RecordVersion,
+    ) -> std::result::Result<Record, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary
+            .restore_record_version(collection_id, record_id, version)
+            .await
+    }
+
+    async fn batch(
+        &self,
+        collection_id: i64,
+        operations: &[BatchOperation],
+    ) -> std::result::Result<Vec<BatchResult>, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.batch(collection_id, operations).await
+    }
+
+    async fn list_tree_children(
+        &self,
+        collection_id: i64,
+        parent: Option<i64>,
+    ) -> std::result::Result<Vec<TreeNode>, Box<dyn std::error::Error + Send + Sync>> {
+        self.reader()
+            .list_tree_children(collection_id, parent)
+            .await
+    }
+
+    async fn get_subtree(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        max_depth: Option<u32>,
+    ) -> std::result::Result<Vec<TreeNode>, Box<dyn std::error::Error + Send + Sync>> {
+        self.reader()
+            .get_subtree(collection_id, record_id, max_depth)
+            .await
+    }
+
+    async fn set_tree_position(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        position: usize,
+    ) -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.primary
+            .set_tree_position(collection_id, record_id, position)
+            .await
+    }
+
+    async fn create_admin(
+        &self,
+        email: &str,
+        password_hash: &str,
+    ) -> std::result::Result<Admin, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.create_admin(email, password_hash).await
+    }
+
+    async fn get_admin(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<Admin>, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.get_admin(id).await
+    }
+
+    async fn get_admin_by_email(
+        &self,
+        email: &str,
+    ) -> std::result::Result<Option<Admin>, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.get_admin_by_email(email).await
+    }
+
+    async fn count_admins(
+        &self,
+    ) -> std::result::Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.count_admins().await
+    }
+
+    async fn list_admins(
+        &self,
+    ) -> std::result::Result<Vec<Admin>, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.list_admins().await
+    }
+
+    async fn create_notification_channel(
+        &self,
+        settings: &ChannelSettings,
+    ) -> std::result::Result<NotificationChannel, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.create_notification_channel(settings).await
+    }
+
+    async fn get_notification_channel(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<NotificationChannel>, Box<dyn std::error::Error + Send + Sync>>
+    {
+        self.primary.get_notification_channel(id).await
+    }
+
+    async fn list_notification_channels(
+        &self,
+    ) -> std::result::Result<Vec<NotificationChannel>, Box<dyn std::error::Error + Send + Sync>>
+    {
+        self.primary.list_notification_channels().await
+    }
+
+    async fn delete_notification_channel(&self, id: i64) -> Result<()> {
+        self.primary.delete_notification_channel(id).await
+    }
+
+    async fn create_webhook(
+        &self,
+        settings: &WebhookSettings,
+        secret: &str,
+    ) -> std::result::Result<Webhook, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.create_webhook(settings, secret).await
+    }
+
+    async fn get_webhook(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<Webhook>, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.get_webhook(id).await
+    }
+
+    async fn list_webhooks(
+        &self,
+    ) -> std::result::Result<Vec<Webhook>, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.list_webhooks().await
+    }
+
+    async fn create_api_token(
+        &self,
+        name: &str,
+        scopes: &[TokenScope],
+        token_hash: &str,
+    ) -> std::result::Result<ApiToken, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary
+            .create_api_token(name, scopes, token_hash)
+            .await
+    }
+
+    async fn get_api_token(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<ApiToken>, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.get_api_token(id).await
+    }
+
+    async fn get_api_token_by_hash(
+        &self,
+        token_hash: &str,
+    ) -> std::result::Result<Option<ApiToken>, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.get_api_token_by_hash(token_hash).await
+    }
+
+    async fn list_api_tokens(
+        &self,
+    ) -> std::result::Result<Vec<ApiToken>, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.list_api_tokens().await
+    }
+
+    async fn delete_api_token(&self, id: i64) -> Result<()> {
+        self.primary.delete_api_token(id).await
+    }
+
+    async fn delete_webhook(&self, id: i64) -> Result<()> {
+        self.primary.delete_webhook(id).await
+    }
+
+    async fn update_webhook(
+        &self,
+        id: i64,
+        settings: &WebhookSettings,
+    ) -> std::result::Result<Webhook, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.update_webhook(id, settings).await
+    }
+
+    async fn record_webhook_delivery(
+        &self,
+        attempt: &DeliveryAttempt,
+    ) -> std::result::Result<WebhookDelivery, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.record_webhook_delivery(attempt).await
+    }
+
+    async fn list_webhook_deliveries(
+        &self,
+        webhook_id: i64,
+    ) -> std::result::Result<Vec<WebhookDelivery>, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.list_webhook_deliveries(webhook_id).await
+    }
+
+    async fn get_webhook_delivery(
+        &self,
+        webhook_id: i64,
+        id: i64,
+    ) -> std::result::Result<Option<WebhookDelivery>, Box<dyn std::error::Error + Send + Sync>>
+    {
+        self.primary.get_webhook_delivery(webhook_id, id).await
+    }
+
+    async fn create_script_hook(
+        &self,
+        settings: &ScriptHookSettings,
+    ) -> std::result::Result<ScriptHook, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.create_script_hook(settings).await
+    }
+
+    async fn get_script_hook(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<ScriptHook>, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.get_script_hook(id).await
+    }
+
+    async fn list_script_hooks(
+        &self,
+    ) -> std::result::Result<Vec<ScriptHook>, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.list_script_hooks().await
+    }
+
+    async fn delete_script_hook(&self, id: i64) -> Result<()> {
+        self.primary.delete_script_hook(id).await
+    }
+
+    async fn create_job(
+        &self,
+        kind: &str,
+        progress: &Value,
+    ) -> std::result::Result<Job, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.create_job(kind, progress).await
+    }
+
+    async fn get_job(
+        &self,
+        id: i64,
+    ) -> std::result::Result<Option<Job>, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.get_job(id).await
+    }
+
+    async fn update_job(
+        &self,
+        id: i64,
+        state: JobState,
+        progress: Option<&Value>,
+        outcome: Option<&Value>,
+    ) -> Result<bool> {
+        self.primary.update_job(id, state, progress, outcome).await
+    }
+
+    async fn fail_unfinished_jobs(&self, error: &Value) -> Result<u64> {
+        self.primary.fail_unfinished_jobs(error).await
+    }
+
+    async fn upgrade_record_formats(
+        &self,
+        limit: usize,
+    ) -> std::result::Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.upgrade_record_formats(limit).await
+    }
+
+    async fn storage_stats(
+        &self,
+    ) -> std::result::Result<Vec<StorageStats>, Box<dyn std::error::Error + Send + Sync>> {
+        self.reader().storage_stats().await
+    }
+
+    async fn scrub_records(
+        &self,
+        after_id: i64,
+        limit: usize,
+    ) -> std::result::Result<ScrubBatch, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.scrub_records(after_id, limit).await
+    }
+
+    async fn list_checksum_mismatches(
+        &self,
+    ) -> std::result::Result<Vec<MismatchReport>, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.list_checksum_mismatches().await
+    }
+
+    async fn get_kv(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> std::result::Result<Option<KvEntry>, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.get_kv(namespace, key).await
+    }
+
+    async fn set_kv(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &Value,
+        ttl: Option<Duration>,
+    ) -> std::result::Result<KvEntry, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.set_kv(namespace, key, value, ttl).await
+    }
+
+    async fn delete_kv(&self, namespace: &str, key: &str) -> Result<bool> {
+        self.primary.delete_kv(namespace, key).await
+    }
+
+    async fn claim_idempotency_key(
+        &self,
+        key: &str,
+        collection_id: i64,
+        ttl: Duration,
+    ) -> std::result::Result<IdempotencyClaim, Box<dyn std::error::Error + Send + Sync>> {
+        self.primary
+            .claim_idempotency_key(key, collection_id, ttl)
+            .await
+    }
+
+    async fn complete_idempotency_key(&self, key: &str, record_id: i64) -> Result<()> {
+        self.primary.complete_idempotency_key(key, record_id).await
+    }
+
+    async fn release_idempotency_key(&self, key: &str) -> Result<()> {
+        self.primary.release_idempotency_key(key).await
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.primary.ping().await
+    }
+
+    async fn backup_into(&self, path: &Path) -> Result<()> {
+        self.primary.backup_into(path).await
+    }
+
+    async fn restore_from(
+        &self,
+        path: &Path,
+    ) -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.primary.restore_from(path).await
+    }
+}
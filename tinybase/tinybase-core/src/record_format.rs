@@ -0,0 +1,207 @@
+//! The on-disk format of record data, so the way records are stored can
+//! change without a big-bang migration.
+//!
+//! Each row of `records` carries the format its `data` was written in. Rows
+//! are read in whatever format they have and rewritten in [`CURRENT`]
+//! lazily: whenever a record is written, when one is read by id, and in
+//! batches by [`Db::upgrade_record_formats`](crate::Db::upgrade_record_formats),
+//! which the server runs as a background job. Changing the format, e.g. to
+//! canonicalize data, adds a [`RecordFormat`] with its decoding, makes
+//! [`encode`] write it and bumps [`CURRENT`].
+//!
+//! Filters, sorts and partial updates work on `data` in SQL, so every
+//! format keeps it readable as JSON there.
+//!
+//! Collections holding large documents can set `"compress": {"min_bytes":
+//! 4096}` in their schema. Records whose data takes at least `min_bytes`
+//! as JSON are then written [`Packed`](RecordFormat::Packed): their
+//! strings, arrays and objects of [`MIN_PACKED_MEMBER`] bytes or more are
+//! compressed with zstd into one [`PACKED_KEY`] member, and only the rest
+//! stays plain. Short fields, the ones records are usually filtered and
+//! sorted by, keep working in SQL; the compressed members don't match
+//! filters, sorts or lookups. A record is only packed when that saves room,
+//! and packed records are unpacked before their data is updated in place.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// The formats records have been stored in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordFormat {
+    /// Rows written before formats were recorded: JSON text.
+    Legacy = 0,
+    /// JSON text, stamped with its format.
+    Json = 1,
+    /// JSON text with its large members compressed into [`PACKED_KEY`].
+    Packed = 2,
+}
+
+/// The format records are written in, unless they are packed. Rows in an
+/// earlier format are upgraded.
+pub const CURRENT: RecordFormat = RecordFormat::Json;
+
+/// The member packed records keep their compressed members in, as
+/// `{"size": <bytes as JSON>, "zstd": <base64>}`.
+pub const PACKED_KEY: &str = "_zstd";
+
+/// Members shorter than this as JSON are never compressed.
+pub const MIN_PACKED_MEMBER: usize = 256;
+
+/// The zstd level packed members are compressed at.
+const LEVEL: i32 = 3;
+
+/// How a collection compresses the data of its records.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CompressSettings {
+    /// The size of record data, as JSON, from which records are packed.
+    #[serde(default = "default_min_bytes")]
+    pub min_bytes: usize,
+}
+
+/// `min_bytes` when a collection's `compress` setting has none.
+pub const DEFAULT_MIN_BYTES: usize = 4096;
+
+fn default_min_bytes() -> usize {
+    DEFAULT_MIN_BYTES
+}
+
+impl RecordFormat {
+    /// The format stored as `number`, if this build knows it.
+    pub fn from_number(number: i64) -> Option<Self> {
+        match number {
+            0 => Some(RecordFormat::Legacy),
+            1 => Some(RecordFormat::Json),
+            2 => Some(RecordFormat::Packed),
+            _ => None,
+        }
+    }
+
+    pub fn number(self) -> i64 {
+        self as i64
+    }
+}
+
+/// Why stored record data can't be read.
+#[derive(Debug)]
+pub enum FormatError {
+    /// The row was written in a format this build doesn't know, e.g. by a
+    /// newer server before a downgrade.
+    Unknown(i64),
+    Invalid(serde_json::Error),
+    /// The compressed members of a packed row can't be unpacked.
+    Corrupt(String),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::Unknown(format) => write!(
+                f,
+                "Record data is stored in format {}, newer than format {} this server reads",
+                format,
+                CURRENT.number()
+            ),
+            FormatError::Invalid(e) => write!(f, "Invalid record data: {}", e),
+            FormatError::Corrupt(e) => write!(f, "Corrupt compressed record data: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+/// The data of a row stored as `raw` in `format`.
+pub fn decode(format: i64, raw: &str) -> Result<Value, FormatError> {
+    match RecordFormat::from_number(format).ok_or(FormatError::Unknown(format))? {
+        RecordFormat::Legacy | RecordFormat::Json => {
+            serde_json::from_str(raw).map_err(FormatError::Invalid)
+        }
+        RecordFormat::Packed => {
+            let mut data: Map<String, Value> =
+                serde_json::from_str(raw).map_err(FormatError::Invalid)?;
+            if let Some(packed) = data.remove(PACKED_KEY) {
+                // Members set in place since the record was packed are plain
+                // and newer than their compressed copy.
+                for (key, value) in unpack(&packed)? {
+                    data.entry(key).or_insert(value);
+                }
+            }
+            Ok(Value::Object(data))
+        }
+    }
+}
+
+fn unpack(packed: &Value) -> Result<Map<String, Value>, FormatError> {
+    let encoded = packed
+        .get("zstd")
+        .and_then(Value::as_str)
+        .ok_or_else(|| FormatError::Corrupt("no compressed members".to_string()))?;
+    let compressed = BASE64
+        .decode(encoded)
+        .map_err(|e| FormatError::Corrupt(e.to_string()))?;
+    let json =
+        zstd::decode_all(compressed.as_slice()).map_err(|e| FormatError::Corrupt(e.to_string()))?;
+    serde_json::from_slice(&json).map_err(FormatError::Invalid)
+}
+
+/// `data` stored in the [`CURRENT`] format, or [`Packed`](RecordFormat::Packed)
+/// when it takes at least `min_bytes` and packing it saves room. Returns the
+/// stored text with its format.
+pub fn encode(
+    data: &Value,
+    min_bytes: Option<usize>,
+) -> serde_json::Result<(String, RecordFormat)> {
+    let json = serde_json::to_string(data)?;
+    if let (Some(min_bytes), Value::Object(members)) = (min_bytes, data) {
+        // Data already using the key is left plain, so it reads back as is.
+        if json.len() >= min_bytes && !members.contains_key(PACKED_KEY) {
+            if let Some(packed) = pack(members)? {
+                return Ok((packed, RecordFormat::Packed));
+            }
+        }
+    }
+    Ok((json, CURRENT))
+}
+
+/// `members` with the large ones compressed, `None` if that saves nothing.
+fn pack(members: &Map<String, Value>) -> serde_json::Result<Option<String>> {
+    let mut plain = Map::new();
+    let mut large = Map::new();
+    for (key, value) in members {
+        let compressible = matches!(value, Value::String(_) | Value::Array(_) | Value::Object(_))
+            && serde_json::to_string(value)?.len() >= MIN_PACKED_MEMBER;
+        let target = if compressible { &mut large } else { &mut plain };
+        target.insert(key.clone(), value.clone());
+    }
+    if large.is_empty() {
+        return Ok(None);
+    }
+    let json = serde_json::to_vec(&large)?;
+    let Ok(compressed) = zstd::encode_all(json.as_slice(), LEVEL) else {
+        return Ok(None);
+    };
+    let encoded = BASE64.encode(compressed);
+    if encoded.len() >= json.len() {
+        return Ok(None);
+    }
+    plain.insert(
+        PACKED_KEY.to_string(),
+        serde_json::json!({ "size": json.len(), "zstd": encoded }),
+    );
+    serde_json::to_string(&plain).map(Some)
+}
+
+/// How much room the records of a collection take.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StorageStats {
+    pub collection_id: i64,
+    pub records: u64,
+    /// Records stored [`Packed`](RecordFormat::Packed).
+    pub packed: u64,
+    /// The size of the stored data of the records.
+    pub data_bytes: u64,
+    /// How many bytes fewer the compressed members of packed records take
+    /// than they would as JSON.
+    pub saved_bytes: u64,
+}
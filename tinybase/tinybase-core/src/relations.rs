@@ -0,0 +1,155 @@
+use crate::schema::{CollectionSchema, FieldType};
+use crate::validation::{ValidationError, ValidationErrorKind};
+use crate::{stream_records, Collection, Db, Record};
+use futures_util::TryStreamExt;
+use serde_json::Value;
+use std::sync::Arc;
+
+type BoxResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Iterates over the relation fields of a schema as
+/// `(field, target collection id, cascade_delete)`.
+pub fn relation_fields(schema: &CollectionSchema) -> impl Iterator<Item = (&String, i64, bool)> {
+    schema
+        .fields
+        .iter()
+        .filter_map(|(name, def)| match def.r#type {
+            FieldType::Relation {
+                collection_id,
+                cascade_delete,
+            } => Some((name, collection_id, cascade_delete)),
+            _ => None,
+        })
+}
+
+/// Checks that every relation field of `data` points at an existing record,
+/// returning one `MissingRelation` error per dangling reference.
+pub async fn check_relations(
+    db: &dyn Db,
+    schema: &CollectionSchema,
+    data: &Value,
+) -> BoxResult<Vec<ValidationError>> {
+    let mut errors = Vec::new();
+    for (field, target, _) in relation_fields(schema) {
+        if let Some(id) = data.get(field).and_then(Value::as_i64) {
+            if db.get_record(target, id).await?.is_none() {
+                errors.push(ValidationError::field(
+                    field,
+                    ValidationErrorKind::MissingRelation,
+                    Some(Value::from(id)),
+                ));
+            }
+        }
+    }
+    Ok(errors)
+}
+
+/// Deletes a record along with every record that references it through a
+/// relation field declared with `cascade_delete`, transitively.
+///
+/// Returns the removed records as `(collection_id, record)` pairs, starting
+/// with the requested one.
+pub async fn delete_record_cascade(
+    db: &dyn Db,
+    collection_id: i64,
+    record_id: i64,
+) -> BoxResult<Vec<(i64, Record)>> {
+    let collections = db.list_collections().await?;
+    cascade(db, &collections, vec![(collection_id, record_id)]).await
+}
+
+/// Cascades the deletion of a record that has already been removed, e.g.
+/// inside a batch, to the records depending on it.
+pub async fn delete_dependents(
+    db: &dyn Db,
+    collection_id: i64,
+    record_id: i64,
+) -> BoxResult<Vec<(i64, Record)>> {
+    let collections = db.list_collections().await?;
+    let pending = dependents(db, &collections, collection_id, record_id).await?;
+    cascade(db, &collections, pending).await
+}
+
+/// Cascades the deletion of a collection, already removed along with its
+/// records, to the records of other collections referencing any of them.
+/// The referencing collections are read a page at a time.
+pub async fn delete_collection_dependents(
+    db: Arc<dyn Db>,
+    collection_id: i64,
+) -> BoxResult<Vec<(i64, Record)>> {
+    let collections = db.list_collections().await?;
+    let mut pending = Vec::new();
+    for collection in &collections {
+        let Some(schema) = &collection.schema else {
+            continue;
+        };
+        let fields: Vec<&String> = relation_fields(schema)
+            .filter(|(_, target, cascade)| *cascade && *target == collection_id)
+            .map(|(field, _, _)| field)
+            .collect();
+        if fields.is_empty() {
+            continue;
+        }
+        let mut records = stream_records(db.clone(), collection.id);
+        while let Some(record) = records.try_next().await? {
+            if fields
+                .iter()
+                .any(|field| record.data.get(field).and_then(Value::as_i64).is_some())
+            {
+                pending.push((collection.id, record.id));
+            }
+        }
+    }
+    cascade(db.as_ref(), &collections, pending).await
+}
+
+/// Records referencing `(collection_id, record_id)` through a cascading
+/// relation field.
+async fn dependents(
+    db: &dyn Db,
+    collections: &[Collection],
+    collection_id: i64,
+    record_id: i64,
+) -> BoxResult<Vec<(i64, i64)>> {
+    let mut found = Vec::new();
+    for collection in collections {
+        let Some(schema) = &collection.schema else {
+            continue;
+        };
+        for (field, target, cascade) in relation_fields(schema) {
+            if cascade && target == collection_id {
+                for dependent in db
+                    .find_records_by_field(collection.id, field, &Value::from(record_id))
+                    .await?
+                {
+                    found.push((collection.id, dependent.id));
+                }
+            }
+        }
+    }
+    Ok(found)
+}
+
+async fn cascade(
+    db: &dyn Db,
+    collections: &[Collection],
+    mut pending: Vec<(i64, i64)>,
+) -> BoxResult<Vec<(i64, Record)>> {
+    let mut removed: Vec<(i64, Record)> = Vec::new();
+    while let Some((cid, rid)) = pending.pop() {
+        if removed.iter().any(|(c, r)| *c == cid && r.id == rid) {
+            continue;
+        }
+        let Some(record) = db.get_record(cid, rid).await? else {
+            continue;
+        };
+        let found = dependents(db, collections, cid, rid).await?;
+        // Deleted in the meantime, by someone else.
+        if db.delete_record(cid, rid).await? == 0 {
+            continue;
+        }
+        pending.extend(found);
+        removed.push((cid, record));
+    }
+    Ok(removed)
+}
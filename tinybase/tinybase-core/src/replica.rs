@@ -0,0 +1,160 @@
+//! Running on an embedded replica of a remote libsql database, e.g. Turso.
+//!
+//! Reads are served from a local copy of the database that is kept in sync
+//! with the primary; writes go to the primary and are read back locally.
+//! [`ReplicaSync`] pulls changes on an interval and keeps the status of the
+//! last sync, which the health endpoint reports.
+
+use crate::{clock, format_timestamp, setup_database};
+use libsql::{Builder, Database, Result};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const DEFAULT_SYNC_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Where the replica lives and what it replicates.
+#[derive(Clone)]
+pub struct ReplicaConfig {
+    /// The local replica file.
+    pub path: PathBuf,
+    /// URL of the primary, e.g. `libsql://<db>.turso.io`.
+    pub url: String,
+    pub auth_token: String,
+    pub sync_interval: Duration,
+}
+
+impl ReplicaConfig {
+    pub fn new(
+        path: impl Into<PathBuf>,
+        url: impl Into<String>,
+        auth_token: impl Into<String>,
+    ) -> Self {
+        Self {
+            path: path.into(),
+            url: url.into(),
+            auth_token: auth_token.into(),
+            sync_interval: DEFAULT_SYNC_INTERVAL,
+        }
+    }
+
+    pub fn with_sync_interval(mut self, interval: Duration) -> Self {
+        self.sync_interval = interval;
+        self
+    }
+
+    /// Reads `TINYBASE_REPLICA_URL`, `TINYBASE_REPLICA_AUTH_TOKEN` and
+    /// `TINYBASE_REPLICA_SYNC_SECS`; `None` unless a URL is set.
+    pub fn from_env(path: impl Into<PathBuf>) -> Option<Self> {
+        let url = std::env::var("TINYBASE_REPLICA_URL").ok()?;
+        let auth_token = std::env::var("TINYBASE_REPLICA_AUTH_TOKEN").unwrap_or_default();
+        let config = Self::new(path, url, auth_token);
+        Some(
+            match std::env::var("TINYBASE_REPLICA_SYNC_SECS")
+                .ok()
+                .and_then(|s| s.parse::<u64>().ok())
+            {
+                Some(secs) => config.with_sync_interval(Duration::from_secs(secs.max(1))),
+                None => config,
+            },
+        )
+    }
+
+    /// Opens the replica, syncs it once and creates the tables Tinybase
+    /// needs on the primary if they are missing.
+    pub async fn open(&self) -> Result<Database> {
+        let db = Builder::new_remote_replica(&self.path, self.url.clone(), self.auth_token.clone())
+            .build()
+            .await?;
+        db.sync().await?;
+        setup_database(&db.connect()?).await?;
+        Ok(db)
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ReplicationStatus {
+    /// URL of the primary.
+    pub url: String,
+    /// Whether the last sync succeeded.
+    pub ok: bool,
+    /// When the replica last synced successfully.
+    pub last_sync: Option<String>,
+    /// When a sync was last attempted.
+    pub last_attempt: Option<String>,
+    /// Replication frame the replica is at.
+    pub frame_no: Option<u64>,
+    /// Write-ahead log frames pulled since startup.
+    pub frames_synced: u64,
+    /// Why the last sync failed.
+    pub error: Option<String>,
+    pub sync_interval_secs: u64,
+}
+
+/// Syncs a replica periodically and keeps the status of the last sync.
+#[derive(Clone)]
+pub struct ReplicaSync {
+    db: Arc<Database>,
+    interval: Duration,
+    status: Arc<Mutex<ReplicationStatus>>,
+}
+
+impl ReplicaSync {
+    pub fn new(db: Arc<Database>, config: &ReplicaConfig) -> Self {
+        Self {
+            db,
+            interval: config.sync_interval,
+            status: Arc::new(Mutex::new(ReplicationStatus {
+                url: config.url.clone(),
+                ok: true,
+                last_sync: None,
+                last_attempt: None,
+                frame_no: None,
+                frames_synced: 0,
+                error: None,
+                sync_interval_secs: config.sync_interval.as_secs(),
+            })),
+        }
+    }
+
+    pub fn status(&self) -> ReplicationStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Pulls changes from the primary now and updates the status.
+    pub async fn sync_once(&self) -> ReplicationStatus {
+        let result = self.db.sync().await;
+        let now = format_timestamp(clock::now());
+        let mut status = self.status.lock().unwrap();
+        status.last_attempt = Some(now.clone());
+        match result {
+            Ok(replicated) => {
+                status.ok = true;
+                status.last_sync = Some(now);
+                status.frame_no = replicated.frame_no().or(status.frame_no);
+                status.frames_synced += replicated.frames_synced() as u64;
+                status.error = None;
+            }
+            Err(e) => {
+                status.ok = false;
+                status.error = Some(e.to_string());
+            }
+        }
+        status.clone()
+    }
+
+    /// Runs [`sync_once`](Self::sync_once) every interval, starting now.
+    pub fn spawn(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.interval);
+            loop {
+                ticker.tick().await;
+                let status = self.sync_once().await;
+                if let Some(error) = status.error {
+                    tracing::warn!(%error, "replica sync failed");
+                }
+            }
+        })
+    }
+}
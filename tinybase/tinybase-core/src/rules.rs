@@ -0,0 +1,670 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use thiserror::Error;
+
+use crate::{clock, format_timestamp};
+
+/// Errors raised while parsing or evaluating a rule expression.
+#[derive(Error, Debug, PartialEq, Serialize)]
+pub enum RuleError {
+    #[error("Unexpected character '{0}' at position {1}")]
+    UnexpectedCharacter(char, usize),
+    #[error("Unterminated string literal starting at position {0}")]
+    UnterminatedString(usize),
+    #[error("Unexpected token '{0}'")]
+    UnexpectedToken(String),
+    #[error("Unexpected end of rule expression")]
+    UnexpectedEnd,
+    #[error("Unknown function '{0}'")]
+    UnknownFunction(String),
+    #[error("Function '{0}' takes {1}")]
+    WrongArgumentCount(String, &'static str),
+}
+
+/// The data a rule is evaluated against.
+///
+/// `@request.auth.*` resolves into `auth`, `@request.data.*` into `data`
+/// (the incoming request body) and bare identifiers into `record`.
+/// `@collection.<name>` is the list of records of `collections` under that
+/// name, usually narrowed with `.filter(...)`, inside which bare identifiers
+/// are the fields of each record and `@record.*` still the record at hand.
+#[derive(Debug, Default, Clone)]
+pub struct RuleContext {
+    pub auth: Value,
+    pub data: Value,
+    pub record: Value,
+    pub collections: HashMap<String, Vec<Value>>,
+}
+
+/// The outcome of evaluating a rule, together with a step-by-step trace of
+/// how the result was reached.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Evaluation {
+    pub allowed: bool,
+    pub trace: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Str(String),
+    Num(f64),
+    Ident(String),
+    Op(&'static str),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+    Comma,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Str(s) => write!(f, "\"{}\"", s),
+            Token::Num(n) => write!(f, "{}", n),
+            Token::Ident(i) => write!(f, "{}", i),
+            Token::Op(op) => write!(f, "{}", op),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::Not => write!(f, "!"),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::Comma => write!(f, ","),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Literal(Value),
+    Path(String),
+    Call(&'static str, Vec<Operand>),
+    /// `@collection.<name>.filter(...)`: the records of a collection the
+    /// expression holds for.
+    Filter(String, Box<Expr>),
+}
+
+/// The built-in functions, with the least and most arguments they take and
+/// how error messages put that.
+const FUNCTIONS: &[(&str, usize, usize, &str)] = &[
+    ("now", 0, 0, "no arguments"),
+    ("in", 2, usize::MAX, "at least two arguments"),
+    ("lower", 1, 1, "one argument"),
+    ("upper", 1, 1, "one argument"),
+    ("len", 1, 1, "one argument"),
+    ("geo_distance", 4, 4, "four arguments"),
+    ("date_add", 3, 3, "three arguments"),
+    ("date_diff", 3, 3, "three arguments"),
+];
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Operand(Operand),
+    Compare(Operand, &'static str, Operand),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, RuleError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                i += 1;
+            }
+            '&' if chars.get(i + 1) == Some(&'&') => {
+                tokens.push(Token::And);
+                i += 2;
+            }
+            '|' if chars.get(i + 1) == Some(&'|') => {
+                tokens.push(Token::Or);
+                i += 2;
+            }
+            '=' => {
+                tokens.push(Token::Op("="));
+                i += if chars.get(i + 1) == Some(&'=') { 2 } else { 1 };
+            }
+            '!' => match chars.get(i + 1) {
+                Some('=') => {
+                    tokens.push(Token::Op("!="));
+                    i += 2;
+                }
+                Some('~') => {
+                    tokens.push(Token::Op("!~"));
+                    i += 2;
+                }
+                _ => {
+                    tokens.push(Token::Not);
+                    i += 1;
+                }
+            },
+            '>' | '<' => {
+                let op = match (c, chars.get(i + 1)) {
+                    ('>', Some('=')) => ">=",
+                    ('<', Some('=')) => "<=",
+                    ('>', _) => ">",
+                    _ => "<",
+                };
+                tokens.push(Token::Op(op));
+                i += op.len();
+            }
+            '~' => {
+                tokens.push(Token::Op("~"));
+                i += 1;
+            }
+            '"' | '\'' => {
+                let start = i;
+                i += 1;
+                let mut s = String::new();
+                loop {
+                    match chars.get(i) {
+                        None => return Err(RuleError::UnterminatedString(start)),
+                        Some('\\') if chars.get(i + 1).is_some() => {
+                            s.push(chars[i + 1]);
+                            i += 2;
+                        }
+                        Some(&q) if q == c => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&other) => {
+                            s.push(other);
+                            i += 1;
+                        }
+                    }
+                }
+                tokens.push(Token::Str(s));
+            }
+            c if c.is_ascii_digit()
+                || (c == '-' && chars.get(i + 1).is_some_and(|n| n.is_ascii_digit())) =>
+            {
+                let start = i;
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let n = text
+                    .parse::<f64>()
+                    .map_err(|_| RuleError::UnexpectedToken(text.clone()))?;
+                tokens.push(Token::Num(n));
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '@' => {
+                let start = i;
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | '@' | '.'))
+                {
+                    i += 1;
+                }
+                tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            }
+            other => return Err(RuleError::UnexpectedCharacter(other, i)),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, RuleError> {
+        let mut left = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            let right = self.parse_and()?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, RuleError> {
+        let mut left = self.parse_not()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            let right = self.parse_not()?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, RuleError> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, RuleError> {
+        if self.peek() == Some(&Token::LParen) {
+            self.next();
+            let expr = self.parse_or()?;
+            self.expect_rparen()?;
+            return Ok(expr);
+        }
+        let left = self.parse_operand()?;
+        if let Some(Token::Op(op)) = self.peek() {
+            let op = *op;
+            self.next();
+            let right = self.parse_operand()?;
+            return Ok(Expr::Compare(left, op, right));
+        }
+        Ok(Expr::Operand(left))
+    }
+
+    fn expect_rparen(&mut self) -> Result<(), RuleError> {
+        match self.next() {
+            Some(Token::RParen) => Ok(()),
+            Some(token) => Err(RuleError::UnexpectedToken(token.to_string())),
+            None => Err(RuleError::UnexpectedEnd),
+        }
+    }
+
+    /// The arguments of a call, its opening parenthesis already read.
+    fn parse_arguments(&mut self) -> Result<Vec<Operand>, RuleError> {
+        let mut args = Vec::new();
+        if self.peek() == Some(&Token::RParen) {
+            self.next();
+            return Ok(args);
+        }
+        loop {
+            args.push(self.parse_operand()?);
+            match self.next() {
+                Some(Token::Comma) => {}
+                Some(Token::RParen) => return Ok(args),
+                Some(token) => return Err(RuleError::UnexpectedToken(token.to_string())),
+                None => return Err(RuleError::UnexpectedEnd),
+            }
+        }
+    }
+
+    fn parse_call(&mut self, name: String) -> Result<Operand, RuleError> {
+        if let Some(collection) = name
+            .strip_prefix("@collection.")
+            .and_then(|rest| rest.strip_suffix(".filter"))
+        {
+            let condition = self.parse_or()?;
+            self.expect_rparen()?;
+            return Ok(Operand::Filter(collection.to_string(), Box::new(condition)));
+        }
+        let &(function, min, max, takes) = FUNCTIONS
+            .iter()
+            .find(|(function, ..)| *function == name)
+            .ok_or(RuleError::UnknownFunction(name))?;
+        let args = self.parse_arguments()?;
+        if args.len() < min || args.len() > max {
+            return Err(RuleError::WrongArgumentCount(function.to_string(), takes));
+        }
+        Ok(Operand::Call(function, args))
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand, RuleError> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(Operand::Literal(Value::String(s))),
+            Some(Token::Num(n)) => Ok(Operand::Literal(serde_json::json!(n))),
+            Some(Token::Ident(ident)) if self.peek() == Some(&Token::LParen) => {
+                self.next();
+                self.parse_call(ident)
+            }
+            Some(Token::Ident(ident)) => Ok(match ident.as_str() {
+                "true" => Operand::Literal(Value::Bool(true)),
+                "false" => Operand::Literal(Value::Bool(false)),
+                "null" => Operand::Literal(Value::Null),
+                _ => Operand::Path(ident),
+            }),
+            Some(token) => Err(RuleError::UnexpectedToken(token.to_string())),
+            None => Err(RuleError::UnexpectedEnd),
+        }
+    }
+}
+
+fn parse(rule: &str) -> Result<Expr, RuleError> {
+    let mut parser = Parser {
+        tokens: tokenize(rule)?,
+        pos: 0,
+    };
+    let expr = parser.parse_or()?;
+    match parser.next() {
+        None => Ok(expr),
+        Some(token) => Err(RuleError::UnexpectedToken(token.to_string())),
+    }
+}
+
+/// Checks that a rule expression parses, without evaluating it.
+pub fn check_rule(rule: &str) -> Result<(), RuleError> {
+    if rule.trim().is_empty() {
+        return Ok(());
+    }
+    parse(rule).map(|_| ())
+}
+
+/// The collections a rule looks up with `@collection.<name>`, sorted and
+/// without duplicates; none for a rule that doesn't parse.
+pub fn referenced_collections(rule: &str) -> Vec<String> {
+    fn walk_operand(operand: &Operand, names: &mut Vec<String>) {
+        match operand {
+            Operand::Literal(_) => {}
+            Operand::Path(path) => {
+                if let Some(rest) = path.strip_prefix("@collection.") {
+                    let name = rest.split('.').next().unwrap_or(rest);
+                    names.push(name.to_string());
+                }
+            }
+            Operand::Call(_, args) => args.iter().for_each(|arg| walk_operand(arg, names)),
+            Operand::Filter(collection, condition) => {
+                names.push(collection.clone());
+                walk(condition, names);
+            }
+        }
+    }
+    fn walk(expr: &Expr, names: &mut Vec<String>) {
+        match expr {
+            Expr::Operand(operand) => walk_operand(operand, names),
+            Expr::Compare(left, _, right) => {
+                walk_operand(left, names);
+                walk_operand(right, names);
+            }
+            Expr::Not(inner) => walk(inner, names),
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                walk(left, names);
+                walk(right, names);
+            }
+        }
+    }
+    let mut names = Vec::new();
+    if let Ok(expr) = parse(rule) {
+        walk(&expr, &mut names);
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+fn lookup<'a>(root: &'a Value, path: &str) -> &'a Value {
+    let mut current = root;
+    for segment in path.split('.').filter(|s| !s.is_empty()) {
+        current = match current {
+            Value::Object(map) => map.get(segment).unwrap_or(&Value::Null),
+            Value::Array(items) => segment
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| items.get(i))
+                .unwrap_or(&Value::Null),
+            _ => &Value::Null,
+        };
+    }
+    current
+}
+
+/// The value of `operand`, looking bare identifiers up in `scope`: the
+/// record at hand, or the record a filter is looking at.
+fn resolve(operand: &Operand, ctx: &RuleContext, scope: &Value) -> Value {
+    match operand {
+        Operand::Literal(v) => v.clone(),
+        Operand::Path(path) => {
+            if let Some(rest) = path.strip_prefix("@request.auth") {
+                lookup(&ctx.auth, rest).clone()
+            } else if let Some(rest) = path.strip_prefix("@request.data") {
+                lookup(&ctx.data, rest).clone()
+            } else if let Some(rest) = path.strip_prefix("@record") {
+                lookup(&ctx.record, rest).clone()
+            } else if let Some(rest) = path.strip_prefix("@collection.") {
+                let (name, rest) = rest.split_once('.').unwrap_or((rest, ""));
+                let records = ctx.collections.get(name).cloned().unwrap_or_default();
+                lookup(&Value::Array(records), rest).clone()
+            } else {
+                lookup(scope, path).clone()
+            }
+        }
+        Operand::Call(function, args) => {
+            let args: Vec<Value> = args.iter().map(|arg| resolve(arg, ctx, scope)).collect();
+            call(function, &args)
+        }
+        Operand::Filter(collection, condition) => {
+            let records = ctx.collections.get(collection).map(Vec::as_slice);
+            Value::Array(
+                records
+                    .unwrap_or_default()
+                    .iter()
+                    .filter(|record| eval(condition, ctx, record, &mut Vec::new()))
+                    .cloned()
+                    .collect(),
+            )
+        }
+    }
+}
+
+/// A date in RFC 3339, like the timestamps of records, or `YYYY-MM-DD`.
+fn parse_date(value: &Value) -> Option<DateTime<Utc>> {
+    let text = value.as_str()?;
+    DateTime::parse_from_rfc3339(text)
+        .map(|date| date.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            let date = NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?;
+            Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+        })
+}
+
+/// Seconds in one of the units of `date_add` and `date_diff`.
+fn unit_seconds(unit: &Value) -> Option<f64> {
+    Some(match unit.as_str()?.trim_end_matches('s') {
+        "second" => 1.0,
+        "minute" => 60.0,
+        "hour" => 3_600.0,
+        "day" => 86_400.0,
+        "week" => 604_800.0,
+        _ => return None,
+    })
+}
+
+/// Great-circle distance in kilometres between two points given in degrees.
+fn geo_distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    const EARTH_RADIUS_KM: f64 = 6_371.0;
+    let (dlat, dlon) = ((lat2 - lat1).to_radians(), (lon2 - lon1).to_radians());
+    let a = (dlat / 2.0).sin().powi(2)
+        + lat1.to_radians().cos() * lat2.to_radians().cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+/// Applies a built-in function. Arguments of the wrong type give `null`,
+/// like paths leading nowhere.
+fn call(function: &str, args: &[Value]) -> Value {
+    match (function, args) {
+        ("now", []) => Value::String(format_timestamp(clock::now())),
+        ("in", [needle, Value::Array(items)]) => {
+            Value::Bool(items.iter().any(|item| values_equal(needle, item)))
+        }
+        ("in", [needle, candidates @ ..]) => {
+            Value::Bool(candidates.iter().any(|item| values_equal(needle, item)))
+        }
+        ("lower", [Value::String(s)]) => Value::String(s.to_lowercase()),
+        ("upper", [Value::String(s)]) => Value::String(s.to_uppercase()),
+        ("len", [value]) => json!(match value {
+            Value::String(s) => s.chars().count(),
+            Value::Array(items) => items.len(),
+            Value::Object(map) => map.len(),
+            _ => 0,
+        }),
+        ("geo_distance", [lat1, lon1, lat2, lon2]) => {
+            match (lat1.as_f64(), lon1.as_f64(), lat2.as_f64(), lon2.as_f64()) {
+                (Some(lat1), Some(lon1), Some(lat2), Some(lon2)) => {
+                    json!(geo_distance(lat1, lon1, lat2, lon2))
+                }
+                _ => Value::Null,
+            }
+        }
+        ("date_add", [date, amount, unit]) => {
+            match (parse_date(date), amount.as_f64(), unit_seconds(unit)) {
+                (Some(date), Some(amount), Some(seconds)) => {
+                    let millis = (amount * seconds * 1_000.0) as i64;
+                    date.checked_add_signed(chrono::Duration::milliseconds(millis))
+                        .map(|date| Value::String(format_timestamp(date.into())))
+                        .unwrap_or(Value::Null)
+                }
+                _ => Value::Null,
+            }
+        }
+        ("date_diff", [later, earlier, unit]) => {
+            match (parse_date(later), parse_date(earlier), unit_seconds(unit)) {
+                (Some(later), Some(earlier), Some(seconds)) => {
+                    let millis = (later - earlier).num_milliseconds() as f64;
+                    json!(millis / 1_000.0 / seconds)
+                }
+                _ => Value::Null,
+            }
+        }
+        _ => Value::Null,
+    }
+}
+
+fn describe(operand: &Operand) -> String {
+    match operand {
+        Operand::Literal(v) => v.to_string(),
+        Operand::Path(p) => p.clone(),
+        Operand::Call(function, args) => format!(
+            "{}({})",
+            function,
+            args.iter().map(describe).collect::<Vec<_>>().join(", ")
+        ),
+        Operand::Filter(collection, _) => format!("@collection.{}.filter(...)", collection),
+    }
+}
+
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+    }
+}
+
+fn values_equal(left: &Value, right: &Value) -> bool {
+    match (left.as_f64(), right.as_f64()) {
+        (Some(l), Some(r)) => l == r,
+        _ => left == right,
+    }
+}
+
+fn contains(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Array(items), _) => items.iter().any(|item| values_equal(item, right)),
+        (Value::String(l), Value::String(r)) => l.to_lowercase().contains(&r.to_lowercase()),
+        _ => false,
+    }
+}
+
+fn compare(left: &Value, op: &str, right: &Value) -> bool {
+    let ordering = match (left, right) {
+        (Value::String(l), Value::String(r)) => Some(l.cmp(r)),
+        _ => match (left.as_f64(), right.as_f64()) {
+            (Some(l), Some(r)) => l.partial_cmp(&r),
+            _ => None,
+        },
+    };
+    match op {
+        "=" => values_equal(left, right),
+        "!=" => !values_equal(left, right),
+        "~" => contains(left, right),
+        "!~" => !contains(left, right),
+        ">" => ordering.is_some_and(|o| o.is_gt()),
+        ">=" => ordering.is_some_and(|o| o.is_ge()),
+        "<" => ordering.is_some_and(|o| o.is_lt()),
+        "<=" => ordering.is_some_and(|o| o.is_le()),
+        _ => false,
+    }
+}
+
+fn eval(expr: &Expr, ctx: &RuleContext, scope: &Value, trace: &mut Vec<String>) -> bool {
+    match expr {
+        Expr::Operand(operand) => {
+            let value = resolve(operand, ctx, scope);
+            let result = is_truthy(&value);
+            trace.push(format!("{} -> {} -> {}", describe(operand), value, result));
+            result
+        }
+        Expr::Compare(left, op, right) => {
+            let (l, r) = (resolve(left, ctx, scope), resolve(right, ctx, scope));
+            let result = compare(&l, op, &r);
+            trace.push(format!(
+                "{} {} {} -> {} {} {} -> {}",
+                describe(left),
+                op,
+                describe(right),
+                l,
+                op,
+                r,
+                result
+            ));
+            result
+        }
+        Expr::Not(inner) => {
+            let result = !eval(inner, ctx, scope, trace);
+            trace.push(format!("negated -> {}", result));
+            result
+        }
+        Expr::And(left, right) => {
+            if !eval(left, ctx, scope, trace) {
+                trace.push("&& short-circuited -> false".to_string());
+                return false;
+            }
+            eval(right, ctx, scope, trace)
+        }
+        Expr::Or(left, right) => {
+            if eval(left, ctx, scope, trace) {
+                trace.push("|| short-circuited -> true".to_string());
+                return true;
+            }
+            eval(right, ctx, scope, trace)
+        }
+    }
+}
+
+/// Evaluates a rule expression against the given context.
+///
+/// An empty rule allows everyone.
+pub fn evaluate(rule: &str, ctx: &RuleContext) -> Result<Evaluation, RuleError> {
+    if rule.trim().is_empty() {
+        return Ok(Evaluation {
+            allowed: true,
+            trace: vec!["empty rule -> true".to_string()],
+        });
+    }
+    let expr = parse(rule)?;
+    let mut trace = Vec::new();
+    let allowed = eval(&expr, ctx, &ctx.record, &mut trace);
+    Ok(Evaluation { allowed, trace })
+}
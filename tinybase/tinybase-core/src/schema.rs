@@ -1,16 +1,395 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::collections::HashMap;
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+use crate::compute;
+use crate::masking::MaskSettings;
+use crate::ordering::OrderSettings;
+use crate::record_format::CompressSettings;
+use crate::transform::{self, Transform};
+use crate::tree::TreeSettings;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
 pub struct CollectionSchema {
     pub fields: HashMap<String, FieldDefinition>,
+    #[serde(default)]
+    pub rules: CollectionRules,
+    /// Applied to record data on every write, before validation.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transforms: Vec<Transform>,
+    /// How many earlier versions of each record are kept; `0` keeps no
+    /// history. Defaults to [`DEFAULT_MAX_VERSIONS`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_versions: Option<u32>,
+    /// Keeps the records in a hierarchy through a parent field, see
+    /// [`tree`](crate::tree).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tree: Option<TreeSettings>,
+    /// Keeps the records in a manual order, see [`ordering`](crate::ordering).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<OrderSettings>,
+    /// Caches the responses to record lists and aggregates until the next
+    /// write to the collection.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheSettings>,
+    /// Refuses record writes and deletes that don't send the `ETag` of the
+    /// record they change as `If-Match`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub optimistic_locking: bool,
+    /// Makes records findable by the words of some fields, see
+    /// [`search`](crate::search).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub search: Option<SearchSettings>,
+    /// Stores the large members of big records compressed, see
+    /// [`record_format`](crate::record_format).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compress: Option<CompressSettings>,
+}
+
+/// Record versions kept when a schema sets no `max_versions`.
+pub const DEFAULT_MAX_VERSIONS: u32 = 10;
+
+/// How a collection's list and aggregate responses are cached.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CacheSettings {
+    /// Seconds a cached response is served at most, in case the data changes
+    /// without a write through the API, e.g. from another process.
+    #[serde(default = "default_cache_ttl")]
+    pub ttl: u64,
+}
+
+fn default_cache_ttl() -> u64 {
+    60
+}
+
+/// Which fields of a collection's records full-text search looks in.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SearchSettings {
+    /// Top-level fields holding text, or arrays of text.
+    pub fields: Vec<String>,
+}
+
+impl CollectionSchema {
+    /// Runs the schema's [`transforms`](Self::transforms) over `data`.
+    pub fn apply_transforms(&self, data: &mut serde_json::Value) {
+        transform::apply_transforms(self, data);
+    }
+
+    /// Sets the computed fields of `data` from its other fields, see
+    /// [`compute`].
+    pub fn apply_computed(&self, data: &mut serde_json::Value) {
+        compute::apply_computed(self, data);
+    }
+
+    /// Converts a JSON Schema document describing record data, an object
+    /// with `properties`, into a collection schema of one field per
+    /// property. Strings become `string` fields, or `email`, `url`, `date`
+    /// and `datetime` ones for the `email`, `uri`, `date` and `date-time`
+    /// formats; numbers and integers become `number` fields and booleans
+    /// `boolean` ones. Objects with `properties` become `object` fields and
+    /// arrays with `items` `array` ones, other objects and arrays `json`
+    /// ones. Bounds, `pattern`, `default`, `description` and the first of
+    /// the `examples` are kept. What a collection schema can't express, e.g.
+    /// `$ref`, `enum`, `oneOf` or constraints on the `items` of an array, is
+    /// refused, naming the property, rather than dropped.
+    pub fn from_json_schema(document: &Value) -> Result<Self, String> {
+        let document = document
+            .as_object()
+            .ok_or("The JSON Schema must be an object")?;
+        for (keyword, value) in document {
+            match keyword.as_str() {
+                "$schema" | "$id" | "$comment" | "title" | "description" => {}
+                "properties" | "required" => {}
+                "type" if value == "object" => {}
+                "type" => {
+                    return Err(format!(
+                        "The JSON Schema must describe an object, not {}",
+                        value
+                    ))
+                }
+                "additionalProperties" if value == &Value::Bool(true) => {}
+                "additionalProperties" => {
+                    return Err(
+                        "`additionalProperties` is not supported: records may always \
+                        hold fields the schema doesn't declare"
+                            .to_string(),
+                    )
+                }
+                keyword => return Err(format!("Unsupported keyword `{}`", keyword)),
+            }
+        }
+        if !document.contains_key("properties") {
+            return Err("The JSON Schema has no `properties`".to_string());
+        }
+        Ok(CollectionSchema {
+            fields: json_schema_fields(document)?,
+            ..Default::default()
+        })
+    }
+
+    /// Fills in the `default` of every optional field missing from `data`.
+    pub fn apply_defaults(&self, data: &mut serde_json::Value) {
+        let Some(map) = data.as_object_mut() else {
+            return;
+        };
+        for (name, field) in &self.fields {
+            if let (false, Some(default)) = (field.required, &field.default) {
+                map.entry(name.clone()).or_insert_with(|| default.clone());
+            }
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+/// The fields the `properties` and `required` of the JSON Schema `object`
+/// describe.
+fn json_schema_fields(
+    object: &Map<String, Value>,
+) -> Result<HashMap<String, FieldDefinition>, String> {
+    let properties = match object.get("properties") {
+        Some(Value::Object(properties)) => properties,
+        _ => return Err("`properties` must be an object".to_string()),
+    };
+    let required = match object.get("required") {
+        None => Vec::new(),
+        Some(Value::Array(names)) => names
+            .iter()
+            .map(|name| name.as_str().ok_or("`required` must list property names"))
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(_) => return Err("`required` must list property names".to_string()),
+    };
+    if let Some(name) = required.iter().find(|n| !properties.contains_key(**n)) {
+        return Err(format!("`required` lists unknown property '{}'", name));
+    }
+    let mut fields = HashMap::new();
+    for (name, property) in properties {
+        let field = json_schema_field(property, required.contains(&name.as_str()))
+            .map_err(|e| format!("Property '{}': {}", name, e))?;
+        fields.insert(name.clone(), field);
+    }
+    Ok(fields)
+}
+
+/// The keywords of JSON Schema properties a field can take.
+const FIELD_KEYWORDS: &[&str] = &[
+    "type",
+    "format",
+    "title",
+    "$comment",
+    "properties",
+    "required",
+    "items",
+    "default",
+    "description",
+    "examples",
+    "example",
+    "minimum",
+    "maximum",
+    "minLength",
+    "maxLength",
+    "pattern",
+    "minItems",
+    "maxItems",
+];
+
+/// The keywords the `items` of an array may have: those giving their type.
+const ITEM_KEYWORDS: &[&str] = &[
+    "type",
+    "format",
+    "title",
+    "$comment",
+    "description",
+    "properties",
+    "required",
+    "items",
+];
+
+/// The field a property of a JSON Schema describes, see
+/// [`CollectionSchema::from_json_schema`].
+fn json_schema_field(property: &Value, required: bool) -> Result<FieldDefinition, String> {
+    let property: &Map<String, Value> = property.as_object().ok_or("expected a schema object")?;
+    if let Some(keyword) = property
+        .keys()
+        .find(|k| !FIELD_KEYWORDS.contains(&k.as_str()))
+    {
+        return Err(format!("unsupported keyword `{}`", keyword));
+    }
+    let kind = match property.get("type") {
+        Some(Value::String(kind)) => kind.as_str(),
+        Some(_) => return Err("`type` must name a single type".to_string()),
+        None => return Err("no `type`".to_string()),
+    };
+    let format = match property.get("format") {
+        Some(format) => Some(format.as_str().ok_or("`format` must be a string")?),
+        None => None,
+    };
+    let r#type = match (kind, format) {
+        ("string", None) => FieldType::String,
+        ("string", Some("email")) => FieldType::Email,
+        ("string", Some("uri")) => FieldType::Url,
+        ("string", Some("date")) => FieldType::Date,
+        ("string", Some("date-time")) => FieldType::DateTime,
+        ("number" | "integer", None) => FieldType::Number,
+        ("boolean", None) => FieldType::Boolean,
+        ("object", None) if property.contains_key("properties") => FieldType::Object {
+            fields: json_schema_fields(property)?,
+        },
+        ("array", None) if property.contains_key("items") => {
+            let items = &property["items"];
+            if let Some(keyword) = items
+                .as_object()
+                .and_then(|items| items.keys().find(|k| !ITEM_KEYWORDS.contains(&k.as_str())))
+            {
+                return Err(format!("`items` may only give a type, not `{}`", keyword));
+            }
+            let item = json_schema_field(items, true).map_err(|e| format!("`items`: {}", e))?;
+            FieldType::Array {
+                items: Box::new(item.r#type),
+            }
+        }
+        ("object" | "array", None) => FieldType::Json,
+        ("string", Some(format)) => return Err(format!("unsupported format `{}`", format)),
+        ("number" | "integer" | "boolean" | "object" | "array", Some(_)) => {
+            return Err(format!("`format` is not supported on {}s", kind))
+        }
+        (kind, _) => return Err(format!("unsupported type `{}`", kind)),
+    };
+    let mut field = FieldDefinition {
+        required,
+        ..FieldDefinition::new(r#type)
+    };
+    let number = |value: &Value| value.as_f64().ok_or("must be a number");
+    let count = |value: &Value| {
+        value
+            .as_u64()
+            .map(|n| n as usize)
+            .ok_or("must be a non-negative integer")
+    };
+    let text = |value: &Value| value.as_str().map(String::from).ok_or("must be a string");
+    for (keyword, value) in property {
+        let numeric = kind == "number" || kind == "integer";
+        let set = match keyword.as_str() {
+            "type" | "format" | "title" | "$comment" => Ok(()),
+            "properties" | "required" if kind == "object" => Ok(()),
+            "items" if kind == "array" => Ok(()),
+            "default" => {
+                field.default = Some(value.clone());
+                Ok(())
+            }
+            "description" => text(value).map(|d| field.description = Some(d)),
+            "examples" => match value.as_array() {
+                Some(examples) => {
+                    field.example = examples.first().cloned();
+                    Ok(())
+                }
+                None => Err("must be an array"),
+            },
+            "example" => {
+                field.example = Some(value.clone());
+                Ok(())
+            }
+            "minimum" if numeric => number(value).map(|n| field.min = Some(n)),
+            "maximum" if numeric => number(value).map(|n| field.max = Some(n)),
+            "minLength" if kind == "string" => count(value).map(|n| field.min_length = Some(n)),
+            "maxLength" if kind == "string" => count(value).map(|n| field.max_length = Some(n)),
+            "pattern" if kind == "string" => text(value).map(|p| field.pattern = Some(p)),
+            "minItems" if kind == "array" => count(value).map(|n| field.min_items = Some(n)),
+            "maxItems" if kind == "array" => count(value).map(|n| field.max_items = Some(n)),
+            keyword => return Err(format!("`{}` does not apply to {}s", keyword, kind)),
+        };
+        set.map_err(|e| format!("`{}` {}", keyword, e))?;
+    }
+    Ok(field)
+}
+
+/// Access rules for the record operations of a collection.
+///
+/// Each rule is an expression understood by `rules::evaluate`. `None` locks the
+/// operation to admins only, while an empty string allows everyone.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct CollectionRules {
+    pub list: Option<String>,
+    pub view: Option<String>,
+    pub create: Option<String>,
+    pub update: Option<String>,
+    pub delete: Option<String>,
+}
+
+impl CollectionRules {
+    /// Iterates over the configured rules as `(operation, expression)` pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [
+            ("list", &self.list),
+            ("view", &self.view),
+            ("create", &self.create),
+            ("update", &self.update),
+            ("delete", &self.delete),
+        ]
+        .into_iter()
+        .filter_map(|(op, rule)| rule.as_deref().map(|r| (op, r)))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct FieldDefinition {
     pub r#type: FieldType,
     pub required: bool,
     pub default: Option<serde_json::Value>,
+    /// Inclusive bounds for numbers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    /// Inclusive bounds on the length of strings, in characters.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_length: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<usize>,
+    /// A regular expression strings must match somewhere; anchor it with
+    /// `^...$` to match the whole value.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// Inclusive bounds on the number of items of arrays.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_items: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_items: Option<usize>,
+    /// Documents the field in generated API documents and clients.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// A sample value, shown alongside the description.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub example: Option<serde_json::Value>,
+    /// An expression the field is computed from on every write, e.g.
+    /// `price * qty`; see [`compute`]. Values sent for it are ignored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compute: Option<String>,
+    /// Hides the value from viewers without certain roles, see
+    /// [`masking`](crate::masking).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mask: Option<MaskSettings>,
+}
+
+impl FieldDefinition {
+    /// An optional field of type `r#type`, without default, constraints or
+    /// documentation.
+    pub fn new(r#type: FieldType) -> Self {
+        FieldDefinition {
+            r#type,
+            required: false,
+            default: None,
+            min: None,
+            max: None,
+            min_length: None,
+            max_length: None,
+            pattern: None,
+            min_items: None,
+            max_items: None,
+            description: None,
+            example: None,
+            compute: None,
+            mask: None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -21,4 +400,49 @@ pub enum FieldType {
     Number,
     Boolean,
     Json,
+    File,
+    /// An email address, checked against the RFC 5322 `addr-spec` syntax
+    /// without comments or quoted local parts.
+    Email,
+    /// An absolute `http` or `https` URL.
+    Url,
+    /// An RFC 3339 full date, e.g. `2024-05-01`.
+    Date,
+    /// An RFC 3339 date-time with offset, e.g. `2024-05-01T12:00:00Z`.
+    DateTime,
+    /// A reference to a record of another collection, stored as its id.
+    Relation {
+        collection_id: i64,
+        #[serde(default)]
+        cascade_delete: bool,
+    },
+    /// An object with fields of its own, checked like those of a record.
+    Object {
+        fields: HashMap<String, FieldDefinition>,
+    },
+    /// An array whose items are all of one type.
+    Array {
+        items: Box<FieldType>,
+    },
+}
+
+impl FieldType {
+    /// The name of the type, as schemas and errors give it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FieldType::String => "string",
+            FieldType::Text => "text",
+            FieldType::Number => "number",
+            FieldType::Boolean => "boolean",
+            FieldType::Json => "json",
+            FieldType::File => "file",
+            FieldType::Email => "email",
+            FieldType::Url => "url",
+            FieldType::Date => "date",
+            FieldType::DateTime => "datetime",
+            FieldType::Relation { .. } => "relation",
+            FieldType::Object { .. } => "object",
+            FieldType::Array { .. } => "array",
+        }
+    }
 }
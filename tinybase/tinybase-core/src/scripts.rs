@@ -0,0 +1,50 @@
+//! Script hooks: Lua sources admins register to run on the record writes of
+//! a collection, to adjust the data written or refuse the write.
+
+use serde::{Deserialize, Serialize};
+
+/// When a hook runs.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum HookEvent {
+    /// Before a record is created, with the data to create.
+    BeforeCreate,
+    /// After a record is created; the write stands whatever the hook does.
+    AfterCreate,
+    /// Before a record is updated, with the data to store and the previous.
+    BeforeUpdate,
+}
+
+impl HookEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookEvent::BeforeCreate => "beforeCreate",
+            HookEvent::AfterCreate => "afterCreate",
+            HookEvent::BeforeUpdate => "beforeUpdate",
+        }
+    }
+}
+
+/// What a hook runs and on what, as configured by an admin.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ScriptHookSettings {
+    pub collection_id: i64,
+    pub event: HookEvent,
+    /// The Lua chunk run.
+    pub source: String,
+}
+
+impl ScriptHookSettings {
+    /// Whether the hook runs at `event` on `collection_id`.
+    pub fn runs(&self, event: HookEvent, collection_id: i64) -> bool {
+        self.collection_id == collection_id && self.event == event
+    }
+}
+
+#[derive(Debug)]
+pub struct ScriptHook {
+    pub id: i64,
+    pub settings: ScriptHookSettings,
+    pub created: String,
+    pub updated: String,
+}
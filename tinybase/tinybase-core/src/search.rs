@@ -0,0 +1,157 @@
+//! Full-text search over the fields a collection declares searchable with
+//! `"search": {"fields": ["title", "body"]}` in its schema.
+//!
+//! Backends implement [`SearchIndex`]. The built-in [`Fts5Index`] keeps an
+//! SQLite FTS5 table beside the records, ranked by BM25; deployments wanting
+//! typo tolerance or finer ranking control plug in another backend, such as
+//! an embedded Tantivy index or a Meilisearch server. Whichever is used only
+//! holds the searchable text and answers record ids: records are always read
+//! from the database.
+
+use async_trait::async_trait;
+use serde_json::{Map, Value};
+use std::sync::Arc;
+
+use crate::schema::CollectionSchema;
+use crate::Db;
+
+type BoxResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// A record matching a search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub record_id: i64,
+    /// How well the record matches, higher is better; only comparable
+    /// between the hits of one search.
+    pub score: f64,
+}
+
+#[async_trait]
+pub trait SearchIndex: Send + Sync {
+    /// Short name of the backend, e.g. `fts5`.
+    fn name(&self) -> &'static str;
+
+    /// Adds or replaces the searchable fields of a record.
+    async fn index(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        document: &Map<String, Value>,
+    ) -> BoxResult<()>;
+
+    /// Removes a record; removing one that isn't indexed is no error.
+    async fn remove(&self, collection_id: i64, record_id: i64) -> BoxResult<()>;
+
+    /// Removes every record of a collection.
+    async fn clear(&self, collection_id: i64) -> BoxResult<()>;
+
+    /// The records of a collection matching `query`, best first.
+    async fn search(
+        &self,
+        collection_id: i64,
+        query: &str,
+        limit: usize,
+    ) -> BoxResult<Vec<SearchHit>>;
+
+    /// Checks that the backend answers, for the verbose health check.
+    /// Backends living in the database or the process have nothing to check.
+    async fn check(&self) -> BoxResult<()> {
+        Ok(())
+    }
+}
+
+/// The searchable fields of `data`, `None` when the collection isn't
+/// searchable.
+pub fn document(schema: &CollectionSchema, data: &Value) -> Option<Map<String, Value>> {
+    let settings = schema.search.as_ref()?;
+    Some(
+        settings
+            .fields
+            .iter()
+            .filter_map(|field| Some((field.clone(), data.get(field)?.clone())))
+            .collect(),
+    )
+}
+
+/// The text of `document` as one string, for backends indexing a single
+/// column: its strings, and the strings inside its arrays, one per line.
+pub fn text(document: &Map<String, Value>) -> String {
+    fn push(text: &mut String, value: &Value) {
+        match value {
+            Value::String(s) => {
+                text.push_str(s);
+                text.push('\n');
+            }
+            Value::Array(items) => items.iter().for_each(|item| push(text, item)),
+            Value::Number(n) => {
+                text.push_str(&n.to_string());
+                text.push('\n');
+            }
+            _ => {}
+        }
+    }
+    let mut text = String::new();
+    document.values().for_each(|value| push(&mut text, value));
+    text
+}
+
+/// The built-in backend: an FTS5 table in the database itself, so search
+/// needs nothing else to run and is backed up with the records.
+pub struct Fts5Index {
+    db: Arc<dyn Db>,
+}
+
+impl Fts5Index {
+    pub fn new(db: Arc<dyn Db>) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl SearchIndex for Fts5Index {
+    fn name(&self) -> &'static str {
+        "fts5"
+    }
+
+    async fn index(
+        &self,
+        collection_id: i64,
+        record_id: i64,
+        document: &Map<String, Value>,
+    ) -> BoxResult<()> {
+        Ok(self
+            .db
+            .set_search_text(collection_id, record_id, Some(&text(document)))
+            .await?)
+    }
+
+    async fn remove(&self, collection_id: i64, record_id: i64) -> BoxResult<()> {
+        Ok(self
+            .db
+            .set_search_text(collection_id, record_id, None)
+            .await?)
+    }
+
+    async fn clear(&self, collection_id: i64) -> BoxResult<()> {
+        Ok(self.db.clear_search_text(collection_id).await?)
+    }
+
+    async fn search(
+        &self,
+        collection_id: i64,
+        query: &str,
+        limit: usize,
+    ) -> BoxResult<Vec<SearchHit>> {
+        self.db.search_text(collection_id, query, limit).await
+    }
+}
+
+/// `query` as an FTS5 query matching records holding every word of it, so
+/// punctuation and FTS5 operators in user input can't make it invalid.
+pub(crate) fn fts5_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,430 @@
+//! Database backups. Snapshots are consistent copies of the database taken
+//! with `VACUUM INTO` into a backup directory, on demand or on a schedule,
+//! and can be restored in place. The latest snapshot can also be verified:
+//! it is restored into a scratch copy, checked for integrity and sampled, so
+//! backups are known to be restorable before they are needed.
+
+use crate::events::{Alert, EventBus};
+use crate::{clock, format_timestamp, setup_database, Db};
+use chrono::{DateTime, Utc};
+use libsql::{params, Builder, Connection};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Records read back from each collection of a restored snapshot.
+const SAMPLE_SIZE: i64 = 5;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct VerificationReport {
+    /// The snapshot file that was verified, if one was found.
+    pub snapshot: Option<String>,
+    pub ok: bool,
+    /// Result of `PRAGMA integrity_check`.
+    pub integrity: Option<String>,
+    pub collections: usize,
+    pub records: i64,
+    pub errors: Vec<String>,
+    /// Milliseconds since the Unix epoch.
+    pub checked_at: u64,
+    pub duration_ms: u64,
+}
+
+type BoxResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SnapshotInfo {
+    /// File name of the snapshot in the backup directory.
+    pub name: String,
+    pub size: u64,
+    /// When the snapshot was written, as an RFC 3339 timestamp.
+    pub created: String,
+}
+
+impl SnapshotInfo {
+    fn read(path: &Path) -> std::io::Result<Self> {
+        let metadata = std::fs::metadata(path)?;
+        Ok(SnapshotInfo {
+            name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            size: metadata.len(),
+            created: format_timestamp(metadata.modified()?),
+        })
+    }
+}
+
+/// Snapshots the database into a new, timestamped file in `dir`, creating
+/// the directory if needed.
+pub async fn take_snapshot(db: &dyn Db, dir: &Path) -> BoxResult<SnapshotInfo> {
+    tokio::fs::create_dir_all(dir).await?;
+    let time: DateTime<Utc> = clock::now().into();
+    let path = dir.join(time.format("tinybase-%Y%m%dT%H%M%S%3fZ.db").to_string());
+    db.backup_into(&path).await?;
+    Ok(SnapshotInfo::read(&path)?)
+}
+
+/// The snapshots in `dir`, newest first.
+pub fn list_snapshots(dir: &Path) -> std::io::Result<Vec<SnapshotInfo>> {
+    let mut snapshots = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.metadata()?.is_file() {
+            snapshots.push(SnapshotInfo::read(&entry.path())?);
+        }
+    }
+    snapshots.sort_by(|a, b| (&b.created, &b.name).cmp(&(&a.created, &a.name)));
+    Ok(snapshots)
+}
+
+/// Deletes all but the `keep` newest snapshots in `dir`.
+pub fn prune_snapshots(dir: &Path, keep: usize) -> std::io::Result<()> {
+    for snapshot in list_snapshots(dir)?.into_iter().skip(keep) {
+        std::fs::remove_file(dir.join(snapshot.name))?;
+    }
+    Ok(())
+}
+
+/// Replaces the contents of the database with those of `snapshot`. The
+/// snapshot is first upgraded to the current schema in a scratch copy, so
+/// older snapshots restore too; the snapshot itself is left untouched.
+pub async fn restore_snapshot(db: &dyn Db, snapshot: &Path) -> BoxResult<()> {
+    let scratch = std::env::temp_dir().join(format!(
+        "tinybase-restore-{}-{}.db",
+        std::process::id(),
+        now_millis()
+    ));
+    let result = async {
+        tokio::fs::copy(snapshot, &scratch).await?;
+        let copy = Builder::new_local(&scratch).build().await?;
+        let conn = copy.connect()?;
+        if integrity_check(&conn).await? != "ok" {
+            return Err("The snapshot failed its integrity check".into());
+        }
+        setup_database(&conn).await?;
+        drop(conn);
+        drop(copy);
+        db.restore_from(&scratch).await
+    }
+    .await;
+    let _ = std::fs::remove_file(&scratch);
+    result
+}
+
+/// Takes a snapshot into `dir` every `interval`, keeping the `keep` newest
+/// if given.
+pub fn spawn_backups(
+    db: Arc<dyn Db>,
+    dir: impl Into<PathBuf>,
+    interval: Duration,
+    keep: Option<usize>,
+) -> tokio::task::JoinHandle<()> {
+    let dir = dir.into();
+    tokio::spawn(async move {
+        let start = tokio::time::Instant::now() + interval;
+        let mut ticker = tokio::time::interval_at(start, interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = take_snapshot(db.as_ref(), &dir).await {
+                tracing::error!(error = %e, "scheduled backup failed");
+                continue;
+            }
+            if let Some(keep) = keep {
+                if let Err(e) = prune_snapshots(&dir, keep) {
+                    tracing::warn!(error = %e, "failed to prune old backups");
+                }
+            }
+        }
+    })
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn backup_into(conn: &Connection, path: &Path) -> libsql::Result<()> {
+    conn.execute(
+        "VACUUM INTO ?1",
+        params![path.to_string_lossy().into_owned()],
+    )
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip(conn), err)]
+pub(crate) async fn restore_from(conn: &Connection, path: &Path) -> BoxResult<()> {
+    conn.execute(
+        "ATTACH DATABASE ?1 AS snapshot",
+        params![path.to_string_lossy().into_owned()],
+    )
+    .await?;
+    conn.execute("BEGIN", ()).await?;
+    let result = copy_tables(conn).await;
+    match result {
+        Ok(()) => conn.execute("COMMIT", ()).await?,
+        Err(_) => conn.execute("ROLLBACK", ()).await?,
+    };
+    conn.execute("DETACH DATABASE snapshot", ()).await?;
+    result
+}
+
+/// Copies every table the database shares with the attached snapshot, with
+/// the columns they share. Tables the snapshot lacks, e.g. those of plugins
+/// installed since, are left as they are, and so are the jobs, which belong
+/// to this server rather than to its data.
+async fn copy_tables(conn: &Connection) -> BoxResult<()> {
+    let tables = strings(
+        conn,
+        "SELECT name FROM main.sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' \
+         AND name != 'jobs' \
+         AND name IN (SELECT name FROM snapshot.sqlite_master WHERE type = 'table') ORDER BY name",
+        (),
+    )
+    .await?;
+    for table in &tables {
+        let columns = strings(
+            conn,
+            "SELECT name FROM pragma_table_info(?1, 'main') \
+             WHERE name IN (SELECT name FROM pragma_table_info(?1, 'snapshot')) ORDER BY cid",
+            params![table.as_str()],
+        )
+        .await?
+        .iter()
+        .map(|c| format!("\"{}\"", c))
+        .collect::<Vec<_>>()
+        .join(", ");
+        conn.execute(&format!("DELETE FROM main.\"{}\"", table), ())
+            .await?;
+        conn.execute(
+            &format!(
+                "INSERT INTO main.\"{0}\" ({1}) SELECT {1} FROM snapshot.\"{0}\"",
+                table, columns
+            ),
+            (),
+        )
+        .await?;
+    }
+    // AUTOINCREMENT counters only move forward, so ids handed out since the
+    // snapshot are not handed out again.
+    let sequences = strings(
+        conn,
+        "SELECT name FROM snapshot.sqlite_master WHERE name = 'sqlite_sequence'",
+        (),
+    )
+    .await?;
+    if !sequences.is_empty() {
+        conn.execute(
+            "UPDATE main.sqlite_sequence SET seq = MAX(seq, COALESCE((SELECT s.seq FROM snapshot.sqlite_sequence s \
+             WHERE s.name = main.sqlite_sequence.name), 0))",
+            (),
+        )
+        .await?;
+        conn.execute(
+            "INSERT INTO main.sqlite_sequence (name, seq) SELECT name, seq FROM snapshot.sqlite_sequence \
+             WHERE name NOT IN (SELECT name FROM main.sqlite_sequence)",
+            (),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+async fn strings(
+    conn: &Connection,
+    sql: &str,
+    params: impl libsql::params::IntoParams,
+) -> libsql::Result<Vec<String>> {
+    let mut rows = conn.query(sql, params).await?;
+    let mut values = Vec::new();
+    while let Some(row) = rows.next().await? {
+        values.push(row.get(0)?);
+    }
+    Ok(values)
+}
+
+/// Returns the most recently modified file in `dir`.
+pub fn latest_snapshot(dir: &Path) -> std::io::Result<Option<PathBuf>> {
+    let mut latest: Option<(SystemTime, PathBuf)> = None;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let modified = metadata.modified()?;
+        if latest.as_ref().is_none_or(|(t, _)| modified > *t) {
+            latest = Some((modified, entry.path()));
+        }
+    }
+    Ok(latest.map(|(_, path)| path))
+}
+
+/// Restores `snapshot` into a scratch file and checks it.
+pub async fn verify_snapshot(snapshot: &Path) -> VerificationReport {
+    let started = Instant::now();
+    let mut report = VerificationReport {
+        snapshot: Some(snapshot.display().to_string()),
+        ok: false,
+        integrity: None,
+        collections: 0,
+        records: 0,
+        errors: Vec::new(),
+        checked_at: now_millis(),
+        duration_ms: 0,
+    };
+    let scratch = std::env::temp_dir().join(format!(
+        "tinybase-verify-{}-{}.db",
+        std::process::id(),
+        report.checked_at
+    ));
+    if let Err(e) = restore_and_check(snapshot, &scratch, &mut report).await {
+        report.errors.push(e.to_string());
+    }
+    let _ = std::fs::remove_file(&scratch);
+    report.ok = report.errors.is_empty();
+    report.duration_ms = started.elapsed().as_millis() as u64;
+    report
+}
+
+async fn restore_and_check(
+    snapshot: &Path,
+    scratch: &Path,
+    report: &mut VerificationReport,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    tokio::fs::copy(snapshot, scratch).await?;
+    let db = Builder::new_local(scratch).build().await?;
+    let conn = db.connect()?;
+
+    let integrity = integrity_check(&conn).await?;
+    if integrity != "ok" {
+        report
+            .errors
+            .push(format!("Integrity check failed: {}", integrity));
+    }
+    report.integrity = Some(integrity);
+
+    // Bring older snapshots up to the current schema, as startup would.
+    setup_database(&conn).await?;
+    let collections = db.list_collections().await?;
+    report.collections = collections.len();
+
+    let mut rows = conn.query("SELECT COUNT(*) FROM records", ()).await?;
+    if let Some(row) = rows.next().await? {
+        report.records = row.get(0)?;
+    }
+
+    for collection in &collections {
+        let mut rows = conn
+            .query(
+                "SELECT id, data FROM records WHERE collection_id = ?1 ORDER BY id DESC LIMIT ?2",
+                params![collection.id, SAMPLE_SIZE],
+            )
+            .await?;
+        while let Some(row) = rows.next().await? {
+            let id: i64 = row.get(0)?;
+            let data: String = row.get(1)?;
+            if serde_json::from_str::<serde_json::Value>(&data).is_err() {
+                report.errors.push(format!(
+                    "Record {} in collection {} has unreadable data",
+                    id, collection.name
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn integrity_check(conn: &Connection) -> libsql::Result<String> {
+    let mut rows = conn.query("PRAGMA integrity_check", ()).await?;
+    let mut messages = Vec::new();
+    while let Some(row) = rows.next().await? {
+        messages.push(row.get::<String>(0)?);
+    }
+    Ok(messages.join("; "))
+}
+
+fn now_millis() -> u64 {
+    clock::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Periodically verifies the latest snapshot in a backup directory and keeps
+/// the most recent report.
+#[derive(Clone)]
+pub struct SnapshotVerifier {
+    backup_dir: PathBuf,
+    last_report: Arc<Mutex<Option<VerificationReport>>>,
+    alerts: Option<EventBus>,
+}
+
+impl SnapshotVerifier {
+    pub fn new(backup_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            backup_dir: backup_dir.into(),
+            last_report: Arc::new(Mutex::new(None)),
+            alerts: None,
+        }
+    }
+
+    /// Raises a `backup.failed` alert on `bus` whenever verification fails.
+    pub fn with_alerts(mut self, bus: EventBus) -> Self {
+        self.alerts = Some(bus);
+        self
+    }
+
+    pub fn last_report(&self) -> Option<VerificationReport> {
+        self.last_report.lock().unwrap().clone()
+    }
+
+    /// Verifies the latest snapshot now and stores the report.
+    pub async fn run_once(&self) -> VerificationReport {
+        let report = match latest_snapshot(&self.backup_dir) {
+            Ok(Some(snapshot)) => verify_snapshot(&snapshot).await,
+            Ok(None) => failed(format!(
+                "No snapshot found in {}",
+                self.backup_dir.display()
+            )),
+            Err(e) => failed(format!(
+                "Cannot read backup directory {}: {}",
+                self.backup_dir.display(),
+                e
+            )),
+        };
+        *self.last_report.lock().unwrap() = Some(report.clone());
+        if let (false, Some(bus)) = (report.ok, &self.alerts) {
+            bus.raise(Alert::new(
+                "backup.failed",
+                format!("Snapshot verification failed: {}", report.errors.join("; ")),
+            ));
+        }
+        report
+    }
+
+    /// Runs [`run_once`](Self::run_once) every `interval`, starting now.
+    pub fn spawn(self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let report = self.run_once().await;
+                if !report.ok {
+                    tracing::error!(errors = %report.errors.join("; "), "snapshot verification failed");
+                }
+            }
+        })
+    }
+}
+
+fn failed(error: String) -> VerificationReport {
+    VerificationReport {
+        snapshot: None,
+        ok: false,
+        integrity: None,
+        collections: 0,
+        records: 0,
+        errors: vec![error],
+        checked_at: now_millis(),
+        duration_ms: 0,
+    }
+}
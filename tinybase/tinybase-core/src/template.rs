@@ -0,0 +1,130 @@
+//! Payload templates shaping an event into the message a receiver expects,
+//! e.g. a Slack or Discord webhook body.
+//!
+//! A template is a JSON document whose strings may contain handlebars-style
+//! `{{ path }}` placeholders. A path is dot-separated, with numbers indexing
+//! arrays: `record.data.title`, `record.data.tags.0`. A string consisting of
+//! a single placeholder is replaced by the value itself, keeping its JSON
+//! type; placeholders inside longer strings are interpolated as text.
+//! Missing values render as `null`, or as nothing inside text.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum TemplateError {
+    #[error("Unclosed placeholder in '{0}'")]
+    Unclosed(String),
+    #[error("Empty placeholder in '{0}'")]
+    Empty(String),
+}
+
+/// A parsed payload template.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(try_from = "Value", into = "Value")]
+pub struct PayloadTemplate(Value);
+
+impl TryFrom<Value> for PayloadTemplate {
+    type Error = TemplateError;
+
+    fn try_from(template: Value) -> Result<Self, TemplateError> {
+        check(&template)?;
+        Ok(Self(template))
+    }
+}
+
+impl From<PayloadTemplate> for Value {
+    fn from(template: PayloadTemplate) -> Self {
+        template.0
+    }
+}
+
+/// A piece of a template string.
+enum Segment<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+fn segments(text: &str) -> Result<Vec<Segment<'_>>, TemplateError> {
+    let mut segments = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        if start > 0 {
+            segments.push(Segment::Text(&rest[..start]));
+        }
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| TemplateError::Unclosed(text.to_string()))?;
+        let path = rest[start + 2..start + end].trim();
+        if path.is_empty() {
+            return Err(TemplateError::Empty(text.to_string()));
+        }
+        segments.push(Segment::Placeholder(path));
+        rest = &rest[start + end + 2..];
+    }
+    if !rest.is_empty() {
+        segments.push(Segment::Text(rest));
+    }
+    Ok(segments)
+}
+
+fn check(template: &Value) -> Result<(), TemplateError> {
+    match template {
+        Value::String(text) => segments(text).map(|_| ()),
+        Value::Array(items) => items.iter().try_for_each(check),
+        Value::Object(map) => map.values().try_for_each(check),
+        _ => Ok(()),
+    }
+}
+
+/// Looks up a dot-separated `path` in `context`.
+fn lookup<'a>(context: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(context, |value, key| match value {
+        Value::Object(map) => map.get(key),
+        Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+fn render_value(template: &Value, context: &Value) -> Value {
+    match template {
+        Value::String(text) => {
+            // Checked when the template was parsed.
+            let segments = segments(text).unwrap_or_default();
+            if let [Segment::Placeholder(path)] = segments.as_slice() {
+                return lookup(context, path).cloned().unwrap_or(Value::Null);
+            }
+            let mut rendered = String::new();
+            for segment in segments {
+                match segment {
+                    Segment::Text(text) => rendered.push_str(text),
+                    Segment::Placeholder(path) => match lookup(context, path) {
+                        Some(Value::String(s)) => rendered.push_str(s),
+                        Some(Value::Null) | None => {}
+                        Some(value) => rendered.push_str(&value.to_string()),
+                    },
+                }
+            }
+            Value::String(rendered)
+        }
+        Value::Array(items) => items.iter().map(|t| render_value(t, context)).collect(),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, t)| (key.clone(), render_value(t, context)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+impl PayloadTemplate {
+    pub fn new(template: Value) -> Result<Self, TemplateError> {
+        Self::try_from(template)
+    }
+
+    /// The payload for `context`, typically `{ "event": ..., "record": ... }`.
+    pub fn render(&self, context: &Value) -> Value {
+        render_value(&self.0, context)
+    }
+}
@@ -0,0 +1,96 @@
+//! Time limits for queries that can run long: filtered and sorted record
+//! lists and counts, and field lookups.
+//!
+//! [`with_query_timeout`] sets the limit for the queries a future runs, the
+//! way [`clock::with_clock`](crate::clock::with_clock) sets the clock. A
+//! query still running when its time is up is interrupted and fails with
+//! [`QueryTimeout`]. Outside any limit queries run as long as they take.
+//!
+//! A request dropped before it finishes, e.g. because its client went away,
+//! drops its pending queries with it; the limit is what stops a statement
+//! that is already running, since SQLite runs it on the calling thread.
+
+use libsql::Connection;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+type BoxResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+tokio::task_local! {
+    static QUERY_TIMEOUT: Duration;
+}
+
+/// Runs `future` with its long queries limited to `limit`.
+pub async fn with_query_timeout<F: Future>(limit: Duration, future: F) -> F::Output {
+    QUERY_TIMEOUT.scope(limit, future).await
+}
+
+/// A query that was interrupted because it ran out of time.
+#[derive(Debug, thiserror::Error)]
+#[error("The query did not finish within {} ms", .limit.as_millis())]
+pub struct QueryTimeout {
+    pub limit: Duration,
+}
+
+/// Interrupts the statements of a connection once a time limit is up,
+/// unless dropped first.
+struct Watchdog {
+    /// Taken when the watchdog is dropped, so a late timer cannot interrupt
+    /// the next query on the connection.
+    conn: Arc<Mutex<Option<Connection>>>,
+    timed_out: Arc<AtomicBool>,
+    _stop: mpsc::Sender<()>,
+}
+
+impl Watchdog {
+    fn start(conn: &Connection, limit: Duration) -> Self {
+        let conn = Arc::new(Mutex::new(Some(conn.clone())));
+        let timed_out = Arc::new(AtomicBool::new(false));
+        let (stop, stopped) = mpsc::channel::<()>();
+        // A thread rather than a task: a running statement blocks the thread
+        // it runs on, which may be the only one of the runtime.
+        std::thread::spawn({
+            let conn = conn.clone();
+            let timed_out = timed_out.clone();
+            move || {
+                if stopped.recv_timeout(limit) == Err(mpsc::RecvTimeoutError::Timeout) {
+                    if let Some(conn) = &*conn.lock().unwrap() {
+                        timed_out.store(true, Ordering::SeqCst);
+                        let _ = conn.interrupt();
+                    }
+                }
+            }
+        });
+        Watchdog {
+            conn,
+            timed_out,
+            _stop: stop,
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.conn.lock().unwrap().take();
+    }
+}
+
+/// Runs `query` on `conn` within the limit in scope, if any.
+pub(crate) async fn limited<T>(
+    conn: &Connection,
+    query: impl Future<Output = BoxResult<T>>,
+) -> BoxResult<T> {
+    let Ok(limit) = QUERY_TIMEOUT.try_with(|limit| *limit) else {
+        return query.await;
+    };
+    let watchdog = Watchdog::start(conn, limit);
+    let result = query.await;
+    let timed_out = watchdog.timed_out.load(Ordering::SeqCst);
+    drop(watchdog);
+    match result {
+        Err(_) if timed_out => Err(Box::new(QueryTimeout { limit })),
+        result => result,
+    }
+}
@@ -0,0 +1,55 @@
+//! API tokens: long-lived credentials admins mint for services, limited to
+//! reading or writing the records of some collections. Only a hash of each
+//! token is stored.
+
+use serde::{Deserialize, Serialize};
+
+/// What a scope allows on the records of its collection.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenAccess {
+    Read,
+    /// Reading as well as creating, updating and deleting.
+    Write,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TokenScope {
+    /// The name of the collection, or `*` for every collection.
+    pub collection: String,
+    pub access: TokenAccess,
+}
+
+impl TokenScope {
+    /// Whether the scope lets a token read, or with `write` change, the
+    /// records of `collection`.
+    pub fn allows(&self, collection: &str, write: bool) -> bool {
+        (self.collection == "*" || self.collection == collection)
+            && (!write || self.access == TokenAccess::Write)
+    }
+
+    /// The scope as introspection reports it, e.g. `posts:read`.
+    pub fn name(&self) -> String {
+        let access = match self.access {
+            TokenAccess::Read => "read",
+            TokenAccess::Write => "write",
+        };
+        format!("{}:{}", self.collection, access)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiToken {
+    pub id: i64,
+    /// What the token is for, e.g. the service holding it.
+    pub name: String,
+    pub scopes: Vec<TokenScope>,
+    pub created: String,
+    pub updated: String,
+}
+
+impl ApiToken {
+    pub fn allows(&self, collection: &str, write: bool) -> bool {
+        self.scopes.iter().any(|s| s.allows(collection, write))
+    }
+}
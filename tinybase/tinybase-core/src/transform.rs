@@ -0,0 +1,59 @@
+//! Built-in transforms applied to record data before it is validated and
+//! stored, configured per collection in [`CollectionSchema::transforms`].
+
+use crate::schema::{CollectionSchema, FieldType};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use unicode_normalization::UnicodeNormalization;
+
+/// One step of a collection's pipeline. Steps run in the order they are
+/// declared and only touch top-level fields; the contents of `json` fields
+/// are left alone.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Transform {
+    /// Removes leading and trailing whitespace from strings.
+    Trim,
+    /// Lowercases the values of `email` fields.
+    LowercaseEmails,
+    /// Drops fields set to `null`, so they count as missing.
+    StripNulls,
+    /// Converts strings to Unicode Normalization Form C.
+    NormalizeUnicode,
+}
+
+/// Runs the collection's transforms over `data`.
+pub fn apply_transforms(schema: &CollectionSchema, data: &mut Value) {
+    let Some(map) = data.as_object_mut() else {
+        return;
+    };
+    for transform in &schema.transforms {
+        match transform {
+            Transform::StripNulls => map.retain(|_, value| !value.is_null()),
+            Transform::LowercaseEmails => {
+                for (name, field) in &schema.fields {
+                    if field.r#type != FieldType::Email {
+                        continue;
+                    }
+                    if let Some(Value::String(s)) = map.get_mut(name) {
+                        *s = s.to_lowercase();
+                    }
+                }
+            }
+            Transform::Trim | Transform::NormalizeUnicode => {
+                for (name, value) in map.iter_mut() {
+                    let is_json = schema
+                        .fields
+                        .get(name)
+                        .is_some_and(|f| f.r#type == FieldType::Json);
+                    if let (Value::String(s), false) = (value, is_json) {
+                        *s = match transform {
+                            Transform::Trim => s.trim().to_string(),
+                            _ => s.nfc().collect(),
+                        };
+                    }
+                }
+            }
+        }
+    }
+}
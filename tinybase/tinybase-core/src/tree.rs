@@ -0,0 +1,154 @@
+//! Trees of records. A collection whose schema sets `tree.parent` to one of
+//! its fields keeps its records in a hierarchy: the parent of a record is
+//! the record of the same collection whose id that field holds.
+//!
+//! Every record write keeps the `record_tree` table up to date with the
+//! materialized path of each record, the ids from its root down to it as
+//! `/1/4/9/`, and its depth, so a subtree is read with one range query
+//! instead of walking the tree; moving a record rewrites the paths of its
+//! subtree. Siblings also keep a position, their order.
+//!
+//! A record whose parent field is empty, or names a record that doesn't
+//! exist, is a root; a record whose parent is deleted becomes a root, unless
+//! the field is a relation with `cascade_delete`. Writes that would make a
+//! record its own ancestor are refused.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::Record;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TreeSettings {
+    /// The field holding the id of the parent record.
+    pub parent: String,
+}
+
+/// A record with its place in the tree of its collection.
+#[derive(Debug)]
+pub struct TreeNode {
+    pub record: Record,
+    /// The parent named by the record, `None` for roots.
+    pub parent: Option<i64>,
+    /// The materialized path, e.g. `/1/4/9/`.
+    pub path: String,
+    /// `0` for roots.
+    pub depth: u32,
+    /// The order of the record among its siblings.
+    pub position: i64,
+}
+
+impl TreeNode {
+    /// The ids of the ancestors of the record, its root first.
+    pub fn ancestors(&self) -> Vec<i64> {
+        let ids = path_ids(&self.path);
+        ids[..ids.len().saturating_sub(1)].to_vec()
+    }
+}
+
+/// The parent `data` names through the `field` of a tree.
+pub fn parent_of(data: &Value, field: &str) -> Option<i64> {
+    data.get(field).and_then(Value::as_i64)
+}
+
+fn path_ids(path: &str) -> Vec<i64> {
+    path.split('/').filter_map(|id| id.parse().ok()).collect()
+}
+
+/// The path of `id` under a parent with `parent_path`, or as a root.
+pub(crate) fn child_path(parent_path: Option<&str>, id: i64) -> String {
+    format!("{}{}/", parent_path.unwrap_or("/"), id)
+}
+
+/// The smallest string greater than every path starting with `path`, so
+/// `path <= p < upper` selects a subtree: the trailing `/` becomes the next
+/// character, `0`.
+pub(crate) fn path_upper_bound(path: &str) -> String {
+    format!("{}0", path.trim_end_matches('/'))
+}
+
+/// Whether the record at `path` lies in the subtree of `id`.
+pub(crate) fn is_under(path: &str, id: i64) -> bool {
+    path_ids(path).contains(&id)
+}
+
+/// Orders the nodes of a subtree depth-first, siblings by position, starting
+/// from its root `root`.
+pub(crate) fn depth_first(nodes: Vec<TreeNode>, root: i64) -> Vec<TreeNode> {
+    let mut children: HashMap<Option<i64>, Vec<TreeNode>> = HashMap::new();
+    let mut top = None;
+    for node in nodes {
+        if node.record.id == root {
+            top = Some(node);
+        } else {
+            children.entry(node.parent).or_default().push(node);
+        }
+    }
+    let mut ordered = Vec::new();
+    let mut stack: Vec<TreeNode> = top.into_iter().collect();
+    while let Some(node) = stack.pop() {
+        if let Some(mut siblings) = children.remove(&Some(node.record.id)) {
+            siblings.sort_by_key(|n| (n.position, n.record.id));
+            // Reversed, so the first sibling is popped first.
+            stack.extend(siblings.into_iter().rev());
+        }
+        ordered.push(node);
+    }
+    ordered
+}
+
+/// Places the records of a collection, given as `(id, parent)` pairs, in
+/// their tree: the `(id, parent, path, depth, position)` of each. Records
+/// caught in a cycle, which writes refuse but data from before the tree was
+/// declared may hold, are made roots one at a time, lowest id first.
+pub(crate) fn layout(records: &[(i64, Option<i64>)]) -> Vec<(i64, Option<i64>, String, u32, i64)> {
+    let ids: std::collections::HashSet<i64> = records.iter().map(|(id, _)| *id).collect();
+    let mut children: HashMap<i64, Vec<i64>> = HashMap::new();
+    let mut roots = Vec::new();
+    for (id, parent) in records {
+        match parent {
+            Some(parent) if *parent != *id && ids.contains(parent) => {
+                children.entry(*parent).or_default().push(*id)
+            }
+            _ => roots.push(*id),
+        }
+    }
+    let parents: HashMap<i64, Option<i64>> = records.iter().copied().collect();
+    let mut placed = Vec::with_capacity(records.len());
+    let mut seen = std::collections::HashSet::new();
+    let mut root_position = 0;
+    let mut pending: Vec<i64> = records.iter().map(|(id, _)| *id).collect();
+    pending.sort_unstable();
+    loop {
+        roots.sort_unstable();
+        for root in roots.drain(..) {
+            let mut queue = vec![(root, child_path(None, root), 0)];
+            seen.insert(root);
+            placed.push((
+                root,
+                parents[&root],
+                child_path(None, root),
+                0,
+                root_position,
+            ));
+            root_position += 1;
+            while let Some((id, path, depth)) = queue.pop() {
+                let mut kids = children.remove(&id).unwrap_or_default();
+                kids.sort_unstable();
+                for (position, kid) in kids.into_iter().enumerate() {
+                    if !seen.insert(kid) {
+                        continue;
+                    }
+                    let kid_path = child_path(Some(&path), kid);
+                    placed.push((kid, Some(id), kid_path.clone(), depth + 1, position as i64));
+                    queue.push((kid, kid_path, depth + 1));
+                }
+            }
+        }
+        match pending.iter().find(|id| !seen.contains(id)) {
+            Some(id) => roots.push(*id),
+            None => return placed,
+        }
+    }
+}
@@ -1,16 +1,319 @@
-use serde::Serialize;
-use crate::schema::{CollectionSchema, FieldType};
-use serde_json::Value;
-use thiserror::Error;
+use crate::compute::check_computed;
+use crate::schema::{CollectionSchema, FieldDefinition, FieldType};
+use chrono::{DateTime, NaiveDate};
+use regex::Regex;
+use serde::{Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use url::Url;
 
-#[derive(Error, Debug, PartialEq, Serialize)]
-pub enum ValidationError {
-    #[error("Missing required field: {0}")]
-    MissingRequiredField(String),
-    #[error("Invalid type for field '{0}': expected {1}, got {2}")]
-    InvalidType(String, String, String),
+/// Names of fields kept by the system, which schemas can't declare and the
+/// data of records can't hold: the [system fields](crate::json_schema::SYSTEM_FIELDS)
+/// and the other columns records are stored with.
+pub const RESERVED_FIELDS: &[&str] = &["id", "created", "updated", "collection_id", "version"];
+
+/// Names collections can't take, as they would be confused with routes
+/// such as `/api/v1/collections/import`.
+pub const RESERVED_COLLECTION_NAMES: &[&str] =
+    &["admin", "api", "collections", "import", "records"];
+
+/// A value of record data that fails its schema, or a collection name that
+/// breaks the naming rules.
+///
+/// Serializes as `{"path", "code", "message", "value"}` plus the parameters
+/// of its [`kind`](ValidationErrorKind), e.g. `{"path": "/address/zip",
+/// "code": "invalid_type", "expected": "string", "got": "number", ...}`,
+/// so clients can map errors to form fields.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    /// A JSON Pointer to the value in the record data, e.g. `/address/zip`
+    /// or `/lines/2/qty`.
+    pub path: String,
+    pub kind: ValidationErrorKind,
+    /// The offending value; `None` for a missing one.
+    pub value: Option<Value>,
+}
+
+/// What is wrong with the value of a [`ValidationError`]. Its `code` is
+/// the variant name in snake case.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum ValidationErrorKind {
+    MissingRequiredField,
+    InvalidType {
+        expected: String,
+        got: String,
+    },
+    InvalidFormat {
+        expected: String,
+    },
+    BelowMinimum {
+        min: f64,
+    },
+    AboveMaximum {
+        max: f64,
+    },
+    TooShort {
+        min_length: usize,
+    },
+    TooLong {
+        max_length: usize,
+    },
+    PatternMismatch {
+        pattern: String,
+    },
+    TooFewItems {
+        min_items: usize,
+    },
+    TooManyItems {
+        max_items: usize,
+    },
+    /// A relation to a record that doesn't exist; the value is its id.
+    MissingRelation,
+    /// A tree parent that is the record itself or one of its descendants.
+    AncestorCycle,
+    /// A top-level field named like a system field, see [`RESERVED_FIELDS`].
+    ReservedField,
+    /// A collection name with characters other than lowercase letters,
+    /// digits and underscores, or starting with a digit, with a [slug] of it
+    /// that is valid, if it has letters or digits.
+    InvalidName {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        suggestion: Option<String>,
+    },
+    /// A collection name in [`RESERVED_COLLECTION_NAMES`].
+    ReservedName,
+}
+
+impl ValidationError {
+    pub fn new(path: impl Into<String>, kind: ValidationErrorKind, value: Option<Value>) -> Self {
+        ValidationError {
+            path: path.into(),
+            kind,
+            value,
+        }
+    }
+
+    /// An error about the top-level field `name` of a record.
+    pub fn field(name: &str, kind: ValidationErrorKind, value: Option<Value>) -> Self {
+        Self::new(pointer("", name), kind, value)
+    }
+
+    /// The machine-readable code of the error, e.g. `invalid_type`.
+    pub fn code(&self) -> &'static str {
+        match self.kind {
+            ValidationErrorKind::MissingRequiredField => "missing_required_field",
+            ValidationErrorKind::InvalidType { .. } => "invalid_type",
+            ValidationErrorKind::InvalidFormat { .. } => "invalid_format",
+            ValidationErrorKind::BelowMinimum { .. } => "below_minimum",
+            ValidationErrorKind::AboveMaximum { .. } => "above_maximum",
+            ValidationErrorKind::TooShort { .. } => "too_short",
+            ValidationErrorKind::TooLong { .. } => "too_long",
+            ValidationErrorKind::PatternMismatch { .. } => "pattern_mismatch",
+            ValidationErrorKind::TooFewItems { .. } => "too_few_items",
+            ValidationErrorKind::TooManyItems { .. } => "too_many_items",
+            ValidationErrorKind::MissingRelation => "missing_relation",
+            ValidationErrorKind::AncestorCycle => "ancestor_cycle",
+            ValidationErrorKind::ReservedField => "reserved_field",
+            ValidationErrorKind::InvalidName { .. } => "invalid_name",
+            ValidationErrorKind::ReservedName => "reserved_name",
+        }
+    }
+
+    /// The path as people write it, e.g. `address.zip` or `lines[2].qty`;
+    /// `tags[]` for `/tags/-`, an item to be added to an array.
+    pub fn field_name(&self) -> String {
+        let mut name = String::new();
+        for segment in self.path.split('/').skip(1) {
+            let segment = segment.replace("~1", "/").replace("~0", "~");
+            if segment == "-" {
+                name.push_str("[]");
+            } else if !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit()) {
+                name.push_str(&format!("[{}]", segment));
+            } else {
+                if !name.is_empty() {
+                    name.push('.');
+                }
+                name.push_str(&segment);
+            }
+        }
+        name
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let field = self.field_name();
+        let value = self
+            .value
+            .as_ref()
+            .map(Value::to_string)
+            .unwrap_or_default();
+        match &self.kind {
+            ValidationErrorKind::MissingRequiredField => {
+                write!(f, "Missing required field: {}", field)
+            }
+            ValidationErrorKind::InvalidType { expected, got } => write!(
+                f,
+                "Invalid type for field '{}': expected {}, got {}",
+                field, expected, got
+            ),
+            ValidationErrorKind::InvalidFormat { expected } => write!(
+                f,
+                "Invalid format for field '{}': expected {}",
+                field, expected
+            ),
+            ValidationErrorKind::BelowMinimum { min } => {
+                write!(f, "Field '{}' must be at least {}", field, min)
+            }
+            ValidationErrorKind::AboveMaximum { max } => {
+                write!(f, "Field '{}' must be at most {}", field, max)
+            }
+            ValidationErrorKind::TooShort { min_length } => write!(
+                f,
+                "Field '{}' must be at least {} characters long",
+                field, min_length
+            ),
+            ValidationErrorKind::TooLong { max_length } => write!(
+                f,
+                "Field '{}' must be at most {} characters long",
+                field, max_length
+            ),
+            ValidationErrorKind::PatternMismatch { pattern } => write!(
+                f,
+                "Field '{}' does not match the pattern {}",
+                field, pattern
+            ),
+            ValidationErrorKind::TooFewItems { min_items } => write!(
+                f,
+                "Field '{}' must have at least {} items",
+                field, min_items
+            ),
+            ValidationErrorKind::TooManyItems { max_items } => {
+                write!(f, "Field '{}' must have at most {} items", field, max_items)
+            }
+            ValidationErrorKind::MissingRelation => write!(
+                f,
+                "Related record {} not found for field '{}'",
+                value, field
+            ),
+            ValidationErrorKind::AncestorCycle => write!(
+                f,
+                "Parent {} in field '{}' is the record itself or one of its descendants",
+                value, field
+            ),
+            ValidationErrorKind::ReservedField => write!(
+                f,
+                "Field '{}' is reserved for the system and can't be set",
+                field
+            ),
+            ValidationErrorKind::InvalidName { suggestion } => {
+                write!(
+                    f,
+                    "Name {} may only hold lowercase letters, digits and underscores, \
+                     and not start with a digit",
+                    value
+                )?;
+                match suggestion {
+                    Some(suggestion) => write!(f, "; try '{}'", suggestion),
+                    None => Ok(()),
+                }
+            }
+            ValidationErrorKind::ReservedName => write!(
+                f,
+                "Name {} is reserved; reserved names are {}",
+                value,
+                RESERVED_COLLECTION_NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl Serialize for ValidationError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Detail<'a> {
+            path: &'a str,
+            #[serde(flatten)]
+            kind: &'a ValidationErrorKind,
+            message: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            value: &'a Option<Value>,
+        }
+        Detail {
+            path: &self.path,
+            kind: &self.kind,
+            message: self.to_string(),
+            value: &self.value,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// The JSON Pointer to the member `name` of the value at `parent`, e.g.
+/// `/address/zip` for `zip` of `/address`; `""` is the record data itself.
+pub fn pointer(parent: &str, name: &str) -> String {
+    format!("{}/{}", parent, name.replace('~', "~0").replace('/', "~1"))
+}
+
+/// Checks that `name` can be the name of a collection: not empty, made of
+/// lowercase letters, digits and underscores, not starting with a digit so
+/// it can't be taken for an id, and not [reserved](RESERVED_COLLECTION_NAMES).
+/// Errors point at `/name`, the name in collection requests. A name
+/// breaking the charset rule is suggested its [`slug`], suffixed with `_1`
+/// if that is reserved.
+pub fn check_collection_name(name: &str) -> Result<(), ValidationError> {
+    let fail = |kind| Err(ValidationError::field("name", kind, Some(name.into())));
+    if name.is_empty() {
+        return fail(ValidationErrorKind::TooShort { min_length: 1 });
+    }
+    let valid = name.starts_with(|c: char| !c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if !valid {
+        let mut suggestion = slug(name);
+        if RESERVED_COLLECTION_NAMES.contains(&suggestion.as_str()) {
+            suggestion.push_str("_1");
+        }
+        return fail(ValidationErrorKind::InvalidName {
+            suggestion: (!suggestion.is_empty()).then_some(suggestion),
+        });
+    }
+    if RESERVED_COLLECTION_NAMES.contains(&name) {
+        return fail(ValidationErrorKind::ReservedName);
+    }
+    Ok(())
 }
 
+/// `name` as a collection name, e.g. `blog_posts` for `Blog Posts!`:
+/// lowercased, with each run of other characters replaced by an underscore
+/// and one put before a leading digit. Empty if `name` has no ASCII letters
+/// or digits.
+pub fn slug(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('_') {
+            slug.push('_');
+        }
+    }
+    let slug = slug.trim_end_matches('_');
+    if slug.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", slug)
+    } else {
+        slug.to_string()
+    }
+}
+
+/// Checks `data` against the fields of `schema`. The fields of objects and
+/// the items of arrays are checked too, errors pointing at them by their
+/// path, e.g. `/address/zip` or `/lines/2/qty`. Data holding a
+/// [reserved](RESERVED_FIELDS) field fails whatever the schema says.
 pub fn validate_record(
     schema: &CollectionSchema,
     data: &Value,
@@ -19,42 +322,262 @@ pub fn validate_record(
     let data_map = match data.as_object() {
         Some(map) => map,
         None => {
-            errors.push(ValidationError::InvalidType(
-                "data".to_string(),
-                "object".to_string(),
-                "not an object".to_string(),
+            errors.push(ValidationError::new(
+                "",
+                ValidationErrorKind::InvalidType {
+                    expected: "object".to_string(),
+                    got: get_value_type(data),
+                },
+                Some(data.clone()),
             ));
             return Err(errors);
         }
     };
 
-    for (field_name, field_def) in &schema.fields {
-        match data_map.get(field_name) {
+    for name in RESERVED_FIELDS {
+        if let Some(value) = data_map.get(*name) {
+            errors.push(ValidationError::field(
+                name,
+                ValidationErrorKind::ReservedField,
+                Some(value.clone()),
+            ));
+        }
+    }
+    check_fields("", &schema.fields, data_map, &mut errors);
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Checks `value`, at the JSON Pointer `path`, against `field_type` as
+/// [`validate_record`] checks the fields of records.
+pub fn validate_value(
+    path: &str,
+    field_type: &FieldType,
+    value: &Value,
+) -> Result<(), Vec<ValidationError>> {
+    let mut errors = Vec::new();
+    check_value(path, field_type, value, &mut errors);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Checks the values of `data`, the object at `parent`, against `fields`.
+fn check_fields(
+    parent: &str,
+    fields: &HashMap<String, FieldDefinition>,
+    data: &Map<String, Value>,
+    errors: &mut Vec<ValidationError>,
+) {
+    for (field_name, field_def) in fields {
+        let path = pointer(parent, field_name);
+        match data.get(field_name) {
             Some(value) => {
-                if !is_correct_type(value, &field_def.r#type) {
-                    errors.push(ValidationError::InvalidType(
-                        field_name.clone(),
-                        format!("{:?}", field_def.r#type),
-                        get_value_type(value),
-                    ));
+                if check_value(&path, &field_def.r#type, value, errors) {
+                    check_constraints(&path, field_def, value, errors);
                 }
             }
             None => {
                 if field_def.required {
-                    errors.push(ValidationError::MissingRequiredField(field_name.clone()));
+                    errors.push(ValidationError::new(
+                        path,
+                        ValidationErrorKind::MissingRequiredField,
+                        None,
+                    ));
                 }
             }
         }
     }
+}
 
-    if errors.is_empty() {
-        Ok(())
-    } else {
-        Err(errors)
+/// Checks that the value at `path` has type `field_type` and its format,
+/// then the fields of an object or the items of an array. Returns whether
+/// the value itself had the right type and format.
+fn check_value(
+    path: &str,
+    field_type: &FieldType,
+    value: &Value,
+    errors: &mut Vec<ValidationError>,
+) -> bool {
+    if !is_correct_type(value, field_type) {
+        errors.push(ValidationError::new(
+            path,
+            ValidationErrorKind::InvalidType {
+                expected: field_type.name().to_string(),
+                got: get_value_type(value),
+            },
+            Some(value.clone()),
+        ));
+        return false;
+    }
+    if let Some(expected) = check_format(value, field_type) {
+        errors.push(ValidationError::new(
+            path,
+            ValidationErrorKind::InvalidFormat {
+                expected: expected.to_string(),
+            },
+            Some(value.clone()),
+        ));
+        return false;
+    }
+    match (field_type, value) {
+        (FieldType::Object { fields }, Value::Object(map)) => {
+            check_fields(path, fields, map, errors);
+        }
+        (FieldType::Array { items }, Value::Array(values)) => {
+            for (i, item) in values.iter().enumerate() {
+                check_value(&pointer(path, &i.to_string()), items, item, errors);
+            }
+        }
+        _ => {}
+    }
+    true
+}
+
+/// Checks the min/max, length, pattern and item-count constraints of a field
+/// against a value, at `path`, already known to have the right type.
+fn check_constraints(
+    path: &str,
+    field: &FieldDefinition,
+    value: &Value,
+    errors: &mut Vec<ValidationError>,
+) {
+    let mut fail = |kind| errors.push(ValidationError::new(path, kind, Some(value.clone())));
+    if let Some(number) = value.as_f64() {
+        if let Some(min) = field.min.filter(|min| number < *min) {
+            fail(ValidationErrorKind::BelowMinimum { min });
+        }
+        if let Some(max) = field.max.filter(|max| number > *max) {
+            fail(ValidationErrorKind::AboveMaximum { max });
+        }
+    }
+    if let Some(string) = value.as_str() {
+        let length = string.chars().count();
+        if let Some(min_length) = field.min_length.filter(|min| length < *min) {
+            fail(ValidationErrorKind::TooShort { min_length });
+        }
+        if let Some(max_length) = field.max_length.filter(|max| length > *max) {
+            fail(ValidationErrorKind::TooLong { max_length });
+        }
+        if let Some(pattern) = &field.pattern {
+            // Patterns are checked when the schema is saved, see `check_schema`.
+            if Regex::new(pattern).is_ok_and(|re| !re.is_match(string)) {
+                fail(ValidationErrorKind::PatternMismatch {
+                    pattern: pattern.clone(),
+                });
+            }
+        }
+    }
+    if let Some(items) = value.as_array() {
+        if let Some(min_items) = field.min_items.filter(|min| items.len() < *min) {
+            fail(ValidationErrorKind::TooFewItems { min_items });
+        }
+        if let Some(max_items) = field.max_items.filter(|max| items.len() > *max) {
+            fail(ValidationErrorKind::TooManyItems { max_items });
+        }
     }
 }
 
-fn get_value_type(value: &Value) -> String {
+/// Checks that the constraints declared by a schema make sense: patterns
+/// compile, lower bounds don't exceed upper bounds, examples have the type
+/// of their field, search fields exist and compute expressions are sound.
+/// Fields of records can't be [reserved](RESERVED_FIELDS). Fields of objects
+/// are held to the same, and can't be files, relations, computed or masked,
+/// which only apply to the fields of records.
+pub fn check_schema(schema: &CollectionSchema) -> Result<(), String> {
+    if let Some(name) = RESERVED_FIELDS
+        .iter()
+        .find(|n| schema.fields.contains_key(**n))
+    {
+        return Err(format!(
+            "Field '{}' is reserved for the system; reserved names are {}",
+            name,
+            RESERVED_FIELDS.join(", ")
+        ));
+    }
+    check_field_definitions("", &schema.fields)?;
+    if let Some(search) = &schema.search {
+        if let Some(field) = search
+            .fields
+            .iter()
+            .find(|f| !schema.fields.contains_key(*f))
+        {
+            return Err(format!(
+                "Search field '{}' is not a field of the schema",
+                field
+            ));
+        }
+    }
+    check_computed(schema).map_err(|e| format!("Invalid compute expression: {}", e))
+}
+
+/// See [`check_schema`]; `prefix` is empty for the fields of records.
+fn check_field_definitions(
+    prefix: &str,
+    fields: &HashMap<String, FieldDefinition>,
+) -> Result<(), String> {
+    for (name, field) in fields {
+        let name = &format!("{}{}", prefix, name);
+        if let Some(pattern) = &field.pattern {
+            Regex::new(pattern)
+                .map_err(|e| format!("Invalid pattern for field '{}': {}", name, e))?;
+        }
+        let inverted = field.min.zip(field.max).is_some_and(|(min, max)| min > max)
+            || field
+                .min_length
+                .zip(field.max_length)
+                .is_some_and(|(min, max)| min > max)
+            || field
+                .min_items
+                .zip(field.max_items)
+                .is_some_and(|(min, max)| min > max);
+        if inverted {
+            return Err(format!(
+                "Field '{}' has a minimum greater than its maximum",
+                name
+            ));
+        }
+        if let Some(example) = &field.example {
+            if !is_correct_type(example, &field.r#type) {
+                return Err(format!(
+                    "The example of field '{}' is not of its type",
+                    name
+                ));
+            }
+        }
+        if !prefix.is_empty() && (field.compute.is_some() || field.mask.is_some()) {
+            return Err(format!(
+                "Field '{}' of an object can't be computed or masked",
+                name
+            ));
+        }
+        check_field_type(name, &field.r#type, !prefix.is_empty())?;
+    }
+    Ok(())
+}
+
+/// Checks the nested fields and items of the field `name` of type
+/// `field_type`, `nested` in an object or array if so.
+fn check_field_type(name: &str, field_type: &FieldType, nested: bool) -> Result<(), String> {
+    match field_type {
+        FieldType::File | FieldType::Relation { .. } if nested => Err(format!(
+            "Field '{}' can't be a {}: only fields of records can",
+            name,
+            field_type.name()
+        )),
+        FieldType::Object { fields } => check_field_definitions(&format!("{}.", name), fields),
+        FieldType::Array { items } => check_field_type(&format!("{}[]", name), items, true),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn get_value_type(value: &Value) -> String {
     match value {
         Value::Null => "null".to_string(),
         Value::Bool(_) => "boolean".to_string(),
@@ -72,5 +595,58 @@ fn is_correct_type(value: &Value, field_type: &FieldType) -> bool {
         FieldType::Number => value.is_number(),
         FieldType::Boolean => value.is_boolean(),
         FieldType::Json => value.is_object() || value.is_array(),
+        FieldType::Object { .. } => value.is_object(),
+        FieldType::Array { .. } => value.is_array(),
+        FieldType::File => value.get("filename").is_some_and(Value::is_string),
+        FieldType::Relation { .. } => value.is_i64(),
+        FieldType::Email | FieldType::Url | FieldType::Date | FieldType::DateTime => {
+            value.is_string()
+        }
     }
 }
+
+/// Checks string formats, returning a description of the expected format if
+/// `value` doesn't match it.
+fn check_format(value: &Value, field_type: &FieldType) -> Option<&'static str> {
+    let value = value.as_str()?;
+    let (valid, expected) = match field_type {
+        FieldType::Email => (is_email(value), "an email address"),
+        FieldType::Url => (is_url(value), "an http(s) URL"),
+        FieldType::Date => (
+            NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok(),
+            "an RFC 3339 date (YYYY-MM-DD)",
+        ),
+        FieldType::DateTime => (
+            DateTime::parse_from_rfc3339(value).is_ok(),
+            "an RFC 3339 date-time",
+        ),
+        _ => return None,
+    };
+    (!valid).then_some(expected)
+}
+
+/// Characters allowed in a dot-atom by RFC 5322, besides letters and digits.
+const ATOM_SPECIALS: &str = "!#$%&'*+-/=?^_`{|}~";
+
+fn is_email(value: &str) -> bool {
+    let Some((local, domain)) = value.rsplit_once('@') else {
+        return false;
+    };
+    let is_dot_atom = |s: &str, allowed: &dyn Fn(char) -> bool| {
+        s.split('.')
+            .all(|atom| !atom.is_empty() && atom.chars().all(allowed))
+    };
+    local.len() <= 64
+        && domain.len() <= 255
+        && domain.contains('.')
+        && is_dot_atom(local, &|c| c.is_alphanumeric() || ATOM_SPECIALS.contains(c))
+        && is_dot_atom(domain, &|c| c.is_alphanumeric() || c == '-')
+        && domain
+            .split('.')
+            .all(|label| !label.starts_with('-') && !label.ends_with('-'))
+}
+
+fn is_url(value: &str) -> bool {
+    Url::parse(value)
+        .is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.host().is_some())
+}
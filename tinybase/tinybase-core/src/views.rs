@@ -0,0 +1,184 @@
+//! Read-only view collections backed by auxiliary SQLite files.
+//!
+//! Reference datasets (postcodes, currencies, ...) can be attached at startup
+//! under an alias. Every table in an attached file is exposed as a view
+//! collection named `alias.table`, whose rows are served like records with
+//! the SQLite `rowid` as their id.
+
+use libsql::{params, Builder, Database, OpenFlags, Row};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::path::Path;
+
+type BoxResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+#[derive(Debug, Serialize)]
+pub struct ViewCollection {
+    /// `alias.table`
+    pub name: String,
+    pub columns: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ViewRow {
+    pub id: i64,
+    pub data: Value,
+}
+
+/// The auxiliary databases attached at startup, each opened read-only.
+#[derive(Default)]
+pub struct AttachedDatabases {
+    databases: Vec<(String, Database)>,
+}
+
+impl AttachedDatabases {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens the SQLite file at `path` read-only under `alias`.
+    pub async fn attach(&mut self, alias: &str, path: impl AsRef<Path>) -> BoxResult<()> {
+        if alias.is_empty() || !alias.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("Invalid database alias '{}'", alias).into());
+        }
+        if self.databases.iter().any(|(a, _)| a == alias) {
+            return Err(format!("Database alias '{}' is already attached", alias).into());
+        }
+        let path = path.as_ref();
+        if !path.is_file() {
+            return Err(format!("Database file {} does not exist", path.display()).into());
+        }
+        let db = Builder::new_local(path)
+            .flags(OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .build()
+            .await?;
+        // Fail at startup rather than on the first request if the file is
+        // not a readable SQLite database.
+        tables(&db).await?;
+        self.databases.push((alias.to_string(), db));
+        Ok(())
+    }
+
+    pub async fn list_views(&self) -> BoxResult<Vec<ViewCollection>> {
+        let mut views = Vec::new();
+        for (alias, db) in &self.databases {
+            for (table, columns) in tables(db).await? {
+                views.push(ViewCollection {
+                    name: format!("{}.{}", alias, table),
+                    columns,
+                });
+            }
+        }
+        Ok(views)
+    }
+
+    /// Lists rows of the view `name`, or `None` if no such view exists.
+    pub async fn list_rows(
+        &self,
+        name: &str,
+        limit: u32,
+        offset: u32,
+    ) -> BoxResult<Option<Vec<ViewRow>>> {
+        let Some((db, table)) = self.resolve(name).await? else {
+            return Ok(None);
+        };
+        let conn = db.connect()?;
+        let mut rows = conn
+            .query(
+                &format!(
+                    "SELECT rowid, * FROM {} ORDER BY rowid LIMIT ?1 OFFSET ?2",
+                    quote(&table)
+                ),
+                params![limit, offset],
+            )
+            .await?;
+        let mut result = Vec::new();
+        while let Some(row) = rows.next().await? {
+            result.push(row_to_view_row(&row)?);
+        }
+        Ok(Some(result))
+    }
+
+    /// Fetches a single row of the view `name` by rowid.
+    pub async fn get_row(&self, name: &str, id: i64) -> BoxResult<Option<ViewRow>> {
+        let Some((db, table)) = self.resolve(name).await? else {
+            return Ok(None);
+        };
+        let conn = db.connect()?;
+        let mut rows = conn
+            .query(
+                &format!("SELECT rowid, * FROM {} WHERE rowid = ?1", quote(&table)),
+                params![id],
+            )
+            .await?;
+        match rows.next().await? {
+            Some(row) => Ok(Some(row_to_view_row(&row)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Maps a view name onto its database and an existing table.
+    async fn resolve(&self, name: &str) -> BoxResult<Option<(&Database, String)>> {
+        let Some((alias, table)) = name.split_once('.') else {
+            return Ok(None);
+        };
+        let Some((_, db)) = self.databases.iter().find(|(a, _)| a == alias) else {
+            return Ok(None);
+        };
+        let exists = tables(db).await?.into_iter().any(|(t, _)| t == table);
+        Ok(exists.then(|| (db, table.to_string())))
+    }
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Lists the user tables of `db` with their column names.
+async fn tables(db: &Database) -> BoxResult<Vec<(String, Vec<String>)>> {
+    let conn = db.connect()?;
+    let mut rows = conn
+        .query(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+            (),
+        )
+        .await?;
+    let mut names = Vec::new();
+    while let Some(row) = rows.next().await? {
+        names.push(row.get::<String>(0)?);
+    }
+    let mut tables = Vec::with_capacity(names.len());
+    for name in names {
+        let mut rows = conn
+            .query(
+                "SELECT name FROM pragma_table_info(?1)",
+                params![name.as_str()],
+            )
+            .await?;
+        let mut columns = Vec::new();
+        while let Some(row) = rows.next().await? {
+            columns.push(row.get::<String>(0)?);
+        }
+        tables.push((name, columns));
+    }
+    Ok(tables)
+}
+
+fn row_to_view_row(row: &Row) -> BoxResult<ViewRow> {
+    let mut data = Map::new();
+    for idx in 1..row.column_count() {
+        let name = row.column_name(idx).unwrap_or_default().to_string();
+        let value = match row.get_value(idx)? {
+            libsql::Value::Null => Value::Null,
+            libsql::Value::Integer(i) => Value::from(i),
+            libsql::Value::Real(f) => Value::from(f),
+            libsql::Value::Text(s) => Value::String(s),
+            libsql::Value::Blob(b) => Value::from(b),
+        };
+        data.insert(name, value);
+    }
+    Ok(ViewRow {
+        id: row.get(0)?,
+        data: Value::Object(data),
+    })
+}
@@ -0,0 +1,83 @@
+//! Webhooks: URLs admins register to be POSTed the record changes of a
+//! collection, and the log of the deliveries made to them.
+
+use crate::events::EventAction;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Deliveries kept in the log of each webhook; older ones are dropped.
+pub const DELIVERY_LOG_SIZE: i64 = 100;
+
+/// Bytes of each response body kept in the delivery log.
+pub const RESPONSE_BODY_LIMIT: usize = 4096;
+
+/// The events a webhook can subscribe to.
+pub const WEBHOOK_EVENTS: [EventAction; 3] = [
+    EventAction::RecordCreated,
+    EventAction::RecordUpdated,
+    EventAction::RecordDeleted,
+];
+
+/// Where a webhook delivers and what, as configured by an admin.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WebhookSettings {
+    pub url: String,
+    pub collection_id: i64,
+    /// Event names to deliver: `record.created`, `record.updated` and
+    /// `record.deleted`.
+    pub events: Vec<String>,
+}
+
+impl WebhookSettings {
+    /// Whether an event of `action` on `collection_id` should be delivered.
+    pub fn wants(&self, action: EventAction, collection_id: i64) -> bool {
+        self.collection_id == collection_id && self.events.iter().any(|e| e == action.name())
+    }
+}
+
+#[derive(Debug)]
+pub struct Webhook {
+    pub id: i64,
+    pub settings: WebhookSettings,
+    /// Key deliveries are signed with, so receivers can tell they come from
+    /// this instance.
+    pub secret: String,
+    pub created: String,
+    pub updated: String,
+}
+
+/// One attempt at delivering an event to a webhook.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DeliveryAttempt {
+    pub webhook_id: i64,
+    pub event: String,
+    /// The body that was sent.
+    pub payload: Value,
+    /// 1 for the first attempt, counting up with each retry.
+    pub attempt: u32,
+    /// The response status, if the receiver answered.
+    pub status: Option<u16>,
+    /// Why the attempt failed, if it did.
+    pub error: Option<String>,
+    /// The start of the response body, if the receiver answered, cut at
+    /// [`RESPONSE_BODY_LIMIT`] bytes.
+    #[serde(default)]
+    pub response: Option<String>,
+    pub duration_ms: u64,
+    /// The delivery this attempt replays, when an admin replayed one.
+    #[serde(default)]
+    pub replay_of: Option<i64>,
+}
+
+impl DeliveryAttempt {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+#[derive(Debug)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub attempt: DeliveryAttempt,
+    pub created: String,
+}
@@ -0,0 +1,65 @@
+use serde_json::json;
+use tinybase_core::{embedded::Error, validation::ValidationErrorKind, ListOptions, Tinybase};
+
+#[tokio::test]
+async fn test_embedded_store() {
+    let path = std::env::temp_dir().join(format!("tinybase-embedded-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let tinybase = Tinybase::open(&path).await.unwrap();
+    let mut events = tinybase.events().subscribe();
+    let schema = serde_json::from_value(json!({ "fields": {
+        "title": { "type": "string", "required": true },
+        "status": { "type": "string", "required": false, "default": "draft" }
+    } }))
+    .unwrap();
+    tinybase
+        .create_collection("posts", Some(schema))
+        .await
+        .unwrap();
+    let posts = tinybase.collection("posts");
+
+    // Writes run the schema as they do over HTTP.
+    let post = posts.create(json!({ "title": "Hello" })).await.unwrap();
+    assert_eq!(post.data, json!({ "title": "Hello", "status": "draft" }));
+    match posts.create(json!({ "status": "draft" })).await {
+        Err(Error::Validation(errors)) => {
+            assert_eq!(errors[0].kind, ValidationErrorKind::MissingRequiredField);
+            assert_eq!(errors[0].path, "/title");
+        }
+        other => panic!("expected a validation error, got {:?}", other.map(|r| r.id)),
+    }
+    let updated = posts
+        .update(post.id, json!({ "status": null, "title": "Hi" }))
+        .await
+        .unwrap();
+    assert_eq!(updated.data, json!({ "title": "Hi" }));
+    assert!(posts.update(post.id, json!({ "title": 1 })).await.is_err());
+
+    let listed = posts.list(&ListOptions::default()).await.unwrap();
+    assert_eq!(listed.len(), 1);
+    assert!(posts.delete(post.id).await.unwrap());
+    assert!(posts.get(post.id).await.unwrap().is_none());
+    assert!(matches!(
+        tinybase.collection("missing").get(1).await,
+        Err(Error::NotFound(_))
+    ));
+
+    let actions: Vec<&str> = std::iter::from_fn(|| events.try_recv().ok())
+        .map(|event| event.action.name())
+        .collect();
+    assert_eq!(
+        actions,
+        [
+            "collection.created",
+            "record.created",
+            "record.updated",
+            "record.deleted"
+        ]
+    );
+
+    // The data is in the file, for the next time it is opened.
+    drop(tinybase);
+    let reopened = Tinybase::open(&path).await.unwrap();
+    assert_eq!(reopened.collections().await.unwrap()[0].name, "posts");
+    let _ = std::fs::remove_file(&path);
+}
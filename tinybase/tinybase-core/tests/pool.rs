@@ -0,0 +1,149 @@
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tinybase_core::{a_new_database_connection, pool::ConnectionPool, Db, VersionMismatch};
+
+async fn pool(name: &str, size: usize) -> ConnectionPool {
+    let path =
+        std::env::temp_dir().join(format!("tinybase-pool-{}-{}.db", std::process::id(), name));
+    let _ = std::fs::remove_file(&path);
+    let db = a_new_database_connection(&path).await.unwrap();
+    ConnectionPool::new(Arc::new(db), size).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_concurrent_writes_and_reads() {
+    let pool = Arc::new(pool("concurrent", 4).await);
+    let collection = pool.create_collection("posts", &None).await.unwrap();
+    let tasks: Vec<_> = (0..32)
+        .map(|n| {
+            let pool = pool.clone();
+            tokio::spawn(async move {
+                let record = pool
+                    .create_record(collection.id, &json!({ "n": n }))
+                    .await
+                    .unwrap();
+                let read = pool.get_record(collection.id, record.id).await.unwrap();
+                assert_eq!(read.unwrap().data["n"], n);
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    assert_eq!(pool.count_records(collection.id).await.unwrap(), 32);
+}
+
+#[tokio::test]
+async fn test_concurrent_increments_are_not_lost() {
+    let pool = Arc::new(pool("increments", 4).await);
+    let collection = pool.create_collection("posts", &None).await.unwrap();
+    let record = pool
+        .create_record(collection.id, &json!({ "likes": 0 }))
+        .await
+        .unwrap();
+    let tasks: Vec<_> = (0..32)
+        .map(|_| {
+            let pool = pool.clone();
+            tokio::spawn(async move {
+                pool.increment_field(collection.id, record.id, "likes", &1.into(), (None, None))
+                    .await
+                    .unwrap()
+                    .unwrap();
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    let record = pool.get_record(collection.id, record.id).await.unwrap();
+    assert_eq!(record.unwrap().data["likes"], 32);
+}
+
+#[tokio::test]
+async fn test_concurrent_get_or_create_creates_one_record() {
+    let pool = Arc::new(pool("get-or-create", 4).await);
+    let collection = pool.create_collection("settings", &None).await.unwrap();
+    let tasks: Vec<_> = (0..16)
+        .map(|n| {
+            let pool = pool.clone();
+            tokio::spawn(async move {
+                let data = json!({ "key": "theme", "value": n });
+                pool.get_or_create_record(collection.id, "key", &json!("theme"), &data)
+                    .await
+                    .unwrap()
+            })
+        })
+        .collect();
+    let mut created = 0;
+    let mut ids = Vec::new();
+    for task in tasks {
+        let (record, was_created) = task.await.unwrap();
+        created += was_created as usize;
+        ids.push(record.id);
+    }
+    assert_eq!(created, 1);
+    assert!(ids.iter().all(|id| *id == ids[0]));
+    assert_eq!(pool.count_records(collection.id).await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_concurrent_updates_at_one_version_let_one_win() {
+    let pool = Arc::new(pool("update-at", 4).await);
+    let collection = pool.create_collection("docs", &None).await.unwrap();
+    let record = pool
+        .create_record(collection.id, &json!({ "text": "draft" }))
+        .await
+        .unwrap();
+    assert_eq!(record.version, 1);
+    let tasks: Vec<_> = (0..8)
+        .map(|n| {
+            let pool = pool.clone();
+            tokio::spawn(async move {
+                pool.update_record_at(collection.id, record.id, 1, &json!({ "text": n }))
+                    .await
+            })
+        })
+        .collect();
+    let mut won = 0;
+    for task in tasks {
+        match task.await.unwrap() {
+            Ok(updated) => {
+                assert_eq!(updated.unwrap().version, 2);
+                won += 1;
+            }
+            Err(e) => assert!(e.downcast_ref::<VersionMismatch>().is_some(), "{}", e),
+        }
+    }
+    assert_eq!(won, 1);
+    let record = pool.get_record(collection.id, record.id).await.unwrap();
+    assert_eq!(record.unwrap().version, 2);
+}
+
+#[tokio::test]
+async fn test_connections_are_limited_and_reused() {
+    let pool = pool("limits", 2).await;
+    let first = pool.get().await.unwrap();
+    let second = pool.get().await.unwrap();
+    // Both connections are out, so a third request waits.
+    assert!(tokio::time::timeout(Duration::from_millis(50), pool.get())
+        .await
+        .is_err());
+
+    // A connection dropped inside a transaction isn't handed out again.
+    second.execute("BEGIN", ()).await.unwrap();
+    drop(second);
+    let third = pool.get().await.unwrap();
+    assert!(third.is_autocommit());
+    drop((first, third));
+
+    let mut rows = pool
+        .get()
+        .await
+        .unwrap()
+        .query("PRAGMA journal_mode", ())
+        .await
+        .unwrap();
+    let mode: String = rows.next().await.unwrap().unwrap().get(0).unwrap();
+    assert_eq!(mode, "wal");
+}
@@ -0,0 +1,86 @@
+//! Conversions between the JSON of the REST API and protobuf messages.
+
+use prost_types::{value::Kind, ListValue, Struct};
+use serde_json::{Map, Number, Value};
+
+use crate::proto::{Collection, Record};
+
+fn to_value(value: Value) -> prost_types::Value {
+    let kind = match value {
+        Value::Null => Kind::NullValue(0),
+        Value::Bool(b) => Kind::BoolValue(b),
+        Value::Number(n) => Kind::NumberValue(n.as_f64().unwrap_or_default()),
+        Value::String(s) => Kind::StringValue(s),
+        Value::Array(items) => Kind::ListValue(ListValue {
+            values: items.into_iter().map(to_value).collect(),
+        }),
+        Value::Object(map) => Kind::StructValue(to_struct(map)),
+    };
+    prost_types::Value { kind: Some(kind) }
+}
+
+fn from_value(value: prost_types::Value) -> Value {
+    match value.kind {
+        None | Some(Kind::NullValue(_)) => Value::Null,
+        Some(Kind::BoolValue(b)) => Value::Bool(b),
+        // Protobuf only has doubles; whole numbers go back as integers, so
+        // e.g. relation ids stay ids.
+        Some(Kind::NumberValue(n)) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => {
+            Value::from(n as i64)
+        }
+        Some(Kind::NumberValue(n)) => Number::from_f64(n).map_or(Value::Null, Value::Number),
+        Some(Kind::StringValue(s)) => Value::String(s),
+        Some(Kind::ListValue(list)) => {
+            Value::Array(list.values.into_iter().map(from_value).collect())
+        }
+        Some(Kind::StructValue(s)) => from_struct(s),
+    }
+}
+
+pub(crate) fn to_struct(map: Map<String, Value>) -> Struct {
+    Struct {
+        fields: map.into_iter().map(|(k, v)| (k, to_value(v))).collect(),
+    }
+}
+
+pub(crate) fn from_struct(s: Struct) -> Value {
+    Value::Object(
+        s.fields
+            .into_iter()
+            .map(|(k, v)| (k, from_value(v)))
+            .collect(),
+    )
+}
+
+fn object(value: &mut Value, key: &str) -> Option<Struct> {
+    match value.get_mut(key).map(Value::take) {
+        Some(Value::Object(map)) => Some(to_struct(map)),
+        _ => None,
+    }
+}
+
+fn string(value: &Value, key: &str) -> String {
+    value[key].as_str().unwrap_or_default().to_string()
+}
+
+/// A record as the REST API returns it.
+pub(crate) fn record(mut value: Value) -> Record {
+    Record {
+        id: value["id"].as_i64().unwrap_or_default(),
+        data: object(&mut value, "data"),
+        created: string(&value, "created"),
+        updated: string(&value, "updated"),
+    }
+}
+
+/// A collection as the REST API returns it.
+pub(crate) fn collection(mut value: Value) -> Collection {
+    Collection {
+        id: value["id"].as_i64().unwrap_or_default(),
+        name: string(&value, "name"),
+        schema: object(&mut value, "schema"),
+        created: string(&value, "created"),
+        updated: string(&value, "updated"),
+        group: value["group"].as_str().map(str::to_string),
+    }
+}
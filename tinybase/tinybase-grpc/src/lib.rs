@@ -0,0 +1,383 @@
+//! gRPC access to Tinybase collections and records, for services that would
+//! rather speak protobuf than JSON.
+//!
+//! [`TinybaseGrpc`] serves the `tinybase.v1.Tinybase` service defined in
+//! `proto/tinybase.proto` from the same [`AppState`] as the REST server.
+//! Unary calls are answered by the REST handlers themselves, called
+//! in-process, so validation, rules, hooks, events and errors are exactly
+//! those of the REST API; `WatchRecords` streams the instance's event bus.
+//!
+//! ```no_run
+//! # async fn run(state: tinybase_api::AppState) {
+//! let listener = tokio::net::TcpListener::bind("0.0.0.0:50051").await.unwrap();
+//! tokio::spawn(tinybase_grpc::serve(state, listener));
+//! # }
+//! ```
+
+mod convert;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use tinybase_api::{app_router, AppState};
+use tinybase_core::events::EventAction;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{
+    metadata::MetadataMap, transport::Server, Code, Request as GrpcRequest, Response, Status,
+};
+use tower::ServiceExt;
+
+use proto::{
+    tinybase_server::{Tinybase, TinybaseServer},
+    Collection, CreateCollectionRequest, CreateRecordRequest, DeleteCollectionRequest,
+    DeleteCollectionResponse, DeleteRecordRequest, DeleteRecordResponse, GetCollectionRequest,
+    GetRecordRequest, ListCollectionsRequest, ListCollectionsResponse, ListRecordsRequest,
+    ListRecordsResponse, Record, RecordEvent, UpdateRecordRequest, WatchRecordsRequest,
+};
+
+pub mod proto {
+    tonic::include_proto!("tinybase.v1");
+}
+
+/// Largest REST response read back; a page of large records can be far
+/// bigger than any request body.
+const MAX_RESPONSE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Events buffered for a slow `WatchRecords` client before it misses some.
+const WATCH_BUFFER: usize = 64;
+
+/// The record-change events `WatchRecords` streams.
+const RECORD_EVENTS: [EventAction; 3] = [
+    EventAction::RecordCreated,
+    EventAction::RecordUpdated,
+    EventAction::RecordDeleted,
+];
+
+/// The gRPC code for an HTTP status of the REST API.
+fn code(status: StatusCode) -> Code {
+    match status {
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Code::InvalidArgument,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::NOT_FOUND => Code::NotFound,
+        StatusCode::CONFLICT => Code::AlreadyExists,
+        StatusCode::PRECONDITION_FAILED
+        | StatusCode::PRECONDITION_REQUIRED
+        | StatusCode::LOCKED => Code::FailedPrecondition,
+        StatusCode::PAYLOAD_TOO_LARGE | StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+        StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+        StatusCode::GATEWAY_TIMEOUT => Code::DeadlineExceeded,
+        _ => Code::Internal,
+    }
+}
+
+/// Percent-encodes a path segment, e.g. a collection name.
+fn segment(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// The records of a list response, bare or wrapped in the envelope format.
+fn list_items(value: Value) -> Vec<Value> {
+    match value {
+        Value::Array(items) => items,
+        Value::Object(mut envelope) => match envelope.remove("items") {
+            Some(Value::Array(items)) => items,
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    }
+}
+
+/// Serves `tinybase.v1.Tinybase` over `state`, see the [crate docs](crate).
+#[derive(Clone)]
+pub struct TinybaseGrpc {
+    state: AppState,
+    router: Router,
+}
+
+impl TinybaseGrpc {
+    pub fn new(state: AppState) -> Self {
+        Self {
+            router: app_router(state.clone()),
+            state,
+        }
+    }
+
+    pub fn into_service(self) -> TinybaseServer<Self> {
+        TinybaseServer::new(self)
+    }
+
+    /// Answers `method uri` with the REST API, passing on the caller's
+    /// `authorization` metadata.
+    async fn rest(
+        &self,
+        method: Method,
+        uri: &str,
+        body: Option<Value>,
+        metadata: &MetadataMap,
+    ) -> Result<Value, Status> {
+        rest(&self.router, method, uri, body, metadata).await
+    }
+}
+
+async fn rest(
+    router: &Router,
+    method: Method,
+    uri: &str,
+    body: Option<Value>,
+    metadata: &MetadataMap,
+) -> Result<Value, Status> {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(format!("/api/v1{}", uri))
+        .header(header::CONTENT_TYPE, "application/json");
+    if let Some(auth) = metadata.get("authorization").and_then(|v| v.to_str().ok()) {
+        request = request.header(header::AUTHORIZATION, auth);
+    }
+    let body = body.map_or_else(Body::empty, |b| Body::from(b.to_string()));
+    let request = request
+        .body(body)
+        .map_err(|e| Status::invalid_argument(e.to_string()))?;
+    let response = router
+        .clone()
+        .oneshot(request)
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?;
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), MAX_RESPONSE_BYTES)
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?;
+    let value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    if status.is_success() {
+        return Ok(value);
+    }
+    let message = value["message"]
+        .as_str()
+        .unwrap_or_else(|| status.canonical_reason().unwrap_or("The request failed"));
+    Err(Status::new(code(status), message))
+}
+
+#[tonic::async_trait]
+impl Tinybase for TinybaseGrpc {
+    async fn list_collections(
+        &self,
+        request: GrpcRequest<ListCollectionsRequest>,
+    ) -> Result<Response<ListCollectionsResponse>, Status> {
+        let value = self
+            .rest(Method::GET, "/collections", None, request.metadata())
+            .await?;
+        Ok(Response::new(ListCollectionsResponse {
+            collections: list_items(value)
+                .into_iter()
+                .map(convert::collection)
+                .collect(),
+        }))
+    }
+
+    async fn get_collection(
+        &self,
+        request: GrpcRequest<GetCollectionRequest>,
+    ) -> Result<Response<Collection>, Status> {
+        let uri = format!("/collections/{}", segment(&request.get_ref().collection));
+        let value = self
+            .rest(Method::GET, &uri, None, request.metadata())
+            .await?;
+        Ok(Response::new(convert::collection(value)))
+    }
+
+    async fn create_collection(
+        &self,
+        request: GrpcRequest<CreateCollectionRequest>,
+    ) -> Result<Response<Collection>, Status> {
+        let (metadata, _, message) = request.into_parts();
+        let body = json!({
+            "name": message.name,
+            "schema": message.schema.map(convert::from_struct),
+        });
+        let value = self
+            .rest(Method::POST, "/collections", Some(body), &metadata)
+            .await?;
+        Ok(Response::new(convert::collection(value)))
+    }
+
+    async fn delete_collection(
+        &self,
+        request: GrpcRequest<DeleteCollectionRequest>,
+    ) -> Result<Response<DeleteCollectionResponse>, Status> {
+        let uri = format!("/collections/{}", segment(&request.get_ref().collection));
+        self.rest(Method::DELETE, &uri, None, request.metadata())
+            .await?;
+        Ok(Response::new(DeleteCollectionResponse {}))
+    }
+
+    async fn list_records(
+        &self,
+        request: GrpcRequest<ListRecordsRequest>,
+    ) -> Result<Response<ListRecordsResponse>, Status> {
+        let message = request.get_ref();
+        let mut query = Vec::new();
+        if let Some(page) = message.page {
+            query.push(format!("page={}", page));
+        }
+        if let Some(per_page) = message.per_page {
+            query.push(format!("per_page={}", per_page));
+        }
+        if let Some(sort) = &message.sort {
+            query.push(format!("sort={}", segment(sort)));
+        }
+        let uri = format!(
+            "/collections/{}/records?{}",
+            segment(&message.collection),
+            query.join("&")
+        );
+        let value = self
+            .rest(Method::GET, &uri, None, request.metadata())
+            .await?;
+        Ok(Response::new(ListRecordsResponse {
+            records: list_items(value).into_iter().map(convert::record).collect(),
+        }))
+    }
+
+    async fn get_record(
+        &self,
+        request: GrpcRequest<GetRecordRequest>,
+    ) -> Result<Response<Record>, Status> {
+        let message = request.get_ref();
+        let uri = format!(
+            "/collections/{}/records/{}",
+            segment(&message.collection),
+            message.id
+        );
+        let value = self
+            .rest(Method::GET, &uri, None, request.metadata())
+            .await?;
+        Ok(Response::new(convert::record(value)))
+    }
+
+    async fn create_record(
+        &self,
+        request: GrpcRequest<CreateRecordRequest>,
+    ) -> Result<Response<Record>, Status> {
+        let (metadata, _, message) = request.into_parts();
+        let uri = format!("/collections/{}/records", segment(&message.collection));
+        let body = json!({ "data": message.data.map(convert::from_struct).unwrap_or(json!({})) });
+        let value = self.rest(Method::POST, &uri, Some(body), &metadata).await?;
+        Ok(Response::new(convert::record(value)))
+    }
+
+    async fn update_record(
+        &self,
+        request: GrpcRequest<UpdateRecordRequest>,
+    ) -> Result<Response<Record>, Status> {
+        let (metadata, _, message) = request.into_parts();
+        let uri = format!(
+            "/collections/{}/records/{}",
+            segment(&message.collection),
+            message.id
+        );
+        let body = json!({ "data": message.data.map(convert::from_struct).unwrap_or(json!({})) });
+        let value = self
+            .rest(Method::PATCH, &uri, Some(body), &metadata)
+            .await?;
+        Ok(Response::new(convert::record(value)))
+    }
+
+    async fn delete_record(
+        &self,
+        request: GrpcRequest<DeleteRecordRequest>,
+    ) -> Result<Response<DeleteRecordResponse>, Status> {
+        let message = request.get_ref();
+        let uri = format!(
+            "/collections/{}/records/{}",
+            segment(&message.collection),
+            message.id
+        );
+        self.rest(Method::DELETE, &uri, None, request.metadata())
+            .await?;
+        Ok(Response::new(DeleteRecordResponse {}))
+    }
+
+    type WatchRecordsStream = ReceiverStream<Result<RecordEvent, Status>>;
+
+    async fn watch_records(
+        &self,
+        request: GrpcRequest<WatchRecordsRequest>,
+    ) -> Result<Response<Self::WatchRecordsStream>, Status> {
+        let (metadata, _, message) = request.into_parts();
+        let collection = segment(&message.collection);
+        // Subscribe before resolving the collection, so no event between the
+        // two is missed.
+        let mut events = self.state.events.subscribe();
+        let found = self
+            .rest(
+                Method::GET,
+                &format!("/collections/{}", collection),
+                None,
+                &metadata,
+            )
+            .await?;
+        let collection_id = found["id"].as_i64().unwrap_or_default();
+        let router = self.router.clone();
+        let (tx, rx) = mpsc::channel(WATCH_BUFFER);
+        tokio::spawn(async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(missed)) => {
+                        let status = Status::data_loss(format!("{} events were missed", missed));
+                        if tx.send(Err(status)).await.is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if event.collection_id != collection_id || !RECORD_EVENTS.contains(&event.action) {
+                    continue;
+                }
+                let record_id = event.record_id.unwrap_or_default();
+                let record = match event.action {
+                    EventAction::RecordDeleted => None,
+                    _ => {
+                        let uri = format!("/collections/{}/records/{}", collection_id, record_id);
+                        rest(&router, Method::GET, &uri, None, &metadata)
+                            .await
+                            .ok()
+                            .map(convert::record)
+                    }
+                };
+                let message = RecordEvent {
+                    action: event.action.name().to_string(),
+                    collection_id,
+                    record_id,
+                    record,
+                    timestamp: event.timestamp,
+                };
+                if tx.send(Ok(message)).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+/// Serves the gRPC API over `state` on `listener` until the server fails.
+pub async fn serve(state: AppState, listener: TcpListener) -> Result<(), tonic::transport::Error> {
+    Server::builder()
+        .add_service(TinybaseGrpc::new(state).into_service())
+        .serve_with_incoming(TcpListenerStream::new(listener))
+        .await
+}
@@ -0,0 +1,161 @@
+use prost_types::{value::Kind, Struct, Value};
+use std::sync::Arc;
+use tinybase_api::AppState;
+use tinybase_core::{setup_database, Db};
+use tinybase_grpc::proto::{
+    tinybase_client::TinybaseClient, CreateCollectionRequest, CreateRecordRequest,
+    DeleteRecordRequest, GetRecordRequest, ListRecordsRequest, UpdateRecordRequest,
+    WatchRecordsRequest,
+};
+use tinybase_storage::LocalStorage;
+use tokio::sync::Mutex;
+use tonic::{transport::Channel, Code};
+
+async fn setup_client() -> TinybaseClient<Channel> {
+    let db = libsql::Builder::new_local(":memory:")
+        .build()
+        .await
+        .unwrap();
+    let conn = db.connect().unwrap();
+    setup_database(&conn).await.unwrap();
+    let db: Arc<dyn Db> = Arc::new(Mutex::new(conn));
+    let storage = std::env::temp_dir().join(format!("tinybase-grpc-test-{}", std::process::id()));
+    let state = AppState::new(db, Arc::new(LocalStorage::new(storage)));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(tinybase_grpc::serve(state, listener));
+    TinybaseClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap()
+}
+
+fn object(fields: &[(&str, Kind)]) -> Struct {
+    Struct {
+        fields: fields
+            .iter()
+            .map(|(k, v)| {
+                (
+                    k.to_string(),
+                    Value {
+                        kind: Some(v.clone()),
+                    },
+                )
+            })
+            .collect(),
+    }
+}
+
+fn string(s: &str) -> Kind {
+    Kind::StringValue(s.to_string())
+}
+
+#[tokio::test]
+async fn test_record_crud_and_watch() {
+    let mut client = setup_client().await;
+    let title = object(&[
+        ("type", string("string")),
+        ("required", Kind::BoolValue(true)),
+    ]);
+    let schema = object(&[(
+        "fields",
+        Kind::StructValue(object(&[("title", Kind::StructValue(title))])),
+    )]);
+    let collection = client
+        .create_collection(CreateCollectionRequest {
+            name: "posts".to_string(),
+            schema: Some(schema),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(collection.name, "posts");
+    assert!(collection.schema.unwrap().fields.contains_key("fields"));
+
+    let mut events = client
+        .watch_records(WatchRecordsRequest {
+            collection: "posts".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+
+    let record = client
+        .create_record(CreateRecordRequest {
+            collection: "posts".to_string(),
+            data: Some(object(&[("title", string("Hello"))])),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    let data = record.data.unwrap();
+    assert_eq!(data.fields["title"].kind, Some(string("Hello")));
+
+    let event = events.message().await.unwrap().unwrap();
+    assert_eq!(event.action, "record.created");
+    assert_eq!(event.collection_id, collection.id);
+    assert_eq!(event.record.unwrap().id, record.id);
+
+    let updated = client
+        .update_record(UpdateRecordRequest {
+            collection: "posts".to_string(),
+            id: record.id,
+            data: Some(object(&[("title", string("Hello again"))])),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(
+        updated.data.unwrap().fields["title"].kind,
+        Some(string("Hello again"))
+    );
+    let event = events.message().await.unwrap().unwrap();
+    assert_eq!(event.action, "record.updated");
+
+    let records = client
+        .list_records(ListRecordsRequest {
+            collection: collection.id.to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .records;
+    assert_eq!(records.len(), 1);
+
+    // Errors carry the REST API's message and the matching code.
+    let invalid = client
+        .create_record(CreateRecordRequest {
+            collection: "posts".to_string(),
+            data: Some(object(&[])),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(invalid.code(), Code::InvalidArgument);
+
+    client
+        .delete_record(DeleteRecordRequest {
+            collection: "posts".to_string(),
+            id: record.id,
+        })
+        .await
+        .unwrap();
+    let event = events.message().await.unwrap().unwrap();
+    assert_eq!(event.action, "record.deleted");
+    assert!(event.record.is_none());
+    let missing = client
+        .get_record(GetRecordRequest {
+            collection: "posts".to_string(),
+            id: record.id,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(missing.code(), Code::NotFound);
+    let missing = client
+        .watch_records(WatchRecordsRequest {
+            collection: "drafts".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(missing.code(), Code::NotFound);
+}
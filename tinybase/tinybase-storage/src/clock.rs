@@ -0,0 +1,69 @@
+//! The time Tinybase runs on.
+//!
+//! Everything that stamps or expires data asks [`now`] instead of the system
+//! clock: record timestamps, event times, entry TTLs and with them API key
+//! expiry. [`now`] follows the clock of the enclosing [`with_clock`] scope,
+//! so tests can run code on a [`ManualClock`] and move time forward instead
+//! of sleeping; outside any scope it is the system clock.
+
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// The system clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when told to.
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<SystemTime>,
+}
+
+impl ManualClock {
+    pub fn new(start: SystemTime) -> Self {
+        Self {
+            now: Mutex::new(start),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap() = now;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+}
+
+tokio::task_local! {
+    static CLOCK: Arc<dyn Clock>;
+}
+
+/// Runs `future` with [`now`] reading `clock`.
+pub async fn with_clock<F: Future>(clock: Arc<dyn Clock>, future: F) -> F::Output {
+    CLOCK.scope(clock, future).await
+}
+
+/// The current time on the clock in scope.
+pub fn now() -> SystemTime {
+    CLOCK
+        .try_with(|clock| clock.now())
+        .unwrap_or_else(|_| SystemTime::now())
+}
@@ -0,0 +1,103 @@
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+pub mod clock;
+pub mod store;
+
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[error("Invalid storage key: {0}")]
+    InvalidKey(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Storage backend error: {0}")]
+    Backend(String),
+}
+
+/// A blob store for uploaded files, addressed by slash-separated keys such as
+/// `"{collection_id}/{record_id}/{filename}"`.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn put(&self, key: &str, bytes: &[u8]) -> Result<(), StorageError>;
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError>;
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+    /// Deletes every blob whose key starts with `prefix/`, e.g. all the
+    /// files of a collection.
+    async fn delete_prefix(&self, prefix: &str) -> Result<(), StorageError>;
+}
+
+/// Stores blobs as plain files below a root directory.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path_for(&self, key: &str) -> Result<PathBuf, StorageError> {
+        let mut path = self.root.clone();
+        for segment in key.split('/') {
+            if segment.is_empty() || segment == "." || segment == ".." || segment.contains('\\') {
+                return Err(StorageError::InvalidKey(key.to_string()));
+            }
+            path.push(segment);
+        }
+        Ok(path)
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, bytes: &[u8]) -> Result<(), StorageError> {
+        let path = self.path_for(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&path, bytes).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        let path = self.path_for(key)?;
+        match tokio::fs::read(&path).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        let path = self.path_for(key)?;
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        remove_empty_parents(&self.root, &path).await;
+        Ok(())
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<(), StorageError> {
+        let path = self.path_for(prefix)?;
+        match tokio::fs::remove_dir_all(&path).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        remove_empty_parents(&self.root, &path).await;
+        Ok(())
+    }
+}
+
+async fn remove_empty_parents(root: &Path, path: &Path) {
+    let mut current = path.parent();
+    while let Some(dir) = current {
+        if dir == root || tokio::fs::remove_dir(dir).await.is_err() {
+            break;
+        }
+        current = dir.parent();
+    }
+}
@@ -0,0 +1,299 @@
+//! Short-lived shared state (rate-limit counters, sessions, idempotency keys)
+//! that multiple Tinybase instances need to agree on.
+
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::{clock, StorageError};
+
+/// A key-value store with expiring entries. The in-memory default only
+/// shares state within one process; deployments running several instances
+/// use a networked implementation such as `RedisStore` (feature `redis`).
+#[async_trait]
+pub trait DistributedStore: Send + Sync {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError>;
+    async fn set(&self, key: &str, value: &[u8], ttl: Option<Duration>)
+        -> Result<(), StorageError>;
+    /// Stores `value` only if `key` is not set, returning whether it was stored.
+    async fn set_if_absent(
+        &self,
+        key: &str,
+        value: &[u8],
+        ttl: Option<Duration>,
+    ) -> Result<bool, StorageError>;
+    /// Increments the counter at `key` and returns its new value. The first
+    /// increment starts a window of `ttl` after which the counter resets.
+    async fn increment(&self, key: &str, ttl: Duration) -> Result<i64, StorageError>;
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+}
+
+struct Entry {
+    value: Vec<u8>,
+    expires: Option<SystemTime>,
+}
+
+impl Entry {
+    fn is_live(&self, now: SystemTime) -> bool {
+        self.expires.is_none_or(|at| at > now)
+    }
+}
+
+/// Minimum number of entries before expired ones are swept out.
+const SWEEP_THRESHOLD: usize = 1024;
+
+#[derive(Default)]
+struct Entries {
+    map: HashMap<String, Entry>,
+    sweep_at: usize,
+}
+
+impl Entries {
+    fn live(&mut self, key: &str, now: SystemTime) -> Option<&mut Entry> {
+        if self.map.get(key).is_some_and(|e| !e.is_live(now)) {
+            self.map.remove(key);
+        }
+        self.map.get_mut(key)
+    }
+
+    fn insert(&mut self, key: &str, entry: Entry, now: SystemTime) {
+        self.map.insert(key.to_string(), entry);
+        if self.map.len() >= self.sweep_at.max(SWEEP_THRESHOLD) {
+            self.map.retain(|_, e| e.is_live(now));
+            self.sweep_at = self.map.len() * 2;
+        }
+    }
+}
+
+/// Keeps entries in process memory. Entries expire by [`clock::now`].
+#[derive(Default)]
+pub struct MemoryStore {
+    entries: Mutex<Entries>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl DistributedStore for MemoryStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        let mut entries = self.entries.lock().unwrap();
+        Ok(entries.live(key, clock::now()).map(|e| e.value.clone()))
+    }
+
+    async fn set(
+        &self,
+        key: &str,
+        value: &[u8],
+        ttl: Option<Duration>,
+    ) -> Result<(), StorageError> {
+        let now = clock::now();
+        let entry = Entry {
+            value: value.to_vec(),
+            expires: ttl.map(|ttl| now + ttl),
+        };
+        self.entries.lock().unwrap().insert(key, entry, now);
+        Ok(())
+    }
+
+    async fn set_if_absent(
+        &self,
+        key: &str,
+        value: &[u8],
+        ttl: Option<Duration>,
+    ) -> Result<bool, StorageError> {
+        let now = clock::now();
+        let mut entries = self.entries.lock().unwrap();
+        if entries.live(key, now).is_some() {
+            return Ok(false);
+        }
+        let entry = Entry {
+            value: value.to_vec(),
+            expires: ttl.map(|ttl| now + ttl),
+        };
+        entries.insert(key, entry, now);
+        Ok(true)
+    }
+
+    async fn increment(&self, key: &str, ttl: Duration) -> Result<i64, StorageError> {
+        let now = clock::now();
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries.live(key, now) {
+            let count = std::str::from_utf8(&entry.value)
+                .ok()
+                .and_then(|v| v.parse::<i64>().ok())
+                .ok_or_else(|| StorageError::Backend(format!("{} is not a counter", key)))?
+                + 1;
+            entry.value = count.to_string().into_bytes();
+            return Ok(count);
+        }
+        let entry = Entry {
+            value: b"1".to_vec(),
+            expires: Some(now + ttl),
+        };
+        entries.insert(key, entry, now);
+        Ok(1)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        self.entries.lock().unwrap().map.remove(key);
+        Ok(())
+    }
+}
+
+/// Keeps the entries of another store under a prefix of their keys, so
+/// several users of one store, such as the namespaces of an instance, can't
+/// see each other's entries.
+pub struct PrefixedStore {
+    inner: Arc<dyn DistributedStore>,
+    prefix: String,
+}
+
+impl PrefixedStore {
+    pub fn new(inner: Arc<dyn DistributedStore>, prefix: impl Into<String>) -> Self {
+        Self {
+            inner,
+            prefix: prefix.into(),
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+#[async_trait]
+impl DistributedStore for PrefixedStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        self.inner.get(&self.key(key)).await
+    }
+
+    async fn set(
+        &self,
+        key: &str,
+        value: &[u8],
+        ttl: Option<Duration>,
+    ) -> Result<(), StorageError> {
+        self.inner.set(&self.key(key), value, ttl).await
+    }
+
+    async fn set_if_absent(
+        &self,
+        key: &str,
+        value: &[u8],
+        ttl: Option<Duration>,
+    ) -> Result<bool, StorageError> {
+        self.inner.set_if_absent(&self.key(key), value, ttl).await
+    }
+
+    async fn increment(&self, key: &str, ttl: Duration) -> Result<i64, StorageError> {
+        self.inner.increment(&self.key(key), ttl).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        self.inner.delete(&self.key(key)).await
+    }
+}
+
+#[cfg(feature = "redis")]
+pub use self::redis_store::RedisStore;
+
+#[cfg(feature = "redis")]
+mod redis_store {
+    use super::*;
+    use redis::aio::ConnectionManager;
+    use redis::AsyncCommands;
+
+    /// Keeps entries in Redis so every instance pointed at the same server
+    /// shares them.
+    #[derive(Clone)]
+    pub struct RedisStore {
+        conn: ConnectionManager,
+        prefix: String,
+    }
+
+    impl RedisStore {
+        /// Connects to `url` (e.g. `redis://127.0.0.1/`), namespacing every
+        /// key under `prefix`.
+        pub async fn connect(url: &str, prefix: &str) -> Result<Self, StorageError> {
+            let client = redis::Client::open(url).map_err(backend)?;
+            let conn = ConnectionManager::new(client).await.map_err(backend)?;
+            Ok(Self {
+                conn,
+                prefix: prefix.to_string(),
+            })
+        }
+
+        fn key(&self, key: &str) -> String {
+            format!("{}{}", self.prefix, key)
+        }
+    }
+
+    fn backend(e: redis::RedisError) -> StorageError {
+        StorageError::Backend(e.to_string())
+    }
+
+    fn millis(ttl: Duration) -> u64 {
+        (ttl.as_millis() as u64).max(1)
+    }
+
+    #[async_trait]
+    impl DistributedStore for RedisStore {
+        async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+            let mut conn = self.conn.clone();
+            conn.get(self.key(key)).await.map_err(backend)
+        }
+
+        async fn set(
+            &self,
+            key: &str,
+            value: &[u8],
+            ttl: Option<Duration>,
+        ) -> Result<(), StorageError> {
+            let mut conn = self.conn.clone();
+            let mut cmd = redis::cmd("SET");
+            cmd.arg(self.key(key)).arg(value);
+            if let Some(ttl) = ttl {
+                cmd.arg("PX").arg(millis(ttl));
+            }
+            cmd.query_async(&mut conn).await.map_err(backend)
+        }
+
+        async fn set_if_absent(
+            &self,
+            key: &str,
+            value: &[u8],
+            ttl: Option<Duration>,
+        ) -> Result<bool, StorageError> {
+            let mut conn = self.conn.clone();
+            let mut cmd = redis::cmd("SET");
+            cmd.arg(self.key(key)).arg(value).arg("NX");
+            if let Some(ttl) = ttl {
+                cmd.arg("PX").arg(millis(ttl));
+            }
+            let reply: Option<String> = cmd.query_async(&mut conn).await.map_err(backend)?;
+            Ok(reply.is_some())
+        }
+
+        async fn increment(&self, key: &str, ttl: Duration) -> Result<i64, StorageError> {
+            let mut conn = self.conn.clone();
+            let key = self.key(key);
+            let count: i64 = conn.incr(&key, 1).await.map_err(backend)?;
+            if count == 1 {
+                conn.pexpire::<_, ()>(&key, millis(ttl) as i64)
+                    .await
+                    .map_err(backend)?;
+            }
+            Ok(count)
+        }
+
+        async fn delete(&self, key: &str) -> Result<(), StorageError> {
+            let mut conn = self.conn.clone();
+            conn.del(self.key(key)).await.map_err(backend)
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::time::Duration;
+use tinybase_storage::store::{DistributedStore, MemoryStore};
+
+#[tokio::test]
+async fn test_memory_store_expiry() {
+    let store = MemoryStore::new();
+
+    store
+        .set("session:a", b"user-1", Some(Duration::from_millis(20)))
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get("session:a").await.unwrap(),
+        Some(b"user-1".to_vec())
+    );
+    tokio::time::sleep(Duration::from_millis(40)).await;
+    assert_eq!(store.get("session:a").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_memory_store_set_if_absent() {
+    let store = MemoryStore::new();
+
+    assert!(store.set_if_absent("idem:1", b"first", None).await.unwrap());
+    assert!(!store
+        .set_if_absent("idem:1", b"second", None)
+        .await
+        .unwrap());
+    assert_eq!(store.get("idem:1").await.unwrap(), Some(b"first".to_vec()));
+
+    store.delete("idem:1").await.unwrap();
+    assert!(store.set_if_absent("idem:1", b"third", None).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_memory_store_counter_window() {
+    let store = MemoryStore::new();
+    let window = Duration::from_millis(20);
+
+    assert_eq!(store.increment("rate:ip", window).await.unwrap(), 1);
+    assert_eq!(store.increment("rate:ip", window).await.unwrap(), 2);
+    tokio::time::sleep(Duration::from_millis(40)).await;
+    assert_eq!(store.increment("rate:ip", window).await.unwrap(), 1);
+}